@@ -0,0 +1,69 @@
+//! HTML export benchmark
+//!
+//! Exports a heavily formatted document and reports the elapsed time.
+//! Export walks format runs per line, so doubling the document size
+//! should roughly double the time. The run fails if the ratio suggests
+//! the exporter has regressed to per-character format lookups.
+//!
+//! Run with `cargo bench --bench html_export`.
+
+use rte_core::document::{Document, Range};
+use rte_core::formatting::InlineFormat;
+use std::time::{Duration, Instant};
+
+/// Builds a document of `lines` lines with a format change every few characters
+fn formatted_document(lines: usize) -> Document {
+    let line = "The quick brown fox jumps over the lazy dog";
+    let text = vec![line; lines].join("\n");
+    let mut doc = Document::from_text(&text);
+    let len = doc.get_length();
+
+    let mut offset = 0;
+    while offset + 4 <= len {
+        doc.apply_format(Range::from_offsets(offset, offset + 2), InlineFormat::Bold);
+        doc.apply_format(
+            Range::from_offsets(offset + 1, offset + 3),
+            InlineFormat::Italic,
+        );
+        offset += 7;
+    }
+    doc.clear_history();
+    doc
+}
+
+/// Returns the fastest of several exports to reduce timer noise
+fn time_export(doc: &Document) -> Duration {
+    (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            std::hint::black_box(doc.to_html());
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let small = formatted_document(200);
+    let large = formatted_document(800);
+
+    let small_time = time_export(&small);
+    let large_time = time_export(&large);
+    let ratio = large_time.as_secs_f64() / small_time.as_secs_f64().max(1e-9);
+
+    println!(
+        "to_html: {} chars in {:?}, {} chars in {:?} (ratio {:.1}x for 4x input)",
+        small.get_length(),
+        small_time,
+        large.get_length(),
+        large_time,
+        ratio
+    );
+
+    // Linear export gives ~4x; per-character lookups give ~16x
+    assert!(
+        ratio < 10.0,
+        "HTML export scaled {:.1}x for 4x input; expected roughly linear",
+        ratio
+    );
+}
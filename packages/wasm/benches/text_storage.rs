@@ -0,0 +1,113 @@
+//! Text storage benchmark
+//!
+//! Compares the gap buffer and rope backends on a 500K character document
+//! under random edits (scattered across the document) and sequential typing
+//! (all at one cursor).
+//!
+//! Run with `cargo bench --bench text_storage`.
+
+use rte_core::document::rope::RopeStorage;
+use rte_core::document::text_storage::TextStorage;
+use std::time::{Duration, Instant};
+
+const DOCUMENT_SIZE: usize = 500_000;
+const EDIT_COUNT: usize = 5_000;
+
+/// Minimal interface shared by both backends for the benchmark loops
+trait Storage {
+    fn from_text(text: &str) -> Self;
+    fn len(&self) -> usize;
+    fn insert(&mut self, pos: usize, text: &str);
+    fn delete(&mut self, start: usize, end: usize);
+}
+
+impl Storage for TextStorage {
+    fn from_text(text: &str) -> Self {
+        TextStorage::from_text(text)
+    }
+    fn len(&self) -> usize {
+        TextStorage::len(self)
+    }
+    fn insert(&mut self, pos: usize, text: &str) {
+        TextStorage::insert(self, pos, text)
+    }
+    fn delete(&mut self, start: usize, end: usize) {
+        TextStorage::delete(self, start, end)
+    }
+}
+
+impl Storage for RopeStorage {
+    fn from_text(text: &str) -> Self {
+        RopeStorage::from_text(text)
+    }
+    fn len(&self) -> usize {
+        RopeStorage::len(self)
+    }
+    fn insert(&mut self, pos: usize, text: &str) {
+        RopeStorage::insert(self, pos, text)
+    }
+    fn delete(&mut self, start: usize, end: usize) {
+        RopeStorage::delete(self, start, end)
+    }
+}
+
+/// Deterministic pseudo-random positions (LCG)
+struct Positions(u64);
+
+impl Positions {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 33) as usize) % bound.max(1)
+    }
+}
+
+fn random_edits<S: Storage>(text: &str) -> Duration {
+    let mut storage = S::from_text(text);
+    let mut positions = Positions(7);
+    let start = Instant::now();
+    for i in 0..EDIT_COUNT {
+        let pos = positions.next(storage.len());
+        if i % 2 == 0 {
+            storage.insert(pos, "edit");
+        } else {
+            let end = (pos + 4).min(storage.len());
+            storage.delete(pos, end);
+        }
+    }
+    std::hint::black_box(storage.len());
+    start.elapsed()
+}
+
+fn sequential_typing<S: Storage>(text: &str) -> Duration {
+    let mut storage = S::from_text(text);
+    let middle = storage.len() / 2;
+    let start = Instant::now();
+    for i in 0..EDIT_COUNT {
+        storage.insert(middle + i, "a");
+    }
+    std::hint::black_box(storage.len());
+    start.elapsed()
+}
+
+fn main() {
+    let text = "lorem ipsum dolor sit amet\n".repeat(DOCUMENT_SIZE / 27);
+
+    println!(
+        "{} edits on a {} char document",
+        EDIT_COUNT,
+        text.chars().count()
+    );
+    println!(
+        "random edits:      gap buffer {:>10.2?}  rope {:>10.2?}",
+        random_edits::<TextStorage>(&text),
+        random_edits::<RopeStorage>(&text)
+    );
+    println!(
+        "sequential typing: gap buffer {:>10.2?}  rope {:>10.2?}",
+        sequential_typing::<TextStorage>(&text),
+        sequential_typing::<RopeStorage>(&text)
+    );
+}
@@ -1,11 +1,35 @@
 // WasmDocument wrapper for JavaScript bindings
 
-use crate::bindings::events::EventCallbacks;
-use crate::document::{Document, Position, Range};
-use crate::formatting::{BlockType, InlineFormat};
-use crate::operations::search::SearchQuery;
+use crate::bindings::events::{
+    self, ChangeEvent, EventBatching, EventCallbacks, HistoryState, fragmentation_to_js,
+};
+use crate::document::{
+    Annotation, AuditKind, AuditOptions, Bias, ControlCharPolicy, Decoration, Dictionary, Document,
+    Image, Position, Range, Snapshot, StorageBackend, StreamingLoad, TextChunks, WordList,
+};
+use crate::formatting::block::MAX_QUOTE_DEPTH;
+use crate::formatting::{BlockType, FormatAffinity, InlineFormat, ListStyle, TextDirection};
+use crate::operations::cleanup::CleanupOptions;
+use crate::operations::history::CommandHistory;
+use crate::operations::input_rules::{InputRule, InputRules};
+use crate::operations::search::{IncrementalSearch, SearchQuery, SearchState};
+use crate::operations::{CommandError, FormattedSegment, Operation};
 use crate::selection::Selection;
+use crate::selection::navigation::DEFAULT_NAVIGATION_LIMIT;
+use crate::serialization::html::{
+    HtmlExportOptions, HtmlFormatStyle, HtmlImportOptions, HtmlSanitizer, WhitespaceHandling,
+};
+use crate::serialization::json::JsonOptions;
+use crate::serialization::limits::ImportLimits;
+use crate::serialization::markdown::{
+    BulletMarker, EmphasisMarker, HardBreakStyle, HeadingStyle, MarkdownOptions, MarkdownSourceMap,
+};
+use crate::serialization::round_trip::RoundTripFormat;
+use crate::utils::segmenter::WordSegmenter;
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::throw_val;
 
 /// WASM-exposed wrapper around the Document struct
 ///
@@ -56,29 +80,339 @@ use wasm_bindgen::prelude::*;
 /// - Formats: 1.6KB
 /// - History: 6.4KB (100 commands)
 /// - Total: ~48KB
+///
+/// ## Calls from callbacks
+///
+/// Methods borrow the document for as long as they run, so a callback they
+/// trigger cannot call back into the same document; such calls throw at
+/// once. Wrap them in `WasmDocument.defer` to run them after the method
+/// has returned.
 #[wasm_bindgen]
 pub struct WasmDocument {
+    /// Borrowed by each method for as long as it runs
+    state: RefCell<DocumentState>,
+}
+
+/// The document and binding state behind a `WasmDocument`
+struct DocumentState {
     inner: Document,
     callbacks: EventCallbacks,
+    /// Text collected by `appendChunk` since `beginStreamingLoad`
+    streaming: Option<StreamingLoad>,
+    /// Search started by `startSearch` that has not finished
+    search: Option<IncrementalSearch>,
 }
 
-#[wasm_bindgen]
 impl WasmDocument {
-    /// Creates a new empty document
-    #[wasm_bindgen(constructor)]
+    /// Creates a new empty document with default settings
+    ///
+    /// JavaScript callers use the `new WasmDocument(options)` constructor.
     pub fn new() -> Self {
+        Self::from_document(Document::new())
+    }
+
+    /// Wraps `inner` with no callbacks registered
+    fn from_document(inner: Document) -> Self {
         Self {
-            inner: Document::new(),
-            callbacks: EventCallbacks::new(),
+            state: RefCell::new(DocumentState {
+                inner,
+                callbacks: EventCallbacks::new(),
+                streaming: None,
+                search: None,
+            }),
+        }
+    }
+
+    /// Borrows the state for a method that reads it
+    ///
+    /// Fails if a method of this document is still running, which only
+    /// happens when a callback it triggered calls back in. Methods that
+    /// return a `Result` pass the error on, so wasm-bindgen releases its
+    /// hold on the document before the error reaches JavaScript.
+    fn try_state(&self) -> Result<Ref<'_, DocumentState>, JsValue> {
+        self.state
+            .try_borrow()
+            .map_err(|_| JsValue::from_str(events::REENTRANT_CALL_ERROR))
+    }
+
+    /// Borrows the state for a method that changes it
+    ///
+    /// Fails like `try_state` if a method of this document is still running.
+    fn try_state_mut(&self) -> Result<RefMut<'_, DocumentState>, JsValue> {
+        self.state
+            .try_borrow_mut()
+            .map_err(|_| JsValue::from_str(events::REENTRANT_CALL_ERROR))
+    }
+
+    /// Borrows the state for a method that reads it and cannot return an
+    /// error
+    ///
+    /// Throws the `try_state` error instead. Throwing skips destructors, so
+    /// the nested call's hold on the document leaks and it can no longer be
+    /// freed; use `try_state` wherever a `Result` can be returned.
+    fn state(&self) -> Ref<'_, DocumentState> {
+        self.try_state().unwrap_or_else(|err| throw_val(err))
+    }
+
+    /// Borrows the state for a method that changes it and cannot return an
+    /// error, throwing like `state`
+    fn state_mut(&self) -> RefMut<'_, DocumentState> {
+        self.try_state_mut().unwrap_or_else(|err| throw_val(err))
+    }
+
+    /// Answers one request passed to `query`
+    fn answer_query(&self, request: &JsValue) -> Result<JsValue, JsValue> {
+        let get = |key: &str| js_sys::Reflect::get(request, &JsValue::from_str(key));
+        let query_type = match request.as_string() {
+            Some(query_type) => query_type,
+            None if request.is_object() => get("type")?
+                .as_string()
+                .ok_or_else(|| JsValue::from_str("missing string 'type'"))?,
+            None => {
+                return Err(JsValue::from_str(
+                    "requests must be names or { type, ... } objects",
+                ));
+            }
+        };
+
+        match query_type.as_str() {
+            "formatsAt" => {
+                let position = required_count(&get("position")?, "position")?;
+                Ok(self.get_formats_at(position).into())
+            }
+            "blockTypeAt" => {
+                let position = required_count(&get("position")?, "position")?;
+                Ok(self.get_block_type_at(position).into())
+            }
+            "textInRange" => {
+                let start = required_count(&get("start")?, "start")?;
+                let end = required_count(&get("end")?, "end")?;
+                self.get_text_in_range(start, end).map(JsValue::from)
+            }
+            "selection" => Ok(self.get_selection()),
+            _ => Err(JsValue::from_str(&format!(
+                "unknown request type '{}'",
+                query_type
+            ))),
+        }
+    }
+}
+
+impl DocumentState {
+    /// Triggers change callbacks once per edit recorded since the last
+    /// notification, then history callbacks if undo or redo availability
+    /// changed
+    fn notify_change(&mut self) {
+        let version = self.inner.version();
+        for change in self.inner.take_changes() {
+            self.callbacks
+                .trigger_change_callbacks(ChangeEvent::from_change(change, version));
+        }
+        self.notify_history();
+        self.notify_fragmentation();
+        for paste in self.inner.take_suppressed_pastes() {
+            self.callbacks.trigger_duplicate_paste_callbacks(&paste);
+        }
+    }
+
+    /// Reports the format fragmentation to fragmentation callbacks
+    fn notify_fragmentation(&self) {
+        if self.callbacks.has_fragmentation_callbacks() {
+            self.callbacks
+                .trigger_fragmentation_callbacks(&self.inner.fragmentation_stats());
+        }
+    }
+
+    /// Reports the current undo/redo state to history callbacks
+    fn notify_history(&self) {
+        self.callbacks.trigger_history_callbacks(HistoryState {
+            can_undo: self.inner.can_undo(),
+            can_redo: self.inner.can_redo(),
+            undo_description: self.inner.undo_label(),
+            redo_description: self.inner.redo_label(),
+        });
+    }
+
+    /// Converts the result of a find that selects its match to a match
+    /// object or null, notifying selection listeners if a match was selected
+    fn found_match_to_js(
+        &mut self,
+        found: Result<Option<Range>, String>,
+    ) -> Result<JsValue, JsValue> {
+        let found = found.map_err(|e| JsValue::from_str(&format!("Search failed: {}", e)))?;
+        let Some(range) = found else {
+            return Ok(JsValue::NULL);
+        };
+        self.callbacks.trigger_selection_callbacks();
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"start".into(), &range.start_offset().into()).unwrap();
+        js_sys::Reflect::set(&obj, &"end".into(), &range.end_offset().into()).unwrap();
+        Ok(obj.into())
+    }
+}
+
+impl Default for WasmDocument {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An opaque copy of a document's text and formatting, taken with
+/// `WasmDocument.snapshot()` and compared with `WasmDocument.diff()`
+#[wasm_bindgen]
+pub struct WasmSnapshot {
+    inner: Snapshot,
+}
+
+#[wasm_bindgen]
+impl WasmSnapshot {
+    /// Returns the document version when the snapshot was taken
+    #[wasm_bindgen(getter)]
+    pub fn version(&self) -> u64 {
+        self.inner.version()
+    }
+}
+
+/// A cursor over a document's text, taken with `WasmDocument.getTextChunks()`
+#[wasm_bindgen]
+pub struct WasmTextChunks {
+    inner: TextChunks,
+}
+
+#[wasm_bindgen]
+impl WasmTextChunks {
+    /// Returns the next chunk of text, or undefined once all of it has
+    /// been returned
+    #[wasm_bindgen(js_name = nextChunk)]
+    pub fn next_chunk(&mut self) -> Option<String> {
+        self.inner.next()
+    }
+}
+
+#[wasm_bindgen]
+impl WasmDocument {
+    /// Creates a new document, optionally configured in one call
+    ///
+    /// All options are optional, and no callbacks fire while they are applied.
+    ///
+    /// # Arguments
+    /// * `options` - An object with:
+    ///   - `content`: Initial content as plain text, JSON, HTML or Markdown
+    ///   - `contentFormat`: "text", "json", "html", "markdown" or "auto"
+    ///     (default), which detects JSON and HTML and treats anything else as text
+    ///   - `historyLimit`: Maximum number of undo steps
+    ///   - `maxLength`: Maximum document length in characters
+    ///   - `readOnly`: Whether local edits are rejected
+    ///   - `sanitizer`: `{ disallowedTags, disallowedAttributes }` string arrays
+    ///     narrowing the HTML whitelist for the initial content and pastes
+    ///
+    /// # Errors
+    /// Returns a JsValue error if an option has the wrong type, the content
+    /// fails to parse, or the content is longer than `maxLength`
+    ///
+    /// # Example
+    /// ```javascript
+    /// const doc = new WasmDocument({
+    ///   content: "<p>Hello <strong>World</strong></p>",
+    ///   historyLimit: 50,
+    ///   maxLength: 10000,
+    ///   readOnly: false,
+    ///   sanitizer: { disallowedTags: ["img"] },
+    /// });
+    /// ```
+    #[wasm_bindgen(constructor)]
+    pub fn with_options(options: Option<js_sys::Object>) -> Result<WasmDocument, JsValue> {
+        let Some(options) = options else {
+            return Ok(Self::new());
+        };
+        let get = |key: &str| js_sys::Reflect::get(&options, &JsValue::from_str(key));
+
+        let sanitizer = parse_sanitizer_options(&get("sanitizer")?)?;
+        let content = optional_string(&get("content")?, "content")?;
+        let format = optional_string(&get("contentFormat")?, "contentFormat")?;
+        let mut inner = match content {
+            Some(content) => {
+                let format = format.unwrap_or_else(|| "auto".to_string());
+                parse_initial_content(&content, &format, sanitizer.as_ref())?
+            }
+            None => Document::new(),
+        };
+
+        if let Some(limit) = optional_count(&get("historyLimit")?, "historyLimit")? {
+            inner.set_history_limit(limit);
+        }
+        let max_length = optional_count(&get("maxLength")?, "maxLength")?;
+        if let Some(limit) = max_length
+            && inner.get_length() > limit
+        {
+            return Err(JsValue::from_str(&format!(
+                "Initial content length {} exceeds maxLength {}",
+                inner.get_length(),
+                limit
+            )));
+        }
+        inner.set_max_length(max_length);
+        let read_only = get("readOnly")?;
+        if !read_only.is_undefined() {
+            let read_only = read_only
+                .as_bool()
+                .ok_or_else(|| JsValue::from_str("'readOnly' must be a boolean"))?;
+            inner.set_read_only(read_only);
         }
+        inner.set_html_sanitizer(sanitizer);
+
+        Ok(Self::from_document(inner))
     }
 
     /// Creates a document from existing text
     #[wasm_bindgen(js_name = fromText)]
     pub fn from_text(text: &str) -> Self {
-        Self {
-            inner: Document::from_text(text),
-            callbacks: EventCallbacks::new(),
+        Self::from_document(Document::from_text(text))
+    }
+
+    /// Creates a new empty document using the given text storage backend
+    ///
+    /// # Arguments
+    /// * `backend` - "gapBuffer" (default for `new`) or "rope"
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the backend name is unknown
+    ///
+    /// # Example
+    /// ```javascript
+    /// // Large documents with scattered edits benefit from the rope
+    /// const doc = WasmDocument.withBackend("rope");
+    /// ```
+    #[wasm_bindgen(js_name = withBackend)]
+    pub fn with_backend(backend: &str) -> Result<WasmDocument, JsValue> {
+        Ok(Self::from_document(Document::with_backend(
+            parse_storage_backend(backend)?,
+        )))
+    }
+
+    /// Creates a document from existing text using the given text storage backend
+    ///
+    /// # Arguments
+    /// * `text` - The initial document text
+    /// * `backend` - "gapBuffer" or "rope"
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the backend name is unknown
+    #[wasm_bindgen(js_name = fromTextWithBackend)]
+    pub fn from_text_with_backend(text: &str, backend: &str) -> Result<WasmDocument, JsValue> {
+        Ok(Self::from_document(Document::from_text_with_backend(
+            text,
+            parse_storage_backend(backend)?,
+        )))
+    }
+
+    /// Returns the name of the text storage backend ("gapBuffer" or "rope")
+    #[wasm_bindgen(js_name = getStorageBackend)]
+    pub fn get_storage_backend(&self) -> String {
+        let this = self.state();
+        match this.inner.storage_backend() {
+            StorageBackend::GapBuffer => "gapBuffer".to_string(),
+            StorageBackend::Rope => "rope".to_string(),
         }
     }
 
@@ -91,15 +425,81 @@ impl WasmDocument {
     /// # Errors
     /// Returns a JsValue error if the position is invalid
     #[wasm_bindgen(js_name = insertText)]
-    pub fn insert_text(&mut self, text: &str, position: usize) -> Result<(), JsValue> {
+    pub fn insert_text(&self, text: &str, position: usize) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
         let pos = Position::new(position);
-        self.inner
+        this.inner
             .insert_text(pos, text)
             .map_err(|e| JsValue::from_str(&format!("Insert failed: {}", e)))?;
-        self.callbacks.trigger_change_callbacks();
+        this.notify_change();
         Ok(())
     }
 
+    /// Inserts text made of segments with their own formats, recorded as a
+    /// single undo step
+    ///
+    /// Each segment gets exactly the formats it lists, without picking up
+    /// formats from the text it is inserted next to.
+    ///
+    /// # Arguments
+    /// * `position` - The character offset where the text should be inserted
+    /// * `segments` - An array of `{ text, formats?, blockType? }` objects.
+    ///   `formats` entries are names accepted by `applyFormat`, or
+    ///   `{ type, value }` objects for formats that carry a value. A
+    ///   `blockType`, as accepted by `setBlockType`, applies to every line
+    ///   the segment touches.
+    ///
+    /// # Returns
+    /// `{ start, end }` of the inserted text
+    ///
+    /// # Errors
+    /// Returns a JsValue error naming the first invalid segment, or if the
+    /// position is invalid or the document is read-only; nothing is
+    /// inserted in that case
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.insertFormattedText(0, [
+    ///     { text: "Title\n", blockType: "heading1" },
+    ///     { text: "Read the " },
+    ///     { text: "docs", formats: ["bold", { type: "link", value: "https://example.com" }] },
+    /// ]);
+    /// ```
+    #[wasm_bindgen(js_name = insertFormattedText)]
+    pub fn insert_formatted_text(
+        &self,
+        position: usize,
+        segments: js_sys::Array,
+    ) -> Result<JsValue, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let segments = segments
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| {
+                parse_segment(&segment).map_err(|e| {
+                    JsValue::from_str(&format!(
+                        "Invalid segment at index {}: {}",
+                        index,
+                        e.as_string().unwrap_or_default()
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let range = this
+            .inner
+            .insert_formatted_text(Position::new(position), &segments)
+            .map_err(|e| JsValue::from_str(&format!("Insert failed: {}", e)))?;
+        if !range.is_empty() {
+            this.notify_change();
+        }
+
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"start".into(), &range.start_offset().into()).unwrap();
+        js_sys::Reflect::set(&obj, &"end".into(), &range.end_offset().into()).unwrap();
+        Ok(obj.into())
+    }
+
     /// Deletes text in the specified range
     ///
     /// # Arguments
@@ -109,12 +509,13 @@ impl WasmDocument {
     /// # Errors
     /// Returns a JsValue error if the range is invalid
     #[wasm_bindgen(js_name = deleteRange)]
-    pub fn delete_range(&mut self, start: usize, end: usize) -> Result<(), JsValue> {
+    pub fn delete_range(&self, start: usize, end: usize) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
         let range = Range::from_offsets(start, end);
-        self.inner
+        this.inner
             .delete_range(range)
             .map_err(|e| JsValue::from_str(&format!("Delete failed: {}", e)))?;
-        self.callbacks.trigger_change_callbacks();
+        this.notify_change();
         Ok(())
     }
 
@@ -128,1340 +529,5849 @@ impl WasmDocument {
     /// # Errors
     /// Returns a JsValue error if the range is invalid
     #[wasm_bindgen(js_name = replaceRange)]
-    pub fn replace_range(&mut self, start: usize, end: usize, text: &str) -> Result<(), JsValue> {
+    pub fn replace_range(&self, start: usize, end: usize, text: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
         let range = Range::from_offsets(start, end);
-        self.inner
+        this.inner
             .replace_range(range, text)
             .map_err(|e| JsValue::from_str(&format!("Replace failed: {}", e)))?;
-        self.callbacks.trigger_change_callbacks();
+        this.notify_change();
         Ok(())
     }
 
-    /// Returns the entire content of the document
-    #[wasm_bindgen(js_name = getContent)]
-    pub fn get_content(&self) -> String {
-        self.inner.get_content()
-    }
-
-    /// Returns the length of the document in characters
-    #[wasm_bindgen(js_name = getLength)]
-    pub fn get_length(&self) -> usize {
-        self.inner.get_length()
-    }
-
-    /// Returns the number of words in the document
+    /// Checks whether `insertText(text, position)` would succeed, without
+    /// changing the document
     ///
-    /// Words are defined as sequences of non-whitespace characters
-    /// separated by whitespace. This matches the behavior of most
-    /// word processors.
+    /// Useful to validate drops and pastes before performing them.
     ///
-    /// # Returns
-    /// The number of words in the document
+    /// # Arguments
+    /// * `position` - The character offset to insert at
+    /// * `text` - The text to insert
     ///
-    /// # Example
-    /// ```javascript
-    /// const doc = new WasmDocument();
-    /// doc.insertText("Hello world", 0);
-    /// console.log(doc.getWordCount()); // 2
-    /// ```
-    #[wasm_bindgen(js_name = getWordCount)]
-    pub fn get_word_count(&self) -> usize {
-        let content = self.inner.get_content();
-        if content.trim().is_empty() {
-            return 0;
-        }
-        content
-            .split_whitespace()
-            .filter(|word| !word.is_empty())
-            .count()
+    /// # Returns
+    /// `{ ok: true, start, end }` with the range the inserted text would
+    /// occupy once control characters are handled, or `{ ok: false, reason,
+    /// message }` where reason is "readOnly", "invalidPosition" or "maxLength"
+    #[wasm_bindgen(js_name = canInsertAt)]
+    pub fn can_insert_at(&self, position: usize, text: &str) -> JsValue {
+        let this = self.state();
+        edit_check_to_js(this.inner.check_insert(Position::new(position), text))
     }
 
-    /// Returns the number of lines in the document
+    /// Checks whether `deleteRange(start, end)` would succeed, without
+    /// changing the document
     ///
-    /// Lines are separated by newline characters (\n).
-    /// An empty document has 1 line.
+    /// # Arguments
+    /// * `start` - The start position of the range to delete
+    /// * `end` - The end position of the range to delete
     ///
     /// # Returns
-    /// The number of lines in the document
-    ///
-    /// # Example
-    /// ```javascript
-    /// const doc = new WasmDocument();
-    /// doc.insertText("Line 1\nLine 2\nLine 3", 0);
-    /// console.log(doc.getLineCount()); // 3
-    /// ```
-    #[wasm_bindgen(js_name = getLineCount)]
-    pub fn get_line_count(&self) -> usize {
-        let content = self.inner.get_content();
-        if content.is_empty() {
-            return 1;
-        }
-        content.chars().filter(|&c| c == '\n').count() + 1
+    /// `{ ok: true, start, end }` with the normalized range that would be
+    /// deleted, or `{ ok: false, reason, message }` where reason is
+    /// "readOnly" or "invalidRange"
+    #[wasm_bindgen(js_name = canDeleteRange)]
+    pub fn can_delete_range(&self, start: usize, end: usize) -> JsValue {
+        let this = self.state();
+        edit_check_to_js(this.inner.check_delete(Range::from_offsets(start, end)))
     }
 
-    /// Returns the text within the specified range
+    /// Inserts an inline image at the specified position
     ///
-    /// # Arguments
-    /// * `start` - The start position of the range
-    /// * `end` - The end position of the range
+    /// The image occupies one character in the document content.
     ///
-    /// # Errors
-    /// Returns a JsValue error if the range is invalid
-    #[wasm_bindgen(js_name = getTextInRange)]
-    pub fn get_text_in_range(&self, start: usize, end: usize) -> Result<String, JsValue> {
-        if end > self.inner.get_length() {
-            return Err(JsValue::from_str(&format!(
-                "Range end {} exceeds document length {}",
-                end,
-                self.inner.get_length()
-            )));
-        }
-        let range = Range::from_offsets(start, end);
-        Ok(self.inner.get_text_in_range(range))
-    }
-
-    /// Returns the current version of the document
-    #[wasm_bindgen(js_name = getVersion)]
-    pub fn get_version(&self) -> u64 {
-        self.inner.version()
-    }
-
-    /// Returns true if the document is empty
-    #[wasm_bindgen(js_name = isEmpty)]
-    pub fn is_empty(&self) -> bool {
-        self.inner.is_empty()
-    }
-
-    /// Undoes the last operation
+    /// # Arguments
+    /// * `position` - The character offset where the image should be inserted
+    /// * `src` - The image URL (http(s), relative, or a raster `data:` URL)
+    /// * `alt` - Alternative text
+    /// * `width` - Optional width in pixels
+    /// * `height` - Optional height in pixels
     ///
     /// # Errors
-    /// Returns a JsValue error if there is nothing to undo
-    pub fn undo(&mut self) -> Result<(), JsValue> {
-        self.inner
-            .undo()
-            .map_err(|e| JsValue::from_str(&format!("Undo failed: {}", e)))?;
-        self.callbacks.trigger_change_callbacks();
+    /// Returns a JsValue error if the position is invalid or the source is unsafe
+    #[wasm_bindgen(js_name = insertImage)]
+    pub fn insert_image(
+        &self,
+        position: usize,
+        src: &str,
+        alt: &str,
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let image = Image::new(src, alt).with_size(width, height);
+        this.inner
+            .insert_image(Position::new(position), image)
+            .map_err(|e| JsValue::from_str(&format!("Insert image failed: {}", e)))?;
+        this.notify_change();
         Ok(())
     }
 
-    /// Redoes the last undone operation
+    /// Replaces the attributes of the image at the specified position
+    ///
+    /// # Arguments
+    /// * `position` - The character offset of the image
+    /// * `src` - The image URL
+    /// * `alt` - Alternative text
+    /// * `width` - Optional width in pixels
+    /// * `height` - Optional height in pixels
     ///
     /// # Errors
-    /// Returns a JsValue error if there is nothing to redo
-    pub fn redo(&mut self) -> Result<(), JsValue> {
-        self.inner
-            .redo()
-            .map_err(|e| JsValue::from_str(&format!("Redo failed: {}", e)))?;
-        self.callbacks.trigger_change_callbacks();
+    /// Returns a JsValue error if there is no image at the position or the source is unsafe
+    #[wasm_bindgen(js_name = updateImage)]
+    pub fn update_image(
+        &self,
+        position: usize,
+        src: &str,
+        alt: &str,
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let image = Image::new(src, alt).with_size(width, height);
+        this.inner
+            .update_image(Position::new(position), image)
+            .map_err(|e| JsValue::from_str(&format!("Update image failed: {}", e)))?;
+        this.notify_change();
         Ok(())
     }
 
-    /// Returns true if there are operations that can be undone
-    #[wasm_bindgen(js_name = canUndo)]
-    pub fn can_undo(&self) -> bool {
-        self.inner.can_undo()
-    }
-
-    /// Returns true if there are operations that can be redone
-    #[wasm_bindgen(js_name = canRedo)]
-    pub fn can_redo(&self) -> bool {
-        self.inner.can_redo()
+    /// Gets the image at the specified position
+    ///
+    /// Returns a JsValue object with `src`, `alt`, `width` and `height`
+    /// properties (dimensions may be null), or null if there is no image
+    #[wasm_bindgen(js_name = getImageAt)]
+    pub fn get_image_at(&self, position: usize) -> JsValue {
+        let this = self.state();
+        let Some(image) = this.inner.get_image_at(Position::new(position)) else {
+            return JsValue::NULL;
+        };
+        let dimension = |value: Option<u32>| value.map_or(JsValue::NULL, JsValue::from);
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"src".into(), &image.src.as_str().into()).unwrap();
+        js_sys::Reflect::set(&obj, &"alt".into(), &image.alt.as_str().into()).unwrap();
+        js_sys::Reflect::set(&obj, &"width".into(), &dimension(image.width)).unwrap();
+        js_sys::Reflect::set(&obj, &"height".into(), &dimension(image.height)).unwrap();
+        obj.into()
     }
 
-    /// Applies a format to the specified range
+    /// Attaches an annotation, such as a comment, to a range
+    ///
+    /// The annotation moves with the text around it as the document is
+    /// edited. Adding an annotation with an existing id replaces it.
     ///
     /// # Arguments
-    /// * `format_type` - The type of format ("bold", "italic", "underline", "strikethrough", "code")
     /// * `start` - The start position of the range
     /// * `end` - The end position of the range
+    /// * `id` - An identifier, unique within the document
+    /// * `author` - Optional author of the annotation
+    /// * `payload` - Optional application data, such as a JSON string
     ///
     /// # Errors
-    /// Returns a JsValue error if the format type is invalid or range is invalid
-    #[wasm_bindgen(js_name = applyFormat)]
-    pub fn apply_format(
-        &mut self,
-        format_type: &str,
+    /// Returns a JsValue error if the range is empty or out of bounds
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.addAnnotation(0, 5, "c1", "ann", JSON.stringify({ text: "Typo?" }));
+    /// ```
+    #[wasm_bindgen(js_name = addAnnotation)]
+    pub fn add_annotation(
+        &self,
         start: usize,
         end: usize,
+        id: &str,
+        author: Option<String>,
+        payload: Option<String>,
     ) -> Result<(), JsValue> {
-        let format = parse_inline_format(format_type)?;
-        let range = Range::from_offsets(start, end);
-        self.inner.apply_format(range, format);
-        self.callbacks.trigger_change_callbacks();
+        let mut this = self.try_state_mut()?;
+        let annotation = Annotation {
+            id: id.to_string(),
+            author,
+            payload: payload.unwrap_or_default(),
+        };
+        this.inner
+            .add_annotation(Range::from_offsets(start, end), annotation)
+            .map_err(|e| JsValue::from_str(&format!("Add annotation failed: {}", e)))?;
+        this.notify_change();
         Ok(())
     }
 
-    /// Applies a format with a parameter (link, text color, background color)
-    ///
-    /// # Arguments
-    /// * `format_type` - The type of format ("link", "textColor", "text-color", "backgroundColor", "background-color")
-    /// * `value` - The value for the format (URL for link, color for colors)
-    /// * `start` - The start position of the range
-    /// * `end` - The end position of the range
+    /// Removes the annotation with the given id
     ///
     /// # Errors
-    /// Returns a JsValue error if the format type is invalid or range is invalid
-    #[wasm_bindgen(js_name = applyFormatWithValue)]
-    pub fn apply_format_with_value(
-        &mut self,
-        format_type: &str,
-        value: &str,
-        start: usize,
-        end: usize,
-    ) -> Result<(), JsValue> {
-        let format = match format_type {
-            "link" => InlineFormat::Link {
-                url: value.to_string(),
-            },
-            "textColor" | "text-color" => InlineFormat::TextColor {
-                color: value.to_string(),
-            },
-            "backgroundColor" | "background-color" => InlineFormat::BackgroundColor {
-                color: value.to_string(),
-            },
-            _ => {
-                return Err(JsValue::from_str(&format!(
-                    "Unknown format type with value: {}",
-                    format_type
-                )));
-            }
-        };
-        let range = Range::from_offsets(start, end);
-        self.inner.apply_format(range, format);
-        self.callbacks.trigger_change_callbacks();
+    /// Returns a JsValue error if there is no annotation with the id
+    #[wasm_bindgen(js_name = removeAnnotation)]
+    pub fn remove_annotation(&self, id: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .remove_annotation(id)
+            .map_err(|e| JsValue::from_str(&format!("Remove annotation failed: {}", e)))?;
+        this.notify_change();
         Ok(())
     }
 
-    /// Removes a format from the specified range
+    /// Gets the annotations covering the character at the specified position
+    ///
+    /// # Returns
+    /// A JavaScript array of objects with `id`, `author` (may be null),
+    /// `payload`, `start` and `end` properties
+    #[wasm_bindgen(js_name = getAnnotationsAt)]
+    pub fn get_annotations_at(&self, position: usize) -> js_sys::Array {
+        let this = self.state();
+        let annotations = this
+            .inner
+            .get_annotations_at(Position::new(position))
+            .into_iter()
+            .map(|(range, annotation)| {
+                let author = annotation
+                    .author
+                    .as_deref()
+                    .map_or(JsValue::NULL, JsValue::from);
+                let obj = js_sys::Object::new();
+                js_sys::Reflect::set(&obj, &"id".into(), &annotation.id.as_str().into()).unwrap();
+                js_sys::Reflect::set(&obj, &"author".into(), &author).unwrap();
+                js_sys::Reflect::set(&obj, &"payload".into(), &annotation.payload.as_str().into())
+                    .unwrap();
+                js_sys::Reflect::set(&obj, &"start".into(), &range.start_offset().into()).unwrap();
+                js_sys::Reflect::set(&obj, &"end".into(), &range.end_offset().into()).unwrap();
+                JsValue::from(obj)
+            });
+        js_sys::Array::from_iter(annotations)
+    }
+
+    /// Sets a named bookmark at the specified position
+    ///
+    /// The bookmark moves with later edits. Setting an existing id moves it.
+    ///
+    /// # Arguments
+    /// * `id` - Bookmark id
+    /// * `position` - The character offset to bookmark
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the position is out of bounds
+    #[wasm_bindgen(js_name = setBookmark)]
+    pub fn set_bookmark(&self, id: &str, position: usize) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .set_bookmark(id, Position::new(position))
+            .map_err(|e| JsValue::from_str(&format!("Set bookmark failed: {}", e)))
+    }
+
+    /// Gets the current position of a bookmark, or undefined if it does not exist
+    ///
+    /// # Arguments
+    /// * `id` - Bookmark id
+    #[wasm_bindgen(js_name = getBookmark)]
+    pub fn get_bookmark(&self, id: &str) -> Option<usize> {
+        let this = self.state();
+        this.inner.get_bookmark(id).map(|pos| pos.offset())
+    }
+
+    /// Deletes a bookmark
+    ///
+    /// # Arguments
+    /// * `id` - Bookmark id
+    ///
+    /// # Returns
+    /// True if the bookmark existed
+    #[wasm_bindgen(js_name = deleteBookmark)]
+    pub fn delete_bookmark(&self, id: &str) -> bool {
+        let mut this = self.state_mut();
+        this.inner.remove_bookmark(id)
+    }
+
+    /// Marks a range of text for spellcheckers to skip, such as a code span
+    /// or a proper noun
+    ///
+    /// The mark moves with later edits, is saved by `toJSON` and is written
+    /// by `toHTML` as `<span spellcheck="false">`. It is not part of the
+    /// undo history.
     ///
     /// # Arguments
-    /// * `format_type` - The type of format to remove
     /// * `start` - The start position of the range
     /// * `end` - The end position of the range
     ///
     /// # Errors
-    /// Returns a JsValue error if the format type is invalid or range is invalid
-    #[wasm_bindgen(js_name = removeFormat)]
-    pub fn remove_format(
-        &mut self,
-        format_type: &str,
-        start: usize,
-        end: usize,
-    ) -> Result<(), JsValue> {
-        let format = parse_inline_format(format_type)?;
-        let range = Range::from_offsets(start, end);
-        self.inner.remove_format(range, &format);
-        self.callbacks.trigger_change_callbacks();
-        Ok(())
+    /// Returns a JsValue error if the range is out of bounds
+    #[wasm_bindgen(js_name = markNoSpellcheck)]
+    pub fn mark_no_spellcheck(&self, start: usize, end: usize) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .mark_no_spellcheck(Range::from_offsets(start, end))
+            .map_err(|e| JsValue::from_str(&format!("Mark no spellcheck failed: {}", e)))
     }
 
-    /// Toggles a format on the specified range
+    /// Lets spellcheckers check a range of text again
     ///
     /// # Arguments
-    /// * `format_type` - The type of format to toggle
     /// * `start` - The start position of the range
     /// * `end` - The end position of the range
     ///
     /// # Errors
-    /// Returns a JsValue error if the format type is invalid or range is invalid
-    #[wasm_bindgen(js_name = toggleFormat)]
-    pub fn toggle_format(
-        &mut self,
-        format_type: &str,
-        start: usize,
-        end: usize,
-    ) -> Result<(), JsValue> {
-        let format = parse_inline_format(format_type)?;
-        let range = Range::from_offsets(start, end);
-        self.inner.toggle_format(range, format);
-        self.callbacks.trigger_change_callbacks();
-        Ok(())
+    /// Returns a JsValue error if the range is out of bounds
+    #[wasm_bindgen(js_name = clearNoSpellcheck)]
+    pub fn clear_no_spellcheck(&self, start: usize, end: usize) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .clear_no_spellcheck(Range::from_offsets(start, end))
+            .map_err(|e| JsValue::from_str(&format!("Clear no spellcheck failed: {}", e)))
+    }
+
+    /// Checks if the character at the offset is marked for spellcheckers to
+    /// skip
+    #[wasm_bindgen(js_name = isNoSpellcheckAt)]
+    pub fn is_no_spellcheck_at(&self, offset: usize) -> bool {
+        let this = self.state();
+        this.inner.is_no_spellcheck_at(Position::new(offset))
+    }
+
+    /// Gets the ranges marked for spellcheckers to skip, in order
+    ///
+    /// Returned shape: Array<{ start: number, end: number }>
+    #[wasm_bindgen(js_name = getNoSpellcheckRanges)]
+    pub fn get_no_spellcheck_ranges(&self) -> js_sys::Array {
+        let this = self.state();
+        let ranges = this.inner.no_spellcheck_ranges().iter().map(|range| {
+            let obj = js_sys::Object::new();
+            js_sys::Reflect::set(&obj, &"start".into(), &range.start_offset().into()).unwrap();
+            js_sys::Reflect::set(&obj, &"end".into(), &range.end_offset().into()).unwrap();
+            JsValue::from(obj)
+        });
+        js_sys::Array::from_iter(ranges)
     }
 
-    /// Gets all formats at the specified position
+    /// Marks a range of text for an application feature, such as a linter
+    /// warning or a collaborator's highlight
     ///
-    /// Returns a JsValue array of format type strings
+    /// The decoration moves with later edits and is saved by `toJSON`. It
+    /// does not change the text, its formatting or `toHTML`, and is not
+    /// part of the undo history. Any number of decorations may cover the
+    /// same text.
     ///
     /// # Arguments
-    /// * `position` - The character offset to query
-    #[wasm_bindgen(js_name = getFormatsAt)]
-    pub fn get_formats_at(&self, position: usize) -> js_sys::Array {
-        let pos = Position::new(position);
-        let formats = self.inner.get_formats_at(pos);
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
+    /// * `kind` - Kind chosen by the application
+    /// * `attributes_json` - Optional JSON object of string attributes
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the range is empty or out of bounds, or the
+    /// attributes are not a JSON object of strings
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.addDecoration(0, 3, "lint", JSON.stringify({ message: "Typo" }));
+    /// ```
+    #[wasm_bindgen(js_name = addDecoration)]
+    pub fn add_decoration(
+        &self,
+        start: usize,
+        end: usize,
+        kind: &str,
+        attributes_json: Option<String>,
+    ) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let mut decoration = Decoration::new(kind);
+        if let Some(json) = attributes_json {
+            decoration.attributes = serde_json::from_str(&json).map_err(|_| {
+                JsValue::from_str(
+                    "Add decoration failed: attributes must be a JSON object of strings",
+                )
+            })?;
+        }
+        this.inner
+            .add_decoration(Range::from_offsets(start, end), decoration)
+            .map_err(|e| JsValue::from_str(&format!("Add decoration failed: {}", e)))
+    }
 
-        let format_values: Vec<JsValue> = formats.iter().map(|f| format_to_js_value(f)).collect();
-        js_sys::Array::from_iter(format_values)
+    /// Removes the decorations of the given kind overlapping a range
+    ///
+    /// # Returns
+    /// The number of decorations removed
+    #[wasm_bindgen(js_name = removeDecorations)]
+    pub fn remove_decorations(&self, kind: &str, start: usize, end: usize) -> usize {
+        let mut this = self.state_mut();
+        this.inner
+            .remove_decorations(kind, Range::from_offsets(start, end))
     }
 
-    /// Returns formats at position as a plain array of strings (debug-friendly)
-    #[wasm_bindgen(js_name = getFormatsAtStrings)]
-    pub fn get_formats_at_strings(&self, position: usize) -> js_sys::Array {
-        let pos = Position::new(position);
-        let formats = self.inner.get_formats_at(pos);
-        let strings: Vec<JsValue> = formats
+    /// Replaces the transient decorations
+    ///
+    /// Transient decorations are highlights the app recomputes as it goes,
+    /// such as search hits, spelling errors or collaborator cursors. They
+    /// move with edits but are never saved, unlike decorations added with
+    /// `addDecoration`. `toHTMLRange` wraps the text they cover in
+    /// `<mark data-deco-id="...">` elements.
+    ///
+    /// # Arguments
+    /// * `decorations` - An array of `{ id, start, end }` objects
+    ///
+    /// # Errors
+    /// Returns a JsValue error naming the first invalid entry, or if a range
+    /// is empty or out of bounds; the current decorations are kept in that
+    /// case
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.setDecorations(hits.map((hit, i) => ({ id: `hit-${i}`, start: hit.start, end: hit.end })));
+    /// ```
+    #[wasm_bindgen(js_name = setDecorations)]
+    pub fn set_decorations(&self, decorations: js_sys::Array) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let decorations = decorations
             .iter()
-            .map(|f| match f {
-                InlineFormat::Bold => JsValue::from_str("bold"),
-                InlineFormat::Italic => JsValue::from_str("italic"),
-                InlineFormat::Underline => JsValue::from_str("underline"),
-                InlineFormat::Strikethrough => JsValue::from_str("strikethrough"),
-                InlineFormat::Code => JsValue::from_str("code"),
-                InlineFormat::Link { .. } => JsValue::from_str("link"),
-                InlineFormat::TextColor { .. } => JsValue::from_str("textColor"),
-                InlineFormat::BackgroundColor { .. } => JsValue::from_str("backgroundColor"),
+            .enumerate()
+            .map(|(index, entry)| {
+                parse_transient_decoration(&entry).map_err(|e| {
+                    JsValue::from_str(&format!(
+                        "Invalid decoration at index {}: {}",
+                        index,
+                        e.as_string().unwrap_or_default()
+                    ))
+                })
             })
-            .collect();
-        js_sys::Array::from_iter(strings)
+            .collect::<Result<Vec<_>, _>>()?;
+        this.inner
+            .set_transient_decorations(decorations)
+            .map_err(|e| JsValue::from_str(&format!("Set decorations failed: {}", e)))
     }
 
-    /// Debug helper: returns all format runs with start/end and formats
-    /// Returned shape: Array of objects { start: number, end: number, formats: Array<string|object> }
-    #[wasm_bindgen(js_name = getFormatRunsDebug)]
-    pub fn get_format_runs_debug(&self) -> JsValue {
-        let runs = self.inner.formats().get_runs();
-        let out = js_sys::Array::new();
+    /// Removes all transient decorations set with `setDecorations`
+    #[wasm_bindgen(js_name = clearDecorations)]
+    pub fn clear_decorations(&self) {
+        let mut this = self.state_mut();
+        this.inner.clear_transient_decorations();
+    }
 
-        for run in runs.iter() {
-            let obj = js_sys::Object::new();
-            let start = run.range.start_offset() as u32;
-            let end = run.range.end_offset() as u32;
+    /// Gets the decorations covering the character at the specified position
+    ///
+    /// Returned shape: Array<{ kind: string, attributes: object, start:
+    /// number, end: number }>
+    #[wasm_bindgen(js_name = getDecorationsAt)]
+    pub fn get_decorations_at(&self, position: usize) -> js_sys::Array {
+        let this = self.state();
+        let decorations = this
+            .inner
+            .get_decorations_at(Position::new(position))
+            .into_iter()
+            .map(|(range, decoration)| {
+                let attributes = js_sys::Object::new();
+                for (key, value) in &decoration.attributes {
+                    js_sys::Reflect::set(&attributes, &key.as_str().into(), &value.as_str().into())
+                        .unwrap();
+                }
+                let obj = js_sys::Object::new();
+                js_sys::Reflect::set(&obj, &"kind".into(), &decoration.kind.as_str().into())
+                    .unwrap();
+                js_sys::Reflect::set(&obj, &"attributes".into(), &attributes).unwrap();
+                js_sys::Reflect::set(&obj, &"start".into(), &range.start_offset().into()).unwrap();
+                js_sys::Reflect::set(&obj, &"end".into(), &range.end_offset().into()).unwrap();
+                JsValue::from(obj)
+            });
+        js_sys::Array::from_iter(decorations)
+    }
 
-            // Build formats array
-            let fmts = js_sys::Array::new();
-            for f in run.formats.iter() {
-                fmts.push(&format_to_js_value(f));
-            }
+    /// Stores application data under a name in the `extensions` section of
+    /// `toJSON`, replacing any data stored under that name
+    ///
+    /// The data is kept as given when the document is saved and loaded, so
+    /// features built on top of the editor need no file of their own. Use a
+    /// namespaced name such as "com.example.outline".
+    ///
+    /// # Errors
+    /// Returns a JsValue error if `data_json` is not valid JSON
+    #[wasm_bindgen(js_name = setJSONExtension)]
+    pub fn set_json_extension(&self, name: &str, data_json: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .set_json_extension(name, data_json)
+            .map_err(|e| JsValue::from_str(&format!("Set JSON extension failed: {}", e)))
+    }
 
-            js_sys::Reflect::set(&obj, &"start".into(), &JsValue::from_f64(start as f64)).unwrap();
-            js_sys::Reflect::set(&obj, &"end".into(), &JsValue::from_f64(end as f64)).unwrap();
-            js_sys::Reflect::set(&obj, &"formats".into(), &fmts.into()).unwrap();
+    /// Gets the application data stored under a name as a JSON string, or
+    /// undefined if there is none
+    #[wasm_bindgen(js_name = getJSONExtension)]
+    pub fn get_json_extension(&self, name: &str) -> Option<String> {
+        let this = self.state();
+        this.inner.get_json_extension(name)
+    }
 
-            out.push(&obj);
+    /// Removes the application data stored under a name
+    ///
+    /// # Returns
+    /// True if there was any
+    #[wasm_bindgen(js_name = removeJSONExtension)]
+    pub fn remove_json_extension(&self, name: &str) -> bool {
+        let mut this = self.state_mut();
+        this.inner.remove_json_extension(name)
+    }
+
+    /// Scans the document for content that is risky to publish
+    ///
+    /// Reports links, images and quote citations with unsafe URLs such as
+    /// `javascript:`, which can be present in documents loaded from JSON or
+    /// built through the API; runs of non-whitespace characters longer than
+    /// `max_token_length` (256 by default); and control, bidirectional
+    /// override and invisible characters. The document is not changed.
+    ///
+    /// Returned shape: Array<{ kind: "unsafeUrl" | "longToken" |
+    /// "controlCharacter", start: number, end: number, detail: string }>,
+    /// ordered by start. `detail` holds the URL, the token length, or the
+    /// code points found, such as "U+202E".
+    #[wasm_bindgen(js_name = auditDocument)]
+    pub fn audit_document(&self, max_token_length: Option<usize>) -> js_sys::Array {
+        let this = self.state();
+        let mut options = AuditOptions::new();
+        if let Some(max_token_length) = max_token_length {
+            options = options.max_token_length(max_token_length);
         }
-
-        out.into()
+        let findings = this.inner.audit(&options).into_iter().map(|finding| {
+            let kind = match finding.kind {
+                AuditKind::UnsafeUrl => "unsafeUrl",
+                AuditKind::LongToken => "longToken",
+                AuditKind::ControlCharacter => "controlCharacter",
+            };
+            let obj = js_sys::Object::new();
+            js_sys::Reflect::set(&obj, &"kind".into(), &kind.into()).unwrap();
+            js_sys::Reflect::set(&obj, &"start".into(), &finding.range.start_offset().into())
+                .unwrap();
+            js_sys::Reflect::set(&obj, &"end".into(), &finding.range.end_offset().into()).unwrap();
+            js_sys::Reflect::set(&obj, &"detail".into(), &finding.detail.into()).unwrap();
+            JsValue::from(obj)
+        });
+        js_sys::Array::from_iter(findings)
     }
 
-    /// Sets the block type for the specified range
+    /// Exports the document to a format, imports it again and reports what
+    /// the format loses for this document
+    ///
+    /// Use it to warn before exporting, such as "Markdown will lose underline
+    /// and colors". When the text comes back unchanged, formats and block
+    /// types are compared character by character and line by line;
+    /// otherwise only those missing from the result entirely are reported.
     ///
     /// # Arguments
-    /// * `block_type` - The type of block ("paragraph", "heading1"-"heading6", "bulletList", "numberedList", "blockQuote", "codeBlock")
-    /// * `start` - The start position of the range
-    /// * `end` - The end position of the range
+    /// * `format` - "json", "html", "markdown", "rtf", "delta" or "text"
+    ///
+    /// Returned shape: { format: string, lossless: boolean, textPreserved:
+    /// boolean, lostFormats: Array<{ format: string, value?: string,
+    /// ranges: Array<{ start: number, end: number }> }>, lostBlocks:
+    /// Array<{ line: number, blockType: string, resultType: string | null }> }
     ///
     /// # Errors
-    /// Returns a JsValue error if the block type is invalid or range is invalid
-    #[wasm_bindgen(js_name = setBlockType)]
-    pub fn set_block_type(
-        &mut self,
-        block_type: &str,
-        start: usize,
-        end: usize,
-    ) -> Result<(), JsValue> {
-        let block = parse_block_type(block_type)?;
-        let range = Range::from_offsets(start, end);
-        self.inner.set_block_type(range, block);
-        self.callbacks.trigger_change_callbacks();
-        Ok(())
+    /// Returns a JsValue error if the format is unknown or exporting or
+    /// importing fails
+    #[wasm_bindgen(js_name = verifyRoundTrip)]
+    pub fn verify_round_trip(&self, format: &str) -> Result<JsValue, JsValue> {
+        let this = self.try_state()?;
+        let round_trip_format = RoundTripFormat::from_name(format)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown round trip format: {}", format)))?;
+        let report = this
+            .inner
+            .verify_round_trip(round_trip_format)
+            .map_err(|e| JsValue::from_str(&format!("Verify round trip failed: {}", e)))?;
+
+        let lost_formats = report.lost_formats.iter().map(|loss| {
+            let obj = js_sys::Object::new();
+            js_sys::Reflect::set(&obj, &"format".into(), &format_to_js_value(&loss.format))
+                .unwrap();
+            if let InlineFormat::Link { url: value }
+            | InlineFormat::TextColor { color: value }
+            | InlineFormat::BackgroundColor { color: value } = &loss.format
+            {
+                js_sys::Reflect::set(&obj, &"value".into(), &value.into()).unwrap();
+            }
+            let ranges = loss.ranges.iter().map(|range| {
+                let range_obj = js_sys::Object::new();
+                js_sys::Reflect::set(&range_obj, &"start".into(), &range.start_offset().into())
+                    .unwrap();
+                js_sys::Reflect::set(&range_obj, &"end".into(), &range.end_offset().into())
+                    .unwrap();
+                JsValue::from(range_obj)
+            });
+            js_sys::Reflect::set(&obj, &"ranges".into(), &js_sys::Array::from_iter(ranges))
+                .unwrap();
+            JsValue::from(obj)
+        });
+        let lost_blocks = report.lost_blocks.iter().map(|loss| {
+            let obj = js_sys::Object::new();
+            js_sys::Reflect::set(&obj, &"line".into(), &loss.line.into()).unwrap();
+            js_sys::Reflect::set(
+                &obj,
+                &"blockType".into(),
+                &block_type_to_string(&loss.block_type).into(),
+            )
+            .unwrap();
+            let result_type = loss
+                .result
+                .as_ref()
+                .map_or(JsValue::NULL, |result| block_type_to_string(result).into());
+            js_sys::Reflect::set(&obj, &"resultType".into(), &result_type).unwrap();
+            JsValue::from(obj)
+        });
+
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"format".into(), &format.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"lossless".into(), &report.is_lossless().into()).unwrap();
+        js_sys::Reflect::set(&obj, &"textPreserved".into(), &report.text_preserved.into()).unwrap();
+        js_sys::Reflect::set(
+            &obj,
+            &"lostFormats".into(),
+            &js_sys::Array::from_iter(lost_formats),
+        )
+        .unwrap();
+        js_sys::Reflect::set(
+            &obj,
+            &"lostBlocks".into(),
+            &js_sys::Array::from_iter(lost_blocks),
+        )
+        .unwrap();
+        Ok(obj.into())
     }
 
-    /// Gets the block type at the specified position
+    /// Sets a document metadata value, such as the title or a front matter
+    /// field
     ///
-    /// Returns a string representing the block type
+    /// Metadata is written as front matter by `toMarkdown`, kept by
+    /// `toJSON` and written as `<meta>` tags by `toHTMLDocument`. The change
+    /// can be undone.
     ///
     /// # Arguments
-    /// * `position` - The character offset to query
-    #[wasm_bindgen(js_name = getBlockTypeAt)]
-    pub fn get_block_type_at(&self, position: usize) -> String {
-        let pos = Position::new(position);
-        let block_type = self.inner.get_block_type_at(pos);
-        block_type_to_string(&block_type)
+    /// * `key` - Metadata key
+    /// * `value` - Value to store
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the document is read-only
+    #[wasm_bindgen(js_name = setMetadata)]
+    pub fn set_metadata(&self, key: &str, value: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .set_metadata(key, value)
+            .map_err(|e| JsValue::from_str(&format!("Set metadata failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
     }
 
-    /// Sets the selection to the specified anchor and focus positions
+    /// Gets a document metadata value, or undefined if the key is not set
     ///
     /// # Arguments
-    /// * `anchor` - The anchor position (where selection started)
-    /// * `focus` - The focus position (where selection ends)
-    #[wasm_bindgen(js_name = setSelection)]
-    pub fn set_selection(&mut self, anchor: usize, focus: usize) {
-        let selection = Selection::new(Position::new(anchor), Position::new(focus));
-        self.inner.set_selection(selection);
-        self.callbacks.trigger_selection_callbacks();
+    /// * `key` - Metadata key
+    #[wasm_bindgen(js_name = getMetadata)]
+    pub fn get_metadata(&self, key: &str) -> Option<String> {
+        let this = self.state();
+        this.inner.get_metadata(key).map(str::to_string)
     }
 
-    /// Gets the current selection
+    /// Deletes a document metadata value
     ///
-    /// Returns a JsValue object with anchor and focus properties
-    #[wasm_bindgen(js_name = getSelection)]
-    pub fn get_selection(&self) -> JsValue {
-        let selection = self.inner.get_selection();
-        let obj = js_sys::Object::new();
-        js_sys::Reflect::set(&obj, &"anchor".into(), &selection.anchor.offset().into()).unwrap();
-        js_sys::Reflect::set(&obj, &"focus".into(), &selection.focus.offset().into()).unwrap();
-        obj.into()
+    /// # Arguments
+    /// * `key` - Metadata key
+    ///
+    /// # Returns
+    /// True if the key was set
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the document is read-only
+    #[wasm_bindgen(js_name = deleteMetadata)]
+    pub fn delete_metadata(&self, key: &str) -> Result<bool, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let removed = this
+            .inner
+            .remove_metadata(key)
+            .map_err(|e| JsValue::from_str(&format!("Delete metadata failed: {}", e)))?;
+        this.notify_change();
+        Ok(removed)
     }
 
-    /// Gets the text within the current selection
-    #[wasm_bindgen(js_name = getSelectedText)]
-    pub fn get_selected_text(&self) -> String {
-        self.inner.get_selected_text()
+    /// Sets several document metadata values as one undoable change
+    ///
+    /// # Arguments
+    /// * `meta_json` - JSON object of keys to set, such as
+    ///   `{"title": "Notes", "author": "Ada", "created": "2024-01-01"}`; a
+    ///   `null` value deletes the key
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the JSON is not an object of strings or
+    /// nulls, or the document is read-only
+    #[wasm_bindgen(js_name = setDocumentMeta)]
+    pub fn set_document_meta(&self, meta_json: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let invalid = || JsValue::from_str("Set document meta failed: expected a JSON object");
+        let meta: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(meta_json).map_err(|_| invalid())?;
+        let changes = meta
+            .into_iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(value) => Ok((key, Some(value))),
+                serde_json::Value::Null => Ok((key, None)),
+                _ => Err(JsValue::from_str(&format!(
+                    "Set document meta failed: '{}' must be a string or null",
+                    key
+                ))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        this.inner
+            .update_metadata(changes)
+            .map_err(|e| JsValue::from_str(&format!("Set document meta failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
     }
 
-    /// Selects all content in the document
-    #[wasm_bindgen(js_name = selectAll)]
-    pub fn select_all(&mut self) {
-        self.inner.select_all();
-        self.callbacks.trigger_selection_callbacks();
+    /// Gets all document metadata as an object of string values, in the
+    /// order the keys were added
+    #[wasm_bindgen(js_name = getDocumentMeta)]
+    pub fn get_document_meta(&self) -> JsValue {
+        let this = self.state();
+        let obj = js_sys::Object::new();
+        for (key, value) in this.inner.metadata().iter() {
+            js_sys::Reflect::set(&obj, &key.into(), &value.into()).unwrap();
+        }
+        obj.into()
     }
 
-    /// Collapses the selection to the start position
-    #[wasm_bindgen(js_name = collapseToStart)]
-    pub fn collapse_to_start(&mut self) {
-        self.inner.collapse_to_start();
-        self.callbacks.trigger_selection_callbacks();
+    /// Creates a sticky position that tracks edits and returns its handle
+    ///
+    /// Text inserted exactly at a "left" position ends up after it; a
+    /// "right" position moves past the inserted text. Release the handle with
+    /// `releaseStickyPosition` once it is no longer needed.
+    ///
+    /// # Arguments
+    /// * `offset` - The character offset to track
+    /// * `bias` - "left" or "right"
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the offset is out of bounds or the bias is unknown
+    #[wasm_bindgen(js_name = createStickyPosition)]
+    pub fn create_sticky_position(&self, offset: usize, bias: &str) -> Result<u32, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let bias = parse_bias(bias)?;
+        this.inner
+            .create_sticky_position(Position::new(offset), bias)
+            .map_err(|e| JsValue::from_str(&format!("Create sticky position failed: {}", e)))
     }
 
-    /// Collapses the selection to the end position
-    #[wasm_bindgen(js_name = collapseToEnd)]
-    pub fn collapse_to_end(&mut self) {
-        self.inner.collapse_to_end();
-        self.callbacks.trigger_selection_callbacks();
+    /// Gets the current offset of a sticky position, or undefined if it was released
+    ///
+    /// # Arguments
+    /// * `handle` - Handle returned by `createStickyPosition`
+    #[wasm_bindgen(js_name = resolveStickyPosition)]
+    pub fn resolve_sticky_position(&self, handle: u32) -> Option<usize> {
+        let this = self.state();
+        this.inner
+            .resolve_sticky_position(handle)
+            .map(|pos| pos.offset())
     }
 
-    /// Moves the cursor left by one character
-    #[wasm_bindgen(js_name = moveCursorLeft)]
-    pub fn move_cursor_left(&mut self) {
-        self.inner.move_cursor_left();
-        self.callbacks.trigger_selection_callbacks();
+    /// Releases a sticky position
+    ///
+    /// # Arguments
+    /// * `handle` - Handle returned by `createStickyPosition`
+    ///
+    /// # Returns
+    /// True if the handle was live
+    #[wasm_bindgen(js_name = releaseStickyPosition)]
+    pub fn release_sticky_position(&self, handle: u32) -> bool {
+        let mut this = self.state_mut();
+        this.inner.release_sticky_position(handle)
     }
 
-    /// Moves the cursor right by one character
-    #[wasm_bindgen(js_name = moveCursorRight)]
-    pub fn move_cursor_right(&mut self) {
-        self.inner.move_cursor_right();
-        self.callbacks.trigger_selection_callbacks();
+    /// Returns the entire content of the document
+    #[wasm_bindgen(js_name = getContent)]
+    pub fn get_content(&self) -> String {
+        let this = self.state();
+        this.inner.get_content()
     }
 
-    /// Moves the cursor up by one line
-    #[wasm_bindgen(js_name = moveCursorUp)]
-    pub fn move_cursor_up(&mut self) {
-        self.inner.move_cursor_up();
-        self.callbacks.trigger_selection_callbacks();
-    }
-
-    /// Moves the cursor down by one line
-    #[wasm_bindgen(js_name = moveCursorDown)]
-    pub fn move_cursor_down(&mut self) {
-        self.inner.move_cursor_down();
-        self.callbacks.trigger_selection_callbacks();
-    }
-
-    /// Moves the cursor to the start of the current line
-    #[wasm_bindgen(js_name = moveToLineStart)]
-    pub fn move_to_line_start(&mut self) {
-        self.inner.move_to_line_start();
-        self.callbacks.trigger_selection_callbacks();
-    }
-
-    /// Moves the cursor to the end of the current line
-    #[wasm_bindgen(js_name = moveToLineEnd)]
-    pub fn move_to_line_end(&mut self) {
-        self.inner.move_to_line_end();
-        self.callbacks.trigger_selection_callbacks();
-    }
-
-    /// Moves the cursor to the start of the document
-    #[wasm_bindgen(js_name = moveToDocumentStart)]
-    pub fn move_to_document_start(&mut self) {
-        self.inner.move_to_document_start();
-        self.callbacks.trigger_selection_callbacks();
-    }
-
-    /// Moves the cursor to the end of the document
-    #[wasm_bindgen(js_name = moveToDocumentEnd)]
-    pub fn move_to_document_end(&mut self) {
-        self.inner.move_to_document_end();
-        self.callbacks.trigger_selection_callbacks();
-    }
-
-    /// Moves the cursor by word boundaries
-    ///
-    /// # Arguments
-    /// * `forward` - If true, moves to the next word; otherwise, moves to the previous word
-    #[wasm_bindgen(js_name = moveByWord)]
-    pub fn move_by_word(&mut self, forward: bool) {
-        self.inner.move_by_word(forward);
-        self.callbacks.trigger_selection_callbacks();
-    }
-
-    /// Serializes the document to JSON format
-    ///
-    /// # Returns
-    /// A JSON string representation of the document
-    ///
-    /// # Errors
-    /// Returns a JsValue error if serialization fails
-    #[wasm_bindgen(js_name = toJSON)]
-    pub fn to_json(&self) -> Result<String, JsValue> {
-        self.inner
-            .to_json()
-            .map_err(|e| JsValue::from_str(&format!("JSON serialization failed: {}", e)))
-    }
-
-    /// Serializes the document to pretty-printed JSON format
+    /// Returns a cursor over the content in chunks, so it never has to be
+    /// passed to JavaScript as one string
     ///
-    /// # Returns
-    /// A formatted JSON string representation of the document
-    ///
-    /// # Errors
-    /// Returns a JsValue error if serialization fails
-    #[wasm_bindgen(js_name = toJSONPretty)]
-    pub fn to_json_pretty(&self) -> Result<String, JsValue> {
-        self.inner
-            .to_json_pretty()
-            .map_err(|e| JsValue::from_str(&format!("JSON serialization failed: {}", e)))
-    }
-
-    /// Deserializes a document from JSON format
+    /// The cursor reads the content as it was when this was called; edits
+    /// made while iterating do not affect it.
     ///
     /// # Arguments
-    /// * `json` - A JSON string representation of a document
-    ///
-    /// # Returns
-    /// A new WasmDocument instance
+    /// * `chunk_size` - Most characters per chunk; the last chunk may be
+    ///   shorter
     ///
     /// # Errors
-    /// Returns a JsValue error if deserialization fails
-    #[wasm_bindgen(js_name = fromJSON)]
-    pub fn from_json(json: &str) -> Result<WasmDocument, JsValue> {
-        let doc = Document::from_json(json)
-            .map_err(|e| JsValue::from_str(&format!("JSON deserialization failed: {}", e)))?;
-        Ok(WasmDocument {
-            inner: doc,
-            callbacks: EventCallbacks::new(),
+    /// Returns a JsValue error if `chunk_size` is 0
+    ///
+    /// # Example
+    /// ```javascript
+    /// const chunks = doc.getTextChunks(64 * 1024);
+    /// for (let chunk = chunks.nextChunk(); chunk !== undefined; chunk = chunks.nextChunk()) {
+    ///     writer.write(chunk);
+    /// }
+    /// chunks.free();
+    /// ```
+    #[wasm_bindgen(js_name = getTextChunks)]
+    pub fn get_text_chunks(&self, chunk_size: usize) -> Result<WasmTextChunks, JsValue> {
+        let this = self.try_state()?;
+        if chunk_size == 0 {
+            return Err(JsValue::from_str("Chunk size must be at least 1"));
+        }
+        Ok(WasmTextChunks {
+            inner: this.inner.text_chunks(chunk_size),
         })
     }
 
-    /// Exports the document to Markdown format
-    ///
-    /// # Returns
-    /// A Markdown string representation of the document
-    #[wasm_bindgen(js_name = toMarkdown)]
-    pub fn to_markdown(&self) -> String {
-        self.inner.to_markdown()
+    /// Returns the length of the document in characters
+    #[wasm_bindgen(js_name = getLength)]
+    pub fn get_length(&self) -> usize {
+        let this = self.state();
+        this.inner.get_length()
     }
 
-    /// Exports the document to HTML format
+    /// Returns the number of words in the document
     ///
-    /// Maps inline formats to HTML tags and block types to appropriate HTML elements.
-    /// All content is properly escaped to prevent XSS attacks.
+    /// Words are defined as sequences of non-whitespace characters
+    /// separated by whitespace. This matches the behavior of most
+    /// word processors. Thai, Lao, Khmer and Myanmar text is split further
+    /// by the callback passed to `setWordSegmenter`.
     ///
     /// # Returns
-    /// An HTML string representation of the document
-    #[wasm_bindgen(js_name = toHTML)]
-    pub fn to_html(&self) -> String {
-        self.inner.to_html()
+    /// The number of words in the document
+    ///
+    /// # Example
+    /// ```javascript
+    /// const doc = new WasmDocument();
+    /// doc.insertText("Hello world", 0);
+    /// console.log(doc.getWordCount()); // 2
+    /// ```
+    #[wasm_bindgen(js_name = getWordCount)]
+    pub fn get_word_count(&self) -> usize {
+        let this = self.state();
+        this.inner.word_count()
     }
 
-    /// Exports a specific range of the document to HTML format
+    /// Sets the callback that splits Thai, Lao, Khmer and Myanmar text,
+    /// which is written without spaces, into words
     ///
-    /// This method supports incremental rendering by allowing you to generate
-    /// HTML for only specific regions of the document.
-    ///
-    /// # Arguments
-    /// * `start` - The start position of the range
-    /// * `end` - The end position of the range
-    ///
-    /// # Returns
-    /// An HTML string representation of the specified range
+    /// The callback receives a run of such text and returns the offsets at
+    /// which words start. It is used by `moveByWord`, `selectWord`, the word
+    /// deletion commands and `getWordCount`.
+    /// Pass null to treat each run as one word.
     ///
     /// # Example
     /// ```javascript
-    /// // Render only the first 100 characters
-    /// const html = doc.toHTMLRange(0, 100);
+    /// const segmenter = new Intl.Segmenter("th", { granularity: "word" });
+    /// doc.setWordSegmenter((text) =>
+    ///     Array.from(segmenter.segment(text), (s) => s.index));
     /// ```
-    #[wasm_bindgen(js_name = toHTMLRange)]
-    pub fn to_html_range(&self, start: usize, end: usize) -> String {
-        let range = Range::from_offsets(start, end);
-        self.inner.to_html_range(Some(range))
+    #[wasm_bindgen(js_name = setWordSegmenter)]
+    pub fn set_word_segmenter(&self, callback: Option<js_sys::Function>) {
+        let mut this = self.state_mut();
+        this.inner.set_word_segmenter(
+            callback.map(|f| Box::new(JsWordSegmenter(f)) as Box<dyn WordSegmenter>),
+        );
     }
 
-    /// Exports HTML for all dirty regions in the document
+    /// Sets the dictionary `checkSpelling` looks words up in
     ///
-    /// This method is useful for incremental rendering. It returns an array
-    /// of objects containing the range and HTML for each dirty region.
+    /// Setting a dictionary marks the whole document for checking. Words
+    /// in code blocks, words containing digits and text marked with
+    /// `markNoSpellcheck` are skipped.
     ///
-    /// # Returns
-    /// A JavaScript array of objects with `start`, `end`, and `html` properties
+    /// # Arguments
+    /// * `dictionary` - A callback that receives a word and returns true
+    ///   if it is spelled correctly, an array of correctly spelled words,
+    ///   or null to turn spellchecking off
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the dictionary is none of these
     ///
     /// # Example
     /// ```javascript
-    /// const dirtyRegions = doc.getDirtyHTML();
-    /// dirtyRegions.forEach(region => {
-    ///   console.log(`Update range ${region.start}-${region.end}`);
-    ///   updateDOM(region.start, region.end, region.html);
+    /// doc.setSpellcheckDictionary((word) => hunspell.check(word));
+    /// requestIdleCallback(function check(deadline) {
+    ///     if (!doc.checkSpelling(deadline.timeRemaining())) requestIdleCallback(check);
+    ///     renderSquiggles(doc.getMisspellings());
     /// });
     /// ```
-    #[wasm_bindgen(js_name = getDirtyHTML)]
-    pub fn get_dirty_html(&self) -> JsValue {
-        let dirty_regions = self.inner.to_html_dirty_regions();
-
-        let result: Vec<JsValue> = dirty_regions
-            .into_iter()
-            .map(|(range, html)| {
-                let obj = js_sys::Object::new();
-                let normalized = range.normalize();
-                js_sys::Reflect::set(&obj, &"start".into(), &normalized.start_offset().into())
-                    .unwrap();
-                js_sys::Reflect::set(&obj, &"end".into(), &normalized.end_offset().into()).unwrap();
-                js_sys::Reflect::set(&obj, &"html".into(), &html.into()).unwrap();
-                obj.into()
-            })
-            .collect();
-
-        js_sys::Array::from_iter(result).into()
+    #[wasm_bindgen(js_name = setSpellcheckDictionary)]
+    pub fn set_spellcheck_dictionary(&self, dictionary: JsValue) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let dictionary = dictionary_from_js(&dictionary)?;
+        this.inner.set_spellcheck_dictionary(dictionary);
+        Ok(())
     }
 
-    /// Clears all dirty region flags
+    /// Checks the lines edited since the last check against the dictionary
+    /// set with `setSpellcheckDictionary`
     ///
-    /// This should be called after rendering dirty regions to reset the
-    /// dirty tracking state.
+    /// # Arguments
+    /// * `budget_ms` - Time after which to stop, checked after each line,
+    ///   or null for no limit. The next call carries on where this one
+    ///   stopped.
     ///
-    /// # Example
-    /// ```javascript
-    /// // Get and render dirty regions
-    /// const dirtyRegions = doc.getDirtyHTML();
-    /// renderDirtyRegions(dirtyRegions);
+    /// # Returns
+    /// `true` once every line has been checked
+    #[wasm_bindgen(js_name = checkSpelling)]
+    pub fn check_spelling(&self, budget_ms: Option<f64>) -> bool {
+        let mut this = self.state_mut();
+        let budget = budget_ms
+            .filter(|ms| ms.is_finite())
+            .map(|ms| std::time::Duration::try_from_secs_f64(ms / 1000.0).unwrap_or_default());
+        this.inner.check_spelling(budget)
+    }
+
+    /// Gets the misspelled words found by `checkSpelling`
+    ///
+    /// Returns an array of `{ start, end, word }` objects sorted by start
+    #[wasm_bindgen(js_name = getMisspellings)]
+    pub fn get_misspellings(&self) -> js_sys::Array {
+        let this = self.state();
+        let misspellings = this.inner.misspellings().iter().map(|(range, word)| {
+            let obj = js_sys::Object::new();
+            js_sys::Reflect::set(&obj, &"start".into(), &range.start_offset().into()).unwrap();
+            js_sys::Reflect::set(&obj, &"end".into(), &range.end_offset().into()).unwrap();
+            js_sys::Reflect::set(&obj, &"word".into(), &word.as_str().into()).unwrap();
+            JsValue::from(obj)
+        });
+        js_sys::Array::from_iter(misspellings)
+    }
+
+    /// Accepts a word as correctly spelled, clearing its misspellings
     ///
-    /// // Clear dirty flags after rendering
-    /// doc.clearDirtyFlags();
-    /// ```
-    #[wasm_bindgen(js_name = clearDirtyFlags)]
-    pub fn clear_dirty_flags(&mut self) {
-        self.inner.clear_dirty_flags();
+    /// Given in lowercase, the word also matches its capitalized forms.
+    #[wasm_bindgen(js_name = addToDictionary)]
+    pub fn add_to_dictionary(&self, word: &str) {
+        let mut this = self.state_mut();
+        this.inner.add_to_dictionary(word);
     }
 
-    /// Returns true if there are any dirty regions
+    /// Returns the number of lines in the document
     ///
-    /// This can be used to check if rendering is needed.
+    /// Lines are separated by newline characters (\n).
+    /// An empty document has 1 line.
     ///
     /// # Returns
-    /// true if there are dirty regions, false otherwise
+    /// The number of lines in the document
     ///
     /// # Example
     /// ```javascript
-    /// if (doc.hasDirtyRegions()) {
-    ///   const html = doc.toHTML();
-    ///   updateEditor(html);
-    ///   doc.clearDirtyFlags();
-    /// }
+    /// const doc = new WasmDocument();
+    /// doc.insertText("Line 1\nLine 2\nLine 3", 0);
+    /// console.log(doc.getLineCount()); // 3
     /// ```
-    #[wasm_bindgen(js_name = hasDirtyRegions)]
-    pub fn has_dirty_regions(&self) -> bool {
-        self.inner.has_dirty_regions()
+    #[wasm_bindgen(js_name = getLineCount)]
+    pub fn get_line_count(&self) -> usize {
+        let this = self.state();
+        this.inner.line_count()
     }
 
-    /// Imports a document from HTML format
+    /// Converts a character offset to zero-based line and column numbers
     ///
-    /// Parses HTML and converts tags to internal format representation.
-    /// Dangerous content like javascript: URLs and event handlers are stripped.
+    /// Returned shape: { line: number, column: number }
     ///
     /// # Arguments
-    /// * `html` - An HTML string
-    ///
-    /// # Returns
-    /// A new WasmDocument instance
+    /// * `offset` - The character offset to convert
     ///
     /// # Errors
-    /// Returns a JsValue error if HTML parsing fails
-    #[wasm_bindgen(js_name = fromHTML)]
-    pub fn from_html(html: &str) -> Result<WasmDocument, JsValue> {
-        let doc = Document::from_html(html)
-            .map_err(|e| JsValue::from_str(&format!("HTML parsing failed: {}", e)))?;
-        Ok(WasmDocument {
-            inner: doc,
-            callbacks: EventCallbacks::new(),
-        })
+    /// Returns a JsValue error if the offset is out of bounds
+    #[wasm_bindgen(js_name = positionToLineColumn)]
+    pub fn position_to_line_column(&self, offset: usize) -> Result<JsValue, JsValue> {
+        let this = self.try_state()?;
+        let (line, column) = this
+            .inner
+            .position_to_line_column(Position::new(offset))
+            .ok_or_else(|| {
+                JsValue::from_str(&format!(
+                    "Position to line/column failed: offset {} is out of bounds",
+                    offset
+                ))
+            })?;
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"line".into(), &line.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"column".into(), &column.into()).unwrap();
+        Ok(obj.into())
     }
 
-    /// Imports a document from Markdown format (static method)
+    /// Locates a character offset for scrolling it into view
     ///
-    /// # Arguments
-    /// * `markdown` - A Markdown string
+    /// Returned shape: { block: number, line: number, column: number }, where
+    /// `block` is the block index (the line number from `positionToLineColumn`),
+    /// `line` counts the soft breaks before the offset within the block, and
+    /// `column` counts characters from the start of that line.
     ///
-    /// # Returns
-    /// A new WasmDocument instance
+    /// # Arguments
+    /// * `offset` - The character offset to locate
     ///
     /// # Errors
-    /// Returns a JsValue error if parsing fails
-    #[wasm_bindgen(js_name = fromMarkdown)]
-    pub fn from_markdown(markdown: &str) -> Result<WasmDocument, JsValue> {
-        let doc = Document::from_markdown(markdown)
-            .map_err(|e| JsValue::from_str(&format!("Markdown parsing failed: {}", e)))?;
-        Ok(WasmDocument {
-            inner: doc,
-            callbacks: EventCallbacks::new(),
-        })
+    /// Returns a JsValue error if the offset is out of bounds
+    #[wasm_bindgen(js_name = getOffsetGeometryHints)]
+    pub fn get_offset_geometry_hints(&self, offset: usize) -> Result<JsValue, JsValue> {
+        let this = self.try_state()?;
+        let geometry = this
+            .inner
+            .offset_geometry(Position::new(offset))
+            .ok_or_else(|| {
+                JsValue::from_str(&format!(
+                    "Get offset geometry hints failed: offset {} is out of bounds",
+                    offset
+                ))
+            })?;
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"block".into(), &geometry.block.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"line".into(), &geometry.line.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"column".into(), &geometry.column.into()).unwrap();
+        Ok(obj.into())
     }
 
-    /// Imports markdown content into the current document (instance method)
+    /// Converts zero-based line and column numbers to a character offset
     ///
-    /// Replaces the current document content with the parsed markdown.
-    /// This is useful for loading markdown into an existing document instance.
+    /// A column past the end of the line is clamped to the line end.
     ///
     /// # Arguments
-    /// * `markdown` - A Markdown string
+    /// * `line` - The zero-based line number
+    /// * `column` - The zero-based column, in characters
     ///
     /// # Errors
-    /// Returns a JsValue error if parsing fails
-    #[wasm_bindgen(js_name = loadFromMarkdown)]
-    pub fn load_from_markdown(&mut self, markdown: &str) -> Result<(), JsValue> {
-        let doc = Document::from_markdown(markdown)
-            .map_err(|e| JsValue::from_str(&format!("Markdown parsing failed: {}", e)))?;
-        self.inner = doc;
-        self.callbacks.trigger_change_callbacks();
-        Ok(())
-    }
-
-    /// Exports the document to plain text format (strips all formatting)
-    ///
-    /// # Returns
-    /// A plain text string representation of the document
-    #[wasm_bindgen(js_name = toPlainText)]
-    pub fn to_plain_text(&self) -> String {
-        // Simply return the content without formatting
-        self.inner.get_content()
+    /// Returns a JsValue error if the line does not exist
+    #[wasm_bindgen(js_name = lineColumnToPosition)]
+    pub fn line_column_to_position(&self, line: usize, column: usize) -> Result<usize, JsValue> {
+        let this = self.try_state()?;
+        this.inner
+            .line_column_to_position(line, column)
+            .map(|pos| pos.offset())
+            .ok_or_else(|| {
+                JsValue::from_str(&format!(
+                    "Line/column to position failed: line {} does not exist",
+                    line
+                ))
+            })
     }
 
-    /// Finds all occurrences of the search pattern in the document
+    /// Returns the text within the specified range
     ///
     /// # Arguments
-    /// * `pattern` - The text pattern to search for
-    /// * `case_sensitive` - Whether the search should be case-sensitive
-    /// * `use_regex` - Whether to interpret the pattern as a regular expression
-    ///
-    /// # Returns
-    /// A JsValue array of match objects with start and end properties
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
     ///
     /// # Errors
-    /// Returns a JsValue error if the regex pattern is invalid
-    #[wasm_bindgen(js_name = find)]
-    pub fn find(
-        &self,
-        pattern: &str,
-        case_sensitive: bool,
-        use_regex: bool,
-    ) -> Result<JsValue, JsValue> {
-        let query = SearchQuery::new(pattern.to_string())
-            .case_sensitive(case_sensitive)
-            .use_regex(use_regex);
-
-        let result = self
-            .inner
-            .find(&query)
-            .map_err(|e| JsValue::from_str(&format!("Search failed: {}", e)))?;
-
-        let matches: Vec<JsValue> = result
-            .matches
-            .iter()
-            .map(|range| {
-                let obj = js_sys::Object::new();
-                let normalized = range.normalize();
-                js_sys::Reflect::set(&obj, &"start".into(), &normalized.start_offset().into())
-                    .unwrap();
-                js_sys::Reflect::set(&obj, &"end".into(), &normalized.end_offset().into()).unwrap();
-                obj.into()
-            })
-            .collect();
-
-        Ok(js_sys::Array::from_iter(matches).into())
+    /// Returns a JsValue error if the range is invalid
+    #[wasm_bindgen(js_name = getTextInRange)]
+    pub fn get_text_in_range(&self, start: usize, end: usize) -> Result<String, JsValue> {
+        let this = self.try_state()?;
+        if end > this.inner.get_length() {
+            return Err(JsValue::from_str(&format!(
+                "Range end {} exceeds document length {}",
+                end,
+                this.inner.get_length()
+            )));
+        }
+        let range = Range::from_offsets(start, end);
+        Ok(this.inner.get_text_in_range(range))
     }
 
-    /// Finds and replaces all occurrences of the search pattern with replacement text
-    ///
-    /// # Arguments
-    /// * `pattern` - The text pattern to search for
-    /// * `replacement` - The text to replace matches with
-    /// * `case_sensitive` - Whether the search should be case-sensitive
-    /// * `use_regex` - Whether to interpret the pattern as a regular expression
-    ///
-    /// # Returns
-    /// The number of replacements made
-    ///
-    /// # Errors
-    /// Returns a JsValue error if the regex pattern is invalid or replacement fails
-    #[wasm_bindgen(js_name = findAndReplace)]
-    pub fn find_and_replace(
-        &mut self,
-        pattern: &str,
-        replacement: &str,
-        case_sensitive: bool,
-        use_regex: bool,
-    ) -> Result<usize, JsValue> {
-        let query = SearchQuery::new(pattern.to_string())
-            .case_sensitive(case_sensitive)
-            .use_regex(use_regex);
-
-        let count = self
-            .inner
-            .find_and_replace(&query, replacement)
-            .map_err(|e| JsValue::from_str(&format!("Find and replace failed: {}", e)))?;
-
-        if count > 0 {
-            self.callbacks.trigger_change_callbacks();
-        }
+    /// Returns the current version of the document
+    #[wasm_bindgen(js_name = getVersion)]
+    pub fn get_version(&self) -> u64 {
+        let this = self.state();
+        this.inner.version()
+    }
 
-        Ok(count)
+    /// Returns true if the document is empty
+    #[wasm_bindgen(js_name = isEmpty)]
+    pub fn is_empty(&self) -> bool {
+        let this = self.state();
+        this.inner.is_empty()
     }
 
-    /// Registers a callback to be called when the document content changes
-    ///
-    /// # Arguments
-    /// * `callback` - JavaScript function to call on document changes
+    /// Undoes the last operation
     ///
-    /// # Example
-    /// ```javascript
-    /// document.onChange(() => {
-    ///     console.log("Document changed!");
-    /// });
-    /// ```
-    #[wasm_bindgen(js_name = onChange)]
-    pub fn on_change(&mut self, callback: js_sys::Function) {
-        self.callbacks.add_change_callback(callback);
+    /// # Errors
+    /// Returns a JsValue error if there is nothing to undo
+    pub fn undo(&self) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .undo()
+            .map_err(|e| JsValue::from_str(&format!("Undo failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
     }
 
-    /// Registers a callback to be called when the selection changes
+    /// Starts a batch of operations that undo as a single step
     ///
-    /// # Arguments
-    /// * `callback` - JavaScript function to call on selection changes
+    /// Every edit made until the matching `endBatch()` becomes one history
+    /// entry. Batches may be nested; the entry is recorded when the
+    /// outermost batch ends.
     ///
     /// # Example
     /// ```javascript
-    /// document.onSelectionChange(() => {
-    ///     console.log("Selection changed!");
-    /// });
+    /// doc.beginBatch();
+    /// doc.insertText("Title\n", 0);
+    /// doc.setBlockType("h1", 0, 5);
+    /// doc.endBatch();
+    /// doc.undo(); // reverts both operations
     /// ```
-    #[wasm_bindgen(js_name = onSelectionChange)]
-    pub fn on_selection_change(&mut self, callback: js_sys::Function) {
-        self.callbacks.add_selection_callback(callback);
+    #[wasm_bindgen(js_name = beginBatch)]
+    pub fn begin_batch(&self) {
+        let mut this = self.state_mut();
+        this.inner.begin_batch();
     }
 
-    /// Unregisters a change callback
-    ///
-    /// # Arguments
-    /// * `callback` - JavaScript function to remove from change callbacks
+    /// Ends the current batch started with `beginBatch()`
     ///
     /// # Returns
-    /// true if the callback was found and removed, false otherwise
-    #[wasm_bindgen(js_name = offChange)]
-    pub fn off_change(&mut self, callback: js_sys::Function) -> bool {
-        self.callbacks.remove_change_callback(&callback)
+    /// false if no batch was open
+    #[wasm_bindgen(js_name = endBatch)]
+    pub fn end_batch(&self) -> bool {
+        let mut this = self.state_mut();
+        let ended = this.inner.end_batch();
+        this.notify_history();
+        ended
     }
 
-    /// Unregisters a selection change callback
-    ///
-    /// # Arguments
-    /// * `callback` - JavaScript function to remove from selection callbacks
-    ///
-    /// # Returns
-    /// true if the callback was found and removed, false otherwise
-    #[wasm_bindgen(js_name = offSelectionChange)]
-    pub fn off_selection_change(&mut self, callback: js_sys::Function) -> bool {
-        self.callbacks.remove_selection_callback(&callback)
+    /// Returns true if a batch is currently open
+    #[wasm_bindgen(js_name = isBatching)]
+    pub fn is_batching(&self) -> bool {
+        let this = self.state();
+        this.inner.is_batching()
     }
 
-    /// Copies the current selection to clipboard format
-    ///
-    /// Returns an object with `text` and `html` properties containing the
-    /// selected content in both plain text and HTML formats. The HTML format
-    /// preserves all inline formatting.
-    ///
-    /// # Returns
-    /// An object with:
-    /// - `text`: Plain text content
-    /// - `html`: HTML formatted content
-    ///
-    /// Returns an object with empty strings if selection is collapsed.
+    /// Redoes the last undone operation
     ///
-    /// # Example
+    /// # Errors
+    /// Returns a JsValue error if there is nothing to redo
+    pub fn redo(&self) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .redo()
+            .map_err(|e| JsValue::from_str(&format!("Redo failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Returns every state in the undo history
     ///
-    /// ```javascript
-    /// const clipboardData = document.copy();
-    /// console.log(clipboardData.text); // "Hello"
-    /// console.log(clipboardData.html); // "<strong>Hello</strong>"
-    /// ```
-    #[wasm_bindgen(js_name = copy)]
-    pub fn copy(&self) -> JsValue {
-        let content = self.inner.copy();
-        let html = content.to_html();
-        let text = content.text;
+    /// Returned shape: { current: number, nodes: Array<{ id: number,
+    /// parent: number | null, description: string, children: number[] }> }.
+    /// Edits made after an undo start a new branch, so a node may have
+    /// several children.
+    #[wasm_bindgen(js_name = getHistoryTree)]
+    pub fn get_history_tree(&self) -> JsValue {
+        let this = self.state();
+        let nodes = js_sys::Array::new();
+        for node in this.inner.history_nodes() {
+            let obj = js_sys::Object::new();
+            let parent = node
+                .parent
+                .map_or(JsValue::NULL, |p| JsValue::from(p as u32));
+            let children: js_sys::Array = node
+                .children
+                .iter()
+                .map(|&c| JsValue::from(c as u32))
+                .collect();
+            js_sys::Reflect::set(&obj, &"id".into(), &JsValue::from(node.id as u32)).unwrap();
+            js_sys::Reflect::set(&obj, &"parent".into(), &parent).unwrap();
+            js_sys::Reflect::set(&obj, &"description".into(), &node.description.into()).unwrap();
+            js_sys::Reflect::set(&obj, &"children".into(), &children.into()).unwrap();
+            nodes.push(&obj);
+        }
 
-        let obj = js_sys::Object::new();
-        js_sys::Reflect::set(&obj, &"text".into(), &text.into()).unwrap();
-        js_sys::Reflect::set(&obj, &"html".into(), &html.into()).unwrap();
-        obj.into()
+        let tree = js_sys::Object::new();
+        let current = JsValue::from(this.inner.current_history_node() as u32);
+        js_sys::Reflect::set(&tree, &"current".into(), &current).unwrap();
+        js_sys::Reflect::set(&tree, &"nodes".into(), &nodes.into()).unwrap();
+        tree.into()
     }
 
-    /// Cuts the current selection to clipboard format
-    ///
-    /// Copies the selected text and formats, then deletes the selection.
-    /// This operation is undoable.
-    ///
-    /// # Returns
-    /// An object with:
-    /// - `text`: Plain text content
-    /// - `html`: HTML formatted content
+    /// Moves the document to any state in the undo history
     ///
-    /// Returns an object with empty strings if selection is collapsed.
+    /// # Arguments
+    /// * `id` - A node id from `getHistoryTree`
     ///
     /// # Errors
-    /// Returns a JsValue error if the delete operation fails.
-    ///
-    /// # Example
-    ///
-    /// ```javascript
-    /// const clipboardData = document.cut();
-    /// console.log(clipboardData.text); // "Hello"
-    /// // Selection is now deleted from document
-    /// ```
-    #[wasm_bindgen(js_name = cut)]
-    pub fn cut(&mut self) -> Result<JsValue, JsValue> {
-        let content = self
-            .inner
-            .cut()
-            .map_err(|e| JsValue::from_str(&format!("Cut failed: {}", e)))?;
-
-        let is_empty = content.is_empty();
-        let html = content.to_html();
-        let text = content.text;
+    /// Returns a JsValue error if the node does not exist or a step fails
+    #[wasm_bindgen(js_name = undoToNode)]
+    pub fn undo_to_node(&self, id: u32) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let result = this.inner.undo_to_node(id as usize);
+        // Steps taken before a failure still changed the document
+        this.notify_change();
+        result.map_err(|e| JsValue::from_str(&format!("Undo to node failed: {}", e)))
+    }
 
-        if !is_empty {
-            self.callbacks.trigger_change_callbacks();
-            self.callbacks.trigger_selection_callbacks();
-        }
+    /// Returns true if there are operations that can be undone
+    #[wasm_bindgen(js_name = canUndo)]
+    pub fn can_undo(&self) -> bool {
+        let this = self.state();
+        this.inner.can_undo()
+    }
 
-        let obj = js_sys::Object::new();
-        js_sys::Reflect::set(&obj, &"text".into(), &text.into()).unwrap();
-        js_sys::Reflect::set(&obj, &"html".into(), &html.into()).unwrap();
-        Ok(obj.into())
+    /// Returns true if there are operations that can be redone
+    #[wasm_bindgen(js_name = canRedo)]
+    pub fn can_redo(&self) -> bool {
+        let this = self.state();
+        this.inner.can_redo()
     }
 
-    /// Pastes HTML content at the current cursor position
-    ///
-    /// If there is a selection, it will be replaced with the pasted content.
-    /// The HTML is parsed and sanitized to prevent XSS attacks. All formatting
-    /// is preserved from the HTML.
-    ///
-    /// This operation is undoable.
+    /// Applies a format to the specified range
     ///
     /// # Arguments
-    /// * `html` - The HTML string to paste
+    /// * `format_type` - The type of format ("bold", "italic", "underline", "strikethrough", "code", "highlight", "smallCaps", "subscript", "superscript", "math", "mathDisplay")
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
     ///
     /// # Errors
-    /// Returns a JsValue error if HTML parsing or paste operation fails.
-    ///
-    /// # Example
-    ///
-    /// ```javascript
-    /// document.pasteHtml("<strong>Hello</strong> World");
-    /// // Document now contains "Hello World" with "Hello" in bold
-    /// ```
-    #[wasm_bindgen(js_name = pasteHtml)]
-    pub fn paste_html(&mut self, html: &str) -> Result<(), JsValue> {
-        self.inner
-            .paste_html(html)
-            .map_err(|e| JsValue::from_str(&format!("Paste HTML failed: {}", e)))?;
-        self.callbacks.trigger_change_callbacks();
-        self.callbacks.trigger_selection_callbacks();
+    /// Returns a JsValue error if the format type is invalid or range is invalid
+    #[wasm_bindgen(js_name = applyFormat)]
+    pub fn apply_format(&self, format_type: &str, start: usize, end: usize) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let format = parse_inline_format(format_type)?;
+        let range = Range::from_offsets(start, end);
+        this.inner.apply_format(range, format);
+        this.notify_change();
         Ok(())
     }
 
-    /// Pastes plain text at the current cursor position
-    ///
-    /// If there is a selection, it will be replaced with the pasted text.
-    /// No formatting is applied to the pasted text.
-    ///
-    /// This operation is undoable.
+    /// Applies a format with a parameter (link, text color, background color)
     ///
     /// # Arguments
-    /// * `text` - The plain text to paste
+    /// * `format_type` - The type of format ("link", "textColor", "text-color", "backgroundColor", "background-color")
+    /// * `value` - The value for the format (URL for link, color for colors)
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
     ///
     /// # Errors
-    /// Returns a JsValue error if the paste operation fails.
-    ///
-    /// # Example
-    ///
-    /// ```javascript
-    /// document.pastePlainText("Hello World");
-    /// // Document now contains "Hello World" with no formatting
-    /// ```
-    #[wasm_bindgen(js_name = pastePlainText)]
-    pub fn paste_plain_text(&mut self, text: &str) -> Result<(), JsValue> {
-        self.inner
-            .paste_plain_text(text)
-            .map_err(|e| JsValue::from_str(&format!("Paste plain text failed: {}", e)))?;
-        self.callbacks.trigger_change_callbacks();
-        self.callbacks.trigger_selection_callbacks();
+    /// Returns a JsValue error if the format type is invalid or range is invalid
+    #[wasm_bindgen(js_name = applyFormatWithValue)]
+    pub fn apply_format_with_value(
+        &self,
+        format_type: &str,
+        value: &str,
+        start: usize,
+        end: usize,
+    ) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let format = parse_inline_format_with_value(format_type, value)?;
+        let range = Range::from_offsets(start, end);
+        this.inner.apply_format(range, format);
+        this.notify_change();
         Ok(())
     }
 
-    /// Starts an IME composition at the current cursor position
+    /// Applies many formats in one call, recorded as a single undo step
     ///
-    /// This should be called when the browser fires a `compositionstart` event.
-    /// It marks the beginning of an IME composition session for languages like
-    /// Chinese, Japanese, or Korean.
+    /// # Arguments
+    /// * `ops` - An array of `{ type, value?, start, end }` objects. `type`
+    ///   accepts the names used by `applyFormat`, or by `applyFormatWithValue`
+    ///   when `value` is given.
     ///
-    /// # Example
+    /// # Errors
+    /// Returns a JsValue error naming the first invalid entry; nothing is
+    /// applied in that case
     ///
+    /// # Example
     /// ```javascript
-    /// element.addEventListener('compositionstart', () => {
-    ///     document.startComposition();
-    /// });
+    /// doc.applyFormats([
+    ///     { type: "bold", start: 0, end: 5 },
+    ///     { type: "link", value: "https://example.com", start: 6, end: 11 },
+    /// ]);
     /// ```
-    #[wasm_bindgen(js_name = startComposition)]
-    pub fn start_composition(&mut self) {
-        self.inner.start_composition();
+    #[wasm_bindgen(js_name = applyFormats)]
+    pub fn apply_formats(&self, ops: js_sys::Array) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let formats = ops
+            .iter()
+            .enumerate()
+            .map(|(index, op)| {
+                parse_format_op(&op).map_err(|e| {
+                    JsValue::from_str(&format!(
+                        "Invalid format at index {}: {}",
+                        index,
+                        e.as_string().unwrap_or_default()
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if formats.is_empty() {
+            return Ok(());
+        }
+
+        this.inner
+            .apply_formats(formats)
+            .map_err(|e| JsValue::from_str(&format!("Apply formats failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
     }
 
-    /// Updates the IME composition with new text
-    ///
-    /// This should be called when the browser fires a `compositionupdate` event.
-    /// It replaces the current composition text with the new text.
+    /// Removes a format from the specified range
     ///
     /// # Arguments
-    /// * `text` - The new composition text
-    ///
-    /// # Example
+    /// * `format_type` - The type of format to remove
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
     ///
-    /// ```javascript
-    /// element.addEventListener('compositionupdate', (e) => {
-    ///     document.updateComposition(e.data);
-    /// });
-    /// ```
-    #[wasm_bindgen(js_name = updateComposition)]
-    pub fn update_composition(&mut self, text: &str) {
-        self.inner.update_composition(text);
+    /// # Errors
+    /// Returns a JsValue error if the format type is invalid or range is invalid
+    #[wasm_bindgen(js_name = removeFormat)]
+    pub fn remove_format(
+        &self,
+        format_type: &str,
+        start: usize,
+        end: usize,
+    ) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let format = parse_inline_format(format_type)?;
+        let range = Range::from_offsets(start, end);
+        this.inner.remove_format(range, &format);
+        this.notify_change();
+        Ok(())
     }
 
-    /// Ends the IME composition and commits the final text
-    ///
-    /// This should be called when the browser fires a `compositionend` event.
-    /// It finalizes the composition and commits the text to the document.
+    /// Toggles a format on the specified range
     ///
-    /// # Example
+    /// # Arguments
+    /// * `format_type` - The type of format to toggle
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
     ///
-    /// ```javascript
-    /// element.addEventListener('compositionend', () => {
-    ///     document.endComposition();
-    /// });
-    /// ```
-    #[wasm_bindgen(js_name = endComposition)]
-    pub fn end_composition(&mut self) {
-        self.inner.end_composition();
-        self.callbacks.trigger_change_callbacks();
+    /// # Errors
+    /// Returns a JsValue error if the format type is invalid or range is invalid
+    #[wasm_bindgen(js_name = toggleFormat)]
+    pub fn toggle_format(
+        &self,
+        format_type: &str,
+        start: usize,
+        end: usize,
+    ) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let format = parse_inline_format(format_type)?;
+        let range = Range::from_offsets(start, end);
+        this.inner.toggle_format(range, format);
+        this.notify_change();
+        Ok(())
     }
 
-    /// Cancels the IME composition without committing
-    ///
-    /// This removes any composition text and returns the document to its
-    /// state before composition started. This is useful if the user cancels
-    /// the composition (e.g., by pressing Escape).
+    /// Gets all formats at the specified position
     ///
-    /// # Example
+    /// Returns a JsValue array of format type strings
     ///
-    /// ```javascript
-    /// element.addEventListener('keydown', (e) => {
-    ///     if (e.key === 'Escape' && document.isComposing()) {
-    ///         document.cancelComposition();
-    ///     }
-    /// });
-    /// ```
-    #[wasm_bindgen(js_name = cancelComposition)]
-    pub fn cancel_composition(&mut self) {
-        self.inner.cancel_composition();
-        self.callbacks.trigger_change_callbacks();
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = getFormatsAt)]
+    pub fn get_formats_at(&self, position: usize) -> js_sys::Array {
+        let this = self.state();
+        let pos = Position::new(position);
+        let formats = this.inner.get_formats_at(pos);
+
+        let format_values: Vec<JsValue> = formats.iter().map(|f| format_to_js_value(f)).collect();
+        js_sys::Array::from_iter(format_values)
     }
 
-    /// Returns true if IME composition is currently active
-    ///
-    /// This can be used to check whether the user is currently composing text
-    /// with an IME, which may affect how keyboard events are handled.
-    ///
-    /// # Returns
-    /// true if composition is active, false otherwise
+    /// Returns formats at position as a plain array of strings (debug-friendly)
+    #[wasm_bindgen(js_name = getFormatsAtStrings)]
+    pub fn get_formats_at_strings(&self, position: usize) -> js_sys::Array {
+        let this = self.state();
+        let pos = Position::new(position);
+        let formats = this.inner.get_formats_at(pos);
+        let strings: Vec<JsValue> = formats
+            .iter()
+            .map(|f| match f {
+                InlineFormat::Bold => JsValue::from_str("bold"),
+                InlineFormat::Italic => JsValue::from_str("italic"),
+                InlineFormat::Underline => JsValue::from_str("underline"),
+                InlineFormat::Strikethrough => JsValue::from_str("strikethrough"),
+                InlineFormat::Code => JsValue::from_str("code"),
+                InlineFormat::Link { .. } => JsValue::from_str("link"),
+                InlineFormat::TextColor { .. } => JsValue::from_str("textColor"),
+                InlineFormat::BackgroundColor { .. } => JsValue::from_str("backgroundColor"),
+                InlineFormat::Highlight => JsValue::from_str("highlight"),
+                InlineFormat::SmallCaps => JsValue::from_str("smallCaps"),
+                InlineFormat::Subscript => JsValue::from_str("subscript"),
+                InlineFormat::Superscript => JsValue::from_str("superscript"),
+                InlineFormat::Math { display: false } => JsValue::from_str("math"),
+                InlineFormat::Math { display: true } => JsValue::from_str("mathDisplay"),
+            })
+            .collect();
+        js_sys::Array::from_iter(strings)
+    }
+
+    /// Debug helper: returns all format runs with start/end and formats
+    /// Returned shape: Array of objects { start: number, end: number, formats: Array<string|object> }
+    #[wasm_bindgen(js_name = getFormatRunsDebug)]
+    pub fn get_format_runs_debug(&self) -> JsValue {
+        let this = self.state();
+        let runs = this.inner.formats().get_runs();
+        let out = js_sys::Array::new();
+
+        for run in runs.iter() {
+            let obj = js_sys::Object::new();
+            let start = run.range.start_offset() as u32;
+            let end = run.range.end_offset() as u32;
+
+            // Build formats array
+            let fmts = js_sys::Array::new();
+            for f in run.formats.iter() {
+                fmts.push(&format_to_js_value(f));
+            }
+
+            js_sys::Reflect::set(&obj, &"start".into(), &JsValue::from_f64(start as f64)).unwrap();
+            js_sys::Reflect::set(&obj, &"end".into(), &JsValue::from_f64(end as f64)).unwrap();
+            js_sys::Reflect::set(&obj, &"formats".into(), &fmts.into()).unwrap();
+
+            out.push(&obj);
+        }
+
+        out.into()
+    }
+
+    /// Sets the block type for the specified range
     ///
-    /// # Example
+    /// # Arguments
+    /// * `block_type` - The type of block ("paragraph", "heading1"-"heading6", "bulletList", "numberedList", "blockQuote", "codeBlock")
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
     ///
-    /// ```javascript
-    /// if (document.isComposing()) {
-    ///     console.log("User is composing text");
-    /// }
-    /// ```
-    #[wasm_bindgen(js_name = isComposing)]
-    pub fn is_composing(&self) -> bool {
-        self.inner.is_composing()
+    /// # Errors
+    /// Returns a JsValue error if the block type is invalid or range is invalid
+    #[wasm_bindgen(js_name = setBlockType)]
+    pub fn set_block_type(
+        &self,
+        block_type: &str,
+        start: usize,
+        end: usize,
+    ) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let block = parse_block_type(block_type)?;
+        let range = Range::from_offsets(start, end);
+        this.inner.set_block_type(range, block);
+        this.notify_change();
+        Ok(())
     }
 
-    /// Returns the current composition range, if composition is active
+    /// Sets the block type of every line the range touches, giving each line
+    /// its own block, as one undoable change
     ///
-    /// Returns a JsValue object with start and end properties, or null if
-    /// composition is not active.
+    /// Use it when several paragraphs are selected: whole lines change even
+    /// if the selection starts or ends inside one, and the lines stay
+    /// independent when edited later.
     ///
-    /// # Returns
-    /// An object with start and end properties, or null
+    /// # Arguments
+    /// * `block_type` - The type of block, as accepted by `setBlockType`
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
     ///
-    /// # Example
+    /// # Errors
+    /// Returns a JsValue error if the block type is invalid, the range is
+    /// invalid, or the document is read-only
+    #[wasm_bindgen(js_name = setBlockTypeLines)]
+    pub fn set_block_type_lines(
+        &self,
+        block_type: &str,
+        start: usize,
+        end: usize,
+    ) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let block = parse_block_type(block_type)?;
+        let range = Range::from_offsets(start, end);
+        this.inner
+            .set_block_type_lines(range, block)
+            .map_err(|e| JsValue::from_str(&format!("Set block type failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Gets the block type at the specified position
     ///
-    /// ```javascript
-    /// const range = document.getCompositionRange();
-    /// if (range) {
-    ///     console.log(`Composing from ${range.start} to ${range.end}`);
-    /// }
-    /// ```
-    #[wasm_bindgen(js_name = getCompositionRange)]
-    pub fn get_composition_range(&self) -> JsValue {
-        if let Some(range) = self.inner.composition_range() {
-            let obj = js_sys::Object::new();
-            js_sys::Reflect::set(&obj, &"start".into(), &range.start.offset().into()).unwrap();
-            js_sys::Reflect::set(&obj, &"end".into(), &range.end.offset().into()).unwrap();
-            obj.into()
-        } else {
-            JsValue::NULL
-        }
+    /// Returns a string representing the block type
+    ///
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = getBlockTypeAt)]
+    pub fn get_block_type_at(&self, position: usize) -> String {
+        let this = self.state();
+        let pos = Position::new(position);
+        let block_type = this.inner.get_block_type_at(pos);
+        block_type_to_string(&block_type)
     }
 
-    /// Returns the current composition text, if composition is active
+    /// Answers several read requests in one call
     ///
-    /// Returns the text being composed, or null if composition is not active.
+    /// Saves the overhead of crossing into WASM once per value, for
+    /// toolbars that read a handful of values on every selection change.
+    ///
+    /// # Arguments
+    /// * `batch` - An array of requests, each one of:
+    ///   - `{ type: "formatsAt", position }`: as `getFormatsAt`
+    ///   - `{ type: "blockTypeAt", position }`: as `getBlockTypeAt`
+    ///   - `{ type: "textInRange", start, end }`: as `getTextInRange`
+    ///   - `{ type: "selection" }`, or just `"selection"`: as `getSelection`
     ///
     /// # Returns
-    /// The composition text as a string, or null
+    /// An array with the answer to each request, in order
     ///
-    /// # Example
+    /// # Errors
+    /// Returns a JsValue error naming the first invalid request
     ///
+    /// # Example
     /// ```javascript
-    /// const text = document.getCompositionText();
-    /// if (text) {
-    ///     console.log(`Composing: ${text}`);
-    /// }
+    /// const { anchor, focus } = doc.getSelection();
+    /// const [formats, blockType] = doc.query([
+    ///     { type: "formatsAt", position: focus },
+    ///     { type: "blockTypeAt", position: focus },
+    /// ]);
     /// ```
-    #[wasm_bindgen(js_name = getCompositionText)]
-    pub fn get_composition_text(&self) -> JsValue {
-        if let Some(text) = self.inner.composition_text() {
-            JsValue::from_str(text)
-        } else {
-            JsValue::NULL
-        }
+    pub fn query(&self, batch: js_sys::Array) -> Result<js_sys::Array, JsValue> {
+        let answers = batch
+            .iter()
+            .enumerate()
+            .map(|(index, request)| {
+                self.answer_query(&request).map_err(|e| {
+                    JsValue::from_str(&format!(
+                        "Invalid query at index {}: {}",
+                        index,
+                        e.as_string().unwrap_or_default()
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(js_sys::Array::from_iter(answers))
     }
 
-    /// Gets the current maximum history size
-    ///
-    /// Returns the maximum number of commands that can be stored in the
-    /// undo/redo history. The default is 100 commands.
+    /// Nests the list items on the lines in the range one level deeper
     ///
-    /// # Returns
-    /// The maximum number of commands in the history
+    /// Lines that are not list items are left unchanged.
     ///
-    /// # Example
+    /// # Arguments
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
     ///
-    /// ```javascript
-    /// const limit = doc.getHistoryLimit();
-    /// console.log(`History limit: ${limit}`); // "History limit: 100"
-    /// ```
-    #[wasm_bindgen(js_name = getHistoryLimit)]
-    pub fn get_history_limit(&self) -> usize {
-        self.inner.get_history_limit()
+    /// # Errors
+    /// Returns a JsValue error if the range is invalid or the document is read-only
+    #[wasm_bindgen(js_name = indentListItem)]
+    pub fn indent_list_item(&self, start: usize, end: usize) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .indent_list_items(Range::from_offsets(start, end))
+            .map_err(|e| JsValue::from_str(&format!("Indent list item failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
     }
 
-    /// Sets the maximum history size
+    /// Moves the list items on the lines in the range one level up
     ///
-    /// Configures the maximum number of commands that can be stored in the
-    /// undo/redo history. If the new size is smaller than the current number
-    /// of commands, the oldest commands will be removed to fit the new limit.
+    /// Top-level items and lines that are not list items are left unchanged.
     ///
     /// # Arguments
-    /// * `max_size` - The new maximum number of commands to store
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
     ///
-    /// # Memory Impact
+    /// # Errors
+    /// Returns a JsValue error if the range is invalid or the document is read-only
+    #[wasm_bindgen(js_name = outdentListItem)]
+    pub fn outdent_list_item(&self, start: usize, end: usize) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .outdent_list_items(Range::from_offsets(start, end))
+            .map_err(|e| JsValue::from_str(&format!("Outdent list item failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Gets the list nesting level at the specified position
     ///
-    /// Reducing the history limit immediately frees memory from removed commands.
-    /// Each command stores approximately 64 bytes plus the size of affected text.
+    /// Returns 0 for top-level list items and lines that are not list items
     ///
-    /// Examples:
-    /// - Reducing from 100 to 50 commands: Saves ~3.2KB + text data
-    /// - Reducing from 100 to 20 commands: Saves ~5.1KB + text data
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = getListIndentAt)]
+    pub fn get_list_indent_at(&self, position: usize) -> u8 {
+        let this = self.state();
+        this.inner.get_list_indent_at(Position::new(position))
+    }
+
+    /// Sets the marker style of the list items on the lines in the range
     ///
-    /// # Use Cases
+    /// Lines that are not list items are left unchanged.
     ///
-    /// - **Mobile devices**: Use 20-50 commands to reduce memory usage
-    /// - **Large documents**: Use smaller history to prevent memory pressure
-    /// - **Memory-constrained environments**: Reduce to minimum needed
-    /// - **Desktop applications**: Default 100 is usually fine
+    /// # Arguments
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
+    /// * `style` - "decimal", "lower-alpha", "upper-alpha", "lower-roman",
+    ///   "upper-roman" or a single bullet character; null restores the default
     ///
-    /// # Example
+    /// # Errors
+    /// Returns a JsValue error if the style is unknown, the range is invalid or
+    /// the document is read-only
+    #[wasm_bindgen(js_name = setListStyle)]
+    pub fn set_list_style(
+        &self,
+        start: usize,
+        end: usize,
+        style: Option<String>,
+    ) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let style = style.as_deref().map(parse_list_style).transpose()?;
+        this.inner
+            .set_list_style(Range::from_offsets(start, end), style)
+            .map_err(|e| JsValue::from_str(&format!("Set list style failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Gets the list marker style at the specified position
     ///
-    /// ```javascript
-    /// // Reduce history for mobile devices
-    /// doc.setHistoryLimit(50);
+    /// Returns the style in the format accepted by `setListStyle`, or undefined
+    /// for the default style and lines that are not list items
     ///
-    /// // Minimal history for memory-constrained environments
-    /// doc.setHistoryLimit(20);
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = getListStyleAt)]
+    pub fn get_list_style_at(&self, position: usize) -> Option<String> {
+        let this = self.state();
+        this.inner
+            .get_list_style_at(Position::new(position))
+            .map(|style| list_style_to_string(&style))
+    }
+
+    /// Gets the marker shown before the list item at the specified position
     ///
-    /// // Restore default
-    /// doc.setHistoryLimit(100);
-    /// ```
-    #[wasm_bindgen(js_name = setHistoryLimit)]
-    pub fn set_history_limit(&mut self, max_size: usize) {
-        self.inner.set_history_limit(max_size);
+    /// Numbered markers include the item's number and a period, such as "3."
+    /// or "iv."; bullet markers are the bullet character. Returns undefined
+    /// for lines that are not list items.
+    ///
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = getListMarkerAt)]
+    pub fn get_list_marker_at(&self, position: usize) -> Option<String> {
+        let this = self.state();
+        this.inner.get_list_marker_at(Position::new(position))
     }
 
-    /// Clears all undo and redo history
+    /// Gets the number the list item at the specified position restarts its
+    /// list at
     ///
-    /// This immediately frees all memory used by the command history.
-    /// Useful after save operations or when memory is constrained.
+    /// Returns undefined for items that continue the count of the items
+    /// before them and for lines that are not numbered list items.
     ///
-    /// # Memory Impact
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = getListStartAt)]
+    pub fn get_list_start_at(&self, position: usize) -> Option<usize> {
+        let this = self.state();
+        this.inner.get_list_start_at(Position::new(position))
+    }
+
+    /// Normalizes where numbered lists restart their count
     ///
-    /// Frees approximately 6.4KB plus text data for a full history of 100 commands.
-    /// The actual amount depends on the size of operations in the history.
+    /// Call after moving list items around, such as by cutting and pasting
+    /// them. Each list keeps the number its first item starts at and the
+    /// items after it are numbered on from there. Undoable.
     ///
-    /// # Use Cases
+    /// # Errors
+    /// Returns a JsValue error if the document is read-only
+    #[wasm_bindgen(js_name = renumberLists)]
+    pub fn renumber_lists(&self) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .renumber_lists()
+            .map_err(|e| JsValue::from_str(&format!("Renumber lists failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Indents the paragraphs on the lines in the range by one step
     ///
-    /// - After saving the document (user can reload if needed)
-    /// - When memory usage is high
-    /// - After major operations that you don't want to undo
-    /// - When switching between documents
+    /// Separate from list nesting; lines that are not paragraphs are left
+    /// unchanged.
     ///
-    /// # Warning
+    /// # Arguments
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
     ///
-    /// After calling this method, users will not be able to undo or redo
-    /// any previous operations. Use with caution.
+    /// # Errors
+    /// Returns a JsValue error if the range is invalid or the document is read-only
+    #[wasm_bindgen(js_name = increaseBlockIndent)]
+    pub fn increase_block_indent(&self, start: usize, end: usize) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .increase_block_indent(Range::from_offsets(start, end))
+            .map_err(|e| JsValue::from_str(&format!("Increase block indent failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Removes one indent step from the paragraphs on the lines in the range
     ///
-    /// # Example
+    /// # Arguments
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
     ///
-    /// ```javascript
-    /// // Clear history after save
-    /// function saveDocument(doc) {
-    ///   const json = doc.toJSON();
-    ///   localStorage.setItem('document', json);
-    ///   doc.clearHistory(); // Free memory
-    /// }
+    /// # Errors
+    /// Returns a JsValue error if the range is invalid or the document is read-only
+    #[wasm_bindgen(js_name = decreaseBlockIndent)]
+    pub fn decrease_block_indent(&self, start: usize, end: usize) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .decrease_block_indent(Range::from_offsets(start, end))
+            .map_err(|e| JsValue::from_str(&format!("Decrease block indent failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Gets the paragraph indent at the specified position, in steps
     ///
-    /// // Clear history when memory is low
-    /// if (performance.memory.usedJSHeapSize > threshold) {
-    ///   doc.clearHistory();
-    /// }
-    /// ```
-    #[wasm_bindgen(js_name = clearHistory)]
-    pub fn clear_history(&mut self) {
-        self.inner.clear_history();
+    /// Returns 0 for unindented paragraphs and other blocks
+    ///
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = getBlockIndentAt)]
+    pub fn get_block_indent_at(&self, position: usize) -> u8 {
+        let this = self.state();
+        this.inner.get_block_indent_at(Position::new(position))
     }
 
-    /// Returns memory usage statistics for the document
+    /// Turns the first-line indent of the paragraphs in the range on or off
     ///
-    /// Provides detailed information about memory usage across all components
-    /// of the document. Useful for monitoring and optimizing memory usage.
+    /// # Arguments
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
+    /// * `indented` - Whether the first line is indented
     ///
-    /// # Returns
+    /// # Errors
+    /// Returns a JsValue error if the range is invalid or the document is read-only
+    #[wasm_bindgen(js_name = setFirstLineIndent)]
+    pub fn set_first_line_indent(
+        &self,
+        start: usize,
+        end: usize,
+        indented: bool,
+    ) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .set_first_line_indent(Range::from_offsets(start, end), indented)
+            .map_err(|e| JsValue::from_str(&format!("Set first-line indent failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Returns true if the paragraph at the specified position has an
+    /// indented first line
     ///
-    /// A JavaScript object with the following properties:
-    /// - `textLength`: Number of characters in the document
-    /// - `formatRuns`: Number of format runs (fewer is better)
-    /// - `blocks`: Number of block info entries
-    /// - `undoCommands`: Number of commands in undo stack
-    /// - `redoCommands`: Number of commands in redo stack
-    /// - `estimatedBytes`: Estimated total memory usage in bytes
-    /// - `estimatedKB`: Estimated total memory usage in kilobytes
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = hasFirstLineIndentAt)]
+    pub fn has_first_line_indent_at(&self, position: usize) -> bool {
+        let this = self.state();
+        this.inner.has_first_line_indent_at(Position::new(position))
+    }
+
+    /// Sets the base text direction of the lines in the range
     ///
-    /// # Memory Breakdown
+    /// The direction applies to every block type and is exported as a `dir`
+    /// attribute in HTML.
     ///
-    /// The estimated memory includes:
-    /// - Base overhead: ~1KB
-    /// - Text storage: ~4 bytes per character + gap overhead
-    /// - Format storage: ~32 bytes per format run + ~16 bytes per block
-    /// - Command history: ~64 bytes per command + affected text
+    /// # Arguments
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
+    /// * `direction` - "ltr" or "rtl"; "auto" or null detects the direction
+    ///   from the text
     ///
-    /// # Example
+    /// # Errors
+    /// Returns a JsValue error if the direction is unknown, the range is
+    /// invalid or the document is read-only
+    #[wasm_bindgen(js_name = setTextDirection)]
+    pub fn set_text_direction(
+        &self,
+        start: usize,
+        end: usize,
+        direction: Option<String>,
+    ) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let direction = parse_text_direction(direction.as_deref().unwrap_or("auto"))?;
+        this.inner
+            .set_text_direction(Range::from_offsets(start, end), direction)
+            .map_err(|e| JsValue::from_str(&format!("Set text direction failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Gets the text direction set on the line at the specified position
     ///
-    /// ```javascript
-    /// const stats = doc.getMemoryStats();
-    /// console.log(`Document: ${stats.textLength} chars`);
-    /// console.log(`Format runs: ${stats.formatRuns}`);
-    /// console.log(`Memory: ${stats.estimatedKB.toFixed(2)} KB`);
+    /// Returns "ltr" or "rtl", or undefined where the direction is detected
+    /// from the text
     ///
-    /// // Monitor memory usage
-    /// if (stats.estimatedKB > 500) {
-    ///   console.warn('Large document, consider reducing history');
-    ///   doc.setHistoryLimit(50);
-    /// }
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = getTextDirectionAt)]
+    pub fn get_text_direction_at(&self, position: usize) -> Option<String> {
+        let this = self.state();
+        this.inner
+            .get_text_direction_at(Position::new(position))
+            .map(|direction| direction.html_value().to_string())
+    }
+
+    /// Gets the direction the line at the specified position is laid out in
     ///
-    /// // Check format efficiency
-    /// if (stats.formatRuns > stats.textLength / 10) {
-    ///   console.warn('Many format runs, document may be fragmented');
-    /// }
-    /// ```
-    #[wasm_bindgen(js_name = getMemoryStats)]
-    pub fn get_memory_stats(&self) -> JsValue {
-        let (text_length, run_count, block_count, undo_count, redo_count, total_bytes) =
-            self.inner.memory_stats();
+    /// Returns "ltr" or "rtl": the direction set on the line, or else the
+    /// direction of its first letter
+    ///
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = getBaseDirectionAt)]
+    pub fn get_base_direction_at(&self, position: usize) -> String {
+        let this = self.state();
+        this.inner
+            .get_base_direction_at(Position::new(position))
+            .html_value()
+            .to_string()
+    }
 
-        let obj = js_sys::Object::new();
-        js_sys::Reflect::set(&obj, &"textLength".into(), &text_length.into()).unwrap();
-        js_sys::Reflect::set(&obj, &"formatRuns".into(), &run_count.into()).unwrap();
-        js_sys::Reflect::set(&obj, &"blocks".into(), &block_count.into()).unwrap();
-        js_sys::Reflect::set(&obj, &"undoCommands".into(), &undo_count.into()).unwrap();
-        js_sys::Reflect::set(&obj, &"redoCommands".into(), &redo_count.into()).unwrap();
-        js_sys::Reflect::set(&obj, &"estimatedBytes".into(), &total_bytes.into()).unwrap();
-        js_sys::Reflect::set(
-            &obj,
-            &"estimatedKB".into(),
-            &((total_bytes as f64) / 1024.0).into(),
-        )
-        .unwrap();
+    /// Gets the runs of left-to-right and right-to-left text of the line at
+    /// the specified position, in display order from left to right
+    ///
+    /// Returned shape: Array of objects
+    /// { start: number, end: number, level: number, direction: "ltr" | "rtl" }
+    ///
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = getBidiRuns)]
+    pub fn get_bidi_runs(&self, position: usize) -> js_sys::Array {
+        let this = self.state();
+        let line = this.inner.bidi_line_at(Position::new(position));
+        let runs = line.runs().into_iter().map(|run| {
+            let obj = js_sys::Object::new();
+            js_sys::Reflect::set(&obj, &"start".into(), &run.start.into()).unwrap();
+            js_sys::Reflect::set(&obj, &"end".into(), &run.end.into()).unwrap();
+            js_sys::Reflect::set(&obj, &"level".into(), &run.level.into()).unwrap();
+            js_sys::Reflect::set(
+                &obj,
+                &"direction".into(),
+                &run.direction().html_value().into(),
+            )
+            .unwrap();
+            JsValue::from(obj)
+        });
+        js_sys::Array::from_iter(runs)
+    }
+
+    /// Gets the offset of each character of the line at the specified
+    /// position, in display order from left to right
+    ///
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = getVisualOrder)]
+    pub fn get_visual_order(&self, position: usize) -> Vec<u32> {
+        let this = self.state();
+        this.inner
+            .bidi_line_at(Position::new(position))
+            .visual_order()
+            .into_iter()
+            .map(|offset| offset as u32)
+            .collect()
+    }
+
+    /// Turns the specified range into a block quote with a citation and nesting depth
+    ///
+    /// # Arguments
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
+    /// * `cite` - Optional URL of the quoted source
+    /// * `depth` - Nesting depth, where 1 is a top-level quote
+    ///
+    /// # Errors
+    /// Returns a JsValue error if depth is 0 or greater than 16
+    #[wasm_bindgen(js_name = setBlockQuote)]
+    pub fn set_block_quote(
+        &self,
+        start: usize,
+        end: usize,
+        cite: Option<String>,
+        depth: u8,
+    ) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        if !(1..=MAX_QUOTE_DEPTH).contains(&depth) {
+            return Err(JsValue::from_str(&format!(
+                "Block quote depth must be between 1 and {}",
+                MAX_QUOTE_DEPTH
+            )));
+        }
+        let range = Range::from_offsets(start, end);
+        this.inner
+            .set_block_type(range, BlockType::block_quote_with(cite, depth));
+        this.notify_change();
+        Ok(())
+    }
 
+    /// Gets the block quote attributes at the specified position
+    ///
+    /// Returns a JsValue object with `cite` (string or null) and `depth`
+    /// properties, or null if the position is not inside a block quote
+    ///
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = getBlockQuoteAt)]
+    pub fn get_block_quote_at(&self, position: usize) -> JsValue {
+        let this = self.state();
+        let block_type = this.inner.get_block_type_at(Position::new(position));
+        let Some(depth) = block_type.quote_depth() else {
+            return JsValue::NULL;
+        };
+        let cite = block_type
+            .quote_cite()
+            .map_or(JsValue::NULL, JsValue::from_str);
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"cite".into(), &cite).unwrap();
+        js_sys::Reflect::set(&obj, &"depth".into(), &depth.into()).unwrap();
         obj.into()
     }
 
+    /// Turns the specified range into a code block in the given language
+    ///
+    /// The language is written as a `language-*` class in HTML and as the
+    /// info string of the fence in Markdown, for syntax highlighters. Only
+    /// its first word is kept, and names with characters other than
+    /// letters, digits and `+-#._` are dropped.
+    ///
+    /// # Arguments
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
+    /// * `language` - Language such as "rust", or null for none
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.setCodeBlockLanguage(0, 20, "rust");
+    /// doc.getCodeBlockLanguageAt(0); // "rust"
+    /// ```
+    #[wasm_bindgen(js_name = setCodeBlockLanguage)]
+    pub fn set_code_block_language(&self, start: usize, end: usize, language: Option<String>) {
+        let mut this = self.state_mut();
+        let range = Range::from_offsets(start, end);
+        this.inner
+            .set_block_type(range, BlockType::code_block_with(language.as_deref()));
+        this.notify_change();
+    }
 
-}
+    /// Gets the language of the code block at the specified position
+    ///
+    /// `getBlockTypeAt` returns "code-block" for code blocks in any
+    /// language; this returns the language, or undefined if the position is
+    /// not in a code block or the block has none.
+    ///
+    /// # Arguments
+    /// * `position` - The character offset to query
+    #[wasm_bindgen(js_name = getCodeBlockLanguageAt)]
+    pub fn get_code_block_language_at(&self, position: usize) -> Option<String> {
+        let this = self.state();
+        this.inner
+            .get_block_type_at(Position::new(position))
+            .code_language()
+            .map(str::to_string)
+    }
 
-// Implement Drop trait for automatic cleanup
-impl Drop for WasmDocument {
-    /// Automatically cleans up resources when the document is dropped
+    /// Sets the selection to the specified anchor and focus positions
     ///
-    /// This is called automatically by Rust when:
-    /// 1. The `free()` method is called explicitly
-    /// 2. The JavaScript garbage collector collects the object
-    /// 3. The document goes out of scope in Rust code
+    /// # Arguments
+    /// * `anchor` - The anchor position (where selection started)
+    /// * `focus` - The focus position (where selection ends)
+    #[wasm_bindgen(js_name = setSelection)]
+    pub fn set_selection(&self, anchor: usize, focus: usize) {
+        let mut this = self.state_mut();
+        let selection = Selection::new(Position::new(anchor), Position::new(focus));
+        this.inner.set_selection(selection);
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Gets the current selection
     ///
-    /// The Drop implementation ensures that all resources are properly
-    /// released, including:
-    /// - Clearing the command history to free command objects
-    /// - Clearing event callbacks to release JavaScript function references
-    /// - Allowing the Document and its internal structures to be deallocated
-    fn drop(&mut self) {
-        // Clear event callbacks to release JavaScript function references
-        // This is important to avoid memory leaks in JavaScript
-        self.callbacks.clear_all();
+    /// Returns a JsValue object with anchor and focus properties
+    #[wasm_bindgen(js_name = getSelection)]
+    pub fn get_selection(&self) -> JsValue {
+        let this = self.state();
+        let selection = this.inner.get_selection();
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"anchor".into(), &selection.anchor.offset().into()).unwrap();
+        js_sys::Reflect::set(&obj, &"focus".into(), &selection.focus.offset().into()).unwrap();
+        obj.into()
+    }
 
-        // The Document's Drop implementation (if any) will be called automatically
-        // The inner Document will be dropped, which will:
-        // - Drop the TextStorage (releasing the gap buffer)
-        // - Drop the FormatStorage (releasing format runs and blocks)
-        // - Drop the CommandHistory (releasing all commands in undo/redo stacks)
-        // - Drop the Selection
+    /// Gets the text within the current selection
+    #[wasm_bindgen(js_name = getSelectedText)]
+    pub fn get_selected_text(&self) -> String {
+        let this = self.state();
+        this.inner.get_selected_text()
     }
-}
 
-/// Helper function to parse inline format from string
-fn parse_inline_format(format_type: &str) -> Result<InlineFormat, JsValue> {
-    match format_type {
+    /// Selects all content in the document
+    #[wasm_bindgen(js_name = selectAll)]
+    pub fn select_all(&self) {
+        let mut this = self.state_mut();
+        this.inner.select_all();
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Collapses the selection to the start position
+    #[wasm_bindgen(js_name = collapseToStart)]
+    pub fn collapse_to_start(&self) {
+        let mut this = self.state_mut();
+        this.inner.collapse_to_start();
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Collapses the selection to the end position
+    #[wasm_bindgen(js_name = collapseToEnd)]
+    pub fn collapse_to_end(&self) {
+        let mut this = self.state_mut();
+        this.inner.collapse_to_end();
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Selects the word at the specified position, as on double-click
+    ///
+    /// Between words, the run of spaces or the punctuation character there is selected.
+    ///
+    /// # Arguments
+    /// * `position` - The character offset to select the word at
+    #[wasm_bindgen(js_name = selectWord)]
+    pub fn select_word(&self, position: usize) {
+        let mut this = self.state_mut();
+        this.inner.select_word(Position::new(position));
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Selects the line at the specified position, as on triple-click
+    ///
+    /// # Arguments
+    /// * `position` - The character offset to select the line at
+    #[wasm_bindgen(js_name = selectLine)]
+    pub fn select_line(&self, position: usize) {
+        let mut this = self.state_mut();
+        this.inner.select_line(Position::new(position));
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Selects the block at the specified position
+    ///
+    /// Consecutive lines of the same list, block quote or code block form one block.
+    ///
+    /// # Arguments
+    /// * `position` - The character offset to select the block at
+    #[wasm_bindgen(js_name = selectBlock)]
+    pub fn select_block(&self, position: usize) {
+        let mut this = self.state_mut();
+        this.inner.select_block(Position::new(position));
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Grows the selection to the enclosing word, line, block or document
+    ///
+    /// # Returns
+    /// false if the whole document is already selected
+    #[wasm_bindgen(js_name = expandSelection)]
+    pub fn expand_selection(&self) -> bool {
+        let mut this = self.state_mut();
+        let expanded = this.inner.expand_selection();
+        if expanded {
+            this.callbacks.trigger_selection_callbacks();
+        }
+        expanded
+    }
+
+    /// Undoes the last `expandSelection`
+    ///
+    /// # Returns
+    /// false if the selection was not expanded, or has changed since
+    #[wasm_bindgen(js_name = shrinkSelection)]
+    pub fn shrink_selection(&self) -> bool {
+        let mut this = self.state_mut();
+        let shrunk = this.inner.shrink_selection();
+        if shrunk {
+            this.callbacks.trigger_selection_callbacks();
+        }
+        shrunk
+    }
+
+    /// Adds a secondary selection for multi-cursor editing
+    ///
+    /// Selections that overlap or touch are merged.
+    ///
+    /// # Arguments
+    /// * `anchor` - The anchor position (where selection started)
+    /// * `focus` - The focus position (where selection ends)
+    #[wasm_bindgen(js_name = addSelection)]
+    pub fn add_selection(&self, anchor: usize, focus: usize) {
+        let mut this = self.state_mut();
+        let selection = Selection::new(Position::new(anchor), Position::new(focus));
+        this.inner.add_selection(selection);
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Selects every occurrence of the selected text, or of the word at the
+    /// caret, for editing them all at once
+    ///
+    /// Occurrences are matched case-sensitively, and only whole words match
+    /// when there is only a caret.
+    ///
+    /// # Returns
+    /// The number of selections, or 0 if the caret is not on a word
+    #[wasm_bindgen(js_name = selectAllOccurrences)]
+    pub fn select_all_occurrences(&self) -> usize {
+        let mut this = self.state_mut();
+        let count = this.inner.select_all_occurrences();
+        if count > 0 {
+            this.callbacks.trigger_selection_callbacks();
+        }
+        count
+    }
+
+    /// Removes all secondary selections, keeping the primary one
+    #[wasm_bindgen(js_name = clearSecondarySelections)]
+    pub fn clear_secondary_selections(&self) {
+        let mut this = self.state_mut();
+        this.inner.clear_secondary_selections();
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Gets all selections
+    ///
+    /// Returns a JsValue array of objects with anchor and focus properties,
+    /// the primary selection first and the secondary ones in document order
+    #[wasm_bindgen(js_name = getSelections)]
+    pub fn get_selections(&self) -> js_sys::Array {
+        let this = self.state();
+        let selections = this.inner.get_selections().into_iter().map(|selection| {
+            let obj = js_sys::Object::new();
+            js_sys::Reflect::set(&obj, &"anchor".into(), &selection.anchor.offset().into())
+                .unwrap();
+            js_sys::Reflect::set(&obj, &"focus".into(), &selection.focus.offset().into()).unwrap();
+            JsValue::from(obj)
+        });
+        js_sys::Array::from_iter(selections)
+    }
+
+    /// Types text at every selection as a single undo step
+    ///
+    /// Selected text is replaced; each selection ends up collapsed after the
+    /// inserted text.
+    ///
+    /// # Arguments
+    /// * `text` - The text to insert
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the document is read-only or would exceed its maximum length
+    #[wasm_bindgen(js_name = insertTextAtSelections)]
+    pub fn insert_text_at_selections(&self, text: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .insert_text_at_selections(text)
+            .map_err(|e| JsValue::from_str(&format!("Insert failed: {}", e)))?;
+        this.notify_change();
+        this.callbacks.trigger_selection_callbacks();
+        Ok(())
+    }
+
+    /// Inserts a soft line break (shift+enter) at every selection
+    ///
+    /// The break stays inside the current block instead of starting a new
+    /// one; it is exported as `<br>` in HTML and as a hard line break in
+    /// markdown.
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the document is read-only or would exceed its maximum length
+    #[wasm_bindgen(js_name = insertSoftBreak)]
+    pub fn insert_soft_break(&self) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .insert_soft_break()
+            .map_err(|e| JsValue::from_str(&format!("Insert failed: {}", e)))?;
+        this.notify_change();
+        this.callbacks.trigger_selection_callbacks();
+        Ok(())
+    }
+
+    /// Deletes at every selection as a single undo step
+    ///
+    /// Selected text is deleted; collapsed carets delete one character like
+    /// Backspace, or like Delete when `forward` is true.
+    ///
+    /// # Arguments
+    /// * `forward` - Whether collapsed carets delete the character after them
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the document is read-only
+    #[wasm_bindgen(js_name = deleteAtSelections)]
+    pub fn delete_at_selections(&self, forward: bool) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .delete_at_selections(forward)
+            .map_err(|e| JsValue::from_str(&format!("Delete failed: {}", e)))?;
+        this.notify_change();
+        this.callbacks.trigger_selection_callbacks();
+        Ok(())
+    }
+
+    /// Applies a format to every non-collapsed selection as a single undo step
+    ///
+    /// # Arguments
+    /// * `format_type` - The type of format to apply
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the format type is invalid
+    #[wasm_bindgen(js_name = applyFormatToSelections)]
+    pub fn apply_format_to_selections(&self, format_type: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let format = parse_inline_format(format_type)?;
+        this.inner
+            .apply_format_to_selections(format)
+            .map_err(|e| JsValue::from_str(&format!("Apply format failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Removes a format from every non-collapsed selection as a single undo step
+    ///
+    /// # Arguments
+    /// * `format_type` - The type of format to remove
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the format type is invalid
+    #[wasm_bindgen(js_name = removeFormatFromSelections)]
+    pub fn remove_format_from_selections(&self, format_type: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let format = parse_inline_format(format_type)?;
+        this.inner
+            .remove_format_from_selections(&format)
+            .map_err(|e| JsValue::from_str(&format!("Remove format failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Moves the cursor left by one character
+    #[wasm_bindgen(js_name = moveCursorLeft)]
+    pub fn move_cursor_left(&self) {
+        let mut this = self.state_mut();
+        this.inner.move_cursor_left();
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Moves the cursor right by one character
+    #[wasm_bindgen(js_name = moveCursorRight)]
+    pub fn move_cursor_right(&self) {
+        let mut this = self.state_mut();
+        this.inner.move_cursor_right();
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Moves the cursor up by one line
+    #[wasm_bindgen(js_name = moveCursorUp)]
+    pub fn move_cursor_up(&self) {
+        let mut this = self.state_mut();
+        this.inner.move_cursor_up();
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Moves the cursor down by one line
+    #[wasm_bindgen(js_name = moveCursorDown)]
+    pub fn move_cursor_down(&self) {
+        let mut this = self.state_mut();
+        this.inner.move_cursor_down();
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Moves the cursor to the start of the current line
+    #[wasm_bindgen(js_name = moveToLineStart)]
+    pub fn move_to_line_start(&self) {
+        let mut this = self.state_mut();
+        this.inner.move_to_line_start();
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Moves the cursor to the end of the current line
+    #[wasm_bindgen(js_name = moveToLineEnd)]
+    pub fn move_to_line_end(&self) {
+        let mut this = self.state_mut();
+        this.inner.move_to_line_end();
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Moves the cursor to the start of the document
+    #[wasm_bindgen(js_name = moveToDocumentStart)]
+    pub fn move_to_document_start(&self) {
+        let mut this = self.state_mut();
+        this.inner.move_to_document_start();
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Moves the cursor to the end of the document
+    #[wasm_bindgen(js_name = moveToDocumentEnd)]
+    pub fn move_to_document_end(&self) {
+        let mut this = self.state_mut();
+        this.inner.move_to_document_end();
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Moves the cursor by word boundaries
+    ///
+    /// # Arguments
+    /// * `forward` - If true, moves to the next word; otherwise, moves to the previous word
+    #[wasm_bindgen(js_name = moveByWord)]
+    pub fn move_by_word(&self, forward: bool) {
+        let mut this = self.state_mut();
+        this.inner.move_by_word(forward);
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Moves the cursor one character to the left or right on screen
+    ///
+    /// Unlike `moveCursorLeft` and `moveCursorRight`, which move through the
+    /// text, this follows the display order of right-to-left text.
+    ///
+    /// # Arguments
+    /// * `right` - If true, moves right; otherwise, moves left
+    #[wasm_bindgen(js_name = moveCursorVisually)]
+    pub fn move_cursor_visually(&self, right: bool) {
+        let mut this = self.state_mut();
+        this.inner.move_cursor_visually(right);
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Deletes back to the start of the word before each caret, like
+    /// Ctrl+Backspace, as a single undo step
+    ///
+    /// Non-collapsed selections are deleted as they are.
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the document is read-only
+    #[wasm_bindgen(js_name = deleteWordBackward)]
+    pub fn delete_word_backward(&self) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .delete_word_backward()
+            .map_err(|e| JsValue::from_str(&format!("Delete failed: {}", e)))?;
+        this.notify_change();
+        this.callbacks.trigger_selection_callbacks();
+        Ok(())
+    }
+
+    /// Deletes up to the start of the word after each caret, like
+    /// Ctrl+Delete, as a single undo step
+    ///
+    /// Non-collapsed selections are deleted as they are.
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the document is read-only
+    #[wasm_bindgen(js_name = deleteWordForward)]
+    pub fn delete_word_forward(&self) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .delete_word_forward()
+            .map_err(|e| JsValue::from_str(&format!("Delete failed: {}", e)))?;
+        this.notify_change();
+        this.callbacks.trigger_selection_callbacks();
+        Ok(())
+    }
+
+    /// Serializes the document to JSON format
+    ///
+    /// # Arguments
+    /// * `options` - Optional object with:
+    ///   - `includeSelection` (false): record the selections, which
+    ///     `fromJSON` restores along with the bookmarks
+    ///   - `pretty` (false): indent the output
+    ///
+    /// # Returns
+    /// A JSON string representation of the document
+    ///
+    /// # Errors
+    /// Returns a JsValue error if serialization fails or an option has the
+    /// wrong type
+    ///
+    /// # Example
+    /// ```javascript
+    /// localStorage.setItem('draft', doc.toJSON({ includeSelection: true }));
+    /// ```
+    #[wasm_bindgen(js_name = toJSON)]
+    pub fn to_json(&self, options: JsValue) -> Result<String, JsValue> {
+        let this = self.try_state()?;
+        let mut json = JsonOptions::new();
+        // JSON.stringify passes the property key as a string
+        if options.is_object() {
+            let get = |key: &str| js_sys::Reflect::get(&options, &JsValue::from_str(key));
+            if let Some(include) = optional_bool(&get("includeSelection")?, "includeSelection")? {
+                json.include_selection = include;
+            }
+            if let Some(pretty) = optional_bool(&get("pretty")?, "pretty")? {
+                json.pretty = pretty;
+            }
+        }
+
+        this.inner
+            .to_json_with_options(&json)
+            .map_err(|e| JsValue::from_str(&format!("JSON serialization failed: {}", e)))
+    }
+
+    /// Serializes the document to pretty-printed JSON format
+    ///
+    /// # Returns
+    /// A formatted JSON string representation of the document
+    ///
+    /// # Errors
+    /// Returns a JsValue error if serialization fails
+    #[wasm_bindgen(js_name = toJSONPretty)]
+    pub fn to_json_pretty(&self) -> Result<String, JsValue> {
+        let this = self.try_state()?;
+        this.inner
+            .to_json_pretty()
+            .map_err(|e| JsValue::from_str(&format!("JSON serialization failed: {}", e)))
+    }
+
+    /// Serializes the document to the compact binary format
+    ///
+    /// Stores the same content as `toJSON` but is much faster to write and
+    /// read for large documents.
+    ///
+    /// # Arguments
+    /// * `options` - Optional object with `includeSelection` (false), as in
+    ///   `toJSON`
+    ///
+    /// # Returns
+    /// The encoded document as a Uint8Array
+    ///
+    /// # Errors
+    /// Returns a JsValue error if serialization fails or an option has the
+    /// wrong type
+    ///
+    /// # Example
+    /// ```javascript
+    /// await fetch('/save', { method: 'PUT', body: doc.toBinary() });
+    /// ```
+    #[wasm_bindgen(js_name = toBinary)]
+    pub fn to_binary(&self, options: JsValue) -> Result<Vec<u8>, JsValue> {
+        let this = self.try_state()?;
+        let mut include_selection = false;
+        if options.is_object() {
+            let value = js_sys::Reflect::get(&options, &JsValue::from_str("includeSelection"))?;
+            if let Some(include) = optional_bool(&value, "includeSelection")? {
+                include_selection = include;
+            }
+        }
+
+        this.inner
+            .to_binary(include_selection)
+            .map_err(|e| JsValue::from_str(&format!("Binary serialization failed: {}", e)))
+    }
+
+    /// Takes a snapshot of the document's text and formatting for `diff`
+    ///
+    /// Images and annotations are not included.
+    pub fn snapshot(&self) -> WasmSnapshot {
+        let this = self.state();
+        WasmSnapshot {
+            inner: this.inner.snapshot(),
+        }
+    }
+
+    /// Lists the changes that turn one snapshot into another
+    ///
+    /// # Arguments
+    /// * `older` - The snapshot to start from
+    /// * `newer` - The snapshot to reach
+    ///
+    /// # Returns
+    /// A JSON array that is empty if nothing changed. It starts with at most
+    /// one `{ type: "text", start, end, text }` replacement in the older
+    /// snapshot's offsets, followed by `{ type: "format", start, end, formats }`
+    /// and `{ type: "block", start, end, block_type, ... }` changes in the
+    /// newer snapshot's offsets. A format change sets the exact formats of
+    /// its range, with formats written as in `toJSON`, and a block change
+    /// sets the attributes of the lines it covers.
+    ///
+    /// # Errors
+    /// Returns a JsValue error if serialization fails
+    ///
+    /// # Example
+    /// ```javascript
+    /// const saved = doc.snapshot();
+    /// // ... edits ...
+    /// const current = doc.snapshot();
+    /// await fetch('/save', { method: 'PATCH', body: WasmDocument.diff(saved, current) });
+    /// ```
+    pub fn diff(older: &WasmSnapshot, newer: &WasmSnapshot) -> Result<String, JsValue> {
+        serde_json::to_string(&older.inner.diff(&newer.inner))
+            .map_err(|e| JsValue::from_str(&format!("Diff failed: {}", e)))
+    }
+
+    /// Deserializes a document from JSON format
+    ///
+    /// # Arguments
+    /// * `json` - A JSON string representation of a document
+    ///
+    /// # Returns
+    /// A new WasmDocument instance
+    ///
+    /// # Errors
+    /// Returns a JsValue error if deserialization fails
+    #[wasm_bindgen(js_name = fromJSON)]
+    pub fn from_json(json: &str) -> Result<WasmDocument, JsValue> {
+        let doc = Document::from_json(json)
+            .map_err(|e| JsValue::from_str(&format!("JSON deserialization failed: {}", e)))?;
+        Ok(WasmDocument::from_document(doc))
+    }
+
+    /// Deserializes a document from the binary format
+    ///
+    /// JSON bytes from `toJSON` are accepted too, so documents saved before
+    /// switching to the binary format keep loading.
+    ///
+    /// # Arguments
+    /// * `bytes` - Bytes written by `toBinary`, or UTF-8 JSON
+    ///
+    /// # Returns
+    /// A new WasmDocument instance
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the bytes are malformed, truncated or
+    /// written by a newer version of the format
+    #[wasm_bindgen(js_name = fromBinary)]
+    pub fn from_binary(bytes: &[u8]) -> Result<WasmDocument, JsValue> {
+        let doc = Document::from_binary(bytes)
+            .map_err(|e| JsValue::from_str(&format!("Binary deserialization failed: {}", e)))?;
+        Ok(WasmDocument::from_document(doc))
+    }
+
+    /// Converts a document saved with `toJSON` to the binary format
+    ///
+    /// Lets stored documents be migrated without creating an editor for
+    /// each one.
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the JSON is not a valid document
+    ///
+    /// # Example
+    /// ```javascript
+    /// const bytes = WasmDocument.jsonToBinary(localStorage.getItem('draft'));
+    /// ```
+    #[wasm_bindgen(js_name = jsonToBinary)]
+    pub fn json_to_binary(json: &str) -> Result<Vec<u8>, JsValue> {
+        Document::from_json(json)
+            .map_err(|e| JsValue::from_str(&format!("JSON deserialization failed: {}", e)))?
+            .to_binary(true)
+            .map_err(|e| JsValue::from_str(&format!("Binary serialization failed: {}", e)))
+    }
+
+    /// Exports the document to Markdown format
+    ///
+    /// # Returns
+    /// A Markdown string representation of the document
+    #[wasm_bindgen(js_name = toMarkdown)]
+    pub fn to_markdown(&self) -> String {
+        let this = self.state();
+        this.inner.to_markdown()
+    }
+
+    /// Exports the document to Markdown in the given dialect
+    ///
+    /// # Arguments
+    /// * `options_json` - Optional JSON object; every key is optional:
+    ///   - `gfm`: GitHub Flavored Markdown extensions on or off (default true)
+    ///   - `strikethrough`: Whether to write `~~text~~` (overrides `gfm`)
+    ///   - `highlight`: Whether to write `==text==` (default false)
+    ///   - `math`: Whether to write `$latex$` and `$$latex$$` (default true)
+    ///   - `bullet`: List marker, `"-"` (default), `"*"` or `"+"`
+    ///   - `emphasis`: `"*"` (default) for `*italic*`/`**bold**`, or `"_"`
+    ///   - `headingStyle`: `"atx"` (default) or `"setext"` for levels 1-2
+    ///   - `hardBreak`: `"backslash"` (default) or `"spaces"`
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the options are not a JSON object or a
+    /// value is not one of the above
+    ///
+    /// # Example (JavaScript)
+    /// ```javascript
+    /// const md = doc.toMarkdownWithOptions(JSON.stringify({ bullet: "*", headingStyle: "setext" }));
+    /// ```
+    #[wasm_bindgen(js_name = toMarkdownWithOptions)]
+    pub fn to_markdown_with_options(
+        &self,
+        options_json: Option<String>,
+    ) -> Result<String, JsValue> {
+        let this = self.try_state()?;
+        let options = markdown_options_from_json(options_json.as_deref())?;
+        Ok(this.inner.to_markdown_with_options(&options))
+    }
+
+    /// Exports the document to Markdown along with a source map
+    ///
+    /// # Returns
+    /// An object with `markdown` and `lines`, an array of objects with
+    /// `modelStart`, `modelEnd`, `sourceLine` and `sourceColumn` properties,
+    /// one per document line. Source lines are zero-based, and
+    /// `sourceColumn` is where the line's text starts after block markers.
+    ///
+    /// # Example
+    /// ```javascript
+    /// const { markdown, lines } = doc.toMarkdownWithSourceMap();
+    /// const line = lines.findLast(l => l.modelStart <= caret);
+    /// sourceEditor.setCursor(line.sourceLine, line.sourceColumn + caret - line.modelStart);
+    /// ```
+    #[wasm_bindgen(js_name = toMarkdownWithSourceMap)]
+    pub fn to_markdown_with_source_map(&self) -> JsValue {
+        let this = self.state();
+        let (markdown, map) = this
+            .inner
+            .to_markdown_with_source_map(&MarkdownOptions::new());
+
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"markdown".into(), &markdown.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"lines".into(), &markdown_source_map_to_js(&map)).unwrap();
+        obj.into()
+    }
+
+    /// Exports the document as Quill Delta JSON
+    ///
+    /// # Returns
+    /// A JSON string of the form `{"ops": [...]}`
+    #[wasm_bindgen(js_name = toDelta)]
+    pub fn to_delta(&self) -> String {
+        let this = self.state();
+        this.inner.to_delta()
+    }
+
+    /// Exports the document as RTF
+    ///
+    /// # Returns
+    /// An RTF string for pasting into or saving for word processors
+    #[wasm_bindgen(js_name = toRTF)]
+    pub fn to_rtf(&self) -> String {
+        let this = self.state();
+        this.inner.to_rtf()
+    }
+
+    /// Exports the document as a Word (.docx) file
+    ///
+    /// # Returns
+    /// A Uint8Array holding the .docx package
+    ///
+    /// # Example
+    /// ```javascript
+    /// const blob = new Blob([doc.toDocx()], {
+    ///   type: "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = toDocx)]
+    pub fn to_docx(&self) -> Vec<u8> {
+        let this = self.state();
+        this.inner.to_docx()
+    }
+
+    /// Exports the document to HTML format
+    ///
+    /// Maps inline formats to HTML tags and block types to appropriate HTML elements.
+    /// All content is properly escaped to prevent XSS attacks.
+    ///
+    /// # Returns
+    /// An HTML string representation of the document
+    #[wasm_bindgen(js_name = toHTML)]
+    pub fn to_html(&self) -> String {
+        let this = self.state();
+        this.inner.to_html()
+    }
+
+    /// Exports the document as a complete HTML page
+    ///
+    /// The body is the output of `toHTML`; the head holds the document title
+    /// and a `<meta>` tag for each metadata entry.
+    #[wasm_bindgen(js_name = toHTMLDocument)]
+    pub fn to_html_document(&self) -> String {
+        let this = self.state();
+        this.inner.to_html_document()
+    }
+
+    /// Exports the document to HTML with options
+    ///
+    /// # Arguments
+    /// * `options` - Optional object with:
+    ///   - `formatStyle` ("semantic"): how inline formats are written, as
+    ///     semantic tags ("semantic"), `<span>` elements with `rte-*` classes
+    ///     such as `rte-bold` ("classes"), or `<span>` elements with inline
+    ///     styles ("inlineStyles")
+    ///   - `dataOffsets` (false): add a `data-offset` attribute with the
+    ///     start offset to each block element, for hydration
+    ///   - `standalone` (false): write a complete page as `toHTMLDocument`
+    ///     does instead of a fragment
+    ///   - `decorationMarks` (false): wrap text covered by decorations set
+    ///     with `setDecorations` in `<mark data-deco-id>` elements
+    ///
+    /// # Errors
+    /// Returns a JsValue error if an option has the wrong type or an unknown
+    /// value
+    ///
+    /// # Example
+    /// ```javascript
+    /// const html = doc.toHTMLWithOptions({ formatStyle: 'classes', dataOffsets: true });
+    /// ```
+    #[wasm_bindgen(js_name = toHTMLWithOptions)]
+    pub fn to_html_with_options(&self, options: JsValue) -> Result<String, JsValue> {
+        let this = self.try_state()?;
+        let mut html = HtmlExportOptions::new();
+        if options.is_object() {
+            let get = |key: &str| js_sys::Reflect::get(&options, &JsValue::from_str(key));
+            if let Some(style) = optional_string(&get("formatStyle")?, "formatStyle")? {
+                html.format_style = match style.as_str() {
+                    "semantic" => HtmlFormatStyle::Semantic,
+                    "classes" => HtmlFormatStyle::Classes,
+                    "inlineStyles" => HtmlFormatStyle::InlineStyles,
+                    _ => {
+                        return Err(JsValue::from_str(&format!(
+                            "Unknown format style: {}",
+                            style
+                        )));
+                    }
+                };
+            }
+            if let Some(offsets) = optional_bool(&get("dataOffsets")?, "dataOffsets")? {
+                html.data_offsets = offsets;
+            }
+            if let Some(standalone) = optional_bool(&get("standalone")?, "standalone")? {
+                html.standalone = standalone;
+            }
+            if let Some(marks) = optional_bool(&get("decorationMarks")?, "decorationMarks")? {
+                html.decoration_marks = marks;
+            }
+        }
+
+        Ok(this.inner.to_html_with_options(&html))
+    }
+
+    /// Pre-renders the next slice of the document into the HTML cache
+    ///
+    /// Call this from an idle callback after loading a large document. Each
+    /// call renders lines until `budget_ms` has elapsed, resuming where the
+    /// previous call stopped. Once it returns true, `toHTML()` serves the
+    /// cached HTML until the document is edited.
+    ///
+    /// # Arguments
+    /// * `budget_ms` - Time budget for this slice in milliseconds
+    ///
+    /// # Returns
+    /// true when the whole document has been rendered and cached
+    ///
+    /// # Example
+    /// ```javascript
+    /// function prerender(deadline) {
+    ///   if (!doc.prerenderNextChunk(deadline.timeRemaining())) {
+    ///     requestIdleCallback(prerender);
+    ///   }
+    /// }
+    /// requestIdleCallback(prerender);
+    /// ```
+    #[wasm_bindgen(js_name = prerenderNextChunk)]
+    pub fn prerender_next_chunk(&self, budget_ms: f64) -> bool {
+        let mut this = self.state_mut();
+        // Lines rendered between clock checks
+        const LINES_PER_CHECK: usize = 64;
+
+        let started = js_sys::Date::now();
+        loop {
+            if this.inner.prerender_html_chunk(LINES_PER_CHECK) {
+                return true;
+            }
+            if js_sys::Date::now() - started >= budget_ms {
+                return false;
+            }
+        }
+    }
+
+    /// Exports a specific range of the document to HTML format
+    ///
+    /// This method supports incremental rendering by allowing you to generate
+    /// HTML for only specific regions of the document.
+    ///
+    /// # Arguments
+    /// * `start` - The start position of the range
+    /// * `end` - The end position of the range
+    ///
+    /// # Returns
+    /// An HTML string representation of the specified range. Text covered
+    /// by decorations set with `setDecorations` is wrapped in
+    /// `<mark data-deco-id>` elements.
+    ///
+    /// # Example
+    /// ```javascript
+    /// // Render only the first 100 characters
+    /// const html = doc.toHTMLRange(0, 100);
+    /// ```
+    #[wasm_bindgen(js_name = toHTMLRange)]
+    pub fn to_html_range(&self, start: usize, end: usize) -> String {
+        let this = self.state();
+        let range = Range::from_offsets(start, end);
+        this.inner.to_html_range(Some(range))
+    }
+
+    /// Exports a single block to HTML
+    ///
+    /// Every line is a block and blocks have no ids, so a block is
+    /// addressed by its line index. Lets a framework re-render only the
+    /// component for a changed block instead of slicing `toHTMLRange` output.
+    ///
+    /// # Arguments
+    /// * `index` - The line index of the block
+    ///
+    /// # Errors
+    /// Returns a JsValue error if there is no such line
+    ///
+    /// # Example
+    /// ```javascript
+    /// const html = doc.getBlockHTML(doc.positionToLineColumn(cursor).line);
+    /// ```
+    #[wasm_bindgen(js_name = getBlockHTML)]
+    pub fn get_block_html(&self, index: usize) -> Result<String, JsValue> {
+        let this = self.try_state()?;
+        this.inner.block_to_html(index).ok_or_else(|| {
+            JsValue::from_str(&format!(
+                "Get block HTML failed: block {} is out of bounds ({} lines)",
+                index,
+                this.inner.line_count()
+            ))
+        })
+    }
+
+    /// Exports the document to HTML along with a source map
+    ///
+    /// Each mapping relates a stretch of document text to where it appears in
+    /// the HTML, for syncing scroll position and selection with a preview
+    /// pane. Offsets and indices count characters. `path` lists child node
+    /// indexes (including whitespace text nodes) from the element whose
+    /// `innerHTML` is the exported HTML down to the node holding the text,
+    /// and `nodeOffset` is the offset of the text within that node.
+    ///
+    /// # Returns
+    /// An object with `html` and `mappings`, an array of objects with
+    /// `modelStart`, `modelEnd`, `htmlStart`, `htmlEnd`, `path` and
+    /// `nodeOffset` properties
+    ///
+    /// # Example
+    /// ```javascript
+    /// const { html, mappings } = doc.toHTMLWithSourceMap();
+    /// preview.innerHTML = html;
+    /// const m = mappings.find(m => m.modelStart <= caret && caret <= m.modelEnd);
+    /// const node = m.path.reduce((node, i) => node.childNodes[i], preview);
+    /// ```
+    #[wasm_bindgen(js_name = toHTMLWithSourceMap)]
+    pub fn to_html_with_source_map(&self) -> JsValue {
+        let this = self.state();
+        let (html, map) = this.inner.to_html_with_source_map();
+
+        let mappings: Vec<JsValue> = map
+            .mappings()
+            .iter()
+            .map(|mapping| {
+                let obj = js_sys::Object::new();
+                let path: Vec<JsValue> = mapping.node_path.iter().map(|&i| i.into()).collect();
+                js_sys::Reflect::set(&obj, &"modelStart".into(), &mapping.model_start.into())
+                    .unwrap();
+                js_sys::Reflect::set(&obj, &"modelEnd".into(), &mapping.model_end.into()).unwrap();
+                js_sys::Reflect::set(&obj, &"htmlStart".into(), &mapping.html_start.into())
+                    .unwrap();
+                js_sys::Reflect::set(&obj, &"htmlEnd".into(), &mapping.html_end.into()).unwrap();
+                js_sys::Reflect::set(&obj, &"path".into(), &js_sys::Array::from_iter(path))
+                    .unwrap();
+                js_sys::Reflect::set(&obj, &"nodeOffset".into(), &mapping.node_offset.into())
+                    .unwrap();
+                obj.into()
+            })
+            .collect();
+
+        let result = js_sys::Object::new();
+        js_sys::Reflect::set(&result, &"html".into(), &html.into()).unwrap();
+        let mappings = js_sys::Array::from_iter(mappings);
+        js_sys::Reflect::set(&result, &"mappings".into(), &mappings).unwrap();
+        result.into()
+    }
+
+    /// Exports HTML for all dirty regions in the document
+    ///
+    /// This method is useful for incremental rendering. It returns an array
+    /// of objects containing the range and HTML for each dirty region.
+    ///
+    /// # Returns
+    /// A JavaScript array of objects with `start`, `end`, `html`, `id` and
+    /// `version` properties. `version` (a BigInt, like `getVersion`) is the
+    /// document version of the latest edit inside the region.
+    ///
+    /// # Example
+    /// ```javascript
+    /// const dirtyRegions = doc.getDirtyHTML();
+    /// dirtyRegions.forEach(region => {
+    ///   console.log(`Update range ${region.start}-${region.end}`);
+    ///   updateDOM(region.start, region.end, region.html);
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = getDirtyHTML)]
+    pub fn get_dirty_html(&self) -> JsValue {
+        let this = self.state();
+        let dirty_regions = this.inner.to_html_dirty_region_entries();
+        let current_version = this.inner.version();
+
+        let result: Vec<JsValue> = dirty_regions
+            .into_iter()
+            .map(|(region, html)| {
+                let obj = js_sys::Object::new();
+                let normalized = region.range.normalize();
+                let version = region.version.unwrap_or(current_version);
+                js_sys::Reflect::set(&obj, &"start".into(), &normalized.start_offset().into())
+                    .unwrap();
+                js_sys::Reflect::set(&obj, &"end".into(), &normalized.end_offset().into()).unwrap();
+                js_sys::Reflect::set(&obj, &"html".into(), &html.into()).unwrap();
+                js_sys::Reflect::set(&obj, &"id".into(), &region.id.into()).unwrap();
+                js_sys::Reflect::set(&obj, &"version".into(), &version.into()).unwrap();
+                obj.into()
+            })
+            .collect();
+
+        js_sys::Array::from_iter(result).into()
+    }
+
+    /// Clears the dirty regions last changed at or before `upToVersion`
+    ///
+    /// Unlike `clearDirtyFlags`, regions edited after the given version
+    /// stay dirty, so a renderer working a frame behind never drops
+    /// changes it has not painted yet.
+    ///
+    /// # Arguments
+    /// * `up_to_version` - The document version the renderer painted
+    ///
+    /// # Example
+    /// ```javascript
+    /// const regions = doc.getDirtyHTML();
+    /// const painted = doc.getVersion();
+    /// requestAnimationFrame(() => {
+    ///   renderDirtyRegions(regions);
+    ///   doc.acknowledgeDirty(painted);
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = acknowledgeDirty)]
+    pub fn acknowledge_dirty(&self, up_to_version: u64) {
+        let mut this = self.state_mut();
+        this.inner.acknowledge_dirty(up_to_version);
+    }
+
+    /// Clears all dirty region flags
+    ///
+    /// This should be called after rendering dirty regions to reset the
+    /// dirty tracking state.
+    ///
+    /// # Example
+    /// ```javascript
+    /// // Get and render dirty regions
+    /// const dirtyRegions = doc.getDirtyHTML();
+    /// renderDirtyRegions(dirtyRegions);
+    ///
+    /// // Clear dirty flags after rendering
+    /// doc.clearDirtyFlags();
+    /// ```
+    #[wasm_bindgen(js_name = clearDirtyFlags)]
+    pub fn clear_dirty_flags(&self) {
+        let mut this = self.state_mut();
+        this.inner.clear_dirty_flags();
+    }
+
+    /// Returns true if there are any dirty regions
+    ///
+    /// This can be used to check if rendering is needed.
+    ///
+    /// # Returns
+    /// true if there are dirty regions, false otherwise
+    ///
+    /// # Example
+    /// ```javascript
+    /// if (doc.hasDirtyRegions()) {
+    ///   const html = doc.toHTML();
+    ///   updateEditor(html);
+    ///   doc.clearDirtyFlags();
+    /// }
+    /// ```
+    #[wasm_bindgen(js_name = hasDirtyRegions)]
+    pub fn has_dirty_regions(&self) -> bool {
+        let this = self.state();
+        this.inner.has_dirty_regions()
+    }
+
+    /// Imports a document from HTML format
+    ///
+    /// Parses HTML and converts tags to internal format representation.
+    /// Dangerous content like javascript: URLs and event handlers are stripped.
+    ///
+    /// # Arguments
+    /// * `html` - An HTML string
+    ///
+    /// # Returns
+    /// A new WasmDocument instance
+    ///
+    /// # Errors
+    /// Returns a JsValue error if HTML parsing fails
+    #[wasm_bindgen(js_name = fromHTML)]
+    pub fn from_html(html: &str) -> Result<WasmDocument, JsValue> {
+        let doc = Document::from_html(html)
+            .map_err(|e| JsValue::from_str(&format!("HTML parsing failed: {}", e)))?;
+        Ok(WasmDocument::from_document(doc))
+    }
+
+    /// Imports a document from HTML with the given whitespace handling (static method)
+    ///
+    /// # Arguments
+    /// * `html` - An HTML string
+    /// * `preserve_whitespace` - Keep text whitespace as written instead of
+    ///   collapsing it the way browsers render it
+    ///
+    /// # Errors
+    /// Returns a JsValue error if HTML parsing fails
+    #[wasm_bindgen(js_name = fromHTMLWithOptions)]
+    pub fn from_html_with_options(
+        html: &str,
+        preserve_whitespace: bool,
+    ) -> Result<WasmDocument, JsValue> {
+        let whitespace = if preserve_whitespace {
+            WhitespaceHandling::Preserve
+        } else {
+            WhitespaceHandling::Collapse
+        };
+        let options = HtmlImportOptions::new().whitespace(whitespace);
+        let doc = Document::from_html_with_options(html, &HtmlSanitizer::new(), &options)
+            .map_err(|e| JsValue::from_str(&format!("HTML parsing failed: {}", e)))?;
+        Ok(WasmDocument::from_document(doc))
+    }
+
+    /// Imports a document from untrusted HTML within import limits (static method)
+    ///
+    /// Parsing stops with an error instead of running on when the input
+    /// produces too many tokens, nests elements too deeply or would make
+    /// too large a document.
+    ///
+    /// # Arguments
+    /// * `html` - An HTML string
+    /// * `limits` - Object with any of `maxTokens`, `maxDepth` and
+    ///   `maxOutputSize` (in characters); limits not given, or all of them
+    ///   if the object is missing, default to 1,000,000 tokens, a depth of
+    ///   256 and the maximum document size
+    ///
+    /// # Errors
+    /// Returns a JsValue error naming the limit if the input goes over one,
+    /// or if a limit is not a non-negative integer
+    ///
+    /// # Example
+    /// ```javascript
+    /// const doc = WasmDocument.fromHTMLSafe(pasted, { maxDepth: 64 });
+    /// ```
+    #[wasm_bindgen(js_name = fromHTMLSafe)]
+    pub fn from_html_safe(html: &str, limits: JsValue) -> Result<WasmDocument, JsValue> {
+        let options = HtmlImportOptions::new().limits(import_limits_from_js(&limits)?);
+        let doc = Document::from_html_with_options(html, &HtmlSanitizer::new(), &options)
+            .map_err(|e| JsValue::from_str(&format!("HTML parsing failed: {}", e)))?;
+        Ok(WasmDocument::from_document(doc))
+    }
+
+    /// Imports a document from HTML with a custom sanitizer policy (static method)
+    ///
+    /// The policy widens or narrows the default whitelists. It also becomes
+    /// the sanitizer for HTML pasted into the document.
+    ///
+    /// # Arguments
+    /// * `html` - An HTML string
+    /// * `policy_json` - JSON object with any of the string arrays
+    ///   `allowTags`, `removeTags`, `allowAttributes`, `removeAttributes`,
+    ///   `allowUrlSchemes`, `removeUrlSchemes`, `allowCssProperties` and
+    ///   `removeCssProperties`, such as
+    ///   `{"allowAttributes": ["target", "rel", "data-*"]}`. An attribute
+    ///   name ending in `*` matches every attribute with that prefix. Script
+    ///   tags, event handlers and `javascript:` URLs can never be allowed.
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the policy is invalid or HTML parsing fails
+    #[wasm_bindgen(js_name = fromHTMLWithPolicy)]
+    pub fn from_html_with_policy(html: &str, policy_json: &str) -> Result<WasmDocument, JsValue> {
+        let sanitizer = parse_sanitizer_policy(policy_json)?;
+        let mut doc = Document::from_html_with_sanitizer(html, &sanitizer)
+            .map_err(|e| JsValue::from_str(&format!("HTML parsing failed: {}", e)))?;
+        doc.set_html_sanitizer(Some(sanitizer));
+        Ok(WasmDocument::from_document(doc))
+    }
+
+    /// Sanitizes HTML without importing it (static method)
+    ///
+    /// Returns markup that keeps only what the policy allows. Unlike an
+    /// imported document, it keeps allowed attributes the editor has no
+    /// format for, such as `target`, `rel` or `data-*` attributes.
+    ///
+    /// # Arguments
+    /// * `html` - An HTML string
+    /// * `policy_json` - Sanitizer policy as accepted by `fromHTMLWithPolicy`;
+    ///   the default policy is used when omitted
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the policy is invalid
+    #[wasm_bindgen(js_name = sanitizeHTML)]
+    pub fn sanitize_html(html: &str, policy_json: Option<String>) -> Result<String, JsValue> {
+        let sanitizer = match policy_json {
+            Some(policy_json) => parse_sanitizer_policy(&policy_json)?,
+            None => HtmlSanitizer::new(),
+        };
+        Ok(sanitizer.sanitize(html))
+    }
+
+    /// Imports a document from Markdown format (static method)
+    ///
+    /// # Arguments
+    /// * `markdown` - A Markdown string
+    ///
+    /// # Returns
+    /// A new WasmDocument instance
+    ///
+    /// # Errors
+    /// Returns a JsValue error if parsing fails
+    #[wasm_bindgen(js_name = fromMarkdown)]
+    pub fn from_markdown(markdown: &str) -> Result<WasmDocument, JsValue> {
+        let doc = Document::from_markdown(markdown)
+            .map_err(|e| JsValue::from_str(&format!("Markdown parsing failed: {}", e)))?;
+        Ok(WasmDocument::from_document(doc))
+    }
+
+    /// Imports plain text, inferring headings, lists and quotes (static method)
+    ///
+    /// Lines starting with `- `, `* ` or `1. ` become list items, lines
+    /// starting with `> ` become quotes, and short lines set off by blank
+    /// lines become headings. Other lines stay paragraphs.
+    ///
+    /// # Arguments
+    /// * `text` - Plain text, such as pasted notes
+    ///
+    /// # Returns
+    /// A new WasmDocument instance
+    #[wasm_bindgen(js_name = fromPlainTextSmart)]
+    pub fn from_plain_text_smart(text: &str) -> WasmDocument {
+        WasmDocument::from_document(Document::from_plain_text_smart(text))
+    }
+
+    /// Imports a document from Markdown and records which source line each
+    /// document line came from (static method)
+    ///
+    /// # Arguments
+    /// * `markdown` - A Markdown string
+    ///
+    /// # Returns
+    /// An object with `document`, the new WasmDocument, and `lines` in the
+    /// format returned by `toMarkdownWithSourceMap`
+    ///
+    /// # Errors
+    /// Returns a JsValue error if parsing fails
+    #[wasm_bindgen(js_name = fromMarkdownWithSourceMap)]
+    pub fn from_markdown_with_source_map(markdown: &str) -> Result<JsValue, JsValue> {
+        let (doc, map) = Document::from_markdown_with_source_map(markdown, &MarkdownOptions::new())
+            .map_err(|e| JsValue::from_str(&format!("Markdown parsing failed: {}", e)))?;
+        let document = WasmDocument::from_document(doc);
+
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"document".into(), &document.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"lines".into(), &markdown_source_map_to_js(&map)).unwrap();
+        Ok(obj.into())
+    }
+
+    /// Imports a document from Quill Delta JSON (static method)
+    ///
+    /// # Arguments
+    /// * `json` - A delta as `{"ops": [...]}` or a bare array of insert ops
+    ///
+    /// # Returns
+    /// A new WasmDocument instance
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the delta is malformed or contains
+    /// retain/delete operations
+    #[wasm_bindgen(js_name = fromDelta)]
+    pub fn from_delta(json: &str) -> Result<WasmDocument, JsValue> {
+        let doc = Document::from_delta(json)
+            .map_err(|e| JsValue::from_str(&format!("Delta parsing failed: {}", e)))?;
+        Ok(WasmDocument::from_document(doc))
+    }
+
+    /// Imports a document from RTF (static method)
+    ///
+    /// # Arguments
+    /// * `rtf` - An RTF string, e.g. from a word processor or the clipboard
+    ///
+    /// # Returns
+    /// A new WasmDocument instance
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the input is not RTF or its groups are unbalanced
+    #[wasm_bindgen(js_name = fromRTF)]
+    pub fn from_rtf(rtf: &str) -> Result<WasmDocument, JsValue> {
+        let doc = Document::from_rtf(rtf)
+            .map_err(|e| JsValue::from_str(&format!("RTF parsing failed: {}", e)))?;
+        Ok(WasmDocument::from_document(doc))
+    }
+
+    /// Imports a document from Markdown with the given syntax extensions (static method)
+    ///
+    /// # Arguments
+    /// * `markdown` - A Markdown string
+    /// * `strikethrough` - Whether to parse `~~text~~` strikethrough
+    /// * `highlight` - Whether to parse `==text==` highlight
+    ///
+    /// # Errors
+    /// Returns a JsValue error if parsing fails
+    #[wasm_bindgen(js_name = fromMarkdownWithOptions)]
+    pub fn from_markdown_with_options(
+        markdown: &str,
+        strikethrough: bool,
+        highlight: bool,
+    ) -> Result<WasmDocument, JsValue> {
+        let options = MarkdownOptions::new()
+            .strikethrough(strikethrough)
+            .highlight(highlight);
+        let doc = Document::from_markdown_with_options(markdown, &options)
+            .map_err(|e| JsValue::from_str(&format!("Markdown parsing failed: {}", e)))?;
+        Ok(WasmDocument::from_document(doc))
+    }
+
+    /// Imports a document from untrusted Markdown within import limits (static method)
+    ///
+    /// Works like `fromHTMLSafe`: lines and inline spans count as tokens,
+    /// and nested emphasis and links count toward the depth.
+    ///
+    /// # Arguments
+    /// * `markdown` - A Markdown string
+    /// * `limits` - Object with any of `maxTokens`, `maxDepth` and
+    ///   `maxOutputSize`, defaulting as in `fromHTMLSafe`
+    ///
+    /// # Errors
+    /// Returns a JsValue error naming the limit if the input goes over one,
+    /// or if a limit is not a non-negative integer
+    #[wasm_bindgen(js_name = fromMarkdownSafe)]
+    pub fn from_markdown_safe(markdown: &str, limits: JsValue) -> Result<WasmDocument, JsValue> {
+        let options = MarkdownOptions::new().limits(import_limits_from_js(&limits)?);
+        let doc = Document::from_markdown_with_options(markdown, &options)
+            .map_err(|e| JsValue::from_str(&format!("Markdown parsing failed: {}", e)))?;
+        Ok(WasmDocument::from_document(doc))
+    }
+
+    /// Imports markdown content into the current document (instance method)
+    ///
+    /// Replaces the current document content with the parsed markdown.
+    /// This is useful for loading markdown into an existing document instance.
+    ///
+    /// # Arguments
+    /// * `markdown` - A Markdown string
+    ///
+    /// # Errors
+    /// Returns a JsValue error if parsing fails
+    #[wasm_bindgen(js_name = loadFromMarkdown)]
+    pub fn load_from_markdown(&self, markdown: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let doc = Document::from_markdown(markdown)
+            .map_err(|e| JsValue::from_str(&format!("Markdown parsing failed: {}", e)))?;
+        this.inner
+            .load_content(doc)
+            .map_err(|e| JsValue::from_str(&format!("Load failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Starts loading the document's text in chunks
+    ///
+    /// Chunks passed to `appendChunk` are collected without changing the
+    /// document; `finishStreamingLoad` then replaces the content with them
+    /// in one step. This avoids passing a multi-megabyte string to the
+    /// editor at once. Calling this again discards the chunks collected so
+    /// far.
+    ///
+    /// # Example
+    /// ```javascript
+    /// const response = await fetch('/large.txt');
+    /// const reader = response.body.pipeThrough(new TextDecoderStream()).getReader();
+    /// doc.beginStreamingLoad();
+    /// for (let r = await reader.read(); !r.done; r = await reader.read()) {
+    ///     doc.appendChunk(r.value);
+    /// }
+    /// doc.finishStreamingLoad();
+    /// ```
+    #[wasm_bindgen(js_name = beginStreamingLoad)]
+    pub fn begin_streaming_load(&self) {
+        let mut this = self.state_mut();
+        this.streaming = Some(StreamingLoad::new(
+            this.inner.storage_backend(),
+            this.inner.get_max_length(),
+        ));
+    }
+
+    /// Appends a chunk of text to the load started by `beginStreamingLoad`
+    ///
+    /// # Arguments
+    /// * `text` - The next chunk of plain text
+    ///
+    /// # Errors
+    /// Returns a JsValue error if no load is in progress, or if the text
+    /// would exceed the maximum length, which also abandons the load
+    #[wasm_bindgen(js_name = appendChunk)]
+    pub fn append_chunk(&self, text: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let load = this
+            .streaming
+            .as_mut()
+            .ok_or_else(|| JsValue::from_str("No streaming load in progress"))?;
+        if let Err(e) = load.append_chunk(text) {
+            this.streaming = None;
+            return Err(JsValue::from_str(&format!("Load failed: {}", e)));
+        }
+        Ok(())
+    }
+
+    /// Replaces the document's content with the text collected since
+    /// `beginStreamingLoad`
+    ///
+    /// Formatting, images and undo history are cleared, and one change
+    /// event is sent.
+    ///
+    /// # Errors
+    /// Returns a JsValue error if no load is in progress
+    #[wasm_bindgen(js_name = finishStreamingLoad)]
+    pub fn finish_streaming_load(&self) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let load = this
+            .streaming
+            .take()
+            .ok_or_else(|| JsValue::from_str("No streaming load in progress"))?;
+        this.inner
+            .load_content(load.finish())
+            .map_err(|e| JsValue::from_str(&format!("Load failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Discards the load started by `beginStreamingLoad`, leaving the
+    /// document unchanged
+    ///
+    /// # Returns
+    /// false if no load was in progress
+    #[wasm_bindgen(js_name = abortStreamingLoad)]
+    pub fn abort_streaming_load(&self) -> bool {
+        let mut this = self.state_mut();
+        this.streaming.take().is_some()
+    }
+
+    /// Exports the document to plain text format (strips all formatting)
+    ///
+    /// # Returns
+    /// A plain text string representation of the document
+    #[wasm_bindgen(js_name = toPlainText)]
+    pub fn to_plain_text(&self) -> String {
+        let this = self.state();
+        // Simply return the content without formatting
+        this.inner.get_content()
+    }
+
+    /// Enables collaborative editing for this document
+    ///
+    /// # Arguments
+    /// * `client_id` - An id unique among the documents exchanging updates
+    ///
+    /// A document joining an existing session should be empty and apply an
+    /// update from a peer. Has no effect if already enabled.
+    #[wasm_bindgen(js_name = enableCollaboration)]
+    pub fn enable_collaboration(&self, client_id: u32) {
+        let mut this = self.state_mut();
+        this.inner.enable_collaboration(client_id);
+    }
+
+    /// Returns true if collaborative editing is enabled
+    #[wasm_bindgen(js_name = isCollaborative)]
+    pub fn is_collaborative(&self) -> bool {
+        let this = self.state();
+        this.inner.is_collaborative()
+    }
+
+    /// Encodes the full collaborative state as a binary update
+    ///
+    /// # Returns
+    /// A Uint8Array that peers pass to `applyUpdate`
+    ///
+    /// # Errors
+    /// Returns a JsValue error if collaboration is not enabled
+    #[wasm_bindgen(js_name = encodeStateAsUpdate)]
+    pub fn encode_state_as_update(&self) -> Result<Vec<u8>, JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .encode_state_as_update()
+            .map_err(|e| JsValue::from_str(&format!("Encode update failed: {}", e)))
+    }
+
+    /// Merges a binary update from a peer into this document
+    ///
+    /// Local undo steps are moved past the remote edits; steps whose text a
+    /// peer changed are dropped along with older ones, as are redo steps.
+    ///
+    /// # Arguments
+    /// * `update` - A Uint8Array produced by a peer's `encodeStateAsUpdate`
+    ///
+    /// # Returns
+    /// True if the document changed
+    ///
+    /// # Errors
+    /// Returns a JsValue error if collaboration is not enabled or the update is malformed
+    #[wasm_bindgen(js_name = applyUpdate)]
+    pub fn apply_update(&self, update: &[u8]) -> Result<bool, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let changed = this
+            .inner
+            .apply_update(update)
+            .map_err(|e| JsValue::from_str(&format!("Apply update failed: {}", e)))?;
+        if changed {
+            this.notify_change();
+        }
+        Ok(changed)
+    }
+
+    /// Starts recording local text edits as pending operations for a server
+    ///
+    /// Has no effect if already enabled.
+    #[wasm_bindgen(js_name = enableOperationalTransform)]
+    pub fn enable_operational_transform(&self) {
+        let mut this = self.state_mut();
+        this.inner.enable_operational_transform();
+    }
+
+    /// Returns the local operations not yet acknowledged by the server
+    ///
+    /// # Returns
+    /// A JSON array of `{ type: "insert", position, text }` and
+    /// `{ type: "delete", start, end }` objects, in the order they were made
+    ///
+    /// The returned operations are treated as sent; typing that follows is
+    /// recorded as new operations rather than extending them.
+    ///
+    /// # Errors
+    /// Returns a JsValue error if operational transform is not enabled
+    #[wasm_bindgen(js_name = getPendingOps)]
+    pub fn get_pending_ops(&self) -> Result<String, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let ops = this
+            .inner
+            .pending_ops()
+            .map_err(|e| JsValue::from_str(&format!("Get pending ops failed: {}", e)))?;
+        serde_json::to_string(ops)
+            .map_err(|e| JsValue::from_str(&format!("Get pending ops failed: {}", e)))
+    }
+
+    /// Drops pending operations once the server has acknowledged them
+    ///
+    /// # Arguments
+    /// * `count` - The number of operations acknowledged, oldest first, as
+    ///   `getPendingOps` returned them; one that a remote operation has since
+    ///   split still counts as one
+    ///
+    /// # Errors
+    /// Returns a JsValue error if operational transform is not enabled
+    #[wasm_bindgen(js_name = acknowledgePendingOps)]
+    pub fn acknowledge_pending_ops(&self, count: usize) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .acknowledge_ops(count)
+            .map_err(|e| JsValue::from_str(&format!("Acknowledge ops failed: {}", e)))
+    }
+
+    /// Transforms an operation from the server against pending local
+    /// operations and applies it
+    ///
+    /// Pending operations and local undo steps are rebased on top of the
+    /// remote one. Undo steps whose text it changed are dropped along with
+    /// older ones, as are redo steps.
+    ///
+    /// # Arguments
+    /// * `json` - A single operation in the format returned by `getPendingOps`
+    ///
+    /// # Returns
+    /// A JSON array of the operations applied to this document
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the JSON is invalid, the operation does not
+    /// fit the document, or operational transform is not enabled
+    ///
+    /// # Example
+    /// ```javascript
+    /// socket.onmessage = (event) => doc.transformRemoteOp(event.data);
+    /// ```
+    #[wasm_bindgen(js_name = transformRemoteOp)]
+    pub fn transform_remote_op(&self, json: &str) -> Result<String, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let op: Operation = serde_json::from_str(json)
+            .map_err(|e| JsValue::from_str(&format!("Transform remote op failed: {}", e)))?;
+        let applied = this
+            .inner
+            .transform_remote_op(op)
+            .map_err(|e| JsValue::from_str(&format!("Transform remote op failed: {}", e)))?;
+
+        if !applied.is_empty() {
+            this.notify_change();
+        }
+        serde_json::to_string(&applied)
+            .map_err(|e| JsValue::from_str(&format!("Transform remote op failed: {}", e)))
+    }
+
+    /// Applies an operation from an operation log as a local edit
+    ///
+    /// Unlike `transformRemoteOp`, the operation is applied as is, recorded
+    /// in the undo history and reported to change listeners.
+    ///
+    /// # Arguments
+    /// * `json` - A single operation in the format returned by `getPendingOps`
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the JSON is invalid, the operation does not
+    /// fit the document, or the document is read-only
+    #[wasm_bindgen(js_name = applyOperation)]
+    pub fn apply_operation(&self, json: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let op: Operation = serde_json::from_str(json)
+            .map_err(|e| JsValue::from_str(&format!("Apply operation failed: {}", e)))?;
+        this.inner
+            .apply_operation(&op)
+            .map_err(|e| JsValue::from_str(&format!("Apply operation failed: {}", e)))?;
+        this.notify_change();
+        Ok(())
+    }
+
+    /// Re-applies an operation log to a document step by step (static method)
+    ///
+    /// Each step is applied with `applyOperation`, so it can be undone and
+    /// reaches change listeners like an edit made by hand. Without a speed
+    /// the whole log is applied before this returns, which suits
+    /// reproducing a bug. With a speed the first step is applied at once
+    /// and the rest on timers, for "watch this being written" demos. This
+    /// is a static method because the steps run after it returns.
+    ///
+    /// # Arguments
+    /// * `document` - The document to edit
+    /// * `log` - A JSON array of operations as returned by `getPendingOps`
+    /// * `options` - Optional object with:
+    ///   - `speed`: steps per second; when omitted every step is applied
+    ///     immediately
+    ///   - `onStep`: function called after each step with
+    ///     `{ index, total, operation }`; returning `false` stops the replay.
+    ///     A timed step that fails stops the replay and is reported as
+    ///     `{ index, total, error }`
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the log or options are invalid, or if a
+    /// step applied before this returns fails or `onStep` throws
+    ///
+    /// # Example
+    /// ```javascript
+    /// const log = recorder.getPendingOps();
+    /// WasmDocument.replayOperations(viewer, log, {
+    ///     speed: 20,
+    ///     onStep: ({ index, total }) => progress.update(index + 1, total),
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = replayOperations)]
+    pub fn replay_operations(
+        #[wasm_bindgen(unchecked_param_type = "WasmDocument")] document: JsValue,
+        log: &str,
+        options: JsValue,
+    ) -> Result<(), JsValue> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            JsValue::from_str(&format!("Replay operations failed: {}", e))
+        };
+        let ops: Vec<Operation> = serde_json::from_str(log).map_err(|e| invalid(&e))?;
+        let steps = ops
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| invalid(&e))?;
+
+        let mut delay = None;
+        let mut on_step = None;
+        if options.is_object() {
+            let get = |key: &str| js_sys::Reflect::get(&options, &JsValue::from_str(key));
+            let speed = get("speed")?;
+            if !speed.is_undefined() && !speed.is_null() {
+                let speed = speed
+                    .as_f64()
+                    .filter(|speed| speed.is_finite() && *speed > 0.0)
+                    .ok_or_else(|| invalid(&"'speed' must be a positive number"))?;
+                delay = Some((1000.0 / speed).round().min(f64::from(i32::MAX)) as i32);
+            }
+            let callback = get("onStep")?;
+            if !callback.is_undefined() && !callback.is_null() {
+                on_step = Some(
+                    callback
+                        .dyn_into::<js_sys::Function>()
+                        .map_err(|_| invalid(&"'onStep' must be a function"))?,
+                );
+            }
+        }
+
+        replay_steps(document, steps, 0, delay, on_step)
+    }
+
+    /// Finds all occurrences of the search pattern in the document
+    ///
+    /// # Arguments
+    /// * `pattern` - The text pattern to search for
+    /// * `case_sensitive` - Whether the search should be case-sensitive
+    /// * `use_regex` - Whether to interpret the pattern as a regular expression
+    /// * `ignore_accents` - Whether "resume" also matches "résumé" (defaults to false)
+    ///
+    /// # Returns
+    /// A JsValue array of match objects with start and end properties
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the regex pattern is invalid
+    #[wasm_bindgen(js_name = find)]
+    pub fn find(
+        &self,
+        pattern: &str,
+        case_sensitive: bool,
+        use_regex: bool,
+        ignore_accents: Option<bool>,
+    ) -> Result<JsValue, JsValue> {
+        let this = self.try_state()?;
+        let query = SearchQuery::new(pattern.to_string())
+            .case_sensitive(case_sensitive)
+            .use_regex(use_regex)
+            .ignore_accents(ignore_accents.unwrap_or(false));
+
+        let result = this
+            .inner
+            .find(&query)
+            .map_err(|e| JsValue::from_str(&format!("Search failed: {}", e)))?;
+
+        Ok(matches_to_js(&result.matches))
+    }
+
+    /// Finds the occurrences of the search pattern inside the selection
+    ///
+    /// Only the selected text is searched, so matches never extend past the
+    /// selection and regex anchors such as `^` match at its start.
+    ///
+    /// # Arguments
+    /// * `pattern` - The text pattern to search for
+    /// * `case_sensitive` - Whether the search should be case-sensitive
+    /// * `use_regex` - Whether to interpret the pattern as a regular expression
+    /// * `ignore_accents` - Whether "resume" also matches "résumé" (defaults to false)
+    ///
+    /// # Returns
+    /// A JsValue array of match objects with start and end properties,
+    /// empty if the selection is collapsed
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the regex pattern is invalid
+    #[wasm_bindgen(js_name = findInSelection)]
+    pub fn find_in_selection(
+        &self,
+        pattern: &str,
+        case_sensitive: bool,
+        use_regex: bool,
+        ignore_accents: Option<bool>,
+    ) -> Result<JsValue, JsValue> {
+        let this = self.try_state()?;
+        let query = SearchQuery::new(pattern.to_string())
+            .case_sensitive(case_sensitive)
+            .use_regex(use_regex)
+            .ignore_accents(ignore_accents.unwrap_or(false));
+
+        let result = this
+            .inner
+            .find_in_range(&query, this.inner.get_selection().range())
+            .map_err(|e| JsValue::from_str(&format!("Search failed: {}", e)))?;
+
+        Ok(matches_to_js(&result.matches))
+    }
+
+    /// Selects the next match of the search pattern after the current
+    /// selection, wrapping around to the start of the document
+    ///
+    /// The move is recorded in the navigation history when it is enabled,
+    /// and the pattern and options become the document's search state.
+    ///
+    /// # Arguments
+    /// * `pattern` - The text pattern to search for
+    /// * `case_sensitive` - Whether the search should be case-sensitive
+    /// * `use_regex` - Whether to interpret the pattern as a regular expression
+    /// * `ignore_accents` - Whether "resume" also matches "résumé" (defaults to false)
+    ///
+    /// # Returns
+    /// A JsValue object with start and end properties, or null if nothing matches
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the regex pattern is invalid
+    #[wasm_bindgen(js_name = findNext)]
+    pub fn find_next(
+        &self,
+        pattern: &str,
+        case_sensitive: bool,
+        use_regex: bool,
+        ignore_accents: Option<bool>,
+    ) -> Result<JsValue, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let query = SearchQuery::new(pattern.to_string())
+            .case_sensitive(case_sensitive)
+            .use_regex(use_regex)
+            .ignore_accents(ignore_accents.unwrap_or(false));
+
+        let found = this.inner.find_next(&query);
+        this.found_match_to_js(found)
+    }
+
+    /// Selects the last match of the search pattern before the current
+    /// selection, wrapping around to the end of the document
+    ///
+    /// Behaves like `findNext` otherwise.
+    ///
+    /// # Arguments
+    /// * `pattern` - The text pattern to search for
+    /// * `case_sensitive` - Whether the search should be case-sensitive
+    /// * `use_regex` - Whether to interpret the pattern as a regular expression
+    /// * `ignore_accents` - Whether "resume" also matches "résumé" (defaults to false)
+    ///
+    /// # Returns
+    /// A JsValue object with start and end properties, or null if nothing matches
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the regex pattern is invalid
+    #[wasm_bindgen(js_name = findPrevious)]
+    pub fn find_previous(
+        &self,
+        pattern: &str,
+        case_sensitive: bool,
+        use_regex: bool,
+        ignore_accents: Option<bool>,
+    ) -> Result<JsValue, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let query = SearchQuery::new(pattern.to_string())
+            .case_sensitive(case_sensitive)
+            .use_regex(use_regex)
+            .ignore_accents(ignore_accents.unwrap_or(false));
+
+        let found = this.inner.find_previous(&query);
+        this.found_match_to_js(found)
+    }
+
+    /// Finds the match of the search state's pattern nearest to an offset,
+    /// without changing the selection
+    ///
+    /// Lets a find bar step through matches without fetching them all:
+    /// only the matches up to the one returned are scanned. Set the pattern
+    /// and options, including `wholeWord`, with `setSearchState`.
+    ///
+    /// # Arguments
+    /// * `from_offset` - Where to search from: the end of the current match
+    ///   to step forward, or its start to step back
+    /// * `forward` - Whether to find the first match starting at or after
+    ///   `from_offset` rather than the last one ending at or before it
+    /// * `wrap` - Whether to continue from the other end of the document
+    ///   when nothing is found
+    ///
+    /// # Returns
+    /// A JsValue object with start and end properties, or null if nothing matches
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the regex pattern is invalid
+    #[wasm_bindgen(js_name = findNextFrom)]
+    pub fn find_next_from(
+        &self,
+        from_offset: usize,
+        forward: bool,
+        wrap: bool,
+    ) -> Result<JsValue, JsValue> {
+        let this = self.try_state()?;
+        let query = &this.inner.search_state().query;
+        let found = this
+            .inner
+            .find_from(query, Position::new(from_offset), forward, wrap)
+            .map_err(|e| JsValue::from_str(&format!("Search failed: {}", e)))?;
+        Ok(found.map_or(JsValue::NULL, |range| {
+            let obj = js_sys::Object::new();
+            js_sys::Reflect::set(&obj, &"start".into(), &range.start_offset().into()).unwrap();
+            js_sys::Reflect::set(&obj, &"end".into(), &range.end_offset().into()).unwrap();
+            obj.into()
+        }))
+    }
+
+    /// Starts a search that returns its matches in batches
+    ///
+    /// Finding every match of a document of several megabytes in one call
+    /// can block the UI. After this, call `continueSearch` from idle
+    /// callbacks or animation frames until it reports `done`. Starting a
+    /// new search discards the previous one.
+    ///
+    /// # Arguments
+    /// * `pattern` - The text pattern to search for
+    /// * `case_sensitive` - Whether the search should be case-sensitive
+    /// * `use_regex` - Whether to interpret the pattern as a regular expression
+    /// * `ignore_accents` - Whether "resume" also matches "résumé" (defaults to false)
+    /// * `whole_word` - Whether only whole words match (defaults to false)
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the regex pattern is invalid
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.startSearch('needle', false, false);
+    /// function step() {
+    ///   const { matches, done } = doc.continueSearch(null, 8);
+    ///   highlight(matches);
+    ///   if (!done) requestIdleCallback(step);
+    /// }
+    /// requestIdleCallback(step);
+    /// ```
+    #[wasm_bindgen(js_name = startSearch)]
+    pub fn start_search(
+        &self,
+        pattern: &str,
+        case_sensitive: bool,
+        use_regex: bool,
+        ignore_accents: Option<bool>,
+        whole_word: Option<bool>,
+    ) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let query = SearchQuery::new(pattern.to_string())
+            .case_sensitive(case_sensitive)
+            .use_regex(use_regex)
+            .ignore_accents(ignore_accents.unwrap_or(false))
+            .whole_word(whole_word.unwrap_or(false));
+        this.search = None;
+        let search = this
+            .inner
+            .start_search(&query)
+            .map_err(|e| JsValue::from_str(&format!("Search failed: {}", e)))?;
+        this.search = Some(search);
+        Ok(())
+    }
+
+    /// Finds the next batch of matches of the search started by
+    /// `startSearch`
+    ///
+    /// The search ends once it is done, after which `startSearch` must be
+    /// called again.
+    ///
+    /// # Arguments
+    /// * `max_matches` - Most matches to return, or null for no limit
+    /// * `budget_ms` - Time after which to stop, checked after each match,
+    ///   or null for no limit
+    ///
+    /// # Returns
+    /// A JsValue object with `matches` (an array of objects with start and
+    /// end properties), `done` and `count`, the number of matches found
+    /// so far
+    ///
+    /// # Errors
+    /// Returns a JsValue error if no search is in progress, or if the
+    /// document changed since the search started, which also ends it
+    #[wasm_bindgen(js_name = continueSearch)]
+    pub fn continue_search(
+        &self,
+        max_matches: Option<usize>,
+        budget_ms: Option<f64>,
+    ) -> Result<JsValue, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let this = &mut *this;
+        let search = this
+            .search
+            .as_mut()
+            .ok_or_else(|| JsValue::from_str("No search in progress"))?;
+        if !search.is_current(&this.inner) {
+            this.search = None;
+            return Err(JsValue::from_str(
+                "Search failed: the document changed since startSearch",
+            ));
+        }
+
+        let budget = budget_ms
+            .filter(|ms| ms.is_finite())
+            .map(|ms| std::time::Duration::try_from_secs_f64(ms / 1000.0).unwrap_or_default());
+        let matches = search.next_batch(max_matches, budget);
+        let done = search.is_done();
+        let count = search.match_count();
+        if done {
+            this.search = None;
+        }
+
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"matches".into(), &matches_to_js(&matches)).unwrap();
+        js_sys::Reflect::set(&obj, &"done".into(), &done.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"count".into(), &count.into()).unwrap();
+        Ok(obj.into())
+    }
+
+    /// Ends the search started by `startSearch`
+    ///
+    /// # Returns
+    /// true if a search was in progress
+    #[wasm_bindgen(js_name = cancelSearch)]
+    pub fn cancel_search(&self) -> bool {
+        let mut this = self.state_mut();
+        this.search.take().is_some()
+    }
+
+    /// Selects the next match of the pattern in the search state, like
+    /// `findNext` with the last pattern and options
+    ///
+    /// # Returns
+    /// A JsValue object with start and end properties, or null if nothing matches
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the regex pattern is invalid
+    #[wasm_bindgen(js_name = searchNext)]
+    pub fn search_next(&self) -> Result<JsValue, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let found = this.inner.search_next();
+        this.found_match_to_js(found)
+    }
+
+    /// Selects the previous match of the pattern in the search state, like
+    /// `findPrevious` with the last pattern and options
+    ///
+    /// # Returns
+    /// A JsValue object with start and end properties, or null if nothing matches
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the regex pattern is invalid
+    #[wasm_bindgen(js_name = searchPrevious)]
+    pub fn search_previous(&self) -> Result<JsValue, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let found = this.inner.search_previous();
+        this.found_match_to_js(found)
+    }
+
+    /// Gets the search state kept by the document
+    ///
+    /// Returned shape:
+    /// { pattern: string, caseSensitive: boolean, useRegex: boolean,
+    ///   ignoreAccents: boolean, wholeWord: boolean,
+    ///   currentMatch: number | null }
+    ///
+    /// `currentMatch` is the index of the match last selected by `findNext`,
+    /// `findPrevious`, `searchNext` or `searchPrevious`.
+    #[wasm_bindgen(js_name = getSearchState)]
+    pub fn get_search_state(&self) -> JsValue {
+        let this = self.state();
+        let state = this.inner.search_state();
+        let current_match = state
+            .current_match
+            .map_or(JsValue::NULL, |index| index.into());
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(
+            &obj,
+            &"pattern".into(),
+            &state.query.pattern.as_str().into(),
+        )
+        .unwrap();
+        js_sys::Reflect::set(
+            &obj,
+            &"caseSensitive".into(),
+            &state.query.case_sensitive.into(),
+        )
+        .unwrap();
+        js_sys::Reflect::set(&obj, &"useRegex".into(), &state.query.use_regex.into()).unwrap();
+        js_sys::Reflect::set(
+            &obj,
+            &"ignoreAccents".into(),
+            &state.query.ignore_accents.into(),
+        )
+        .unwrap();
+        js_sys::Reflect::set(&obj, &"wholeWord".into(), &state.query.whole_word.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"currentMatch".into(), &current_match).unwrap();
+        obj.into()
+    }
+
+    /// Updates the search state, such as to restore a find bar when the
+    /// document regains focus or to toggle case sensitivity
+    ///
+    /// Takes an object shaped like the one returned by `getSearchState`.
+    /// Missing properties keep their current value, except that
+    /// `currentMatch` is cleared when the pattern or an option changes.
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the state is not an object or a property
+    /// has the wrong type
+    #[wasm_bindgen(js_name = setSearchState)]
+    pub fn set_search_state(&self, state: JsValue) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        if !state.is_object() {
+            return Err(JsValue::from_str(
+                "Set search state failed: state must be an object",
+            ));
+        }
+        let get = |key: &str| js_sys::Reflect::get(&state, &JsValue::from_str(key));
+
+        let current = this.inner.search_state();
+        let mut query = current.query.clone();
+        if let Some(pattern) = optional_string(&get("pattern")?, "pattern")? {
+            query.pattern = pattern;
+        }
+        if let Some(case_sensitive) = optional_bool(&get("caseSensitive")?, "caseSensitive")? {
+            query.case_sensitive = case_sensitive;
+        }
+        if let Some(use_regex) = optional_bool(&get("useRegex")?, "useRegex")? {
+            query.use_regex = use_regex;
+        }
+        if let Some(ignore_accents) = optional_bool(&get("ignoreAccents")?, "ignoreAccents")? {
+            query.ignore_accents = ignore_accents;
+        }
+        if let Some(whole_word) = optional_bool(&get("wholeWord")?, "wholeWord")? {
+            query.whole_word = whole_word;
+        }
+        let current_match = get("currentMatch")?;
+        let current_match = if current_match.is_undefined() {
+            current.current_match.filter(|_| query == current.query)
+        } else {
+            optional_count(&current_match, "currentMatch")?
+        };
+
+        this.inner.set_search_state(SearchState {
+            query,
+            current_match,
+        });
+        Ok(())
+    }
+
+    /// Starts recording significant selection jumps (`jumpTo`, `findNext`)
+    /// in a navigation history separate from undo/redo
+    ///
+    /// Has no effect if already enabled.
+    ///
+    /// # Arguments
+    /// * `limit` - Maximum number of jumps to keep (defaults to 50)
+    #[wasm_bindgen(js_name = enableNavigationHistory)]
+    pub fn enable_navigation_history(&self, limit: Option<usize>) {
+        let mut this = self.state_mut();
+        this.inner
+            .enable_navigation_history(limit.unwrap_or(DEFAULT_NAVIGATION_LIMIT));
+    }
+
+    /// Stops recording selection jumps and forgets the recorded ones
+    #[wasm_bindgen(js_name = disableNavigationHistory)]
+    pub fn disable_navigation_history(&self) {
+        let mut this = self.state_mut();
+        this.inner.disable_navigation_history();
+    }
+
+    /// Moves the selection as a significant jump, such as going to a heading
+    ///
+    /// Behaves like `setSelection`, and also records the previous selection
+    /// when navigation history is enabled.
+    ///
+    /// # Arguments
+    /// * `anchor` - The anchor position (where selection started)
+    /// * `focus` - The focus position (where selection ends)
+    #[wasm_bindgen(js_name = jumpTo)]
+    pub fn jump_to(&self, anchor: usize, focus: usize) {
+        let mut this = self.state_mut();
+        let selection = Selection::new(Position::new(anchor), Position::new(focus));
+        this.inner.jump_to(selection);
+        this.callbacks.trigger_selection_callbacks();
+    }
+
+    /// Moves the selection back to where it was before the last jump
+    ///
+    /// # Returns
+    /// false if navigation history is disabled or there is nothing to go back to
+    #[wasm_bindgen(js_name = navigateBack)]
+    pub fn navigate_back(&self) -> bool {
+        let mut this = self.state_mut();
+        let moved = this.inner.navigate_back();
+        if moved {
+            this.callbacks.trigger_selection_callbacks();
+        }
+        moved
+    }
+
+    /// Re-does the jump undone by the last `navigateBack`
+    ///
+    /// # Returns
+    /// false if navigation history is disabled or there is nothing to go forward to
+    #[wasm_bindgen(js_name = navigateForward)]
+    pub fn navigate_forward(&self) -> bool {
+        let mut this = self.state_mut();
+        let moved = this.inner.navigate_forward();
+        if moved {
+            this.callbacks.trigger_selection_callbacks();
+        }
+        moved
+    }
+
+    /// Checks if `navigateBack` would move the selection
+    #[wasm_bindgen(js_name = canNavigateBack)]
+    pub fn can_navigate_back(&self) -> bool {
+        let this = self.state();
+        this.inner
+            .navigation_history()
+            .is_some_and(|n| n.can_go_back())
+    }
+
+    /// Checks if `navigateForward` would move the selection
+    #[wasm_bindgen(js_name = canNavigateForward)]
+    pub fn can_navigate_forward(&self) -> bool {
+        let this = self.state();
+        this.inner
+            .navigation_history()
+            .is_some_and(|n| n.can_go_forward())
+    }
+
+    /// Finds and replaces all occurrences of the search pattern with replacement text
+    ///
+    /// # Arguments
+    /// * `pattern` - The text pattern to search for
+    /// * `replacement` - The text to replace matches with
+    /// * `case_sensitive` - Whether the search should be case-sensitive
+    /// * `use_regex` - Whether to interpret the pattern as a regular expression
+    /// * `ignore_accents` - Whether "resume" also matches "résumé" (defaults to false)
+    ///
+    /// # Returns
+    /// The number of replacements made
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the regex pattern is invalid or replacement fails
+    #[wasm_bindgen(js_name = findAndReplace)]
+    pub fn find_and_replace(
+        &self,
+        pattern: &str,
+        replacement: &str,
+        case_sensitive: bool,
+        use_regex: bool,
+        ignore_accents: Option<bool>,
+    ) -> Result<usize, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let query = SearchQuery::new(pattern.to_string())
+            .case_sensitive(case_sensitive)
+            .use_regex(use_regex)
+            .ignore_accents(ignore_accents.unwrap_or(false));
+
+        let count = this
+            .inner
+            .find_and_replace(&query, replacement)
+            .map_err(|e| JsValue::from_str(&format!("Find and replace failed: {}", e)))?;
+
+        if count > 0 {
+            this.notify_change();
+        }
+
+        Ok(count)
+    }
+
+    /// Replaces the occurrences of the search pattern inside the selection
+    ///
+    /// Matches are found as by `findInSelection` and replaced as one undo
+    /// step. The selection is resized to cover the replaced text, so the
+    /// same selection can be searched again.
+    ///
+    /// # Arguments
+    /// * `pattern` - The text pattern to search for
+    /// * `replacement` - The text to replace matches with
+    /// * `case_sensitive` - Whether the search should be case-sensitive
+    /// * `use_regex` - Whether to interpret the pattern as a regular expression
+    /// * `ignore_accents` - Whether "resume" also matches "résumé" (defaults to false)
+    ///
+    /// # Returns
+    /// The number of replacements made
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the regex pattern is invalid or replacement fails
+    #[wasm_bindgen(js_name = replaceInSelection)]
+    pub fn replace_in_selection(
+        &self,
+        pattern: &str,
+        replacement: &str,
+        case_sensitive: bool,
+        use_regex: bool,
+        ignore_accents: Option<bool>,
+    ) -> Result<usize, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let query = SearchQuery::new(pattern.to_string())
+            .case_sensitive(case_sensitive)
+            .use_regex(use_regex)
+            .ignore_accents(ignore_accents.unwrap_or(false));
+
+        let selection = this.inner.get_selection().range();
+        let count = this
+            .inner
+            .find_and_replace_in_range(&query, replacement, selection)
+            .map_err(|e| JsValue::from_str(&format!("Replace in selection failed: {}", e)))?;
+
+        if count > 0 {
+            this.notify_change();
+            this.callbacks.trigger_selection_callbacks();
+        }
+
+        Ok(count)
+    }
+
+    /// Tidies the document before publishing, as a single undo step
+    ///
+    /// Takes an optional object; missing properties use their defaults:
+    /// - `trimTrailingWhitespace` (true): remove whitespace at the end of
+    ///   each line, outside code blocks
+    /// - `collapseEmptyParagraphs` (true): shorten runs of empty paragraphs
+    /// - `maxEmptyParagraphs` (2): the most consecutive empty paragraphs kept
+    /// - `clearWhitespaceFormats` (true): remove formatting from runs that
+    ///   only cover whitespace
+    ///
+    /// # Returns
+    /// true if the document changed
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the document is read-only or an option has
+    /// the wrong type
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.cleanupDocument({ maxEmptyParagraphs: 1 });
+    /// ```
+    #[wasm_bindgen(js_name = cleanupDocument)]
+    pub fn cleanup_document(&self, options: JsValue) -> Result<bool, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let mut cleanup = CleanupOptions::new();
+        if !options.is_undefined() && !options.is_null() {
+            let get = |key: &str| js_sys::Reflect::get(&options, &JsValue::from_str(key));
+            if let Some(trim) =
+                optional_bool(&get("trimTrailingWhitespace")?, "trimTrailingWhitespace")?
+            {
+                cleanup.trim_trailing_whitespace = trim;
+            }
+            if let Some(collapse) =
+                optional_bool(&get("collapseEmptyParagraphs")?, "collapseEmptyParagraphs")?
+            {
+                cleanup.collapse_empty_paragraphs = collapse;
+            }
+            if let Some(max) = optional_count(&get("maxEmptyParagraphs")?, "maxEmptyParagraphs")? {
+                cleanup.max_empty_paragraphs = max;
+            }
+            if let Some(clear) =
+                optional_bool(&get("clearWhitespaceFormats")?, "clearWhitespaceFormats")?
+            {
+                cleanup.clear_whitespace_formats = clear;
+            }
+        }
+
+        let changed = this
+            .inner
+            .cleanup(&cleanup)
+            .map_err(|e| JsValue::from_str(&format!("Cleanup failed: {}", e)))?;
+        if changed {
+            this.notify_change();
+            this.callbacks.trigger_selection_callbacks();
+        }
+        Ok(changed)
+    }
+
+    /// Rewrites every inline format through a mapping, as a single undo
+    /// step
+    ///
+    /// `map_fn` is called once for each distinct format with a
+    /// `{ type, value? }` object, such as `{ type: "link", value: "https://..." }`,
+    /// and returns what the format becomes: undefined to keep it, null to
+    /// remove it, or a format name or `{ type, value }` object to replace
+    /// it. Every answer is collected before the document changes, so an
+    /// invalid one leaves the document untouched.
+    ///
+    /// # Returns
+    /// A report `{ runsChanged, formatsReplaced, formatsRemoved,
+    /// charactersChanged }`
+    ///
+    /// # Errors
+    /// Rethrows any error thrown by `map_fn`; returns a JsValue error if it
+    /// returns something that is not a format or the document is read-only
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.migrateFormats(({ type, value }) => {
+    ///   if (type === "textColor" && value === "#FF0000") return "highlight";
+    ///   if (type === "link") return { type, value: value.replace("old.example", "new.example") };
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = migrateFormats)]
+    pub fn migrate_formats(&self, map_fn: &js_sys::Function) -> Result<JsValue, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let mut mapping: HashMap<InlineFormat, Option<InlineFormat>> = HashMap::new();
+        for run in this.inner.formats().get_runs() {
+            for format in &run.formats {
+                if mapping.contains_key(format) {
+                    continue;
+                }
+                let target = map_fn.call1(&JsValue::NULL, &format_entry_to_js(format))?;
+                let target = match target {
+                    target if target.is_undefined() => Some(format.clone()),
+                    target if target.is_null() => None,
+                    target => Some(parse_format_js(&target)?),
+                };
+                mapping.insert(format.clone(), target);
+            }
+        }
+
+        let report = this
+            .inner
+            .migrate_formats(|format| mapping.get(format).cloned().unwrap_or(Some(format.clone())))
+            .map_err(|e| JsValue::from_str(&format!("Migrate formats failed: {}", e)))?;
+        if !report.is_empty() {
+            this.notify_change();
+        }
+
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"runsChanged".into(), &report.runs_changed.into()).unwrap();
+        js_sys::Reflect::set(
+            &obj,
+            &"formatsReplaced".into(),
+            &report.formats_replaced.into(),
+        )
+        .unwrap();
+        js_sys::Reflect::set(
+            &obj,
+            &"formatsRemoved".into(),
+            &report.formats_removed.into(),
+        )
+        .unwrap();
+        js_sys::Reflect::set(
+            &obj,
+            &"charactersChanged".into(),
+            &report.characters_changed.into(),
+        )
+        .unwrap();
+        Ok(obj.into())
+    }
+
+    /// Runs an editing command addressed by name
+    ///
+    /// Gives test harnesses and remote-control tooling one entry point for
+    /// driving the editor. Each command takes its arguments from a JSON
+    /// object whose keys follow the parameter names of the method with the
+    /// same name, and notifies listeners like that method does.
+    ///
+    /// Supported commands:
+    /// - Text: `insertText {text, position}`, `deleteRange {start, end}`,
+    ///   `replaceRange {start, end, text}`, `insertSoftBreak`,
+    ///   `pastePlainText {text}`, `deleteWordBackward`, `deleteWordForward`,
+    ///   `findAndReplace {pattern, replacement, caseSensitive?, useRegex?,
+    ///   ignoreAccents?}`
+    /// - Formats: `applyFormat {format, value?, start, end}`,
+    ///   `removeFormat {format, start, end}`, `toggleFormat {format, start, end}`
+    /// - Blocks: `setBlockType {blockType, start, end}`,
+    ///   `indentListItem {start, end}`, `outdentListItem {start, end}`,
+    ///   `increaseBlockIndent {start, end}`, `decreaseBlockIndent {start, end}`
+    /// - Selection: `setSelection {anchor, focus}`, `selectAll`,
+    ///   `selectWord {position}`, `selectLine {position}`,
+    ///   `selectBlock {position}`, `expandSelection`, `shrinkSelection`,
+    ///   `collapseToStart`, `collapseToEnd`, `moveCursorLeft`,
+    ///   `moveCursorRight`, `moveCursorUp`, `moveCursorDown`,
+    ///   `moveToLineStart`, `moveToLineEnd`, `moveToDocumentStart`,
+    ///   `moveToDocumentEnd`, `moveByWord {forward}`
+    /// - History: `undo`, `redo`
+    ///
+    /// # Arguments
+    /// * `name` - The command name
+    /// * `args_json` - A JSON object with the command's arguments; may be
+    ///   omitted for commands without arguments
+    ///
+    /// # Returns
+    /// The result of the underlying method, such as the replacement count of
+    /// `findAndReplace`, or undefined
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the command is unknown, the arguments are
+    /// missing or invalid, or the command fails
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.executeCommand("insertText", JSON.stringify({ text: "Hello", position: 0 }));
+    /// doc.executeCommand("applyFormat", '{"format": "bold", "start": 0, "end": 5}');
+    /// doc.executeCommand("undo");
+    /// ```
+    #[wasm_bindgen(js_name = executeCommand)]
+    pub fn execute_command(
+        &self,
+        name: &str,
+        args_json: Option<String>,
+    ) -> Result<JsValue, JsValue> {
+        let args = match args_json.as_deref().map(str::trim) {
+            None | Some("") => js_sys::Object::new().into(),
+            Some(json) => js_sys::JSON::parse(json).map_err(|_| {
+                JsValue::from_str(&format!("Invalid arguments for '{}': not valid JSON", name))
+            })?,
+        };
+        if !args.is_object() {
+            return Err(JsValue::from_str(&format!(
+                "Invalid arguments for '{}': expected a JSON object",
+                name
+            )));
+        }
+        let arg = |key: &str| js_sys::Reflect::get(&args, &key.into()).unwrap_or_default();
+        let count = |key: &str| required(key, optional_count(&arg(key), key));
+        let string = |key: &str| required(key, optional_string(&arg(key), key));
+        let flag = |key: &str| optional_bool(&arg(key), key).map(|b| b.unwrap_or(false));
+
+        match name {
+            "insertText" => self.insert_text(&string("text")?, count("position")?)?,
+            "deleteRange" => self.delete_range(count("start")?, count("end")?)?,
+            "replaceRange" => {
+                self.replace_range(count("start")?, count("end")?, &string("text")?)?
+            }
+            "insertSoftBreak" => self.insert_soft_break()?,
+            "pastePlainText" => self.paste_plain_text(&string("text")?)?,
+            "deleteWordBackward" => self.delete_word_backward()?,
+            "deleteWordForward" => self.delete_word_forward()?,
+            "findAndReplace" => {
+                let replaced = self.find_and_replace(
+                    &string("pattern")?,
+                    &string("replacement")?,
+                    flag("caseSensitive")?,
+                    flag("useRegex")?,
+                    Some(flag("ignoreAccents")?),
+                )?;
+                return Ok(JsValue::from(replaced));
+            }
+            "applyFormat" => {
+                let format = string("format")?;
+                match optional_string(&arg("value"), "value")? {
+                    Some(value) => self.apply_format_with_value(
+                        &format,
+                        &value,
+                        count("start")?,
+                        count("end")?,
+                    )?,
+                    None => self.apply_format(&format, count("start")?, count("end")?)?,
+                }
+            }
+            "removeFormat" => {
+                self.remove_format(&string("format")?, count("start")?, count("end")?)?
+            }
+            "toggleFormat" => {
+                self.toggle_format(&string("format")?, count("start")?, count("end")?)?
+            }
+            "setBlockType" => {
+                self.set_block_type(&string("blockType")?, count("start")?, count("end")?)?
+            }
+            "indentListItem" => self.indent_list_item(count("start")?, count("end")?)?,
+            "outdentListItem" => self.outdent_list_item(count("start")?, count("end")?)?,
+            "increaseBlockIndent" => self.increase_block_indent(count("start")?, count("end")?)?,
+            "decreaseBlockIndent" => self.decrease_block_indent(count("start")?, count("end")?)?,
+            "setSelection" => self.set_selection(count("anchor")?, count("focus")?),
+            "selectAll" => self.select_all(),
+            "selectWord" => self.select_word(count("position")?),
+            "selectLine" => self.select_line(count("position")?),
+            "selectBlock" => self.select_block(count("position")?),
+            "expandSelection" => return Ok(JsValue::from(self.expand_selection())),
+            "shrinkSelection" => return Ok(JsValue::from(self.shrink_selection())),
+            "collapseToStart" => self.collapse_to_start(),
+            "collapseToEnd" => self.collapse_to_end(),
+            "moveCursorLeft" => self.move_cursor_left(),
+            "moveCursorRight" => self.move_cursor_right(),
+            "moveCursorUp" => self.move_cursor_up(),
+            "moveCursorDown" => self.move_cursor_down(),
+            "moveToLineStart" => self.move_to_line_start(),
+            "moveToLineEnd" => self.move_to_line_end(),
+            "moveToDocumentStart" => self.move_to_document_start(),
+            "moveToDocumentEnd" => self.move_to_document_end(),
+            "moveByWord" => self.move_by_word(flag("forward")?),
+            "undo" => self.undo()?,
+            "redo" => self.redo()?,
+            _ => {
+                return Err(JsValue::from_str(&format!("Unknown command: '{}'", name)));
+            }
+        }
+        Ok(JsValue::UNDEFINED)
+    }
+
+    /// Registers a callback to be called when the document content changes
+    ///
+    /// The callback runs once per edit with `{type, range: {start, end},
+    /// insertedText, deletedLength, version}`. `type` is "insert",
+    /// "delete", "replace" or "format". For text edits, `range` is the span
+    /// replaced in the text as it was before the edit; for format changes
+    /// it is the affected span. After `resumeEvents`, several edits made
+    /// while suspended arrive as one "batch" event: its range spans them
+    /// all in the current text, `insertedText` and `deletedLength` are
+    /// null, and a `changes` array holds the individual events.
+    /// `setEventBatching` batches the edits of each script task the same
+    /// way.
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to call on document changes
+    ///
+    /// # Example
+    /// ```javascript
+    /// document.onChange(({ type, range, insertedText }) => {
+    ///     if (type === "insert") {
+    ///         view.insertAt(range.start, insertedText);
+    ///     } else {
+    ///         view.rerender(range.start, range.end);
+    ///     }
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = onChange)]
+    pub fn on_change(&self, callback: js_sys::Function) {
+        let mut this = self.state_mut();
+        this.callbacks.add_change_callback(callback);
+        this.inner.set_change_recording(true);
+    }
+
+    /// Registers a callback to be called when the selection changes
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to call on selection changes
+    ///
+    /// # Example
+    /// ```javascript
+    /// document.onSelectionChange(() => {
+    ///     console.log("Selection changed!");
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = onSelectionChange)]
+    pub fn on_selection_change(&self, callback: js_sys::Function) {
+        let mut this = self.state_mut();
+        this.callbacks.add_selection_callback(callback);
+    }
+
+    /// Registers a callback to be called when undo/redo availability changes
+    ///
+    /// The callback receives `{canUndo, canRedo, undoDescription,
+    /// redoDescription}`, where the descriptions are short labels such as
+    /// "typing", "deletion" or "formatting", or null. It runs only when one
+    /// of these values changes.
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to call on history changes
+    ///
+    /// # Example
+    /// ```javascript
+    /// document.onHistoryChange(({ canUndo, undoDescription }) => {
+    ///     undoButton.disabled = !canUndo;
+    ///     undoButton.title = undoDescription ? `Undo ${undoDescription}` : "Undo";
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = onHistoryChange)]
+    pub fn on_history_change(&self, callback: js_sys::Function) {
+        let mut this = self.state_mut();
+        this.callbacks.add_history_callback(callback);
+    }
+
+    /// Unregisters a history change callback
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to remove from history callbacks
+    ///
+    /// # Returns
+    /// true if the callback was found and removed, false otherwise
+    #[wasm_bindgen(js_name = offHistoryChange)]
+    pub fn off_history_change(&self, callback: js_sys::Function) -> bool {
+        let mut this = self.state_mut();
+        this.callbacks.remove_history_callback(&callback)
+    }
+
+    /// Registers a callback to be called when the formatting becomes
+    /// fragmented
+    ///
+    /// Formatting is fragmented when it has more format runs per 1000
+    /// characters than the threshold set with `setFragmentationThreshold`
+    /// (200 by default); documents with fewer than 256 runs never are. The
+    /// callback receives the stats from `getFragmentationStats` once when
+    /// the threshold is crossed, and again only after the formatting has
+    /// dropped back below it.
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to call with the stats
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.onFragmentationWarning(() => {
+    ///     requestIdleCallback(() => doc.normalizeFormats());
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = onFragmentationWarning)]
+    pub fn on_fragmentation_warning(&self, callback: js_sys::Function) {
+        let mut this = self.state_mut();
+        this.callbacks.add_fragmentation_callback(callback);
+    }
+
+    /// Unregisters a fragmentation warning callback
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to remove from fragmentation callbacks
+    ///
+    /// # Returns
+    /// true if the callback was found and removed, false otherwise
+    #[wasm_bindgen(js_name = offFragmentationWarning)]
+    pub fn off_fragmentation_warning(&self, callback: js_sys::Function) -> bool {
+        let mut this = self.state_mut();
+        this.callbacks.remove_fragmentation_callback(&callback)
+    }
+
+    /// Registers a callback to be called when a duplicate paste is
+    /// suppressed
+    ///
+    /// Pastes are only checked for duplicates once `setPasteDedupWindow`
+    /// has set a window. The callback receives `{ text, offset, elapsedMs }`
+    /// with the dropped text, where it would have gone and how long after
+    /// the identical paste it came.
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to call with the suppressed paste
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.setPasteDedupWindow(150);
+    /// doc.onDuplicatePaste(({ elapsedMs }) => telemetry.count("double-paste", elapsedMs));
+    /// ```
+    #[wasm_bindgen(js_name = onDuplicatePaste)]
+    pub fn on_duplicate_paste(&self, callback: js_sys::Function) {
+        let mut this = self.state_mut();
+        this.callbacks.add_duplicate_paste_callback(callback);
+    }
+
+    /// Unregisters a duplicate paste callback
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to remove from duplicate paste callbacks
+    ///
+    /// # Returns
+    /// true if the callback was found and removed, false otherwise
+    #[wasm_bindgen(js_name = offDuplicatePaste)]
+    pub fn off_duplicate_paste(&self, callback: js_sys::Function) -> bool {
+        let mut this = self.state_mut();
+        this.callbacks.remove_duplicate_paste_callback(&callback)
+    }
+
+    /// Sets the format runs per 1000 characters above which
+    /// `onFragmentationWarning` callbacks are warned
+    #[wasm_bindgen(js_name = setFragmentationThreshold)]
+    pub fn set_fragmentation_threshold(&self, runs_per_1000_chars: f64) {
+        let mut this = self.state_mut();
+        this.callbacks
+            .set_fragmentation_threshold(runs_per_1000_chars);
+        this.notify_fragmentation();
+    }
+
+    /// Unregisters a change callback
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to remove from change callbacks
+    ///
+    /// # Returns
+    /// true if the callback was found and removed, false otherwise
+    #[wasm_bindgen(js_name = offChange)]
+    pub fn off_change(&self, callback: js_sys::Function) -> bool {
+        let mut this = self.state_mut();
+        let removed = this.callbacks.remove_change_callback(&callback);
+        if !this.callbacks.has_change_callbacks() {
+            this.inner.set_change_recording(false);
+        }
+        removed
+    }
+
+    /// Unregisters a selection change callback
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to remove from selection callbacks
+    ///
+    /// # Returns
+    /// true if the callback was found and removed, false otherwise
+    #[wasm_bindgen(js_name = offSelectionChange)]
+    pub fn off_selection_change(&self, callback: js_sys::Function) -> bool {
+        let mut this = self.state_mut();
+        this.callbacks.remove_selection_callback(&callback)
+    }
+
+    /// Holds back change and selection callbacks until `resumeEvents`
+    ///
+    /// Calls nest, so every `suspendEvents` needs a matching
+    /// `resumeEvents`. Use this when populating a document with many
+    /// programmatic edits.
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.suspendEvents();
+    /// for (const line of lines) {
+    ///     doc.insertText(line + "\n", doc.getLength());
+    /// }
+    /// doc.resumeEvents(); // one change callback
+    /// ```
+    #[wasm_bindgen(js_name = suspendEvents)]
+    pub fn suspend_events(&self) {
+        let mut this = self.state_mut();
+        this.callbacks.suspend();
+    }
+
+    /// Ends one `suspendEvents` call
+    ///
+    /// When the outermost suspension ends, the dirty regions produced while
+    /// suspended are merged into one, and each callback kind that had an
+    /// event held back runs once. Several held-back changes arrive as one
+    /// "batch" change event.
+    #[wasm_bindgen(js_name = resumeEvents)]
+    pub fn resume_events(&self) {
+        let mut this = self.state_mut();
+        if this.callbacks.resume() {
+            if this.callbacks.has_pending_change() {
+                this.inner.coalesce_dirty_regions();
+            }
+            this.callbacks.flush_pending();
+            this.notify_history();
+            this.notify_fragmentation();
+        }
+    }
+
+    /// Returns true while callbacks are suspended
+    #[wasm_bindgen(js_name = isEventsSuspended)]
+    pub fn is_events_suspended(&self) -> bool {
+        let this = self.state();
+        this.callbacks.is_suspended()
+    }
+
+    /// Gets when change and selection callbacks run
+    ///
+    /// Returns "immediate" or "microtask"
+    #[wasm_bindgen(js_name = getEventBatching)]
+    pub fn get_event_batching(&self) -> String {
+        let this = self.state();
+        match this.callbacks.batching() {
+            EventBatching::Immediate => "immediate",
+            EventBatching::Microtask => "microtask",
+        }
+        .to_string()
+    }
+
+    /// Sets when change and selection callbacks run
+    ///
+    /// With "immediate" (the default), callbacks run after every edit.
+    /// With "microtask", events are held back until the current script
+    /// finishes and then delivered together, like an implicit
+    /// `suspendEvents`/`resumeEvents` around each task: several changes
+    /// arrive as one "batch" change event and selection callbacks run at
+    /// most once. History callbacks are not batched. Switching back to
+    /// "immediate" delivers anything held back.
+    ///
+    /// # Arguments
+    /// * `mode` - "immediate" or "microtask"
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the mode is unknown
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.setEventBatching("microtask");
+    /// doc.onChange((event) => view.update(event));
+    /// doc.findAndReplace("colour", "color", false, false); // one event, later
+    /// ```
+    #[wasm_bindgen(js_name = setEventBatching)]
+    pub fn set_event_batching(&self, mode: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let batching = match mode {
+            "immediate" => EventBatching::Immediate,
+            "microtask" => EventBatching::Microtask,
+            _ => {
+                return Err(JsValue::from_str(&format!(
+                    "Unknown event batching mode: {}",
+                    mode
+                )));
+            }
+        };
+        this.callbacks.set_batching(batching);
+        Ok(())
+    }
+
+    /// Delivers the change and selection events held back by microtask
+    /// batching now instead of at the end of the task
+    ///
+    /// Has no effect while events are suspended.
+    #[wasm_bindgen(js_name = flushEvents)]
+    pub fn flush_events(&self) {
+        let this = self.state();
+        if !this.callbacks.is_suspended() {
+            this.callbacks.flush_pending();
+        }
+    }
+
+    /// Runs `callback` with notifications suspended (static method)
+    ///
+    /// Equivalent to `suspendEvents()`, calling `callback(document)`, then
+    /// `resumeEvents()`. Events are resumed even if the callback throws.
+    /// This is a static method because the callback needs to edit the
+    /// document while it runs.
+    ///
+    /// # Arguments
+    /// * `document` - The document to edit
+    /// * `callback` - Function receiving the document
+    ///
+    /// # Returns
+    /// Whatever `callback` returns
+    ///
+    /// # Errors
+    /// Rethrows any error thrown by `callback`
+    ///
+    /// # Example
+    /// ```javascript
+    /// WasmDocument.withoutNotifications(doc, (d) => {
+    ///     d.insertText("Title", 0);
+    ///     d.setBlockType("h1", 0, 5);
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = withoutNotifications)]
+    pub fn without_notifications(
+        #[wasm_bindgen(unchecked_param_type = "WasmDocument")] document: JsValue,
+        callback: &js_sys::Function,
+    ) -> Result<JsValue, JsValue> {
+        let handle: &DocumentHandle = document.unchecked_ref();
+        handle.suspend_events();
+        let result = callback.call1(&JsValue::NULL, &document);
+        handle.resume_events();
+        result
+    }
+
+    /// Runs `callback` once it is safe to edit documents (static method)
+    ///
+    /// Event callbacks run inside the method that made the change, so a
+    /// callback cannot call methods of that document directly: they throw
+    /// at once. Wrap such calls in `defer` instead. Outside event callbacks,
+    /// `callback` runs immediately; inside them, it runs in a microtask after
+    /// the outer method has returned, in the order the calls were deferred.
+    ///
+    /// # Arguments
+    /// * `callback` - Function to run
+    ///
+    /// # Returns
+    /// Whatever `callback` returns, or undefined if it was deferred
+    ///
+    /// # Errors
+    /// Rethrows any error thrown by a `callback` that runs immediately
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.onChange((event) => {
+    ///     if (event.insertedText === ":)") {
+    ///         const start = event.range.start;
+    ///         WasmDocument.defer(() => doc.replaceRange(start, start + 2, "🙂"));
+    ///     }
+    /// });
+    /// ```
+    pub fn defer(callback: js_sys::Function) -> Result<JsValue, JsValue> {
+        events::defer(callback)
+    }
+
+    /// Copies the current selection to clipboard format
+    ///
+    /// Returns an object with `text` and `html` properties containing the
+    /// selected content in both plain text and HTML formats. The HTML format
+    /// preserves all inline formatting, and declares its charset and embeds
+    /// the content as JSON so that `pasteHtml` in any editor instance
+    /// restores it exactly, block types included.
+    ///
+    /// # Returns
+    /// An object with:
+    /// - `text`: Plain text content
+    /// - `html`: HTML formatted content
+    ///
+    /// Returns an object with empty strings if selection is collapsed.
+    /// List items in the text keep their bullets and numbers and quoted
+    /// lines their `> ` markers, so pasting into plain text targets keeps
+    /// the structure.
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// const clipboardData = document.copy();
+    /// console.log(clipboardData.text); // "Hello"
+    /// event.clipboardData.setData('text/html', clipboardData.html);
+    /// ```
+    #[wasm_bindgen(js_name = copy)]
+    pub fn copy(&self) -> JsValue {
+        let this = self.state();
+        let content = this.inner.copy();
+        let html = content.to_clipboard_html();
+        let text = content.to_plain_text();
+
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"text".into(), &text.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"html".into(), &html.into()).unwrap();
+        obj.into()
+    }
+
+    /// Copies the current selection as Markdown
+    ///
+    /// Inline formats, links, images and the block types of lines the
+    /// selection starts are written as Markdown syntax. Returns an empty
+    /// string if the selection is collapsed.
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// event.clipboardData.setData('text/markdown', document.copyAsMarkdown());
+    /// ```
+    #[wasm_bindgen(js_name = copyAsMarkdown)]
+    pub fn copy_as_markdown(&self) -> String {
+        let this = self.state();
+        this.inner.copy().to_markdown()
+    }
+
+    /// Cuts the current selection to clipboard format
+    ///
+    /// Copies the selected text and formats, then deletes the selection.
+    /// This operation is undoable.
+    ///
+    /// # Returns
+    /// An object with:
+    /// - `text`: Plain text content
+    /// - `html`: HTML formatted content, as from `copy`
+    ///
+    /// Returns an object with empty strings if selection is collapsed.
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the delete operation fails.
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// const clipboardData = document.cut();
+    /// console.log(clipboardData.text); // "Hello"
+    /// // Selection is now deleted from document
+    /// ```
+    #[wasm_bindgen(js_name = cut)]
+    pub fn cut(&self) -> Result<JsValue, JsValue> {
+        let mut this = self.try_state_mut()?;
+        let content = this
+            .inner
+            .cut()
+            .map_err(|e| JsValue::from_str(&format!("Cut failed: {}", e)))?;
+
+        let is_empty = content.is_empty();
+        let html = content.to_clipboard_html();
+        let text = content.to_plain_text();
+
+        if !is_empty {
+            this.notify_change();
+            this.callbacks.trigger_selection_callbacks();
+        }
+
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"text".into(), &text.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"html".into(), &html.into()).unwrap();
+        Ok(obj.into())
+    }
+
+    /// Moves the selection of one document to the cursor of another (static method)
+    ///
+    /// Formats, images and block types are preserved, and each document
+    /// records a single undo step. If pasting fails, the cut is undone.
+    ///
+    /// The two arguments must be different documents. Passing the same
+    /// document twice is an error and changes nothing; use `cut` and
+    /// `pasteHtml` to move text within one document.
+    ///
+    /// # Arguments
+    /// * `cut_from` - The document whose selection is cut
+    /// * `paste_to` - The document that receives the content
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the cut or paste fails, or if both
+    /// arguments are the same document
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// WasmDocument.transfer(sourceDoc, targetDoc);
+    /// sourceDoc.undo(); // restores the cut text in the source only
+    /// ```
+    #[wasm_bindgen(js_name = transfer)]
+    pub fn transfer(cut_from: &WasmDocument, paste_to: &WasmDocument) -> Result<(), JsValue> {
+        if std::ptr::eq(cut_from, paste_to) {
+            return Err(JsValue::from_str(
+                "Transfer failed: source and target are the same document",
+            ));
+        }
+        let content = {
+            let mut source = cut_from.state_mut();
+            let mut target = paste_to.state_mut();
+            Document::transfer(&mut source.inner, &mut target.inner)
+                .map_err(|e| JsValue::from_str(&format!("Transfer failed: {}", e)))?
+        };
+
+        // Each document is notified on its own, so callbacks can read the other
+        if !content.is_empty() {
+            for doc in [cut_from, paste_to] {
+                let mut this = doc.state_mut();
+                this.notify_change();
+                this.callbacks.trigger_selection_callbacks();
+            }
+        }
+        Ok(())
+    }
+
+    /// Pastes HTML content at the current cursor position
+    ///
+    /// If there is a selection, it will be replaced with the pasted content.
+    /// The HTML is parsed and sanitized to prevent XSS attacks. All formatting
+    /// is preserved from the HTML. HTML from `copy` is pasted from the JSON
+    /// it embeds, as long as its links, colors and images pass the sanitizer.
+    ///
+    /// This operation is undoable.
+    ///
+    /// # Arguments
+    /// * `html` - The HTML string to paste
+    ///
+    /// # Errors
+    /// Returns a JsValue error if HTML parsing or paste operation fails.
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// document.pasteHtml("<strong>Hello</strong> World");
+    /// // Document now contains "Hello World" with "Hello" in bold
+    /// ```
+    #[wasm_bindgen(js_name = pasteHtml)]
+    pub fn paste_html(&self, html: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .paste_html(html)
+            .map_err(|e| JsValue::from_str(&format!("Paste HTML failed: {}", e)))?;
+        this.notify_change();
+        this.callbacks.trigger_selection_callbacks();
+        Ok(())
+    }
+
+    /// Pastes plain text at the current cursor position
+    ///
+    /// If there is a selection, it will be replaced with the pasted text.
+    /// No formatting is applied to the pasted text.
+    ///
+    /// With multiple cursors, text with one line per cursor is distributed
+    /// over them, one line each; otherwise it is pasted at every cursor.
+    ///
+    /// This operation is undoable.
+    ///
+    /// # Arguments
+    /// * `text` - The plain text to paste
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the paste operation fails.
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// document.pastePlainText("Hello World");
+    /// // Document now contains "Hello World" with no formatting
+    /// ```
+    #[wasm_bindgen(js_name = pastePlainText)]
+    pub fn paste_plain_text(&self, text: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        this.inner
+            .paste_plain_text(text)
+            .map_err(|e| JsValue::from_str(&format!("Paste plain text failed: {}", e)))?;
+        this.notify_change();
+        this.callbacks.trigger_selection_callbacks();
+        Ok(())
+    }
+
+    /// Starts an IME composition at the current cursor position
+    ///
+    /// This should be called when the browser fires a `compositionstart` event.
+    /// It marks the beginning of an IME composition session for languages like
+    /// Chinese, Japanese, or Korean.
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// element.addEventListener('compositionstart', () => {
+    ///     document.startComposition();
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = startComposition)]
+    pub fn start_composition(&self) {
+        let mut this = self.state_mut();
+        this.inner.start_composition();
+    }
+
+    /// Updates the IME composition with new text
+    ///
+    /// This should be called when the browser fires a `compositionupdate` event.
+    /// It replaces the current composition text with the new text.
+    ///
+    /// # Arguments
+    /// * `text` - The new composition text
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// element.addEventListener('compositionupdate', (e) => {
+    ///     document.updateComposition(e.data);
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = updateComposition)]
+    pub fn update_composition(&self, text: &str) {
+        let mut this = self.state_mut();
+        this.inner.update_composition(text);
+    }
+
+    /// Ends the IME composition and commits the final text
+    ///
+    /// This should be called when the browser fires a `compositionend` event.
+    /// It finalizes the composition and commits the text to the document.
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// element.addEventListener('compositionend', () => {
+    ///     document.endComposition();
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = endComposition)]
+    pub fn end_composition(&self) {
+        let mut this = self.state_mut();
+        this.inner.end_composition();
+        this.notify_change();
+    }
+
+    /// Cancels the IME composition without committing
+    ///
+    /// This removes any composition text and returns the document to its
+    /// state before composition started. This is useful if the user cancels
+    /// the composition (e.g., by pressing Escape).
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// element.addEventListener('keydown', (e) => {
+    ///     if (e.key === 'Escape' && document.isComposing()) {
+    ///         document.cancelComposition();
+    ///     }
+    /// });
+    /// ```
+    #[wasm_bindgen(js_name = cancelComposition)]
+    pub fn cancel_composition(&self) {
+        let mut this = self.state_mut();
+        this.inner.cancel_composition();
+        this.notify_change();
+    }
+
+    /// Returns true if IME composition is currently active
+    ///
+    /// This can be used to check whether the user is currently composing text
+    /// with an IME, which may affect how keyboard events are handled.
+    ///
+    /// # Returns
+    /// true if composition is active, false otherwise
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// if (document.isComposing()) {
+    ///     console.log("User is composing text");
+    /// }
+    /// ```
+    #[wasm_bindgen(js_name = isComposing)]
+    pub fn is_composing(&self) -> bool {
+        let this = self.state();
+        this.inner.is_composing()
+    }
+
+    /// Returns the current composition range, if composition is active
+    ///
+    /// Returns a JsValue object with start and end properties, or null if
+    /// composition is not active.
+    ///
+    /// # Returns
+    /// An object with start and end properties, or null
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// const range = document.getCompositionRange();
+    /// if (range) {
+    ///     console.log(`Composing from ${range.start} to ${range.end}`);
+    /// }
+    /// ```
+    #[wasm_bindgen(js_name = getCompositionRange)]
+    pub fn get_composition_range(&self) -> JsValue {
+        let this = self.state();
+        if let Some(range) = this.inner.composition_range() {
+            let obj = js_sys::Object::new();
+            js_sys::Reflect::set(&obj, &"start".into(), &range.start.offset().into()).unwrap();
+            js_sys::Reflect::set(&obj, &"end".into(), &range.end.offset().into()).unwrap();
+            obj.into()
+        } else {
+            JsValue::NULL
+        }
+    }
+
+    /// Returns the current composition text, if composition is active
+    ///
+    /// Returns the text being composed, or null if composition is not active.
+    ///
+    /// # Returns
+    /// The composition text as a string, or null
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// const text = document.getCompositionText();
+    /// if (text) {
+    ///     console.log(`Composing: ${text}`);
+    /// }
+    /// ```
+    #[wasm_bindgen(js_name = getCompositionText)]
+    pub fn get_composition_text(&self) -> JsValue {
+        let this = self.state();
+        if let Some(text) = this.inner.composition_text() {
+            JsValue::from_str(text)
+        } else {
+            JsValue::NULL
+        }
+    }
+
+    /// Gets the current maximum history size
+    ///
+    /// Returns the maximum number of commands that can be stored in the
+    /// undo/redo history. The default is 100 commands.
+    ///
+    /// # Returns
+    /// The maximum number of commands in the history
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// const limit = doc.getHistoryLimit();
+    /// console.log(`History limit: ${limit}`); // "History limit: 100"
+    /// ```
+    #[wasm_bindgen(js_name = getHistoryLimit)]
+    pub fn get_history_limit(&self) -> usize {
+        let this = self.state();
+        this.inner.get_history_limit()
+    }
+
+    /// Sets the maximum history size
+    ///
+    /// Configures the maximum number of commands that can be stored in the
+    /// undo/redo history. If the new size is smaller than the current number
+    /// of commands, the oldest commands will be removed to fit the new limit.
+    ///
+    /// # Arguments
+    /// * `max_size` - The new maximum number of commands to store
+    ///
+    /// # Memory Impact
+    ///
+    /// Reducing the history limit immediately frees memory from removed commands.
+    /// Each command stores approximately 64 bytes plus the size of affected text.
+    ///
+    /// Examples:
+    /// - Reducing from 100 to 50 commands: Saves ~3.2KB + text data
+    /// - Reducing from 100 to 20 commands: Saves ~5.1KB + text data
+    ///
+    /// # Use Cases
+    ///
+    /// - **Mobile devices**: Use 20-50 commands to reduce memory usage
+    /// - **Large documents**: Use smaller history to prevent memory pressure
+    /// - **Memory-constrained environments**: Reduce to minimum needed
+    /// - **Desktop applications**: Default 100 is usually fine
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// // Reduce history for mobile devices
+    /// doc.setHistoryLimit(50);
+    ///
+    /// // Minimal history for memory-constrained environments
+    /// doc.setHistoryLimit(20);
+    ///
+    /// // Restore default
+    /// doc.setHistoryLimit(100);
+    /// ```
+    #[wasm_bindgen(js_name = setHistoryLimit)]
+    pub fn set_history_limit(&self, max_size: usize) {
+        let mut this = self.state_mut();
+        this.inner.set_history_limit(max_size);
+        this.notify_history();
+    }
+
+    /// Gets the window, in milliseconds, within which typed characters share
+    /// an undo step
+    ///
+    /// # Returns
+    /// The window, or null if every typed character is undone separately
+    #[wasm_bindgen(js_name = getUndoCoalesceWindow)]
+    pub fn get_undo_coalesce_window(&self) -> Option<u32> {
+        let this = self.state();
+        this.inner
+            .get_undo_coalesce_window()
+            .map(|window| u32::try_from(window.as_millis()).unwrap_or(u32::MAX))
+    }
+
+    /// Sets the window, in milliseconds, within which typed characters share
+    /// an undo step
+    ///
+    /// Characters typed with `insertText` one at a time, each less than the
+    /// window after the previous one, are undone together; whitespace after
+    /// a word starts a new step. Defaults to 500ms.
+    ///
+    /// # Arguments
+    /// * `window_ms` - The window, or null to undo every typed character separately
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// doc.setUndoCoalesceWindow(1000);
+    /// doc.setUndoCoalesceWindow(null); // one undo step per character
+    /// ```
+    #[wasm_bindgen(js_name = setUndoCoalesceWindow)]
+    pub fn set_undo_coalesce_window(&self, window_ms: Option<u32>) {
+        let mut this = self.state_mut();
+        this.inner.set_undo_coalesce_window(
+            window_ms.map(|window| std::time::Duration::from_millis(u64::from(window))),
+        );
+    }
+
+    /// Gets the window, in milliseconds, within which an identical paste
+    /// is dropped as a duplicate
+    ///
+    /// # Returns
+    /// The window, or null if every paste is applied
+    #[wasm_bindgen(js_name = getPasteDedupWindow)]
+    pub fn get_paste_dedup_window(&self) -> Option<u32> {
+        let this = self.state();
+        this.inner
+            .get_paste_dedup_window()
+            .map(|window| u32::try_from(window.as_millis()).unwrap_or(u32::MAX))
+    }
+
+    /// Sets the window, in milliseconds, within which an identical paste
+    /// is dropped as a duplicate
+    ///
+    /// Some browsers fire the same paste event twice. A paste is dropped
+    /// when it has the same payload as the one before it, comes less than
+    /// the window after it, and finds the cursor where that paste left it
+    /// with no edit in between. Dropped pastes are reported to
+    /// `onDuplicatePaste` callbacks. Off by default.
+    ///
+    /// # Arguments
+    /// * `window_ms` - The window, or null to apply every paste
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// doc.setPasteDedupWindow(150);
+    /// ```
+    #[wasm_bindgen(js_name = setPasteDedupWindow)]
+    pub fn set_paste_dedup_window(&self, window_ms: Option<u32>) {
+        let mut this = self.state_mut();
+        this.inner.set_paste_dedup_window(
+            window_ms.map(|window| std::time::Duration::from_millis(u64::from(window))),
+        );
+    }
+
+    /// Merges runs of older undo steps with the same label, such as a long
+    /// stretch of typing, into single steps
+    ///
+    /// This frees room under the history limit, so all-day sessions keep
+    /// more history. The newest steps are left alone and still undo one at
+    /// a time. Call it from an idle callback, or let
+    /// `compactHistoryWhenIdle` schedule it.
+    ///
+    /// # Arguments
+    /// * `budget_ms` - Time after which to stop, or null to finish
+    /// * `keep_recent` - Number of newest steps to leave alone (defaults to 20)
+    ///
+    /// # Returns
+    /// true if the budget ran out and another call would compact more
+    ///
+    /// # Example
+    /// ```javascript
+    /// requestIdleCallback((deadline) => doc.compactHistory(deadline.timeRemaining()));
+    /// ```
+    #[wasm_bindgen(js_name = compactHistory)]
+    pub fn compact_history(&self, budget_ms: Option<f64>, keep_recent: Option<usize>) -> bool {
+        let mut this = self.state_mut();
+        let budget = budget_ms
+            .filter(|ms| ms.is_finite())
+            .map(|ms| std::time::Duration::try_from_secs_f64(ms / 1000.0).unwrap_or_default());
+        this.inner.compact_history(
+            keep_recent.unwrap_or(CommandHistory::DEFAULT_KEEP_RECENT),
+            budget,
+        )
+    }
+
+    /// Compacts the undo history in the background, a little in each idle
+    /// period, until there is nothing left to merge
+    ///
+    /// Each idle period runs `compactHistory` for the time the browser
+    /// offers. Where `requestIdleCallback` is unavailable, a timeout with a
+    /// short budget is used instead. Compaction stops quietly if the
+    /// document is freed. This is a static method because the work happens
+    /// after it returns.
+    ///
+    /// # Arguments
+    /// * `document` - The document whose history to compact
+    /// * `keep_recent` - Number of newest steps to leave alone (defaults to 20)
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.onHistoryChange(() => WasmDocument.compactHistoryWhenIdle(doc));
+    /// ```
+    #[wasm_bindgen(js_name = compactHistoryWhenIdle)]
+    pub fn compact_history_when_idle(
+        #[wasm_bindgen(unchecked_param_type = "WasmDocument")] document: JsValue,
+        keep_recent: Option<usize>,
+    ) {
+        let callback = Closure::once_into_js(move |deadline: JsValue| {
+            let budget = if deadline.is_object() {
+                deadline.unchecked_ref::<IdleDeadline>().time_remaining()
+            } else {
+                FALLBACK_IDLE_BUDGET_MS
+            };
+            let handle: &DocumentHandle = document.unchecked_ref();
+            if let Ok(true) = handle.compact_history(Some(budget), keep_recent) {
+                Self::compact_history_when_idle(document, keep_recent);
+            }
+        });
+        if request_idle_callback(&callback).is_err() {
+            set_timeout(&callback, 0);
+        }
+    }
+
+    /// Returns true if local edits are rejected
+    #[wasm_bindgen(js_name = isReadOnly)]
+    pub fn is_read_only(&self) -> bool {
+        let this = self.state();
+        this.inner.is_read_only()
+    }
+
+    /// Sets whether local edits are rejected
+    ///
+    /// While read-only, editing methods return an error and formatting
+    /// methods do nothing. Selection changes and remote updates still work.
+    #[wasm_bindgen(js_name = setReadOnly)]
+    pub fn set_read_only(&self, read_only: bool) {
+        let mut this = self.state_mut();
+        this.inner.set_read_only(read_only);
+    }
+
+    /// Gets the maximum document length in characters, or undefined if unlimited
+    #[wasm_bindgen(js_name = getMaxLength)]
+    pub fn get_max_length(&self) -> Option<usize> {
+        let this = self.state();
+        this.inner.get_max_length()
+    }
+
+    /// Sets the maximum document length in characters
+    ///
+    /// Pass undefined to remove the limit. Edits that would grow the document
+    /// past the limit return an error.
+    #[wasm_bindgen(js_name = setMaxLength)]
+    pub fn set_max_length(&self, max_length: Option<usize>) {
+        let mut this = self.state_mut();
+        this.inner.set_max_length(max_length);
+    }
+
+    /// Gets how control characters in inserted and pasted text are handled
+    ///
+    /// Returns "strip", "replace" or "keep"
+    #[wasm_bindgen(js_name = getControlCharPolicy)]
+    pub fn get_control_char_policy(&self) -> String {
+        let this = self.state();
+        match this.inner.control_char_policy() {
+            ControlCharPolicy::Strip => "strip",
+            ControlCharPolicy::ReplaceWithSpace => "replace",
+            ControlCharPolicy::Keep => "keep",
+        }
+        .to_string()
+    }
+
+    /// Sets how control characters in inserted and pasted text are handled
+    ///
+    /// Line feed, carriage return and tab are always kept and NUL is always
+    /// removed. With "strip" (the default) or "replace", vertical tab and
+    /// form feed become line breaks.
+    ///
+    /// # Arguments
+    /// * `policy` - "strip" to remove them, "replace" to turn them into spaces, or "keep"
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the policy is unknown
+    #[wasm_bindgen(js_name = setControlCharPolicy)]
+    pub fn set_control_char_policy(&self, policy: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let policy = match policy {
+            "strip" => ControlCharPolicy::Strip,
+            "replace" => ControlCharPolicy::ReplaceWithSpace,
+            "keep" => ControlCharPolicy::Keep,
+            _ => {
+                return Err(JsValue::from_str(&format!(
+                    "Unknown control character policy: {}",
+                    policy
+                )));
+            }
+        };
+        this.inner.set_control_char_policy(policy);
+        Ok(())
+    }
+
+    /// Turns autocorrect on or off
+    ///
+    /// Autocorrect rewrites typed text when a space or punctuation follows
+    /// it: "(c)" becomes "©", "--" an em dash and "..." an ellipsis. Only
+    /// single characters passed to `insertText`, as typing inserts them, are
+    /// corrected, never pasted text or code. Each correction is its own
+    /// undo step. Off by default.
+    ///
+    /// # Arguments
+    /// * `enabled` - Whether corrections are made
+    /// * `smart_quotes` - Whether straight quotes become curly ones as they
+    ///   are typed; unchanged if omitted (on by default)
+    #[wasm_bindgen(js_name = setAutocorrect)]
+    pub fn set_autocorrect(&self, enabled: bool, smart_quotes: Option<bool>) {
+        let mut this = self.state_mut();
+        let autocorrect = this.inner.autocorrect_mut();
+        autocorrect.enabled = enabled;
+        if let Some(smart_quotes) = smart_quotes {
+            autocorrect.smart_quotes = smart_quotes;
+        }
+    }
+
+    /// Adds an autocorrect rule replacing `from` with `to`, or changes the
+    /// replacement of an existing rule
+    ///
+    /// A rule for text that starts with a letter or digit only applies at
+    /// the start of a word.
+    ///
+    /// # Errors
+    /// Returns a JsValue error if `from` is empty
+    ///
+    /// # Example
+    /// ```javascript
+    /// doc.addAutocorrectRule("teh", "the");
+    /// doc.addAutocorrectRule("1/2", "½");
+    /// ```
+    #[wasm_bindgen(js_name = addAutocorrectRule)]
+    pub fn add_autocorrect_rule(&self, from: &str, to: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        if from.is_empty() {
+            return Err(JsValue::from_str(
+                "Add autocorrect rule failed: the text to replace is empty",
+            ));
+        }
+        this.inner.autocorrect_mut().add_rule(from, to);
+        Ok(())
+    }
+
+    /// Removes the autocorrect rule for `from`
+    ///
+    /// # Returns
+    /// `true` if there was such a rule
+    #[wasm_bindgen(js_name = removeAutocorrectRule)]
+    pub fn remove_autocorrect_rule(&self, from: &str) -> bool {
+        let mut this = self.state_mut();
+        this.inner.autocorrect_mut().remove_rule(from)
+    }
+
+    /// Turns link autodetection on or off
+    ///
+    /// Bare URLs starting with "http://", "https://" or "www." and email
+    /// addresses are turned into links when whitespace is typed after them
+    /// with `insertText` or when they are pasted. Linking is its own undo
+    /// step, so undoing right after it removes only the link. On by
+    /// default.
+    #[wasm_bindgen(js_name = setAutoLink)]
+    pub fn set_auto_link(&self, enabled: bool) {
+        let mut this = self.state_mut();
+        this.inner.set_auto_link_enabled(enabled);
+    }
+
+    /// Returns true if link autodetection is on
+    #[wasm_bindgen(js_name = isAutoLinkEnabled)]
+    pub fn is_auto_link_enabled(&self) -> bool {
+        let this = self.state();
+        this.inner.is_auto_link_enabled()
+    }
+
+    /// Turns every input rule on or off
+    ///
+    /// Input rules are Markdown-style shortcuts applied while typing: at
+    /// the start of a paragraph "# " makes a heading, "- " a bullet list
+    /// item, "> " a block quote and "``` " a code block, and typing the
+    /// closing "**" of "**text**" makes the text bold. Only single
+    /// characters passed to `insertText` trigger a rule, and each rule is
+    /// its own undo step. Off by default.
+    #[wasm_bindgen(js_name = setInputRules)]
+    pub fn set_input_rules(&self, enabled: bool) {
+        let mut this = self.state_mut();
+        this.inner.set_input_rules(if enabled {
+            InputRules::new()
+        } else {
+            InputRules::default()
+        });
+    }
+
+    /// Turns one input rule on or off
+    ///
+    /// # Arguments
+    /// * `rule` - "heading", "bulletList", "blockQuote", "codeBlock" or "bold"
+    /// * `enabled` - Whether the rule applies
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the rule is unknown
+    #[wasm_bindgen(js_name = setInputRule)]
+    pub fn set_input_rule(&self, rule: &str, enabled: bool) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let rule = parse_input_rule(rule)?;
+        this.inner.input_rules_mut().set_enabled(rule, enabled);
+        Ok(())
+    }
+
+    /// Returns true if an input rule is on
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the rule is unknown
+    #[wasm_bindgen(js_name = isInputRuleEnabled)]
+    pub fn is_input_rule_enabled(&self, rule: &str) -> Result<bool, JsValue> {
+        let this = self.try_state()?;
+        let rule = parse_input_rule(rule)?;
+        Ok(this.inner.input_rules().is_enabled(rule))
+    }
+
+    /// Gets which side text typed at the edge of a run of a format type
+    /// takes the format from
+    ///
+    /// Returns "inherit-left", "inherit-right" or "none"
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the format type is unknown
+    #[wasm_bindgen(js_name = getFormatAffinity)]
+    pub fn get_format_affinity(&self, format_type: &str) -> Result<String, JsValue> {
+        let this = self.try_state()?;
+        let format = parse_inline_format(format_type)?;
+        Ok(match this.inner.format_affinity(&format) {
+            FormatAffinity::Left => "inherit-left",
+            FormatAffinity::Right => "inherit-right",
+            FormatAffinity::Neither => "none",
+        }
+        .to_string())
+    }
+
+    /// Sets which side text typed at the edge of a run of a format type
+    /// takes the format from
+    ///
+    /// With "inherit-left" (the default) typing at the end of a run extends
+    /// it, with "inherit-right" typing at its start does, and with "none"
+    /// neither does. Text typed inside a run always gets its formats.
+    ///
+    /// # Arguments
+    /// * `format_type` - The format type, e.g. "bold" or "link"
+    /// * `affinity` - "inherit-left", "inherit-right" or "none"
+    ///
+    /// # Errors
+    /// Returns a JsValue error if the format type or affinity is unknown
+    #[wasm_bindgen(js_name = setFormatAffinity)]
+    pub fn set_format_affinity(&self, format_type: &str, affinity: &str) -> Result<(), JsValue> {
+        let mut this = self.try_state_mut()?;
+        let format = parse_inline_format(format_type)?;
+        let affinity = match affinity {
+            "inherit-left" => FormatAffinity::Left,
+            "inherit-right" => FormatAffinity::Right,
+            "none" => FormatAffinity::Neither,
+            _ => {
+                return Err(JsValue::from_str(&format!(
+                    "Unknown format affinity: {}",
+                    affinity
+                )));
+            }
+        };
+        this.inner.set_format_affinity(&format, affinity);
+        Ok(())
+    }
+
+    /// Clears all undo and redo history
+    ///
+    /// This immediately frees all memory used by the command history.
+    /// Useful after save operations or when memory is constrained.
+    ///
+    /// # Memory Impact
+    ///
+    /// Frees approximately 6.4KB plus text data for a full history of 100 commands.
+    /// The actual amount depends on the size of operations in the history.
+    ///
+    /// # Use Cases
+    ///
+    /// - After saving the document (user can reload if needed)
+    /// - When memory usage is high
+    /// - After major operations that you don't want to undo
+    /// - When switching between documents
+    ///
+    /// # Warning
+    ///
+    /// After calling this method, users will not be able to undo or redo
+    /// any previous operations. Use with caution.
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// // Clear history after save
+    /// function saveDocument(doc) {
+    ///   const json = doc.toJSON();
+    ///   localStorage.setItem('document', json);
+    ///   doc.clearHistory(); // Free memory
+    /// }
+    ///
+    /// // Clear history when memory is low
+    /// if (performance.memory.usedJSHeapSize > threshold) {
+    ///   doc.clearHistory();
+    /// }
+    /// ```
+    #[wasm_bindgen(js_name = clearHistory)]
+    pub fn clear_history(&self) {
+        let mut this = self.state_mut();
+        this.inner.clear_history();
+        this.notify_history();
+    }
+
+    /// Returns memory usage statistics for the document
+    ///
+    /// Provides detailed information about memory usage across all components
+    /// of the document. Useful for monitoring and optimizing memory usage.
+    ///
+    /// # Returns
+    ///
+    /// A JavaScript object with the following properties:
+    /// - `textLength`: Number of characters in the document
+    /// - `formatRuns`: Number of format runs (fewer is better)
+    /// - `blocks`: Number of block info entries
+    /// - `undoCommands`: Number of commands in undo stack
+    /// - `redoCommands`: Number of commands in redo stack
+    /// - `estimatedBytes`: Estimated total memory usage in bytes
+    /// - `estimatedKB`: Estimated total memory usage in kilobytes
+    ///
+    /// # Memory Breakdown
+    ///
+    /// The estimated memory includes:
+    /// - Base overhead: ~1KB
+    /// - Text storage: ~4 bytes per character + gap overhead
+    /// - Format storage: ~32 bytes per format run + ~16 bytes per block
+    /// - Command history: ~64 bytes per command + affected text
+    ///
+    /// # Example
+    ///
+    /// ```javascript
+    /// const stats = doc.getMemoryStats();
+    /// console.log(`Document: ${stats.textLength} chars`);
+    /// console.log(`Format runs: ${stats.formatRuns}`);
+    /// console.log(`Memory: ${stats.estimatedKB.toFixed(2)} KB`);
+    ///
+    /// // Monitor memory usage
+    /// if (stats.estimatedKB > 500) {
+    ///   console.warn('Large document, consider reducing history');
+    ///   doc.setHistoryLimit(50);
+    /// }
+    ///
+    /// // Check format efficiency
+    /// if (stats.formatRuns > stats.textLength / 10) {
+    ///   console.warn('Many format runs, document may be fragmented');
+    /// }
+    /// ```
+    #[wasm_bindgen(js_name = getMemoryStats)]
+    pub fn get_memory_stats(&self) -> JsValue {
+        let this = self.state();
+        let (text_length, run_count, block_count, undo_count, redo_count, total_bytes) =
+            this.inner.memory_stats();
+
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"textLength".into(), &text_length.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"formatRuns".into(), &run_count.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"blocks".into(), &block_count.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"undoCommands".into(), &undo_count.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"redoCommands".into(), &redo_count.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"estimatedBytes".into(), &total_bytes.into()).unwrap();
+        js_sys::Reflect::set(
+            &obj,
+            &"estimatedKB".into(),
+            &((total_bytes as f64) / 1024.0).into(),
+        )
+        .unwrap();
+
+        obj.into()
+    }
+
+    /// Returns how fragmented the inline formatting is
+    ///
+    /// # Returns
+    /// An object with:
+    /// - `formatRuns`: Number of format runs
+    /// - `runsPer1000Chars`: Format runs per 1000 characters of text
+    /// - `averageRunLength`: Average number of characters in a run
+    /// - `mergeEvents`: Number of times two runs have been merged into one
+    #[wasm_bindgen(js_name = getFragmentationStats)]
+    pub fn get_fragmentation_stats(&self) -> JsValue {
+        let this = self.state();
+        fragmentation_to_js(&this.inner.fragmentation_stats())
+    }
+
+    /// Merges touching format runs with identical formats
+    ///
+    /// Formatting looks the same afterwards, so nothing is added to the
+    /// undo history and no change event is sent.
+    ///
+    /// # Returns
+    /// The number of runs removed
+    #[wasm_bindgen(js_name = normalizeFormats)]
+    pub fn normalize_formats(&self) -> usize {
+        let mut this = self.state_mut();
+        let removed = this.inner.normalize_formats();
+        this.notify_fragmentation();
+        removed
+    }
+}
+
+// Implement Drop trait for automatic cleanup
+impl Drop for WasmDocument {
+    /// Automatically cleans up resources when the document is dropped
+    ///
+    /// This is called automatically by Rust when:
+    /// 1. The `free()` method is called explicitly
+    /// 2. The JavaScript garbage collector collects the object
+    /// 3. The document goes out of scope in Rust code
+    ///
+    /// The Drop implementation ensures that all resources are properly
+    /// released, including:
+    /// - Clearing the command history to free command objects
+    /// - Clearing event callbacks to release JavaScript function references
+    /// - Allowing the Document and its internal structures to be deallocated
+    fn drop(&mut self) {
+        // Clear event callbacks to release JavaScript function references
+        // This is important to avoid memory leaks in JavaScript
+        self.state.get_mut().callbacks.clear_all();
+
+        // The Document's Drop implementation (if any) will be called automatically
+        // The inner Document will be dropped, which will:
+        // - Drop the TextStorage (releasing the gap buffer)
+        // - Drop the FormatStorage (releasing format runs and blocks)
+        // - Drop the CommandHistory (releasing all commands in undo/redo stacks)
+        // - Drop the Selection
+    }
+}
+
+/// Helper function to convert search matches to an array of `{start, end}`
+/// objects
+fn matches_to_js(matches: &[Range]) -> JsValue {
+    let matches: Vec<JsValue> = matches
+        .iter()
+        .map(|range| {
+            let obj = js_sys::Object::new();
+            let normalized = range.normalize();
+            js_sys::Reflect::set(&obj, &"start".into(), &normalized.start_offset().into()).unwrap();
+            js_sys::Reflect::set(&obj, &"end".into(), &normalized.end_offset().into()).unwrap();
+            obj.into()
+        })
+        .collect();
+    js_sys::Array::from_iter(matches).into()
+}
+
+/// Helper function to parse inline format from string
+fn parse_inline_format(format_type: &str) -> Result<InlineFormat, JsValue> {
+    match format_type {
         "bold" => Ok(InlineFormat::Bold),
         "italic" => Ok(InlineFormat::Italic),
         "underline" => Ok(InlineFormat::Underline),
         "strikethrough" => Ok(InlineFormat::Strikethrough),
         "code" => Ok(InlineFormat::Code),
+        "highlight" => Ok(InlineFormat::Highlight),
+        "smallCaps" | "small-caps" => Ok(InlineFormat::SmallCaps),
+        "subscript" | "sub" => Ok(InlineFormat::Subscript),
+        "superscript" | "sup" => Ok(InlineFormat::Superscript),
+        "math" => Ok(InlineFormat::Math { display: false }),
+        "mathDisplay" | "math-display" => Ok(InlineFormat::Math { display: true }),
         // For removeFormat, we need to support link/textColor/backgroundColor
         // We'll use empty values as placeholders since we're removing them anyway
         "link" => Ok(InlineFormat::Link { url: String::new() }),
@@ -1472,649 +6382,2883 @@ fn parse_inline_format(format_type: &str) -> Result<InlineFormat, JsValue> {
             color: String::new(),
         }),
         _ => Err(JsValue::from_str(&format!(
-            "Unknown format type: {}",
-            format_type
+            "Unknown format type: {}",
+            format_type
+        ))),
+    }
+}
+
+/// Helper function to parse a format that carries a value
+fn parse_inline_format_with_value(format_type: &str, value: &str) -> Result<InlineFormat, JsValue> {
+    match format_type {
+        "link" => Ok(InlineFormat::Link {
+            url: value.to_string(),
+        }),
+        "textColor" | "text-color" => Ok(InlineFormat::TextColor {
+            color: value.to_string(),
+        }),
+        "backgroundColor" | "background-color" => Ok(InlineFormat::BackgroundColor {
+            color: value.to_string(),
+        }),
+        _ => Err(JsValue::from_str(&format!(
+            "Unknown format type with value: {}",
+            format_type
+        ))),
+    }
+}
+
+/// Helper function to parse a `{ type, value?, start, end }` format object
+fn parse_format_op(op: &JsValue) -> Result<(Range, InlineFormat), JsValue> {
+    let get = |key: &str| js_sys::Reflect::get(op, &JsValue::from_str(key));
+
+    let format_type = get("type")?
+        .as_string()
+        .ok_or_else(|| JsValue::from_str("missing string 'type'"))?;
+    let offset = |key: &str| -> Result<usize, JsValue> {
+        get(key)?
+            .as_f64()
+            .filter(|n| *n >= 0.0 && n.fract() == 0.0)
+            .map(|n| n as usize)
+            .ok_or_else(|| JsValue::from_str(&format!("'{}' must be a non-negative integer", key)))
+    };
+    let (start, end) = (offset("start")?, offset("end")?);
+    let format = parse_format_entry(&format_type, get("value")?.as_string())?;
+
+    Ok((Range::from_offsets(start, end), format))
+}
+
+/// Helper function to parse a format name with an optional value, requiring
+/// the value for formats that carry one
+fn parse_format_entry(format_type: &str, value: Option<String>) -> Result<InlineFormat, JsValue> {
+    match value {
+        Some(value) => parse_inline_format_with_value(format_type, &value),
+        None => match parse_inline_format(format_type)? {
+            InlineFormat::Link { .. }
+            | InlineFormat::TextColor { .. }
+            | InlineFormat::BackgroundColor { .. } => Err(JsValue::from_str(&format!(
+                "Format type {} requires a value",
+                format_type
+            ))),
+            format => Ok(format),
+        },
+    }
+}
+
+/// Helper function to parse a format given as a name or a `{ type, value }`
+/// object
+fn parse_format_js(entry: &JsValue) -> Result<InlineFormat, JsValue> {
+    match entry.as_string() {
+        Some(format_type) => parse_format_entry(&format_type, None),
+        None if entry.is_object() => {
+            let format_type = js_sys::Reflect::get(entry, &"type".into())?
+                .as_string()
+                .ok_or_else(|| JsValue::from_str("format entry missing string 'type'"))?;
+            let value = js_sys::Reflect::get(entry, &"value".into())?.as_string();
+            parse_format_entry(&format_type, value)
+        }
+        None => Err(JsValue::from_str(
+            "format entries must be names or { type, value } objects",
+        )),
+    }
+}
+
+/// Helper function to convert a format to a `{ type, value? }` object
+fn format_entry_to_js(format: &InlineFormat) -> JsValue {
+    let obj = js_sys::Object::new();
+    js_sys::Reflect::set(&obj, &"type".into(), &format_to_js_value(format)).unwrap();
+    if let InlineFormat::Link { url: value }
+    | InlineFormat::TextColor { color: value }
+    | InlineFormat::BackgroundColor { color: value } = format
+    {
+        js_sys::Reflect::set(&obj, &"value".into(), &value.into()).unwrap();
+    }
+    obj.into()
+}
+
+/// Helper function to parse a `{ text, formats?, blockType? }` segment
+fn parse_segment(segment: &JsValue) -> Result<FormattedSegment, JsValue> {
+    let get = |key: &str| js_sys::Reflect::get(segment, &JsValue::from_str(key));
+
+    let text = get("text")?
+        .as_string()
+        .ok_or_else(|| JsValue::from_str("missing string 'text'"))?;
+    let mut result = FormattedSegment::new(text);
+
+    let formats = get("formats")?;
+    if !formats.is_undefined() && !formats.is_null() {
+        if !js_sys::Array::is_array(&formats) {
+            return Err(JsValue::from_str("'formats' must be an array"));
+        }
+        for entry in js_sys::Array::from(&formats).iter() {
+            result = result.with_format(parse_format_js(&entry)?);
+        }
+    }
+
+    if let Some(block_type) = get("blockType")?.as_string() {
+        result = result.with_block_type(parse_block_type(&block_type)?);
+    }
+    Ok(result)
+}
+
+/// Parses a `{ id, start, end }` transient decoration
+fn parse_transient_decoration(entry: &JsValue) -> Result<(Range, String), JsValue> {
+    let get = |key: &str| js_sys::Reflect::get(entry, &JsValue::from_str(key));
+
+    let id = get("id")?
+        .as_string()
+        .ok_or_else(|| JsValue::from_str("missing string 'id'"))?;
+    let start = required_count(&get("start")?, "start")?;
+    let end = required_count(&get("end")?, "end")?;
+    Ok((Range::from_offsets(start, end), id))
+}
+
+/// Helper function to convert InlineFormat to JsValue
+fn format_to_js_value(format: &InlineFormat) -> JsValue {
+    match format {
+        InlineFormat::Bold => JsValue::from_str("bold"),
+        InlineFormat::Italic => JsValue::from_str("italic"),
+        InlineFormat::Underline => JsValue::from_str("underline"),
+        InlineFormat::Strikethrough => JsValue::from_str("strikethrough"),
+        InlineFormat::Code => JsValue::from_str("code"),
+        InlineFormat::Link { .. } => JsValue::from_str("link"),
+        InlineFormat::TextColor { .. } => JsValue::from_str("textColor"),
+        InlineFormat::BackgroundColor { .. } => JsValue::from_str("backgroundColor"),
+        InlineFormat::Highlight => JsValue::from_str("highlight"),
+        InlineFormat::SmallCaps => JsValue::from_str("smallCaps"),
+        InlineFormat::Subscript => JsValue::from_str("subscript"),
+        InlineFormat::Superscript => JsValue::from_str("superscript"),
+        InlineFormat::Math { display: false } => JsValue::from_str("math"),
+        InlineFormat::Math { display: true } => JsValue::from_str("mathDisplay"),
+    }
+}
+
+/// Helper function to parse block type from string
+fn parse_block_type(block_type: &str) -> Result<BlockType, JsValue> {
+    match block_type {
+        "paragraph" => Ok(BlockType::Paragraph),
+        // Support both old and new naming conventions
+        "heading1" | "h1" => Ok(BlockType::heading(1)),
+        "heading2" | "h2" => Ok(BlockType::heading(2)),
+        "heading3" | "h3" => Ok(BlockType::heading(3)),
+        "heading4" | "h4" => Ok(BlockType::heading(4)),
+        "heading5" | "h5" => Ok(BlockType::heading(5)),
+        "heading6" | "h6" => Ok(BlockType::heading(6)),
+        "bulletList" | "unordered-list" => Ok(BlockType::BulletList),
+        "numberedList" | "ordered-list" => Ok(BlockType::NumberedList),
+        "blockQuote" | "blockquote" => Ok(BlockType::block_quote()),
+        "codeBlock" | "code-block" => Ok(BlockType::code_block()),
+        _ => Err(JsValue::from_str(&format!(
+            "Unknown block type: {}",
+            block_type
+        ))),
+    }
+}
+
+/// Helper function to parse an input rule from its name
+fn parse_input_rule(rule: &str) -> Result<InputRule, JsValue> {
+    match rule {
+        "heading" => Ok(InputRule::Heading),
+        "bulletList" => Ok(InputRule::BulletList),
+        "blockQuote" => Ok(InputRule::BlockQuote),
+        "codeBlock" => Ok(InputRule::CodeBlock),
+        "bold" => Ok(InputRule::Bold),
+        _ => Err(JsValue::from_str(&format!("Unknown input rule: {}", rule))),
+    }
+}
+
+/// Helper function to parse a list style from its CSS name or a bullet character
+fn parse_list_style(style: &str) -> Result<ListStyle, JsValue> {
+    let mut chars = style.chars();
+    match (style, chars.next(), chars.next()) {
+        ("decimal", _, _) => Ok(ListStyle::Decimal),
+        ("lower-alpha", _, _) => Ok(ListStyle::LowerAlpha),
+        ("upper-alpha", _, _) => Ok(ListStyle::UpperAlpha),
+        ("lower-roman", _, _) => Ok(ListStyle::LowerRoman),
+        ("upper-roman", _, _) => Ok(ListStyle::UpperRoman),
+        (_, Some(bullet), None) if !bullet.is_whitespace() && !bullet.is_control() => {
+            Ok(ListStyle::Bullet(bullet))
+        }
+        _ => Err(JsValue::from_str(&format!("Unknown list style: {}", style))),
+    }
+}
+
+/// Helper function to convert the result of an edit check to a JS object
+fn edit_check_to_js(result: Result<Range, CommandError>) -> JsValue {
+    let obj = js_sys::Object::new();
+    match result {
+        Ok(range) => {
+            js_sys::Reflect::set(&obj, &"ok".into(), &true.into()).unwrap();
+            js_sys::Reflect::set(&obj, &"start".into(), &range.start_offset().into()).unwrap();
+            js_sys::Reflect::set(&obj, &"end".into(), &range.end_offset().into()).unwrap();
+        }
+        Err(e) => {
+            let reason = match e {
+                CommandError::ReadOnly => "readOnly",
+                CommandError::InvalidPosition { .. } => "invalidPosition",
+                CommandError::InvalidRange { .. } => "invalidRange",
+                CommandError::MaxLengthExceeded { .. } => "maxLength",
+                _ => "other",
+            };
+            js_sys::Reflect::set(&obj, &"ok".into(), &false.into()).unwrap();
+            js_sys::Reflect::set(&obj, &"reason".into(), &reason.into()).unwrap();
+            js_sys::Reflect::set(&obj, &"message".into(), &e.to_string().into()).unwrap();
+        }
+    }
+    obj.into()
+}
+
+/// Word segmenter backed by a JavaScript callback
+///
+/// The scripts it is asked to split are all in the Basic Multilingual
+/// Plane, so the UTF-16 offsets JavaScript returns are character offsets.
+struct JsWordSegmenter(js_sys::Function);
+
+impl WordSegmenter for JsWordSegmenter {
+    fn segment(&self, text: &str) -> Vec<usize> {
+        match self.0.call1(&JsValue::NULL, &JsValue::from_str(text)) {
+            Ok(result) if js_sys::Array::is_array(&result) => js_sys::Array::from(&result)
+                .iter()
+                .filter_map(|offset| offset.as_f64())
+                .filter(|offset| *offset >= 0.0)
+                .map(|offset| offset as usize)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Helper function to read a spellcheck dictionary: a callback, an array
+/// of words, or null for none
+fn dictionary_from_js(dictionary: &JsValue) -> Result<Option<Box<dyn Dictionary>>, JsValue> {
+    if dictionary.is_null() || dictionary.is_undefined() {
+        return Ok(None);
+    }
+    if let Some(callback) = dictionary.dyn_ref::<js_sys::Function>() {
+        return Ok(Some(Box::new(JsDictionary(callback.clone()))));
+    }
+    if !js_sys::Array::is_array(dictionary) {
+        return Err(JsValue::from_str(
+            "Dictionary must be a function, an array of words or null",
+        ));
+    }
+    let words = js_sys::Array::from(dictionary)
+        .iter()
+        .map(|word| {
+            word.as_string()
+                .ok_or_else(|| JsValue::from_str("Dictionary words must be strings"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Some(Box::new(WordList::new(words))))
+}
+
+/// Spellcheck dictionary backed by a JavaScript callback
+///
+/// A callback that throws treats the word as correctly spelled.
+struct JsDictionary(js_sys::Function);
+
+impl Dictionary for JsDictionary {
+    fn contains(&self, word: &str) -> bool {
+        self.0
+            .call1(&JsValue::NULL, &JsValue::from_str(word))
+            .map_or(true, |result| result.is_truthy())
+    }
+}
+
+/// Helper function to parse a sticky position bias from string
+fn parse_bias(bias: &str) -> Result<Bias, JsValue> {
+    match bias {
+        "left" => Ok(Bias::Left),
+        "right" => Ok(Bias::Right),
+        _ => Err(JsValue::from_str(&format!("Unknown bias: {}", bias))),
+    }
+}
+
+/// Helper function to parse a text direction from string
+fn parse_text_direction(direction: &str) -> Result<Option<TextDirection>, JsValue> {
+    match direction {
+        "ltr" => Ok(Some(TextDirection::Ltr)),
+        "rtl" => Ok(Some(TextDirection::Rtl)),
+        "auto" => Ok(None),
+        _ => Err(JsValue::from_str(&format!(
+            "Unknown text direction: {}",
+            direction
+        ))),
+    }
+}
+
+/// Helper function to parse a text storage backend from string
+fn parse_storage_backend(backend: &str) -> Result<StorageBackend, JsValue> {
+    match backend {
+        "gapBuffer" | "gap-buffer" => Ok(StorageBackend::GapBuffer),
+        "rope" => Ok(StorageBackend::Rope),
+        _ => Err(JsValue::from_str(&format!(
+            "Unknown storage backend: {}",
+            backend
+        ))),
+    }
+}
+
+/// Helper function to parse the initial content passed to the constructor
+fn parse_initial_content(
+    content: &str,
+    format: &str,
+    sanitizer: Option<&HtmlSanitizer>,
+) -> Result<Document, JsValue> {
+    let format = match format {
+        "auto" => {
+            let trimmed = content.trim_start();
+            if trimmed.starts_with('{') && Document::from_json(content).is_ok() {
+                "json"
+            } else if trimmed.starts_with('<') {
+                "html"
+            } else {
+                "text"
+            }
+        }
+        other => other,
+    };
+
+    match format {
+        "text" => Ok(Document::from_text(content)),
+        "json" => Document::from_json(content)
+            .map_err(|e| JsValue::from_str(&format!("JSON parsing failed: {}", e))),
+        "html" => {
+            let default_sanitizer = HtmlSanitizer::new();
+            Document::from_html_with_sanitizer(content, sanitizer.unwrap_or(&default_sanitizer))
+                .map_err(|e| JsValue::from_str(&format!("HTML parsing failed: {}", e)))
+        }
+        "markdown" => Document::from_markdown(content)
+            .map_err(|e| JsValue::from_str(&format!("Markdown parsing failed: {}", e))),
+        _ => Err(JsValue::from_str(&format!(
+            "Unknown content format: {}",
+            format
         ))),
     }
-}
+}
+
+/// Helper function to parse the sanitizer overrides passed to the constructor
+fn parse_sanitizer_options(value: &JsValue) -> Result<Option<HtmlSanitizer>, JsValue> {
+    if value.is_undefined() || value.is_null() {
+        return Ok(None);
+    }
+
+    let mut sanitizer = HtmlSanitizer::new();
+    for key in ["disallowedTags", "disallowedAttributes"] {
+        let list = js_sys::Reflect::get(value, &JsValue::from_str(key))?;
+        if list.is_undefined() {
+            continue;
+        }
+        if !js_sys::Array::is_array(&list) {
+            return Err(JsValue::from_str(&format!(
+                "'sanitizer.{}' must be an array",
+                key
+            )));
+        }
+        for name in js_sys::Array::from(&list).iter() {
+            let name = name.as_string().ok_or_else(|| {
+                JsValue::from_str(&format!("'sanitizer.{}' must contain strings", key))
+            })?;
+            sanitizer = if key == "disallowedTags" {
+                sanitizer.without_tag(&name)
+            } else {
+                sanitizer.without_attribute(&name)
+            };
+        }
+    }
+    Ok(Some(sanitizer))
+}
+
+/// Helper function to parse a sanitizer policy given as JSON
+fn parse_sanitizer_policy(policy_json: &str) -> Result<HtmlSanitizer, JsValue> {
+    let invalid =
+        |message: String| JsValue::from_str(&format!("Invalid sanitizer policy: {}", message));
+    let policy: serde_json::Map<String, serde_json::Value> = serde_json::from_str(policy_json)
+        .map_err(|_| invalid("expected a JSON object".to_string()))?;
+
+    let mut sanitizer = HtmlSanitizer::new();
+    for (key, value) in policy {
+        let names = value
+            .as_array()
+            .and_then(|names| {
+                names
+                    .iter()
+                    .map(|name| name.as_str())
+                    .collect::<Option<Vec<_>>>()
+            })
+            .ok_or_else(|| invalid(format!("'{}' must be an array of strings", key)))?;
+        for name in names {
+            sanitizer = match key.as_str() {
+                "allowTags" => sanitizer.with_tag(name),
+                "removeTags" => sanitizer.without_tag(name),
+                "allowAttributes" => sanitizer.with_attribute(name),
+                "removeAttributes" => sanitizer.without_attribute(name),
+                "allowUrlSchemes" => sanitizer.with_url_scheme(name),
+                "removeUrlSchemes" => sanitizer.without_url_scheme(name),
+                "allowCssProperties" => sanitizer.with_css_property(name),
+                "removeCssProperties" => sanitizer.without_css_property(name),
+                _ => return Err(invalid(format!("unknown key '{}'", key))),
+            };
+        }
+    }
+    Ok(sanitizer)
+}
+
+/// Helper function to read an optional string option
+fn optional_string(value: &JsValue, key: &str) -> Result<Option<String>, JsValue> {
+    if value.is_undefined() || value.is_null() {
+        return Ok(None);
+    }
+    value
+        .as_string()
+        .map(Some)
+        .ok_or_else(|| JsValue::from_str(&format!("'{}' must be a string", key)))
+}
+
+/// Helper function to read an optional boolean option
+fn optional_bool(value: &JsValue, key: &str) -> Result<Option<bool>, JsValue> {
+    if value.is_undefined() || value.is_null() {
+        return Ok(None);
+    }
+    value
+        .as_bool()
+        .map(Some)
+        .ok_or_else(|| JsValue::from_str(&format!("'{}' must be a boolean", key)))
+}
+
+/// Helper function to read an optional non-negative integer option
+fn optional_count(value: &JsValue, key: &str) -> Result<Option<usize>, JsValue> {
+    if value.is_undefined() || value.is_null() {
+        return Ok(None);
+    }
+    value
+        .as_f64()
+        .filter(|n| *n >= 0.0 && n.fract() == 0.0)
+        .map(|n| Some(n as usize))
+        .ok_or_else(|| JsValue::from_str(&format!("'{}' must be a non-negative integer", key)))
+}
+
+/// Helper function to read a count that must be present
+fn required_count(value: &JsValue, key: &str) -> Result<usize, JsValue> {
+    optional_count(value, key)?.ok_or_else(|| JsValue::from_str(&format!("missing '{}'", key)))
+}
+
+/// Helper function to read import limits, starting from the safe defaults
+fn import_limits_from_js(limits: &JsValue) -> Result<ImportLimits, JsValue> {
+    let mut result = ImportLimits::safe();
+    if limits.is_undefined() || limits.is_null() {
+        return Ok(result);
+    }
+    if !limits.is_object() {
+        return Err(JsValue::from_str(
+            "Invalid import limits: expected an object",
+        ));
+    }
+    let get = |key: &str| js_sys::Reflect::get(limits, &JsValue::from_str(key));
+    if let Some(max) = optional_count(&get("maxTokens")?, "maxTokens")? {
+        result = result.max_tokens(Some(max));
+    }
+    if let Some(max) = optional_count(&get("maxDepth")?, "maxDepth")? {
+        result = result.max_depth(Some(max));
+    }
+    if let Some(max) = optional_count(&get("maxOutputSize")?, "maxOutputSize")? {
+        result = result.max_output_size(Some(max));
+    }
+    Ok(result)
+}
+
+/// Helper function to read Markdown export options from a JSON object
+fn markdown_options_from_json(json: Option<&str>) -> Result<MarkdownOptions, JsValue> {
+    let options = match json.map(str::trim) {
+        None | Some("") => return Ok(MarkdownOptions::new()),
+        Some(json) => js_sys::JSON::parse(json)
+            .map_err(|_| JsValue::from_str("Invalid Markdown options: not valid JSON"))?,
+    };
+    if !options.is_object() {
+        return Err(JsValue::from_str(
+            "Invalid Markdown options: expected a JSON object",
+        ));
+    }
+    let get = |key: &str| js_sys::Reflect::get(&options, &key.into()).unwrap_or_default();
+    let invalid = |key: &str, value: &str| {
+        JsValue::from_str(&format!("Invalid Markdown option '{}': '{}'", key, value))
+    };
+
+    let mut result = MarkdownOptions::new();
+    if let Some(gfm) = optional_bool(&get("gfm"), "gfm")? {
+        result = result.gfm(gfm);
+    }
+    if let Some(strikethrough) = optional_bool(&get("strikethrough"), "strikethrough")? {
+        result = result.strikethrough(strikethrough);
+    }
+    if let Some(highlight) = optional_bool(&get("highlight"), "highlight")? {
+        result = result.highlight(highlight);
+    }
+    if let Some(math) = optional_bool(&get("math"), "math")? {
+        result = result.math(math);
+    }
+    if let Some(bullet) = optional_string(&get("bullet"), "bullet")? {
+        result = result.bullet(match bullet.as_str() {
+            "-" => BulletMarker::Dash,
+            "*" => BulletMarker::Asterisk,
+            "+" => BulletMarker::Plus,
+            _ => return Err(invalid("bullet", &bullet)),
+        });
+    }
+    if let Some(emphasis) = optional_string(&get("emphasis"), "emphasis")? {
+        result = result.emphasis(match emphasis.as_str() {
+            "*" => EmphasisMarker::Asterisk,
+            "_" => EmphasisMarker::Underscore,
+            _ => return Err(invalid("emphasis", &emphasis)),
+        });
+    }
+    if let Some(style) = optional_string(&get("headingStyle"), "headingStyle")? {
+        result = result.heading_style(match style.as_str() {
+            "atx" => HeadingStyle::Atx,
+            "setext" => HeadingStyle::Setext,
+            _ => return Err(invalid("headingStyle", &style)),
+        });
+    }
+    if let Some(style) = optional_string(&get("hardBreak"), "hardBreak")? {
+        result = result.hard_break(match style.as_str() {
+            "backslash" => HardBreakStyle::Backslash,
+            "spaces" => HardBreakStyle::Spaces,
+            _ => return Err(invalid("hardBreak", &style)),
+        });
+    }
+    Ok(result)
+}
+
+/// Helper function to turn a missing option into an error
+fn required<T>(key: &str, value: Result<Option<T>, JsValue>) -> Result<T, JsValue> {
+    value?.ok_or_else(|| JsValue::from_str(&format!("'{}' is required", key)))
+}
+
+/// Helper function to convert BlockType to string
+/// Converts a Markdown source map to an array of line mapping objects
+fn markdown_source_map_to_js(map: &MarkdownSourceMap) -> js_sys::Array {
+    let lines = map.mappings().iter().map(|line| {
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"modelStart".into(), &line.model_start.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"modelEnd".into(), &line.model_end.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"sourceLine".into(), &line.source_line.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"sourceColumn".into(), &line.source_column.into()).unwrap();
+        JsValue::from(obj)
+    });
+    js_sys::Array::from_iter(lines)
+}
+
+fn list_style_to_string(style: &ListStyle) -> String {
+    match style {
+        ListStyle::Bullet(bullet) => bullet.to_string(),
+        _ => style.css_value(),
+    }
+}
+
+fn block_type_to_string(block_type: &BlockType) -> String {
+    match block_type {
+        BlockType::Paragraph => "paragraph".to_string(),
+        // Use short format for consistency with tests
+        BlockType::Heading { level } => format!("h{}", level),
+        BlockType::BulletList => "unordered-list".to_string(),
+        BlockType::NumberedList => "ordered-list".to_string(),
+        BlockType::BlockQuote { .. } => "blockquote".to_string(),
+        BlockType::CodeBlock { .. } => "code-block".to_string(),
+    }
+}
+
+/// Applies the operations in `steps` from `index` on, reporting each to
+/// `on_step`
+///
+/// With a delay, one step is applied and the next is scheduled on a timer.
+fn replay_steps(
+    document: JsValue,
+    steps: Vec<String>,
+    mut index: usize,
+    delay: Option<i32>,
+    on_step: Option<js_sys::Function>,
+) -> Result<(), JsValue> {
+    let total = steps.len();
+    while index < total {
+        let handle: &DocumentHandle = document.unchecked_ref();
+        handle.apply_operation(&steps[index])?;
+        if let Some(on_step) = &on_step {
+            let operation = js_sys::JSON::parse(&steps[index])?;
+            let info = replay_step_info(index, total, "operation", &operation);
+            if on_step.call1(&JsValue::NULL, &info)? == JsValue::FALSE {
+                return Ok(());
+            }
+        }
+        index += 1;
+
+        if let Some(delay) = delay
+            && index < total
+        {
+            let callback = Closure::once_into_js(move || {
+                let on_step_for_error = on_step.clone();
+                if let Err(error) = replay_steps(document, steps, index, Some(delay), on_step)
+                    && let Some(on_step) = on_step_for_error
+                {
+                    let info = replay_step_info(index, total, "error", &error);
+                    let _ = on_step.call1(&JsValue::NULL, &info);
+                }
+            });
+            set_timeout(&callback, delay);
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Builds the `{ index, total, ... }` object passed to a replay's `onStep`
+fn replay_step_info(index: usize, total: usize, key: &str, value: &JsValue) -> js_sys::Object {
+    let info = js_sys::Object::new();
+    js_sys::Reflect::set(&info, &"index".into(), &JsValue::from(index)).unwrap();
+    js_sys::Reflect::set(&info, &"total".into(), &JsValue::from(total)).unwrap();
+    js_sys::Reflect::set(&info, &key.into(), value).unwrap();
+    info
+}
+
+#[wasm_bindgen]
+extern "C" {
+    /// A `WasmDocument` reached through JavaScript, so its methods can be
+    /// called without holding a Rust borrow
+    type DocumentHandle;
+
+    #[wasm_bindgen(method, js_name = suspendEvents)]
+    fn suspend_events(this: &DocumentHandle);
+
+    #[wasm_bindgen(method, js_name = resumeEvents)]
+    fn resume_events(this: &DocumentHandle);
+
+    #[wasm_bindgen(method, catch, js_name = applyOperation)]
+    fn apply_operation(this: &DocumentHandle, json: &str) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = compactHistory)]
+    fn compact_history(
+        this: &DocumentHandle,
+        budget_ms: Option<f64>,
+        keep_recent: Option<usize>,
+    ) -> Result<bool, JsValue>;
+
+    /// The argument `requestIdleCallback` passes to its callback
+    type IdleDeadline;
+
+    #[wasm_bindgen(method, js_name = timeRemaining)]
+    fn time_remaining(this: &IdleDeadline) -> f64;
+
+    #[wasm_bindgen(catch, js_name = requestIdleCallback)]
+    fn request_idle_callback(callback: &JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(callback: &JsValue, delay: i32) -> JsValue;
+}
+
+/// Compaction budget in milliseconds when no idle deadline is available
+const FALLBACK_IDLE_BUDGET_MS: f64 = 5.0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    fn test_wasm_document_creation() {
+        let doc = WasmDocument::new();
+        assert_eq!(doc.get_content(), "");
+        assert_eq!(doc.get_length(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_document_from_text() {
+        let doc = WasmDocument::from_text("Hello World");
+        assert_eq!(doc.get_content(), "Hello World");
+        assert_eq!(doc.get_length(), 11);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_insert_text() {
+        let doc = WasmDocument::new();
+        assert!(doc.insert_text("Hello", 0).is_ok());
+        assert_eq!(doc.get_content(), "Hello");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_delete_range() {
+        let doc = WasmDocument::from_text("Hello World");
+        assert!(doc.delete_range(5, 11).is_ok());
+        assert_eq!(doc.get_content(), "Hello");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_replace_range() {
+        let doc = WasmDocument::from_text("Hello World");
+        assert!(doc.replace_range(6, 11, "Rust").is_ok());
+        assert_eq!(doc.get_content(), "Hello Rust");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_undo_redo() {
+        let doc = WasmDocument::new();
+        doc.insert_text("Hello", 0).unwrap();
+        assert_eq!(doc.get_content(), "Hello");
+
+        assert!(doc.can_undo());
+        assert!(!doc.can_redo());
+
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "");
+        assert!(doc.can_redo());
+
+        doc.redo().unwrap();
+        assert_eq!(doc.get_content(), "Hello");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_apply_format() {
+        let doc = WasmDocument::from_text("Hello");
+        assert!(doc.apply_format("bold", 0, 5).is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_toggle_format() {
+        let doc = WasmDocument::from_text("Hello");
+        // Toggle on
+        assert!(doc.toggle_format("bold", 0, 5).is_ok());
+        let formats = doc.get_formats_at(2);
+        let arr = js_sys::Array::from(&formats);
+        let contains_bold = arr
+            .iter()
+            .any(|v| v.as_string().map(|s| s == "bold").unwrap_or(false));
+        assert!(contains_bold);
+
+        // Toggle off
+        assert!(doc.toggle_format("bold", 0, 5).is_ok());
+        let formats2 = doc.get_formats_at(2);
+        let arr2 = js_sys::Array::from(&formats2);
+        let contains_bold2 = arr2
+            .iter()
+            .any(|v| v.as_string().map(|s| s == "bold").unwrap_or(false));
+        assert!(!contains_bold2);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_set_block_type() {
+        let doc = WasmDocument::from_text("Heading");
+        assert!(doc.set_block_type("heading1", 0, 7).is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_selection() {
+        let doc = WasmDocument::from_text("Hello World");
+        doc.set_selection(0, 5);
+        assert_eq!(doc.get_selected_text(), "Hello");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_select_all() {
+        let doc = WasmDocument::from_text("Hello World");
+        doc.select_all();
+        assert_eq!(doc.get_selected_text(), "Hello World");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_to_json() {
+        let doc = WasmDocument::from_text("Hello");
+        let json = doc.to_json(JsValue::UNDEFINED);
+        assert!(json.is_ok());
+        let json_str = json.unwrap();
+        assert!(json_str.contains("Hello"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_to_html_with_options() {
+        let doc = WasmDocument::from_text("Hello");
+        doc.apply_format("bold", 0, 5).unwrap();
+        let options = js_sys::Object::new();
+        js_sys::Reflect::set(&options, &"formatStyle".into(), &"inlineStyles".into()).unwrap();
+        js_sys::Reflect::set(&options, &"dataOffsets".into(), &true.into()).unwrap();
+        assert_eq!(
+            doc.to_html_with_options(options.clone().into()).unwrap(),
+            "<p data-offset=\"0\"><span style=\"font-weight: bold\">Hello</span></p>\n"
+        );
+
+        js_sys::Reflect::set(&options, &"formatStyle".into(), &"fancy".into()).unwrap();
+        assert!(doc.to_html_with_options(options.into()).is_err());
+        assert_eq!(
+            doc.to_html_with_options(JsValue::UNDEFINED).unwrap(),
+            doc.to_html()
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_decorations_and_extensions_round_trip() {
+        let doc = WasmDocument::from_text("teh cat");
+        doc.add_decoration(0, 3, "lint", Some(r#"{"message":"Typo"}"#.to_string()))
+            .unwrap();
+        assert!(
+            doc.add_decoration(0, 3, "lint", Some("[1]".to_string()))
+                .is_err()
+        );
+        assert!(doc.add_decoration(2, 2, "lint", None).is_err());
+        doc.set_json_extension("com.example.outline", r#"{"collapsed":[2]}"#)
+            .unwrap();
+
+        let json = doc.to_json(JsValue::UNDEFINED).unwrap();
+        let restored = WasmDocument::from_json(&json).unwrap();
+        assert_eq!(restored.get_decorations_at(1).length(), 1);
+        assert_eq!(
+            restored
+                .get_json_extension("com.example.outline")
+                .as_deref(),
+            Some(r#"{"collapsed":[2]}"#)
+        );
+        assert_eq!(restored.remove_decorations("lint", 0, 7), 1);
+        assert!(restored.remove_json_extension("com.example.outline"));
+        assert_eq!(restored.get_json_extension("com.example.outline"), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_code_block_language() {
+        let doc = WasmDocument::from_text("fn main() {}\nText");
+        doc.set_code_block_language(0, 12, Some("rust".to_string()));
+        assert_eq!(doc.get_block_type_at(0), "code-block");
+        assert_eq!(doc.get_code_block_language_at(3).as_deref(), Some("rust"));
+        assert_eq!(doc.get_code_block_language_at(14), None);
+        assert!(doc.to_markdown().starts_with("```rust\nfn main() {}\n```"));
+
+        let doc =
+            WasmDocument::from_html("<pre><code class=\"language-python\">x = 1</code></pre>")
+                .unwrap();
+        assert_eq!(doc.get_code_block_language_at(0).as_deref(), Some("python"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_math_format() {
+        let doc = WasmDocument::from_markdown("Area $\\pi r^2$").unwrap();
+        assert_eq!(doc.get_content(), "Area \\pi r^2");
+        let formats = doc.get_formats_at_strings(6);
+        assert_eq!(formats.get(0).as_string().as_deref(), Some("math"));
+
+        doc.apply_format("mathDisplay", 5, 12).unwrap();
+        assert_eq!(doc.to_markdown(), "Area $$\\pi r^2$$");
+        assert!(doc.to_html().contains("<span class=\"math math-display\">"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_from_json() {
+        let json = r#"{"version":"1.0","content":"Hello","formats":[],"blocks":[]}"#;
+        let doc = WasmDocument::from_json(json);
+        assert!(doc.is_ok());
+        let doc = doc.unwrap();
+        assert_eq!(doc.get_content(), "Hello");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_to_markdown() {
+        let doc = WasmDocument::from_text("Hello");
+        let md = doc.to_markdown();
+        assert_eq!(md, "Hello");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_from_markdown() {
+        let md = "**Hello**";
+        let doc = WasmDocument::from_markdown(md);
+        assert!(doc.is_ok());
+        let doc = doc.unwrap();
+        assert_eq!(doc.get_content(), "Hello");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_from_plain_text_smart() {
+        let doc = WasmDocument::from_plain_text_smart("Todo\n\n- milk\n1. eggs");
+        assert_eq!(doc.get_content(), "Todo\n\nmilk\neggs");
+        assert_eq!(doc.get_block_type_at(6), "unordered-list");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_paste_html() {
+        let doc = WasmDocument::new();
+        let html = "<p>Hello</p>";
+        assert!(doc.paste_html(html).is_ok());
+        assert_eq!(doc.get_content(), "Hello");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_to_plain_text() {
+        let doc = WasmDocument::from_text("Hello");
+        doc.apply_format("bold", 0, 5).unwrap();
+        assert_eq!(doc.to_plain_text(), "Hello");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_find() {
+        let doc = WasmDocument::from_text("Hello World Hello");
+        let result = doc.find("Hello", false, false, None);
+        assert!(result.is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_search_state() {
+        let doc = WasmDocument::from_text("Cat cat");
+        let found = doc.find_previous("cat", true, false, None).unwrap();
+        let start = js_sys::Reflect::get(&found, &"start".into()).unwrap();
+        assert_eq!(start.as_f64(), Some(4.0));
+
+        let state = doc.get_search_state();
+        let pattern = js_sys::Reflect::get(&state, &"pattern".into()).unwrap();
+        assert_eq!(pattern.as_string(), Some("cat".to_string()));
+        let current = js_sys::Reflect::get(&state, &"currentMatch".into()).unwrap();
+        assert_eq!(current.as_f64(), Some(0.0));
+
+        let toggle = js_sys::Object::new();
+        js_sys::Reflect::set(&toggle, &"caseSensitive".into(), &false.into()).unwrap();
+        doc.set_search_state(toggle.into()).unwrap();
+        let state = doc.get_search_state();
+        let current = js_sys::Reflect::get(&state, &"currentMatch".into()).unwrap();
+        assert!(current.is_null());
+        assert!(!doc.search_next().unwrap().is_null());
+        assert!(doc.set_search_state(JsValue::from_str("cat")).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_find_next_from_whole_words() {
+        let doc = WasmDocument::from_text("cat catch cat");
+        let state = js_sys::Object::new();
+        js_sys::Reflect::set(&state, &"pattern".into(), &"cat".into()).unwrap();
+        js_sys::Reflect::set(&state, &"wholeWord".into(), &true.into()).unwrap();
+        doc.set_search_state(state.into()).unwrap();
+
+        let found = doc.find_next_from(3, true, false).unwrap();
+        let start = js_sys::Reflect::get(&found, &"start".into()).unwrap();
+        assert_eq!(start.as_f64(), Some(10.0));
+        assert!(doc.find_next_from(13, true, false).unwrap().is_null());
+        let wrapped = doc.find_next_from(0, false, true).unwrap();
+        let start = js_sys::Reflect::get(&wrapped, &"start".into()).unwrap();
+        assert_eq!(start.as_f64(), Some(10.0));
+        assert_eq!(doc.get_selected_text(), "");
+
+        let state = doc.get_search_state();
+        let whole_word = js_sys::Reflect::get(&state, &"wholeWord".into()).unwrap();
+        assert_eq!(whole_word.as_bool(), Some(true));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_incremental_search() {
+        let doc = WasmDocument::from_text("cat cat cat");
+        assert!(doc.continue_search(None, None).is_err());
+        doc.start_search("cat", false, false, None, None).unwrap();
+
+        let batch = doc.continue_search(Some(2), None).unwrap();
+        let matches = js_sys::Reflect::get(&batch, &"matches".into()).unwrap();
+        assert_eq!(js_sys::Array::from(&matches).length(), 2);
+        let done = js_sys::Reflect::get(&batch, &"done".into()).unwrap();
+        assert_eq!(done.as_bool(), Some(false));
+
+        let batch = doc.continue_search(None, None).unwrap();
+        let count = js_sys::Reflect::get(&batch, &"count".into()).unwrap();
+        assert_eq!(count.as_f64(), Some(3.0));
+        let done = js_sys::Reflect::get(&batch, &"done".into()).unwrap();
+        assert_eq!(done.as_bool(), Some(true));
+        assert!(!doc.cancel_search());
+
+        // An edit ends the search
+        doc.start_search("cat", false, false, None, None).unwrap();
+        doc.insert_text("a", 0).unwrap();
+        assert!(doc.continue_search(None, None).is_err());
+        assert!(!doc.cancel_search());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_error_invalid_position() {
+        let doc = WasmDocument::from_text("Hello");
+        let result = doc.insert_text("World", 100);
+        assert!(result.is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_error_invalid_range() {
+        let doc = WasmDocument::from_text("Hello");
+        let result = doc.delete_range(10, 20);
+        assert!(result.is_err());
+    }
+
+    // Memory management tests
+    #[wasm_bindgen_test]
+    fn test_wasm_get_history_limit() {
+        let doc = WasmDocument::new();
+        assert_eq!(doc.get_history_limit(), 100);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_set_history_limit() {
+        let doc = WasmDocument::new();
+        doc.set_history_limit(50);
+        assert_eq!(doc.get_history_limit(), 50);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_undo_coalesce_window() {
+        let doc = WasmDocument::new();
+        assert_eq!(doc.get_undo_coalesce_window(), Some(500));
+
+        doc.set_undo_coalesce_window(Some(60_000));
+        doc.insert_text("a", 0).unwrap();
+        doc.insert_text("b", 1).unwrap();
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "");
+
+        doc.set_undo_coalesce_window(None);
+        assert_eq!(doc.get_undo_coalesce_window(), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_history_tree() {
+        let doc = WasmDocument::new();
+        doc.insert_text("one", 0).unwrap();
+        doc.undo().unwrap();
+        doc.insert_text("two", 0).unwrap();
+
+        let tree = doc.get_history_tree();
+        let nodes: js_sys::Array = js_sys::Reflect::get(&tree, &"nodes".into()).unwrap().into();
+        assert_eq!(nodes.length(), 3);
+        let root = nodes.get(0);
+        let children: js_sys::Array = js_sys::Reflect::get(&root, &"children".into())
+            .unwrap()
+            .into();
+        assert_eq!(children.length(), 2);
+
+        let one = children.get(0).as_f64().unwrap() as u32;
+        doc.undo_to_node(one).unwrap();
+        assert_eq!(doc.get_content(), "one");
+        assert!(doc.undo_to_node(99).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_on_history_change() {
+        let doc = WasmDocument::new();
+        let log = js_sys::Array::new();
+        let func = js_sys::Function::new_with_args("state", "this.push(state.undoDescription);");
+        doc.on_history_change(func.bind0(&log));
+
+        doc.insert_text("Hi", 0).unwrap();
+        doc.apply_format("bold", 0, 2).unwrap();
+        doc.set_selection(0, 1);
+        doc.undo().unwrap();
+        assert_eq!(log.length(), 3);
+        assert_eq!(log.get(0).as_string().as_deref(), Some("typing"));
+        assert_eq!(log.get(1).as_string().as_deref(), Some("formatting"));
+        assert_eq!(log.get(2).as_string().as_deref(), Some("typing"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_on_fragmentation_warning() {
+        let doc = WasmDocument::from_text(&"a".repeat(1024));
+        let log = js_sys::Array::new();
+        let func = js_sys::Function::new_with_args("stats", "this.push(stats.formatRuns);");
+        doc.on_fragmentation_warning(func.bind0(&log));
+
+        for i in 0..300 {
+            doc.apply_format("bold", i * 2, i * 2 + 1).unwrap();
+        }
+        assert_eq!(log.length(), 1);
+        assert_eq!(log.get(0).as_f64(), Some(256.0));
+
+        doc.set_fragmentation_threshold(1000.0);
+        doc.set_fragmentation_threshold(100.0);
+        assert_eq!(log.length(), 2);
+        assert_eq!(doc.normalize_formats(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_on_duplicate_paste() {
+        let doc = WasmDocument::new();
+        let log = js_sys::Array::new();
+        let func = js_sys::Function::new_with_args("paste", "this.push(paste.offset);");
+        doc.on_duplicate_paste(func.bind0(&log));
+
+        doc.paste_plain_text("ab").unwrap();
+        doc.paste_plain_text("ab").unwrap();
+        assert_eq!(doc.get_content(), "abab");
+        assert_eq!(doc.get_paste_dedup_window(), None);
+
+        doc.set_paste_dedup_window(Some(60_000));
+        doc.paste_html("<b>cd</b>").unwrap();
+        doc.paste_html("<b>cd</b>").unwrap();
+        assert_eq!(doc.get_content(), "ababcd");
+        assert_eq!(log.length(), 1);
+        assert_eq!(log.get(0).as_f64(), Some(6.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_on_change_payload() {
+        let doc = WasmDocument::new();
+        let log = js_sys::Array::new();
+        let func = js_sys::Function::new_with_args("event", "this.push(event);");
+        doc.on_change(func.bind0(&log));
+
+        doc.insert_text("Hello", 0).unwrap();
+        doc.delete_range(1, 3).unwrap();
+        assert_eq!(log.length(), 2);
+        let get = |i: u32, key: &str| js_sys::Reflect::get(&log.get(i), &key.into()).unwrap();
+        assert_eq!(get(0, "type").as_string().as_deref(), Some("insert"));
+        assert_eq!(get(0, "insertedText").as_string().as_deref(), Some("Hello"));
+        assert_eq!(get(1, "type").as_string().as_deref(), Some("delete"));
+        assert_eq!(get(1, "deletedLength").as_f64(), Some(2.0));
+        assert_eq!(get(1, "version").as_f64(), Some(doc.get_version() as f64));
+
+        doc.suspend_events();
+        doc.insert_text("a", 0).unwrap();
+        doc.insert_text("b", 0).unwrap();
+        doc.resume_events();
+        assert_eq!(log.length(), 3);
+        assert_eq!(get(2, "type").as_string().as_deref(), Some("batch"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_microtask_event_batching() {
+        let doc = WasmDocument::from_text("one one one");
+        let log = js_sys::Array::new();
+        let func = js_sys::Function::new_with_args("event", "this.push(event);");
+        doc.on_change(func.bind0(&log));
+        doc.set_event_batching("microtask").unwrap();
+        assert_eq!(doc.get_event_batching(), "microtask");
+
+        let replaced = doc
+            .find_and_replace("one", "two", true, false, None)
+            .unwrap();
+        assert_eq!(replaced, 3);
+        assert_eq!(log.length(), 0);
+
+        doc.flush_events();
+        assert_eq!(log.length(), 1);
+        let event = log.get(0);
+        let get = |key: &str| js_sys::Reflect::get(&event, &key.into()).unwrap();
+        assert_eq!(get("type").as_string().as_deref(), Some("batch"));
+        assert_eq!(js_sys::Array::from(&get("changes")).length(), 3);
+        assert!(doc.set_event_batching("never").is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_snapshot_diff() {
+        let doc = WasmDocument::from_text("Hello World");
+        let saved = doc.snapshot();
+        doc.insert_text(",", 5).unwrap();
+        doc.apply_format("bold", 0, 5).unwrap();
+        let current = doc.snapshot();
+
+        assert_eq!(current.version(), doc.get_version());
+        assert_eq!(
+            WasmDocument::diff(&saved, &current).unwrap(),
+            r#"[{"type":"text","start":5,"end":5,"text":","},{"type":"format","start":0,"end":5,"formats":["Bold"]}]"#
+        );
+        assert_eq!(WasmDocument::diff(&current, &current).unwrap(), "[]");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_binary_round_trip() {
+        let doc = WasmDocument::from_text("Hello World");
+        doc.apply_format("bold", 0, 5).unwrap();
+        let json = doc.to_json(JsValue::UNDEFINED).unwrap();
+
+        let bytes = doc.to_binary(JsValue::UNDEFINED).unwrap();
+        let restored = WasmDocument::from_binary(&bytes).unwrap();
+        assert_eq!(restored.to_json(JsValue::UNDEFINED).unwrap(), json);
+
+        let migrated = WasmDocument::json_to_binary(&json).unwrap();
+        assert_eq!(migrated, bytes);
+        assert!(WasmDocument::from_binary(json.as_bytes()).is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_text_chunks() {
+        let doc = WasmDocument::from_text("Hello World");
+        assert!(doc.get_text_chunks(0).is_err());
+
+        let mut chunks = doc.get_text_chunks(5).unwrap();
+        assert_eq!(chunks.next_chunk().as_deref(), Some("Hello"));
+        assert_eq!(chunks.next_chunk().as_deref(), Some(" Worl"));
+        assert_eq!(chunks.next_chunk().as_deref(), Some("d"));
+        assert_eq!(chunks.next_chunk(), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_streaming_load() {
+        let doc = WasmDocument::from_text("Old");
+        assert!(doc.append_chunk("x").is_err());
+
+        doc.begin_streaming_load();
+        doc.append_chunk("Hello ").unwrap();
+        doc.append_chunk("World").unwrap();
+        assert_eq!(doc.get_content(), "Old");
+        doc.finish_streaming_load().unwrap();
+        assert_eq!(doc.get_content(), "Hello World");
+        assert!(doc.finish_streaming_load().is_err());
+
+        doc.begin_streaming_load();
+        doc.append_chunk("discarded").unwrap();
+        assert!(doc.abort_streaming_load());
+        assert_eq!(doc.get_content(), "Hello World");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_compact_history() {
+        let doc = WasmDocument::new();
+        doc.set_undo_coalesce_window(None);
+        for (i, c) in "abc".chars().enumerate() {
+            doc.insert_text(&c.to_string(), i).unwrap();
+        }
+
+        assert!(!doc.compact_history(None, Some(0)));
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "");
+        assert!(!doc.can_undo());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_execute_command() {
+        let doc = WasmDocument::new();
+        let args = |json: &str| Some(json.to_string());
+        doc.execute_command(
+            "insertText",
+            args(r#"{"text": "Hello world", "position": 0}"#),
+        )
+        .unwrap();
+        doc.execute_command(
+            "applyFormat",
+            args(r#"{"format": "bold", "start": 0, "end": 5}"#),
+        )
+        .unwrap();
+        doc.execute_command("setSelection", args(r#"{"anchor": 6, "focus": 11}"#))
+            .unwrap();
+        let replaced = doc
+            .execute_command(
+                "findAndReplace",
+                args(r#"{"pattern": "world", "replacement": "there"}"#),
+            )
+            .unwrap();
+
+        assert_eq!(replaced.as_f64(), Some(1.0));
+        assert_eq!(doc.get_content(), "Hello there");
+        assert!(doc.get_formats_at_strings(0).includes(&"bold".into(), 0));
+
+        doc.execute_command("undo", None).unwrap();
+        assert_eq!(doc.get_content(), "Hello world");
+        assert!(doc.execute_command("explode", None).is_err());
+        assert!(
+            doc.execute_command("deleteRange", args(r#"{"start": 0}"#))
+                .is_err()
+        );
+        assert!(doc.execute_command("insertText", args("[1, 2]")).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_clear_history() {
+        let doc = WasmDocument::new();
+        doc.insert_text("Hello", 0).unwrap();
+        doc.insert_text(" World", 5).unwrap();
+
+        assert!(doc.can_undo());
+
+        doc.clear_history();
+
+        assert!(!doc.can_undo());
+        assert!(!doc.can_redo());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_get_memory_stats() {
+        let doc = WasmDocument::from_text("Hello World");
+        doc.apply_format("bold", 0, 5).unwrap();
+
+        let stats = doc.get_memory_stats();
+        assert!(stats.is_object());
+
+        // Verify stats object has expected properties
+        let obj = js_sys::Object::from(stats);
+        assert!(js_sys::Reflect::has(&obj, &"textLength".into()).unwrap());
+        assert!(js_sys::Reflect::has(&obj, &"formatRuns".into()).unwrap());
+        assert!(js_sys::Reflect::has(&obj, &"estimatedBytes".into()).unwrap());
+    }
+
+    // Data marshalling tests
+    #[wasm_bindgen_test]
+    fn test_wasm_string_marshalling() {
+        let doc = WasmDocument::new();
+
+        // Test with various string types
+        doc.insert_text("ASCII", 0).unwrap();
+        assert_eq!(doc.get_content(), "ASCII");
+
+        doc.insert_text(" Unicode: 你好", 5).unwrap();
+        assert_eq!(doc.get_content(), "ASCII Unicode: 你好");
+
+        doc.insert_text(" Emoji: 🎉", 17).unwrap();
+        assert_eq!(doc.get_content(), "ASCII Unicode: 你好 Emoji: 🎉");
+    }
 
-/// Helper function to convert InlineFormat to JsValue
-fn format_to_js_value(format: &InlineFormat) -> JsValue {
-    match format {
-        InlineFormat::Bold => JsValue::from_str("bold"),
-        InlineFormat::Italic => JsValue::from_str("italic"),
-        InlineFormat::Underline => JsValue::from_str("underline"),
-        InlineFormat::Strikethrough => JsValue::from_str("strikethrough"),
-        InlineFormat::Code => JsValue::from_str("code"),
-        InlineFormat::Link { .. } => JsValue::from_str("link"),
-        InlineFormat::TextColor { .. } => JsValue::from_str("textColor"),
-        InlineFormat::BackgroundColor { .. } => JsValue::from_str("backgroundColor"),
+    #[wasm_bindgen_test]
+    fn test_wasm_number_marshalling() {
+        let doc = WasmDocument::from_text("Hello World");
+
+        // Test position and range parameters
+        assert!(doc.insert_text("!", 11).is_ok());
+        assert!(doc.delete_range(0, 5).is_ok());
+        assert_eq!(doc.get_content(), " World!");
     }
-}
 
-/// Helper function to parse block type from string
-fn parse_block_type(block_type: &str) -> Result<BlockType, JsValue> {
-    match block_type {
-        "paragraph" => Ok(BlockType::Paragraph),
-        // Support both old and new naming conventions
-        "heading1" | "h1" => Ok(BlockType::heading(1)),
-        "heading2" | "h2" => Ok(BlockType::heading(2)),
-        "heading3" | "h3" => Ok(BlockType::heading(3)),
-        "heading4" | "h4" => Ok(BlockType::heading(4)),
-        "heading5" | "h5" => Ok(BlockType::heading(5)),
-        "heading6" | "h6" => Ok(BlockType::heading(6)),
-        "bulletList" | "unordered-list" => Ok(BlockType::BulletList),
-        "numberedList" | "ordered-list" => Ok(BlockType::NumberedList),
-        "blockQuote" | "blockquote" => Ok(BlockType::BlockQuote),
-        "codeBlock" | "code-block" => Ok(BlockType::CodeBlock),
-        _ => Err(JsValue::from_str(&format!(
-            "Unknown block type: {}",
-            block_type
-        ))),
+    #[wasm_bindgen_test]
+    fn test_wasm_array_marshalling() {
+        let doc = WasmDocument::from_text("Hello World Hello");
+
+        // Test find returns array
+        let result = doc.find("Hello", false, false, None);
+        assert!(result.is_ok());
+
+        let matches = result.unwrap();
+        assert!(matches.is_array());
     }
-}
 
-/// Helper function to convert BlockType to string
-fn block_type_to_string(block_type: &BlockType) -> String {
-    match block_type {
-        BlockType::Paragraph => "paragraph".to_string(),
-        // Use short format for consistency with tests
-        BlockType::Heading { level } => format!("h{}", level),
-        BlockType::BulletList => "unordered-list".to_string(),
-        BlockType::NumberedList => "ordered-list".to_string(),
-        BlockType::BlockQuote => "blockquote".to_string(),
-        BlockType::CodeBlock => "code-block".to_string(),
+    #[wasm_bindgen_test]
+    fn test_wasm_object_marshalling() {
+        let doc = WasmDocument::from_text("Hello World");
+        doc.set_selection(0, 5);
+
+        // Test getSelection returns object
+        let selection = doc.get_selection();
+        assert!(selection.is_object());
+
+        let obj = js_sys::Object::from(selection);
+        assert!(js_sys::Reflect::has(&obj, &"anchor".into()).unwrap());
+        assert!(js_sys::Reflect::has(&obj, &"focus".into()).unwrap());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use wasm_bindgen_test::*;
+    // Format with value tests
+    #[wasm_bindgen_test]
+    fn test_wasm_apply_format_with_value_link() {
+        let doc = WasmDocument::from_text("Click here");
+        let result = doc.apply_format_with_value("link", "https://example.com", 0, 10);
+        assert!(result.is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_apply_format_with_value_color() {
+        let doc = WasmDocument::from_text("Colored text");
+        let result = doc.apply_format_with_value("textColor", "#FF0000", 0, 12);
+        assert!(result.is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_apply_format_with_value_background() {
+        let doc = WasmDocument::from_text("Highlighted");
+        let result = doc.apply_format_with_value("backgroundColor", "#FFFF00", 0, 11);
+        assert!(result.is_ok());
+    }
+
+    // Clipboard tests
+    #[wasm_bindgen_test]
+    fn test_wasm_copy() {
+        let doc = WasmDocument::from_text("Hello World");
+        doc.set_selection(0, 5);
+
+        let clipboard = doc.copy();
+        assert!(clipboard.is_object());
+
+        let obj = js_sys::Object::from(clipboard);
+        assert!(js_sys::Reflect::has(&obj, &"text".into()).unwrap());
+        let html = js_sys::Reflect::get(&obj, &"html".into()).unwrap();
+        assert!(html.as_string().unwrap().contains("data-rte-source"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_cut() {
+        let doc = WasmDocument::from_text("Hello World");
+        doc.set_selection(0, 5);
+
+        let result = doc.cut();
+        assert!(result.is_ok());
+
+        let clipboard = result.unwrap();
+        assert!(clipboard.is_object());
+
+        // Text should be removed
+        assert_eq!(doc.get_content(), " World");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_paste_plain_text() {
+        let doc = WasmDocument::new();
+        let result = doc.paste_plain_text("Hello World");
+        assert!(result.is_ok());
+        assert_eq!(doc.get_content(), "Hello World");
+    }
+
+    // IME composition tests
+    #[wasm_bindgen_test]
+    fn test_wasm_ime_composition() {
+        let doc = WasmDocument::from_text("Hello");
+
+        doc.set_selection(5, 5);
+        doc.start_composition();
+        assert!(doc.is_composing());
+
+        doc.update_composition("世");
+        assert_eq!(doc.get_content(), "Hello世");
+
+        doc.update_composition("世界");
+        assert_eq!(doc.get_content(), "Hello世界");
+
+        doc.end_composition();
+        assert!(!doc.is_composing());
+        assert_eq!(doc.get_content(), "Hello世界");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_ime_cancel_composition() {
+        let doc = WasmDocument::from_text("Hello");
+
+        doc.set_selection(5, 5);
+        doc.start_composition();
+        doc.update_composition("世界");
+
+        doc.cancel_composition();
+        assert!(!doc.is_composing());
+        assert_eq!(doc.get_content(), "Hello");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_get_composition_range() {
+        let doc = WasmDocument::from_text("Hello");
+
+        doc.set_selection(5, 5);
+        doc.start_composition();
+        doc.update_composition("世界");
+
+        let range = doc.get_composition_range();
+        assert!(!range.is_null());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_get_composition_text() {
+        let doc = WasmDocument::from_text("Hello");
+
+        doc.set_selection(5, 5);
+        doc.start_composition();
+        doc.update_composition("世界");
+
+        let text = doc.get_composition_text();
+        assert!(!text.is_null());
+    }
+
+    // Cursor movement tests
+    #[wasm_bindgen_test]
+    fn test_wasm_cursor_movement() {
+        let doc = WasmDocument::from_text("Hello World");
+
+        doc.set_selection(0, 0);
+        doc.move_cursor_right();
+
+        let sel = doc.get_selection();
+        let obj = js_sys::Object::from(sel);
+        let anchor = js_sys::Reflect::get(&obj, &"anchor".into()).unwrap();
+        assert_eq!(anchor.as_f64().unwrap() as usize, 1);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_move_by_word() {
+        let doc = WasmDocument::from_text("Hello World Test");
+
+        doc.set_selection(0, 0);
+        doc.move_by_word(true); // Move forward
+
+        let sel = doc.get_selection();
+        let obj = js_sys::Object::from(sel);
+        let anchor = js_sys::Reflect::get(&obj, &"anchor".into()).unwrap();
+        assert!(anchor.as_f64().unwrap() as usize > 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_delete_word() {
+        let doc = WasmDocument::from_text("can't stop now");
+
+        doc.set_selection(6, 6);
+        doc.delete_word_forward().unwrap();
+        assert_eq!(doc.get_content(), "can't now");
+
+        doc.set_selection(6, 6);
+        doc.delete_word_backward().unwrap();
+        assert_eq!(doc.get_content(), "now");
+    }
+
+    // Search and replace tests
+    #[wasm_bindgen_test]
+    fn test_wasm_find_and_replace() {
+        let doc = WasmDocument::from_text("Hello World Hello");
+        let result = doc.find_and_replace("Hello", "Hi", false, false, None);
+        assert!(result.is_ok());
+
+        let count = result.unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(doc.get_content(), "Hi World Hi");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_find_and_replace_ignoring_accents() {
+        let doc = WasmDocument::from_text("Résumé and resume");
+        let count = doc
+            .find_and_replace("resume", "CV", false, false, Some(true))
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(doc.get_content(), "CV and CV");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_find_and_replace_in_selection() {
+        let doc = WasmDocument::from_text("cat cat cat");
+        doc.set_selection(4, 11);
+
+        let matches: js_sys::Array = doc
+            .find_in_selection("cat", false, false, None)
+            .unwrap()
+            .into();
+        assert_eq!(matches.length(), 2);
+        let first = matches.get(0);
+        let start = js_sys::Reflect::get(&first, &"start".into()).unwrap();
+        assert_eq!(start.as_f64(), Some(4.0));
+
+        let count = doc
+            .replace_in_selection("cat", "dog", false, false, None)
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(doc.get_content(), "cat dog dog");
+        assert_eq!(doc.get_selected_text(), "dog dog");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_cleanup_document() {
+        let doc = WasmDocument::from_text("a  \n\n\n\nb");
+        let options = js_sys::Object::new();
+        js_sys::Reflect::set(&options, &"maxEmptyParagraphs".into(), &1.into()).unwrap();
+
+        assert!(doc.cleanup_document(options.into()).unwrap());
+        assert_eq!(doc.get_content(), "a\n\nb");
+        assert!(!doc.cleanup_document(JsValue::UNDEFINED).unwrap());
+    }
+
+    // Block type tests
+    #[wasm_bindgen_test]
+    fn test_wasm_get_block_type_at() {
+        let doc = WasmDocument::from_text("Heading");
+        doc.set_block_type("heading1", 0, 7).unwrap();
+
+        let block_type = doc.get_block_type_at(0);
+        assert_eq!(block_type, "h1");
+    }
+
+    // Format query tests
+    #[wasm_bindgen_test]
+    fn test_wasm_get_formats_at() {
+        let doc = WasmDocument::from_text("Hello");
+        doc.apply_format("bold", 0, 5).unwrap();
+        doc.apply_format("italic", 0, 5).unwrap();
 
+        let formats = doc.get_formats_at(2);
+        assert!(formats.is_array());
 
+        let arr = js_sys::Array::from(&formats);
+        assert_eq!(arr.length(), 2);
+    }
 
+    // Error handling tests
     #[wasm_bindgen_test]
-    fn test_wasm_document_creation() {
-        let doc = WasmDocument::new();
-        assert_eq!(doc.get_content(), "");
-        assert_eq!(doc.get_length(), 0);
+    fn test_wasm_error_messages() {
+        let doc = WasmDocument::from_text("Hello");
+
+        // Test invalid position error
+        let result = doc.insert_text("World", 100);
+        assert!(result.is_err());
+
+        let err = result.unwrap_err();
+        let err_str = err.as_string().unwrap();
+        assert!(err_str.contains("Insert failed"));
     }
 
+    // Serialization roundtrip tests
     #[wasm_bindgen_test]
-    fn test_wasm_document_from_text() {
+    fn test_wasm_json_roundtrip() {
         let doc = WasmDocument::from_text("Hello World");
-        assert_eq!(doc.get_content(), "Hello World");
-        assert_eq!(doc.get_length(), 11);
+        doc.apply_format("bold", 0, 5).unwrap();
+
+        let json = doc.to_json(JsValue::UNDEFINED).unwrap();
+        let doc2 = WasmDocument::from_json(&json).unwrap();
+
+        assert_eq!(doc2.get_content(), "Hello World");
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_insert_text() {
-        let mut doc = WasmDocument::new();
-        assert!(doc.insert_text("Hello", 0).is_ok());
-        assert_eq!(doc.get_content(), "Hello");
+    fn test_wasm_json_selection() {
+        let doc = WasmDocument::from_text("Hello World");
+        doc.set_selection(6, 11);
+
+        let options = js_sys::Object::new();
+        js_sys::Reflect::set(&options, &"includeSelection".into(), &true.into()).unwrap();
+        let json = doc.to_json(options.into()).unwrap();
+        let restored = WasmDocument::from_json(&json).unwrap();
+        assert_eq!(restored.get_selected_text(), "World");
+
+        let json = doc.to_json(JsValue::UNDEFINED).unwrap();
+        let restored = WasmDocument::from_json(&json).unwrap();
+        assert_eq!(restored.get_selected_text(), "");
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_delete_range() {
-        let mut doc = WasmDocument::from_text("Hello World");
-        assert!(doc.delete_range(5, 11).is_ok());
-        assert_eq!(doc.get_content(), "Hello");
+    fn test_wasm_insert_formatted_text() {
+        let doc = WasmDocument::from_text("!");
+        let link = js_sys::Object::new();
+        js_sys::Reflect::set(&link, &"type".into(), &"link".into()).unwrap();
+        js_sys::Reflect::set(&link, &"value".into(), &"https://example.com".into()).unwrap();
+
+        let title = js_sys::Object::new();
+        js_sys::Reflect::set(&title, &"text".into(), &"Title\n".into()).unwrap();
+        js_sys::Reflect::set(&title, &"blockType".into(), &"heading1".into()).unwrap();
+        let docs = js_sys::Object::new();
+        js_sys::Reflect::set(&docs, &"text".into(), &"docs".into()).unwrap();
+        let formats = js_sys::Array::of2(&"bold".into(), &link);
+        js_sys::Reflect::set(&docs, &"formats".into(), &formats).unwrap();
+
+        let range = doc
+            .insert_formatted_text(0, js_sys::Array::of2(&title, &docs))
+            .unwrap();
+        let end = js_sys::Reflect::get(&range, &"end".into()).unwrap();
+        assert_eq!(end.as_f64(), Some(10.0));
+        assert_eq!(doc.get_content(), "Title\ndocs!");
+        assert_eq!(doc.get_block_type_at(0), "heading1");
+        let formats = doc.get_formats_at(7);
+        assert_eq!(formats.length(), 2);
+
+        let bad = js_sys::Object::new();
+        js_sys::Reflect::set(&bad, &"text".into(), &"x".into()).unwrap();
+        let formats = js_sys::Array::of1(&"link".into());
+        js_sys::Reflect::set(&bad, &"formats".into(), &formats).unwrap();
+        let result = doc.insert_formatted_text(0, js_sys::Array::of1(&bad));
+        assert!(result.is_err());
+        assert_eq!(doc.get_content(), "Title\ndocs!");
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_replace_range() {
-        let mut doc = WasmDocument::from_text("Hello World");
-        assert!(doc.replace_range(6, 11, "Rust").is_ok());
-        assert_eq!(doc.get_content(), "Hello Rust");
+    fn test_wasm_html_export() {
+        let doc = WasmDocument::from_text("Hello");
+        doc.apply_format("bold", 0, 5).unwrap();
+
+        let html = doc.to_html();
+        assert!(html.contains("<strong>"));
+        assert!(html.contains("Hello"));
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_undo_redo() {
-        let mut doc = WasmDocument::new();
-        doc.insert_text("Hello", 0).unwrap();
-        assert_eq!(doc.get_content(), "Hello");
+    fn test_wasm_markdown_roundtrip() {
+        let md = "**Bold** and *italic*";
+        let doc = WasmDocument::from_markdown(md).unwrap();
 
-        assert!(doc.can_undo());
-        assert!(!doc.can_redo());
+        let content = doc.get_content();
+        assert!(content.contains("Bold"));
+        assert!(content.contains("italic"));
+    }
 
-        doc.undo().unwrap();
+    // Edge case tests
+    #[wasm_bindgen_test]
+    fn test_wasm_empty_document() {
+        let doc = WasmDocument::new();
+        assert_eq!(doc.get_length(), 0);
+        assert!(doc.is_empty());
         assert_eq!(doc.get_content(), "");
-        assert!(doc.can_redo());
-
-        doc.redo().unwrap();
-        assert_eq!(doc.get_content(), "Hello");
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_apply_format() {
-        let mut doc = WasmDocument::from_text("Hello");
-        assert!(doc.apply_format("bold", 0, 5).is_ok());
+    fn test_wasm_large_text() {
+        let doc = WasmDocument::new();
+        let large_text = "a".repeat(10000);
+
+        let result = doc.insert_text(&large_text, 0);
+        assert!(result.is_ok());
+        assert_eq!(doc.get_length(), 10000);
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_toggle_format() {
-        let mut doc = WasmDocument::from_text("Hello");
-        // Toggle on
-        assert!(doc.toggle_format("bold", 0, 5).is_ok());
-        let formats = doc.get_formats_at(2);
-        let arr = js_sys::Array::from(&formats);
-        let contains_bold = arr
-            .iter()
-            .any(|v| v.as_string().map(|s| s == "bold").unwrap_or(false));
-        assert!(contains_bold);
+    fn test_wasm_unicode_handling() {
+        let doc = WasmDocument::new();
 
-        // Toggle off
-        assert!(doc.toggle_format("bold", 0, 5).is_ok());
-        let formats2 = doc.get_formats_at(2);
-        let arr2 = js_sys::Array::from(&formats2);
-        let contains_bold2 = arr2
-            .iter()
-            .any(|v| v.as_string().map(|s| s == "bold").unwrap_or(false));
-        assert!(!contains_bold2);
+        // Test various unicode characters
+        doc.insert_text("Hello 世界 🌍 مرحبا", 0).unwrap();
+        assert!(doc.get_length() > 0);
+
+        let content = doc.get_content();
+        assert!(content.contains("世界"));
+        assert!(content.contains("🌍"));
+        assert!(content.contains("مرحبا"));
     }
 
+    // Document statistics tests
     #[wasm_bindgen_test]
-    fn test_wasm_set_block_type() {
-        let mut doc = WasmDocument::from_text("Heading");
-        assert!(doc.set_block_type("heading1", 0, 7).is_ok());
+    fn test_wasm_get_word_count_empty() {
+        let doc = WasmDocument::new();
+        assert_eq!(doc.get_word_count(), 0);
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_selection() {
-        let mut doc = WasmDocument::from_text("Hello World");
-        doc.set_selection(0, 5);
-        assert_eq!(doc.get_selected_text(), "Hello");
+    fn test_wasm_get_word_count_simple() {
+        let doc = WasmDocument::from_text("Hello world");
+        assert_eq!(doc.get_word_count(), 2);
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_select_all() {
-        let mut doc = WasmDocument::from_text("Hello World");
-        doc.select_all();
-        assert_eq!(doc.get_selected_text(), "Hello World");
+    fn test_wasm_get_word_count_multiple_spaces() {
+        let doc = WasmDocument::from_text("Hello   world   test");
+        assert_eq!(doc.get_word_count(), 3);
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_to_json() {
-        let doc = WasmDocument::from_text("Hello");
-        let json = doc.to_json();
-        assert!(json.is_ok());
-        let json_str = json.unwrap();
-        assert!(json_str.contains("Hello"));
+    fn test_wasm_get_word_count_with_newlines() {
+        let doc = WasmDocument::from_text("Hello\nworld\ntest");
+        assert_eq!(doc.get_word_count(), 3);
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_from_json() {
-        let json = r#"{"version":"1.0","content":"Hello","formats":[],"blocks":[]}"#;
-        let doc = WasmDocument::from_json(json);
-        assert!(doc.is_ok());
-        let doc = doc.unwrap();
+    fn test_wasm_can_insert_and_delete() {
+        let doc = WasmDocument::from_text("Hello");
+        let check = doc.can_insert_at(5, " world");
+        let get = |obj: &JsValue, key: &str| js_sys::Reflect::get(obj, &key.into()).unwrap();
+        assert_eq!(get(&check, "ok"), JsValue::TRUE);
+        assert_eq!(get(&check, "end"), JsValue::from(11));
+
+        let check = doc.can_delete_range(2, 9);
+        assert_eq!(get(&check, "ok"), JsValue::FALSE);
+        assert_eq!(get(&check, "reason"), JsValue::from_str("invalidRange"));
+
+        doc.set_read_only(true);
+        let check = doc.can_insert_at(0, "x");
+        assert_eq!(get(&check, "reason"), JsValue::from_str("readOnly"));
         assert_eq!(doc.get_content(), "Hello");
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_to_markdown() {
-        let doc = WasmDocument::from_text("Hello");
-        let md = doc.to_markdown();
-        assert_eq!(md, "Hello");
+    fn test_wasm_control_char_policy() {
+        let doc = WasmDocument::new();
+        assert_eq!(doc.get_control_char_policy(), "strip");
+        doc.insert_text("a\u{7}b", 0).unwrap();
+        assert_eq!(doc.get_content(), "ab");
+
+        doc.set_control_char_policy("replace").unwrap();
+        doc.insert_text("\u{7}", 2).unwrap();
+        assert_eq!(doc.get_content(), "ab ");
+        assert!(doc.set_control_char_policy("drop").is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_from_markdown() {
-        let md = "**Hello**";
-        let doc = WasmDocument::from_markdown(md);
-        assert!(doc.is_ok());
-        let doc = doc.unwrap();
-        assert_eq!(doc.get_content(), "Hello");
+    fn test_wasm_spellcheck() {
+        let doc = WasmDocument::from_text("Teh cat sat");
+        assert!(doc.set_spellcheck_dictionary(JsValue::from(5)).is_err());
+        let words = js_sys::Array::of3(&"the".into(), &"cat".into(), &"sat".into());
+        doc.set_spellcheck_dictionary(words.into()).unwrap();
+        assert!(doc.check_spelling(None));
+
+        let misspellings = doc.get_misspellings();
+        assert_eq!(misspellings.length(), 1);
+        let word = js_sys::Reflect::get(&misspellings.get(0), &"word".into()).unwrap();
+        assert_eq!(word.as_string().as_deref(), Some("Teh"));
+
+        doc.add_to_dictionary("Teh");
+        assert_eq!(doc.get_misspellings().length(), 0);
+
+        let dictionary = js_sys::Function::new_with_args("word", "return word.length > 2;");
+        doc.set_spellcheck_dictionary(dictionary.into()).unwrap();
+        doc.insert_text(" on", 11).unwrap();
+        assert!(doc.check_spelling(Some(f64::INFINITY)));
+        assert_eq!(doc.get_misspellings().length(), 1);
+
+        doc.set_spellcheck_dictionary(JsValue::NULL).unwrap();
+        assert_eq!(doc.get_misspellings().length(), 0);
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_paste_html() {
-        let mut doc = WasmDocument::new();
-        let html = "<p>Hello</p>";
-        assert!(doc.paste_html(html).is_ok());
-        assert_eq!(doc.get_content(), "Hello");
+    fn test_wasm_query_batch() {
+        let doc = WasmDocument::from_text("Hello world");
+        doc.apply_format("bold", 0, 5).unwrap();
+        doc.set_selection(2, 4);
+
+        let request = |query_type: &str, keys: &[(&str, usize)]| {
+            let obj = js_sys::Object::new();
+            js_sys::Reflect::set(&obj, &"type".into(), &query_type.into()).unwrap();
+            for (key, value) in keys {
+                js_sys::Reflect::set(&obj, &(*key).into(), &(*value).into()).unwrap();
+            }
+            JsValue::from(obj)
+        };
+        let batch = js_sys::Array::new();
+        batch.push(&request("formatsAt", &[("position", 1)]));
+        batch.push(&request("blockTypeAt", &[("position", 1)]));
+        batch.push(&request("textInRange", &[("start", 6), ("end", 11)]));
+        batch.push(&"selection".into());
+
+        let answers = doc.query(batch.clone()).unwrap();
+        assert_eq!(answers.length(), 4);
+        assert_eq!(js_sys::Array::from(&answers.get(0)).length(), 1);
+        assert_eq!(answers.get(1).as_string().as_deref(), Some("paragraph"));
+        assert_eq!(answers.get(2).as_string().as_deref(), Some("world"));
+        let focus = js_sys::Reflect::get(&answers.get(3), &"focus".into()).unwrap();
+        assert_eq!(focus.as_f64(), Some(4.0));
+
+        batch.push(&request("textInRange", &[("start", 0), ("end", 99)]));
+        assert!(doc.query(batch).is_err());
+        assert!(
+            doc.query(js_sys::Array::of1(&request("wordAt", &[])))
+                .is_err()
+        );
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_to_plain_text() {
-        let mut doc = WasmDocument::from_text("Hello");
-        doc.apply_format("bold", 0, 5).unwrap();
-        assert_eq!(doc.to_plain_text(), "Hello");
+    fn test_wasm_transient_decorations() {
+        let doc = WasmDocument::from_text("one two");
+        let entry = |id: &str, start: usize, end: usize| {
+            let obj = js_sys::Object::new();
+            js_sys::Reflect::set(&obj, &"id".into(), &id.into()).unwrap();
+            js_sys::Reflect::set(&obj, &"start".into(), &start.into()).unwrap();
+            js_sys::Reflect::set(&obj, &"end".into(), &end.into()).unwrap();
+            JsValue::from(obj)
+        };
+        doc.set_decorations(js_sys::Array::of2(&entry("a", 0, 3), &entry("b", 2, 7)))
+            .unwrap();
+        assert_eq!(
+            doc.to_html_range(0, 7),
+            "<p><mark data-deco-id=\"a\">on</mark><mark data-deco-id=\"a\"><mark data-deco-id=\"b\">e</mark></mark><mark data-deco-id=\"b\"> two</mark></p>\n"
+        );
+        assert_eq!(doc.to_html(), "<p>one two</p>\n");
+
+        assert!(
+            doc.set_decorations(js_sys::Array::of1(&entry("c", 0, 99)))
+                .is_err()
+        );
+        assert!(
+            doc.set_decorations(js_sys::Array::of1(&"c".into()))
+                .is_err()
+        );
+        assert_eq!(doc.state().inner.transient_decorations().len(), 2);
+
+        doc.clear_decorations();
+        assert_eq!(doc.to_html_range(0, 7), "<p>one two</p>\n");
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_find() {
-        let doc = WasmDocument::from_text("Hello World Hello");
-        let result = doc.find("Hello", false, false);
-        assert!(result.is_ok());
+    fn test_wasm_autocorrect() {
+        let doc = WasmDocument::new();
+        doc.set_autocorrect(true, Some(false));
+        doc.add_autocorrect_rule("teh", "the").unwrap();
+        assert!(doc.add_autocorrect_rule("", "x").is_err());
+        for (i, c) in "teh 'x' ".chars().enumerate() {
+            doc.insert_text(&c.to_string(), i).unwrap();
+        }
+        assert_eq!(doc.get_content(), "the 'x' ");
+
+        assert!(doc.remove_autocorrect_rule("teh"));
+        doc.set_autocorrect(false, None);
+        doc.insert_text("(c)", 8).unwrap();
+        doc.insert_text(" ", 11).unwrap();
+        assert_eq!(doc.get_content(), "the 'x' (c) ");
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_error_invalid_position() {
-        let mut doc = WasmDocument::from_text("Hello");
-        let result = doc.insert_text("World", 100);
-        assert!(result.is_err());
+    fn test_wasm_migrate_formats() {
+        let doc = WasmDocument::from_text("red link");
+        doc.apply_format_with_value("textColor", "#FF0000", 0, 3)
+            .unwrap();
+        doc.apply_format_with_value("link", "https://old.example/x", 4, 8)
+            .unwrap();
+        let map = js_sys::Function::new_with_args(
+            "format",
+            "if (format.type === 'textColor') return 'highlight';\n\
+             if (format.type === 'link') return { type: 'link', value: format.value.replace('old', 'new') };",
+        );
+        let report = doc.migrate_formats(&map).unwrap();
+        let get = |key: &str| js_sys::Reflect::get(&report, &key.into()).unwrap();
+        assert_eq!(get("formatsReplaced").as_f64(), Some(2.0));
+        assert_eq!(get("charactersChanged").as_f64(), Some(7.0));
+        assert!(
+            doc.state()
+                .inner
+                .get_formats_at(Position::new(5))
+                .contains(&InlineFormat::Link {
+                    url: "https://new.example/x".to_string()
+                })
+        );
+
+        let invalid = js_sys::Function::new_with_args("format", "return 'blink';");
+        assert!(doc.migrate_formats(&invalid).is_err());
+        doc.undo().unwrap();
+        assert!(doc.state().inner.get_formats_at(Position::new(1)).contains(
+            &InlineFormat::TextColor {
+                color: "#FF0000".to_string()
+            }
+        ));
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_error_invalid_range() {
-        let mut doc = WasmDocument::from_text("Hello");
-        let result = doc.delete_range(10, 20);
-        assert!(result.is_err());
+    fn test_wasm_copy_as_markdown() {
+        let doc = WasmDocument::from_markdown("1. **One**\n2. Two").unwrap();
+        assert_eq!(doc.copy_as_markdown(), "");
+        doc.set_selection(0, 7);
+        assert_eq!(doc.copy_as_markdown(), "1. **One**\n2. Two");
+        assert_eq!(doc.state().inner.copy().to_plain_text(), "1. One\n2. Two");
     }
 
-    // Memory management tests
     #[wasm_bindgen_test]
-    fn test_wasm_get_history_limit() {
+    fn test_wasm_auto_link() {
         let doc = WasmDocument::new();
-        assert_eq!(doc.get_history_limit(), 100);
+        assert!(doc.is_auto_link_enabled());
+        for (i, c) in "www.x.io ".chars().enumerate() {
+            doc.insert_text(&c.to_string(), i).unwrap();
+        }
+        let link = InlineFormat::Link {
+            url: "https://www.x.io".to_string(),
+        };
+        assert!(
+            doc.state()
+                .inner
+                .get_formats_at(Position::new(0))
+                .contains(&link)
+        );
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "www.x.io ");
+        assert!(
+            doc.state()
+                .inner
+                .get_formats_at(Position::new(0))
+                .is_empty()
+        );
+
+        doc.set_auto_link(false);
+        doc.insert_text(" ", 9).unwrap();
+        assert!(
+            doc.state()
+                .inner
+                .get_formats_at(Position::new(0))
+                .is_empty()
+        );
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_set_history_limit() {
-        let mut doc = WasmDocument::new();
-        doc.set_history_limit(50);
-        assert_eq!(doc.get_history_limit(), 50);
+    fn test_wasm_input_rules() {
+        let doc = WasmDocument::new();
+        assert!(!doc.is_input_rule_enabled("bold").unwrap());
+        doc.set_input_rules(true);
+        doc.set_input_rule("heading", false).unwrap();
+        assert!(doc.set_input_rule("italic", true).is_err());
+        assert!(doc.is_input_rule_enabled("bulletList").unwrap());
+        for (i, c) in "# - **xy**".chars().enumerate() {
+            doc.insert_text(&c.to_string(), i).unwrap();
+        }
+        assert_eq!(doc.get_content(), "# - xy");
+        assert_eq!(doc.get_block_type_at(0), "paragraph");
+        assert!(
+            doc.state()
+                .inner
+                .get_formats_at(Position::new(5))
+                .contains(&InlineFormat::Bold)
+        );
+
+        doc.set_input_rules(false);
+        for (i, c) in "\n> ".chars().enumerate() {
+            doc.insert_text(&c.to_string(), 6 + i).unwrap();
+        }
+        assert_eq!(doc.get_content(), "# - xy\n> ");
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_clear_history() {
-        let mut doc = WasmDocument::new();
-        doc.insert_text("Hello", 0).unwrap();
-        doc.insert_text(" World", 5).unwrap();
+    fn test_wasm_renumber_lists() {
+        let doc = WasmDocument::from_markdown("4. Four\n\nBreak\n\n9. Nine").unwrap();
+        assert_eq!(doc.get_list_start_at(0), Some(4));
+        doc.delete_range(5, 12).unwrap();
+        assert_eq!(doc.get_list_start_at(5), Some(9));
+        assert_eq!(doc.to_markdown(), "4. Four\n9. Nine");
+
+        doc.renumber_lists().unwrap();
+        assert_eq!(doc.get_list_start_at(5), None);
+        assert_eq!(doc.to_markdown(), "4. Four\n5. Nine");
+        doc.undo().unwrap();
+        assert_eq!(doc.get_list_marker_at(5).as_deref(), Some("9."));
+    }
 
-        assert!(doc.can_undo());
+    #[wasm_bindgen_test]
+    fn test_wasm_format_affinity() {
+        let doc = WasmDocument::from_text("bold");
+        doc.apply_format("bold", 0, 4).unwrap();
+        assert_eq!(doc.get_format_affinity("bold").unwrap(), "inherit-left");
 
-        doc.clear_history();
+        doc.set_format_affinity("bold", "none").unwrap();
+        assert_eq!(doc.get_format_affinity("bold").unwrap(), "none");
+        doc.insert_text("!", 4).unwrap();
+        assert!(
+            !doc.state()
+                .inner
+                .get_formats_at(Position::new(4))
+                .contains(&InlineFormat::Bold)
+        );
 
-        assert!(!doc.can_undo());
-        assert!(!doc.can_redo());
+        assert!(doc.set_format_affinity("bold", "sticky").is_err());
+        assert!(doc.get_format_affinity("blink").is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_get_memory_stats() {
-        let mut doc = WasmDocument::from_text("Hello World");
-        doc.apply_format("bold", 0, 5).unwrap();
+    fn test_wasm_word_segmenter() {
+        let doc = WasmDocument::from_text("ภาษาไทย ok");
+        assert_eq!(doc.get_word_count(), 2);
 
-        let stats = doc.get_memory_stats();
-        assert!(stats.is_object());
+        doc.set_word_segmenter(Some(js_sys::Function::new_with_args(
+            "text",
+            "return [0, 4];",
+        )));
+        assert_eq!(doc.get_word_count(), 3);
+        doc.move_by_word(true);
+        assert_eq!(doc.state().inner.get_selection().focus.offset(), 4);
 
-        // Verify stats object has expected properties
-        let obj = js_sys::Object::from(stats);
-        assert!(js_sys::Reflect::has(&obj, &"textLength".into()).unwrap());
-        assert!(js_sys::Reflect::has(&obj, &"formatRuns".into()).unwrap());
-        assert!(js_sys::Reflect::has(&obj, &"estimatedBytes".into()).unwrap());
+        doc.set_word_segmenter(None);
+        assert_eq!(doc.get_word_count(), 2);
     }
 
-    // Data marshalling tests
     #[wasm_bindgen_test]
-    fn test_wasm_string_marshalling() {
-        let mut doc = WasmDocument::new();
+    fn test_wasm_get_word_count_whitespace_only() {
+        let doc = WasmDocument::from_text("   \n\t  ");
+        assert_eq!(doc.get_word_count(), 0);
+    }
 
-        // Test with various string types
-        doc.insert_text("ASCII", 0).unwrap();
-        assert_eq!(doc.get_content(), "ASCII");
+    #[wasm_bindgen_test]
+    fn test_wasm_get_line_count_empty() {
+        let doc = WasmDocument::new();
+        assert_eq!(doc.get_line_count(), 1);
+    }
 
-        doc.insert_text(" Unicode: 你好", 5).unwrap();
-        assert_eq!(doc.get_content(), "ASCII Unicode: 你好");
+    #[wasm_bindgen_test]
+    fn test_wasm_get_line_count_single_line() {
+        let doc = WasmDocument::from_text("Hello world");
+        assert_eq!(doc.get_line_count(), 1);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_get_line_count_multiple_lines() {
+        let doc = WasmDocument::from_text("Line 1\nLine 2\nLine 3");
+        assert_eq!(doc.get_line_count(), 3);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wasm_get_line_count_trailing_newline() {
+        let doc = WasmDocument::from_text("Line 1\nLine 2\n");
+        assert_eq!(doc.get_line_count(), 3);
+    }
 
-        doc.insert_text(" Emoji: 🎉", 17).unwrap();
-        assert_eq!(doc.get_content(), "ASCII Unicode: 你好 Emoji: 🎉");
+    #[wasm_bindgen_test]
+    fn test_wasm_get_line_count_only_newlines() {
+        let doc = WasmDocument::from_text("\n\n\n");
+        assert_eq!(doc.get_line_count(), 4);
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_number_marshalling() {
-        let mut doc = WasmDocument::from_text("Hello World");
+    fn test_wasm_prerender_next_chunk() {
+        let doc = WasmDocument::from_text("Line 1\nLine 2\nLine 3");
+        let expected = doc.to_html();
+        assert!(doc.prerender_next_chunk(1000.0));
+        assert_eq!(doc.to_html(), expected);
+    }
 
-        // Test position and range parameters
-        assert!(doc.insert_text("!", 11).is_ok());
-        assert!(doc.delete_range(0, 5).is_ok());
-        assert_eq!(doc.get_content(), " World!");
+    #[wasm_bindgen_test]
+    fn test_wasm_rope_backend() {
+        let doc = WasmDocument::from_text_with_backend("Hello", "rope").unwrap();
+        assert_eq!(doc.get_storage_backend(), "rope");
+        assert!(doc.insert_text(" World", 5).is_ok());
+        assert_eq!(doc.get_content(), "Hello World");
+        assert!(WasmDocument::with_backend("btree").is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_array_marshalling() {
-        let doc = WasmDocument::from_text("Hello World Hello");
+    fn test_wasm_batch_undo() {
+        let doc = WasmDocument::new();
+        doc.begin_batch();
+        assert!(doc.insert_text("Hello", 0).is_ok());
+        assert!(doc.insert_text(" World", 5).is_ok());
+        assert!(doc.end_batch());
+        assert!(doc.undo().is_ok());
+        assert_eq!(doc.get_content(), "");
+    }
 
-        // Test find returns array
-        let result = doc.find("Hello", false, false);
-        assert!(result.is_ok());
+    #[wasm_bindgen_test]
+    fn test_wasm_block_quote_attributes() {
+        let doc = WasmDocument::from_text("Quote");
+        assert!(doc.get_block_quote_at(0).is_null());
+        assert!(
+            doc.set_block_quote(0, 5, Some("https://example.com".to_string()), 2)
+                .is_ok()
+        );
+        assert_eq!(doc.get_block_type_at(0), "blockquote");
+        assert!(!doc.get_block_quote_at(0).is_null());
+        assert!(doc.set_block_quote(0, 5, None, 0).is_err());
+    }
 
-        let matches = result.unwrap();
-        assert!(matches.is_array());
+    #[wasm_bindgen_test]
+    fn test_wasm_highlight_and_small_caps() {
+        let doc = WasmDocument::from_text("Hello");
+        assert!(doc.apply_format("highlight", 0, 5).is_ok());
+        assert!(doc.apply_format("smallCaps", 0, 5).is_ok());
+        assert!(doc.to_html().contains("<mark>"));
+        assert!(doc.toggle_format("highlight", 0, 5).is_ok());
+        assert!(!doc.to_html().contains("<mark>"));
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_object_marshalling() {
-        let mut doc = WasmDocument::from_text("Hello World");
-        doc.set_selection(0, 5);
+    fn test_wasm_insert_and_update_image() {
+        let doc = WasmDocument::from_text("ab");
+        assert!(
+            doc.insert_image(1, "https://example.com/a.png", "A", Some(64), None)
+                .is_ok()
+        );
+        assert_eq!(doc.get_length(), 3);
+        assert!(!doc.get_image_at(1).is_null());
+        assert!(doc.update_image(1, "b.png", "B", None, None).is_ok());
+        assert!(doc.to_html().contains("<img src=\"b.png\" alt=\"B\">"));
+        assert!(doc.update_image(0, "b.png", "B", None, None).is_err());
+        assert!(
+            doc.insert_image(0, "javascript:alert(1)", "", None, None)
+                .is_err()
+        );
+    }
 
-        // Test getSelection returns object
-        let selection = doc.get_selection();
-        assert!(selection.is_object());
+    #[wasm_bindgen_test]
+    fn test_wasm_subscript_and_superscript() {
+        let doc = WasmDocument::from_text("x2");
+        assert!(doc.apply_format("superscript", 1, 2).is_ok());
+        assert!(doc.apply_format("subscript", 1, 2).is_ok());
+        assert!(doc.to_html().contains("<sub>2</sub>"));
+        assert!(!doc.to_html().contains("<sup>"));
+    }
 
-        let obj = js_sys::Object::from(selection);
-        assert!(js_sys::Reflect::has(&obj, &"anchor".into()).unwrap());
-        assert!(js_sys::Reflect::has(&obj, &"focus".into()).unwrap());
+    #[wasm_bindgen_test]
+    fn test_wasm_markdown_source_map() {
+        let result = WasmDocument::from_markdown_with_source_map("# Title\n\n- item").unwrap();
+        let lines: js_sys::Array = js_sys::Reflect::get(&result, &"lines".into())
+            .unwrap()
+            .into();
+        assert_eq!(lines.length(), 3);
+        let item = lines.get(2);
+        let get = |name: &str| js_sys::Reflect::get(&item, &name.into()).unwrap();
+        assert_eq!(get("sourceLine").as_f64(), Some(2.0));
+        assert_eq!(get("sourceColumn").as_f64(), Some(2.0));
+        assert_eq!(get("modelStart").as_f64(), Some(7.0));
+
+        let doc = WasmDocument::from_markdown("# Title").unwrap();
+        let exported = doc.to_markdown_with_source_map();
+        let markdown = js_sys::Reflect::get(&exported, &"markdown".into()).unwrap();
+        assert_eq!(markdown.as_string().unwrap(), "# Title");
     }
 
-    // Format with value tests
     #[wasm_bindgen_test]
-    fn test_wasm_apply_format_with_value_link() {
-        let mut doc = WasmDocument::from_text("Click here");
-        let result = doc.apply_format_with_value("link", "https://example.com", 0, 10);
-        assert!(result.is_ok());
+    fn test_wasm_bookmarks() {
+        let doc = WasmDocument::from_text("Intro\nDetails");
+        doc.set_bookmark("details", 6).unwrap();
+        assert!(doc.set_bookmark("past", 99).is_err());
+
+        doc.insert_text("# ", 0).unwrap();
+        assert_eq!(doc.get_bookmark("details"), Some(8));
+        assert_eq!(doc.get_bookmark("missing"), None);
+
+        assert!(doc.delete_bookmark("details"));
+        assert!(!doc.delete_bookmark("details"));
+        assert_eq!(doc.get_bookmark("details"), None);
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_apply_format_with_value_color() {
-        let mut doc = WasmDocument::from_text("Colored text");
-        let result = doc.apply_format_with_value("textColor", "#FF0000", 0, 12);
-        assert!(result.is_ok());
+    fn test_wasm_no_spellcheck() {
+        let doc = WasmDocument::from_text("Run cargo test");
+        doc.mark_no_spellcheck(4, 9).unwrap();
+        assert!(doc.mark_no_spellcheck(4, 99).is_err());
+        doc.insert_text("Please ", 0).unwrap();
+        assert!(doc.is_no_spellcheck_at(11));
+        assert!(!doc.is_no_spellcheck_at(16));
+        assert_eq!(doc.get_no_spellcheck_ranges().length(), 1);
+        assert!(
+            doc.to_html()
+                .contains("<span spellcheck=\"false\">cargo</span>")
+        );
+
+        let restored = WasmDocument::from_json(&doc.to_json(JsValue::UNDEFINED).unwrap()).unwrap();
+        assert!(restored.is_no_spellcheck_at(11));
+
+        doc.clear_no_spellcheck(0, 21).unwrap();
+        assert_eq!(doc.get_no_spellcheck_ranges().length(), 0);
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_apply_format_with_value_background() {
-        let mut doc = WasmDocument::from_text("Highlighted");
-        let result = doc.apply_format_with_value("backgroundColor", "#FFFF00", 0, 11);
-        assert!(result.is_ok());
+    fn test_wasm_audit_document() {
+        let doc = WasmDocument::from_text("See docs\u{202E} here");
+        doc.apply_format_with_value("link", "javascript:alert(1)", 0, 3)
+            .unwrap();
+        let findings = doc.audit_document(None);
+        assert_eq!(findings.length(), 2);
+        let first = findings.get(0);
+        let kind = js_sys::Reflect::get(&first, &"kind".into()).unwrap();
+        assert_eq!(kind.as_string().as_deref(), Some("unsafeUrl"));
+        let end = js_sys::Reflect::get(&first, &"end".into()).unwrap();
+        assert_eq!(end.as_f64(), Some(3.0));
+
+        assert_eq!(doc.audit_document(Some(4)).length(), 3);
     }
 
-    // Clipboard tests
     #[wasm_bindgen_test]
-    fn test_wasm_copy() {
-        let mut doc = WasmDocument::from_text("Hello World");
-        doc.set_selection(0, 5);
+    fn test_wasm_html_policy() {
+        let policy = r#"{"allowTags": ["script", "abbr"], "allowAttributes": ["target", "data-*"], "allowUrlSchemes": ["tel"]}"#;
+        let html = r#"<p><a href="tel:123" target="_blank" data-kind="phone">Call</a> <abbr>HTML</abbr><script>x()</script></p>"#;
 
-        let clipboard = doc.copy();
-        assert!(clipboard.is_object());
+        let doc = WasmDocument::from_html_with_policy(html, policy).unwrap();
+        assert_eq!(doc.get_content(), "Call HTML");
+        assert!(doc.to_html().contains("href=\"tel:123\""));
+        let default = WasmDocument::from_html(html).unwrap();
+        assert!(!default.to_html().contains("tel:"));
 
-        let obj = js_sys::Object::from(clipboard);
-        assert!(js_sys::Reflect::has(&obj, &"text".into()).unwrap());
-        assert!(js_sys::Reflect::has(&obj, &"html".into()).unwrap());
+        assert_eq!(
+            WasmDocument::sanitize_html(html, Some(policy.to_string())).unwrap(),
+            r#"<p><a data-kind="phone" href="tel:123" target="_blank">Call</a> <abbr>HTML</abbr></p>"#
+        );
+        assert!(WasmDocument::from_html_with_policy(html, r#"{"allowTag": []}"#).is_err());
+        assert!(WasmDocument::from_html_with_policy(html, r#"{"allowTags": "b"}"#).is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_cut() {
-        let mut doc = WasmDocument::from_text("Hello World");
-        doc.set_selection(0, 5);
+    fn test_wasm_verify_round_trip() {
+        let doc = WasmDocument::from_text("Hello world");
+        doc.apply_format("underline", 0, 5).unwrap();
+        doc.apply_format_with_value("textColor", "red", 6, 11)
+            .unwrap();
 
-        let result = doc.cut();
-        assert!(result.is_ok());
+        let report = doc.verify_round_trip("markdown").unwrap();
+        let lossless = js_sys::Reflect::get(&report, &"lossless".into()).unwrap();
+        assert_eq!(lossless.as_bool(), Some(false));
+        let lost = js_sys::Reflect::get(&report, &"lostFormats".into()).unwrap();
+        assert_eq!(js_sys::Array::from(&lost).length(), 2);
 
-        let clipboard = result.unwrap();
-        assert!(clipboard.is_object());
+        let report = doc.verify_round_trip("json").unwrap();
+        let lossless = js_sys::Reflect::get(&report, &"lossless".into()).unwrap();
+        assert_eq!(lossless.as_bool(), Some(true));
+        assert!(doc.verify_round_trip("docx").is_err());
+    }
 
-        // Text should be removed
-        assert_eq!(doc.get_content(), " World");
+    #[wasm_bindgen_test]
+    fn test_wasm_set_block_type_lines() {
+        let doc = WasmDocument::from_text("One\nTwo\nThree");
+        doc.set_block_type_lines("bulletList", 1, 5).unwrap();
+        assert_eq!(doc.get_block_type_at(0), "unordered-list");
+        assert_eq!(doc.get_block_type_at(4), "unordered-list");
+        assert_eq!(doc.get_block_type_at(8), "paragraph");
+        assert!(doc.set_block_type_lines("heading1", 0, 99).is_err());
+
+        doc.undo().unwrap();
+        assert_eq!(doc.get_block_type_at(0), "paragraph");
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_paste_plain_text() {
-        let mut doc = WasmDocument::new();
-        let result = doc.paste_plain_text("Hello World");
-        assert!(result.is_ok());
-        assert_eq!(doc.get_content(), "Hello World");
+    fn test_wasm_select_and_expand() {
+        let doc = WasmDocument::from_text("Hello world\nBye");
+        doc.select_word(7);
+        assert_eq!(doc.get_selected_text(), "world");
+        doc.select_line(7);
+        assert_eq!(doc.get_selected_text(), "Hello world");
+        doc.select_block(13);
+        assert_eq!(doc.get_selected_text(), "Bye");
+
+        doc.set_selection(1, 1);
+        assert!(doc.expand_selection());
+        assert_eq!(doc.get_selected_text(), "Hello");
+        assert!(doc.shrink_selection());
+        assert!(!doc.shrink_selection());
     }
 
-    // IME composition tests
     #[wasm_bindgen_test]
-    fn test_wasm_ime_composition() {
-        let mut doc = WasmDocument::from_text("Hello");
+    fn test_wasm_multiple_selections() {
+        let doc = WasmDocument::from_text("one\ntwo");
+        doc.add_selection(4, 4);
+        assert_eq!(doc.get_selections().length(), 2);
 
-        doc.set_selection(5, 5);
-        doc.start_composition();
-        assert!(doc.is_composing());
+        doc.insert_text_at_selections("- ").unwrap();
+        assert_eq!(doc.get_content(), "- one\n- two");
+        doc.delete_at_selections(false).unwrap();
+        assert_eq!(doc.get_content(), "-one\n-two");
 
-        doc.update_composition("世");
-        assert_eq!(doc.get_content(), "Hello世");
+        doc.clear_secondary_selections();
+        assert_eq!(doc.get_selections().length(), 1);
+        assert!(doc.apply_format_to_selections("unknown").is_err());
 
-        doc.update_composition("世界");
-        assert_eq!(doc.get_content(), "Hello世界");
+        doc.set_selection(4, 4);
+        doc.add_selection(9, 9);
+        doc.paste_plain_text("!\n?").unwrap();
+        assert_eq!(doc.get_content(), "-one!\n-two?");
 
-        doc.end_composition();
-        assert!(!doc.is_composing());
-        assert_eq!(doc.get_content(), "Hello世界");
+        let doc = WasmDocument::from_text("a b a");
+        assert_eq!(doc.select_all_occurrences(), 2);
+        doc.insert_text_at_selections("c").unwrap();
+        assert_eq!(doc.get_content(), "c b c");
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_ime_cancel_composition() {
-        let mut doc = WasmDocument::from_text("Hello");
-
-        doc.set_selection(5, 5);
-        doc.start_composition();
-        doc.update_composition("世界");
+    fn test_wasm_line_column() {
+        let doc = WasmDocument::from_text("one\ntwo");
+        assert_eq!(doc.get_line_count(), 2);
+        let obj = doc.position_to_line_column(5).unwrap();
+        let line = js_sys::Reflect::get(&obj, &"line".into()).unwrap();
+        let column = js_sys::Reflect::get(&obj, &"column".into()).unwrap();
+        assert_eq!(line.as_f64(), Some(1.0));
+        assert_eq!(column.as_f64(), Some(1.0));
+        assert!(doc.position_to_line_column(8).is_err());
+
+        doc.insert_text("\n", 0).unwrap();
+        assert_eq!(doc.line_column_to_position(2, 1).unwrap(), 6);
+        assert_eq!(doc.line_column_to_position(0, 5).unwrap(), 0);
+        assert!(doc.line_column_to_position(3, 0).is_err());
+    }
 
-        doc.cancel_composition();
-        assert!(!doc.is_composing());
-        assert_eq!(doc.get_content(), "Hello");
+    #[wasm_bindgen_test]
+    fn test_wasm_offset_geometry_hints() {
+        let doc = WasmDocument::from_text("# Title\nab\u{2028}cde");
+        let obj = doc.get_offset_geometry_hints(13).unwrap();
+        let get = |key: &str| js_sys::Reflect::get(&obj, &key.into()).unwrap().as_f64();
+        assert_eq!(get("block"), Some(1.0));
+        assert_eq!(get("line"), Some(1.0));
+        assert_eq!(get("column"), Some(2.0));
+        assert!(doc.get_offset_geometry_hints(15).is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_get_composition_range() {
-        let mut doc = WasmDocument::from_text("Hello");
+    fn test_wasm_sticky_positions() {
+        let doc = WasmDocument::from_text("Hello world");
+        let left = doc.create_sticky_position(6, "left").unwrap();
+        let right = doc.create_sticky_position(6, "right").unwrap();
+        assert!(doc.create_sticky_position(99, "left").is_err());
+        assert!(doc.create_sticky_position(0, "up").is_err());
 
-        doc.set_selection(5, 5);
-        doc.start_composition();
-        doc.update_composition("世界");
+        doc.insert_text("big ", 6).unwrap();
+        assert_eq!(doc.resolve_sticky_position(left), Some(6));
+        assert_eq!(doc.resolve_sticky_position(right), Some(10));
 
-        let range = doc.get_composition_range();
-        assert!(!range.is_null());
+        assert!(doc.release_sticky_position(left));
+        assert!(!doc.release_sticky_position(left));
+        assert_eq!(doc.resolve_sticky_position(left), None);
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_get_composition_text() {
-        let mut doc = WasmDocument::from_text("Hello");
+    fn test_wasm_annotations() {
+        let doc = WasmDocument::from_text("Hello World");
+        doc.add_annotation(6, 11, "c1", Some("ann".to_string()), None)
+            .unwrap();
+        assert!(doc.add_annotation(3, 3, "c2", None, None).is_err());
 
-        doc.set_selection(5, 5);
-        doc.start_composition();
-        doc.update_composition("世界");
+        let found = doc.get_annotations_at(7);
+        assert_eq!(found.length(), 1);
+        let get = |name: &str| js_sys::Reflect::get(&found.get(0), &name.into()).unwrap();
+        assert_eq!(get("id").as_string().unwrap(), "c1");
+        assert_eq!(get("author").as_string().unwrap(), "ann");
+        assert_eq!(get("start").as_f64(), Some(6.0));
+        assert!(doc.get_annotations_at(2).length() == 0);
 
-        let text = doc.get_composition_text();
-        assert!(!text.is_null());
+        doc.remove_annotation("c1").unwrap();
+        assert_eq!(doc.get_annotations_at(7).length(), 0);
+        assert!(doc.remove_annotation("c1").is_err());
     }
 
-    // Cursor movement tests
     #[wasm_bindgen_test]
-    fn test_wasm_cursor_movement() {
-        let mut doc = WasmDocument::from_text("Hello World");
-
-        doc.set_selection(0, 0);
-        doc.move_cursor_right();
+    fn test_wasm_to_html_with_source_map() {
+        let doc = WasmDocument::from_text("One\nTwo");
+        let result = doc.to_html_with_source_map();
+        let html = js_sys::Reflect::get(&result, &"html".into()).unwrap();
+        assert_eq!(html.as_string().unwrap(), "<p>One</p>\n<p>Two</p>\n");
+
+        let mappings: js_sys::Array = js_sys::Reflect::get(&result, &"mappings".into())
+            .unwrap()
+            .into();
+        let two = mappings.get(2);
+        let get = |name: &str| js_sys::Reflect::get(&two, &name.into()).unwrap();
+        assert_eq!(get("modelStart").as_f64(), Some(4.0));
+        assert_eq!(get("htmlStart").as_f64(), Some(14.0));
+        let path: js_sys::Array = get("path").into();
+        assert_eq!(path.length(), 2);
+        assert_eq!(path.get(0).as_f64(), Some(2.0));
+    }
 
-        let sel = doc.get_selection();
-        let obj = js_sys::Object::from(sel);
-        let anchor = js_sys::Reflect::get(&obj, &"anchor".into()).unwrap();
-        assert_eq!(anchor.as_f64().unwrap() as usize, 1);
+    #[wasm_bindgen_test]
+    fn test_wasm_from_html_whitespace_option() {
+        let html = "<p>\n  a   b\n</p>";
+        assert_eq!(WasmDocument::from_html(html).unwrap().get_content(), "a b");
+        let doc = WasmDocument::from_html_with_options(html, true).unwrap();
+        assert_eq!(doc.get_content(), "\n  a   b");
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_move_by_word() {
-        let mut doc = WasmDocument::from_text("Hello World Test");
+    fn test_wasm_import_limits() {
+        let deep = format!("{}text", "<div>".repeat(300));
+        let err = WasmDocument::from_html_safe(&deep, JsValue::UNDEFINED)
+            .err()
+            .and_then(|err| err.as_string())
+            .unwrap();
+        assert!(err.contains("nesting depth is over 256"));
 
-        doc.set_selection(0, 0);
-        doc.move_by_word(true); // Move forward
+        let limits = js_sys::Object::new();
+        js_sys::Reflect::set(&limits, &"maxDepth".into(), &JsValue::from_f64(400.0)).unwrap();
+        let doc = WasmDocument::from_html_safe(&deep, limits.clone().into()).unwrap();
+        assert_eq!(doc.get_content(), "text");
 
-        let sel = doc.get_selection();
-        let obj = js_sys::Object::from(sel);
-        let anchor = js_sys::Reflect::get(&obj, &"anchor".into()).unwrap();
-        assert!(anchor.as_f64().unwrap() as usize > 0);
+        js_sys::Reflect::set(&limits, &"maxOutputSize".into(), &JsValue::from_f64(4.0)).unwrap();
+        assert!(WasmDocument::from_markdown_safe("**bold**", limits.clone().into()).is_ok());
+        assert!(WasmDocument::from_markdown_safe("**bolder**", limits.into()).is_err());
+        assert!(WasmDocument::from_markdown_safe("x", JsValue::from_str("big")).is_err());
     }
 
-    // Search and replace tests
     #[wasm_bindgen_test]
-    fn test_wasm_find_and_replace() {
-        let mut doc = WasmDocument::from_text("Hello World Hello");
-        let result = doc.find_and_replace("Hello", "Hi", false, false);
-        assert!(result.is_ok());
-
-        let count = result.unwrap();
-        assert_eq!(count, 2);
-        assert_eq!(doc.get_content(), "Hi World Hi");
+    fn test_wasm_get_block_html() {
+        let doc = WasmDocument::from_markdown("# Title\nSome *text*").unwrap();
+        assert_eq!(doc.get_block_html(0).unwrap(), "<h1>Title</h1>\n");
+        assert_eq!(
+            doc.get_block_html(1).unwrap(),
+            "<p>Some <em>text</em></p>\n"
+        );
+        assert!(doc.get_block_html(2).is_err());
     }
 
-    // Block type tests
     #[wasm_bindgen_test]
-    fn test_wasm_get_block_type_at() {
-        let mut doc = WasmDocument::from_text("Heading");
-        doc.set_block_type("heading1", 0, 7).unwrap();
+    fn test_wasm_markdown_front_matter() {
+        let doc = WasmDocument::from_markdown("+++\ntitle = \"Notes\"\n+++\nBody").unwrap();
+        assert_eq!(doc.get_content(), "Body");
+        assert_eq!(doc.get_metadata("title").as_deref(), Some("Notes"));
 
-        let block_type = doc.get_block_type_at(0);
-        assert_eq!(block_type, "h1");
+        doc.set_metadata("author", "Ada").unwrap();
+        assert_eq!(
+            doc.to_markdown(),
+            "+++\ntitle = \"Notes\"\nauthor = \"Ada\"\n+++\nBody"
+        );
+        assert!(doc.delete_metadata("title").unwrap());
+        assert_eq!(doc.get_metadata("title"), None);
     }
 
-    // Format query tests
     #[wasm_bindgen_test]
-    fn test_wasm_get_formats_at() {
-        let mut doc = WasmDocument::from_text("Hello");
-        doc.apply_format("bold", 0, 5).unwrap();
-        doc.apply_format("italic", 0, 5).unwrap();
-
-        let formats = doc.get_formats_at(2);
-        assert!(formats.is_array());
+    fn test_wasm_set_document_meta() {
+        let doc = WasmDocument::from_text("Body");
+        doc.set_document_meta(r#"{"title": "Notes", "status": "draft"}"#)
+            .unwrap();
+        doc.set_document_meta(r#"{"status": null, "author": "Ada"}"#)
+            .unwrap();
+        assert_eq!(doc.get_metadata("status"), None);
+        assert_eq!(doc.get_metadata("author").as_deref(), Some("Ada"));
+        assert!(doc.to_html_document().contains("<title>Notes</title>"));
+
+        let restored = WasmDocument::from_json(&doc.to_json(JsValue::UNDEFINED).unwrap()).unwrap();
+        assert_eq!(restored.get_metadata("title").as_deref(), Some("Notes"));
 
-        let arr = js_sys::Array::from(&formats);
-        assert_eq!(arr.length(), 2);
+        doc.undo().unwrap();
+        assert_eq!(doc.get_metadata("status").as_deref(), Some("draft"));
+        assert!(doc.set_document_meta(r#"{"title": 1}"#).is_err());
     }
 
-    // Error handling tests
     #[wasm_bindgen_test]
-    fn test_wasm_error_messages() {
-        let mut doc = WasmDocument::from_text("Hello");
-
-        // Test invalid position error
-        let result = doc.insert_text("World", 100);
-        assert!(result.is_err());
-
-        let err = result.unwrap_err();
-        let err_str = err.as_string().unwrap();
-        assert!(err_str.contains("Insert failed"));
+    fn test_wasm_markdown_highlight_option() {
+        let doc = WasmDocument::from_markdown_with_options("==Hi==", true, true).unwrap();
+        assert_eq!(doc.get_content(), "Hi");
+        let options = r#"{"highlight": true}"#.to_string();
+        assert_eq!(
+            doc.to_markdown_with_options(Some(options)).unwrap(),
+            "==Hi=="
+        );
+        assert_eq!(doc.to_markdown(), "Hi");
     }
 
-    // Serialization roundtrip tests
     #[wasm_bindgen_test]
-    fn test_wasm_json_roundtrip() {
-        let mut doc = WasmDocument::from_text("Hello World");
-        doc.apply_format("bold", 0, 5).unwrap();
+    fn test_wasm_markdown_dialect_options() {
+        let doc = WasmDocument::from_markdown("# Title\n- *item*").unwrap();
+        let options = r#"{"bullet": "+", "emphasis": "_", "headingStyle": "setext"}"#;
+        assert_eq!(
+            doc.to_markdown_with_options(Some(options.to_string()))
+                .unwrap(),
+            "Title\n=====\n+ _item_"
+        );
+        assert_eq!(
+            doc.to_markdown_with_options(None).unwrap(),
+            doc.to_markdown()
+        );
+        assert!(
+            doc.to_markdown_with_options(Some(r#"{"bullet": "x"}"#.to_string()))
+                .is_err()
+        );
+    }
 
-        let json = doc.to_json().unwrap();
-        let doc2 = WasmDocument::from_json(&json).unwrap();
+    #[wasm_bindgen_test]
+    fn test_wasm_collaboration_sync() {
+        let alice = WasmDocument::new();
+        alice.enable_collaboration(1);
+        alice.insert_text("Hello", 0).unwrap();
+        let bob = WasmDocument::new();
+        bob.enable_collaboration(2);
 
-        assert_eq!(doc2.get_content(), "Hello World");
+        let update = alice.encode_state_as_update().unwrap();
+        assert!(bob.apply_update(&update).unwrap());
+        assert_eq!(bob.get_content(), "Hello");
+        assert!(bob.apply_update(&[0xff]).is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_html_export() {
-        let mut doc = WasmDocument::from_text("Hello");
-        doc.apply_format("bold", 0, 5).unwrap();
+    fn test_wasm_transfer() {
+        let from = WasmDocument::new();
+        from.insert_text("Hello World", 0).unwrap();
+        from.set_selection(0, 6);
+        let to = WasmDocument::new();
 
-        let html = doc.to_html();
-        assert!(html.contains("<strong>"));
-        assert!(html.contains("Hello"));
+        WasmDocument::transfer(&from, &to).unwrap();
+        assert_eq!(from.get_content(), "World");
+        assert_eq!(to.get_content(), "Hello ");
+        assert!(WasmDocument::transfer(&from, &from).is_err());
+        assert_eq!(from.get_content(), "World");
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_markdown_roundtrip() {
-        let md = "**Bold** and *italic*";
-        let doc = WasmDocument::from_markdown(md).unwrap();
+    fn test_wasm_callbacks_cannot_reenter() {
+        use std::rc::Rc;
+
+        let doc = Rc::new(WasmDocument::from_text("Hi"));
+        let other = Rc::new(WasmDocument::from_text("Other"));
+        let busy = Rc::new(RefCell::new(Vec::new()));
+        let (inner, outer, seen) = (Rc::clone(&doc), Rc::clone(&other), Rc::clone(&busy));
+        let listener = Closure::<dyn Fn(JsValue)>::new(move |_| {
+            // The document that triggered the event is still in use; other
+            // documents are not
+            seen.borrow_mut().push(inner.state.try_borrow().is_err());
+            seen.borrow_mut()
+                .push(outer.state.try_borrow_mut().is_err());
+        });
+        doc.on_change(
+            listener
+                .as_ref()
+                .unchecked_ref::<js_sys::Function>()
+                .clone(),
+        );
+
+        doc.insert_text("!", 2).unwrap();
+        assert_eq!(*busy.borrow(), [true, false]);
+        // Once the edit has returned, the document can be used again
+        doc.insert_text("?", 3).unwrap();
+        assert_eq!(doc.get_content(), "Hi!?");
+
+        // A nested call throws to the callback that made it
+        let edit = js_sys::Function::new_with_args(
+            "doc",
+            "const errors = [];
+             doc.onChange(() => {
+                 try { doc.insertText('x', 0); } catch (e) { errors.push(String(e)); }
+             });
+             doc.insertText('!', 0);
+             return errors;",
+        );
+        let errors = edit
+            .call1(&JsValue::NULL, &WasmDocument::from_text("Hi").into())
+            .unwrap();
+        let errors = js_sys::Array::from(&errors);
+        assert_eq!(errors.length(), 1);
+        assert!(
+            errors
+                .get(0)
+                .as_string()
+                .unwrap()
+                .contains("WasmDocument.defer")
+        );
+    }
 
-        let content = doc.get_content();
-        assert!(content.contains("Bold"));
-        assert!(content.contains("italic"));
+    #[wasm_bindgen_test]
+    fn test_wasm_document_frees_after_rejected_reentrant_call() {
+        // Freeing claims the document back from wasm-bindgen, which fails
+        // if the rejected call left it borrowed
+        let edit = js_sys::Function::new_with_args(
+            "doc",
+            "let rejected = false;
+             doc.onChange(() => {
+                 try { doc.insertText('x', 0); } catch (e) { rejected = true; }
+             });
+             doc.insertText('!', 0);
+             doc.free();
+             return rejected;",
+        );
+        let rejected = edit
+            .call1(&JsValue::NULL, &WasmDocument::from_text("Hi").into())
+            .unwrap();
+        assert_eq!(rejected.as_bool(), Some(true));
     }
 
-    // Edge case tests
     #[wasm_bindgen_test]
-    fn test_wasm_empty_document() {
+    fn test_wasm_apply_formats() {
         let doc = WasmDocument::new();
-        assert_eq!(doc.get_length(), 0);
-        assert!(doc.is_empty());
-        assert_eq!(doc.get_content(), "");
+        doc.insert_text("Hello World", 0).unwrap();
+
+        let ops = js_sys::Array::new();
+        let bold = js_sys::Object::new();
+        js_sys::Reflect::set(&bold, &"type".into(), &"bold".into()).unwrap();
+        js_sys::Reflect::set(&bold, &"start".into(), &0.into()).unwrap();
+        js_sys::Reflect::set(&bold, &"end".into(), &5.into()).unwrap();
+        ops.push(&bold);
+        let link = js_sys::Object::new();
+        js_sys::Reflect::set(&link, &"type".into(), &"link".into()).unwrap();
+        js_sys::Reflect::set(&link, &"value".into(), &"https://example.com".into()).unwrap();
+        js_sys::Reflect::set(&link, &"start".into(), &6.into()).unwrap();
+        js_sys::Reflect::set(&link, &"end".into(), &11.into()).unwrap();
+        ops.push(&link);
+
+        doc.apply_formats(ops).unwrap();
+        let formats = doc.get_formats_at_strings(7);
+        assert!(formats.includes(&"link".into(), 0));
+
+        let invalid = js_sys::Array::new();
+        invalid.push(&link);
+        js_sys::Reflect::set(&link, &"end".into(), &99.into()).unwrap();
+        assert!(doc.apply_formats(invalid).is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_large_text() {
-        let mut doc = WasmDocument::new();
-        let large_text = "a".repeat(10000);
-
-        let result = doc.insert_text(&large_text, 0);
-        assert!(result.is_ok());
-        assert_eq!(doc.get_length(), 10000);
+    fn test_wasm_transform_remote_op() {
+        let doc = WasmDocument::new();
+        doc.enable_operational_transform();
+        doc.insert_text("world", 0).unwrap();
+        doc.acknowledge_pending_ops(1).unwrap();
+        doc.insert_text("!", 5).unwrap();
+
+        let applied = doc
+            .transform_remote_op(r#"{"type":"insert","position":0,"text":"Hello "}"#)
+            .unwrap();
+        assert_eq!(
+            applied,
+            r#"[{"type":"insert","position":0,"text":"Hello "}]"#
+        );
+        assert_eq!(doc.get_content(), "Hello world!");
+        assert_eq!(
+            doc.get_pending_ops().unwrap(),
+            r#"[{"type":"insert","position":11,"text":"!"}]"#
+        );
+        assert!(doc.transform_remote_op("not json").is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_unicode_handling() {
-        let mut doc = WasmDocument::new();
+    fn test_wasm_apply_operation() {
+        let doc = WasmDocument::from_text("Hello");
+        doc.apply_operation(r#"{"type":"insert","position":5,"text":" world"}"#)
+            .unwrap();
+        doc.apply_operation(r#"{"type":"delete","start":0,"end":1}"#)
+            .unwrap();
+        assert_eq!(doc.get_content(), "ello world");
+        assert!(
+            doc.apply_operation(r#"{"type":"delete","start":3,"end":90}"#)
+                .is_err()
+        );
+        assert!(doc.undo().is_ok());
+        assert_eq!(doc.get_content(), "Hello world");
+
+        let target = JsValue::from(WasmDocument::new());
+        assert!(
+            WasmDocument::replay_operations(target.clone(), "not json", JsValue::UNDEFINED)
+                .is_err()
+        );
+        let options = js_sys::Object::new();
+        js_sys::Reflect::set(&options, &"speed".into(), &JsValue::from(-1)).unwrap();
+        assert!(WasmDocument::replay_operations(target, "[]", options.into()).is_err());
+    }
 
-        // Test various unicode characters
-        doc.insert_text("Hello 世界 🌍 مرحبا", 0).unwrap();
-        assert!(doc.get_length() > 0);
+    #[wasm_bindgen_test]
+    fn test_wasm_delta_roundtrip() {
+        let doc = WasmDocument::from_text("Hello");
+        doc.apply_format("bold", 0, 5).unwrap();
+        let delta = doc.to_delta();
+        assert_eq!(
+            delta,
+            r#"{"ops":[{"attributes":{"bold":true},"insert":"Hello"},{"insert":"\n"}]}"#
+        );
 
-        let content = doc.get_content();
-        assert!(content.contains("世界"));
-        assert!(content.contains("🌍"));
-        assert!(content.contains("مرحبا"));
+        let restored = WasmDocument::from_delta(&delta).unwrap();
+        assert_eq!(restored.get_content(), "Hello");
+        assert!(WasmDocument::from_delta(r#"[{"delete":1}]"#).is_err());
     }
 
-    // Document statistics tests
     #[wasm_bindgen_test]
-    fn test_wasm_get_word_count_empty() {
-        let doc = WasmDocument::new();
-        assert_eq!(doc.get_word_count(), 0);
+    fn test_wasm_constructor_options() {
+        let options = js_sys::Object::new();
+        let set = |key: &str, value: JsValue| {
+            js_sys::Reflect::set(&options, &JsValue::from_str(key), &value).unwrap();
+        };
+        set(
+            "content",
+            "<p>Hi <img src=\"https://example.com/a.png\"></p>".into(),
+        );
+        set("historyLimit", 10.into());
+        set("maxLength", 5.into());
+        set("readOnly", true.into());
+        let sanitizer = js_sys::Object::new();
+        let tags = js_sys::Array::of1(&"img".into());
+        js_sys::Reflect::set(&sanitizer, &"disallowedTags".into(), &tags).unwrap();
+        set("sanitizer", sanitizer.into());
+
+        let doc = WasmDocument::with_options(Some(options.clone())).unwrap();
+        assert_eq!(doc.get_content(), "Hi ");
+        assert_eq!(doc.get_history_limit(), 10);
+        assert_eq!(doc.get_max_length(), Some(5));
+        assert!(doc.is_read_only());
+        assert!(doc.insert_text("x", 0).is_err());
+
+        doc.set_read_only(false);
+        assert!(doc.insert_text("!!", 3).is_ok());
+        assert!(doc.insert_text("?", 5).is_err());
+
+        set("readOnly", "yes".into());
+        assert!(WasmDocument::with_options(Some(options.clone())).is_err());
+
+        set("readOnly", false.into());
+        set("content", "Too long for five".into());
+        assert!(WasmDocument::with_options(Some(options)).is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_get_word_count_simple() {
-        let doc = WasmDocument::from_text("Hello world");
-        assert_eq!(doc.get_word_count(), 2);
+    fn test_wasm_to_docx() {
+        let doc = WasmDocument::from_text("Hello");
+        let docx = doc.to_docx();
+        assert_eq!(&docx[..4], b"PK\x03\x04");
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_get_word_count_multiple_spaces() {
-        let doc = WasmDocument::from_text("Hello   world   test");
-        assert_eq!(doc.get_word_count(), 3);
+    fn test_wasm_suspend_events() {
+        let doc = WasmDocument::new();
+        doc.suspend_events();
+        doc.insert_text("Hello", 0).unwrap();
+        doc.insert_text(" World", 5).unwrap();
+        assert!(doc.is_events_suspended());
+        doc.resume_events();
+
+        assert!(!doc.is_events_suspended());
+        assert_eq!(doc.state().inner.get_dirty_regions().len(), 1);
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_get_word_count_with_newlines() {
-        let doc = WasmDocument::from_text("Hello\nworld\ntest");
-        assert_eq!(doc.get_word_count(), 3);
+    fn test_wasm_acknowledge_dirty() {
+        let doc = WasmDocument::from_text("Hello World");
+        doc.clear_dirty_flags();
+        doc.insert_text("!", 11).unwrap();
+        let painted = doc.get_version();
+        doc.insert_text(">", 0).unwrap();
+
+        doc.acknowledge_dirty(painted);
+        let state = doc.state();
+        let regions = state.inner.dirty_regions();
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].range, Range::from_offsets(0, 1));
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_get_word_count_whitespace_only() {
-        let doc = WasmDocument::from_text("   \n\t  ");
-        assert_eq!(doc.get_word_count(), 0);
+    fn test_wasm_rtf_roundtrip() {
+        let doc = WasmDocument::from_text("Hello");
+        doc.apply_format("bold", 0, 5).unwrap();
+        let rtf = doc.to_rtf();
+        assert!(rtf.contains(r"{\b Hello}\par"));
+
+        let restored = WasmDocument::from_rtf(&rtf).unwrap();
+        assert_eq!(restored.get_content(), "Hello");
+        assert!(WasmDocument::from_rtf("Hello").is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_get_line_count_empty() {
-        let doc = WasmDocument::new();
-        assert_eq!(doc.get_line_count(), 1);
+    fn test_wasm_list_style() {
+        let doc = WasmDocument::from_text("One\nTwo");
+        doc.set_block_type("ordered-list", 0, 7).unwrap();
+        assert_eq!(doc.get_list_marker_at(4).as_deref(), Some("2."));
+        assert_eq!(doc.get_list_style_at(4), None);
+
+        doc.set_list_style(0, 7, Some("lower-roman".to_string()))
+            .unwrap();
+        assert_eq!(doc.get_list_style_at(4).as_deref(), Some("lower-roman"));
+        assert_eq!(doc.get_list_marker_at(4).as_deref(), Some("ii."));
+
+        doc.set_list_style(0, 7, Some("→".to_string())).unwrap();
+        assert_eq!(doc.get_list_style_at(0).as_deref(), Some("→"));
+        assert_eq!(doc.get_list_marker_at(0).as_deref(), Some("→"));
+
+        doc.set_list_style(0, 7, None).unwrap();
+        assert_eq!(doc.get_list_marker_at(0).as_deref(), Some("1."));
+        assert!(
+            doc.set_list_style(0, 7, Some("circle".to_string()))
+                .is_err()
+        );
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_get_line_count_single_line() {
-        let doc = WasmDocument::from_text("Hello world");
-        assert_eq!(doc.get_line_count(), 1);
+    fn test_wasm_block_indent() {
+        let doc = WasmDocument::from_text("One\nTwo");
+        doc.increase_block_indent(0, 7).unwrap();
+        doc.increase_block_indent(4, 4).unwrap();
+        assert_eq!(doc.get_block_indent_at(0), 1);
+        assert_eq!(doc.get_block_indent_at(4), 2);
+        doc.decrease_block_indent(0, 7).unwrap();
+        assert_eq!(doc.get_block_indent_at(0), 0);
+        assert_eq!(doc.get_block_indent_at(4), 1);
+
+        doc.set_first_line_indent(0, 0, true).unwrap();
+        assert!(doc.has_first_line_indent_at(0));
+        assert!(!doc.has_first_line_indent_at(4));
+        assert!(doc.increase_block_indent(0, 99).is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_get_line_count_multiple_lines() {
-        let doc = WasmDocument::from_text("Line 1\nLine 2\nLine 3");
-        assert_eq!(doc.get_line_count(), 3);
+    fn test_wasm_text_direction() {
+        let doc = WasmDocument::from_text("ab אבג\nxyz");
+        assert_eq!(doc.get_base_direction_at(0), "ltr");
+        assert_eq!(doc.get_text_direction_at(0), None);
+        assert_eq!(doc.get_visual_order(0), vec![0, 1, 2, 5, 4, 3]);
+        assert_eq!(doc.get_bidi_runs(0).length(), 2);
+
+        doc.set_text_direction(7, 7, Some("rtl".to_string()))
+            .unwrap();
+        assert_eq!(doc.get_text_direction_at(8), Some("rtl".to_string()));
+        assert!(doc.to_html().contains("<p dir=\"rtl\">xyz</p>"));
+        doc.set_text_direction(7, 7, None).unwrap();
+        assert_eq!(doc.get_text_direction_at(8), None);
+        assert!(
+            doc.set_text_direction(0, 0, Some("up".to_string()))
+                .is_err()
+        );
+
+        doc.set_selection(2, 2);
+        doc.move_cursor_visually(true);
+        let sel = js_sys::Object::from(doc.get_selection());
+        let focus = js_sys::Reflect::get(&sel, &"focus".into()).unwrap();
+        assert_eq!(focus.as_f64().unwrap() as usize, 6);
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_get_line_count_trailing_newline() {
-        let doc = WasmDocument::from_text("Line 1\nLine 2\n");
-        assert_eq!(doc.get_line_count(), 3);
+    fn test_wasm_list_indent() {
+        let doc = WasmDocument::from_text("One\nTwo");
+        doc.set_block_type("bulletList", 0, 7).unwrap();
+        doc.indent_list_item(4, 7).unwrap();
+        assert_eq!(doc.get_list_indent_at(4), 1);
+        assert!(doc.to_html().contains("<li>One\n<ul>\n<li>Two</li>"));
+
+        doc.outdent_list_item(4, 7).unwrap();
+        assert_eq!(doc.get_list_indent_at(4), 0);
+        assert!(doc.indent_list_item(0, 99).is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_wasm_get_line_count_only_newlines() {
-        let doc = WasmDocument::from_text("\n\n\n");
-        assert_eq!(doc.get_line_count(), 4);
+    fn test_wasm_navigation_history() {
+        let doc = WasmDocument::from_text("cat dog cat");
+        doc.enable_navigation_history(None);
+        assert!(!doc.can_navigate_back());
+
+        doc.jump_to(4, 7);
+        assert!(!doc.find_next("cat", true, false, None).unwrap().is_null());
+        assert!(doc.navigate_back());
+        assert!(doc.can_navigate_forward());
+        assert!(doc.navigate_forward());
+
+        doc.disable_navigation_history();
+        assert!(!doc.navigate_back());
     }
 }
@@ -231,6 +231,17 @@ impl From<CommandError> for EditorError {
                 operation: "history".to_string(),
                 reason: format!("History limit of {} commands reached", limit),
             },
+            CommandError::ReadOnly => EditorError::CommandFailed {
+                operation: "edit".to_string(),
+                reason: "Document is read-only".to_string(),
+            },
+            CommandError::MaxLengthExceeded { length, limit } => EditorError::CommandFailed {
+                operation: "edit".to_string(),
+                reason: format!("Length {} would exceed the maximum of {}", length, limit),
+            },
+            CommandError::UnknownHistoryNode { id } => EditorError::UndoFailed {
+                reason: format!("History node {} does not exist", id),
+            },
         }
     }
 }
@@ -250,6 +261,27 @@ impl From<JsonError> for EditorError {
             JsonError::InvalidBlock(msg) => EditorError::JsonDeserializationError {
                 message: format!("Invalid block data: {}", msg),
             },
+            JsonError::InvalidImage(msg) => EditorError::JsonDeserializationError {
+                message: format!("Invalid image data: {}", msg),
+            },
+            JsonError::InvalidAnnotation(msg) => EditorError::JsonDeserializationError {
+                message: format!("Invalid annotation data: {}", msg),
+            },
+            JsonError::InvalidBookmark(msg) => EditorError::JsonDeserializationError {
+                message: format!("Invalid bookmark data: {}", msg),
+            },
+            JsonError::InvalidSelection(msg) => EditorError::JsonDeserializationError {
+                message: format!("Invalid selection data: {}", msg),
+            },
+            JsonError::InvalidNoSpellcheck(msg) => EditorError::JsonDeserializationError {
+                message: format!("Invalid spellcheck range: {}", msg),
+            },
+            JsonError::InvalidDecoration(msg) => EditorError::JsonDeserializationError {
+                message: format!("Invalid decoration data: {}", msg),
+            },
+            JsonError::UnsupportedSection(section) => EditorError::JsonDeserializationError {
+                message: format!("Unsupported required section: {}", section),
+            },
         }
     }
 }
@@ -261,6 +293,9 @@ impl From<MarkdownError> for EditorError {
             MarkdownError::InvalidFormat(msg) => EditorError::MarkdownParseError {
                 message: format!("Invalid format: {}", msg),
             },
+            MarkdownError::LimitExceeded(err) => EditorError::MarkdownParseError {
+                message: err.to_string(),
+            },
         }
     }
 }
@@ -275,6 +310,9 @@ impl From<HtmlError> for EditorError {
             HtmlError::SanitizationError(msg) => {
                 EditorError::HtmlSanitizationError { message: msg }
             }
+            HtmlError::LimitExceeded(err) => EditorError::HtmlParseError {
+                message: err.to_string(),
+            },
         }
     }
 }
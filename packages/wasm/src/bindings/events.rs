@@ -1,22 +1,369 @@
 // Event callback system for JavaScript callbacks
 
+use crate::document::{ContentChange, Range};
+use crate::formatting::{DEFAULT_FRAGMENTATION_THRESHOLD, FragmentationStats};
+use crate::operations::clipboard::SuppressedPaste;
 use js_sys::Function;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 
-/// Manages event callbacks for document changes and selection changes
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = queueMicrotask)]
+    fn queue_microtask(callback: &JsValue);
+}
+
+/// Error thrown when a callback calls a method of the document whose method
+/// triggered it
+pub(crate) const REENTRANT_CALL_ERROR: &str = "WasmDocument: a callback called a document method while the method that \
+     triggered it was still running; wrap the call in \
+     WasmDocument.defer(() => ...) to run it once that method is done";
+
+thread_local! {
+    /// Number of event callbacks currently running
+    static DISPATCH_DEPTH: Cell<usize> = const { Cell::new(0) };
+    /// Functions passed to `defer` while event callbacks were running
+    static DEFERRED: RefCell<Vec<Function>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Calls an event callback, ignoring any error it throws
+///
+/// Callbacks usually run inside the document method that made the change;
+/// document methods they call throw `REENTRANT_CALL_ERROR` to them at once.
+fn call_listener(callback: &Function, arg: Option<&JsValue>) {
+    DISPATCH_DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let _ = match arg {
+        Some(arg) => callback.call1(&JsValue::NULL, arg),
+        None => callback.call0(&JsValue::NULL),
+    };
+    DISPATCH_DEPTH.with(|depth| depth.set(depth.get() - 1));
+}
+
+/// Runs `callback` now, or once the running document method has returned
+/// if called from an event callback
+///
+/// Deferred functions run in a microtask, in the order they were deferred.
+///
+/// # Returns
+/// What `callback` returned, or undefined if it was deferred
+pub fn defer(callback: Function) -> Result<JsValue, JsValue> {
+    if DISPATCH_DEPTH.with(Cell::get) == 0 {
+        return callback.call0(&JsValue::NULL);
+    }
+    let first = DEFERRED.with(|deferred| {
+        let mut deferred = deferred.borrow_mut();
+        deferred.push(callback);
+        deferred.len() == 1
+    });
+    if first {
+        queue_microtask(&Closure::once_into_js(run_deferred));
+    }
+    Ok(JsValue::UNDEFINED)
+}
+
+/// Runs the functions queued by `defer`, throwing the first error once all
+/// of them have run
+fn run_deferred() -> Result<(), JsValue> {
+    let callbacks = DEFERRED.with(|deferred| std::mem::take(&mut *deferred.borrow_mut()));
+    let mut first_error = None;
+    for callback in callbacks {
+        if let Err(error) = callback.call0(&JsValue::NULL) {
+            first_error.get_or_insert(error);
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+/// A content change delivered to change callbacks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    /// "insert", "delete", "replace", "format", or "batch" for the merged
+    /// event standing for several held-back changes
+    pub kind: &'static str,
+    /// The replaced span before the edit, or the affected span after it for
+    /// format and batch events
+    pub range: Range,
+    /// Inserted text, or None for batch events
+    pub inserted_text: Option<String>,
+    /// Number of removed characters, or None for batch events
+    pub deleted_length: Option<usize>,
+    /// Document version after the change
+    pub version: u64,
+    /// The changes a batch event stands for, oldest first; empty otherwise
+    pub changes: Vec<ChangeEvent>,
+}
+
+impl ChangeEvent {
+    /// Creates the event for a recorded change, using `version` if the
+    /// change has none
+    pub fn from_change(change: ContentChange, version: u64) -> Self {
+        Self {
+            kind: change.kind.as_str(),
+            range: change.range,
+            inserted_text: Some(change.inserted_text),
+            deleted_length: Some(change.deleted_length),
+            version: change.version.unwrap_or(version),
+            changes: Vec::new(),
+        }
+    }
+
+    /// Creates the batch event standing for `changes`
+    ///
+    /// The range spans everything the changes touched, in the document as
+    /// it is after the last one.
+    pub fn merged(changes: Vec<ChangeEvent>) -> Self {
+        let mut span: Option<(usize, usize)> = None;
+        for change in &changes {
+            let start = change.range.start_offset();
+            let old_end = change.range.end_offset();
+            // Text edits move what follows them; format changes do not
+            let new_end = match &change.inserted_text {
+                Some(text) if change.kind != "format" => start + text.chars().count(),
+                _ => old_end,
+            };
+            let moved = |offset: usize| {
+                if offset >= old_end {
+                    offset + new_end - old_end
+                } else {
+                    offset.min(start)
+                }
+            };
+            span = Some(match span {
+                None => (start, new_end),
+                Some((a, b)) => (moved(a).min(start), moved(b).max(new_end)),
+            });
+        }
+        let (start, end) = span.unwrap_or((0, 0));
+        Self {
+            kind: "batch",
+            range: Range::from_offsets(start, end),
+            inserted_text: None,
+            deleted_length: None,
+            version: changes.last().map_or(0, |change| change.version),
+            changes,
+        }
+    }
+
+    /// Converts the event to a `{type, range: {start, end}, insertedText,
+    /// deletedLength, version}` object, with null for missing fields
+    ///
+    /// Batch events also get a `changes` array of the events they stand for.
+    pub fn to_js(&self) -> JsValue {
+        let range = js_sys::Object::new();
+        js_sys::Reflect::set(&range, &"start".into(), &self.range.start_offset().into()).unwrap();
+        js_sys::Reflect::set(&range, &"end".into(), &self.range.end_offset().into()).unwrap();
+
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"type".into(), &self.kind.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"range".into(), &range).unwrap();
+        js_sys::Reflect::set(
+            &obj,
+            &"insertedText".into(),
+            &self
+                .inserted_text
+                .as_deref()
+                .map_or(JsValue::NULL, JsValue::from),
+        )
+        .unwrap();
+        js_sys::Reflect::set(
+            &obj,
+            &"deletedLength".into(),
+            &self.deleted_length.map_or(JsValue::NULL, JsValue::from),
+        )
+        .unwrap();
+        js_sys::Reflect::set(&obj, &"version".into(), &(self.version as f64).into()).unwrap();
+        if self.kind == "batch" {
+            let changes: js_sys::Array = self.changes.iter().map(ChangeEvent::to_js).collect();
+            js_sys::Reflect::set(&obj, &"changes".into(), &changes).unwrap();
+        }
+        obj.into()
+    }
+}
+
+/// When change and selection events reach their callbacks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EventBatching {
+    /// Each event is delivered as it happens, unless suspended
+    #[default]
+    Immediate,
+    /// Events are held back and delivered together in a microtask, so all
+    /// edits made by one script turn arrive as one event
+    Microtask,
+}
+
+/// Events held back while suspended or waiting for a microtask
+#[derive(Debug, Default)]
+struct PendingEvents {
+    changes: Vec<ChangeEvent>,
+    selection: bool,
+    /// Whether a microtask flush has been queued
+    scheduled: bool,
+}
+
+/// Change and selection callbacks with their held-back events, shared with
+/// queued microtask flushes
+#[derive(Default)]
+struct Listeners {
+    change: RefCell<Vec<Function>>,
+    selection: RefCell<Vec<Function>>,
+    pending: RefCell<PendingEvents>,
+}
+
+impl Listeners {
+    /// Calls every change callback with `event`
+    fn emit_change(&self, event: &ChangeEvent) {
+        // Clone the list so callbacks can register or remove callbacks
+        let callbacks = self.change.borrow().clone();
+        if callbacks.is_empty() {
+            return;
+        }
+        let value = event.to_js();
+        for callback in &callbacks {
+            call_listener(callback, Some(&value));
+        }
+    }
+
+    /// Calls every selection callback
+    fn emit_selection(&self) {
+        let callbacks = self.selection.borrow().clone();
+        for callback in &callbacks {
+            call_listener(callback, None);
+        }
+    }
+
+    /// Delivers the held-back events: changes as one event, or unchanged
+    /// if there was just one, then one selection event
+    fn flush(&self) {
+        let (mut changes, selection) = {
+            let mut pending = self.pending.borrow_mut();
+            (
+                std::mem::take(&mut pending.changes),
+                std::mem::take(&mut pending.selection),
+            )
+        };
+        match changes.len() {
+            0 => {}
+            1 => self.emit_change(&changes.remove(0)),
+            _ => self.emit_change(&ChangeEvent::merged(changes)),
+        }
+        if selection {
+            self.emit_selection();
+        }
+    }
+}
+
+/// Undo/redo availability reported to history callbacks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HistoryState {
+    pub can_undo: bool,
+    pub can_redo: bool,
+    /// Label of the change undo would revert, such as "typing"
+    pub undo_description: Option<&'static str>,
+    /// Label of the change redo would reapply
+    pub redo_description: Option<&'static str>,
+}
+
+impl HistoryState {
+    /// Converts the state to a `{canUndo, canRedo, undoDescription,
+    /// redoDescription}` object, with null for missing descriptions
+    pub fn to_js(&self) -> JsValue {
+        let description = |label: Option<&str>| label.map_or(JsValue::NULL, JsValue::from);
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"canUndo".into(), &self.can_undo.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"canRedo".into(), &self.can_redo.into()).unwrap();
+        js_sys::Reflect::set(
+            &obj,
+            &"undoDescription".into(),
+            &description(self.undo_description),
+        )
+        .unwrap();
+        js_sys::Reflect::set(
+            &obj,
+            &"redoDescription".into(),
+            &description(self.redo_description),
+        )
+        .unwrap();
+        obj.into()
+    }
+}
+
+/// Converts fragmentation stats to a `{formatRuns, runsPer1000Chars,
+/// averageRunLength, mergeEvents}` object
+pub fn fragmentation_to_js(stats: &FragmentationStats) -> JsValue {
+    let obj = js_sys::Object::new();
+    js_sys::Reflect::set(&obj, &"formatRuns".into(), &stats.run_count.into()).unwrap();
+    js_sys::Reflect::set(
+        &obj,
+        &"runsPer1000Chars".into(),
+        &stats.runs_per_thousand_chars.into(),
+    )
+    .unwrap();
+    js_sys::Reflect::set(
+        &obj,
+        &"averageRunLength".into(),
+        &stats.average_run_length.into(),
+    )
+    .unwrap();
+    js_sys::Reflect::set(
+        &obj,
+        &"mergeEvents".into(),
+        &(stats.merge_count as f64).into(),
+    )
+    .unwrap();
+    obj.into()
+}
+
+/// Converts a suppressed paste to a `{text, offset, elapsedMs}` object
+pub fn suppressed_paste_to_js(paste: &SuppressedPaste) -> JsValue {
+    let obj = js_sys::Object::new();
+    js_sys::Reflect::set(&obj, &"text".into(), &paste.text.as_str().into()).unwrap();
+    js_sys::Reflect::set(&obj, &"offset".into(), &paste.offset.into()).unwrap();
+    js_sys::Reflect::set(
+        &obj,
+        &"elapsedMs".into(),
+        &(paste.elapsed.as_secs_f64() * 1000.0).into(),
+    )
+    .unwrap();
+    obj.into()
+}
+
+/// Manages event callbacks for document, selection and history changes
 pub struct EventCallbacks {
-    /// Callbacks triggered when document content changes
-    change_callbacks: Vec<Function>,
-    /// Callbacks triggered when selection changes
-    selection_callbacks: Vec<Function>,
+    /// Change and selection callbacks, and the events held back for them
+    listeners: Rc<Listeners>,
+    /// Callbacks triggered when undo/redo availability changes
+    history_callbacks: Vec<Function>,
+    /// The history state last delivered to history callbacks
+    last_history: RefCell<HistoryState>,
+    /// Callbacks triggered when the formatting becomes fragmented
+    fragmentation_callbacks: Vec<Function>,
+    /// Runs per 1000 characters above which formatting is fragmented
+    fragmentation_threshold: f64,
+    /// Whether fragmentation callbacks have been warned since the formatting
+    /// was last below the threshold
+    fragmented: Cell<bool>,
+    /// Callbacks triggered when a duplicate paste is suppressed
+    duplicate_paste_callbacks: Vec<Function>,
+    /// Number of open `suspend` calls; callbacks only run at zero
+    suspend_depth: usize,
+    /// When change and selection events are delivered
+    batching: EventBatching,
 }
 
 impl EventCallbacks {
     /// Creates a new EventCallbacks instance with empty callback vectors
     pub fn new() -> Self {
         Self {
-            change_callbacks: Vec::new(),
-            selection_callbacks: Vec::new(),
+            listeners: Rc::new(Listeners::default()),
+            history_callbacks: Vec::new(),
+            last_history: RefCell::new(HistoryState::default()),
+            fragmentation_callbacks: Vec::new(),
+            fragmentation_threshold: DEFAULT_FRAGMENTATION_THRESHOLD,
+            fragmented: Cell::new(false),
+            duplicate_paste_callbacks: Vec::new(),
+            suspend_depth: 0,
+            batching: EventBatching::default(),
         }
     }
 
@@ -25,7 +372,7 @@ impl EventCallbacks {
     /// # Arguments
     /// * `callback` - JavaScript function to call when document changes
     pub fn add_change_callback(&mut self, callback: Function) {
-        self.change_callbacks.push(callback);
+        self.listeners.change.borrow_mut().push(callback);
     }
 
     /// Registers a callback for selection change events
@@ -33,7 +380,31 @@ impl EventCallbacks {
     /// # Arguments
     /// * `callback` - JavaScript function to call when selection changes
     pub fn add_selection_callback(&mut self, callback: Function) {
-        self.selection_callbacks.push(callback);
+        self.listeners.selection.borrow_mut().push(callback);
+    }
+
+    /// Registers a callback for undo/redo history events
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to call with the new `HistoryState`
+    pub fn add_history_callback(&mut self, callback: Function) {
+        self.history_callbacks.push(callback);
+    }
+
+    /// Registers a callback for fragmentation warnings
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to call with the fragmentation stats
+    pub fn add_fragmentation_callback(&mut self, callback: Function) {
+        self.fragmentation_callbacks.push(callback);
+    }
+
+    /// Registers a callback for suppressed duplicate pastes
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to call with the suppressed paste
+    pub fn add_duplicate_paste_callback(&mut self, callback: Function) {
+        self.duplicate_paste_callbacks.push(callback);
     }
 
     /// Removes a specific change callback
@@ -46,11 +417,12 @@ impl EventCallbacks {
     pub fn remove_change_callback(&mut self, callback: &Function) -> bool {
         // Compare functions by their JsValue representation
         let callback_val: &JsValue = callback.as_ref();
-        if let Some(pos) = self.change_callbacks.iter().position(|cb| {
+        let mut callbacks = self.listeners.change.borrow_mut();
+        if let Some(pos) = callbacks.iter().position(|cb| {
             let cb_val: &JsValue = cb.as_ref();
             cb_val == callback_val
         }) {
-            self.change_callbacks.remove(pos);
+            callbacks.remove(pos);
             true
         } else {
             false
@@ -67,34 +439,240 @@ impl EventCallbacks {
     pub fn remove_selection_callback(&mut self, callback: &Function) -> bool {
         // Compare functions by their JsValue representation
         let callback_val: &JsValue = callback.as_ref();
-        if let Some(pos) = self.selection_callbacks.iter().position(|cb| {
+        let mut callbacks = self.listeners.selection.borrow_mut();
+        if let Some(pos) = callbacks.iter().position(|cb| {
             let cb_val: &JsValue = cb.as_ref();
             cb_val == callback_val
         }) {
-            self.selection_callbacks.remove(pos);
+            callbacks.remove(pos);
             true
         } else {
             false
         }
     }
 
-    /// Triggers all registered change callbacks
+    /// Removes a specific history callback
     ///
-    /// Errors from individual callbacks are caught and silently ignored
-    pub fn trigger_change_callbacks(&self) {
-        for callback in &self.change_callbacks {
-            // Call the callback and ignore any errors
-            let _ = callback.call0(&JsValue::NULL);
+    /// # Arguments
+    /// * `callback` - JavaScript function to remove from history callbacks
+    ///
+    /// # Returns
+    /// true if the callback was found and removed, false otherwise
+    pub fn remove_history_callback(&mut self, callback: &Function) -> bool {
+        let callback_val: &JsValue = callback.as_ref();
+        if let Some(pos) = self.history_callbacks.iter().position(|cb| {
+            let cb_val: &JsValue = cb.as_ref();
+            cb_val == callback_val
+        }) {
+            self.history_callbacks.remove(pos);
+            true
+        } else {
+            false
         }
     }
 
+    /// Removes a specific fragmentation callback
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to remove from fragmentation callbacks
+    ///
+    /// # Returns
+    /// true if the callback was found and removed, false otherwise
+    pub fn remove_fragmentation_callback(&mut self, callback: &Function) -> bool {
+        let callback_val: &JsValue = callback.as_ref();
+        if let Some(pos) = self.fragmentation_callbacks.iter().position(|cb| {
+            let cb_val: &JsValue = cb.as_ref();
+            cb_val == callback_val
+        }) {
+            self.fragmentation_callbacks.remove(pos);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes a specific duplicate paste callback
+    ///
+    /// # Arguments
+    /// * `callback` - JavaScript function to remove from duplicate paste callbacks
+    ///
+    /// # Returns
+    /// true if the callback was found and removed, false otherwise
+    pub fn remove_duplicate_paste_callback(&mut self, callback: &Function) -> bool {
+        let callback_val: &JsValue = callback.as_ref();
+        if let Some(pos) = self.duplicate_paste_callbacks.iter().position(|cb| {
+            let cb_val: &JsValue = cb.as_ref();
+            cb_val == callback_val
+        }) {
+            self.duplicate_paste_callbacks.remove(pos);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns true if any fragmentation callbacks are registered
+    pub fn has_fragmentation_callbacks(&self) -> bool {
+        !self.fragmentation_callbacks.is_empty()
+    }
+
+    /// Sets the runs per 1000 characters above which fragmentation
+    /// callbacks are warned
+    pub fn set_fragmentation_threshold(&mut self, threshold: f64) {
+        self.fragmentation_threshold = threshold;
+    }
+
+    /// Suspends callbacks until a matching `resume`
+    ///
+    /// Calls nest; events triggered while suspended are remembered and
+    /// delivered once by `flush_pending` after the outermost `resume`.
+    pub fn suspend(&mut self) {
+        self.suspend_depth += 1;
+    }
+
+    /// Ends one `suspend` call
+    ///
+    /// # Returns
+    /// true if this ended the outermost suspension
+    pub fn resume(&mut self) -> bool {
+        if self.suspend_depth == 0 {
+            return false;
+        }
+        self.suspend_depth -= 1;
+        self.suspend_depth == 0
+    }
+
+    /// Returns true if callbacks are currently suspended
+    pub fn is_suspended(&self) -> bool {
+        self.suspend_depth > 0
+    }
+
+    /// Returns when change and selection events are delivered
+    pub fn batching(&self) -> EventBatching {
+        self.batching
+    }
+
+    /// Sets when change and selection events are delivered
+    ///
+    /// Leaving microtask batching delivers the events it held back.
+    pub fn set_batching(&mut self, batching: EventBatching) {
+        self.batching = batching;
+        if batching == EventBatching::Immediate && !self.is_suspended() {
+            self.flush_pending();
+        }
+    }
+
+    /// Returns true if a change event is waiting to be delivered
+    pub fn has_pending_change(&self) -> bool {
+        !self.listeners.pending.borrow().changes.is_empty()
+    }
+
+    /// Delivers the events held back so far
+    ///
+    /// Held-back changes arrive as one batch event, or unchanged if there
+    /// was only one, followed by at most one selection event.
+    pub fn flush_pending(&self) {
+        self.listeners.flush();
+    }
+
+    /// Returns true if events should be held back rather than delivered,
+    /// queueing a microtask flush if microtask batching needs one
+    fn hold_back(&self) -> bool {
+        if self.is_suspended() {
+            return true;
+        }
+        if self.batching == EventBatching::Immediate {
+            return false;
+        }
+        let mut pending = self.listeners.pending.borrow_mut();
+        if !pending.scheduled {
+            pending.scheduled = true;
+            let listeners = Rc::clone(&self.listeners);
+            queue_microtask(&Closure::once_into_js(move || {
+                listeners.pending.borrow_mut().scheduled = false;
+                listeners.flush();
+            }));
+        }
+        true
+    }
+
+    /// Triggers all registered change callbacks with `event`
+    ///
+    /// Errors from individual callbacks are caught and silently ignored.
+    /// While suspended or batching, the event is held back instead.
+    pub fn trigger_change_callbacks(&self, event: ChangeEvent) {
+        if self.hold_back() {
+            self.listeners.pending.borrow_mut().changes.push(event);
+            return;
+        }
+        self.listeners.emit_change(&event);
+    }
+
+    /// Returns true if any change callbacks are registered
+    pub fn has_change_callbacks(&self) -> bool {
+        !self.listeners.change.borrow().is_empty()
+    }
+
     /// Triggers all registered selection callbacks
     ///
-    /// Errors from individual callbacks are caught and silently ignored
+    /// Errors from individual callbacks are caught and silently ignored.
+    /// While suspended or batching, the event is held back instead.
     pub fn trigger_selection_callbacks(&self) {
-        for callback in &self.selection_callbacks {
-            // Call the callback and ignore any errors
-            let _ = callback.call0(&JsValue::NULL);
+        if self.hold_back() {
+            self.listeners.pending.borrow_mut().selection = true;
+            return;
+        }
+        self.listeners.emit_selection();
+    }
+
+    /// Triggers all registered history callbacks if `state` differs from
+    /// the state they last received
+    ///
+    /// Errors from individual callbacks are caught and silently ignored.
+    /// While suspended, nothing is delivered; the caller reports the state
+    /// again after resuming.
+    pub fn trigger_history_callbacks(&self, state: HistoryState) {
+        if self.is_suspended() || *self.last_history.borrow() == state {
+            return;
+        }
+        self.last_history.replace(state);
+        let value = state.to_js();
+        for callback in &self.history_callbacks {
+            call_listener(callback, Some(&value));
+        }
+    }
+
+    /// Triggers all registered fragmentation callbacks with `stats` if they
+    /// cross the threshold
+    ///
+    /// Callbacks are warned once when the formatting becomes fragmented, and
+    /// again only after it has dropped back below the threshold, for example
+    /// through normalization. Errors from individual callbacks are caught
+    /// and silently ignored. While suspended, nothing is delivered; the
+    /// caller reports the stats again after resuming.
+    pub fn trigger_fragmentation_callbacks(&self, stats: &FragmentationStats) {
+        if self.is_suspended() {
+            return;
+        }
+        let fragmented = stats.exceeds(self.fragmentation_threshold);
+        if self.fragmented.replace(fragmented) || !fragmented {
+            return;
+        }
+        let value = fragmentation_to_js(stats);
+        for callback in &self.fragmentation_callbacks {
+            call_listener(callback, Some(&value));
+        }
+    }
+
+    /// Triggers all registered duplicate paste callbacks with `paste`
+    ///
+    /// Errors from individual callbacks are caught and silently ignored.
+    /// Unlike change events, suppressed pastes are delivered even while
+    /// callbacks are suspended, since nothing reports them later.
+    pub fn trigger_duplicate_paste_callbacks(&self, paste: &SuppressedPaste) {
+        let value = suppressed_paste_to_js(paste);
+        for callback in &self.duplicate_paste_callbacks {
+            call_listener(callback, Some(&value));
         }
     }
 
@@ -113,8 +691,11 @@ impl EventCallbacks {
     /// - Circular references are broken
     /// - Memory leaks are prevented
     pub fn clear_all(&mut self) {
-        self.change_callbacks.clear();
-        self.selection_callbacks.clear();
+        self.listeners.change.borrow_mut().clear();
+        self.listeners.selection.borrow_mut().clear();
+        self.history_callbacks.clear();
+        self.fragmentation_callbacks.clear();
+        self.duplicate_paste_callbacks.clear();
     }
 }
 
@@ -129,13 +710,15 @@ mod tests {
     use super::*;
     use wasm_bindgen_test::*;
 
-
+    fn typed_event() -> ChangeEvent {
+        ChangeEvent::from_change(ContentChange::text(Range::from_offsets(0, 0), "a"), 1)
+    }
 
     #[wasm_bindgen_test]
     fn test_event_callbacks_creation() {
         let callbacks = EventCallbacks::new();
-        assert_eq!(callbacks.change_callbacks.len(), 0);
-        assert_eq!(callbacks.selection_callbacks.len(), 0);
+        assert_eq!(callbacks.listeners.change.borrow().len(), 0);
+        assert_eq!(callbacks.listeners.selection.borrow().len(), 0);
     }
 
     #[wasm_bindgen_test]
@@ -143,7 +726,7 @@ mod tests {
         let mut callbacks = EventCallbacks::new();
         let func = Function::new_no_args("return 42;");
         callbacks.add_change_callback(func);
-        assert_eq!(callbacks.change_callbacks.len(), 1);
+        assert_eq!(callbacks.listeners.change.borrow().len(), 1);
     }
 
     #[wasm_bindgen_test]
@@ -151,7 +734,7 @@ mod tests {
         let mut callbacks = EventCallbacks::new();
         let func = Function::new_no_args("return 42;");
         callbacks.add_selection_callback(func);
-        assert_eq!(callbacks.selection_callbacks.len(), 1);
+        assert_eq!(callbacks.listeners.selection.borrow().len(), 1);
     }
 
     #[wasm_bindgen_test]
@@ -160,7 +743,7 @@ mod tests {
         let func = Function::new_no_args("return 42;");
         callbacks.add_change_callback(func);
         // Should not panic
-        callbacks.trigger_change_callbacks();
+        callbacks.trigger_change_callbacks(typed_event());
     }
 
     #[wasm_bindgen_test]
@@ -182,7 +765,96 @@ mod tests {
 
         callbacks.clear_all();
 
-        assert_eq!(callbacks.change_callbacks.len(), 0);
-        assert_eq!(callbacks.selection_callbacks.len(), 0);
+        assert_eq!(callbacks.listeners.change.borrow().len(), 0);
+        assert_eq!(callbacks.listeners.selection.borrow().len(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_change_event_payload() {
+        let value = ChangeEvent::merged(vec![typed_event(), typed_event()]).to_js();
+        let get = |key: &str| js_sys::Reflect::get(&value, &key.into()).unwrap();
+        assert_eq!(get("type").as_string().as_deref(), Some("batch"));
+        assert!(get("insertedText").is_null());
+        assert_eq!(get("version").as_f64(), Some(1.0));
+        assert_eq!(js_sys::Array::from(&get("changes")).length(), 2);
+        let end = js_sys::Reflect::get(&get("range"), &"end".into()).unwrap();
+        assert_eq!(end.as_f64(), Some(2.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_merged_range_follows_later_edits() {
+        let change = |start: usize, end: usize, text: &str| {
+            ChangeEvent::from_change(
+                ContentChange::text(Range::from_offsets(start, end), text),
+                1,
+            )
+        };
+        // Typing "abc" at 10, then deleting two characters before it
+        let merged = ChangeEvent::merged(vec![change(10, 10, "abc"), change(2, 4, "")]);
+        assert_eq!(merged.range, Range::from_offsets(2, 11));
+
+        // A format change does not move the span of earlier edits
+        let format = ChangeEvent::from_change(ContentChange::format(Range::from_offsets(0, 1)), 2);
+        let merged = ChangeEvent::merged(vec![change(5, 5, "xy"), format]);
+        assert_eq!(merged.range, Range::from_offsets(0, 7));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_defer_runs_now_outside_callbacks() {
+        let result = defer(Function::new_no_args("return 7;")).unwrap();
+        assert_eq!(result.as_f64(), Some(7.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_defer_queues_inside_callbacks() {
+        let mut callbacks = EventCallbacks::new();
+        let deferred = Rc::new(Cell::new(None));
+        let seen = Rc::clone(&deferred);
+        let listener = Closure::<dyn Fn(JsValue)>::new(move |_| {
+            seen.set(Some(defer(Function::new_no_args("return 7;")).unwrap()));
+        });
+        callbacks.add_change_callback(listener.as_ref().unchecked_ref::<Function>().clone());
+
+        callbacks.trigger_change_callbacks(typed_event());
+        assert_eq!(deferred.take(), Some(JsValue::UNDEFINED));
+        assert_eq!(DEFERRED.with(|deferred| deferred.borrow().len()), 1);
+        assert!(run_deferred().is_ok());
+        assert_eq!(DEFERRED.with(|deferred| deferred.borrow().len()), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_history_callbacks_skip_unchanged_state() {
+        let mut callbacks = EventCallbacks::new();
+        let counter = js_sys::Object::new();
+        js_sys::Reflect::set(&counter, &"calls".into(), &0.into()).unwrap();
+        let func = Function::new_with_args("state", "this.calls += 1;").bind0(&counter);
+        callbacks.add_history_callback(func);
+
+        let typed = HistoryState {
+            can_undo: true,
+            undo_description: Some("typing"),
+            ..HistoryState::default()
+        };
+        callbacks.trigger_history_callbacks(HistoryState::default());
+        callbacks.trigger_history_callbacks(typed);
+        callbacks.trigger_history_callbacks(typed);
+        let calls = js_sys::Reflect::get(&counter, &"calls".into()).unwrap();
+        assert_eq!(calls.as_f64(), Some(1.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_suspend_defers_events() {
+        let mut callbacks = EventCallbacks::new();
+        callbacks.suspend();
+        callbacks.suspend();
+        callbacks.trigger_change_callbacks(typed_event());
+        callbacks.trigger_change_callbacks(typed_event());
+        assert!(callbacks.has_pending_change());
+
+        assert!(!callbacks.resume());
+        assert!(callbacks.resume());
+        assert!(!callbacks.resume());
+        callbacks.flush_pending();
+        assert!(!callbacks.has_pending_change());
     }
 }
@@ -69,11 +69,13 @@
 //! - `pattern`: string - The text pattern to search for
 //! - `case_sensitive`: boolean - Whether the search should be case-sensitive
 //! - `use_regex`: boolean - Whether to interpret the pattern as a regular expression
+//! - `ignore_accents`: boolean (optional) - Whether accented letters match their base letter
 //!
 //! Example:
 //! ```typescript
 //! const matches = doc.find("hello", false, false); // Case-insensitive literal search
 //! const regexMatches = doc.find("\\d+", true, true); // Case-sensitive regex search
+//! const accentMatches = doc.find("resume", false, false, true); // Also matches "résumé"
 //! ```
 
 pub mod document;
@@ -81,4 +83,4 @@ pub mod errors;
 pub mod events;
 
 // Re-export WasmDocument for easier access
-pub use document::WasmDocument;
+pub use document::{WasmDocument, WasmSnapshot, WasmTextChunks};
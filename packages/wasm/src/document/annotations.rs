@@ -0,0 +1,244 @@
+//! Annotation ranges
+//!
+//! An annotation attaches application metadata, such as a comment thread or
+//! a review note, to a range of text. Annotations are kept in
+//! `AnnotationStorage` and shift alongside text edits the same way format
+//! runs do. Unlike format runs they may overlap, are never merged, and do
+//! not grow when text is inserted at their edges.
+
+use super::{Position, Range};
+use serde::{Deserialize, Serialize};
+
+/// Metadata attached to a range of text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Annotation {
+    /// Identifier chosen by the application, unique within the document
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Application data, stored as given (typically JSON)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub payload: String,
+}
+
+impl Annotation {
+    /// Creates an annotation without author or payload
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            author: None,
+            payload: String::new(),
+        }
+    }
+
+    /// Sets the author of the annotation
+    pub fn with_author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    /// Sets the application data of the annotation
+    pub fn with_payload(mut self, payload: impl Into<String>) -> Self {
+        self.payload = payload.into();
+        self
+    }
+}
+
+/// Annotations with their ranges, sorted by range start
+#[derive(Debug, Clone, Default)]
+pub struct AnnotationStorage {
+    annotations: Vec<(Range, Annotation)>,
+}
+
+impl AnnotationStorage {
+    /// Creates empty annotation storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of stored annotations
+    pub fn len(&self) -> usize {
+        self.annotations.len()
+    }
+
+    /// Returns true if no annotations are stored
+    pub fn is_empty(&self) -> bool {
+        self.annotations.is_empty()
+    }
+
+    /// Stores an annotation, returning the one with the same id it replaced
+    pub fn insert(&mut self, range: Range, annotation: Annotation) -> Option<(Range, Annotation)> {
+        let previous = self.remove(&annotation.id);
+        let range = range.normalize();
+        let idx = self
+            .annotations
+            .partition_point(|(r, _)| r.start_offset() <= range.start_offset());
+        self.annotations.insert(idx, (range, annotation));
+        previous
+    }
+
+    /// Removes the annotation with the given id
+    pub fn remove(&mut self, id: &str) -> Option<(Range, Annotation)> {
+        self.annotations
+            .iter()
+            .position(|(_, annotation)| annotation.id == id)
+            .map(|idx| self.annotations.remove(idx))
+    }
+
+    /// Gets the annotation with the given id along with its range
+    pub fn get(&self, id: &str) -> Option<(Range, &Annotation)> {
+        self.annotations
+            .iter()
+            .find(|(_, annotation)| annotation.id == id)
+            .map(|(range, annotation)| (*range, annotation))
+    }
+
+    /// Returns the annotations covering the character at `offset`
+    pub fn annotations_at(&self, offset: usize) -> Vec<(Range, &Annotation)> {
+        self.annotations
+            .iter()
+            .take_while(|(range, _)| range.start_offset() <= offset)
+            .filter(|(range, _)| offset < range.end_offset())
+            .map(|(range, annotation)| (*range, annotation))
+            .collect()
+    }
+
+    /// Returns the annotations overlapping the range
+    pub fn annotations_in(&self, range: Range) -> Vec<(Range, Annotation)> {
+        let normalized = range.normalize();
+        self.annotations
+            .iter()
+            .filter(|(r, _)| r.overlaps(&normalized))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns all annotations with their ranges
+    pub fn annotations(&self) -> &[(Range, Annotation)] {
+        &self.annotations
+    }
+
+    /// Removes all annotations
+    pub fn clear(&mut self) {
+        self.annotations.clear();
+    }
+
+    /// Shifts annotations after text insertion
+    ///
+    /// Text inserted strictly inside an annotation extends it; text inserted
+    /// at either edge stays outside.
+    pub fn adjust_for_insert(&mut self, pos: Position, length: usize) {
+        let insert_offset = pos.offset();
+        for (range, _) in &mut self.annotations {
+            let start = range.start_offset();
+            let end = range.end_offset();
+            if start >= insert_offset {
+                *range = Range::from_offsets(start + length, end + length);
+            } else if end > insert_offset {
+                *range = Range::from_offsets(start, end + length);
+            }
+        }
+    }
+
+    /// Shrinks annotations overlapping a deleted range and shifts the ones
+    /// after it
+    ///
+    /// Annotations whose text is deleted entirely are dropped.
+    pub fn adjust_for_delete(&mut self, range: Range) {
+        let normalized = range.normalize();
+        let delete_start = normalized.start_offset();
+        let delete_end = normalized.end_offset();
+        let delete_length = delete_end - delete_start;
+
+        let shift = |offset: usize| {
+            if offset >= delete_end {
+                offset - delete_length
+            } else {
+                offset.min(delete_start)
+            }
+        };
+        for (range, _) in &mut self.annotations {
+            *range = Range::from_offsets(shift(range.start_offset()), shift(range.end_offset()));
+        }
+        self.annotations.retain(|(range, _)| !range.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_get_and_remove() {
+        let mut storage = AnnotationStorage::new();
+        assert!(
+            storage
+                .insert(Range::from_offsets(4, 8), Annotation::new("b"))
+                .is_none()
+        );
+        assert!(
+            storage
+                .insert(
+                    Range::from_offsets(0, 6),
+                    Annotation::new("a").with_author("ann")
+                )
+                .is_none()
+        );
+
+        assert_eq!(storage.len(), 2);
+        assert_eq!(storage.annotations()[0].1.id, "a");
+        let (range, annotation) = storage.get("a").unwrap();
+        assert_eq!(range, Range::from_offsets(0, 6));
+        assert_eq!(annotation.author.as_deref(), Some("ann"));
+
+        let at = storage.annotations_at(5);
+        assert_eq!(at.len(), 2);
+        assert_eq!(storage.annotations_at(6)[0].1.id, "b");
+        assert!(storage.annotations_at(8).is_empty());
+
+        let replaced = storage.insert(Range::from_offsets(1, 2), Annotation::new("b"));
+        assert_eq!(replaced.unwrap().0, Range::from_offsets(4, 8));
+        assert_eq!(storage.len(), 2);
+
+        assert!(storage.remove("a").is_some());
+        assert!(storage.remove("a").is_none());
+        assert_eq!(storage.len(), 1);
+    }
+
+    #[test]
+    fn test_adjust_for_insert() {
+        let mut storage = AnnotationStorage::new();
+        storage.insert(Range::from_offsets(2, 5), Annotation::new("a"));
+
+        // At the edges the annotation does not grow
+        storage.adjust_for_insert(Position::new(5), 1);
+        storage.adjust_for_insert(Position::new(2), 1);
+        assert_eq!(storage.get("a").unwrap().0, Range::from_offsets(3, 6));
+
+        storage.adjust_for_insert(Position::new(4), 2);
+        assert_eq!(storage.get("a").unwrap().0, Range::from_offsets(3, 8));
+    }
+
+    #[test]
+    fn test_adjust_for_delete() {
+        let mut storage = AnnotationStorage::new();
+        storage.insert(Range::from_offsets(0, 4), Annotation::new("before"));
+        storage.insert(Range::from_offsets(3, 5), Annotation::new("inside"));
+        storage.insert(Range::from_offsets(4, 10), Annotation::new("after"));
+
+        storage.adjust_for_delete(Range::from_offsets(2, 6));
+        assert_eq!(storage.get("before").unwrap().0, Range::from_offsets(0, 2));
+        assert!(storage.get("inside").is_none());
+        assert_eq!(storage.get("after").unwrap().0, Range::from_offsets(2, 6));
+    }
+
+    #[test]
+    fn test_annotation_serialization() {
+        let annotation = Annotation::new("c1").with_payload(r#"{"text":"Nice"}"#);
+        let json = serde_json::to_string(&annotation).unwrap();
+        assert_eq!(json, r#"{"id":"c1","payload":"{\"text\":\"Nice\"}"}"#);
+
+        let parsed: Annotation = serde_json::from_str(r#"{"id":"c1"}"#).unwrap();
+        assert_eq!(parsed, Annotation::new("c1"));
+    }
+}
@@ -0,0 +1,310 @@
+//! Content audit before publishing
+//!
+//! Sanitization happens when content is imported, but documents loaded from
+//! JSON, built through the API or saved by an older version may still hold
+//! content that is unsafe to publish. `Document::audit` reports it with the
+//! range it covers, leaving the document unchanged.
+
+use super::{Document, Range};
+use crate::formatting::{BlockType, InlineFormat};
+use crate::serialization::html::HtmlSanitizer;
+
+/// Longest run of non-whitespace characters allowed by default
+pub const DEFAULT_MAX_TOKEN_LENGTH: usize = 256;
+
+/// Kind of risky content found by an audit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditKind {
+    /// A link, image source or quote citation with a dangerous protocol,
+    /// such as `javascript:` or an unsafe `data:` URL
+    UnsafeUrl,
+    /// A run of characters without whitespace longer than the limit, which
+    /// can break layouts and hide content
+    LongToken,
+    /// Control characters, bidirectional overrides or invisible characters
+    /// that can make text display differently from what it contains
+    ControlCharacter,
+}
+
+/// Risky content found by an audit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditFinding {
+    pub kind: AuditKind,
+    /// Text the finding covers
+    pub range: Range,
+    /// The URL, the token length, or the code points found
+    pub detail: String,
+}
+
+/// Options for auditing a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuditOptions {
+    /// Longest run of non-whitespace characters allowed
+    pub max_token_length: usize,
+}
+
+impl Default for AuditOptions {
+    fn default() -> Self {
+        Self {
+            max_token_length: DEFAULT_MAX_TOKEN_LENGTH,
+        }
+    }
+}
+
+impl AuditOptions {
+    /// Creates options with the default limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the longest run of non-whitespace characters allowed
+    pub fn max_token_length(mut self, max_token_length: usize) -> Self {
+        self.max_token_length = max_token_length;
+        self
+    }
+}
+
+impl Document {
+    /// Scans the document for content that is risky to publish
+    ///
+    /// Reports unsafe link, image and citation URLs, overly long tokens,
+    /// and control, bidirectional override and invisible characters.
+    /// Findings are ordered by where they start.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::audit::{AuditKind, AuditOptions};
+    /// use rte_core::document::Document;
+    ///
+    /// let doc = Document::from_text("Invoice\u{202E}fdp.exe");
+    /// let findings = doc.audit(&AuditOptions::new());
+    /// assert_eq!(findings.len(), 1);
+    /// assert_eq!(findings[0].kind, AuditKind::ControlCharacter);
+    /// assert_eq!(findings[0].detail, "U+202E");
+    /// ```
+    pub fn audit(&self, options: &AuditOptions) -> Vec<AuditFinding> {
+        let mut findings = Vec::new();
+        self.audit_urls(&mut findings);
+        self.audit_text(options, &mut findings);
+        findings.sort_by_key(|finding| (finding.range.start_offset(), finding.range.end_offset()));
+        findings
+    }
+
+    /// Finds links, images and quote citations with unsafe URLs
+    fn audit_urls(&self, findings: &mut Vec<AuditFinding>) {
+        let sanitizer = HtmlSanitizer::new();
+        let whole = Range::from_offsets(0, self.get_length());
+
+        let mut links: Vec<AuditFinding> = Vec::new();
+        for (range, formats) in self.formats().styled_runs(whole) {
+            for format in formats {
+                let InlineFormat::Link { url } = format else {
+                    continue;
+                };
+                if sanitizer.is_safe_url(&url) {
+                    continue;
+                }
+                // Runs split by other formats report the link once
+                match links.iter_mut().find(|finding| {
+                    finding.detail == url && finding.range.end_offset() == range.start_offset()
+                }) {
+                    Some(finding) => {
+                        finding.range =
+                            Range::from_offsets(finding.range.start_offset(), range.end_offset());
+                    }
+                    None => links.push(unsafe_url(range, url)),
+                }
+            }
+        }
+        findings.extend(links);
+
+        for (offset, image) in self.embeds().images() {
+            if !image.has_safe_src() {
+                findings.push(unsafe_url(
+                    Range::from_offsets(*offset, offset + 1),
+                    image.src.clone(),
+                ));
+            }
+        }
+
+        // A block runs until the next one starts
+        let blocks = self.formats().get_blocks();
+        for (i, block) in blocks.iter().enumerate() {
+            if let BlockType::BlockQuote {
+                cite: Some(url), ..
+            } = &block.block_type
+                && !sanitizer.is_safe_url(url)
+            {
+                let end = blocks
+                    .get(i + 1)
+                    .map_or(self.get_length(), |next| next.start_offset);
+                findings.push(unsafe_url(
+                    Range::from_offsets(block.start_offset.min(end), end),
+                    url.clone(),
+                ));
+            }
+        }
+    }
+
+    /// Finds long tokens and suspicious characters in the text
+    fn audit_text(&self, options: &AuditOptions, findings: &mut Vec<AuditFinding>) {
+        let mut token_start = 0;
+        // Start and code points of the run of suspicious characters being read
+        let mut suspicious: Option<(usize, Vec<String>)> = None;
+
+        let text = self.get_content();
+        let mut length = 0;
+        for (offset, c) in text.chars().enumerate() {
+            length = offset + 1;
+            if c.is_whitespace() {
+                push_long_token(findings, token_start, offset, options);
+                token_start = offset + 1;
+            }
+
+            if is_suspicious(c) {
+                let (_, code_points) = suspicious.get_or_insert_with(|| (offset, Vec::new()));
+                let code_point = format!("U+{:04X}", c as u32);
+                if !code_points.contains(&code_point) {
+                    code_points.push(code_point);
+                }
+            } else if let Some((start, code_points)) = suspicious.take() {
+                findings.push(control_characters(start, offset, code_points));
+            }
+        }
+        push_long_token(findings, token_start, length, options);
+        if let Some((start, code_points)) = suspicious {
+            findings.push(control_characters(start, length, code_points));
+        }
+    }
+}
+
+fn unsafe_url(range: Range, url: String) -> AuditFinding {
+    AuditFinding {
+        kind: AuditKind::UnsafeUrl,
+        range,
+        detail: url,
+    }
+}
+
+fn control_characters(start: usize, end: usize, code_points: Vec<String>) -> AuditFinding {
+    AuditFinding {
+        kind: AuditKind::ControlCharacter,
+        range: Range::from_offsets(start, end),
+        detail: code_points.join(" "),
+    }
+}
+
+/// Reports the token between `start` and `end` if it is too long
+fn push_long_token(
+    findings: &mut Vec<AuditFinding>,
+    start: usize,
+    end: usize,
+    options: &AuditOptions,
+) {
+    if end - start > options.max_token_length {
+        findings.push(AuditFinding {
+            kind: AuditKind::LongToken,
+            range: Range::from_offsets(start, end),
+            detail: (end - start).to_string(),
+        });
+    }
+}
+
+/// Checks for characters that can make text display differently from what
+/// it contains
+///
+/// Line breaks and tabs are allowed, and so are the joiners and direction
+/// marks that scripts such as Arabic and Devanagari need.
+fn is_suspicious(c: char) -> bool {
+    (c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+        || matches!(
+            c,
+            // Bidirectional embeddings, overrides and isolates
+            '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+            // Zero width space, word joiner and invisible operators
+            | '\u{200B}' | '\u{2060}'..='\u{2064}'
+            // Byte order mark used as a zero width no-break space
+            | '\u{FEFF}'
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Image;
+
+    #[test]
+    fn test_audit_finds_unsafe_urls() {
+        let mut doc = Document::from_text("click here\n\u{FFFC}\nquoted");
+        doc.apply_format(
+            Range::from_offsets(0, 10),
+            InlineFormat::Link {
+                url: "JavaScript:alert(1)".to_string(),
+            },
+        );
+        doc.apply_format(Range::from_offsets(6, 10), InlineFormat::Bold);
+        doc.embeds_mut()
+            .insert(11, Image::new("data:text/html,<b>", ""));
+        doc.set_block_type(
+            Range::from_offsets(13, 19),
+            BlockType::block_quote_with(Some("vbscript:x".to_string()), 1),
+        );
+
+        let findings = doc.audit(&AuditOptions::new());
+        let found: Vec<_> = findings
+            .iter()
+            .map(|finding| (finding.kind, finding.range, finding.detail.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    AuditKind::UnsafeUrl,
+                    Range::from_offsets(0, 10),
+                    "JavaScript:alert(1)"
+                ),
+                (
+                    AuditKind::UnsafeUrl,
+                    Range::from_offsets(11, 12),
+                    "data:text/html,<b>"
+                ),
+                (
+                    AuditKind::UnsafeUrl,
+                    Range::from_offsets(13, 19),
+                    "vbscript:x"
+                ),
+            ]
+        );
+        assert!(
+            Document::from_text("safe")
+                .audit(&AuditOptions::new())
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_audit_finds_long_tokens_and_hidden_characters() {
+        let doc = Document::from_text("short abcdefgh\u{200B}\u{200B}\u{7} ok\u{2066}");
+        let findings = doc.audit(&AuditOptions::new().max_token_length(8));
+        assert_eq!(
+            findings,
+            [
+                AuditFinding {
+                    kind: AuditKind::LongToken,
+                    range: Range::from_offsets(6, 17),
+                    detail: "11".to_string(),
+                },
+                AuditFinding {
+                    kind: AuditKind::ControlCharacter,
+                    range: Range::from_offsets(14, 17),
+                    detail: "U+200B U+0007".to_string(),
+                },
+                AuditFinding {
+                    kind: AuditKind::ControlCharacter,
+                    range: Range::from_offsets(20, 21),
+                    detail: "U+2066".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! Named bookmarks
+//!
+//! A bookmark maps an application-chosen id to a position in the text, for
+//! anchors such as "jump to section" targets or a cursor to restore after
+//! reloading. Bookmarks move with edits the same way the selection does and
+//! are not part of the undo history.
+
+use super::{Position, Range};
+use std::collections::BTreeMap;
+
+/// Bookmark positions keyed by id
+#[derive(Debug, Clone, Default)]
+pub struct BookmarkStorage {
+    bookmarks: BTreeMap<String, Position>,
+}
+
+impl BookmarkStorage {
+    /// Creates empty bookmark storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of stored bookmarks
+    pub fn len(&self) -> usize {
+        self.bookmarks.len()
+    }
+
+    /// Returns true if no bookmarks are stored
+    pub fn is_empty(&self) -> bool {
+        self.bookmarks.is_empty()
+    }
+
+    /// Stores a bookmark, returning the previous position of the same id
+    pub fn set(&mut self, id: impl Into<String>, pos: Position) -> Option<Position> {
+        self.bookmarks.insert(id.into(), pos)
+    }
+
+    /// Gets the position of the bookmark with the given id
+    pub fn get(&self, id: &str) -> Option<Position> {
+        self.bookmarks.get(id).copied()
+    }
+
+    /// Removes the bookmark with the given id, returning its position
+    pub fn remove(&mut self, id: &str) -> Option<Position> {
+        self.bookmarks.remove(id)
+    }
+
+    /// Iterates over all bookmarks in id order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Position)> {
+        self.bookmarks.iter().map(|(id, pos)| (id.as_str(), *pos))
+    }
+
+    /// Removes all bookmarks
+    pub fn clear(&mut self) {
+        self.bookmarks.clear();
+    }
+
+    /// Shifts bookmarks at or after an insertion
+    pub fn adjust_for_insert(&mut self, pos: Position, length: usize) {
+        let insert_offset = pos.offset();
+        for position in self.bookmarks.values_mut() {
+            if position.offset() >= insert_offset {
+                *position = Position::new(position.offset() + length);
+            }
+        }
+    }
+
+    /// Shifts bookmarks after a deletion; bookmarks inside the deleted range
+    /// move to its start
+    pub fn adjust_for_delete(&mut self, range: Range) {
+        let normalized = range.normalize();
+        let delete_start = normalized.start_offset();
+        let delete_end = normalized.end_offset();
+
+        for position in self.bookmarks.values_mut() {
+            let offset = position.offset();
+            if offset >= delete_end {
+                *position = Position::new(offset - (delete_end - delete_start));
+            } else if offset > delete_start {
+                *position = Position::new(delete_start);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_get_and_remove() {
+        let mut storage = BookmarkStorage::new();
+        assert!(storage.set("intro", Position::new(3)).is_none());
+        assert_eq!(
+            storage.set("intro", Position::new(5)),
+            Some(Position::new(3))
+        );
+        storage.set("end", Position::new(9));
+
+        assert_eq!(storage.len(), 2);
+        assert_eq!(storage.get("intro"), Some(Position::new(5)));
+        let ids: Vec<&str> = storage.iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec!["end", "intro"]);
+
+        assert_eq!(storage.remove("intro"), Some(Position::new(5)));
+        assert!(storage.get("intro").is_none());
+        assert_eq!(storage.len(), 1);
+    }
+
+    #[test]
+    fn test_adjust_for_edits() {
+        let mut storage = BookmarkStorage::new();
+        storage.set("before", Position::new(1));
+        storage.set("at", Position::new(4));
+        storage.set("inside", Position::new(6));
+        storage.set("after", Position::new(10));
+
+        storage.adjust_for_insert(Position::new(4), 2);
+        assert_eq!(storage.get("before"), Some(Position::new(1)));
+        assert_eq!(storage.get("at"), Some(Position::new(6)));
+        assert_eq!(storage.get("inside"), Some(Position::new(8)));
+
+        storage.adjust_for_delete(Range::from_offsets(6, 10));
+        assert_eq!(storage.get("at"), Some(Position::new(6)));
+        assert_eq!(storage.get("inside"), Some(Position::new(6)));
+        assert_eq!(storage.get("after"), Some(Position::new(8)));
+    }
+}
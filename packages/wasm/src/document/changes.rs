@@ -0,0 +1,151 @@
+use crate::document::Range;
+
+/// What kind of edit a `ContentChange` describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// Text was inserted without removing any
+    Insert,
+    /// Text was removed without inserting any
+    Delete,
+    /// Text was removed and new text inserted in its place
+    Replace,
+    /// Formatting, block attributes or embeds changed without editing text
+    Format,
+}
+
+impl ChangeKind {
+    /// Returns the name used for the kind in change events
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Insert => "insert",
+            ChangeKind::Delete => "delete",
+            ChangeKind::Replace => "replace",
+            ChangeKind::Format => "format",
+        }
+    }
+}
+
+/// A single edit recorded by the document's change log
+///
+/// For text edits, `range` is the span of the text before the edit that was
+/// replaced, so an insertion has an empty range at the insertion point. For
+/// format changes, `range` is the affected span.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentChange {
+    pub kind: ChangeKind,
+    pub range: Range,
+    /// Text inserted at `range.start`, empty for deletions and format changes
+    pub inserted_text: String,
+    /// Number of characters removed from `range.start`
+    pub deleted_length: usize,
+    /// Document version after the edit, or None while the edit is still
+    /// being applied
+    pub version: Option<u64>,
+}
+
+impl ContentChange {
+    /// Creates a change for replacing `range` with `text`, choosing the kind
+    /// from which of the two is empty
+    pub fn text(range: Range, text: &str) -> Self {
+        let range = range.normalize();
+        let deleted_length = range.len();
+        let kind = match (deleted_length, text.is_empty()) {
+            (0, _) => ChangeKind::Insert,
+            (_, true) => ChangeKind::Delete,
+            _ => ChangeKind::Replace,
+        };
+        Self {
+            kind,
+            range,
+            inserted_text: text.to_string(),
+            deleted_length,
+            version: None,
+        }
+    }
+
+    /// Creates a change for formatting applied to `range`
+    pub fn format(range: Range) -> Self {
+        Self {
+            kind: ChangeKind::Format,
+            range: range.normalize(),
+            inserted_text: String::new(),
+            deleted_length: 0,
+            version: None,
+        }
+    }
+}
+
+/// Collects the edits made to a document until they are taken
+#[derive(Debug, Clone, Default)]
+pub struct ChangeLog {
+    changes: Vec<ContentChange>,
+}
+
+impl ChangeLog {
+    /// Creates an empty change log
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a change; empty text edits are ignored
+    pub fn record(&mut self, change: ContentChange) {
+        if change.kind != ChangeKind::Format
+            && change.deleted_length == 0
+            && change.inserted_text.is_empty()
+        {
+            return;
+        }
+        self.changes.push(change);
+    }
+
+    /// Sets the version of the changes recorded since the last stamp
+    pub fn stamp(&mut self, version: u64) {
+        for change in self.changes.iter_mut().rev() {
+            if change.version.is_some() {
+                break;
+            }
+            change.version = Some(version);
+        }
+    }
+
+    /// Returns the recorded changes, oldest first
+    pub fn changes(&self) -> &[ContentChange] {
+        &self.changes
+    }
+
+    /// Removes and returns the recorded changes
+    pub fn take(&mut self) -> Vec<ContentChange> {
+        std::mem::take(&mut self.changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_change_kind() {
+        let insert = ContentChange::text(Range::from_offsets(2, 2), "ab");
+        assert_eq!(insert.kind, ChangeKind::Insert);
+        let delete = ContentChange::text(Range::from_offsets(4, 1), "");
+        assert_eq!(delete.kind, ChangeKind::Delete);
+        assert_eq!(delete.range, Range::from_offsets(1, 4));
+        assert_eq!(delete.deleted_length, 3);
+        let replace = ContentChange::text(Range::from_offsets(0, 1), "x");
+        assert_eq!(replace.kind, ChangeKind::Replace);
+    }
+
+    #[test]
+    fn test_stamp_only_sets_unversioned_changes() {
+        let mut log = ChangeLog::new();
+        log.record(ContentChange::text(Range::from_offsets(0, 0), "a"));
+        log.stamp(1);
+        log.record(ContentChange::format(Range::from_offsets(0, 1)));
+        log.record(ContentChange::text(Range::from_offsets(0, 0), ""));
+        log.stamp(2);
+
+        let versions: Vec<_> = log.take().iter().map(|c| c.version).collect();
+        assert_eq!(versions, vec![Some(1), Some(2)]);
+        assert!(log.changes().is_empty());
+    }
+}
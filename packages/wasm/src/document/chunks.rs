@@ -0,0 +1,84 @@
+//! Reading the text in pieces
+//!
+//! Lets renderers and exporters walk a large document chunk by chunk
+//! instead of handling its whole text as one string.
+
+use super::Document;
+
+/// Iterator over a document's text in chunks of at most a given number of
+/// characters
+///
+/// It reads from a copy of the text taken when it was created, so edits
+/// made while iterating do not affect it. Chunks split at character
+/// boundaries, which may fall inside a grapheme cluster.
+#[derive(Debug, Clone)]
+pub struct TextChunks {
+    text: String,
+    /// Byte offset of the next chunk
+    pos: usize,
+    chunk_size: usize,
+}
+
+impl Iterator for TextChunks {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let rest = &self.text[self.pos..];
+        if rest.is_empty() {
+            return None;
+        }
+        let len = rest
+            .char_indices()
+            .nth(self.chunk_size)
+            .map_or(rest.len(), |(i, _)| i);
+        self.pos += len;
+        Some(rest[..len].to_string())
+    }
+}
+
+impl Document {
+    /// Returns the text in chunks of `chunk_size` characters; the last
+    /// chunk may be shorter, and a size of 0 is treated as 1
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::Document;
+    ///
+    /// let doc = Document::from_text("Hello World");
+    /// let chunks: Vec<String> = doc.text_chunks(4).collect();
+    /// assert_eq!(chunks, ["Hell", "o Wo", "rld"]);
+    /// ```
+    pub fn text_chunks(&self, chunk_size: usize) -> TextChunks {
+        TextChunks {
+            text: self.get_content(),
+            pos: 0,
+            chunk_size: chunk_size.max(1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Position;
+
+    #[test]
+    fn test_chunks_split_on_characters() {
+        let doc = Document::from_text("añb✓\nc");
+        let chunks: Vec<String> = doc.text_chunks(2).collect();
+        assert_eq!(chunks, ["añ", "b✓", "\nc"]);
+        assert_eq!(doc.text_chunks(0).count(), 6);
+        assert_eq!(Document::new().text_chunks(4).next(), None);
+    }
+
+    #[test]
+    fn test_chunks_ignore_later_edits() {
+        let mut doc = Document::from_text("abcd");
+        let mut chunks = doc.text_chunks(2);
+        assert_eq!(chunks.next().as_deref(), Some("ab"));
+
+        doc.insert_text(Position::new(0), "xyz").unwrap();
+        assert_eq!(chunks.next().as_deref(), Some("cd"));
+        assert_eq!(chunks.next(), None);
+    }
+}
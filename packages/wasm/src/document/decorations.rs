@@ -0,0 +1,203 @@
+//! Decoration ranges
+//!
+//! A decoration marks a range of text for an application feature the editor
+//! knows nothing about, such as a search hit kept across sessions, a
+//! collaborator's highlight or a linter warning. Each decoration has a kind
+//! chosen by the application and string attributes. Like spellcheck ranges
+//! they do not change the text or its formatting, shift with edits the way
+//! annotations do, are saved in JSON and are not part of the undo history.
+//! Unlike annotations they have no id: any number of decorations, of the
+//! same kind or not, may cover the same text.
+//!
+//! Transient decorations are the throwaway kind: search hits, spelling
+//! errors or collaborator cursors that the application recomputes as it
+//! goes. They carry only an id, shift with edits the same way, are never
+//! saved and are wrapped in `<mark data-deco-id>` by `to_html_range`.
+
+use super::{Position, Range};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Application-defined marking of a range of text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Decoration {
+    /// Kind chosen by the application, such as "lint-warning"
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl Decoration {
+    /// Creates a decoration without attributes
+    pub fn new(kind: impl Into<String>) -> Self {
+        Self {
+            kind: kind.into(),
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    /// Sets an attribute of the decoration
+    pub fn with_attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// Decorations with their ranges, sorted by range start
+///
+/// Stores `Decoration`s, or the ids of transient decorations.
+#[derive(Debug, Clone)]
+pub struct DecorationStorage<T = Decoration> {
+    decorations: Vec<(Range, T)>,
+}
+
+impl<T> Default for DecorationStorage<T> {
+    fn default() -> Self {
+        Self {
+            decorations: Vec::new(),
+        }
+    }
+}
+
+impl<T> DecorationStorage<T> {
+    /// Creates empty decoration storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of stored decorations
+    pub fn len(&self) -> usize {
+        self.decorations.len()
+    }
+
+    /// Returns true if no decorations are stored
+    pub fn is_empty(&self) -> bool {
+        self.decorations.is_empty()
+    }
+
+    /// Stores a decoration after the ones starting at the same offset
+    pub fn add(&mut self, range: Range, decoration: T) {
+        let range = range.normalize();
+        let idx = self
+            .decorations
+            .partition_point(|(r, _)| r.start_offset() <= range.start_offset());
+        self.decorations.insert(idx, (range, decoration));
+    }
+
+    /// Returns the decorations covering the character at `offset`
+    pub fn decorations_at(&self, offset: usize) -> Vec<(Range, &T)> {
+        self.decorations
+            .iter()
+            .take_while(|(range, _)| range.start_offset() <= offset)
+            .filter(|(range, _)| offset < range.end_offset())
+            .map(|(range, decoration)| (*range, decoration))
+            .collect()
+    }
+
+    /// Returns all decorations with their ranges
+    pub fn decorations(&self) -> &[(Range, T)] {
+        &self.decorations
+    }
+
+    /// Keeps only the decorations for which `keep` returns true
+    pub fn retain(&mut self, mut keep: impl FnMut(&Range, &T) -> bool) {
+        self.decorations.retain(|(range, value)| keep(range, value));
+    }
+
+    /// Removes all decorations
+    pub fn clear(&mut self) {
+        self.decorations.clear();
+    }
+
+    /// Shifts decorations after text insertion
+    ///
+    /// Text inserted strictly inside a decoration extends it; text inserted
+    /// at either edge stays outside.
+    pub fn adjust_for_insert(&mut self, pos: Position, length: usize) {
+        let insert_offset = pos.offset();
+        for (range, _) in &mut self.decorations {
+            let start = range.start_offset();
+            let end = range.end_offset();
+            if start >= insert_offset {
+                *range = Range::from_offsets(start + length, end + length);
+            } else if end > insert_offset {
+                *range = Range::from_offsets(start, end + length);
+            }
+        }
+    }
+
+    /// Shrinks decorations overlapping a deleted range and shifts the ones
+    /// after it, dropping decorations whose text is deleted entirely
+    pub fn adjust_for_delete(&mut self, range: Range) {
+        let normalized = range.normalize();
+        let delete_start = normalized.start_offset();
+        let delete_end = normalized.end_offset();
+        let delete_length = delete_end - delete_start;
+
+        let shift = |offset: usize| {
+            if offset >= delete_end {
+                offset - delete_length
+            } else {
+                offset.min(delete_start)
+            }
+        };
+        for (range, _) in &mut self.decorations {
+            *range = Range::from_offsets(shift(range.start_offset()), shift(range.end_offset()));
+        }
+        self.decorations.retain(|(range, _)| !range.is_empty());
+    }
+}
+
+impl DecorationStorage {
+    /// Removes the decorations of the given kind overlapping the range,
+    /// returning how many were removed
+    pub fn remove(&mut self, kind: &str, range: Range) -> usize {
+        let normalized = range.normalize();
+        let before = self.decorations.len();
+        self.decorations
+            .retain(|(r, decoration)| decoration.kind != kind || !r.overlaps(&normalized));
+        before - self.decorations.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_query_and_remove() {
+        let mut storage = DecorationStorage::new();
+        storage.add(Range::from_offsets(4, 8), Decoration::new("lint"));
+        storage.add(
+            Range::from_offsets(0, 6),
+            Decoration::new("hit").with_attribute("query", "foo"),
+        );
+        storage.add(Range::from_offsets(0, 6), Decoration::new("lint"));
+
+        assert_eq!(storage.len(), 3);
+        assert_eq!(storage.decorations()[0].1.kind, "hit");
+        assert_eq!(storage.decorations()[1].1.kind, "lint");
+        assert_eq!(storage.decorations_at(5).len(), 3);
+        assert_eq!(storage.decorations_at(6).len(), 1);
+
+        assert_eq!(storage.remove("lint", Range::from_offsets(7, 9)), 1);
+        assert_eq!(storage.remove("other", Range::from_offsets(0, 9)), 0);
+        assert_eq!(storage.len(), 2);
+    }
+
+    #[test]
+    fn test_decorations_follow_edits() {
+        let mut storage = DecorationStorage::new();
+        storage.add(Range::from_offsets(2, 5), Decoration::new("a"));
+        storage.add(Range::from_offsets(7, 9), Decoration::new("b"));
+
+        storage.adjust_for_insert(Position::new(2), 1);
+        storage.adjust_for_insert(Position::new(4), 2);
+        assert_eq!(storage.decorations()[0].0, Range::from_offsets(3, 8));
+        assert_eq!(storage.decorations()[1].0, Range::from_offsets(10, 12));
+
+        storage.adjust_for_delete(Range::from_offsets(9, 13));
+        assert_eq!(storage.len(), 1);
+        assert_eq!(storage.decorations()[0].0, Range::from_offsets(3, 8));
+    }
+}
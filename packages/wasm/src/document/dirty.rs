@@ -4,12 +4,21 @@ use crate::document::{Position, Range};
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DirtyRegion {
     pub range: Range,
+    /// Identifier assigned by the tracker; a merged region gets a new one
+    pub id: u64,
+    /// Document version after the latest edit inside the region, or None
+    /// while that edit is still being applied
+    pub version: Option<u64>,
 }
 
 impl DirtyRegion {
     /// Creates a new dirty region
     pub fn new(range: Range) -> Self {
-        Self { range }
+        Self {
+            range,
+            id: 0,
+            version: None,
+        }
     }
 
     /// Returns true if this region overlaps with another region
@@ -46,6 +55,7 @@ impl DirtyRegion {
 #[derive(Debug, Clone)]
 pub struct DirtyTracker {
     regions: Vec<DirtyRegion>,
+    next_id: u64,
 }
 
 impl DirtyTracker {
@@ -53,12 +63,15 @@ impl DirtyTracker {
     pub fn new() -> Self {
         Self {
             regions: Vec::new(),
+            next_id: 1,
         }
     }
 
     /// Marks a region as dirty
+    ///
+    /// The region stays unversioned until the next call to `stamp`.
     pub fn mark_dirty(&mut self, range: Range) {
-        let new_region = DirtyRegion::new(range);
+        let mut new_region = DirtyRegion::new(range);
 
         // Try to merge with existing regions
         let mut merged = false;
@@ -79,10 +92,33 @@ impl DirtyTracker {
         }
 
         if !merged {
+            new_region.id = self.next_id;
+            self.next_id += 1;
             self.regions.push(new_region);
         }
     }
 
+    /// Records `version` on every region marked since the last stamp
+    pub fn stamp(&mut self, version: u64) {
+        for region in &mut self.regions {
+            region.version.get_or_insert(version);
+        }
+    }
+
+    /// Returns all dirty regions with their ids and versions
+    pub fn regions(&self) -> &[DirtyRegion] {
+        &self.regions
+    }
+
+    /// Clears the regions last changed at or before `up_to_version`
+    ///
+    /// Regions changed by later edits, or by an edit still in progress,
+    /// are kept.
+    pub fn acknowledge(&mut self, up_to_version: u64) {
+        self.regions
+            .retain(|region| region.version.is_none_or(|version| version > up_to_version));
+    }
+
     /// Returns all dirty regions
     pub fn get_dirty_regions(&self) -> Vec<Range> {
         self.regions.iter().map(|r| r.range).collect()
@@ -93,6 +129,31 @@ impl DirtyTracker {
         self.regions.clear();
     }
 
+    /// Replaces all dirty regions with the single range spanning them
+    pub fn coalesce(&mut self) {
+        let Some(start) = self.regions.iter().map(|r| r.range.start_offset()).min() else {
+            return;
+        };
+        let end = self
+            .regions
+            .iter()
+            .map(|r| r.range.end_offset())
+            .max()
+            .unwrap_or(start);
+        let version = self
+            .regions
+            .iter()
+            .map(|r| r.version)
+            .collect::<Option<Vec<_>>>()
+            .and_then(|versions| versions.into_iter().max());
+        self.regions = vec![DirtyRegion {
+            range: Range::from_offsets(start, end),
+            id: self.next_id,
+            version,
+        }];
+        self.next_id += 1;
+    }
+
     /// Returns true if there are any dirty regions
     pub fn has_dirty_regions(&self) -> bool {
         !self.regions.is_empty()
@@ -256,6 +317,49 @@ mod tests {
         assert_eq!(regions.len(), 2);
     }
 
+    #[test]
+    fn test_dirty_tracker_coalesce() {
+        let mut tracker = DirtyTracker::new();
+        tracker.coalesce();
+        assert!(!tracker.has_dirty_regions());
+
+        tracker.mark_dirty(Range::from_offsets(20, 30));
+        tracker.mark_dirty(Range::from_offsets(0, 10));
+        tracker.coalesce();
+
+        assert_eq!(
+            tracker.get_dirty_regions(),
+            vec![Range::from_offsets(0, 30)]
+        );
+    }
+
+    #[test]
+    fn test_dirty_tracker_versions() {
+        let mut tracker = DirtyTracker::new();
+        tracker.mark_dirty(Range::from_offsets(0, 5));
+        tracker.stamp(1);
+        tracker.mark_dirty(Range::from_offsets(10, 15));
+        tracker.stamp(2);
+        tracker.mark_dirty(Range::from_offsets(20, 25));
+
+        let regions = tracker.regions();
+        assert_eq!(
+            regions.iter().map(|r| r.version).collect::<Vec<_>>(),
+            vec![Some(1), Some(2), None]
+        );
+        assert_ne!(regions[0].id, regions[1].id);
+
+        tracker.acknowledge(1);
+        assert_eq!(tracker.get_dirty_regions().len(), 2);
+
+        // Editing an acknowledged-but-kept region restamps it
+        tracker.mark_dirty(Range::from_offsets(12, 13));
+        tracker.stamp(3);
+        tracker.acknowledge(2);
+        let versions: Vec<_> = tracker.regions().iter().map(|r| r.version).collect();
+        assert_eq!(versions, vec![Some(3), Some(3)]);
+    }
+
     #[test]
     fn test_dirty_tracker_clear() {
         let mut tracker = DirtyTracker::new();
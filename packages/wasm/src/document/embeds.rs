@@ -0,0 +1,288 @@
+//! Inline embedded objects
+//!
+//! An embedded object (currently only images) occupies a single
+//! `EMBED_CHAR` in the document text, so cursor movement, selection and
+//! deletion treat it as one atomic character. Its attributes are kept in
+//! `EmbedStorage`, keyed by the offset of that character and shifted
+//! alongside text edits the same way format runs are.
+
+use super::{Position, Range};
+use serde::{Deserialize, Serialize};
+
+/// Placeholder character stored in the text for each embedded object
+/// (U+FFFC OBJECT REPLACEMENT CHARACTER)
+pub const EMBED_CHAR: char = '\u{FFFC}';
+
+/// An inline image
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Image {
+    pub src: String,
+    #[serde(default)]
+    pub alt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+}
+
+impl Image {
+    /// Creates an image without explicit dimensions
+    pub fn new(src: impl Into<String>, alt: impl Into<String>) -> Self {
+        Self {
+            src: src.into(),
+            alt: alt.into(),
+            width: None,
+            height: None,
+        }
+    }
+
+    /// Sets the rendered dimensions of the image
+    pub fn with_size(mut self, width: Option<u32>, height: Option<u32>) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Returns true if the image source is safe to render
+    pub fn has_safe_src(&self) -> bool {
+        is_safe_image_src(&self.src)
+    }
+}
+
+/// Checks whether a URL may be used as an image source
+///
+/// Allows http(s), protocol-relative and relative URLs, plus base64
+/// `data:` URLs for raster formats. SVG data URLs are rejected because
+/// they can carry script.
+pub fn is_safe_image_src(src: &str) -> bool {
+    let trimmed = src.trim();
+    if trimmed.is_empty() {
+        return false;
+    }
+
+    let lower = trimmed.to_lowercase();
+
+    if let Some(data) = lower.strip_prefix("data:") {
+        return ["image/png;", "image/jpeg;", "image/gif;", "image/webp;"]
+            .iter()
+            .any(|mime| data.starts_with(mime))
+            && data.contains(";base64,");
+    }
+
+    lower.starts_with("http:")
+        || lower.starts_with("https:")
+        || lower.starts_with("//")
+        || lower.starts_with('/')
+        || !lower.contains(':') // Relative URLs without protocol
+}
+
+/// Attributes of embedded objects, sorted by offset
+#[derive(Debug, Clone, Default)]
+pub struct EmbedStorage {
+    images: Vec<(usize, Image)>,
+}
+
+impl EmbedStorage {
+    /// Creates empty embed storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of stored images
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    /// Returns true if no images are stored
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// Stores an image at the given offset, returning the one it replaced
+    pub fn insert(&mut self, offset: usize, image: Image) -> Option<Image> {
+        match self.images.binary_search_by_key(&offset, |(o, _)| *o) {
+            Ok(idx) => Some(std::mem::replace(&mut self.images[idx].1, image)),
+            Err(idx) => {
+                self.images.insert(idx, (offset, image));
+                None
+            }
+        }
+    }
+
+    /// Removes the image at the given offset
+    pub fn remove(&mut self, offset: usize) -> Option<Image> {
+        self.images
+            .binary_search_by_key(&offset, |(o, _)| *o)
+            .ok()
+            .map(|idx| self.images.remove(idx).1)
+    }
+
+    /// Gets the image at the given offset
+    pub fn get(&self, offset: usize) -> Option<&Image> {
+        self.images
+            .binary_search_by_key(&offset, |(o, _)| *o)
+            .ok()
+            .map(|idx| &self.images[idx].1)
+    }
+
+    /// Returns all images with their offsets
+    pub fn images(&self) -> &[(usize, Image)] {
+        &self.images
+    }
+
+    /// Returns the images inside the range with their offsets
+    pub fn images_in(&self, range: Range) -> Vec<(usize, Image)> {
+        let normalized = range.normalize();
+        let start = self
+            .images
+            .partition_point(|(o, _)| *o < normalized.start_offset());
+        self.images[start..]
+            .iter()
+            .take_while(|(o, _)| *o < normalized.end_offset())
+            .cloned()
+            .collect()
+    }
+
+    /// Removes all images
+    pub fn clear(&mut self) {
+        self.images.clear();
+    }
+
+    /// Shifts images after text insertion
+    pub fn adjust_for_insert(&mut self, pos: Position, length: usize) {
+        let start = self.images.partition_point(|(o, _)| *o < pos.offset());
+        for (offset, _) in &mut self.images[start..] {
+            *offset += length;
+        }
+    }
+
+    /// Drops images inside a deleted range and shifts the ones after it
+    pub fn adjust_for_delete(&mut self, range: Range) {
+        let normalized = range.normalize();
+        let delete_start = normalized.start_offset();
+        let delete_end = normalized.end_offset();
+        let delete_length = delete_end - delete_start;
+
+        self.images
+            .retain(|(o, _)| *o < delete_start || *o >= delete_end);
+        let start = self.images.partition_point(|(o, _)| *o < delete_end);
+        for (offset, _) in &mut self.images[start..] {
+            *offset -= delete_length;
+        }
+    }
+
+    /// Replaces each `EMBED_CHAR` in `rendered` with the markup produced by
+    /// `render` for the image at the matching offset in `line`
+    ///
+    /// `rendered` must keep embed characters in the same order as `line`,
+    /// which holds for the escaping and inline wrapping done by exporters.
+    /// Embed characters without a stored image render as nothing.
+    pub(crate) fn render_line(
+        &self,
+        rendered: String,
+        line: &str,
+        line_start: usize,
+        render: impl Fn(&Image) -> String,
+    ) -> String {
+        if !rendered.contains(EMBED_CHAR) {
+            return rendered;
+        }
+
+        let mut offsets = line
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| *c == EMBED_CHAR)
+            .map(|(i, _)| line_start + i);
+
+        let mut result = String::with_capacity(rendered.len());
+        for c in rendered.chars() {
+            if c != EMBED_CHAR {
+                result.push(c);
+            } else if let Some(image) = offsets.next().and_then(|offset| self.get(offset)) {
+                result.push_str(&render(image));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_get() {
+        let mut storage = EmbedStorage::new();
+        assert!(storage.insert(5, Image::new("a.png", "a")).is_none());
+        assert!(storage.insert(1, Image::new("b.png", "b")).is_none());
+
+        assert_eq!(storage.len(), 2);
+        assert_eq!(storage.images()[0].0, 1);
+        assert_eq!(storage.get(5).unwrap().src, "a.png");
+        assert!(storage.get(3).is_none());
+
+        let replaced = storage.insert(5, Image::new("c.png", "c"));
+        assert_eq!(replaced.unwrap().src, "a.png");
+        assert_eq!(storage.len(), 2);
+    }
+
+    #[test]
+    fn test_adjust_for_insert() {
+        let mut storage = EmbedStorage::new();
+        storage.insert(2, Image::new("a.png", ""));
+        storage.insert(6, Image::new("b.png", ""));
+
+        storage.adjust_for_insert(Position::new(2), 3);
+        assert!(storage.get(5).is_some());
+        assert!(storage.get(9).is_some());
+    }
+
+    #[test]
+    fn test_adjust_for_delete() {
+        let mut storage = EmbedStorage::new();
+        storage.insert(1, Image::new("a.png", ""));
+        storage.insert(4, Image::new("b.png", ""));
+        storage.insert(8, Image::new("c.png", ""));
+
+        storage.adjust_for_delete(Range::from_offsets(3, 6));
+        assert_eq!(storage.len(), 2);
+        assert_eq!(storage.get(1).unwrap().src, "a.png");
+        assert_eq!(storage.get(5).unwrap().src, "c.png");
+    }
+
+    #[test]
+    fn test_images_in() {
+        let mut storage = EmbedStorage::new();
+        storage.insert(1, Image::new("a.png", ""));
+        storage.insert(4, Image::new("b.png", ""));
+
+        let found = storage.images_in(Range::from_offsets(2, 5));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, 4);
+    }
+
+    #[test]
+    fn test_safe_image_src() {
+        assert!(is_safe_image_src("https://example.com/a.png"));
+        assert!(is_safe_image_src("/images/a.png"));
+        assert!(is_safe_image_src("a.png"));
+        assert!(is_safe_image_src("data:image/png;base64,iVBORw0KGgo="));
+
+        assert!(!is_safe_image_src(""));
+        assert!(!is_safe_image_src("javascript:alert(1)"));
+        assert!(!is_safe_image_src("data:image/svg+xml;base64,PHN2Zz4="));
+        assert!(!is_safe_image_src("data:text/html;base64,PGgxPg=="));
+        assert!(!is_safe_image_src("file:///etc/passwd"));
+    }
+
+    #[test]
+    fn test_render_line() {
+        let mut storage = EmbedStorage::new();
+        storage.insert(12, Image::new("a.png", ""));
+
+        let line = format!("a{}b", EMBED_CHAR);
+        let rendered = format!("<b>a{}</b>b", EMBED_CHAR);
+        let result = storage.render_line(rendered, &line, 11, |image| format!("[{}]", image.src));
+        assert_eq!(result, "<b>a[a.png]</b>b");
+    }
+}
@@ -0,0 +1,152 @@
+//! Line index
+//!
+//! Keeps the offset at which every line starts, so finding line boundaries
+//! and converting between character offsets and line/column coordinates is
+//! a binary search instead of a scan of the text. The text storage updates
+//! the index on each edit rather than rebuilding it.
+//!
+//! Only `\n` ends a line, and with it the block. A soft break (shift+enter,
+//! or `<br>` in HTML) is stored as `SOFT_BREAK` and stays inside the line, so
+//! it keeps the block type and list numbering of the line it breaks.
+
+use super::{Position, Range};
+
+/// Character stored in the text for a soft line break
+/// (U+2028 LINE SEPARATOR)
+pub const SOFT_BREAK: char = '\u{2028}';
+
+/// Where an offset sits in the block structure of a document
+///
+/// Hosts use this to scroll an offset into view without working out the
+/// structure from exported text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OffsetGeometry {
+    /// Index of the block, which is the zero-based line number
+    pub block: usize,
+    /// Zero-based line within the block, counting soft breaks before the
+    /// offset
+    pub line: usize,
+    /// Characters from the start of that line to the offset
+    pub column: usize,
+}
+
+/// Start offsets of the lines of a text, in order
+///
+/// There is always at least one line, starting at offset 0. Every newline
+/// starts a new line right after it.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    /// Creates the index of an empty text
+    pub fn new() -> Self {
+        Self { starts: vec![0] }
+    }
+
+    /// Creates the index of the given text
+    pub fn from_text(text: &str) -> Self {
+        let mut index = Self::new();
+        index.adjust_for_insert(Position::new(0), text);
+        index
+    }
+
+    /// Returns the number of lines
+    pub fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Returns the offset at which `line` starts, if it exists
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.starts.get(line).copied()
+    }
+
+    /// Returns the line containing `offset`
+    ///
+    /// An offset right after a newline belongs to the line the newline
+    /// starts.
+    pub fn line_of(&self, offset: usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    /// Updates the index after `text` was inserted at `pos`
+    pub fn adjust_for_insert(&mut self, pos: Position, text: &str) {
+        let offset = pos.offset();
+        let length = text.chars().count();
+        let line = self.line_of(offset);
+        for start in &mut self.starts[line + 1..] {
+            *start += length;
+        }
+
+        let new_starts = text
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| *c == '\n')
+            .map(|(i, _)| offset + i + 1);
+        self.starts.splice(line + 1..line + 1, new_starts);
+    }
+
+    /// Updates the index after `range` was deleted
+    pub fn adjust_for_delete(&mut self, range: Range) {
+        let normalized = range.normalize();
+        let delete_start = normalized.start_offset();
+        let delete_end = normalized.end_offset();
+
+        // Lines started by a deleted newline disappear
+        self.starts
+            .retain(|&start| start <= delete_start || start > delete_end);
+        for start in &mut self.starts {
+            if *start > delete_end {
+                *start -= delete_end - delete_start;
+            }
+        }
+    }
+}
+
+impl Default for LineIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn starts(index: &LineIndex) -> Vec<usize> {
+        (0..index.line_count())
+            .filter_map(|line| index.line_start(line))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_text() {
+        let index = LineIndex::from_text("ab\n\ncd\n");
+        assert_eq!(starts(&index), vec![0, 3, 4, 7]);
+        assert_eq!(index.line_of(0), 0);
+        assert_eq!(index.line_of(2), 0);
+        assert_eq!(index.line_of(3), 1);
+        assert_eq!(index.line_of(6), 2);
+        assert_eq!(index.line_of(7), 3);
+
+        assert_eq!(starts(&LineIndex::new()), vec![0]);
+    }
+
+    #[test]
+    fn test_adjust_for_edits() {
+        let mut index = LineIndex::from_text("one\ntwo\nthree");
+        index.adjust_for_insert(Position::new(5), "x\ny");
+        // "one\ntx\nywo\nthree"
+        assert_eq!(starts(&index), vec![0, 4, 7, 11]);
+
+        index.adjust_for_delete(Range::from_offsets(2, 8));
+        // "onwo\nthree"
+        assert_eq!(starts(&index), vec![0, 5]);
+
+        index.adjust_for_delete(Range::from_offsets(4, 5));
+        assert_eq!(starts(&index), vec![0]);
+        index.adjust_for_insert(Position::new(9), "\n");
+        assert_eq!(starts(&index), vec![0, 10]);
+    }
+}
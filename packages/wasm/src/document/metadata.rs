@@ -0,0 +1,123 @@
+//! Document metadata
+//!
+//! Key/value pairs that describe the document as a whole, such as its title
+//! or author. Markdown front matter is read into the metadata on import and
+//! written back from it on export; JSON export keeps it and HTML document
+//! export writes it as `<meta>` tags. Metadata is not part of the text, so
+//! text edits leave it alone, but changes made through `Document` are
+//! undoable.
+
+/// Key of the document title
+pub const TITLE: &str = "title";
+/// Key of the document author
+pub const AUTHOR: &str = "author";
+/// Key of the creation timestamp, such as an ISO 8601 date
+pub const CREATED: &str = "created";
+/// Key of the last modification timestamp, such as an ISO 8601 date
+pub const MODIFIED: &str = "modified";
+
+/// Syntax of the front matter block at the start of a Markdown file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrontMatterFormat {
+    /// YAML between `---` lines
+    #[default]
+    Yaml,
+    /// TOML between `+++` lines
+    Toml,
+}
+
+/// Document metadata as string key/value pairs, in insertion order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    entries: Vec<(String, String)>,
+    format: FrontMatterFormat,
+}
+
+impl Metadata {
+    /// Creates empty metadata
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Gets the value of the given key
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets the value of a key, returning its previous value
+    ///
+    /// An existing key keeps its place; a new key is added at the end.
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        let key = key.into();
+        let value = value.into();
+        match self.entries.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, existing)) => Some(std::mem::replace(existing, value)),
+            None => {
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Removes the given key, returning its value
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    /// Iterates over all entries in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Removes all entries
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns the front matter syntax used when exporting to Markdown
+    ///
+    /// This is the syntax the front matter was imported in, or YAML.
+    pub fn format(&self) -> FrontMatterFormat {
+        self.format
+    }
+
+    /// Sets the front matter syntax used when exporting to Markdown
+    pub fn set_format(&mut self, format: FrontMatterFormat) {
+        self.format = format;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_keeps_order() {
+        let mut metadata = Metadata::new();
+        assert_eq!(metadata.set("title", "Draft"), None);
+        metadata.set("author", "Ada");
+        assert_eq!(metadata.set("title", "Final"), Some("Draft".to_string()));
+
+        let entries: Vec<_> = metadata.iter().collect();
+        assert_eq!(entries, [("title", "Final"), ("author", "Ada")]);
+        assert_eq!(metadata.remove("title").as_deref(), Some("Final"));
+        assert_eq!(metadata.get("title"), None);
+        assert_eq!(metadata.len(), 1);
+    }
+}
@@ -6,7 +6,7 @@
 //!
 //! # Responsibilities
 //!
-//! - Text storage using a gap buffer for efficient editing
+//! - Text storage using a gap buffer or rope for efficient editing
 //! - Document state management (version tracking, dirty regions)
 //! - Position and range types for text addressing
 //! - Integration with formatting, operations, and selection modules
@@ -15,33 +15,100 @@
 //!
 //! - `Document`: Main document struct coordinating all operations
 //! - `TextStorage`: Gap buffer implementation for text storage
+//! - `RopeStorage`: Rope implementation for large documents with scattered edits
+//! - `StorageBackend`: Selects the text storage used by a `Document`
 //! - `Position`: Represents a position in the document
 //! - `Range`: Represents a range of text in the document
 //! - `DirtyTracker`: Tracks modified regions for incremental rendering
-
+//! - `ContentChange`: A recorded edit, as delivered in change events
+//! - `Image`: Inline image stored as a single embedded character
+//! - `Annotation`: Application metadata attached to a range of text
+//! - `Decoration`: Application-defined marking of a range of text
+//! - `Dictionary`: Word lookup used to spellcheck the document
+//! - `Bias`: Which side of an insertion a sticky position stays on
+//! - `LineIndex`: Line start offsets for line/column conversion
+//! - `Metadata`: Key/value pairs describing the document, such as front matter
+//! - `Snapshot`: Immutable copy of the content, diffed into `SnapshotChange`s
+//! - `StreamingLoad`: Builds a document from text that arrives in chunks
+//! - `TextChunks`: Iterates over the text in fixed-size chunks
+
+pub mod annotations;
+pub mod audit;
+pub mod bookmarks;
+pub mod changes;
+pub mod chunks;
+pub mod decorations;
 pub mod dirty;
+pub mod embeds;
 pub mod errors;
+pub mod lines;
+pub mod metadata;
 pub mod position;
+pub mod rope;
+pub mod snapshot;
+pub mod spellcheck;
+pub mod spelling;
+pub mod sticky;
+pub mod storage_backend;
+pub mod streaming;
 pub mod text_storage;
 pub mod validation;
 
 // Re-export types for external use
+pub use annotations::Annotation;
+pub use audit::{AuditFinding, AuditKind, AuditOptions};
+pub use changes::{ChangeKind, ChangeLog, ContentChange};
+pub use chunks::TextChunks;
+pub use decorations::Decoration;
 pub use dirty::{DirtyRegion, DirtyTracker};
+pub use embeds::{EMBED_CHAR, Image};
 pub use errors::DocumentError;
+pub use lines::{LineIndex, OffsetGeometry, SOFT_BREAK};
+pub use metadata::{FrontMatterFormat, Metadata};
 pub use position::{Position, Range};
-pub use validation::{MAX_DOCUMENT_SIZE, validate_position, validate_range, validate_text_content};
+pub use snapshot::{Snapshot, SnapshotChange};
+pub use spelling::{Dictionary, WordList};
+pub use sticky::Bias;
+pub use storage_backend::StorageBackend;
+pub use streaming::StreamingLoad;
+pub use validation::{
+    ControlCharPolicy, MAX_DOCUMENT_SIZE, validate_position, validate_range, validate_text_content,
+};
 
-use crate::formatting::{BlockType, FormatStorage, InlineFormat};
-use crate::operations::history::CommandHistory;
+use crate::formatting::{
+    BlockType, FormatAffinities, FormatAffinity, FormatStorage, FragmentationStats, InlineFormat,
+    ListStyle, TextDirection,
+};
+use crate::operations::autocorrect::Autocorrect;
+use crate::operations::clipboard::PasteGuard;
+use crate::operations::crdt::Replica;
+use crate::operations::history::{CommandHistory, HistoryNode};
+use crate::operations::input_rules::InputRules;
+use crate::operations::ot::PendingOps;
+use crate::operations::search::SearchState;
 use crate::operations::{
 
-    ApplyFormatCommand, Command, CommandResult, DeleteCommand, InsertCommand, RemoveFormatCommand,
-    ReplaceCommand, SetBlockTypeCommand,
+    AddAnnotationCommand, ApplyFormatCommand, ChangeBlockIndentCommand, ChangeListIndentCommand,
+    Command, CommandResult, DeleteCommand, InsertCommand, InsertImageCommand,
+    RemoveAnnotationCommand, RemoveFormatCommand, RenumberListsCommand, ReplaceCommand,
+    SetBlockTypeCommand, SetFirstLineIndentCommand, SetLineBlockTypesCommand, SetListStyleCommand,
+    SetMetadataCommand, SetTextDirectionCommand, UpdateImageCommand,
 };
-use crate::selection::Selection;
+use crate::selection::{NavigationHistory, SecondarySelections, Selection};
+use crate::serialization::html::{HtmlCache, HtmlSanitizer};
+use crate::utils::bidi::{BidiLine, first_strong_direction};
 use crate::utils::ime::CompositionState;
-use std::collections::HashSet;
-use text_storage::TextStorage;
+use crate::utils::segmenter::{WordSegmenter, word_breaks};
+use annotations::AnnotationStorage;
+use bookmarks::BookmarkStorage;
+use decorations::DecorationStorage;
+use embeds::EmbedStorage;
+use spellcheck::NoSpellcheckStorage;
+use spelling::Spellchecker;
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+use sticky::StickyPositionStorage;
+use storage_backend::TextBuffer;
 
 /// The main Document struct that manages text content and metadata.
 ///
@@ -83,39 +150,142 @@ use text_storage::TextStorage;
 /// assert_eq!(doc.get_content(), "Hello");
 /// ```
 pub struct Document {
-    text: TextStorage,
+    text: TextBuffer,
     version: u64,
     pub(crate) history: CommandHistory,
     formats: FormatStorage,
+    embeds: EmbedStorage,
+    annotations: AnnotationStorage,
+    bookmarks: BookmarkStorage,
+    no_spellcheck: NoSpellcheckStorage,
+    decorations: DecorationStorage,
+    transient_decorations: DecorationStorage<String>,
+    metadata: Metadata,
+    extensions: BTreeMap<String, serde_json::Value>,
+    sticky_positions: StickyPositionStorage,
     pub(crate) selection: Selection,
+    pub(crate) secondary_selections: SecondarySelections,
+    pub(crate) selection_expansion: Vec<(Selection, Selection)>,
     composition: CompositionState,
     dirty_tracker: DirtyTracker,
+    change_log: Option<ChangeLog>,
+    pub(crate) html_cache: HtmlCache,
+    pub(crate) replica: Option<Replica>,
+    pub(crate) pending_ops: Option<PendingOps>,
+    pub(crate) paste_guard: Option<PasteGuard>,
+    pub(crate) navigation: Option<NavigationHistory>,
+    pub(crate) search_state: SearchState,
+    read_only: bool,
+    max_length: Option<usize>,
+    html_sanitizer: Option<HtmlSanitizer>,
+    pub(crate) word_segmenter: Option<Box<dyn WordSegmenter>>,
+    spellchecker: Option<Spellchecker>,
+    pub(crate) autocorrect: Autocorrect,
+    pub(crate) auto_link: bool,
+    pub(crate) input_rules: InputRules,
+    control_chars: ControlCharPolicy,
+    format_affinities: FormatAffinities,
 }
 
 impl Document {
     /// Creates a new empty Document
     pub fn new() -> Self {
+        Self::with_backend(StorageBackend::default())
+    }
+
+    /// Creates a Document from existing text
+    pub fn from_text(text: &str) -> Self {
+        Self::from_text_with_backend(text, StorageBackend::default())
+    }
+
+    /// Creates a new empty Document using the given text storage backend
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, StorageBackend};
+    ///
+    /// let mut doc = Document::with_backend(StorageBackend::Rope);
+    /// doc.insert_text(Position::new(0), "Hello").unwrap();
+    /// assert_eq!(doc.storage_backend(), StorageBackend::Rope);
+    /// ```
+    pub fn with_backend(backend: StorageBackend) -> Self {
         Self {
-            text: TextStorage::new(),
+            text: TextBuffer::new(backend),
             version: 0,
             history: CommandHistory::new(),
             formats: FormatStorage::new(),
+            embeds: EmbedStorage::new(),
+            annotations: AnnotationStorage::new(),
+            bookmarks: BookmarkStorage::new(),
+            no_spellcheck: NoSpellcheckStorage::new(),
+            decorations: DecorationStorage::new(),
+            transient_decorations: DecorationStorage::new(),
+            metadata: Metadata::new(),
+            extensions: BTreeMap::new(),
+            sticky_positions: StickyPositionStorage::new(),
             selection: Selection::collapsed(Position::new(0)),
+            secondary_selections: SecondarySelections::new(),
+            selection_expansion: Vec::new(),
             composition: CompositionState::new(),
             dirty_tracker: DirtyTracker::new(),
+            change_log: None,
+            html_cache: HtmlCache::new(),
+            replica: None,
+            pending_ops: None,
+            paste_guard: None,
+            navigation: None,
+            search_state: SearchState::default(),
+            read_only: false,
+            max_length: None,
+            html_sanitizer: None,
+            word_segmenter: None,
+            spellchecker: None,
+            autocorrect: Autocorrect::default(),
+            auto_link: true,
+            input_rules: InputRules::default(),
+            control_chars: ControlCharPolicy::default(),
+            format_affinities: FormatAffinities::new(),
         }
     }
 
-    /// Creates a Document from existing text
-    pub fn from_text(text: &str) -> Self {
+    /// Creates a Document from existing text using the given text storage backend
+    pub fn from_text_with_backend(text: &str, backend: StorageBackend) -> Self {
         Self {
-            text: TextStorage::from_text(text),
+            text: TextBuffer::from_text(text, backend),
             version: 0,
             history: CommandHistory::new(),
             formats: FormatStorage::new(),
+            embeds: EmbedStorage::new(),
+            annotations: AnnotationStorage::new(),
+            bookmarks: BookmarkStorage::new(),
+            no_spellcheck: NoSpellcheckStorage::new(),
+            decorations: DecorationStorage::new(),
+            transient_decorations: DecorationStorage::new(),
+            metadata: Metadata::new(),
+            extensions: BTreeMap::new(),
+            sticky_positions: StickyPositionStorage::new(),
             selection: Selection::collapsed(Position::new(0)),
+            secondary_selections: SecondarySelections::new(),
+            selection_expansion: Vec::new(),
             composition: CompositionState::new(),
             dirty_tracker: DirtyTracker::new(),
+            change_log: None,
+            html_cache: HtmlCache::new(),
+            replica: None,
+            pending_ops: None,
+            paste_guard: None,
+            navigation: None,
+            search_state: SearchState::default(),
+            read_only: false,
+            max_length: None,
+            html_sanitizer: None,
+            word_segmenter: None,
+            spellchecker: None,
+            autocorrect: Autocorrect::default(),
+            auto_link: true,
+            input_rules: InputRules::default(),
+            control_chars: ControlCharPolicy::default(),
+            format_affinities: FormatAffinities::new(),
         }
     }
 
@@ -124,6 +294,11 @@ impl Document {
         self.text.get_text()
     }
 
+    /// Returns the text storage backend this document was created with
+    pub fn storage_backend(&self) -> StorageBackend {
+        self.text.backend()
+    }
+
     /// Returns the length of the document in characters
     pub fn get_length(&self) -> usize {
         self.text.len()
@@ -151,6 +326,98 @@ impl Document {
         self.text.get_slice(start, end)
     }
 
+    /// Returns the offset of the end of the line containing `offset`
+    ///
+    /// The end is the position of the next newline, or the document length
+    /// for the last line.
+    pub(crate) fn line_end_from(&self, offset: usize) -> usize {
+        let lines = self.text.lines();
+        let line = lines.line_of(offset.min(self.get_length()));
+        lines
+            .line_start(line + 1)
+            .map_or(self.get_length(), |next| next - 1)
+    }
+
+    /// Returns the offset where the line containing `offset` starts
+    pub(crate) fn line_start_before(&self, offset: usize) -> usize {
+        let lines = self.text.lines();
+        lines
+            .line_start(lines.line_of(offset.min(self.get_length())))
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of lines, which is one more than the number of
+    /// newlines
+    pub fn line_count(&self) -> usize {
+        self.text.lines().line_count()
+    }
+
+    /// Converts a position to zero-based line and column numbers
+    ///
+    /// Columns count characters from the start of the line. Returns None if
+    /// the position is past the end of the document.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    ///
+    /// let doc = Document::from_text("Hello\nworld");
+    /// assert_eq!(doc.position_to_line_column(Position::new(8)), Some((1, 2)));
+    /// assert_eq!(doc.line_column_to_position(1, 2), Some(Position::new(8)));
+    /// ```
+    pub fn position_to_line_column(&self, pos: Position) -> Option<(usize, usize)> {
+        let offset = pos.offset();
+        if offset > self.get_length() {
+            return None;
+        }
+        let lines = self.text.lines();
+        let line = lines.line_of(offset);
+        lines.line_start(line).map(|start| (line, offset - start))
+    }
+
+    /// Converts zero-based line and column numbers to a position
+    ///
+    /// A column past the end of the line is clamped to the line end. Returns
+    /// None if the line does not exist.
+    pub fn line_column_to_position(&self, line: usize, column: usize) -> Option<Position> {
+        let start = self.text.lines().line_start(line)?;
+        let end = self.line_end_from(start);
+        Some(Position::new(start.saturating_add(column).min(end)))
+    }
+
+    /// Locates a position by block, line within the block and column
+    ///
+    /// Unlike `position_to_line_column`, soft breaks start a new line within
+    /// the block. Returns None if the position is past the end of the
+    /// document.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, OffsetGeometry, Position};
+    ///
+    /// let doc = Document::from_text("Title\nfirst\u{2028}second");
+    /// assert_eq!(
+    ///     doc.offset_geometry(Position::new(15)),
+    ///     Some(OffsetGeometry { block: 1, line: 1, column: 3 })
+    /// );
+    /// ```
+    pub fn offset_geometry(&self, pos: Position) -> Option<OffsetGeometry> {
+        let (block, column) = self.position_to_line_column(pos)?;
+        let before =
+            self.get_text_in_range(Range::from_offsets(pos.offset() - column, pos.offset()));
+        let line = before.chars().filter(|&c| c == SOFT_BREAK).count();
+        let column = before
+            .chars()
+            .rev()
+            .take_while(|&c| c != SOFT_BREAK)
+            .count();
+        Some(OffsetGeometry {
+            block,
+            line,
+            column,
+        })
+    }
+
     /// Returns the current version of the document
     pub fn version(&self) -> u64 {
         self.version
@@ -159,18 +426,81 @@ impl Document {
     /// Increments the document version (called after modifications)
     pub(crate) fn increment_version(&mut self) {
         self.version = self.version.wrapping_add(1);
+        self.dirty_tracker.stamp(self.version);
+        if let Some(log) = &mut self.change_log {
+            log.stamp(self.version);
+        }
     }
 
     /// Marks a range as dirty (for internal use by commands)
+    ///
+    /// Text edits are recorded by the direct edit methods, so this records
+    /// a format change when change recording is on.
     pub(crate) fn mark_dirty(&mut self, range: Range) {
         self.dirty_tracker.mark_dirty(range);
+        if let Some(spellchecker) = &mut self.spellchecker {
+            spellchecker.mark_unchecked(range);
+        }
+        self.record_change(ContentChange::format(range));
+    }
+
+    /// Adds a change to the change log if recording is on
+    fn record_change(&mut self, change: ContentChange) {
+        if let Some(log) = &mut self.change_log {
+            log.record(change);
+        }
+    }
+
+    /// Starts or stops recording edits for `take_changes`
+    ///
+    /// Stopping discards the changes not taken yet.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{ChangeKind, Document, Position};
+    ///
+    /// let mut doc = Document::new();
+    /// doc.set_change_recording(true);
+    /// doc.insert_text(Position::new(0), "Hi").unwrap();
+    ///
+    /// let changes = doc.take_changes();
+    /// assert_eq!(changes[0].kind, ChangeKind::Insert);
+    /// assert_eq!(changes[0].inserted_text, "Hi");
+    /// assert_eq!(changes[0].version, Some(doc.version()));
+    /// ```
+    pub fn set_change_recording(&mut self, enabled: bool) {
+        match (enabled, self.change_log.is_some()) {
+            (true, false) => self.change_log = Some(ChangeLog::new()),
+            (false, true) => self.change_log = None,
+            _ => {}
+        }
+    }
+
+    /// Returns true if edits are being recorded
+    pub fn is_recording_changes(&self) -> bool {
+        self.change_log.is_some()
+    }
+
+    /// Removes and returns the edits recorded since the last call, oldest
+    /// first
+    pub fn take_changes(&mut self) -> Vec<ContentChange> {
+        self.change_log
+            .as_mut()
+            .map(ChangeLog::take)
+            .unwrap_or_default()
     }
 
     /// Internal method to insert text without using command history
     /// Used by commands to perform the actual insertion
     pub(crate) fn insert_text_direct(&mut self, pos: Position, text: &str) {
-        // Validate text content - silently skip invalid text
-        if validation::validate_text_content(text).is_err() {
+        // Validate text content - silently skip invalid text. Commands have
+        // already applied the control character policy, so only text the
+        // policy keeps gets past this
+        let allowed = match self.control_chars {
+            ControlCharPolicy::Keep => !text.contains('\0'),
+            _ => validation::validate_text_content(text).is_ok(),
+        };
+        if !allowed {
             return;
         }
 
@@ -182,14 +512,38 @@ impl Document {
         }
 
         self.text.insert(pos.offset(), text);
-        self.formats.adjust_for_insert(pos, text_length);
+        if let Some(replica) = &mut self.replica {
+            replica.local_insert(pos.offset(), text);
+        }
+        if let Some(pending) = &mut self.pending_ops {
+            pending.record_insert(pos.offset(), text);
+        }
+        self.formats
+            .adjust_for_insert_with_affinity(pos, text_length, &self.format_affinities);
+        self.embeds.adjust_for_insert(pos, text_length);
+        self.annotations.adjust_for_insert(pos, text_length);
+        self.no_spellcheck.adjust_for_insert(pos, text_length);
+        self.decorations.adjust_for_insert(pos, text_length);
+        self.transient_decorations
+            .adjust_for_insert(pos, text_length);
+        if let Some(spellchecker) = &mut self.spellchecker {
+            spellchecker.adjust_for_insert(pos, text_length);
+        }
 
         // Adjust selection for insertion
         self.selection = self.selection.adjust_for_insert(pos, text_length);
+        self.secondary_selections
+            .adjust_for_insert(pos, text_length);
+        if let Some(navigation) = &mut self.navigation {
+            navigation.adjust_for_insert(pos, text_length);
+        }
+        self.bookmarks.adjust_for_insert(pos, text_length);
+        self.sticky_positions.adjust_for_insert(pos, text_length);
 
         // Mark the inserted region as dirty
         let dirty_range = Range::new(pos, Position::new(pos.offset() + text_length));
         self.dirty_tracker.mark_dirty(dirty_range);
+        self.record_change(ContentChange::text(Range::new(pos, pos), text));
 
         self.increment_version();
     }
@@ -204,11 +558,32 @@ impl Document {
 
         self.text
             .delete(normalized.start.offset(), normalized.end.offset());
+        if let Some(replica) = &mut self.replica {
+            replica.local_delete(normalized);
+        }
+        if let Some(pending) = &mut self.pending_ops {
+            pending.record_delete(normalized);
+        }
         self.formats.adjust_for_delete(normalized);
+        self.embeds.adjust_for_delete(normalized);
+        self.annotations.adjust_for_delete(normalized);
+        self.no_spellcheck.adjust_for_delete(normalized);
+        self.decorations.adjust_for_delete(normalized);
+        self.transient_decorations.adjust_for_delete(normalized);
+        if let Some(spellchecker) = &mut self.spellchecker {
+            spellchecker.adjust_for_delete(normalized);
+        }
         self.dirty_tracker.adjust_for_delete(normalized);
 
         // Adjust selection for deletion
         self.selection = self.selection.adjust_for_delete(normalized);
+        self.secondary_selections.adjust_for_delete(normalized);
+        if let Some(navigation) = &mut self.navigation {
+            navigation.adjust_for_delete(normalized);
+        }
+        self.bookmarks.adjust_for_delete(normalized);
+        self.sticky_positions.adjust_for_delete(normalized);
+        self.record_change(ContentChange::text(normalized, ""));
 
         self.increment_version();
     }
@@ -229,11 +604,37 @@ impl Document {
         self.text
             .delete(normalized.start.offset(), normalized.end.offset());
         self.text.insert(normalized.start.offset(), text);
+        if let Some(replica) = &mut self.replica {
+            replica.local_delete(normalized);
+            replica.local_insert(normalized.start.offset(), text);
+        }
+        if let Some(pending) = &mut self.pending_ops {
+            pending.record_delete(normalized);
+            pending.record_insert(normalized.start.offset(), text);
+        }
 
         // Adjust formats: first delete, then insert
         self.formats.adjust_for_delete(normalized);
         self.formats
             .adjust_for_insert(normalized.start, text_length);
+        self.embeds.adjust_for_delete(normalized);
+        self.embeds.adjust_for_insert(normalized.start, text_length);
+        self.annotations.adjust_for_delete(normalized);
+        self.annotations
+            .adjust_for_insert(normalized.start, text_length);
+        self.no_spellcheck.adjust_for_delete(normalized);
+        self.no_spellcheck
+            .adjust_for_insert(normalized.start, text_length);
+        self.decorations.adjust_for_delete(normalized);
+        self.decorations
+            .adjust_for_insert(normalized.start, text_length);
+        self.transient_decorations.adjust_for_delete(normalized);
+        self.transient_decorations
+            .adjust_for_insert(normalized.start, text_length);
+        if let Some(spellchecker) = &mut self.spellchecker {
+            spellchecker.adjust_for_delete(normalized);
+            spellchecker.adjust_for_insert(normalized.start, text_length);
+        }
         self.dirty_tracker.adjust_for_delete(normalized);
         self.dirty_tracker
             .adjust_for_insert(normalized.start, text_length);
@@ -243,20 +644,46 @@ impl Document {
         self.selection = self
             .selection
             .adjust_for_insert(normalized.start, text_length);
+        self.secondary_selections.adjust_for_delete(normalized);
+        self.secondary_selections
+            .adjust_for_insert(normalized.start, text_length);
+        if let Some(navigation) = &mut self.navigation {
+            navigation.adjust_for_delete(normalized);
+            navigation.adjust_for_insert(normalized.start, text_length);
+        }
+        self.bookmarks.adjust_for_delete(normalized);
+        self.bookmarks
+            .adjust_for_insert(normalized.start, text_length);
+        self.sticky_positions.adjust_for_delete(normalized);
+        self.sticky_positions
+            .adjust_for_insert(normalized.start, text_length);
+        self.record_change(ContentChange::text(normalized, text));
 
         self.increment_version();
     }
 
     /// Inserts text at the specified position using the command pattern
+    ///
+    /// Control characters are handled according to the document's
+    /// `ControlCharPolicy`. A single character is treated as typed text:
+    /// it can trigger an input rule or, when none applies, link
+    /// autodetection and autocorrect.
     pub fn insert_text(&mut self, pos: Position, text: &str) -> CommandResult<()> {
+        self.check_editable()?;
+        let text = self.control_chars.apply(text);
+        self.check_max_length(text.chars().count(), 0)?;
         let mut cmd = Box::new(InsertCommand::new(pos, text.to_string()));
         cmd.execute(self)?;
         self.history.push_command(cmd);
+        if !self.apply_input_rules(pos, &text) && !self.auto_link_typed(pos, &text) {
+            self.autocorrect_typed(pos, &text);
+        }
         Ok(())
     }
 
     /// Deletes text in the specified range using the command pattern
     pub fn delete_range(&mut self, range: Range) -> CommandResult<()> {
+        self.check_editable()?;
         let mut cmd = Box::new(DeleteCommand::new(range));
         cmd.execute(self)?;
         self.history.push_command(cmd);
@@ -264,33 +691,207 @@ impl Document {
     }
 
     /// Replaces text in the specified range with new text using the command pattern
+    ///
+    /// Control characters are handled according to the document's
+    /// `ControlCharPolicy`. Inside code blocks only the lines that changed
+    /// are replaced, which keeps the undo data for rewriting a long code
+    /// sample small.
     pub fn replace_range(&mut self, range: Range, text: &str) -> CommandResult<()> {
+        self.check_editable()?;
+        let text = self.control_chars.apply(text);
+        self.check_max_length(text.chars().count(), range.normalize().len())?;
+        let normalized = self.check_delete(range)?;
+        if !normalized.is_empty() && self.is_code_block_range(normalized) {
+            return self.replace_changed_lines(normalized, &text);
+        }
         let mut cmd = Box::new(ReplaceCommand::new(range, text.to_string()));
         cmd.execute(self)?;
         self.history.push_command(cmd);
         Ok(())
     }
 
+    /// Checks whether `insert_text(pos, text)` would succeed, without
+    /// changing the document
+    ///
+    /// # Returns
+    /// The range the inserted text would occupy, after control characters
+    /// are handled according to the document's `ControlCharPolicy`
+    ///
+    /// # Errors
+    /// The error the insertion would fail with
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    ///
+    /// let mut doc = Document::from_text("Hello");
+    /// assert_eq!(
+    ///     doc.check_insert(Position::new(5), " world\u{7}").unwrap(),
+    ///     Range::from_offsets(5, 11)
+    /// );
+    /// doc.set_max_length(Some(8));
+    /// assert!(doc.check_insert(Position::new(5), " world").is_err());
+    /// ```
+    pub fn check_insert(&self, pos: Position, text: &str) -> CommandResult<Range> {
+        self.check_editable()?;
+        let length = self.get_length();
+        if pos.offset() > length {
+            return Err(crate::operations::CommandError::invalid_position(
+                pos.offset(),
+                length,
+            ));
+        }
+        let inserted = self.control_chars.apply(text).chars().count();
+        self.check_max_length(inserted, 0)?;
+        if validation::validate_size_limit(length, inserted).is_err() {
+            return Err(crate::operations::CommandError::max_length_exceeded(
+                length + inserted,
+                MAX_DOCUMENT_SIZE,
+            ));
+        }
+        Ok(Range::from_offsets(pos.offset(), pos.offset() + inserted))
+    }
+
+    /// Checks whether `delete_range(range)` would succeed, without changing
+    /// the document
+    ///
+    /// # Returns
+    /// The normalized range that would be deleted
+    ///
+    /// # Errors
+    /// The error the deletion would fail with
+    pub fn check_delete(&self, range: Range) -> CommandResult<Range> {
+        self.check_editable()?;
+        let normalized = range.normalize();
+        let length = self.get_length();
+        if normalized.end_offset() > length {
+            return Err(crate::operations::CommandError::invalid_range(
+                normalized.start_offset(),
+                normalized.end_offset(),
+                length,
+            ));
+        }
+        Ok(normalized)
+    }
+
     /// Undoes the last operation
+    ///
+    /// An open batch is closed first, so undo reverts the whole batch.
     pub fn undo(&mut self) -> CommandResult<()> {
-        if let Some(mut cmd) = self.history.pop_undo() {
-            cmd.undo(self)?;
-            self.history.push_redo(cmd);
-            Ok(())
-        } else {
-            Err(crate::operations::CommandError::NothingToUndo)
-        }
+        self.check_editable()?;
+        self.history.end_all_batches();
+        let (id, mut cmd) = self
+            .history
+            .take_undo()
+            .ok_or(crate::operations::CommandError::NothingToUndo)?;
+        let result = cmd.undo(self);
+        self.history.finish_undo(id, cmd, result.is_ok());
+        result
     }
 
     /// Redoes the last undone operation
+    ///
+    /// After undoing and then editing, redo follows the new edits; the
+    /// undone branch stays reachable through `undo_to_node`.
     pub fn redo(&mut self) -> CommandResult<()> {
-        if let Some(mut cmd) = self.history.pop_redo() {
-            cmd.execute(self)?;
-            self.history.push_undo(cmd);
-            Ok(())
-        } else {
-            Err(crate::operations::CommandError::NothingToRedo)
+        self.check_editable()?;
+        self.history.end_all_batches();
+        self.redo_into(None)
+            .ok_or(crate::operations::CommandError::NothingToRedo)?
+    }
+
+    /// Executes the command leading to a child of the current history state
+    fn redo_into(&mut self, child: Option<usize>) -> Option<CommandResult<()>> {
+        let (id, mut cmd) = self.history.take_redo(child)?;
+        let result = cmd.execute(self);
+        self.history.finish_redo(id, cmd, result.is_ok());
+        Some(result)
+    }
+
+    /// Moves the document to any state in the undo history
+    ///
+    /// Commands are undone up to the state shared with `id`, then redone
+    /// along the branch leading to it. Later redo follows that branch.
+    ///
+    /// # Errors
+    /// Returns `UnknownHistoryNode` if `id` is not in the history, or the
+    /// error of the first undo or redo that fails; the document then stays
+    /// at the state reached so far.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    ///
+    /// let mut doc = Document::new();
+    /// doc.insert_text(Position::new(0), "first").unwrap();
+    /// let first = doc.current_history_node();
+    /// doc.undo().unwrap();
+    /// doc.insert_text(Position::new(0), "second").unwrap();
+    ///
+    /// doc.undo_to_node(first).unwrap();
+    /// assert_eq!(doc.get_content(), "first");
+    /// ```
+    pub fn undo_to_node(&mut self, id: usize) -> CommandResult<()> {
+        self.check_editable()?;
+        self.history.end_all_batches();
+        let (undo, redo) = self
+            .history
+            .path_to(id)
+            .ok_or_else(|| crate::operations::CommandError::unknown_history_node(id))?;
+        for _ in 0..undo {
+            self.undo()?;
         }
+        for child in redo {
+            self.redo_into(Some(child))
+                .ok_or_else(|| crate::operations::CommandError::unknown_history_node(id))??;
+        }
+        Ok(())
+    }
+
+    /// Lists every state in the undo history, oldest first
+    pub fn history_nodes(&self) -> Vec<HistoryNode> {
+        self.history.nodes()
+    }
+
+    /// Returns the id of the current undo history state
+    pub fn current_history_node(&self) -> usize {
+        self.history.current_node()
+    }
+
+    /// Starts a batch of operations that undo as a single step
+    ///
+    /// Every insert, delete, replace and format operation performed until the
+    /// matching `end_batch` is recorded as one history entry. Batches may be
+    /// nested; the entry is recorded when the outermost batch ends.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    ///
+    /// let mut doc = Document::new();
+    /// doc.begin_batch();
+    /// doc.insert_text(Position::new(0), "Hello").unwrap();
+    /// doc.insert_text(Position::new(5), " World").unwrap();
+    /// doc.end_batch();
+    ///
+    /// doc.undo().unwrap();
+    /// assert_eq!(doc.get_content(), "");
+    /// ```
+    pub fn begin_batch(&mut self) {
+        self.history.begin_batch();
+    }
+
+    /// Ends the current batch started with `begin_batch`
+    ///
+    /// # Returns
+    /// false if no batch was open
+    pub fn end_batch(&mut self) -> bool {
+        self.history.end_batch()
+    }
+
+    /// Returns true if a batch is currently open
+    pub fn is_batching(&self) -> bool {
+        self.history.is_batching()
     }
 
     /// Returns true if there are operations that can be undone
@@ -303,16 +904,92 @@ impl Document {
         self.history.can_redo()
     }
 
+    /// Returns a short name for the change undo would revert, such as
+    /// "typing" or "formatting"
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    /// use rte_core::formatting::InlineFormat;
+    ///
+    /// let mut doc = Document::new();
+    /// doc.insert_text(Position::new(0), "Hi").unwrap();
+    /// assert_eq!(doc.undo_label(), Some("typing"));
+    ///
+    /// doc.apply_format(Range::from_offsets(0, 2), InlineFormat::Bold);
+    /// doc.undo().unwrap();
+    /// assert_eq!(doc.redo_label(), Some("formatting"));
+    /// ```
+    pub fn undo_label(&self) -> Option<&'static str> {
+        self.history.undo_label()
+    }
+
+    /// Returns a short name for the change redo would reapply
+    pub fn redo_label(&self) -> Option<&'static str> {
+        self.history.redo_label()
+    }
+
     /// Applies a format to the specified range using the command pattern
     pub fn apply_format(&mut self, range: Range, format: InlineFormat) {
+        if self.read_only {
+            return;
+        }
         let mut cmd = Box::new(ApplyFormatCommand::new(range, format));
         if let Ok(()) = cmd.execute(self) {
             self.history.push_command(cmd);
         }
     }
 
+    /// Applies many formats as a single undo step
+    ///
+    /// The batch fails as a whole: if any format cannot be applied, the
+    /// ones before it are rolled back and the error is returned, leaving
+    /// the document unchanged.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    /// use rte_core::formatting::InlineFormat;
+    ///
+    /// let mut doc = Document::from_text("Hello World");
+    /// doc.apply_formats(vec![
+    ///     (Range::from_offsets(0, 5), InlineFormat::Bold),
+    ///     (Range::from_offsets(6, 11), InlineFormat::Italic),
+    /// ])
+    /// .unwrap();
+    /// assert!(doc.get_formats_at(Position::new(6)).contains(&InlineFormat::Italic));
+    ///
+    /// doc.undo().unwrap();
+    /// assert!(doc.get_formats_at(Position::new(0)).is_empty());
+    /// ```
+    pub fn apply_formats(&mut self, formats: Vec<(Range, InlineFormat)>) -> CommandResult<()> {
+        self.check_editable()?;
+        let mut applied: Vec<Box<dyn Command>> = Vec::with_capacity(formats.len());
+        for (range, format) in formats {
+            let mut cmd = Box::new(ApplyFormatCommand::new(range, format));
+            if let Err(e) = cmd.execute(self) {
+                for mut cmd in applied.into_iter().rev() {
+                    // Restoring saved format runs cannot fail
+                    let _ = cmd.undo(self);
+                }
+                return Err(e);
+            }
+            applied.push(cmd);
+        }
+
+        self.begin_batch();
+        for cmd in applied {
+            self.history.push_command(cmd);
+        }
+        self.end_batch();
+        Ok(())
+    }
+
     /// Removes a format from the specified range using the command pattern
     pub fn remove_format(&mut self, range: Range, format: &InlineFormat) {
+        if self.read_only {
+            return;
+        }
         let mut cmd = Box::new(RemoveFormatCommand::new(range, format.clone()));
         if let Ok(()) = cmd.execute(self) {
             self.history.push_command(cmd);
@@ -353,6 +1030,11 @@ impl Document {
                     (InlineFormat::Link { .. }, InlineFormat::Link { .. }) => true,
                     (InlineFormat::TextColor { .. }, InlineFormat::TextColor { .. }) => true,
                     (InlineFormat::BackgroundColor { .. }, InlineFormat::BackgroundColor { .. }) => true,
+                    (InlineFormat::Highlight, InlineFormat::Highlight) => true,
+                    (InlineFormat::SmallCaps, InlineFormat::SmallCaps) => true,
+                    (InlineFormat::Subscript, InlineFormat::Subscript) => true,
+                    (InlineFormat::Superscript, InlineFormat::Superscript) => true,
+                    (InlineFormat::Math { .. }, InlineFormat::Math { .. }) => true,
                     _ => false,
                 })
             {
@@ -390,76 +1072,979 @@ impl Document {
 
     /// Sets the block type for the specified range
     pub fn set_block_type(&mut self, range: Range, block_type: BlockType) {
+        if self.read_only {
+            return;
+        }
         let mut cmd = Box::new(SetBlockTypeCommand::new(range, block_type));
         if let Ok(()) = cmd.execute(self) {
             self.history.push_command(cmd);
         }
     }
 
+    /// Sets the block type of every line the range touches, giving each line
+    /// its own block, as one undoable change
+    ///
+    /// Unlike `set_block_type`, which starts and ends the block exactly at
+    /// the range, this covers whole lines, so selecting several paragraphs
+    /// changes each of them entirely and lines stay independent when they
+    /// are edited later. Each line keeps its list nesting and direction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the document is read-only or the range is out of
+    /// bounds.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    /// use rte_core::formatting::BlockType;
+    ///
+    /// let mut doc = Document::from_text("One\nTwo\nThree");
+    /// doc.set_block_type_lines(Range::from_offsets(2, 5), BlockType::heading(2))
+    ///     .unwrap();
+    /// assert_eq!(doc.get_block_type_at(Position::new(0)), BlockType::heading(2));
+    /// assert_eq!(doc.get_block_type_at(Position::new(6)), BlockType::heading(2));
+    /// assert_eq!(doc.get_block_type_at(Position::new(8)), BlockType::Paragraph);
+    /// ```
+    pub fn set_block_type_lines(
+        &mut self,
+        range: Range,
+        block_type: BlockType,
+    ) -> CommandResult<()> {
+        self.check_editable()?;
+        let mut cmd = Box::new(SetLineBlockTypesCommand::new(range, block_type));
+        cmd.execute(self)?;
+        self.history.push_command(cmd);
+        Ok(())
+    }
+
     /// Gets the block type at the specified position
     pub fn get_block_type_at(&self, pos: Position) -> BlockType {
         self.formats.get_block_type_at(pos)
     }
 
-    /// Sets the selection to the specified anchor and focus positions
-    /// The selection is automatically normalized to ensure it's within document bounds
-    pub fn set_selection(&mut self, selection: Selection) {
-        let doc_length = self.get_length();
-        self.selection = selection.normalize(doc_length);
-    }
-
-    /// Gets the current selection
-    pub fn get_selection(&self) -> Selection {
-        self.selection
+    /// Moves the list items on the lines touched by `range` one level deeper
+    ///
+    /// Lines that are not list items are left alone, as are items already
+    /// nested `MAX_LIST_INDENT` levels deep. Nothing is added to the undo
+    /// history when no line changes.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    /// use rte_core::formatting::BlockType;
+    ///
+    /// let mut doc = Document::from_text("One\nTwo");
+    /// doc.set_block_type(Range::from_offsets(0, 7), BlockType::BulletList);
+    /// doc.indent_list_items(Range::from_offsets(4, 4)).unwrap();
+    /// assert_eq!(doc.get_list_indent_at(Position::new(0)), 0);
+    /// assert_eq!(doc.get_list_indent_at(Position::new(4)), 1);
+    /// ```
+    pub fn indent_list_items(&mut self, range: Range) -> CommandResult<()> {
+        self.change_list_indent(range, 1)
     }
 
-    /// Gets the text within the current selection
-    pub fn get_selected_text(&self) -> String {
-        if self.selection.is_collapsed() {
-            String::new()
-        } else {
-            self.get_text_in_range(self.selection.range())
-        }
+    /// Moves the list items on the lines touched by `range` one level up
+    ///
+    /// Top-level items and non-list lines are left alone.
+    pub fn outdent_list_items(&mut self, range: Range) -> CommandResult<()> {
+        self.change_list_indent(range, -1)
     }
 
-    /// Selects all content in the document
-    pub fn select_all(&mut self) {
-        let end = Position::new(self.get_length());
-        self.selection = Selection::new(Position::new(0), end);
+    /// Gets the list nesting level at the specified position
+    /// Returns 0 for top-level list items and non-list lines
+    pub fn get_list_indent_at(&self, pos: Position) -> u8 {
+        self.formats.get_indent_at(pos)
     }
 
-    /// Collapses the selection to the start position
-    pub fn collapse_to_start(&mut self) {
-        let range = self.selection.range().normalize();
-        self.selection = Selection::collapsed(range.start);
+    /// Sets the marker style of the list items on the lines touched by `range`
+    ///
+    /// A `style` of None restores the default bullet or decimal marker.
+    /// Lines that are not list items are left alone. Nothing is added to the
+    /// undo history when no line changes.
+    pub fn set_list_style(&mut self, range: Range, style: Option<ListStyle>) -> CommandResult<()> {
+        self.check_editable()?;
+        let mut cmd = Box::new(SetListStyleCommand::new(range, style));
+        cmd.execute(self)?;
+        if cmd.changed() {
+            self.history.push_command(cmd);
+        }
+        Ok(())
     }
 
-    /// Collapses the selection to the end position
-    pub fn collapse_to_end(&mut self) {
-        let range = self.selection.range().normalize();
-        self.selection = Selection::collapsed(range.end);
+    /// Gets the list marker style at the specified position
+    /// Returns None for the default style and outside of lists
+    pub fn get_list_style_at(&self, pos: Position) -> Option<ListStyle> {
+        self.formats.get_list_style_at(pos)
     }
 
-    /// Returns all dirty regions that have been modified since the last clear
-    pub fn get_dirty_regions(&self) -> Vec<Range> {
-        self.dirty_tracker.get_dirty_regions()
-    }
+    /// Gets the number of the list item at the specified position, counting
+    /// from 1
+    ///
+    /// Items belong to the same list while they follow each other at the
+    /// same nesting level with the same type and style; items nested deeper
+    /// in between do not interrupt the count. Returns None outside of lists.
+    pub fn get_list_number_at(&self, pos: Position) -> Option<usize> {
+        let line_start = self.line_start_before(pos.offset());
+        let item = self.list_item_at(line_start)?;
+
+        if let Some(start) = self.get_list_start_at(Position::new(line_start)) {
+            return Some(start);
+        }
 
-    /// Clears all dirty flags
-    pub fn clear_dirty_flags(&mut self) {
-        self.dirty_tracker.clear_dirty_flags();
+        let mut number = 1;
+        let mut previous_end = line_start;
+        while previous_end > 0 {
+            let previous_start = self.line_start_before(previous_end - 1);
+            match self.list_item_at(previous_start) {
+                Some(previous) if previous.1 > item.1 => {}
+                Some(previous) if previous == item => {
+                    if let Some(start) = self.get_list_start_at(Position::new(previous_start)) {
+                        return Some(start + number);
+                    }
+                    number += 1;
+                }
+                _ => break,
+            }
+            previous_end = previous_start;
+        }
+        Some(number)
     }
 
-    /// Returns true if there are any dirty regions
-    pub fn has_dirty_regions(&self) -> bool {
-        self.dirty_tracker.has_dirty_regions()
+    /// Gets the number the list item at the specified position restarts its
+    /// list at
+    ///
+    /// Returns None for items that continue the count of the items before
+    /// them and outside of numbered lists. Restart numbers come from
+    /// imported documents, such as a Markdown list starting at `3.`.
+    pub fn get_list_start_at(&self, pos: Position) -> Option<usize> {
+        let line_start = self.line_start_before(pos.offset());
+        self.formats.get_list_start_at(Position::new(line_start))
+    }
+
+    /// Clears the restart numbers of list items that continue a list, and
+    /// restarts at 1, as `renumber_lists` does, returning true if any line
+    /// changed
+    pub(crate) fn normalize_list_starts(&mut self) -> bool {
+        let length = self.get_length();
+        // Type and style of the open list at each nesting level
+        let mut open: Vec<Option<(BlockType, Option<ListStyle>)>> = Vec::new();
+        let mut changed = false;
+        let mut line_start = 0;
+        loop {
+            let line_end = self.line_end_from(line_start);
+            let next_line = (line_end < length).then_some(line_end + 1);
+            if let Some((block_type, level, style)) = self.list_item_at(line_start) {
+                let level = level as usize;
+                // An item ends the lists nested deeper than it
+                open.resize(level + 1, None);
+                let list = Some((block_type, style));
+                let continues = open[level] == list;
+                open[level] = list;
+
+                let position = Position::new(line_start);
+                let current = self.get_list_start_at(position);
+                let start = current.filter(|&start| !continues && start != 1);
+                if current != start {
+                    self.formats.set_list_start(line_start, next_line, start);
+                    changed = true;
+                }
+            } else {
+                open.clear();
+            }
+            match next_line {
+                Some(next) => line_start = next,
+                None => break,
+            }
+        }
+        changed
     }
 
-    /// Starts an IME composition at the current cursor position
+    /// Normalizes where numbered lists restart their count
     ///
-    /// This marks the beginning of an IME composition session. The composition
-    /// will track the range where composition text is being entered.
+    /// Moving list items around, such as by cutting and pasting them or
+    /// deleting the lines between two lists, can leave an item in the
+    /// middle of a list restarting its count. Each list keeps the number
+    /// its first item starts at, and the items after it are numbered on
+    /// from there. Nothing is added to the undo history when no line
+    /// changes.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    ///
+    /// let mut doc = Document::from_markdown("1. One\n\nBreak\n\n7. Seven").unwrap();
+    /// assert_eq!(doc.get_list_start_at(Position::new(11)), Some(7));
+    ///
+    /// // Joining the lists leaves "Seven" restarting the count
+    /// doc.delete_range(Range::from_offsets(4, 11)).unwrap();
+    /// assert_eq!(doc.get_content(), "One\nSeven");
+    /// assert_eq!(doc.to_markdown(), "1. One\n7. Seven");
+    ///
+    /// doc.renumber_lists().unwrap();
+    /// assert_eq!(doc.to_markdown(), "1. One\n2. Seven");
+    /// ```
+    pub fn renumber_lists(&mut self) -> CommandResult<()> {
+        self.check_editable()?;
+        let mut cmd = Box::new(RenumberListsCommand::new());
+        cmd.execute(self)?;
+        if cmd.changed() {
+            self.history.push_command(cmd);
+        }
+        Ok(())
+    }
+
+    /// Gets the marker shown before the list item at the specified position
+    ///
+    /// Numbered markers are followed by a period, such as `"3."` or `"iv."`;
+    /// bullet lists without a style use `"•"`. Returns None outside of lists.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    /// use rte_core::formatting::{BlockType, ListStyle};
+    ///
+    /// let mut doc = Document::from_text("One\nTwo\nThree");
+    /// doc.set_block_type(Range::from_offsets(0, 13), BlockType::NumberedList);
+    /// assert_eq!(doc.get_list_marker_at(Position::new(8)).as_deref(), Some("3."));
+    ///
+    /// doc.set_list_style(Range::from_offsets(0, 13), Some(ListStyle::LowerAlpha))
+    ///     .unwrap();
+    /// assert_eq!(doc.get_list_marker_at(Position::new(8)).as_deref(), Some("c."));
+    /// ```
+    pub fn get_list_marker_at(&self, pos: Position) -> Option<String> {
+        let line_start = self.line_start_before(pos.offset());
+        let (block_type, _, style) = self.list_item_at(line_start)?;
+        let style = match (style, block_type) {
+            (Some(style), _) => style,
+            (None, BlockType::NumberedList) => ListStyle::Decimal,
+            (None, _) => ListStyle::Bullet('•'),
+        };
+        if !style.is_numbered() {
+            return Some(style.format_number(1));
+        }
+        let number = self.get_list_number_at(Position::new(line_start))?;
+        Some(format!("{}.", style.format_number(number)))
+    }
+
+    /// Returns the type, nesting level and style of the list item on the line
+    /// starting at `line_start`, or None if the line is not a list item
+    fn list_item_at(&self, line_start: usize) -> Option<(BlockType, u8, Option<ListStyle>)> {
+        let position = Position::new(line_start);
+        let block_type = self.get_block_type_at(position);
+        matches!(block_type, BlockType::BulletList | BlockType::NumberedList).then(|| {
+            (
+                block_type,
+                self.get_list_indent_at(position),
+                self.get_list_style_at(position),
+            )
+        })
+    }
+
+    /// Moves the paragraphs on the lines touched by `range` one indent step
+    /// to the right
+    ///
+    /// This is the paragraph indent, separate from list nesting: lines that
+    /// are not paragraphs are left alone, as are paragraphs already
+    /// `MAX_BLOCK_INDENT` steps in. Nothing is added to the undo history
+    /// when no line changes.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    ///
+    /// let mut doc = Document::from_text("One\nTwo");
+    /// doc.increase_block_indent(Range::from_offsets(0, 5)).unwrap();
+    /// doc.increase_block_indent(Range::from_offsets(4, 4)).unwrap();
+    /// assert_eq!(doc.get_block_indent_at(Position::new(0)), 1);
+    /// assert_eq!(doc.get_block_indent_at(Position::new(4)), 2);
+    /// assert!(doc.to_html().contains("<p style=\"margin-left: 4em\">Two</p>"));
+    /// ```
+    pub fn increase_block_indent(&mut self, range: Range) -> CommandResult<()> {
+        self.change_block_indent(range, 1)
+    }
+
+    /// Moves the paragraphs on the lines touched by `range` one indent step
+    /// to the left
+    ///
+    /// Unindented paragraphs and lines that are not paragraphs are left alone.
+    pub fn decrease_block_indent(&mut self, range: Range) -> CommandResult<()> {
+        self.change_block_indent(range, -1)
+    }
+
+    /// Gets the paragraph indent at the specified position, in steps
+    /// Returns 0 for unindented paragraphs and other blocks
+    pub fn get_block_indent_at(&self, pos: Position) -> u8 {
+        self.formats.get_block_indent_at(pos)
+    }
+
+    /// Turns the first-line indent of the paragraphs on the lines touched by
+    /// `range` on or off
+    ///
+    /// Lines that are not paragraphs are left alone. Nothing is added to the
+    /// undo history when no line changes.
+    pub fn set_first_line_indent(&mut self, range: Range, indented: bool) -> CommandResult<()> {
+        self.check_editable()?;
+        let mut cmd = Box::new(SetFirstLineIndentCommand::new(range, indented));
+        cmd.execute(self)?;
+        if cmd.changed() {
+            self.history.push_command(cmd);
+        }
+        Ok(())
+    }
+
+    /// Returns true if the paragraph at the specified position has an
+    /// indented first line
+    pub fn has_first_line_indent_at(&self, pos: Position) -> bool {
+        self.formats.has_first_line_indent_at(pos)
+    }
+
+    /// Sets the base text direction of the lines touched by `range`
+    ///
+    /// A `direction` of None detects each line's direction from its first
+    /// strong character. The direction applies to every block type and is
+    /// kept when the block type changes. Nothing is added to the undo
+    /// history when no line changes.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    /// use rte_core::formatting::TextDirection;
+    ///
+    /// let mut doc = Document::from_text("مرحبا\nHello");
+    /// assert_eq!(doc.get_base_direction_at(Position::new(0)), TextDirection::Rtl);
+    /// assert_eq!(doc.get_base_direction_at(Position::new(6)), TextDirection::Ltr);
+    ///
+    /// doc.set_text_direction(Range::from_offsets(6, 6), Some(TextDirection::Rtl))
+    ///     .unwrap();
+    /// assert_eq!(doc.get_base_direction_at(Position::new(6)), TextDirection::Rtl);
+    /// assert!(doc.to_html().contains("<p dir=\"rtl\">Hello</p>"));
+    /// ```
+    pub fn set_text_direction(
+        &mut self,
+        range: Range,
+        direction: Option<TextDirection>,
+    ) -> CommandResult<()> {
+        self.check_editable()?;
+        let mut cmd = Box::new(SetTextDirectionCommand::new(range, direction));
+        cmd.execute(self)?;
+        if cmd.changed() {
+            self.history.push_command(cmd);
+        }
+        Ok(())
+    }
+
+    /// Gets the text direction set on the line at the specified position
+    /// Returns None where the direction is detected from the text
+    pub fn get_text_direction_at(&self, pos: Position) -> Option<TextDirection> {
+        let line_start = self.line_start_before(pos.offset());
+        self.formats.get_direction_at(Position::new(line_start))
+    }
+
+    /// Gets the base direction of the line at the specified position
+    ///
+    /// This is the direction set on the line, or else the direction of its
+    /// first strong character, or left-to-right if it has none.
+    pub fn get_base_direction_at(&self, pos: Position) -> TextDirection {
+        self.get_text_direction_at(pos).unwrap_or_else(|| {
+            let line_start = self.line_start_before(pos.offset());
+            let line: Vec<char> = self
+                .get_text_in_range(Range::from_offsets(
+                    line_start,
+                    self.line_end_from(line_start),
+                ))
+                .chars()
+                .collect();
+            first_strong_direction(&line).unwrap_or(TextDirection::Ltr)
+        })
+    }
+
+    /// Resolves the bidirectional embedding levels of the line at the
+    /// specified position
+    ///
+    /// The result gives the display order of the line's characters, its
+    /// runs of left-to-right and right-to-left text, and visual caret
+    /// movement.
+    pub fn bidi_line_at(&self, pos: Position) -> BidiLine {
+        let line_start = self.line_start_before(pos.offset());
+        let line_end = self.line_end_from(line_start);
+        let line: Vec<char> = self
+            .get_text_in_range(Range::from_offsets(line_start, line_end))
+            .chars()
+            .collect();
+        BidiLine::resolve(&line, line_start, self.get_text_direction_at(pos))
+    }
+
+    fn change_block_indent(&mut self, range: Range, delta: i8) -> CommandResult<()> {
+        self.check_editable()?;
+        let mut cmd = Box::new(ChangeBlockIndentCommand::new(range, delta));
+        cmd.execute(self)?;
+        if cmd.changed() {
+            self.history.push_command(cmd);
+        }
+        Ok(())
+    }
+
+    fn change_list_indent(&mut self, range: Range, delta: i8) -> CommandResult<()> {
+        self.check_editable()?;
+        let mut cmd = Box::new(ChangeListIndentCommand::new(range, delta));
+        cmd.execute(self)?;
+        if cmd.changed() {
+            self.history.push_command(cmd);
+        }
+        Ok(())
+    }
+
+    /// Inserts an inline image at the specified position
+    ///
+    /// The image occupies a single `EMBED_CHAR` in the text. Fails if the
+    /// position is out of bounds or the image source is not a safe URL.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Image, Position};
+    ///
+    /// let mut doc = Document::from_text("ab");
+    /// doc.insert_image(Position::new(1), Image::new("https://example.com/a.png", "A"))
+    ///     .unwrap();
+    /// assert_eq!(doc.get_length(), 3);
+    /// assert_eq!(doc.get_image_at(Position::new(1)).unwrap().alt, "A");
+    /// ```
+    pub fn insert_image(&mut self, pos: Position, image: Image) -> CommandResult<()> {
+        self.check_editable()?;
+        self.check_max_length(1, 0)?;
+        let mut cmd = Box::new(InsertImageCommand::new(pos, image));
+        cmd.execute(self)?;
+        self.history.push_command(cmd);
+        Ok(())
+    }
+
+    /// Replaces the attributes of the image at the specified position
+    pub fn update_image(&mut self, pos: Position, image: Image) -> CommandResult<()> {
+        self.check_editable()?;
+        let mut cmd = Box::new(UpdateImageCommand::new(pos, image));
+        cmd.execute(self)?;
+        self.history.push_command(cmd);
+        Ok(())
+    }
+
+    /// Gets the image at the specified position, if any
+    pub fn get_image_at(&self, pos: Position) -> Option<&Image> {
+        self.embeds.get(pos.offset())
+    }
+
+    /// Gets the embedded object storage
+    pub(crate) fn embeds(&self) -> &EmbedStorage {
+        &self.embeds
+    }
+
+    /// Gets mutable access to the embedded object storage
+    pub(crate) fn embeds_mut(&mut self) -> &mut EmbedStorage {
+        &mut self.embeds
+    }
+
+    /// Attaches an annotation to a range of text
+    ///
+    /// An annotation with the same id is replaced. The range must not be
+    /// empty.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Annotation, Document, Position, Range};
+    ///
+    /// let mut doc = Document::from_text("Hello World");
+    /// let comment = Annotation::new("c1").with_author("ann").with_payload("Nice");
+    /// doc.add_annotation(Range::from_offsets(6, 11), comment).unwrap();
+    ///
+    /// doc.insert_text(Position::new(0), ">> ").unwrap();
+    /// let (range, annotation) = doc.get_annotation("c1").unwrap();
+    /// assert_eq!(range, Range::from_offsets(9, 14));
+    /// assert_eq!(annotation.payload, "Nice");
+    /// ```
+    pub fn add_annotation(&mut self, range: Range, annotation: Annotation) -> CommandResult<()> {
+        self.check_editable()?;
+        let mut cmd = Box::new(AddAnnotationCommand::new(range, annotation));
+        cmd.execute(self)?;
+        self.history.push_command(cmd);
+        Ok(())
+    }
+
+    /// Removes the annotation with the given id
+    pub fn remove_annotation(&mut self, id: &str) -> CommandResult<()> {
+        self.check_editable()?;
+        let mut cmd = Box::new(RemoveAnnotationCommand::new(id.to_string()));
+        cmd.execute(self)?;
+        self.history.push_command(cmd);
+        Ok(())
+    }
+
+    /// Gets the annotation with the given id along with its current range
+    pub fn get_annotation(&self, id: &str) -> Option<(Range, &Annotation)> {
+        self.annotations.get(id)
+    }
+
+    /// Gets the annotations covering the character at the specified position
+    pub fn get_annotations_at(&self, pos: Position) -> Vec<(Range, &Annotation)> {
+        self.annotations.annotations_at(pos.offset())
+    }
+
+    /// Gets the annotation storage
+    pub(crate) fn annotations(&self) -> &AnnotationStorage {
+        &self.annotations
+    }
+
+    /// Gets mutable access to the annotation storage
+    pub(crate) fn annotations_mut(&mut self) -> &mut AnnotationStorage {
+        &mut self.annotations
+    }
+
+    /// Sets a named bookmark at the specified position
+    ///
+    /// A bookmark with the same id is moved. Bookmarks shift with edits like
+    /// the selection does and are not recorded in the undo history, so they
+    /// can be set on read-only documents.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    ///
+    /// let mut doc = Document::from_text("Intro\nDetails");
+    /// doc.set_bookmark("details", Position::new(6)).unwrap();
+    /// doc.insert_text(Position::new(0), "# ").unwrap();
+    /// assert_eq!(doc.get_bookmark("details"), Some(Position::new(8)));
+    /// ```
+    pub fn set_bookmark(&mut self, id: &str, pos: Position) -> CommandResult<()> {
+        let length = self.get_length();
+        if pos.offset() > length {
+            return Err(crate::operations::CommandError::invalid_position(
+                pos.offset(),
+                length,
+            ));
+        }
+        self.bookmarks.set(id, pos);
+        Ok(())
+    }
+
+    /// Gets the current position of the bookmark with the given id
+    pub fn get_bookmark(&self, id: &str) -> Option<Position> {
+        self.bookmarks.get(id)
+    }
+
+    /// Removes the bookmark with the given id
+    /// Returns true if the bookmark existed
+    pub fn remove_bookmark(&mut self, id: &str) -> bool {
+        self.bookmarks.remove(id).is_some()
+    }
+
+    /// Gets the bookmark storage
+    pub(crate) fn bookmarks(&self) -> &BookmarkStorage {
+        &self.bookmarks
+    }
+
+    /// Marks a range of text for spellcheckers to skip, such as a code span
+    /// or a proper noun
+    ///
+    /// Marked ranges shift with edits and are saved in JSON. HTML export
+    /// wraps them in `<span spellcheck="false">`. Like bookmarks they are
+    /// not recorded in the undo history, so they can be set on read-only
+    /// documents.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    ///
+    /// let mut doc = Document::from_text("Ask Siobhan");
+    /// doc.mark_no_spellcheck(Range::from_offsets(4, 11)).unwrap();
+    /// assert!(doc.is_no_spellcheck_at(Position::new(6)));
+    /// assert!(doc.to_html().contains("<span spellcheck=\"false\">Siobhan</span>"));
+    /// ```
+    ///
+    /// # Errors
+    /// Returns `CommandError::InvalidRange` if the range extends past the
+    /// end of the document
+    pub fn mark_no_spellcheck(&mut self, range: Range) -> CommandResult<()> {
+        let normalized = self.check_marked_range(range)?;
+        self.no_spellcheck.mark(normalized);
+        if let Some(spellchecker) = &mut self.spellchecker {
+            spellchecker.mark_unchecked(normalized);
+        }
+        self.increment_version();
+        Ok(())
+    }
+
+    /// Lets spellcheckers check a range of text again, unmarking any part
+    /// of it marked by `mark_no_spellcheck`
+    ///
+    /// # Errors
+    /// Returns `CommandError::InvalidRange` if the range extends past the
+    /// end of the document
+    pub fn clear_no_spellcheck(&mut self, range: Range) -> CommandResult<()> {
+        let normalized = self.check_marked_range(range)?;
+        self.no_spellcheck.unmark(normalized);
+        if let Some(spellchecker) = &mut self.spellchecker {
+            spellchecker.mark_unchecked(normalized);
+        }
+        self.increment_version();
+        Ok(())
+    }
+
+    /// Returns true if the character at the position is marked for
+    /// spellcheckers to skip
+    pub fn is_no_spellcheck_at(&self, pos: Position) -> bool {
+        self.no_spellcheck.contains(pos.offset())
+    }
+
+    /// Returns the ranges marked for spellcheckers to skip, in order
+    pub fn no_spellcheck_ranges(&self) -> &[Range] {
+        self.no_spellcheck.ranges()
+    }
+
+    /// Gets the storage of ranges marked for spellcheckers to skip
+    pub(crate) fn no_spellcheck(&self) -> &NoSpellcheckStorage {
+        &self.no_spellcheck
+    }
+
+    /// Marks a range of text for an application feature, such as a linter
+    /// warning or a collaborator's highlight
+    ///
+    /// Decorations shift with edits and are saved in JSON, but leave the
+    /// text, its formatting and the exported HTML alone. Any number of
+    /// decorations may cover the same text. Like bookmarks they are not
+    /// recorded in the undo history, so they can be set on read-only
+    /// documents.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Decoration, Document, Position, Range};
+    ///
+    /// let mut doc = Document::from_text("teh cat");
+    /// let warning = Decoration::new("lint").with_attribute("message", "Typo");
+    /// doc.add_decoration(Range::from_offsets(0, 3), warning).unwrap();
+    ///
+    /// doc.insert_text(Position::new(0), "See ").unwrap();
+    /// let decorations = doc.get_decorations_at(Position::new(5));
+    /// assert_eq!(decorations[0].0, Range::from_offsets(4, 7));
+    /// assert_eq!(decorations[0].1.attributes["message"], "Typo");
+    /// ```
+    ///
+    /// # Errors
+    /// Returns `CommandError::InvalidRange` if the range is empty or extends
+    /// past the end of the document
+    pub fn add_decoration(&mut self, range: Range, decoration: Decoration) -> CommandResult<()> {
+        let normalized = self.check_marked_range(range)?;
+        if normalized.is_empty() {
+            return Err(crate::operations::CommandError::invalid_range(
+                normalized.start_offset(),
+                normalized.end_offset(),
+                self.get_length(),
+            ));
+        }
+        self.decorations.add(normalized, decoration);
+        Ok(())
+    }
+
+    /// Removes the decorations of the given kind overlapping a range,
+    /// returning how many were removed
+    pub fn remove_decorations(&mut self, kind: &str, range: Range) -> usize {
+        self.decorations.remove(kind, range)
+    }
+
+    /// Gets the decorations covering the character at the specified position
+    pub fn get_decorations_at(&self, pos: Position) -> Vec<(Range, &Decoration)> {
+        self.decorations.decorations_at(pos.offset())
+    }
+
+    /// Replaces the transient decorations with the given ids and ranges
+    ///
+    /// Transient decorations are for highlights the application recomputes
+    /// as it goes, such as search hits, spelling errors or collaborator
+    /// cursors. They shift with edits like other decorations but are never
+    /// saved, and `to_html_range` wraps the text they cover in
+    /// `<mark data-deco-id="...">` elements. Loading other content clears
+    /// them.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    ///
+    /// let mut doc = Document::from_text("find me");
+    /// doc.set_transient_decorations([(Range::from_offsets(5, 7), "hit-1".to_string())])
+    ///     .unwrap();
+    /// doc.insert_text(Position::new(0), "> ").unwrap();
+    /// assert_eq!(
+    ///     doc.to_html_range(None),
+    ///     "<p>&gt; find <mark data-deco-id=\"hit-1\">me</mark></p>\n"
+    /// );
+    /// assert_eq!(doc.to_html(), "<p>&gt; find me</p>\n");
+    /// ```
+    ///
+    /// # Errors
+    /// Returns `CommandError::InvalidRange`, leaving the current
+    /// decorations in place, if a range is empty or extends past the end
+    /// of the document
+    pub fn set_transient_decorations(
+        &mut self,
+        decorations: impl IntoIterator<Item = (Range, String)>,
+    ) -> CommandResult<()> {
+        let mut storage = DecorationStorage::new();
+        for (range, id) in decorations {
+            let normalized = self.check_marked_range(range)?;
+            if normalized.is_empty() {
+                return Err(crate::operations::CommandError::invalid_range(
+                    normalized.start_offset(),
+                    normalized.end_offset(),
+                    self.get_length(),
+                ));
+            }
+            storage.add(normalized, id);
+        }
+        self.transient_decorations = storage;
+        Ok(())
+    }
+
+    /// Removes all transient decorations
+    pub fn clear_transient_decorations(&mut self) {
+        self.transient_decorations.clear();
+    }
+
+    /// Gets the transient decorations with their ranges, sorted by start
+    pub fn transient_decorations(&self) -> &[(Range, String)] {
+        self.transient_decorations.decorations()
+    }
+
+    /// Gets the decoration storage
+    pub(crate) fn decorations(&self) -> &DecorationStorage {
+        &self.decorations
+    }
+
+    /// Gets mutable access to the decoration storage
+    pub(crate) fn decorations_mut(&mut self) -> &mut DecorationStorage {
+        &mut self.decorations
+    }
+
+    /// Gets the application sections saved in JSON under `extensions`
+    pub(crate) fn extensions(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.extensions
+    }
+
+    /// Gets mutable access to the application sections saved in JSON
+    pub(crate) fn extensions_mut(&mut self) -> &mut BTreeMap<String, serde_json::Value> {
+        &mut self.extensions
+    }
+
+    /// Checks that a range to mark or unmark lies within the document
+    fn check_marked_range(&self, range: Range) -> CommandResult<Range> {
+        let normalized = range.normalize();
+        let length = self.get_length();
+        if normalized.end_offset() > length {
+            return Err(crate::operations::CommandError::invalid_range(
+                normalized.start_offset(),
+                normalized.end_offset(),
+                length,
+            ));
+        }
+        Ok(normalized)
+    }
+
+    /// Sets a metadata value, such as the title or a front matter field
+    ///
+    /// Metadata is kept apart from the text. Setting an existing key
+    /// replaces its value. The change can be undone.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::Document;
+    ///
+    /// let mut doc = Document::from_text("Hello");
+    /// doc.set_metadata("title", "Greeting").unwrap();
+    /// assert_eq!(doc.get_metadata("title"), Some("Greeting"));
+    /// assert_eq!(doc.to_markdown(), "---\ntitle: Greeting\n---\nHello");
+    ///
+    /// doc.undo().unwrap();
+    /// assert_eq!(doc.get_metadata("title"), None);
+    /// ```
+    pub fn set_metadata(&mut self, key: &str, value: &str) -> CommandResult<()> {
+        self.update_metadata(vec![(key.to_string(), Some(value.to_string()))])
+    }
+
+    /// Gets the metadata value of the given key
+    pub fn get_metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key)
+    }
+
+    /// Removes the metadata value of the given key
+    /// Returns true if the key existed
+    pub fn remove_metadata(&mut self, key: &str) -> CommandResult<bool> {
+        if self.metadata.get(key).is_none() {
+            return Ok(false);
+        }
+        self.update_metadata(vec![(key.to_string(), None)])?;
+        Ok(true)
+    }
+
+    /// Sets and removes several metadata values as one undoable change
+    ///
+    /// Each entry sets a key to a value, or removes it if the value is None.
+    pub fn update_metadata(&mut self, changes: Vec<(String, Option<String>)>) -> CommandResult<()> {
+        self.check_editable()?;
+        let mut cmd = Box::new(SetMetadataCommand::new(changes));
+        cmd.execute(self)?;
+        self.history.push_command(cmd);
+        Ok(())
+    }
+
+    /// Gets all document metadata
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Gets mutable access to the document metadata (for internal use)
+    pub(crate) fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    /// Creates a sticky position and returns its handle
+    ///
+    /// The position tracks later edits until it is released. With
+    /// `Bias::Left` text inserted exactly at the position ends up after it;
+    /// with `Bias::Right` the position moves past the inserted text. Like
+    /// bookmarks, sticky positions are not part of the undo history.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Bias, Document, Position};
+    ///
+    /// let mut doc = Document::from_text("Hello world");
+    /// let start = doc.create_sticky_position(Position::new(6), Bias::Left).unwrap();
+    /// let end = doc.create_sticky_position(Position::new(6), Bias::Right).unwrap();
+    /// doc.insert_text(Position::new(6), "big ").unwrap();
+    /// assert_eq!(doc.resolve_sticky_position(start), Some(Position::new(6)));
+    /// assert_eq!(doc.resolve_sticky_position(end), Some(Position::new(10)));
+    /// ```
+    pub fn create_sticky_position(&mut self, pos: Position, bias: Bias) -> CommandResult<u32> {
+        let length = self.get_length();
+        if pos.offset() > length {
+            return Err(crate::operations::CommandError::invalid_position(
+                pos.offset(),
+                length,
+            ));
+        }
+        Ok(self.sticky_positions.create(pos, bias))
+    }
+
+    /// Gets the current position of a sticky position handle
+    pub fn resolve_sticky_position(&self, handle: u32) -> Option<Position> {
+        self.sticky_positions.resolve(handle)
+    }
+
+    /// Releases a sticky position handle
+    /// Returns true if the handle was live
+    pub fn release_sticky_position(&mut self, handle: u32) -> bool {
+        self.sticky_positions.release(handle).is_some()
+    }
+
+    /// Sets the selection to the specified anchor and focus positions
+    /// The selection is automatically normalized to ensure it's within document bounds
+    ///
+    /// This sets the primary selection; secondary selections it overlaps are
+    /// merged into it and the others are kept.
+    pub fn set_selection(&mut self, selection: Selection) {
+        let doc_length = self.get_length();
+        self.selection = selection.normalize(doc_length);
+        self.merge_selections();
+    }
+
+    /// Gets the current selection
+    pub fn get_selection(&self) -> Selection {
+        self.selection
+    }
+
+    /// Gets the text within the current selection
+    pub fn get_selected_text(&self) -> String {
+        if self.selection.is_collapsed() {
+            String::new()
+        } else {
+            self.get_text_in_range(self.selection.range())
+        }
+    }
+
+    /// Selects all content in the document
+    pub fn select_all(&mut self) {
+        let end = Position::new(self.get_length());
+        self.selection = Selection::new(Position::new(0), end);
+        self.secondary_selections.clear();
+    }
+
+    /// Collapses the selection to the start position
+    pub fn collapse_to_start(&mut self) {
+        let range = self.selection.range().normalize();
+        self.selection = Selection::collapsed(range.start);
+    }
+
+    /// Collapses the selection to the end position
+    pub fn collapse_to_end(&mut self) {
+        let range = self.selection.range().normalize();
+        self.selection = Selection::collapsed(range.end);
+    }
+
+    /// Returns all dirty regions that have been modified since the last clear
+    pub fn get_dirty_regions(&self) -> Vec<Range> {
+        self.dirty_tracker.get_dirty_regions()
+    }
+
+    /// Clears all dirty flags
+    pub fn clear_dirty_flags(&mut self) {
+        self.dirty_tracker.clear_dirty_flags();
+    }
+
+    /// Returns the dirty regions with their ids and the document version
+    /// of the latest edit inside each
+    pub fn dirty_regions(&self) -> &[DirtyRegion] {
+        self.dirty_tracker.regions()
+    }
+
+    /// Clears the dirty regions last changed at or before `up_to_version`
+    ///
+    /// Renderers that paint asynchronously pass the version they rendered,
+    /// so regions edited since then stay dirty.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    ///
+    /// let mut doc = Document::from_text("Hello");
+    /// doc.insert_text(Position::new(5), "!").unwrap();
+    /// let painted = doc.version();
+    /// doc.insert_text(Position::new(0), ">").unwrap();
+    ///
+    /// doc.acknowledge_dirty(painted);
+    /// assert_eq!(doc.dirty_regions().len(), 1);
+    /// ```
+    pub fn acknowledge_dirty(&mut self, up_to_version: u64) {
+        self.dirty_tracker.acknowledge(up_to_version);
+    }
+
+    /// Merges all dirty regions into one range spanning them
+    ///
+    /// Useful after bulk updates, so a renderer repaints one region rather
+    /// than many small ones.
+    pub fn coalesce_dirty_regions(&mut self) {
+        self.dirty_tracker.coalesce();
+    }
+
+    /// Returns true if there are any dirty regions
+    pub fn has_dirty_regions(&self) -> bool {
+        self.dirty_tracker.has_dirty_regions()
+    }
+
+    /// Starts an IME composition at the current cursor position
+    ///
+    /// This marks the beginning of an IME composition session. The composition
+    /// will track the range where composition text is being entered.
     pub fn start_composition(&mut self) {
+        if self.read_only {
+            return;
+        }
         let cursor_pos = if self.selection.is_collapsed() {
             self.selection.anchor
         } else {
@@ -483,6 +2068,12 @@ impl Document {
 
         // Delete the old composition text if any
         let old_range = self.composition.range();
+        if self
+            .check_max_length(text.chars().count(), old_range.normalize().len())
+            .is_err()
+        {
+            return;
+        }
         if !old_range.is_empty() {
             self.delete_range_direct(old_range);
         }
@@ -590,6 +2181,48 @@ impl Document {
         self.history.set_max_size(max_size);
     }
 
+    /// Gets the window within which typed characters share an undo step
+    ///
+    /// # Returns
+    /// None if every typed character is its own undo step
+    pub fn get_undo_coalesce_window(&self) -> Option<Duration> {
+        self.history.get_coalesce_window()
+    }
+
+    /// Sets the window within which typed characters share an undo step
+    ///
+    /// Single characters inserted right after the previous one, less than
+    /// `window` apart, are undone together until whitespace follows a word.
+    /// Pass None to undo every typed character separately.
+    pub fn set_undo_coalesce_window(&mut self, window: Option<Duration>) {
+        self.history.set_coalesce_window(window);
+    }
+
+    /// Merges runs of older undo steps with the same label, keeping the
+    /// `keep_recent` newest steps as they are
+    ///
+    /// Meant to be called while the editor is idle; with a `budget` it stops
+    /// once that much time has passed. See `CommandHistory::compact`.
+    ///
+    /// # Returns
+    /// true if the budget ran out and calling again would compact more
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    ///
+    /// let mut doc = Document::new();
+    /// doc.insert_text(Position::new(0), "Hello ").unwrap();
+    /// doc.insert_text(Position::new(6), "World").unwrap();
+    ///
+    /// doc.compact_history(0, None);
+    /// doc.undo().unwrap();
+    /// assert_eq!(doc.get_content(), "");
+    /// ```
+    pub fn compact_history(&mut self, keep_recent: usize, budget: Option<Duration>) -> bool {
+        self.history.compact(keep_recent, budget)
+    }
+
     /// Clears all undo and redo history
     ///
     /// This immediately frees all memory used by the command history.
@@ -601,6 +2234,224 @@ impl Document {
         self.history.clear();
     }
 
+    /// Replaces the content with another document's, keeping this
+    /// document's settings
+    ///
+    /// Text, formats, blocks, images and metadata are taken from `other`.
+    /// The storage backend, read-only flag, maximum length, sanitizer and
+    /// history limit stay as they are, and collaboration or operational
+    /// transform tracking sees the load as one local edit. Undo history is
+    /// cleared.
+    ///
+    /// # Errors
+    /// Returns `CommandError::MaxLengthExceeded` if the new content is longer
+    /// than the maximum length
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, StorageBackend};
+    ///
+    /// let mut doc = Document::with_backend(StorageBackend::Rope);
+    /// doc.set_read_only(true);
+    /// doc.load_content(Document::from_text("Loaded")).unwrap();
+    /// assert_eq!(doc.get_content(), "Loaded");
+    /// assert!(doc.is_read_only());
+    /// assert_eq!(doc.storage_backend(), StorageBackend::Rope);
+    /// ```
+    pub fn load_content(&mut self, other: Document) -> CommandResult<()> {
+        if let Some(limit) = self.max_length
+            && other.get_length() > limit
+        {
+            return Err(crate::operations::CommandError::max_length_exceeded(
+                other.get_length(),
+                limit,
+            ));
+        }
+
+        self.history.end_all_batches();
+        self.composition = CompositionState::new();
+        let text = other.get_content();
+        self.replace_range_direct(Range::from_offsets(0, self.get_length()), &text);
+        self.formats = other.formats;
+        self.embeds = other.embeds;
+        self.annotations = other.annotations;
+        self.bookmarks = other.bookmarks;
+        self.no_spellcheck = other.no_spellcheck;
+        self.decorations = other.decorations;
+        self.transient_decorations.clear();
+        self.metadata = other.metadata;
+        self.extensions = other.extensions;
+        self.selection = Selection::collapsed(Position::new(0));
+        self.secondary_selections.clear();
+        if let Some(navigation) = &mut self.navigation {
+            navigation.clear();
+        }
+        self.history.clear();
+        Ok(())
+    }
+
+    /// Returns true if local edits are rejected
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Sets whether local edits are rejected
+    ///
+    /// While read-only, text edits, undo and redo fail with
+    /// `CommandError::ReadOnly` and formatting calls do nothing. Selection
+    /// changes and remote updates (`apply_update`, `transform_remote_op`)
+    /// are still allowed.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    ///
+    /// let mut doc = Document::from_text("Hello");
+    /// doc.set_read_only(true);
+    /// assert!(doc.insert_text(Position::new(5), "!").is_err());
+    /// assert_eq!(doc.get_content(), "Hello");
+    /// ```
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Gets the maximum document length in characters, if one is set
+    pub fn get_max_length(&self) -> Option<usize> {
+        self.max_length
+    }
+
+    /// Sets the maximum document length in characters
+    ///
+    /// Edits that would make the document longer fail with
+    /// `CommandError::MaxLengthExceeded`; edits that shrink it are always
+    /// allowed. Existing content longer than the limit is kept.
+    pub fn set_max_length(&mut self, max_length: Option<usize>) {
+        self.max_length = max_length;
+    }
+
+    /// Gets the sanitizer used for pasted HTML, if one was set
+    pub fn html_sanitizer(&self) -> Option<&HtmlSanitizer> {
+        self.html_sanitizer.as_ref()
+    }
+
+    /// Sets the sanitizer used for pasted HTML
+    ///
+    /// `None` restores the default `HtmlSanitizer`.
+    pub fn set_html_sanitizer(&mut self, sanitizer: Option<HtmlSanitizer>) {
+        self.html_sanitizer = sanitizer;
+    }
+
+    /// Gets how control characters in inserted and pasted text are handled
+    pub fn control_char_policy(&self) -> ControlCharPolicy {
+        self.control_chars
+    }
+
+    /// Sets how control characters in inserted and pasted text are handled
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{ControlCharPolicy, Document, Position};
+    ///
+    /// let mut doc = Document::new();
+    /// doc.insert_text(Position::new(0), "a\u{7}b").unwrap();
+    /// assert_eq!(doc.get_content(), "ab");
+    ///
+    /// doc.set_control_char_policy(ControlCharPolicy::ReplaceWithSpace);
+    /// doc.insert_text(Position::new(2), "\u{7}c").unwrap();
+    /// assert_eq!(doc.get_content(), "ab c");
+    /// ```
+    pub fn set_control_char_policy(&mut self, policy: ControlCharPolicy) {
+        self.control_chars = policy;
+    }
+
+    /// Gets which side text typed at the edge of a run of `format` takes
+    /// the format from
+    pub fn format_affinity(&self, format: &InlineFormat) -> FormatAffinity {
+        self.format_affinities.get(format)
+    }
+
+    /// Sets which side text typed at the edge of a run of `format` takes
+    /// the format from
+    ///
+    /// The setting applies to every format of the same type, so one call
+    /// covers links whatever their URL. Text typed inside a run always
+    /// gets the run's formats.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    /// use rte_core::formatting::{FormatAffinity, InlineFormat};
+    ///
+    /// let mut doc = Document::from_text("see docs");
+    /// let link = InlineFormat::Link { url: "https://example.com".to_string() };
+    /// doc.apply_format(Range::from_offsets(4, 8), link.clone());
+    /// doc.set_format_affinity(&link, FormatAffinity::Neither);
+    ///
+    /// doc.insert_text(Position::new(8), "!").unwrap();
+    /// assert!(!doc.get_formats_at(Position::new(8)).contains(&link));
+    /// ```
+    pub fn set_format_affinity(&mut self, format: &InlineFormat, affinity: FormatAffinity) {
+        self.format_affinities.set(format, affinity);
+    }
+
+    /// Sets the segmenter used to find words in Thai, Lao, Khmer and
+    /// Myanmar text, which is written without spaces
+    ///
+    /// Word movement and `word_count` use it. With `None`, each run of those
+    /// scripts counts as one word.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::Document;
+    ///
+    /// let mut doc = Document::from_text("ภาษาไทย ok");
+    /// assert_eq!(doc.word_count(), 2);
+    /// // A stand-in for a dictionary-based segmenter
+    /// doc.set_word_segmenter(Some(Box::new(|_: &str| vec![4])));
+    /// assert_eq!(doc.word_count(), 3);
+    /// ```
+    pub fn set_word_segmenter(&mut self, segmenter: Option<Box<dyn WordSegmenter>>) {
+        self.word_segmenter = segmenter;
+    }
+
+    /// Returns the number of words in the document
+    ///
+    /// Words are separated by whitespace, and runs of Thai, Lao, Khmer or
+    /// Myanmar text are split further by the word segmenter.
+    pub fn word_count(&self) -> usize {
+        let chars: Vec<char> = self.get_content().chars().collect();
+        let words = chars
+            .iter()
+            .enumerate()
+            .filter(|&(i, c)| !c.is_whitespace() && (i == 0 || chars[i - 1].is_whitespace()))
+            .count();
+        words + word_breaks(&chars, self.word_segmenter.as_deref()).len()
+    }
+
+    /// Returns an error if the document is read-only
+    pub(crate) fn check_editable(&self) -> CommandResult<()> {
+        if self.read_only {
+            return Err(crate::operations::CommandError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Returns an error if replacing `removed` characters with `inserted`
+    /// ones would grow the document past the maximum length
+    pub(crate) fn check_max_length(&self, inserted: usize, removed: usize) -> CommandResult<()> {
+        if let Some(limit) = self.max_length
+            && inserted > removed
+        {
+            let length = self.get_length() - removed.min(self.get_length()) + inserted;
+            if length > limit {
+                return Err(crate::operations::CommandError::max_length_exceeded(
+                    length, limit,
+                ));
+            }
+        }
+        Ok(())
+    }
+
     /// Returns memory usage statistics for the document
     ///
     /// # Returns
@@ -649,6 +2500,36 @@ impl Document {
             total_memory,
         )
     }
+
+    /// Returns how fragmented the inline formatting is
+    ///
+    /// Documents with many short format runs get slower to edit; call
+    /// `normalize_formats` when the runs per 1000 characters climb.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Range};
+    /// use rte_core::formatting::InlineFormat;
+    ///
+    /// let mut doc = Document::from_text("Hello World");
+    /// doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+    /// let stats = doc.fragmentation_stats();
+    /// assert_eq!(stats.run_count, 1);
+    /// ```
+    pub fn fragmentation_stats(&self) -> FragmentationStats {
+        self.formats.fragmentation_stats(self.get_length())
+    }
+
+    /// Merges touching format runs with identical formats
+    ///
+    /// Formatting looks the same afterwards, so nothing is recorded in the
+    /// history or reported as a change.
+    ///
+    /// # Returns
+    /// The number of runs removed
+    pub fn normalize_formats(&mut self) -> usize {
+        self.formats.normalize()
+    }
 }
 
 impl Default for Document {
@@ -878,6 +2759,46 @@ mod tests {
         assert!(formats.contains(&InlineFormat::Italic));
     }
 
+    #[test]
+    fn test_subscript_and_superscript_are_exclusive() {
+        let mut doc = Document::from_text("x2 y2");
+        doc.toggle_format(Range::from_offsets(0, 5), InlineFormat::Superscript);
+        doc.toggle_format(Range::from_offsets(1, 2), InlineFormat::Subscript);
+
+        let formats = doc.get_formats_at(Position::new(1));
+        assert!(formats.contains(&InlineFormat::Subscript));
+        assert!(!formats.contains(&InlineFormat::Superscript));
+        assert!(
+            doc.get_formats_at(Position::new(4))
+                .contains(&InlineFormat::Superscript)
+        );
+
+        // Toggling subscript off does not bring superscript back
+        doc.toggle_format(Range::from_offsets(1, 2), InlineFormat::Subscript);
+        assert!(doc.get_formats_at(Position::new(1)).is_empty());
+    }
+
+    #[test]
+    fn test_math_format_can_be_removed_toggled_and_undone() {
+        let math = InlineFormat::Math { display: false };
+        let mut doc = Document::from_text("ab cd");
+        let range = Range::from_offsets(0, 2);
+
+        doc.apply_format(range, math.clone());
+        assert!(doc.get_formats_at(Position::new(1)).contains(&math));
+        doc.remove_format(range, &math);
+        assert!(doc.get_formats_at(Position::new(1)).is_empty());
+
+        doc.toggle_format(range, math.clone());
+        assert!(doc.get_formats_at(Position::new(1)).contains(&math));
+        doc.toggle_format(range, math.clone());
+        assert!(doc.get_formats_at(Position::new(1)).is_empty());
+
+        doc.apply_format(range, math.clone());
+        doc.undo().unwrap();
+        assert!(doc.get_formats_at(Position::new(1)).is_empty());
+    }
+
     #[test]
     fn test_toggle_format() {
         let mut doc = Document::from_text("Hello World");
@@ -932,6 +2853,42 @@ mod tests {
         assert_eq!(block_type, BlockType::heading(1));
     }
 
+    #[test]
+    fn test_set_block_type_lines() {
+        let mut doc = Document::from_text("One\nTwo\nThree");
+        doc.set_block_type(Range::from_offsets(0, 13), BlockType::BulletList);
+        doc.indent_list_items(Range::from_offsets(4, 4)).unwrap();
+
+        doc.set_block_type_lines(Range::from_offsets(2, 5), BlockType::NumberedList)
+            .unwrap();
+        let starts: Vec<_> = doc
+            .formats()
+            .get_blocks()
+            .iter()
+            .map(|block| (block.start_offset, block.block_type.clone()))
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                (0, BlockType::NumberedList),
+                (4, BlockType::NumberedList),
+                (8, BlockType::BulletList),
+            ]
+        );
+        assert_eq!(doc.get_list_indent_at(Position::new(4)), 1);
+
+        assert!(
+            doc.set_block_type_lines(Range::from_offsets(0, 20), BlockType::code_block())
+                .is_err()
+        );
+        doc.undo().unwrap();
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::BulletList
+        );
+        assert_eq!(doc.get_list_indent_at(Position::new(4)), 1);
+    }
+
     #[test]
     fn test_get_block_type_at_default() {
         let doc = Document::from_text("Hello World");
@@ -1016,16 +2973,16 @@ mod tests {
             BlockType::NumberedList
         );
 
-        doc.set_block_type(Range::from_offsets(0, 4), BlockType::BlockQuote);
+        doc.set_block_type(Range::from_offsets(0, 4), BlockType::block_quote());
         assert_eq!(
             doc.get_block_type_at(Position::new(0)),
-            BlockType::BlockQuote
+            BlockType::block_quote()
         );
 
-        doc.set_block_type(Range::from_offsets(0, 4), BlockType::CodeBlock);
+        doc.set_block_type(Range::from_offsets(0, 4), BlockType::code_block());
         assert_eq!(
             doc.get_block_type_at(Position::new(0)),
-            BlockType::CodeBlock
+            BlockType::code_block()
         );
     }
 
@@ -1235,62 +3192,275 @@ mod tests {
     }
 
     #[test]
-    fn test_format_at_boundaries() {
+    fn test_format_at_boundaries() {
+        let mut doc = Document::from_text("Hello World");
+        doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+
+        // At start of format
+        let formats_start = doc.get_formats_at(Position::new(0));
+        assert!(formats_start.contains(&InlineFormat::Bold));
+
+        // At end of format (should not have format)
+        let formats_end = doc.get_formats_at(Position::new(5));
+        assert!(!formats_end.contains(&InlineFormat::Bold));
+
+        // Just before end
+        let formats_before_end = doc.get_formats_at(Position::new(4));
+        assert!(formats_before_end.contains(&InlineFormat::Bold));
+    }
+
+    #[test]
+    fn test_overlapping_format_removal() {
+        let mut doc = Document::from_text("Hello World");
+
+        // Apply bold to entire text
+        doc.apply_format(Range::from_offsets(0, 11), InlineFormat::Bold);
+
+        // Remove bold from middle
+        doc.remove_format(Range::from_offsets(3, 8), &InlineFormat::Bold);
+
+        // Check that bold is removed from middle but remains at edges
+        assert!(
+            doc.get_formats_at(Position::new(1))
+                .contains(&InlineFormat::Bold)
+        );
+        assert!(
+            !doc.get_formats_at(Position::new(5))
+                .contains(&InlineFormat::Bold)
+        );
+        assert!(
+            doc.get_formats_at(Position::new(9))
+                .contains(&InlineFormat::Bold)
+        );
+    }
+
+    #[test]
+    fn test_unicode_text_operations() {
+        let mut doc = Document::new();
+
+        // Insert unicode text
+        doc.insert_text(Position::new(0), "Hello 世界 🌍").unwrap();
+        assert_eq!(doc.get_content(), "Hello 世界 🌍");
+
+        // Length should be character count, not byte count
+        assert_eq!(doc.get_length(), 10);
+
+        // Get text in range with unicode
+        let text = doc.get_text_in_range(Range::from_offsets(6, 8));
+        assert_eq!(text, "世界");
+    }
+
+    #[test]
+    fn test_rope_backend_edits_and_undo() {
+        let mut doc = Document::from_text_with_backend("Hello World", StorageBackend::Rope);
+        assert_eq!(doc.storage_backend(), StorageBackend::Rope);
+
+        doc.insert_text(Position::new(5), " there").unwrap();
+        doc.delete_range(Range::from_offsets(0, 1)).unwrap();
+        doc.replace_range(Range::from_offsets(0, 4), "Jell")
+            .unwrap();
+        assert_eq!(doc.get_content(), "Jell there World");
+
+        doc.undo().unwrap();
+        doc.undo().unwrap();
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "Hello World");
+    }
+
+    #[test]
+    fn test_default_backend_is_gap_buffer() {
+        assert_eq!(Document::new().storage_backend(), StorageBackend::GapBuffer);
+        assert_eq!(
+            Document::from_text("abc").storage_backend(),
+            StorageBackend::GapBuffer
+        );
+    }
+
+    #[test]
+    fn test_insert_image_undo_redo() {
+        let mut doc = Document::from_text("ab");
+        doc.insert_image(Position::new(1), Image::new("a.png", "A"))
+            .unwrap();
+        assert_eq!(doc.get_content(), format!("a{}b", EMBED_CHAR));
+
+        // Text typed before the image shifts it
+        doc.insert_text(Position::new(0), "x").unwrap();
+        assert!(doc.get_image_at(Position::new(1)).is_none());
+        assert_eq!(doc.get_image_at(Position::new(2)).unwrap().alt, "A");
+
+        doc.undo().unwrap();
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "ab");
+        assert!(doc.get_image_at(Position::new(1)).is_none());
+
+        doc.redo().unwrap();
+        assert_eq!(doc.get_image_at(Position::new(1)).unwrap().src, "a.png");
+    }
+
+    #[test]
+    fn test_insert_image_rejects_unsafe_src() {
+        let mut doc = Document::from_text("ab");
+        let result = doc.insert_image(Position::new(1), Image::new("javascript:alert(1)", ""));
+        assert!(result.is_err());
+        assert_eq!(doc.get_content(), "ab");
+    }
+
+    #[test]
+    fn test_delete_image_undo_restores_attributes() {
+        let mut doc = Document::from_text("ab");
+        doc.insert_image(
+            Position::new(1),
+            Image::new("a.png", "A").with_size(Some(10), Some(20)),
+        )
+        .unwrap();
+
+        doc.delete_range(Range::from_offsets(0, 2)).unwrap();
+        assert!(doc.get_image_at(Position::new(1)).is_none());
+
+        doc.undo().unwrap();
+        let image = doc.get_image_at(Position::new(1)).unwrap();
+        assert_eq!(image.width, Some(10));
+        assert_eq!(image.height, Some(20));
+    }
+
+    #[test]
+    fn test_update_image_undo() {
+        let mut doc = Document::new();
+        doc.insert_image(Position::new(0), Image::new("a.png", "A"))
+            .unwrap();
+
+        doc.update_image(Position::new(0), Image::new("b.png", "B"))
+            .unwrap();
+        assert_eq!(doc.get_image_at(Position::new(0)).unwrap().src, "b.png");
+
+        doc.undo().unwrap();
+        assert_eq!(doc.get_image_at(Position::new(0)).unwrap().src, "a.png");
+
+        assert!(
+            doc.update_image(Position::new(1), Image::new("b.png", ""))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_update_image_undo_after_image_moved() {
+        let mut doc = Document::new();
+        doc.insert_image(Position::new(0), Image::new("a.png", "A"))
+            .unwrap();
+        doc.update_image(Position::new(0), Image::new("b.png", "B"))
+            .unwrap();
+
+        // A remote edit shifts the image without touching history
+        doc.insert_text_direct(Position::new(0), "x");
+
+        assert!(doc.undo().is_err());
+        assert_eq!(doc.get_image_at(Position::new(1)).unwrap().src, "b.png");
+    }
+
+    #[test]
+    fn test_insert_image_fails_when_insert_is_skipped() {
+        let mut doc = Document::from_text(&"x".repeat(MAX_DOCUMENT_SIZE));
+        let mut cmd = InsertImageCommand::new(Position::new(0), Image::new("a.png", ""));
+
+        // The direct insert is skipped at the size limit
+        assert!(cmd.execute(&mut doc).is_err());
+        assert!(doc.get_image_at(Position::new(0)).is_none());
+    }
+
+    #[test]
+    fn test_annotation_undo_redo() {
         let mut doc = Document::from_text("Hello World");
-        doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+        doc.add_annotation(Range::from_offsets(0, 5), Annotation::new("c1"))
+            .unwrap();
+        doc.add_annotation(
+            Range::from_offsets(2, 8),
+            Annotation::new("c1").with_payload("moved"),
+        )
+        .unwrap();
 
-        // At start of format
-        let formats_start = doc.get_formats_at(Position::new(0));
-        assert!(formats_start.contains(&InlineFormat::Bold));
+        doc.undo().unwrap();
+        let (range, annotation) = doc.get_annotation("c1").unwrap();
+        assert_eq!(range, Range::from_offsets(0, 5));
+        assert!(annotation.payload.is_empty());
 
-        // At end of format (should not have format)
-        let formats_end = doc.get_formats_at(Position::new(5));
-        assert!(!formats_end.contains(&InlineFormat::Bold));
+        doc.remove_annotation("c1").unwrap();
+        assert!(doc.get_annotations_at(Position::new(1)).is_empty());
+        assert!(doc.remove_annotation("c1").is_err());
 
-        // Just before end
-        let formats_before_end = doc.get_formats_at(Position::new(4));
-        assert!(formats_before_end.contains(&InlineFormat::Bold));
+        doc.undo().unwrap();
+        assert_eq!(doc.get_annotations_at(Position::new(1)).len(), 1);
+        doc.undo().unwrap();
+        assert!(doc.get_annotation("c1").is_none());
+
+        doc.redo().unwrap();
+        assert_eq!(
+            doc.get_annotation("c1").unwrap().0,
+            Range::from_offsets(0, 5)
+        );
+        assert!(
+            doc.add_annotation(Range::from_offsets(3, 3), Annotation::new("c2"))
+                .is_err()
+        );
     }
 
     #[test]
-    fn test_overlapping_format_removal() {
+    fn test_annotation_follows_edits() {
         let mut doc = Document::from_text("Hello World");
+        doc.add_annotation(Range::from_offsets(6, 11), Annotation::new("c1"))
+            .unwrap();
 
-        // Apply bold to entire text
-        doc.apply_format(Range::from_offsets(0, 11), InlineFormat::Bold);
-
-        // Remove bold from middle
-        doc.remove_format(Range::from_offsets(3, 8), &InlineFormat::Bold);
-
-        // Check that bold is removed from middle but remains at edges
-        assert!(
-            doc.get_formats_at(Position::new(1))
-                .contains(&InlineFormat::Bold)
+        doc.insert_text(Position::new(8), "rr").unwrap();
+        assert_eq!(
+            doc.get_annotation("c1").unwrap().0,
+            Range::from_offsets(6, 13)
         );
-        assert!(
-            !doc.get_formats_at(Position::new(5))
-                .contains(&InlineFormat::Bold)
+        doc.replace_range(Range::from_offsets(0, 5), "Hi").unwrap();
+        assert_eq!(
+            doc.get_annotation("c1").unwrap().0,
+            Range::from_offsets(3, 10)
         );
-        assert!(
-            doc.get_formats_at(Position::new(9))
-                .contains(&InlineFormat::Bold)
+
+        // Deleting the annotated text drops the annotation until undo
+        doc.delete_range(Range::from_offsets(2, 10)).unwrap();
+        assert!(doc.get_annotation("c1").is_none());
+        doc.undo().unwrap();
+        assert_eq!(
+            doc.get_annotation("c1").unwrap().0,
+            Range::from_offsets(3, 10)
+        );
+        doc.undo().unwrap();
+        assert_eq!(
+            doc.get_annotation("c1").unwrap().0,
+            Range::from_offsets(6, 13)
         );
     }
 
     #[test]
-    fn test_unicode_text_operations() {
-        let mut doc = Document::new();
-
-        // Insert unicode text
-        doc.insert_text(Position::new(0), "Hello 世界 🌍").unwrap();
-        assert_eq!(doc.get_content(), "Hello 世界 🌍");
+    fn test_batch_undoes_in_one_step() {
+        let mut doc = Document::from_text("Hello");
+        doc.begin_batch();
+        doc.insert_text(Position::new(5), " World").unwrap();
+        doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+        doc.set_block_type(Range::from_offsets(0, 11), BlockType::heading(1));
+        doc.delete_range(Range::from_offsets(0, 1)).unwrap();
+        assert!(doc.end_batch());
+        assert_eq!(doc.get_content(), "ello World");
 
-        // Length should be character count, not byte count
-        assert_eq!(doc.get_length(), 10);
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "Hello");
+        assert!(doc.get_formats_at(Position::new(1)).is_empty());
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::Paragraph
+        );
+        assert!(!doc.can_undo());
 
-        // Get text in range with unicode
-        let text = doc.get_text_in_range(Range::from_offsets(6, 8));
-        assert_eq!(text, "世界");
+        doc.redo().unwrap();
+        assert_eq!(doc.get_content(), "ello World");
+        assert!(
+            doc.get_formats_at(Position::new(1))
+                .contains(&InlineFormat::Bold)
+        );
     }
 
     #[test]
@@ -1382,8 +3552,8 @@ mod tests {
         doc.set_history_limit(5);
 
         // Add 10 operations
-        for i in 0..10 {
-            doc.insert_text(Position::new(i), "a").unwrap();
+        for _ in 0..10 {
+            doc.insert_text(Position::new(0), "a").unwrap();
         }
 
         // Should only be able to undo 5 times
@@ -1394,4 +3564,446 @@ mod tests {
         // 6th undo should fail
         assert!(doc.undo().is_err());
     }
+
+    #[test]
+    fn test_apply_formats_fails_as_a_whole() {
+        let mut doc = Document::from_text("Hello");
+        doc.formats_mut()
+            .apply_format(Range::from_offsets(0, 5), InlineFormat::Underline);
+        let result = doc.apply_formats(vec![
+            (Range::from_offsets(0, 2), InlineFormat::Bold),
+            (Range::from_offsets(1, 3), InlineFormat::Italic),
+            (Range::from_offsets(3, 9), InlineFormat::Italic),
+        ]);
+
+        assert!(result.is_err());
+        let formats = doc.get_formats_at(Position::new(1));
+        assert_eq!(formats.len(), 1);
+        assert!(formats.contains(&InlineFormat::Underline));
+        assert!(!doc.can_undo());
+    }
+
+    #[test]
+    fn test_read_only_rejects_local_edits() {
+        let mut doc = Document::from_text("Hello");
+        doc.insert_text(Position::new(5), "!").unwrap();
+        doc.set_read_only(true);
+
+        assert_eq!(
+            doc.insert_text(Position::new(0), "x"),
+            Err(crate::operations::CommandError::ReadOnly)
+        );
+        assert!(doc.delete_range(Range::from_offsets(0, 1)).is_err());
+        assert!(doc.undo().is_err());
+        doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+        doc.set_block_type(Range::from_offsets(0, 5), BlockType::heading(1));
+
+        assert_eq!(doc.get_content(), "Hello!");
+        assert!(doc.get_formats_at(Position::new(0)).is_empty());
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::Paragraph
+        );
+
+        doc.set_read_only(false);
+        assert!(doc.undo().is_ok());
+        assert_eq!(doc.get_content(), "Hello");
+    }
+
+    #[test]
+    fn test_load_content_keeps_settings() {
+        let mut doc = Document::with_backend(StorageBackend::Rope);
+        doc.set_read_only(true);
+        doc.set_max_length(Some(10));
+        doc.set_history_limit(5);
+        doc.enable_operational_transform();
+
+        let mut other = Document::from_text("Title");
+        other.set_block_type(Range::from_offsets(0, 5), BlockType::heading(1));
+        doc.load_content(other).unwrap();
+
+        assert_eq!(doc.get_content(), "Title");
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::heading(1)
+        );
+        assert!(doc.is_read_only());
+        assert_eq!(doc.get_max_length(), Some(10));
+        assert_eq!(doc.get_history_limit(), 5);
+        assert_eq!(doc.storage_backend(), StorageBackend::Rope);
+        assert_eq!(doc.pending_ops().unwrap().len(), 1);
+
+        assert!(
+            doc.load_content(Document::from_text("Far too long"))
+                .is_err()
+        );
+        assert_eq!(doc.get_content(), "Title");
+    }
+
+    #[test]
+    fn test_max_length() {
+        let mut doc = Document::from_text("Hello");
+        doc.set_max_length(Some(7));
+
+        assert!(doc.insert_text(Position::new(5), "!!").is_ok());
+        assert_eq!(
+            doc.insert_text(Position::new(7), "?"),
+            Err(crate::operations::CommandError::max_length_exceeded(8, 7))
+        );
+        // Replacements are measured after the removal
+        assert!(
+            doc.replace_range(Range::from_offsets(0, 5), "Howdy")
+                .is_ok()
+        );
+        assert!(doc.replace_range(Range::from_offsets(0, 1), "Hi").is_err());
+        assert_eq!(doc.get_content(), "Howdy!!");
+        assert!(doc.delete_range(Range::from_offsets(5, 7)).is_ok());
+    }
+
+    #[test]
+    fn test_indent_and_outdent_list_items() {
+        let mut doc = Document::from_text("One\nTwo\nText");
+        doc.set_block_type(Range::from_offsets(0, 7), BlockType::BulletList);
+        doc.set_block_type(Range::from_offsets(8, 12), BlockType::Paragraph);
+
+        doc.indent_list_items(Range::from_offsets(1, 10)).unwrap();
+        assert_eq!(doc.get_list_indent_at(Position::new(0)), 1);
+        assert_eq!(doc.get_list_indent_at(Position::new(4)), 1);
+        assert_eq!(doc.get_list_indent_at(Position::new(8)), 0);
+
+        doc.outdent_list_items(Range::from_offsets(0, 0)).unwrap();
+        assert_eq!(doc.get_list_indent_at(Position::new(0)), 0);
+        assert_eq!(doc.get_list_indent_at(Position::new(4)), 1);
+
+        // Outdenting a top-level item changes nothing and adds no history
+        let version = doc.version();
+        doc.outdent_list_items(Range::from_offsets(0, 0)).unwrap();
+        assert_eq!(doc.version(), version);
+
+        doc.undo().unwrap();
+        assert_eq!(doc.get_list_indent_at(Position::new(0)), 1);
+        doc.undo().unwrap();
+        assert_eq!(doc.get_list_indent_at(Position::new(0)), 0);
+        assert_eq!(doc.get_list_indent_at(Position::new(4)), 0);
+
+        for _ in 0..20 {
+            doc.indent_list_items(Range::from_offsets(0, 0)).unwrap();
+        }
+        assert_eq!(
+            doc.get_list_indent_at(Position::new(0)),
+            crate::formatting::block::MAX_LIST_INDENT
+        );
+    }
+
+    #[test]
+    fn test_list_numbering_and_style() {
+        let mut doc = Document::from_text("A\nB\nC\nD\nE\nF");
+        doc.set_block_type(Range::from_offsets(0, 9), BlockType::NumberedList);
+        doc.set_block_type(Range::from_offsets(10, 11), BlockType::Paragraph);
+        doc.indent_list_items(Range::from_offsets(4, 4)).unwrap();
+
+        // The nested item starts its own count without breaking the outer one
+        let markers: Vec<Option<String>> = [0, 2, 4, 6, 8, 10]
+            .iter()
+            .map(|&offset| doc.get_list_marker_at(Position::new(offset)))
+            .collect();
+        assert_eq!(
+            markers,
+            vec![
+                Some("1.".to_string()),
+                Some("2.".to_string()),
+                Some("1.".to_string()),
+                Some("3.".to_string()),
+                Some("4.".to_string()),
+                None,
+            ]
+        );
+
+        // A different style starts a new list
+        doc.set_list_style(Range::from_offsets(6, 9), Some(ListStyle::UpperRoman))
+            .unwrap();
+        assert_eq!(
+            doc.get_list_style_at(Position::new(8)),
+            Some(ListStyle::UpperRoman)
+        );
+        assert_eq!(
+            doc.get_list_marker_at(Position::new(6)).as_deref(),
+            Some("I.")
+        );
+        assert_eq!(
+            doc.get_list_marker_at(Position::new(8)).as_deref(),
+            Some("II.")
+        );
+        assert_eq!(
+            doc.get_list_marker_at(Position::new(2)).as_deref(),
+            Some("2.")
+        );
+
+        // Paragraphs are not affected and a no-op adds no history
+        let version = doc.version();
+        doc.set_list_style(Range::from_offsets(10, 11), Some(ListStyle::Decimal))
+            .unwrap();
+        assert_eq!(doc.version(), version);
+        assert_eq!(doc.get_list_style_at(Position::new(10)), None);
+
+        doc.undo().unwrap();
+        assert_eq!(
+            doc.get_list_marker_at(Position::new(8)).as_deref(),
+            Some("4.")
+        );
+
+        doc.set_block_type(Range::from_offsets(0, 1), BlockType::BulletList);
+        assert_eq!(
+            doc.get_list_marker_at(Position::new(0)).as_deref(),
+            Some("•")
+        );
+        doc.set_list_style(Range::from_offsets(0, 0), Some(ListStyle::Bullet('–')))
+            .unwrap();
+        assert_eq!(
+            doc.get_list_marker_at(Position::new(0)).as_deref(),
+            Some("–")
+        );
+        assert_eq!(
+            doc.get_list_marker_at(Position::new(2)).as_deref(),
+            Some("•")
+        );
+    }
+
+    #[test]
+    fn test_bookmarks() {
+        let mut doc = Document::from_text("Intro\nDetails");
+        doc.set_bookmark("details", Position::new(6)).unwrap();
+        assert!(doc.set_bookmark("past", Position::new(14)).is_err());
+
+        doc.replace_range(Range::from_offsets(0, 5), "Hi").unwrap();
+        assert_eq!(doc.get_bookmark("details"), Some(Position::new(3)));
+        doc.delete_range(Range::from_offsets(2, 6)).unwrap();
+        assert_eq!(doc.get_bookmark("details"), Some(Position::new(2)));
+
+        // Bookmarks are not part of the undo history; undo only re-inserts
+        // the deleted text in front of this one
+        doc.undo().unwrap();
+        assert_eq!(doc.get_bookmark("details"), Some(Position::new(6)));
+
+        doc.set_read_only(true);
+        doc.set_bookmark("details", Position::new(0)).unwrap();
+        assert_eq!(doc.get_bookmark("details"), Some(Position::new(0)));
+        assert!(doc.remove_bookmark("details"));
+        assert!(!doc.remove_bookmark("details"));
+        assert!(doc.get_bookmark("details").is_none());
+    }
+
+    #[test]
+    fn test_check_insert_and_delete() {
+        let mut doc = Document::from_text("Hello");
+        assert_eq!(
+            doc.check_insert(Position::new(0), "\x07Hi "),
+            Ok(Range::from_offsets(0, 3))
+        );
+        assert!(matches!(
+            doc.check_insert(Position::new(6), "x"),
+            Err(crate::operations::CommandError::InvalidPosition { .. })
+        ));
+        assert_eq!(
+            doc.check_delete(Range::from_offsets(4, 1)),
+            Ok(Range::from_offsets(1, 4))
+        );
+        assert!(matches!(
+            doc.check_delete(Range::from_offsets(2, 9)),
+            Err(crate::operations::CommandError::InvalidRange { .. })
+        ));
+
+        // Checking leaves the document untouched
+        assert_eq!(doc.get_content(), "Hello");
+        assert!(!doc.can_undo());
+
+        doc.set_max_length(Some(6));
+        assert!(matches!(
+            doc.check_insert(Position::new(5), "!!"),
+            Err(crate::operations::CommandError::MaxLengthExceeded { .. })
+        ));
+        doc.set_read_only(true);
+        assert_eq!(
+            doc.check_delete(Range::from_offsets(0, 1)),
+            Err(crate::operations::CommandError::ReadOnly)
+        );
+    }
+
+    #[test]
+    fn test_control_char_policy() {
+        let mut doc = Document::new();
+        doc.insert_text(Position::new(0), "a\x07b").unwrap();
+        assert_eq!(doc.get_content(), "ab");
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "");
+
+        doc.set_control_char_policy(ControlCharPolicy::Keep);
+        doc.insert_text(Position::new(0), "a\x07b\0").unwrap();
+        assert_eq!(doc.get_content(), "a\x07b");
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "");
+
+        doc.set_control_char_policy(ControlCharPolicy::ReplaceWithSpace);
+        doc.insert_text(Position::new(0), "ab").unwrap();
+        doc.replace_range(Range::from_offsets(1, 2), "\x0c\x1b")
+            .unwrap();
+        assert_eq!(doc.get_content(), "a\n ");
+    }
+
+    #[test]
+    fn test_line_column_conversion() {
+        let mut doc = Document::from_text("one\ntwo\n");
+        assert_eq!(doc.line_count(), 3);
+        assert_eq!(doc.position_to_line_column(Position::new(3)), Some((0, 3)));
+        assert_eq!(doc.position_to_line_column(Position::new(8)), Some((2, 0)));
+        assert_eq!(doc.position_to_line_column(Position::new(9)), None);
+        assert_eq!(doc.line_column_to_position(1, 99), Some(Position::new(7)));
+        assert_eq!(doc.line_column_to_position(3, 0), None);
+
+        // The index follows edits and undo
+        doc.insert_text(Position::new(1), "a\nb").unwrap();
+        doc.replace_range(Range::from_offsets(6, 8), "\n").unwrap();
+        assert_eq!(doc.get_content(), "oa\nbne\nwo\n");
+        for offset in 0..=doc.get_length() {
+            let (line, column) = doc.position_to_line_column(Position::new(offset)).unwrap();
+            assert_eq!(
+                doc.line_column_to_position(line, column),
+                Some(Position::new(offset))
+            );
+        }
+        assert_eq!(doc.position_to_line_column(Position::new(4)), Some((1, 1)));
+        doc.undo().unwrap();
+        doc.undo().unwrap();
+        assert_eq!(doc.line_count(), 3);
+        assert_eq!(doc.position_to_line_column(Position::new(5)), Some((1, 1)));
+    }
+
+    #[test]
+    fn test_sticky_positions() {
+        let mut doc = Document::from_text("Hello world");
+        let left = doc
+            .create_sticky_position(Position::new(5), Bias::Left)
+            .unwrap();
+        let right = doc
+            .create_sticky_position(Position::new(5), Bias::Right)
+            .unwrap();
+        assert!(
+            doc.create_sticky_position(Position::new(12), Bias::Left)
+                .is_err()
+        );
+
+        doc.insert_text(Position::new(5), ",").unwrap();
+        assert_eq!(doc.resolve_sticky_position(left), Some(Position::new(5)));
+        assert_eq!(doc.resolve_sticky_position(right), Some(Position::new(6)));
+
+        // Replacing the text around both positions collapses them to the
+        // start; right bias then carries one past the replacement
+        doc.replace_range(Range::from_offsets(4, 7), "o - ")
+            .unwrap();
+        assert_eq!(doc.resolve_sticky_position(left), Some(Position::new(4)));
+        assert_eq!(doc.resolve_sticky_position(right), Some(Position::new(8)));
+
+        assert!(doc.release_sticky_position(left));
+        assert!(!doc.release_sticky_position(left));
+        assert!(doc.resolve_sticky_position(left).is_none());
+    }
+
+    #[test]
+    fn test_block_indent_and_first_line_indent() {
+        let mut doc = Document::from_text("One\nTwo\nItem");
+        doc.set_block_type(Range::from_offsets(8, 12), BlockType::BulletList);
+
+        doc.increase_block_indent(Range::from_offsets(0, 12))
+            .unwrap();
+        assert_eq!(doc.get_block_indent_at(Position::new(0)), 1);
+        assert_eq!(doc.get_block_indent_at(Position::new(4)), 1);
+        // List nesting is separate
+        assert_eq!(doc.get_block_indent_at(Position::new(8)), 0);
+        assert_eq!(doc.get_list_indent_at(Position::new(8)), 0);
+
+        doc.decrease_block_indent(Range::from_offsets(4, 4))
+            .unwrap();
+        assert_eq!(doc.get_block_indent_at(Position::new(4)), 0);
+
+        // Outdenting an unindented paragraph adds no history
+        let version = doc.version();
+        doc.decrease_block_indent(Range::from_offsets(4, 4))
+            .unwrap();
+        assert_eq!(doc.version(), version);
+
+        doc.set_first_line_indent(Range::from_offsets(0, 5), true)
+            .unwrap();
+        assert!(doc.has_first_line_indent_at(Position::new(0)));
+        assert!(doc.has_first_line_indent_at(Position::new(4)));
+        assert!(!doc.has_first_line_indent_at(Position::new(8)));
+
+        doc.undo().unwrap();
+        assert!(!doc.has_first_line_indent_at(Position::new(0)));
+        doc.undo().unwrap();
+        assert_eq!(doc.get_block_indent_at(Position::new(4)), 1);
+
+        for _ in 0..20 {
+            doc.increase_block_indent(Range::from_offsets(0, 0))
+                .unwrap();
+        }
+        assert_eq!(
+            doc.get_block_indent_at(Position::new(0)),
+            crate::formatting::block::MAX_BLOCK_INDENT
+        );
+    }
+
+    #[test]
+    fn test_text_direction() {
+        let mut doc = Document::from_text("שלום\nHello\n123");
+        assert_eq!(
+            doc.get_base_direction_at(Position::new(2)),
+            TextDirection::Rtl
+        );
+        assert_eq!(
+            doc.get_base_direction_at(Position::new(7)),
+            TextDirection::Ltr
+        );
+        // No strong characters
+        assert_eq!(
+            doc.get_base_direction_at(Position::new(12)),
+            TextDirection::Ltr
+        );
+
+        doc.set_text_direction(Range::from_offsets(6, 13), Some(TextDirection::Rtl))
+            .unwrap();
+        assert_eq!(doc.get_text_direction_at(Position::new(0)), None);
+        assert_eq!(
+            doc.get_text_direction_at(Position::new(8)),
+            Some(TextDirection::Rtl)
+        );
+        assert_eq!(
+            doc.get_base_direction_at(Position::new(12)),
+            TextDirection::Rtl
+        );
+        let line = doc.bidi_line_at(Position::new(8));
+        assert_eq!((line.start, line.len()), (5, 5));
+        assert_eq!(line.levels, vec![2; 5]);
+
+        // Setting the same direction again adds no history
+        let version = doc.version();
+        doc.set_text_direction(Range::from_offsets(6, 6), Some(TextDirection::Rtl))
+            .unwrap();
+        assert_eq!(doc.version(), version);
+
+        // The direction survives a block type change, and is undoable
+        doc.set_block_type(Range::from_offsets(5, 10), BlockType::heading(1));
+        assert_eq!(
+            doc.get_text_direction_at(Position::new(8)),
+            Some(TextDirection::Rtl)
+        );
+        doc.undo().unwrap();
+        doc.undo().unwrap();
+        assert_eq!(doc.get_text_direction_at(Position::new(8)), None);
+
+        doc.set_read_only(true);
+        assert!(
+            doc.set_text_direction(Range::from_offsets(0, 0), None)
+                .is_err()
+        );
+    }
 }
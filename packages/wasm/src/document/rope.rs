@@ -0,0 +1,605 @@
+/// Rope implementation for text storage.
+///
+/// The rope keeps text in small leaf chunks held by a height-balanced binary
+/// tree. Every branch caches the character count of its subtree, so locating
+/// an offset, splitting and joining all walk a single root-to-leaf path.
+///
+/// # Performance Characteristics
+///
+/// ## Time Complexity
+/// - **Insert**: O(log n) regardless of where the previous edit happened
+///   - Inserts that fit into an existing leaf are done in place
+/// - **Delete**: O(log n) via split and join
+/// - **Get character**: O(log n) - descends from the root
+/// - **Get slice**: O(log n + m) where m is slice length
+/// - **Get full text**: O(n) where n is document length
+///
+/// ## Space Complexity
+/// - Memory usage: O(n) plus one branch node per pair of leaves
+/// - Leaf size: up to 512 characters
+/// - Overhead: ~4 bytes per character (UTF-32 storage) plus ~48 bytes per node
+///
+/// ## Performance Notes
+/// - Random edits across large documents stay fast, unlike the gap buffer
+/// - Prefer this backend for documents of several hundred KB or more
+///
+/// # Example
+/// ```
+/// use rte_core::document::rope::RopeStorage;
+///
+/// let mut storage = RopeStorage::new();
+/// storage.insert(0, "Hello");
+/// assert_eq!(storage.get_text(), "Hello");
+/// ```
+#[derive(Debug, Clone)]
+pub struct RopeStorage {
+    root: Option<Box<Node>>,
+}
+
+/// A node in the rope tree
+#[derive(Debug, Clone)]
+enum Node {
+    Leaf(Vec<char>),
+    Branch {
+        left: Box<Node>,
+        right: Box<Node>,
+        len: usize,
+        height: usize,
+    },
+}
+
+impl RopeStorage {
+    /// Maximum number of characters stored in a single leaf
+    const MAX_LEAF_SIZE: usize = 512;
+
+    /// Creates a new empty RopeStorage
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Creates a RopeStorage from existing text
+    pub fn from_text(text: &str) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        Self {
+            root: Node::build(&chars),
+        }
+    }
+
+    /// Returns the length of the text in characters
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |root| root.len())
+    }
+
+    /// Returns true if the rope is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the height of the tree (0 for a single leaf or an empty rope)
+    pub fn height(&self) -> usize {
+        self.root.as_ref().map_or(0, |root| root.height())
+    }
+
+    /// Returns the number of leaf chunks in the rope
+    pub fn leaf_count(&self) -> usize {
+        self.root.as_ref().map_or(0, |root| root.leaf_count())
+    }
+
+    /// Inserts text at the specified position
+    pub fn insert(&mut self, pos: usize, text: &str) {
+        if pos > self.len() {
+            panic!(
+                "Insert position {} is out of bounds (len: {})",
+                pos,
+                self.len()
+            );
+        }
+
+        let chars: Vec<char> = text.chars().collect();
+        if chars.is_empty() {
+            return;
+        }
+
+        // Fast path: the text fits into the leaf at the insertion point
+        if let Some(root) = self.root.as_mut()
+            && root.insert_in_leaf(pos, &chars)
+        {
+            return;
+        }
+
+        let (before, after) = split_opt(self.root.take(), pos);
+        self.root = join_opt(join_opt(before, Node::build(&chars)), after);
+    }
+
+    /// Deletes text in the specified range [start, end)
+    pub fn delete(&mut self, start: usize, end: usize) {
+        if start > end {
+            panic!("Invalid range: start {} > end {}", start, end);
+        }
+        if end > self.len() {
+            panic!(
+                "Delete end position {} is out of bounds (len: {})",
+                end,
+                self.len()
+            );
+        }
+
+        if start == end {
+            return;
+        }
+
+        // Fast path: the range lies inside one leaf that stays non-empty
+        if let Some(root) = self.root.as_mut()
+            && root.delete_in_leaf(start, end)
+        {
+            return;
+        }
+
+        let (before, rest) = split_opt(self.root.take(), start);
+        let (_, after) = split_opt(rest, end - start);
+        self.root = join_opt(before, after);
+    }
+
+    /// Returns the character at the specified position
+    pub fn get_char(&self, pos: usize) -> Option<char> {
+        let mut node = self.root.as_deref()?;
+        let mut pos = pos;
+
+        if pos >= node.len() {
+            return None;
+        }
+
+        loop {
+            match node {
+                Node::Leaf(chars) => return chars.get(pos).copied(),
+                Node::Branch { left, right, .. } => {
+                    let left_len = left.len();
+                    if pos < left_len {
+                        node = left;
+                    } else {
+                        pos -= left_len;
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns a slice of text from the specified range [start, end)
+    pub fn get_slice(&self, start: usize, end: usize) -> String {
+        if start > end {
+            panic!("Invalid range: start {} > end {}", start, end);
+        }
+        if end > self.len() {
+            panic!(
+                "Slice end position {} is out of bounds (len: {})",
+                end,
+                self.len()
+            );
+        }
+
+        let mut result = String::with_capacity(end - start);
+        if let Some(root) = self.root.as_deref() {
+            root.collect_into(start, end, &mut result);
+        }
+        result
+    }
+
+    /// Returns the entire text content as a String
+    pub fn get_text(&self) -> String {
+        self.get_slice(0, self.len())
+    }
+}
+
+impl Default for RopeStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Node {
+    /// Builds a balanced tree from a slice of characters
+    fn build(chars: &[char]) -> Option<Box<Node>> {
+        if chars.is_empty() {
+            return None;
+        }
+        Some(Self::build_non_empty(chars))
+    }
+
+    fn build_non_empty(chars: &[char]) -> Box<Node> {
+        if chars.len() <= RopeStorage::MAX_LEAF_SIZE {
+            return Box::new(Node::Leaf(chars.to_vec()));
+        }
+        let mid = chars.len() / 2;
+        Self::branch(
+            Self::build_non_empty(&chars[..mid]),
+            Self::build_non_empty(&chars[mid..]),
+        )
+    }
+
+    /// Creates a branch node, computing its cached length and height
+    fn branch(left: Box<Node>, right: Box<Node>) -> Box<Node> {
+        let len = left.len() + right.len();
+        let height = 1 + left.height().max(right.height());
+        Box::new(Node::Branch {
+            left,
+            right,
+            len,
+            height,
+        })
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Node::Leaf(chars) => chars.len(),
+            Node::Branch { len, .. } => *len,
+        }
+    }
+
+    fn height(&self) -> usize {
+        match self {
+            Node::Leaf(_) => 0,
+            Node::Branch { height, .. } => *height,
+        }
+    }
+
+    fn leaf_count(&self) -> usize {
+        match self {
+            Node::Leaf(_) => 1,
+            Node::Branch { left, right, .. } => left.leaf_count() + right.leaf_count(),
+        }
+    }
+
+    /// Inserts into the leaf containing `pos` if it has room
+    ///
+    /// Returns false without modifying anything if the leaf would overflow.
+    fn insert_in_leaf(&mut self, pos: usize, chars: &[char]) -> bool {
+        match self {
+            Node::Leaf(leaf) => {
+                if leaf.len() + chars.len() > RopeStorage::MAX_LEAF_SIZE {
+                    return false;
+                }
+                leaf.splice(pos..pos, chars.iter().copied());
+                true
+            }
+            Node::Branch {
+                left, right, len, ..
+            } => {
+                let left_len = left.len();
+                let inserted = if pos <= left_len {
+                    left.insert_in_leaf(pos, chars)
+                } else {
+                    right.insert_in_leaf(pos - left_len, chars)
+                };
+                if inserted {
+                    *len += chars.len();
+                }
+                inserted
+            }
+        }
+    }
+
+    /// Deletes [start, end) if it lies inside one leaf that stays non-empty
+    ///
+    /// Returns false without modifying anything otherwise.
+    fn delete_in_leaf(&mut self, start: usize, end: usize) -> bool {
+        match self {
+            Node::Leaf(leaf) => {
+                if end - start >= leaf.len() {
+                    return false;
+                }
+                leaf.drain(start..end);
+                true
+            }
+            Node::Branch {
+                left, right, len, ..
+            } => {
+                let left_len = left.len();
+                let deleted = if end <= left_len {
+                    left.delete_in_leaf(start, end)
+                } else if start >= left_len {
+                    right.delete_in_leaf(start - left_len, end - left_len)
+                } else {
+                    false
+                };
+                if deleted {
+                    *len -= end - start;
+                }
+                deleted
+            }
+        }
+    }
+
+    /// Appends the characters in [start, end) of this subtree to `out`
+    fn collect_into(&self, start: usize, end: usize, out: &mut String) {
+        if start >= end {
+            return;
+        }
+        match self {
+            Node::Leaf(chars) => out.extend(&chars[start..end]),
+            Node::Branch { left, right, .. } => {
+                let left_len = left.len();
+                if start < left_len {
+                    left.collect_into(start, end.min(left_len), out);
+                }
+                if end > left_len {
+                    right.collect_into(start.saturating_sub(left_len), end - left_len, out);
+                }
+            }
+        }
+    }
+}
+
+/// Joins two subtrees, rebalancing along the spine of the taller one
+fn join(left: Box<Node>, right: Box<Node>) -> Box<Node> {
+    // Merge neighbouring leaves so repeated splits don't leave tiny chunks
+    if let (Node::Leaf(a), Node::Leaf(b)) = (left.as_ref(), right.as_ref())
+        && a.len() + b.len() <= RopeStorage::MAX_LEAF_SIZE
+    {
+        let mut chars = Vec::with_capacity(a.len() + b.len());
+        chars.extend_from_slice(a);
+        chars.extend_from_slice(b);
+        return Box::new(Node::Leaf(chars));
+    }
+
+    let left_height = left.height();
+    let right_height = right.height();
+
+    if left_height > right_height + 1 {
+        let Node::Branch {
+            left: outer,
+            right: inner,
+            ..
+        } = *left
+        else {
+            unreachable!("a node taller than its sibling is a branch")
+        };
+        balance(outer, join(inner, right))
+    } else if right_height > left_height + 1 {
+        let Node::Branch {
+            left: inner,
+            right: outer,
+            ..
+        } = *right
+        else {
+            unreachable!("a node taller than its sibling is a branch")
+        };
+        balance(join(left, inner), outer)
+    } else {
+        Node::branch(left, right)
+    }
+}
+
+/// Creates a branch from subtrees whose heights differ by at most two,
+/// rotating once or twice to restore the AVL invariant
+fn balance(left: Box<Node>, right: Box<Node>) -> Box<Node> {
+    let left_height = left.height();
+    let right_height = right.height();
+
+    if left_height > right_height + 1 {
+        let Node::Branch {
+            left: ll,
+            right: lr,
+            ..
+        } = *left
+        else {
+            unreachable!("a node taller than its sibling is a branch")
+        };
+        if ll.height() >= lr.height() {
+            Node::branch(ll, Node::branch(lr, right))
+        } else {
+            let Node::Branch {
+                left: lrl,
+                right: lrr,
+                ..
+            } = *lr
+            else {
+                unreachable!("a node taller than its sibling is a branch")
+            };
+            Node::branch(Node::branch(ll, lrl), Node::branch(lrr, right))
+        }
+    } else if right_height > left_height + 1 {
+        let Node::Branch {
+            left: rl,
+            right: rr,
+            ..
+        } = *right
+        else {
+            unreachable!("a node taller than its sibling is a branch")
+        };
+        if rr.height() >= rl.height() {
+            Node::branch(Node::branch(left, rl), rr)
+        } else {
+            let Node::Branch {
+                left: rll,
+                right: rlr,
+                ..
+            } = *rl
+            else {
+                unreachable!("a node taller than its sibling is a branch")
+            };
+            Node::branch(Node::branch(left, rll), Node::branch(rlr, rr))
+        }
+    } else {
+        Node::branch(left, right)
+    }
+}
+
+/// Joins two optional subtrees
+fn join_opt(left: Option<Box<Node>>, right: Option<Box<Node>>) -> Option<Box<Node>> {
+    match (left, right) {
+        (Some(left), Some(right)) => Some(join(left, right)),
+        (left, None) => left,
+        (None, right) => right,
+    }
+}
+
+/// Splits an optional subtree into [0, pos) and [pos, len)
+fn split_opt(node: Option<Box<Node>>, pos: usize) -> (Option<Box<Node>>, Option<Box<Node>>) {
+    match node {
+        Some(node) => split(node, pos),
+        None => (None, None),
+    }
+}
+
+/// Splits a subtree into [0, pos) and [pos, len)
+fn split(node: Box<Node>, pos: usize) -> (Option<Box<Node>>, Option<Box<Node>>) {
+    if pos == 0 {
+        return (None, Some(node));
+    }
+    if pos >= node.len() {
+        return (Some(node), None);
+    }
+
+    match *node {
+        Node::Leaf(mut chars) => {
+            let tail = chars.split_off(pos);
+            (
+                Some(Box::new(Node::Leaf(chars))),
+                Some(Box::new(Node::Leaf(tail))),
+            )
+        }
+        Node::Branch { left, right, .. } => {
+            let left_len = left.len();
+            if pos < left_len {
+                let (before, after) = split(left, pos);
+                (before, join_opt(after, Some(right)))
+            } else {
+                let (before, after) = split(right, pos - left_len);
+                (join_opt(Some(left), before), after)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks cached lengths and the AVL height invariant for every node
+    fn assert_balanced(node: &Node) -> usize {
+        match node {
+            Node::Leaf(chars) => {
+                assert!(!chars.is_empty());
+                0
+            }
+            Node::Branch {
+                left,
+                right,
+                len,
+                height,
+            } => {
+                assert_eq!(*len, left.len() + right.len());
+                let left_height = assert_balanced(left);
+                let right_height = assert_balanced(right);
+                assert!(left_height.abs_diff(right_height) <= 1);
+                assert_eq!(*height, 1 + left_height.max(right_height));
+                *height
+            }
+        }
+    }
+
+    #[test]
+    fn test_new_rope() {
+        let rope = RopeStorage::new();
+        assert_eq!(rope.len(), 0);
+        assert!(rope.is_empty());
+        assert_eq!(rope.get_text(), "");
+    }
+
+    #[test]
+    fn test_from_text() {
+        let rope = RopeStorage::from_text("Hello");
+        assert_eq!(rope.len(), 5);
+        assert_eq!(rope.get_text(), "Hello");
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        let mut rope = RopeStorage::from_text("Hello World");
+        rope.insert(5, " Beautiful");
+        assert_eq!(rope.get_text(), "Hello Beautiful World");
+
+        rope.delete(5, 15);
+        assert_eq!(rope.get_text(), "Hello World");
+
+        rope.delete(0, 11);
+        assert!(rope.is_empty());
+    }
+
+    #[test]
+    fn test_unicode() {
+        let mut rope = RopeStorage::from_text("héllo 世界");
+        rope.insert(6, "🌍 ");
+        assert_eq!(rope.get_text(), "héllo 🌍 世界");
+        assert_eq!(rope.get_char(6), Some('🌍'));
+        assert_eq!(rope.get_slice(1, 2), "é");
+    }
+
+    #[test]
+    fn test_get_char_out_of_bounds() {
+        let rope = RopeStorage::from_text("abc");
+        assert_eq!(rope.get_char(2), Some('c'));
+        assert_eq!(rope.get_char(3), None);
+        assert_eq!(RopeStorage::new().get_char(0), None);
+    }
+
+    #[test]
+    fn test_large_text_is_balanced() {
+        let text = "abcdefghij".repeat(10_000);
+        let rope = RopeStorage::from_text(&text);
+        assert_eq!(rope.len(), 100_000);
+        assert!(rope.leaf_count() > 1);
+        assert_balanced(rope.root.as_deref().unwrap());
+        assert_eq!(rope.get_slice(99_995, 100_000), "fghij");
+    }
+
+    #[test]
+    fn test_random_edits_match_string() {
+        let mut rope = RopeStorage::from_text(&"0123456789".repeat(500));
+        let mut expected: Vec<char> = "0123456789".repeat(500).chars().collect();
+
+        // Simple LCG so the edit sequence is deterministic
+        let mut seed: u64 = 42;
+        let mut next = |bound: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) as usize) % bound.max(1)
+        };
+
+        for i in 0..2_000 {
+            let len = expected.len();
+            if i % 3 == 0 && len > 0 {
+                let start = next(len);
+                let end = (start + next(700)).min(len);
+                rope.delete(start, end);
+                expected.drain(start..end);
+            } else {
+                let pos = next(len + 1);
+                let text = "xyz".repeat(next(300));
+                rope.insert(pos, &text);
+                expected.splice(pos..pos, text.chars());
+            }
+        }
+
+        assert_eq!(rope.get_text(), expected.iter().collect::<String>());
+        if let Some(root) = rope.root.as_deref() {
+            assert_balanced(root);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn test_insert_out_of_bounds() {
+        let mut rope = RopeStorage::from_text("abc");
+        rope.insert(4, "x");
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn test_delete_out_of_bounds() {
+        let mut rope = RopeStorage::from_text("abc");
+        rope.delete(1, 4);
+    }
+}
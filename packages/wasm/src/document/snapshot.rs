@@ -0,0 +1,397 @@
+//! Document snapshots and structural diffs
+//!
+//! A `Snapshot` is an immutable copy of a document's text, inline formats
+//! and blocks. Diffing two snapshots gives the ranged changes that turn the
+//! older one into the newer one, so an autosave can send a small patch
+//! instead of the whole document.
+//!
+//! Images and annotations are not part of a snapshot; an image shows up in
+//! a text change only as its embedded character.
+
+use crate::document::{Document, Position, Range};
+use crate::formatting::storage::BlockInfo;
+use crate::formatting::{FormatStorage, InlineFormat};
+use crate::serialization::json::SerializableBlock;
+use serde::{Deserialize, Serialize};
+
+/// An immutable copy of a document's text and formatting
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    text: String,
+    formats: FormatStorage,
+    version: u64,
+}
+
+/// A change between two snapshots
+///
+/// A diff holds at most one text change, which comes first and is in the
+/// older snapshot's offsets. The format and block changes after it are in
+/// the newer snapshot's offsets and apply once the text change has been
+/// made, with formats following the edit the way they do when typing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SnapshotChange {
+    /// Replaces the characters in `start..end` with `text`
+    Text {
+        start: usize,
+        end: usize,
+        text: String,
+    },
+    /// Sets the inline formats of `start..end` to exactly `formats`
+    Format {
+        start: usize,
+        end: usize,
+        formats: Vec<InlineFormat>,
+    },
+    /// Sets the block attributes of the lines from `block.start` to `end`
+    Block {
+        end: usize,
+        #[serde(flatten)]
+        block: SerializableBlock,
+    },
+}
+
+impl Snapshot {
+    /// Returns the version of the document when the snapshot was taken
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns the changes that turn this snapshot into `newer`
+    ///
+    /// The text change replaces the span between the longest common prefix
+    /// and suffix of the two texts. Runs of characters whose formats differ
+    /// and lines whose block attributes differ are reported as merged
+    /// ranges, so an unchanged document gives an empty list.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, SnapshotChange};
+    ///
+    /// let mut doc = Document::from_text("Hello World");
+    /// let before = doc.snapshot();
+    /// doc.insert_text(Position::new(5), ",").unwrap();
+    ///
+    /// assert_eq!(
+    ///     before.diff(&doc.snapshot()),
+    ///     vec![SnapshotChange::Text { start: 5, end: 5, text: ",".to_string() }]
+    /// );
+    /// ```
+    pub fn diff(&self, newer: &Snapshot) -> Vec<SnapshotChange> {
+        let old_chars: Vec<char> = self.text.chars().collect();
+        let new_chars: Vec<char> = newer.text.chars().collect();
+        let prefix = old_chars
+            .iter()
+            .zip(&new_chars)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = old_chars[prefix..]
+            .iter()
+            .rev()
+            .zip(new_chars[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let mut changes = Vec::new();
+        let mut formats = self.formats.clone();
+        let deleted = Range::from_offsets(prefix, old_chars.len() - suffix);
+        let inserted = &new_chars[prefix..new_chars.len() - suffix];
+        if !deleted.is_empty() || !inserted.is_empty() {
+            changes.push(SnapshotChange::Text {
+                start: deleted.start_offset(),
+                end: deleted.end_offset(),
+                text: inserted.iter().collect(),
+            });
+            // Move the older formats the same way replacing the text does
+            formats.adjust_for_delete(deleted);
+            formats.adjust_for_insert(Position::new(prefix), inserted.len());
+        }
+
+        diff_formats(&formats, &newer.formats, new_chars.len(), &mut changes);
+        diff_blocks(&formats, &newer.formats, &new_chars, &mut changes);
+        changes
+    }
+}
+
+impl Document {
+    /// Takes a snapshot of the document's text and formatting
+    ///
+    /// Compare two snapshots with `Snapshot::diff` to find what changed
+    /// between them.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            text: self.get_content(),
+            formats: self.formats().clone(),
+            version: self.version(),
+        }
+    }
+}
+
+/// Appends format changes for the spans of `0..len` whose formats in `old`
+/// and `new` differ
+fn diff_formats(
+    old: &FormatStorage,
+    new: &FormatStorage,
+    len: usize,
+    changes: &mut Vec<SnapshotChange>,
+) {
+    let whole = Range::from_offsets(0, len);
+    let old_runs = old.styled_runs(whole);
+    let new_runs = new.styled_runs(whole);
+    let (mut i, mut j) = (0, 0);
+    let mut start = 0;
+
+    // Both lists cover the whole text, so walk them together one
+    // segment boundary at a time
+    while i < old_runs.len() && j < new_runs.len() {
+        let (old_range, old_formats) = &old_runs[i];
+        let (new_range, new_formats) = &new_runs[j];
+        let end = old_range.end_offset().min(new_range.end_offset());
+
+        if old_formats != new_formats {
+            let formats: Vec<InlineFormat> = new_formats.iter().cloned().collect();
+            match changes.last_mut() {
+                Some(SnapshotChange::Format {
+                    end: last_end,
+                    formats: last,
+                    ..
+                }) if *last_end == start && same_formats(last, &formats) => *last_end = end,
+                _ => changes.push(SnapshotChange::Format {
+                    start,
+                    end,
+                    formats,
+                }),
+            }
+        }
+
+        if old_range.end_offset() == end {
+            i += 1;
+        }
+        if new_range.end_offset() == end {
+            j += 1;
+        }
+        start = end;
+    }
+}
+
+/// Appends block changes for the lines of `text` whose block attributes in
+/// `old` and `new` differ
+fn diff_blocks(
+    old: &FormatStorage,
+    new: &FormatStorage,
+    text: &[char],
+    changes: &mut Vec<SnapshotChange>,
+) {
+    let mut line_start = 0;
+    let mut pending: Option<(BlockInfo, usize)> = None;
+
+    loop {
+        let line_end = text[line_start..]
+            .iter()
+            .position(|&c| c == '\n')
+            .map_or(text.len(), |i| line_start + i);
+        let old_block = block_at(old, line_start);
+        let new_block = block_at(new, line_start);
+
+        if old_block.same_attributes(new_block) {
+            if let Some((block, end)) = pending.take() {
+                changes.push(block_change(block, end));
+            }
+        } else {
+            match &mut pending {
+                Some((block, end)) if block.same_attributes(new_block) => *end = line_end,
+                _ => {
+                    if let Some((block, end)) = pending.take() {
+                        changes.push(block_change(block, end));
+                    }
+                    let mut block = new_block.clone();
+                    block.start_offset = line_start;
+                    pending = Some((block, line_end));
+                }
+            }
+        }
+
+        if line_end == text.len() {
+            break;
+        }
+        line_start = line_end + 1;
+    }
+
+    if let Some((block, end)) = pending {
+        changes.push(block_change(block, end));
+    }
+}
+
+/// Returns the block a line starting at `offset` belongs to
+fn block_at(formats: &FormatStorage, offset: usize) -> &BlockInfo {
+    let blocks = formats.get_blocks();
+    let index = blocks.partition_point(|b| b.start_offset <= offset);
+    &blocks[index.saturating_sub(1)]
+}
+
+fn block_change(block: BlockInfo, end: usize) -> SnapshotChange {
+    SnapshotChange::Block {
+        end,
+        block: SerializableBlock {
+            start: block.start_offset,
+            block_type: block.block_type,
+            indent: block.indent,
+            list_style: block.list_style,
+            list_start: block.list_start,
+            block_indent: block.block_indent,
+            first_line_indent: block.first_line_indent,
+            direction: block.direction,
+        },
+    }
+}
+
+/// Returns true if both lists hold the same formats in any order
+fn same_formats(a: &[InlineFormat], b: &[InlineFormat]) -> bool {
+    a.len() == b.len() && a.iter().all(|format| b.contains(format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::BlockType;
+
+    /// Applies `changes` to `doc` the way the diff describes
+    fn apply(doc: &mut Document, changes: &[SnapshotChange]) {
+        for change in changes {
+            match change {
+                SnapshotChange::Text { start, end, text } => {
+                    doc.replace_range_direct(Range::from_offsets(*start, *end), text);
+                }
+                SnapshotChange::Format {
+                    start,
+                    end,
+                    formats,
+                } => {
+                    let range = Range::from_offsets(*start, *end);
+                    for (segment, current) in doc.formats().styled_runs(range) {
+                        for format in &current {
+                            doc.formats_mut().remove_format(segment, format);
+                        }
+                    }
+                    for format in formats {
+                        doc.formats_mut().apply_format(range, format.clone());
+                    }
+                }
+                SnapshotChange::Block { end, block } => {
+                    let next =
+                        (*end < doc.get_length()).then(|| block_at(doc.formats(), end + 1).clone());
+                    let mut blocks: Vec<BlockInfo> = doc
+                        .formats()
+                        .get_blocks()
+                        .iter()
+                        .filter(|b| b.start_offset < block.start || b.start_offset > end + 1)
+                        .cloned()
+                        .collect();
+                    let mut info = BlockInfo::new(block.start, block.block_type.clone())
+                        .with_indent(block.indent)
+                        .with_list_style(block.list_style)
+                        .with_list_start(block.list_start)
+                        .with_block_indent(block.block_indent)
+                        .with_first_line_indent(block.first_line_indent)
+                        .with_direction(block.direction);
+                    info.start_offset = block.start;
+                    blocks.push(info);
+                    if let Some(mut next) = next {
+                        next.start_offset = end + 1;
+                        blocks.push(next);
+                    }
+                    doc.formats_mut().set_blocks(blocks);
+                }
+            }
+        }
+    }
+
+    /// Asserts that the two documents have the same text, formats and line
+    /// attributes
+    fn assert_same(a: &Document, b: &Document) {
+        assert_eq!(a.get_content(), b.get_content());
+        let snapshot = a.snapshot();
+        assert_eq!(snapshot.diff(&b.snapshot()), Vec::new());
+    }
+
+    #[test]
+    fn test_unchanged_document_has_no_changes() {
+        let doc = Document::from_text("Hello\nWorld");
+        assert!(doc.snapshot().diff(&doc.snapshot()).is_empty());
+    }
+
+    #[test]
+    fn test_text_change_covers_only_the_edit() {
+        let mut doc = Document::from_text("Hello World");
+        let before = doc.snapshot();
+        doc.replace_range(Range::from_offsets(6, 11), "Rust")
+            .unwrap();
+
+        assert_eq!(
+            before.diff(&doc.snapshot()),
+            vec![SnapshotChange::Text {
+                start: 6,
+                end: 11,
+                text: "Rust".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_format_and_block_changes() {
+        let mut doc = Document::from_text("one\ntwo\nthree");
+        let before = doc.snapshot();
+        doc.apply_format(Range::from_offsets(1, 6), InlineFormat::Bold);
+        doc.set_block_type(Range::from_offsets(4, 13), BlockType::BulletList);
+
+        let changes = before.diff(&doc.snapshot());
+        assert_eq!(changes.len(), 2);
+        assert_eq!(
+            changes[0],
+            SnapshotChange::Format {
+                start: 1,
+                end: 6,
+                formats: vec![InlineFormat::Bold],
+            }
+        );
+        let SnapshotChange::Block { end, block } = &changes[1] else {
+            panic!("expected a block change, got {:?}", changes[1]);
+        };
+        assert_eq!((block.start, *end), (4, 13));
+        assert_eq!(block.block_type, BlockType::BulletList);
+    }
+
+    #[test]
+    fn test_applying_diff_reproduces_newer_snapshot() {
+        let original = || {
+            let mut doc = Document::from_text("alpha\nbeta\ngamma");
+            doc.apply_format(Range::from_offsets(0, 10), InlineFormat::Italic);
+            doc.set_block_type(Range::from_offsets(6, 10), BlockType::heading(2));
+            doc
+        };
+        let mut doc = original();
+        let before = doc.snapshot();
+
+        doc.delete_range(Range::from_offsets(3, 8)).unwrap();
+        doc.insert_text(Position::new(3), "X\nY").unwrap();
+        doc.remove_format(Range::from_offsets(1, 3), &InlineFormat::Italic);
+        doc.set_block_type(Range::from_offsets(0, 1), BlockType::block_quote());
+
+        let mut patched = original();
+        apply(&mut patched, &before.diff(&doc.snapshot()));
+        assert_same(&patched, &doc);
+    }
+
+    #[test]
+    fn test_changes_serialize_with_type_tag() {
+        let mut doc = Document::from_text("Hi");
+        let before = doc.snapshot();
+        doc.set_block_type(Range::from_offsets(0, 2), BlockType::heading(1));
+
+        let json = serde_json::to_string(&before.diff(&doc.snapshot())).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"type":"block","end":2,"start":0,"block_type":{"Heading":{"level":1}}}]"#
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Ranges excluded from spellchecking
+//!
+//! Marks text a spellchecker should skip, such as code spans or proper
+//! nouns. The ranges are a decoration: they do not change the text or its
+//! formatting, shift with edits the way annotations do, and are not part of
+//! the undo history. HTML export wraps the text in
+//! `<span spellcheck="false">`.
+
+use super::{Position, Range};
+
+/// Sorted, non-overlapping ranges of text that spellcheckers should skip
+#[derive(Debug, Clone, Default)]
+pub struct NoSpellcheckStorage {
+    ranges: Vec<Range>,
+}
+
+impl NoSpellcheckStorage {
+    /// Creates empty storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true if no text is marked
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the marked ranges in order
+    pub fn ranges(&self) -> &[Range] {
+        &self.ranges
+    }
+
+    /// Marks a range, merging it with marked ranges it overlaps or touches
+    ///
+    /// Empty ranges are ignored.
+    pub fn mark(&mut self, range: Range) {
+        let normalized = range.normalize();
+        let mut start = normalized.start_offset();
+        let mut end = normalized.end_offset();
+        if start == end {
+            return;
+        }
+
+        let first = self.ranges.partition_point(|r| r.end_offset() < start);
+        let last = self.ranges.partition_point(|r| r.start_offset() <= end);
+        if first < last {
+            start = start.min(self.ranges[first].start_offset());
+            end = end.max(self.ranges[last - 1].end_offset());
+        }
+        self.ranges
+            .splice(first..last, [Range::from_offsets(start, end)]);
+    }
+
+    /// Unmarks a range, splitting marked ranges that extend past it
+    pub fn unmark(&mut self, range: Range) {
+        let normalized = range.normalize();
+        let start = normalized.start_offset();
+        let end = normalized.end_offset();
+
+        let mut result = Vec::with_capacity(self.ranges.len() + 1);
+        for r in &self.ranges {
+            if r.end_offset() <= start || r.start_offset() >= end {
+                result.push(*r);
+                continue;
+            }
+            if r.start_offset() < start {
+                result.push(Range::from_offsets(r.start_offset(), start));
+            }
+            if r.end_offset() > end {
+                result.push(Range::from_offsets(end, r.end_offset()));
+            }
+        }
+        self.ranges = result;
+    }
+
+    /// Returns true if the character at `offset` is marked
+    pub fn contains(&self, offset: usize) -> bool {
+        let idx = self.ranges.partition_point(|r| r.end_offset() <= offset);
+        self.ranges
+            .get(idx)
+            .is_some_and(|r| r.start_offset() <= offset)
+    }
+
+    /// Returns the marked ranges overlapping `range`, clipped to it
+    pub fn ranges_in(&self, range: Range) -> Vec<Range> {
+        let normalized = range.normalize();
+        let start = normalized.start_offset();
+        let end = normalized.end_offset();
+        self.ranges
+            .iter()
+            .filter(|r| r.start_offset() < end && r.end_offset() > start)
+            .map(|r| Range::from_offsets(r.start_offset().max(start), r.end_offset().min(end)))
+            .collect()
+    }
+
+    /// Removes all marks
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+
+    /// Shifts marks after text insertion
+    ///
+    /// Text inserted strictly inside a marked range is marked too; text
+    /// inserted at either edge is not.
+    pub fn adjust_for_insert(&mut self, pos: Position, length: usize) {
+        let insert_offset = pos.offset();
+        for range in &mut self.ranges {
+            let start = range.start_offset();
+            let end = range.end_offset();
+            if start >= insert_offset {
+                *range = Range::from_offsets(start + length, end + length);
+            } else if end > insert_offset {
+                *range = Range::from_offsets(start, end + length);
+            }
+        }
+    }
+
+    /// Shrinks marks overlapping a deleted range and shifts the ones after
+    /// it, dropping marks whose text is deleted entirely
+    pub fn adjust_for_delete(&mut self, range: Range) {
+        let normalized = range.normalize();
+        let delete_start = normalized.start_offset();
+        let delete_end = normalized.end_offset();
+        let deleted = delete_end - delete_start;
+
+        let shift = |offset: usize| {
+            if offset >= delete_end {
+                offset - deleted
+            } else {
+                offset.min(delete_start)
+            }
+        };
+        let mut result: Vec<Range> = Vec::with_capacity(self.ranges.len());
+        for r in &self.ranges {
+            let start = shift(r.start_offset());
+            let end = shift(r.end_offset());
+            if start == end {
+                continue;
+            }
+            // Deleting the text between two marks joins them
+            match result.last_mut() {
+                Some(last) if last.end_offset() == start => {
+                    *last = Range::from_offsets(last.start_offset(), end);
+                }
+                _ => result.push(Range::from_offsets(start, end)),
+            }
+        }
+        self.ranges = result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark_merges_and_unmark_splits() {
+        let mut storage = NoSpellcheckStorage::new();
+        storage.mark(Range::from_offsets(2, 4));
+        storage.mark(Range::from_offsets(8, 10));
+        storage.mark(Range::from_offsets(4, 6));
+        storage.mark(Range::from_offsets(3, 3));
+        assert_eq!(
+            storage.ranges(),
+            [Range::from_offsets(2, 6), Range::from_offsets(8, 10)]
+        );
+        assert!(storage.contains(5));
+        assert!(!storage.contains(6));
+
+        storage.unmark(Range::from_offsets(3, 9));
+        assert_eq!(
+            storage.ranges(),
+            [Range::from_offsets(2, 3), Range::from_offsets(9, 10)]
+        );
+        assert_eq!(
+            storage.ranges_in(Range::from_offsets(0, 5)),
+            [Range::from_offsets(2, 3)]
+        );
+    }
+
+    #[test]
+    fn test_marks_follow_edits() {
+        let mut storage = NoSpellcheckStorage::new();
+        storage.mark(Range::from_offsets(2, 5));
+        storage.mark(Range::from_offsets(7, 9));
+
+        storage.adjust_for_insert(Position::new(2), 1);
+        storage.adjust_for_insert(Position::new(4), 2);
+        assert_eq!(
+            storage.ranges(),
+            [Range::from_offsets(3, 8), Range::from_offsets(10, 12)]
+        );
+
+        storage.adjust_for_delete(Range::from_offsets(7, 11));
+        assert_eq!(storage.ranges(), [Range::from_offsets(3, 8)]);
+        storage.adjust_for_delete(Range::from_offsets(2, 9));
+        assert!(storage.is_empty());
+    }
+}
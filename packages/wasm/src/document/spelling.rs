@@ -0,0 +1,320 @@
+//! Spellchecking against an application dictionary
+//!
+//! The editor ships no dictionary. The application provides one, as a word
+//! list or a lookup function (in the browser, typically backed by a
+//! dictionary library or a server), and `Document::check_spelling` looks up
+//! the words of the document in it. Only lines edited since the last check
+//! are looked up again, and a check can stop after a time budget and carry
+//! on in the next call, so it fits in idle callbacks. Misspellings are kept
+//! as decorations that shift with edits until their line is checked again.
+//!
+//! Words in code blocks, words containing digits and text marked with
+//! `Document::mark_no_spellcheck` are skipped.
+
+use super::decorations::DecorationStorage;
+use super::{Document, Position, Range};
+use crate::operations::history::now;
+use crate::utils::segmenter::word_segments;
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Decides whether words are spelled correctly
+pub trait Dictionary {
+    /// Returns true if `word` is spelled correctly
+    fn contains(&self, word: &str) -> bool;
+}
+
+impl<F: Fn(&str) -> bool> Dictionary for F {
+    fn contains(&self, word: &str) -> bool {
+        self(word)
+    }
+}
+
+/// A dictionary made of a list of correctly spelled words
+///
+/// A word also matches when its lowercase form is listed, so "Hello" at
+/// the start of a sentence matches "hello".
+#[derive(Debug, Clone, Default)]
+pub struct WordList {
+    words: HashSet<String>,
+}
+
+impl WordList {
+    /// Creates a dictionary of the given words
+    pub fn new(words: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            words: words.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Adds a word to the list
+    pub fn insert(&mut self, word: impl Into<String>) {
+        self.words.insert(word.into());
+    }
+}
+
+impl Dictionary for WordList {
+    fn contains(&self, word: &str) -> bool {
+        self.words.contains(word) || self.words.contains(&word.to_lowercase())
+    }
+}
+
+/// Spellchecking state of a document
+pub(crate) struct Spellchecker {
+    dictionary: Box<dyn Dictionary>,
+    /// Words added with `Document::add_to_dictionary`
+    added: WordList,
+    /// Misspelled words with their ranges
+    misspellings: DecorationStorage<String>,
+    /// Ranges whose lines need checking, sorted by start
+    ///
+    /// An empty range marks the line it is on, such as where text was
+    /// deleted.
+    unchecked: Vec<Range>,
+}
+
+impl Spellchecker {
+    /// Creates a spellchecker with every line of a document of `length`
+    /// characters unchecked
+    fn new(dictionary: Box<dyn Dictionary>, added: WordList, length: usize) -> Self {
+        Self {
+            dictionary,
+            added,
+            misspellings: DecorationStorage::new(),
+            unchecked: vec![Range::from_offsets(0, length)],
+        }
+    }
+
+    fn is_correct(&self, word: &str) -> bool {
+        self.added.contains(word) || self.dictionary.contains(word)
+    }
+
+    /// Marks the lines a range touches for checking
+    pub(crate) fn mark_unchecked(&mut self, range: Range) {
+        let range = range.normalize();
+        let idx = self
+            .unchecked
+            .partition_point(|r| r.start_offset() <= range.start_offset());
+        self.unchecked.insert(idx, range);
+    }
+
+    /// Marks everything up to and including `offset` as checked
+    fn mark_checked_to(&mut self, offset: usize) {
+        let checked = self
+            .unchecked
+            .iter()
+            .take_while(|range| range.start_offset() <= offset)
+            .count();
+        let mut rest: Vec<Range> = self.unchecked[..checked]
+            .iter()
+            .filter(|range| range.end_offset() > offset + 1)
+            .map(|range| Range::from_offsets(offset + 1, range.end_offset()))
+            .collect();
+        rest.extend_from_slice(&self.unchecked[checked..]);
+        self.unchecked = rest;
+    }
+
+    /// Shifts misspellings and unchecked ranges after text insertion and
+    /// marks the inserted text for checking
+    pub(crate) fn adjust_for_insert(&mut self, pos: Position, length: usize) {
+        self.misspellings.adjust_for_insert(pos, length);
+        let insert_offset = pos.offset();
+        for range in &mut self.unchecked {
+            let start = range.start_offset();
+            let end = range.end_offset();
+            if start >= insert_offset {
+                *range = Range::from_offsets(start + length, end + length);
+            } else if end >= insert_offset {
+                *range = Range::from_offsets(start, end + length);
+            }
+        }
+        self.mark_unchecked(Range::from_offsets(insert_offset, insert_offset + length));
+    }
+
+    /// Shrinks misspellings and unchecked ranges after a deletion and marks
+    /// the line it joined for checking
+    pub(crate) fn adjust_for_delete(&mut self, range: Range) {
+        let normalized = range.normalize();
+        self.misspellings.adjust_for_delete(normalized);
+        let delete_start = normalized.start_offset();
+        let delete_end = normalized.end_offset();
+        let shift = |offset: usize| {
+            if offset >= delete_end {
+                offset - (delete_end - delete_start)
+            } else {
+                offset.min(delete_start)
+            }
+        };
+        for range in &mut self.unchecked {
+            *range = Range::from_offsets(shift(range.start_offset()), shift(range.end_offset()));
+        }
+        self.mark_unchecked(Range::from_offsets(delete_start, delete_start));
+    }
+}
+
+impl Document {
+    /// Sets the dictionary used by `check_spelling`, or turns
+    /// spellchecking off with None
+    ///
+    /// Setting a dictionary discards the misspellings found so far and
+    /// marks the whole document for checking. Words added with
+    /// `add_to_dictionary` are kept.
+    pub fn set_spellcheck_dictionary(&mut self, dictionary: Option<Box<dyn Dictionary>>) {
+        let added = self
+            .spellchecker
+            .take()
+            .map(|spellchecker| spellchecker.added)
+            .unwrap_or_default();
+        self.spellchecker =
+            dictionary.map(|dictionary| Spellchecker::new(dictionary, added, self.get_length()));
+    }
+
+    /// Checks the lines edited since the last check against the dictionary
+    ///
+    /// Stops once `budget` has passed, if given, and carries on from there
+    /// in the next call. Does nothing without a dictionary.
+    ///
+    /// # Returns
+    /// `true` once every line has been checked
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range, WordList};
+    ///
+    /// let mut doc = Document::from_text("Teh cat");
+    /// doc.set_spellcheck_dictionary(Some(Box::new(WordList::new(["the", "cat"]))));
+    /// assert!(doc.check_spelling(None));
+    /// assert_eq!(doc.misspellings(), [(Range::from_offsets(0, 3), "Teh".to_string())]);
+    ///
+    /// doc.delete_range(Range::from_offsets(1, 3)).unwrap();
+    /// doc.insert_text(Position::new(1), "he").unwrap();
+    /// doc.check_spelling(None);
+    /// assert!(doc.misspellings().is_empty());
+    /// ```
+    pub fn check_spelling(&mut self, budget: Option<Duration>) -> bool {
+        let Some(mut spellchecker) = self.spellchecker.take() else {
+            return true;
+        };
+        let started = now();
+        while let Some(range) = spellchecker.unchecked.first() {
+            let line_start = self.line_start_before(range.start_offset());
+            let line_end = self.line_end_from(line_start);
+            self.check_line(&mut spellchecker, line_start, line_end);
+            spellchecker.mark_checked_to(line_end);
+            if budget.is_some_and(|budget| now().saturating_sub(started) >= budget) {
+                break;
+            }
+        }
+        let done = spellchecker.unchecked.is_empty();
+        self.spellchecker = Some(spellchecker);
+        done
+    }
+
+    /// Replaces the misspellings found on one line
+    fn check_line(&self, spellchecker: &mut Spellchecker, line_start: usize, line_end: usize) {
+        spellchecker
+            .misspellings
+            .retain(|range, _| !(line_start..=line_end).contains(&range.start_offset()));
+        if line_start == line_end
+            || self
+                .get_block_type_at(Position::new(line_start))
+                .is_code_block()
+        {
+            return;
+        }
+
+        let chars: Vec<char> = self
+            .get_text_in_range(Range::from_offsets(line_start, line_end))
+            .chars()
+            .collect();
+        for segment in word_segments(&chars, self.word_segmenter.as_deref()) {
+            let range = Range::from_offsets(line_start + segment.start, line_start + segment.end);
+            let word: String = chars[segment.start..segment.end].iter().collect();
+            if !segment.is_word
+                || word.chars().any(|c| c.is_numeric())
+                || !self.no_spellcheck.ranges_in(range).is_empty()
+                || spellchecker.is_correct(&word)
+            {
+                continue;
+            }
+            spellchecker.misspellings.add(range, word);
+        }
+    }
+
+    /// Gets the misspelled words found by `check_spelling` with their
+    /// ranges, sorted by start
+    pub fn misspellings(&self) -> &[(Range, String)] {
+        self.spellchecker
+            .as_ref()
+            .map_or(&[], |spellchecker| spellchecker.misspellings.decorations())
+    }
+
+    /// Accepts a word as correctly spelled, clearing its misspellings
+    ///
+    /// Like words in a `WordList`, the word also matches its capitalized
+    /// forms when given in lowercase. Does nothing without a dictionary.
+    pub fn add_to_dictionary(&mut self, word: &str) {
+        if let Some(spellchecker) = &mut self.spellchecker {
+            spellchecker.added.insert(word);
+            let added = &spellchecker.added;
+            spellchecker
+                .misspellings
+                .retain(|_, misspelled| !added.contains(misspelled));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::BlockType;
+
+    #[test]
+    fn test_check_spelling_only_rechecks_edited_lines() {
+        let looked_up = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let log = looked_up.clone();
+        let dictionary = move |word: &str| {
+            log.borrow_mut().push(word.to_string());
+            word != "wrod" && word != "speling"
+        };
+
+        let mut doc = Document::from_text("one wrod\nfn x2()\nspeling 42\nlast");
+        doc.set_block_type_lines(Range::from_offsets(9, 15), BlockType::code_block())
+            .unwrap();
+        doc.set_spellcheck_dictionary(Some(Box::new(dictionary)));
+        assert!(doc.check_spelling(None));
+        assert_eq!(
+            doc.misspellings(),
+            [
+                (Range::from_offsets(4, 8), "wrod".to_string()),
+                (Range::from_offsets(17, 24), "speling".to_string()),
+            ]
+        );
+        assert_eq!(*looked_up.borrow(), ["one", "wrod", "speling", "last"]);
+
+        looked_up.borrow_mut().clear();
+        doc.insert_text(Position::new(0), "Some ").unwrap();
+        assert!(doc.check_spelling(None));
+        assert_eq!(*looked_up.borrow(), ["Some", "one", "wrod"]);
+        assert_eq!(doc.misspellings()[1].0, Range::from_offsets(22, 29));
+
+        doc.add_to_dictionary("speling");
+        assert_eq!(doc.misspellings().len(), 1);
+        doc.mark_no_spellcheck(Range::from_offsets(9, 13)).unwrap();
+        doc.check_spelling(None);
+        assert!(doc.misspellings().is_empty());
+
+        doc.set_spellcheck_dictionary(None);
+        assert!(doc.misspellings().is_empty());
+    }
+
+    #[test]
+    fn test_check_spelling_within_budget() {
+        let mut doc = Document::from_text(&"a b\n".repeat(50));
+        doc.set_spellcheck_dictionary(Some(Box::new(WordList::new(["a"]))));
+        assert!(!doc.check_spelling(Some(Duration::ZERO)));
+        assert_eq!(doc.misspellings().len(), 1);
+        while !doc.check_spelling(Some(Duration::ZERO)) {}
+        assert_eq!(doc.misspellings().len(), 50);
+    }
+}
@@ -0,0 +1,145 @@
+//! Sticky positions
+//!
+//! A sticky position is an offset handed out under a numeric handle that
+//! keeps pointing at the same logical spot while the text is edited, so
+//! decorations can stay attached without re-diffing the document. Its bias
+//! decides which side of the position text typed exactly at it ends up on.
+//! Sticky positions are session state: they are not serialized and not part
+//! of the undo history.
+
+use super::{Position, Range};
+use std::collections::BTreeMap;
+
+/// Which side of text inserted exactly at a sticky position it stays on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Bias {
+    /// Stays before the inserted text (left gravity)
+    Left,
+    /// Moves after the inserted text (right gravity)
+    Right,
+}
+
+/// Sticky positions keyed by handle
+#[derive(Debug, Clone, Default)]
+pub struct StickyPositionStorage {
+    positions: BTreeMap<u32, (Position, Bias)>,
+    next_handle: u32,
+}
+
+impl StickyPositionStorage {
+    /// Creates empty sticky position storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of live sticky positions
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Returns true if no sticky positions are live
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Stores a sticky position and returns its handle
+    ///
+    /// Handles are never reused within one storage.
+    pub fn create(&mut self, pos: Position, bias: Bias) -> u32 {
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1);
+        self.positions.insert(handle, (pos, bias));
+        handle
+    }
+
+    /// Gets the current position of the given handle
+    pub fn resolve(&self, handle: u32) -> Option<Position> {
+        self.positions.get(&handle).map(|(pos, _)| *pos)
+    }
+
+    /// Gets the bias of the given handle
+    pub fn bias(&self, handle: u32) -> Option<Bias> {
+        self.positions.get(&handle).map(|(_, bias)| *bias)
+    }
+
+    /// Releases the given handle, returning its last position
+    pub fn release(&mut self, handle: u32) -> Option<Position> {
+        self.positions.remove(&handle).map(|(pos, _)| pos)
+    }
+
+    /// Releases all handles
+    pub fn clear(&mut self) {
+        self.positions.clear();
+    }
+
+    /// Shifts positions after an insertion; positions exactly at the
+    /// insertion point move only if they have right bias
+    pub fn adjust_for_insert(&mut self, pos: Position, length: usize) {
+        let insert_offset = pos.offset();
+        for (position, bias) in self.positions.values_mut() {
+            let offset = position.offset();
+            if offset > insert_offset || (offset == insert_offset && *bias == Bias::Right) {
+                *position = Position::new(offset + length);
+            }
+        }
+    }
+
+    /// Shifts positions after a deletion; positions inside the deleted range
+    /// move to its start
+    pub fn adjust_for_delete(&mut self, range: Range) {
+        let normalized = range.normalize();
+        let delete_start = normalized.start_offset();
+        let delete_end = normalized.end_offset();
+
+        for (position, _) in self.positions.values_mut() {
+            let offset = position.offset();
+            if offset >= delete_end {
+                *position = Position::new(offset - (delete_end - delete_start));
+            } else if offset > delete_start {
+                *position = Position::new(delete_start);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_resolve_and_release() {
+        let mut storage = StickyPositionStorage::new();
+        let first = storage.create(Position::new(3), Bias::Left);
+        let second = storage.create(Position::new(5), Bias::Right);
+        assert_ne!(first, second);
+        assert_eq!(storage.len(), 2);
+        assert_eq!(storage.resolve(first), Some(Position::new(3)));
+        assert_eq!(storage.bias(second), Some(Bias::Right));
+
+        assert_eq!(storage.release(first), Some(Position::new(3)));
+        assert!(storage.resolve(first).is_none());
+        assert!(storage.release(first).is_none());
+
+        // Released handles are not handed out again
+        let third = storage.create(Position::new(0), Bias::Left);
+        assert_ne!(third, first);
+    }
+
+    #[test]
+    fn test_bias_at_insertion_point() {
+        let mut storage = StickyPositionStorage::new();
+        let left = storage.create(Position::new(4), Bias::Left);
+        let right = storage.create(Position::new(4), Bias::Right);
+        let after = storage.create(Position::new(6), Bias::Left);
+
+        storage.adjust_for_insert(Position::new(4), 2);
+        assert_eq!(storage.resolve(left), Some(Position::new(4)));
+        assert_eq!(storage.resolve(right), Some(Position::new(6)));
+        assert_eq!(storage.resolve(after), Some(Position::new(8)));
+
+        storage.adjust_for_delete(Range::from_offsets(3, 7));
+        assert_eq!(storage.resolve(left), Some(Position::new(3)));
+        assert_eq!(storage.resolve(right), Some(Position::new(3)));
+        assert_eq!(storage.resolve(after), Some(Position::new(4)));
+    }
+}
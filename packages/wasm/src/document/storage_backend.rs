@@ -0,0 +1,122 @@
+//! Text storage backend selection
+//!
+//! This module lets a `Document` choose between the gap buffer and the
+//! rope for holding its text. The choice is made at construction time.
+
+use super::lines::LineIndex;
+use super::rope::RopeStorage;
+use super::text_storage::TextStorage;
+use super::{Position, Range};
+
+/// Text storage implementation used by a Document
+///
+/// - `GapBuffer`: fastest for typing at a single cursor; edits far from the
+///   previous edit move the gap, which is O(n)
+/// - `Rope`: O(log n) edits regardless of locality; better for large
+///   documents with scattered edits (find/replace, collaboration)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageBackend {
+    #[default]
+    GapBuffer,
+    Rope,
+}
+
+/// Text storage that dispatches to the selected backend
+///
+/// It also keeps the line index of the text, updated by every edit, so line
+/// lookups don't have to scan the text.
+#[derive(Debug, Clone)]
+pub(crate) struct TextBuffer {
+    storage: Storage,
+    lines: LineIndex,
+}
+
+#[derive(Debug, Clone)]
+enum Storage {
+    GapBuffer(TextStorage),
+    Rope(RopeStorage),
+}
+
+impl TextBuffer {
+    /// Creates empty storage for the given backend
+    pub(crate) fn new(backend: StorageBackend) -> Self {
+        let storage = match backend {
+            StorageBackend::GapBuffer => Storage::GapBuffer(TextStorage::new()),
+            StorageBackend::Rope => Storage::Rope(RopeStorage::new()),
+        };
+        Self {
+            storage,
+            lines: LineIndex::new(),
+        }
+    }
+
+    /// Creates storage holding `text` for the given backend
+    pub(crate) fn from_text(text: &str, backend: StorageBackend) -> Self {
+        let storage = match backend {
+            StorageBackend::GapBuffer => Storage::GapBuffer(TextStorage::from_text(text)),
+            StorageBackend::Rope => Storage::Rope(RopeStorage::from_text(text)),
+        };
+        Self {
+            storage,
+            lines: LineIndex::from_text(text),
+        }
+    }
+
+    /// Returns the backend this storage uses
+    pub(crate) fn backend(&self) -> StorageBackend {
+        match self.storage {
+            Storage::GapBuffer(_) => StorageBackend::GapBuffer,
+            Storage::Rope(_) => StorageBackend::Rope,
+        }
+    }
+
+    /// Returns the line index of the text
+    pub(crate) fn lines(&self) -> &LineIndex {
+        &self.lines
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match &self.storage {
+            Storage::GapBuffer(storage) => storage.len(),
+            Storage::Rope(storage) => storage.len(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        match &self.storage {
+            Storage::GapBuffer(storage) => storage.is_empty(),
+            Storage::Rope(storage) => storage.is_empty(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, pos: usize, text: &str) {
+        match &mut self.storage {
+            Storage::GapBuffer(storage) => storage.insert(pos, text),
+            Storage::Rope(storage) => storage.insert(pos, text),
+        }
+        self.lines.adjust_for_insert(Position::new(pos), text);
+    }
+
+    pub(crate) fn delete(&mut self, start: usize, end: usize) {
+        match &mut self.storage {
+            Storage::GapBuffer(storage) => storage.delete(start, end),
+            Storage::Rope(storage) => storage.delete(start, end),
+        }
+        self.lines
+            .adjust_for_delete(Range::from_offsets(start, end));
+    }
+
+    pub(crate) fn get_slice(&self, start: usize, end: usize) -> String {
+        match &self.storage {
+            Storage::GapBuffer(storage) => storage.get_slice(start, end),
+            Storage::Rope(storage) => storage.get_slice(start, end),
+        }
+    }
+
+    pub(crate) fn get_text(&self) -> String {
+        match &self.storage {
+            Storage::GapBuffer(storage) => storage.get_text(),
+            Storage::Rope(storage) => storage.get_text(),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Building a document from text that arrives in chunks
+
+use crate::document::{Document, StorageBackend};
+use crate::operations::{CommandError, CommandResult};
+
+/// Collects the text of a document as it arrives in chunks
+///
+/// Each chunk is appended straight to the text storage, so the full text
+/// never has to be passed around as one string. `finish` returns the
+/// document, ready for `Document::load_content`.
+///
+/// # Example
+/// ```
+/// use rte_core::document::{Document, StorageBackend, StreamingLoad};
+///
+/// let mut load = StreamingLoad::new(StorageBackend::Rope, None);
+/// load.append_chunk("Hello ").unwrap();
+/// load.append_chunk("World").unwrap();
+///
+/// let mut doc = Document::new();
+/// doc.load_content(load.finish()).unwrap();
+/// assert_eq!(doc.get_content(), "Hello World");
+/// ```
+pub struct StreamingLoad {
+    document: Document,
+    max_length: Option<usize>,
+}
+
+impl StreamingLoad {
+    /// Starts an empty load into the given storage backend
+    ///
+    /// Chunks that would take the text past `max_length` characters are
+    /// rejected.
+    pub fn new(backend: StorageBackend, max_length: Option<usize>) -> Self {
+        Self {
+            document: Document::with_backend(backend),
+            max_length,
+        }
+    }
+
+    /// Appends a chunk of text
+    ///
+    /// # Errors
+    /// Returns `CommandError::MaxLengthExceeded` if the text would become
+    /// longer than the maximum length; the chunk is not appended
+    pub fn append_chunk(&mut self, chunk: &str) -> CommandResult<()> {
+        let end = self.document.text.len();
+        if let Some(limit) = self.max_length {
+            let length = end + chunk.chars().count();
+            if length > limit {
+                return Err(CommandError::max_length_exceeded(length, limit));
+            }
+        }
+        self.document.text.insert(end, chunk);
+        Ok(())
+    }
+
+    /// Returns the number of characters loaded so far
+    pub fn len(&self) -> usize {
+        self.document.text.len()
+    }
+
+    /// Returns true if no text has been loaded yet
+    pub fn is_empty(&self) -> bool {
+        self.document.text.is_empty()
+    }
+
+    /// Ends the load, returning a document with the collected text
+    pub fn finish(self) -> Document {
+        self.document
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_join_across_lines() {
+        let mut load = StreamingLoad::new(StorageBackend::default(), None);
+        assert!(load.is_empty());
+        for chunk in ["first li", "ne\nsecond", " line\n", "", "third ✓"] {
+            load.append_chunk(chunk).unwrap();
+        }
+        assert_eq!(load.len(), 30);
+
+        let doc = load.finish();
+        assert_eq!(doc.get_content(), "first line\nsecond line\nthird ✓");
+        assert_eq!(doc.line_count(), 3);
+    }
+
+    #[test]
+    fn test_chunk_past_max_length_is_rejected() {
+        let mut load = StreamingLoad::new(StorageBackend::Rope, Some(8));
+        load.append_chunk("Hello").unwrap();
+
+        assert_eq!(
+            load.append_chunk(" World"),
+            Err(CommandError::max_length_exceeded(11, 8))
+        );
+        load.append_chunk("!!!").unwrap();
+        assert_eq!(load.finish().get_content(), "Hello!!!");
+    }
+}
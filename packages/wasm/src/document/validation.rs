@@ -4,6 +4,7 @@
 
 use super::Range;
 use super::errors::DocumentError;
+use std::borrow::Cow;
 
 /// Maximum document size in characters (10 million characters = ~40MB)
 pub const MAX_DOCUMENT_SIZE: usize = 10_000_000;
@@ -115,7 +116,7 @@ pub fn validate_text_content(text: &str) -> Result<(), DocumentError> {
     // Check for other control characters that might cause issues
     // Allow common whitespace: space, tab, newline, carriage return
     for ch in text.chars() {
-        if ch.is_control() && ch != '\n' && ch != '\r' && ch != '\t' {
+        if is_disallowed_control(ch) {
             return Err(DocumentError::invalid_text(format!(
                 "contains invalid control character: U+{:04X}",
                 ch as u32
@@ -126,6 +127,65 @@ pub fn validate_text_content(text: &str) -> Result<(), DocumentError> {
     Ok(())
 }
 
+/// How control characters in inserted and pasted text are handled
+///
+/// Line feed, carriage return and tab are ordinary text and always kept.
+/// NUL is always removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ControlCharPolicy {
+    /// Removes control characters; vertical tab, form feed and next line
+    /// become line breaks
+    #[default]
+    Strip,
+    /// Replaces control characters with a space; vertical tab, form feed
+    /// and next line become line breaks
+    ReplaceWithSpace,
+    /// Keeps control characters
+    Keep,
+}
+
+impl ControlCharPolicy {
+    /// Applies the policy to `text`, borrowing it if nothing changes
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::validation::ControlCharPolicy;
+    ///
+    /// let text = "a\u{7}b\u{B}c";
+    /// assert_eq!(ControlCharPolicy::Strip.apply(text), "ab\nc");
+    /// assert_eq!(ControlCharPolicy::ReplaceWithSpace.apply(text), "a b\nc");
+    /// assert_eq!(ControlCharPolicy::Keep.apply(text), text);
+    /// ```
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        let needs_change = |c: char| match self {
+            Self::Keep => c == '\0',
+            _ => is_disallowed_control(c),
+        };
+        if !text.chars().any(needs_change) {
+            return Cow::Borrowed(text);
+        }
+
+        let mut result = String::with_capacity(text.len());
+        for c in text.chars() {
+            match (self, c) {
+                (_, '\0') => {}
+                (Self::Keep, _) => result.push(c),
+                (_, '\u{B}' | '\u{C}' | '\u{85}') => result.push('\n'),
+                (Self::ReplaceWithSpace, _) if is_disallowed_control(c) => result.push(' '),
+                (Self::Strip, _) if is_disallowed_control(c) => {}
+                _ => result.push(c),
+            }
+        }
+        Cow::Owned(result)
+    }
+}
+
+/// Returns true for control characters other than line feed, carriage
+/// return and tab, the ones `validate_text_content` rejects
+fn is_disallowed_control(c: char) -> bool {
+    c.is_control() && c != '\n' && c != '\r' && c != '\t'
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -244,4 +304,19 @@ mod tests {
         assert!(validate_text_content("\r").is_ok()); // carriage return
         assert!(validate_text_content("\r\n").is_ok()); // CRLF
     }
+
+    #[test]
+    fn test_control_char_policy() {
+        assert!(matches!(
+            ControlCharPolicy::Strip.apply("plain\ttext\r\n"),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(ControlCharPolicy::Strip.apply("a\0b\x1bc\x0cd"), "abc\nd");
+        assert_eq!(
+            ControlCharPolicy::ReplaceWithSpace.apply("a\0b\x1bc\u{85}d"),
+            "ab c\nd"
+        );
+        assert_eq!(ControlCharPolicy::Keep.apply("a\0b\x1bc"), "ab\x1bc");
+        assert!(validate_text_content(&ControlCharPolicy::Strip.apply("\x07\x0b")).is_ok());
+    }
 }
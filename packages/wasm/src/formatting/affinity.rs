@@ -0,0 +1,110 @@
+//! Format affinity at run boundaries
+//!
+//! Text inserted where a formatted run starts or ends could belong to the
+//! run or not. Typing after a bold word usually continues the bold, but
+//! typing after a link usually should not lengthen the link. Each format
+//! type has an affinity that decides which side, if any, text inserted at
+//! its edge takes the format from. Text inserted inside a run always gets
+//! the run's formats.
+
+use crate::formatting::inline::InlineFormat;
+use std::collections::{HashMap, HashSet};
+use std::mem::{Discriminant, discriminant};
+
+/// Which neighbor text inserted at the edge of a format takes it from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FormatAffinity {
+    /// Text inserted at the end of a run extends it
+    #[default]
+    Left,
+    /// Text inserted at the start of a run extends it
+    Right,
+    /// Text inserted at either edge of a run stays outside it
+    Neither,
+}
+
+/// The affinity of each format type
+///
+/// Format types compare by kind, so all links share one affinity whatever
+/// their URL. Types without a setting have `FormatAffinity::Left`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatAffinities {
+    affinities: HashMap<Discriminant<InlineFormat>, FormatAffinity>,
+}
+
+impl FormatAffinities {
+    /// Creates settings where every format type has `FormatAffinity::Left`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the affinity of the type of `format`
+    pub fn get(&self, format: &InlineFormat) -> FormatAffinity {
+        self.affinities
+            .get(&discriminant(format))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sets the affinity of the type of `format`
+    pub fn set(&mut self, format: &InlineFormat, affinity: FormatAffinity) {
+        if affinity == FormatAffinity::default() {
+            self.affinities.remove(&discriminant(format));
+        } else {
+            self.affinities.insert(discriminant(format), affinity);
+        }
+    }
+
+    /// Returns the formats text inserted between two runs gets
+    ///
+    /// A format on both sides is kept whatever its affinity, since the
+    /// text is not at its edge.
+    pub(crate) fn inherited(
+        &self,
+        left: &HashSet<InlineFormat>,
+        right: &HashSet<InlineFormat>,
+    ) -> HashSet<InlineFormat> {
+        let from_left = left
+            .iter()
+            .filter(|format| right.contains(format) || self.get(format) == FormatAffinity::Left);
+        let from_right = right
+            .iter()
+            .filter(|format| !left.contains(format) && self.get(format) == FormatAffinity::Right);
+        from_left.chain(from_right).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inherited_formats_follow_affinity() {
+        let mut affinities = FormatAffinities::new();
+        affinities.set(
+            &InlineFormat::Link { url: String::new() },
+            FormatAffinity::Neither,
+        );
+        affinities.set(&InlineFormat::Code, FormatAffinity::Right);
+
+        let link = InlineFormat::Link {
+            url: "https://example.com".to_string(),
+        };
+        assert_eq!(affinities.get(&link), FormatAffinity::Neither);
+        assert_eq!(affinities.get(&InlineFormat::Bold), FormatAffinity::Left);
+
+        let left = HashSet::from([InlineFormat::Bold, link.clone(), InlineFormat::Code]);
+        let right = HashSet::from([InlineFormat::Italic, InlineFormat::Code]);
+        assert_eq!(
+            affinities.inherited(&left, &right),
+            HashSet::from([InlineFormat::Bold, InlineFormat::Code])
+        );
+        assert_eq!(
+            affinities.inherited(&HashSet::new(), &left),
+            HashSet::from([InlineFormat::Code])
+        );
+
+        affinities.set(&InlineFormat::Code, FormatAffinity::Left);
+        assert_eq!(affinities.get(&InlineFormat::Code), FormatAffinity::Left);
+    }
+}
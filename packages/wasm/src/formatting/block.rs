@@ -1,7 +1,21 @@
-use serde::{Deserialize, Serialize};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Deepest block quote nesting the editor keeps
+pub const MAX_QUOTE_DEPTH: u8 = 16;
+
+/// Deepest list nesting level the editor keeps, where 0 is a top-level item
+pub const MAX_LIST_INDENT: u8 = 8;
+
+/// Largest paragraph left indent the editor keeps, in indent steps
+pub const MAX_BLOCK_INDENT: u8 = 8;
+
+/// Longest code block language name the editor keeps
+pub const MAX_CODE_LANGUAGE_LENGTH: usize = 32;
 
 /// Represents block-level formatting types
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub enum BlockType {
     /// Standard paragraph block
     Paragraph,
@@ -11,10 +25,18 @@ pub enum BlockType {
     BulletList,
     /// Ordered (numbered) list item
     NumberedList,
-    /// Block quote
-    BlockQuote,
-    /// Code block
-    CodeBlock,
+    /// Block quote with an optional citation URL and nesting depth (1 = top level)
+    BlockQuote {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cite: Option<String>,
+        #[serde(default = "default_quote_depth")]
+        depth: u8,
+    },
+    /// Code block with an optional language for syntax highlighting
+    CodeBlock {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+    },
 }
 
 impl BlockType {
@@ -40,6 +62,149 @@ impl BlockType {
             _ => None,
         }
     }
+
+    /// Creates a top-level BlockQuote without a citation
+    pub fn block_quote() -> Self {
+        BlockType::BlockQuote {
+            cite: None,
+            depth: 1,
+        }
+    }
+
+    /// Creates a BlockQuote with the given citation and nesting depth
+    /// The depth is clamped to the range 1 to `MAX_QUOTE_DEPTH`
+    pub fn block_quote_with(cite: Option<String>, depth: u8) -> Self {
+        BlockType::BlockQuote {
+            cite,
+            depth: depth.clamp(1, MAX_QUOTE_DEPTH),
+        }
+    }
+
+    /// Returns true if this is a BlockQuote block type
+    pub fn is_block_quote(&self) -> bool {
+        matches!(self, BlockType::BlockQuote { .. })
+    }
+
+    /// Returns the nesting depth if this is a BlockQuote, None otherwise
+    pub fn quote_depth(&self) -> Option<u8> {
+        match self {
+            BlockType::BlockQuote { depth, .. } => Some(*depth),
+            _ => None,
+        }
+    }
+
+    /// Returns the citation URL if this is a BlockQuote with one, None otherwise
+    pub fn quote_cite(&self) -> Option<&str> {
+        match self {
+            BlockType::BlockQuote { cite, .. } => cite.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Creates a CodeBlock without a language
+    pub fn code_block() -> Self {
+        BlockType::CodeBlock { language: None }
+    }
+
+    /// Creates a CodeBlock with the given language, such as "rust"
+    ///
+    /// Only the first word of the language is kept, as in a Markdown info
+    /// string. Languages that are empty, longer than
+    /// `MAX_CODE_LANGUAGE_LENGTH` or contain characters other than letters,
+    /// digits and `+-#._` are dropped, so the name is safe to use in a
+    /// class attribute.
+    pub fn code_block_with(language: Option<&str>) -> Self {
+        let language = language
+            .and_then(|language| language.split_whitespace().next())
+            .filter(|language| is_valid_code_language(language))
+            .map(str::to_string);
+        BlockType::CodeBlock { language }
+    }
+
+    /// Returns true if this is a CodeBlock block type
+    pub fn is_code_block(&self) -> bool {
+        matches!(self, BlockType::CodeBlock { .. })
+    }
+
+    /// Returns the language if this is a CodeBlock with one, None otherwise
+    pub fn code_language(&self) -> Option<&str> {
+        match self {
+            BlockType::CodeBlock { language } => language.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Checks that a code block language is a short name without markup
+fn is_valid_code_language(language: &str) -> bool {
+    !language.is_empty()
+        && language.len() <= MAX_CODE_LANGUAGE_LENGTH
+        && language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-#._".contains(c))
+}
+
+fn default_quote_depth() -> u8 {
+    1
+}
+
+impl Serialize for BlockType {
+    /// Writes a code block without a language as the bare `"CodeBlock"`
+    /// string, so documents without languages read the same as before
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            BlockType::CodeBlock { language: None } => {
+                serializer.serialize_unit_variant("BlockType", 5, "CodeBlock")
+            }
+            _ => BlockType::serialize(self, serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockType {
+    /// Accepts the current representation as well as the bare `"BlockQuote"`
+    /// and `"CodeBlock"` strings written before those blocks carried
+    /// attributes
+    ///
+    /// Heading levels outside 1-6, quote depths outside 1 to
+    /// `MAX_QUOTE_DEPTH` and invalid code block languages are rejected.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        enum LegacyBlockType {
+            BlockQuote,
+            CodeBlock,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Current(#[serde(with = "BlockType")] BlockType),
+            Legacy(LegacyBlockType),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Current(BlockType::Heading { level }) if !(1..=6).contains(&level) => {
+                Err(D::Error::custom(format!("invalid heading level {}", level)))
+            }
+            Repr::Current(BlockType::BlockQuote { depth, .. })
+                if !(1..=MAX_QUOTE_DEPTH).contains(&depth) =>
+            {
+                Err(D::Error::custom(format!(
+                    "invalid block quote depth {}",
+                    depth
+                )))
+            }
+            Repr::Current(BlockType::CodeBlock {
+                language: Some(language),
+            }) if !is_valid_code_language(&language) => Err(D::Error::custom(format!(
+                "invalid code block language '{}'",
+                language
+            ))),
+            Repr::Current(block_type) => Ok(block_type),
+            Repr::Legacy(LegacyBlockType::BlockQuote) => Ok(BlockType::block_quote()),
+            Repr::Legacy(LegacyBlockType::CodeBlock) => Ok(BlockType::code_block()),
+        }
+    }
 }
 
 impl Default for BlockType {
@@ -48,6 +213,139 @@ impl Default for BlockType {
     }
 }
 
+/// Base direction of the text in a block, for right-to-left scripts such
+/// as Arabic and Hebrew
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextDirection {
+    /// Left to right
+    Ltr,
+    /// Right to left
+    Rtl,
+}
+
+impl TextDirection {
+    /// Returns the HTML `dir` attribute value for this direction
+    pub fn html_value(&self) -> &'static str {
+        match self {
+            TextDirection::Ltr => "ltr",
+            TextDirection::Rtl => "rtl",
+        }
+    }
+
+    /// Parses an HTML `dir` attribute value, ignoring case
+    ///
+    /// Returns None for `auto` and unknown values.
+    pub fn from_html_value(value: &str) -> Option<Self> {
+        if value.trim().eq_ignore_ascii_case("ltr") {
+            Some(TextDirection::Ltr)
+        } else if value.trim().eq_ignore_ascii_case("rtl") {
+            Some(TextDirection::Rtl)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true for right-to-left text
+    pub fn is_rtl(&self) -> bool {
+        *self == TextDirection::Rtl
+    }
+}
+
+/// Marker style of a list, overriding the default bullet or decimal number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ListStyle {
+    /// Decimal numbers (1, 2, 3)
+    Decimal,
+    /// Lowercase letters (a, b, c)
+    LowerAlpha,
+    /// Uppercase letters (A, B, C)
+    UpperAlpha,
+    /// Lowercase roman numerals (i, ii, iii)
+    LowerRoman,
+    /// Uppercase roman numerals (I, II, III)
+    UpperRoman,
+    /// The same character before every item
+    Bullet(char),
+}
+
+impl ListStyle {
+    /// Returns true if items are numbered rather than marked with a bullet
+    pub fn is_numbered(&self) -> bool {
+        !matches!(self, ListStyle::Bullet(_))
+    }
+
+    /// Formats the number of the `number`th item (counting from 1)
+    ///
+    /// Letters continue as `z`, `aa`, `ab`, like CSS counters. Roman
+    /// numerals above 3999 fall back to decimal.
+    pub fn format_number(&self, number: usize) -> String {
+        match self {
+            ListStyle::Decimal => number.to_string(),
+            ListStyle::LowerAlpha => alphabetic(number),
+            ListStyle::UpperAlpha => alphabetic(number).to_uppercase(),
+            ListStyle::LowerRoman => roman(number),
+            ListStyle::UpperRoman => roman(number).to_uppercase(),
+            ListStyle::Bullet(bullet) => bullet.to_string(),
+        }
+    }
+
+    /// Returns the CSS `list-style-type` value for this style
+    pub fn css_value(&self) -> String {
+        match self {
+            ListStyle::Decimal => "decimal".to_string(),
+            ListStyle::LowerAlpha => "lower-alpha".to_string(),
+            ListStyle::UpperAlpha => "upper-alpha".to_string(),
+            ListStyle::LowerRoman => "lower-roman".to_string(),
+            ListStyle::UpperRoman => "upper-roman".to_string(),
+            // A CSS string, followed by a space to separate it from the text
+            ListStyle::Bullet('\'') => "'\\' '".to_string(),
+            ListStyle::Bullet('\\') => "'\\\\ '".to_string(),
+            ListStyle::Bullet(bullet) => format!("'{} '", bullet),
+        }
+    }
+}
+
+fn alphabetic(number: usize) -> String {
+    let mut letters = Vec::new();
+    let mut n = number.max(1);
+    while n > 0 {
+        n -= 1;
+        letters.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+fn roman(number: usize) -> String {
+    if !(1..=3999).contains(&number) {
+        return number.to_string();
+    }
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut n = number;
+    let mut result = String::new();
+    for (value, numeral) in NUMERALS {
+        while n >= value {
+            result.push_str(numeral);
+            n -= value;
+        }
+    }
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -119,4 +417,106 @@ mod tests {
         assert_ne!(BlockType::heading(1), BlockType::heading(2));
         assert_ne!(BlockType::Paragraph, BlockType::BulletList);
     }
+
+    #[test]
+    fn test_block_quote_attributes() {
+        let quote = BlockType::block_quote_with(Some("https://example.com".to_string()), 2);
+        assert!(quote.is_block_quote());
+        assert_eq!(quote.quote_depth(), Some(2));
+        assert_eq!(quote.quote_cite(), Some("https://example.com"));
+
+        assert_eq!(BlockType::block_quote().quote_depth(), Some(1));
+        assert_eq!(BlockType::block_quote().quote_cite(), None);
+        assert_eq!(BlockType::Paragraph.quote_depth(), None);
+    }
+
+    #[test]
+    fn test_block_quote_depth_is_clamped() {
+        assert_eq!(BlockType::block_quote_with(None, 0).quote_depth(), Some(1));
+        assert_eq!(
+            BlockType::block_quote_with(None, u8::MAX).quote_depth(),
+            Some(MAX_QUOTE_DEPTH)
+        );
+    }
+
+    #[test]
+    fn test_invalid_block_types_rejected() {
+        for json in [
+            r#"{"BlockQuote":{"depth":0}}"#,
+            r#"{"BlockQuote":{"depth":200}}"#,
+            r#"{"Heading":{"level":0}}"#,
+            r#"{"Heading":{"level":7}}"#,
+            r#"{"CodeBlock":{"language":"<script>"}}"#,
+        ] {
+            assert!(serde_json::from_str::<BlockType>(json).is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn test_block_quote_serde() {
+        let quote = BlockType::block_quote_with(Some("https://example.com".to_string()), 2);
+        let json = serde_json::to_string(&quote).unwrap();
+        assert_eq!(
+            json,
+            r#"{"BlockQuote":{"cite":"https://example.com","depth":2}}"#
+        );
+        assert_eq!(serde_json::from_str::<BlockType>(&json).unwrap(), quote);
+
+        let heading: BlockType = serde_json::from_str(r#"{"Heading":{"level":2}}"#).unwrap();
+        assert_eq!(heading, BlockType::heading(2));
+    }
+
+    #[test]
+    fn test_legacy_block_quote_deserializes() {
+        let quote: BlockType = serde_json::from_str(r#""BlockQuote""#).unwrap();
+        assert_eq!(quote, BlockType::block_quote());
+
+        let quote: BlockType = serde_json::from_str(r#"{"BlockQuote":{}}"#).unwrap();
+        assert_eq!(quote, BlockType::block_quote());
+    }
+
+    #[test]
+    fn test_code_block_language() {
+        let code = BlockType::code_block_with(Some(" rust ignore"));
+        assert_eq!(code.code_language(), Some("rust"));
+        assert!(code.is_code_block());
+        let json = serde_json::to_string(&code).unwrap();
+        assert_eq!(json, r#"{"CodeBlock":{"language":"rust"}}"#);
+        assert_eq!(serde_json::from_str::<BlockType>(&json).unwrap(), code);
+
+        assert_eq!(
+            BlockType::code_block_with(Some("a\"onclick")),
+            BlockType::code_block()
+        );
+        assert_eq!(
+            BlockType::code_block_with(Some("c++")).code_language(),
+            Some("c++")
+        );
+        assert_eq!(
+            serde_json::to_string(&BlockType::code_block()).unwrap(),
+            r#""CodeBlock""#
+        );
+        let legacy: BlockType = serde_json::from_str(r#""CodeBlock""#).unwrap();
+        assert_eq!(legacy, BlockType::code_block());
+    }
+
+    #[test]
+    fn test_list_style_format_number() {
+        assert_eq!(ListStyle::Decimal.format_number(12), "12");
+        assert_eq!(ListStyle::LowerAlpha.format_number(1), "a");
+        assert_eq!(ListStyle::LowerAlpha.format_number(26), "z");
+        assert_eq!(ListStyle::UpperAlpha.format_number(28), "AB");
+        assert_eq!(ListStyle::LowerRoman.format_number(4), "iv");
+        assert_eq!(ListStyle::UpperRoman.format_number(1994), "MCMXCIV");
+        assert_eq!(ListStyle::LowerRoman.format_number(4000), "4000");
+        assert_eq!(ListStyle::Bullet('–').format_number(3), "–");
+        assert!(!ListStyle::Bullet('–').is_numbered());
+    }
+
+    #[test]
+    fn test_list_style_css_value() {
+        assert_eq!(ListStyle::LowerRoman.css_value(), "lower-roman");
+        assert_eq!(ListStyle::Bullet('→').css_value(), "'→ '");
+        assert_eq!(ListStyle::Bullet('\'').css_value(), r"'\' '");
+    }
 }
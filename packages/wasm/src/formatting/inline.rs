@@ -19,6 +19,39 @@ pub enum InlineFormat {
     TextColor { color: String },
     /// Background color with color value (e.g., "#FFFF00" or "yellow")
     BackgroundColor { color: String },
+    /// Semantic highlight (`<mark>`), independent of any background color
+    Highlight,
+    /// Small capitals
+    SmallCaps,
+    /// Subscript, lowered below the baseline
+    Subscript,
+    /// Superscript, raised above the baseline
+    Superscript,
+    /// A math formula whose text is the LaTeX source, shown on its own
+    /// line when `display` is set (`$$...$$` rather than `$...$`)
+    Math {
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        display: bool,
+    },
+}
+
+impl InlineFormat {
+    /// Returns true if applying `self` removes `other` from the same text
+    ///
+    /// Subscript and superscript are mutually exclusive, and so are inline
+    /// and display math.
+    pub fn excludes(&self, other: &InlineFormat) -> bool {
+        match (self, other) {
+            (InlineFormat::Math { display }, InlineFormat::Math { display: other }) => {
+                display != other
+            }
+            _ => matches!(
+                (self, other),
+                (InlineFormat::Subscript, InlineFormat::Superscript)
+                    | (InlineFormat::Superscript, InlineFormat::Subscript)
+            ),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -59,6 +92,24 @@ mod tests {
         assert_ne!(text_color, bg_color);
     }
 
+    #[test]
+    fn test_highlight_distinct_from_background_color() {
+        let bg_color = InlineFormat::BackgroundColor {
+            color: "yellow".to_string(),
+        };
+
+        assert_ne!(InlineFormat::Highlight, bg_color);
+        assert_ne!(InlineFormat::Highlight, InlineFormat::SmallCaps);
+    }
+
+    #[test]
+    fn test_subscript_excludes_superscript() {
+        assert!(InlineFormat::Subscript.excludes(&InlineFormat::Superscript));
+        assert!(InlineFormat::Superscript.excludes(&InlineFormat::Subscript));
+        assert!(!InlineFormat::Subscript.excludes(&InlineFormat::Subscript));
+        assert!(!InlineFormat::Bold.excludes(&InlineFormat::Superscript));
+    }
+
     #[test]
     fn test_inline_format_clone() {
         let format = InlineFormat::Bold;
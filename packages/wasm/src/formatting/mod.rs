@@ -10,20 +10,30 @@
 //! - Define block types (paragraphs, headings, lists, quotes, code blocks)
 //! - Store and query format information efficiently
 //! - Adjust formats when text is inserted or deleted
+//! - Decide which formats text inserted at a run boundary takes
 //! - Merge adjacent format runs with identical formatting
+//! - Report how fragmented the format runs are
 //!
 //! # Key Types
 //!
 //! - `InlineFormat`: Enum representing inline formatting options
 //! - `BlockType`: Enum representing block-level structure types
+//! - `ListStyle`: Enum representing list marker styles
+//! - `FormatAffinity`: Whether text inserted at a run boundary extends the run
 //! - `FormatStorage`: Run-based storage for efficient format queries
 //! - `FormatRun`: A contiguous range of text with the same formatting
+//! - `FragmentationStats`: How finely the formatting is split into runs
 
+pub mod affinity;
 pub mod block;
 pub mod inline;
 pub mod storage;
 
 // Re-export commonly used types
-pub use block::BlockType;
+pub use affinity::{FormatAffinities, FormatAffinity};
+pub use block::{BlockType, ListStyle, TextDirection};
 pub use inline::InlineFormat;
-pub use storage::{FormatRun, FormatStorage};
+pub use storage::{
+    DEFAULT_FRAGMENTATION_THRESHOLD, FormatRun, FormatStorage, FragmentationStats,
+    MIN_FRAGMENTED_RUNS,
+};
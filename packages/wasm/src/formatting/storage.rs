@@ -1,5 +1,8 @@
 use crate::document::{Position, Range};
-use crate::formatting::block::BlockType;
+use crate::formatting::affinity::FormatAffinities;
+use crate::formatting::block::{
+    BlockType, ListStyle, MAX_BLOCK_INDENT, MAX_LIST_INDENT, TextDirection,
+};
 use crate::formatting::inline::InlineFormat;
 use crate::utils::interner::StringInterner;
 use std::collections::HashSet;
@@ -28,11 +31,58 @@ impl FormatRun {
     }
 }
 
+/// How finely the inline formatting is split into runs
+///
+/// Every run is stored and searched separately, so documents with many
+/// short runs get slower to edit and serialize.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FragmentationStats {
+    /// Number of format runs
+    pub run_count: usize,
+    /// Format runs per 1000 characters of text
+    pub runs_per_thousand_chars: f64,
+    /// Average number of characters in a run
+    pub average_run_length: f64,
+    /// Number of times two runs have been merged into one
+    pub merge_count: u64,
+}
+
+impl FragmentationStats {
+    /// Returns true if there are more than `threshold` runs per 1000
+    /// characters, ignoring documents with fewer than
+    /// `MIN_FRAGMENTED_RUNS` runs
+    pub fn exceeds(&self, threshold: f64) -> bool {
+        self.run_count >= MIN_FRAGMENTED_RUNS && self.runs_per_thousand_chars > threshold
+    }
+}
+
+/// Default fragmentation threshold, in format runs per 1000 characters
+pub const DEFAULT_FRAGMENTATION_THRESHOLD: f64 = 200.0;
+
+/// Fewest format runs a document needs before it counts as fragmented;
+/// small documents are fast however their formatting is split
+pub const MIN_FRAGMENTED_RUNS: usize = 256;
+
 /// Represents block-level formatting information at a specific position
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BlockInfo {
     pub start_offset: usize,
     pub block_type: BlockType,
+    /// List nesting level, where 0 is a top-level item; always 0 for non-list blocks
+    pub indent: u8,
+    /// Marker style of a list item, or None for the default; always None for
+    /// non-list blocks
+    pub list_style: Option<ListStyle>,
+    /// Number a numbered list item restarts its list at, or None to
+    /// continue the count; always None for other blocks
+    pub list_start: Option<usize>,
+    /// Left indent of a paragraph in indent steps; always 0 for other blocks
+    pub block_indent: u8,
+    /// Whether the first line of a paragraph is indented; always false for
+    /// other blocks
+    pub first_line_indent: bool,
+    /// Base text direction, or None to detect it from the text
+    pub direction: Option<TextDirection>,
 }
 
 impl BlockInfo {
@@ -41,8 +91,92 @@ impl BlockInfo {
         Self {
             start_offset,
             block_type,
+            indent: 0,
+            list_style: None,
+            list_start: None,
+            block_indent: 0,
+            first_line_indent: false,
+            direction: None,
+        }
+    }
+
+    /// Sets the list nesting level, clamped to `MAX_LIST_INDENT`
+    /// Non-list blocks always stay at level 0
+    pub fn with_indent(mut self, indent: u8) -> Self {
+        self.indent = if is_list(&self.block_type) {
+            indent.min(MAX_LIST_INDENT)
+        } else {
+            0
+        };
+        self
+    }
+
+    /// Sets the list marker style
+    /// Non-list blocks always keep the default style
+    pub fn with_list_style(mut self, list_style: Option<ListStyle>) -> Self {
+        self.list_style = list_style.filter(|_| is_list(&self.block_type));
+        self
+    }
+
+    /// Sets the number a numbered list item restarts its list at
+    /// Other blocks always continue the count
+    pub fn with_list_start(mut self, list_start: Option<usize>) -> Self {
+        self.list_start = list_start.filter(|_| self.block_type == BlockType::NumberedList);
+        self
+    }
+
+    /// Sets the paragraph left indent, clamped to `MAX_BLOCK_INDENT`
+    /// Other blocks always stay at 0
+    pub fn with_block_indent(mut self, block_indent: u8) -> Self {
+        self.block_indent = if self.block_type == BlockType::Paragraph {
+            block_indent.min(MAX_BLOCK_INDENT)
+        } else {
+            0
+        };
+        self
+    }
+
+    /// Sets whether the first line of a paragraph is indented
+    /// Other blocks are never indented
+    pub fn with_first_line_indent(mut self, first_line_indent: bool) -> Self {
+        self.first_line_indent = first_line_indent && self.block_type == BlockType::Paragraph;
+        self
+    }
+
+    /// Sets the base text direction, or None to detect it from the text
+    pub fn with_direction(mut self, direction: Option<TextDirection>) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Returns a copy of the block starting at `offset`, for the part of it
+    /// from there on
+    ///
+    /// A list start belongs to the first line of the block, so the copy
+    /// continues the count unless it starts at the same line.
+    pub(crate) fn split_at(&self, offset: usize) -> BlockInfo {
+        let mut block = self.clone();
+        if offset != block.start_offset {
+            block.list_start = None;
         }
+        block.start_offset = offset;
+        block
     }
+
+    /// Returns true if `other` has the same type and attributes
+    pub(crate) fn same_attributes(&self, other: &BlockInfo) -> bool {
+        self.block_type == other.block_type
+            && self.indent == other.indent
+            && self.list_style == other.list_style
+            && self.list_start == other.list_start
+            && self.block_indent == other.block_indent
+            && self.first_line_indent == other.first_line_indent
+            && self.direction == other.direction
+    }
+}
+
+fn is_list(block_type: &BlockType) -> bool {
+    matches!(block_type, BlockType::BulletList | BlockType::NumberedList)
 }
 
 /// Cache entry for format queries
@@ -98,6 +232,8 @@ pub struct FormatStorage {
     blocks: Vec<BlockInfo>,
     format_cache: Option<FormatCache>,
     string_interner: StringInterner,
+    /// Number of times two runs have been merged into one
+    merge_count: u64,
 }
 
 impl FormatStorage {
@@ -108,6 +244,7 @@ impl FormatStorage {
             blocks: vec![BlockInfo::new(0, BlockType::Paragraph)],
             format_cache: None,
             string_interner: StringInterner::new(),
+            merge_count: 0,
         }
     }
 
@@ -136,6 +273,7 @@ impl FormatStorage {
         for run in &mut self.runs {
             let run_range = run.range.normalize();
             if run_range.overlaps(&normalized) {
+                run.formats.retain(|existing| !format.excludes(existing));
                 run.formats.insert(format.clone());
                 covered_ranges.push(run.range);
             }
@@ -334,6 +472,56 @@ impl FormatStorage {
         self.runs.len()
     }
 
+    /// Returns how fragmented the inline formatting of `text_length`
+    /// characters is
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::formatting::FormatStorage;
+    /// use rte_core::formatting::InlineFormat;
+    /// use rte_core::document::Range;
+    ///
+    /// let mut storage = FormatStorage::new();
+    /// storage.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+    /// let stats = storage.fragmentation_stats(1000);
+    /// assert_eq!(stats.run_count, 1);
+    /// assert_eq!(stats.average_run_length, 5.0);
+    /// ```
+    pub fn fragmentation_stats(&self, text_length: usize) -> FragmentationStats {
+        let run_count = self.runs.len();
+        let formatted: usize = self.runs.iter().map(|run| run.range.len()).sum();
+        FragmentationStats {
+            run_count,
+            runs_per_thousand_chars: if text_length == 0 {
+                0.0
+            } else {
+                run_count as f64 * 1000.0 / text_length as f64
+            },
+            average_run_length: if run_count == 0 {
+                0.0
+            } else {
+                formatted as f64 / run_count as f64
+            },
+            merge_count: self.merge_count,
+        }
+    }
+
+    /// Merges touching runs with identical formats and drops empty ones
+    ///
+    /// Applying and removing formats keeps runs merged, but deleting the
+    /// text between two runs leaves them split.
+    ///
+    /// # Returns
+    /// The number of runs removed
+    pub fn normalize(&mut self) -> usize {
+        let before = self.runs.len();
+        self.invalidate_cache();
+        self.runs
+            .retain(|run| !run.is_empty() && !run.range.is_empty());
+        self.merge_adjacent_runs();
+        before - self.runs.len()
+    }
+
     /// Returns the number of block info entries
     pub fn block_count(&self) -> usize {
         self.blocks.len()
@@ -344,6 +532,55 @@ impl FormatStorage {
         &self.runs
     }
 
+    /// Returns the styled segments covering a range, in document order
+    ///
+    /// Walks the format runs overlapping `range` once and yields contiguous
+    /// segments with the formats active over each one. Unformatted gaps are
+    /// reported with an empty set, and neighbouring segments with identical
+    /// formats are coalesced, so every boundary is a real format change.
+    ///
+    /// # Performance
+    /// O(log m + r) where m is the total number of runs and r is the number
+    /// of runs overlapping the range, independent of the range length.
+    pub fn styled_runs(&self, range: Range) -> Vec<(Range, HashSet<InlineFormat>)> {
+        let normalized = range.normalize();
+        let start = normalized.start_offset();
+        let end = normalized.end_offset();
+        let mut segments: Vec<(Range, HashSet<InlineFormat>)> = Vec::new();
+
+        if start == end {
+            return segments;
+        }
+
+        // Runs are kept sorted and non-overlapping, so binary search for the first one
+        let first = self
+            .runs
+            .partition_point(|run| run.range.end_offset() <= start);
+        let overlapping = self.runs[first..]
+            .iter()
+            .take_while(|run| run.range.start_offset() < end);
+
+        let mut cursor = start;
+        for run in overlapping {
+            let run_start = run.range.start_offset().max(cursor);
+            let run_end = run.range.end_offset().min(end);
+            if run_end <= run_start {
+                continue;
+            }
+            if run_start > cursor {
+                push_segment(&mut segments, cursor, run_start, &HashSet::new());
+            }
+            push_segment(&mut segments, run_start, run_end, &run.formats);
+            cursor = run_end;
+        }
+
+        if cursor < end {
+            push_segment(&mut segments, cursor, end, &HashSet::new());
+        }
+
+        segments
+    }
+
     /// Clears all format runs
     pub fn clear(&mut self) {
         self.runs.clear();
@@ -362,7 +599,6 @@ impl FormatStorage {
 
         // Find all blocks that overlap with the range
         let mut blocks_to_update = Vec::new();
-        let mut blocks_to_remove = Vec::new();
 
         for (idx, block) in self.blocks.iter().enumerate() {
             // Find the end of this block (start of next block or document end)
@@ -374,45 +610,65 @@ impl FormatStorage {
 
             // Check if this block overlaps with the range
             if block.start_offset < end_offset && block_end > start_offset {
-                if block.start_offset >= start_offset && block_end <= end_offset {
-                    // Block is completely within range - mark for removal
-                    blocks_to_remove.push(idx);
-                } else if block.start_offset < start_offset && block_end > end_offset {
+                if block.start_offset < start_offset && block_end > end_offset {
                     // Range is completely within block - need to split
-                    blocks_to_update.push((idx, block.start_offset, block.block_type.clone()));
+                    blocks_to_update.push((idx, block.start_offset, block.clone()));
                 } else if block.start_offset < start_offset {
                     // Block starts before range - keep the part before
-                    blocks_to_update.push((idx, block.start_offset, block.block_type.clone()));
+                    blocks_to_update.push((idx, block.start_offset, block.clone()));
                 }
             }
         }
 
-        // Remove blocks that are completely within the range (in reverse order)
-        for idx in blocks_to_remove.iter().rev() {
-            self.blocks.remove(*idx);
-        }
+        let start_block = self.retyped_block(start_offset, &block_type);
+        let direction = start_block.direction;
 
-        // Remove any blocks that start within the range
-        self.blocks
-            .retain(|b| b.start_offset < start_offset || b.start_offset >= end_offset);
+        // Remove any blocks that start within the range, except ones that
+        // already have the type, list items that stay list items, which
+        // only change type, and lines with a different direction
+        self.blocks.retain_mut(|b| {
+            // Checked first so an empty range replaces the block it starts
+            if b.start_offset == start_offset {
+                return false;
+            }
+            if b.start_offset < start_offset || b.start_offset >= end_offset {
+                return true;
+            }
+            if b.block_type == block_type {
+                return true;
+            }
+            if is_list(&block_type) && is_list(&b.block_type) {
+                b.block_type = block_type.clone();
+                b.list_style = None;
+                b.list_start = None;
+                return true;
+            }
+            if b.direction != direction {
+                *b = BlockInfo::new(b.start_offset, block_type.clone()).with_direction(b.direction);
+                return true;
+            }
+            false
+        });
 
         // Add the new block at the start of the range
-        self.blocks.push(BlockInfo::new(start_offset, block_type));
+        self.blocks.push(start_block);
 
         // If the range doesn't extend to the end, add a block after it
         // to restore the original block type (if there was one)
-        if let Some((_, _original_offset, original_type)) = blocks_to_update.first() {
+        if let Some((_, _original_offset, original)) = blocks_to_update.first() {
             if end_offset < usize::MAX {
                 // Check if there's already a block at end_offset
                 if !self.blocks.iter().any(|b| b.start_offset == end_offset) {
-                    self.blocks
-                        .push(BlockInfo::new(end_offset, original_type.clone()));
+                    self.blocks.push(original.split_at(end_offset));
                 }
             }
         }
 
-        // Sort blocks by start offset
+        // Sort blocks by start offset and drop lines that kept attributes
+        // identical to the new block before them
         self.blocks.sort_by_key(|b| b.start_offset);
+        self.blocks
+            .dedup_by(|next, previous| next.same_attributes(previous));
 
         // Ensure there's always a block at offset 0
         if self.blocks.is_empty() || self.blocks[0].start_offset != 0 {
@@ -421,6 +677,97 @@ impl FormatStorage {
         }
     }
 
+    /// Sets the block type of each line starting at `line_starts`, giving
+    /// every line its own block
+    ///
+    /// `end` is where the line after the last one starts, if there is one;
+    /// it keeps its current block. Each line keeps its own attributes the
+    /// way `set_block_type` keeps them for the line a range starts on.
+    pub fn set_line_block_types(
+        &mut self,
+        line_starts: &[usize],
+        end: Option<usize>,
+        block_type: BlockType,
+    ) {
+        let Some(&first) = line_starts.first() else {
+            return;
+        };
+        self.invalidate_cache();
+
+        let mut lines: Vec<BlockInfo> = line_starts
+            .iter()
+            .map(|&line_start| self.retyped_block(line_start, &block_type))
+            .collect();
+        if let Some(end) = end
+            && !self.blocks.iter().any(|b| b.start_offset == end)
+            && let Some(block) = self.block_at(end)
+        {
+            lines.push(block.split_at(end));
+        }
+
+        self.blocks
+            .retain(|b| b.start_offset < first || end.is_some_and(|end| b.start_offset >= end));
+        self.blocks.extend(lines);
+        self.blocks.sort_by_key(|b| b.start_offset);
+    }
+
+    /// Returns the block of each line starting at `line_starts`, as it
+    /// applies to that line alone
+    pub(crate) fn line_blocks(&self, line_starts: &[usize]) -> Vec<BlockInfo> {
+        line_starts
+            .iter()
+            .filter_map(|&line_start| self.block_at(line_start).map(|b| b.split_at(line_start)))
+            .collect()
+    }
+
+    /// Gives consecutive lines the blocks `line_blocks` returned for them
+    ///
+    /// `end` is where the line after the last one starts, if there is one;
+    /// it keeps its current block, and so do all other lines.
+    pub(crate) fn restore_line_blocks(&mut self, lines: &[BlockInfo], end: Option<usize>) {
+        let Some(first) = lines.first().map(|b| b.start_offset) else {
+            return;
+        };
+        self.invalidate_cache();
+
+        let mut lines = lines.to_vec();
+        if let Some(end) = end
+            && !self.blocks.iter().any(|b| b.start_offset == end)
+            && let Some(block) = self.block_at(end)
+        {
+            lines.push(block.split_at(end));
+        }
+        self.blocks
+            .retain(|b| b.start_offset < first || end.is_some_and(|end| b.start_offset >= end));
+        self.blocks.extend(lines);
+        self.blocks.sort_by_key(|b| b.start_offset);
+        self.blocks
+            .dedup_by(|next, previous| next.same_attributes(previous));
+
+        if self.blocks.is_empty() || self.blocks[0].start_offset != 0 {
+            self.blocks
+                .insert(0, BlockInfo::new(0, BlockType::Paragraph));
+        }
+    }
+
+    /// Returns the block the line at `offset` gets when set to `block_type`
+    ///
+    /// Lines that already have the type keep their attributes; switching
+    /// between list types keeps each item's nesting level, and every line
+    /// keeps its text direction.
+    fn retyped_block(&self, offset: usize, block_type: &BlockType) -> BlockInfo {
+        let mut block = match self.block_at(offset) {
+            Some(b) if b.block_type == *block_type => b.split_at(offset),
+            Some(b) if is_list(block_type) => BlockInfo::new(offset, block_type.clone())
+                .with_indent(b.indent)
+                .with_direction(b.direction),
+            Some(b) => BlockInfo::new(offset, block_type.clone()).with_direction(b.direction),
+            None => BlockInfo::new(offset, block_type.clone()),
+        };
+        block.start_offset = offset;
+        block
+    }
+
     /// Gets the block type at the specified position using binary search
     pub fn get_block_type_at(&self, pos: Position) -> BlockType {
         let offset = pos.offset();
@@ -466,6 +813,150 @@ impl FormatStorage {
         }
     }
 
+    /// Gets the list nesting level at the specified position
+    /// Returns 0 outside of lists
+    pub fn get_indent_at(&self, pos: Position) -> u8 {
+        self.block_at(pos.offset()).map(|b| b.indent).unwrap_or(0)
+    }
+
+    /// Sets the list nesting level for the line range `start..end`
+    ///
+    /// `start` must be a line start and `end` the start of the line after
+    /// the range, or None when the range runs to the end of the document.
+    /// Blocks are split at both ends so lines outside the range keep their
+    /// level. Non-list lines are left at level 0.
+    pub fn set_indent(&mut self, start: usize, end: Option<usize>, indent: u8) {
+        self.update_lines(start, end, |block| block.clone().with_indent(indent));
+    }
+
+    /// Gets the list marker style at the specified position
+    /// Returns None for the default style and outside of lists
+    pub fn get_list_style_at(&self, pos: Position) -> Option<ListStyle> {
+        self.block_at(pos.offset()).and_then(|b| b.list_style)
+    }
+
+    /// Sets the list marker style for the line range `start..end`
+    ///
+    /// Takes the same line bounds as `set_indent`. Non-list lines keep the
+    /// default style.
+    pub fn set_list_style(&mut self, start: usize, end: Option<usize>, style: Option<ListStyle>) {
+        self.update_lines(start, end, |block| block.clone().with_list_style(style));
+    }
+
+    /// Gets the number the list item on the line starting at `pos` restarts
+    /// its list at
+    /// Returns None for items continuing their list and outside of lists
+    pub fn get_list_start_at(&self, pos: Position) -> Option<usize> {
+        self.block_at(pos.offset())
+            .filter(|b| b.start_offset == pos.offset())
+            .and_then(|b| b.list_start)
+    }
+
+    /// Sets the number list items restart their list at for the line range
+    /// `start..end`
+    ///
+    /// Takes the same line bounds as `set_indent`. Lines that are not
+    /// numbered list items keep None.
+    pub fn set_list_start(&mut self, start: usize, end: Option<usize>, list_start: Option<usize>) {
+        self.update_lines(start, end, |block| {
+            block.clone().with_list_start(list_start)
+        });
+    }
+
+    /// Gets the paragraph left indent at the specified position, in steps
+    /// Returns 0 outside of paragraphs
+    pub fn get_block_indent_at(&self, pos: Position) -> u8 {
+        self.block_at(pos.offset())
+            .map(|b| b.block_indent)
+            .unwrap_or(0)
+    }
+
+    /// Sets the paragraph left indent for the line range `start..end`
+    ///
+    /// Takes the same line bounds as `set_indent`. Lines that are not
+    /// paragraphs are left at 0.
+    pub fn set_block_indent(&mut self, start: usize, end: Option<usize>, block_indent: u8) {
+        self.update_lines(start, end, |block| {
+            block.clone().with_block_indent(block_indent)
+        });
+    }
+
+    /// Returns true if the paragraph at the specified position has an
+    /// indented first line
+    pub fn has_first_line_indent_at(&self, pos: Position) -> bool {
+        self.block_at(pos.offset())
+            .is_some_and(|b| b.first_line_indent)
+    }
+
+    /// Sets whether the paragraphs in the line range `start..end` have an
+    /// indented first line
+    ///
+    /// Takes the same line bounds as `set_indent`. Lines that are not
+    /// paragraphs are never indented.
+    pub fn set_first_line_indent(&mut self, start: usize, end: Option<usize>, indented: bool) {
+        self.update_lines(start, end, |block| {
+            block.clone().with_first_line_indent(indented)
+        });
+    }
+
+    /// Gets the explicit text direction at the specified position
+    /// Returns None where the direction is detected from the text
+    pub fn get_direction_at(&self, pos: Position) -> Option<TextDirection> {
+        self.block_at(pos.offset()).and_then(|b| b.direction)
+    }
+
+    /// Sets the text direction for the line range `start..end`
+    ///
+    /// Takes the same line bounds as `set_indent`. A `direction` of None
+    /// detects the direction from the text.
+    pub fn set_direction(
+        &mut self,
+        start: usize,
+        end: Option<usize>,
+        direction: Option<TextDirection>,
+    ) {
+        self.update_lines(start, end, |block| block.clone().with_direction(direction));
+    }
+
+    /// Splits blocks at the line range `start..end` and replaces each block
+    /// inside it with `update(block)`
+    fn update_lines(
+        &mut self,
+        start: usize,
+        end: Option<usize>,
+        update: impl Fn(&BlockInfo) -> BlockInfo,
+    ) {
+        self.invalidate_cache();
+
+        if let Some(end) = end
+            && !self.blocks.iter().any(|b| b.start_offset == end)
+            && let Some(block) = self.block_at(end)
+        {
+            self.blocks.push(block.split_at(end));
+        }
+        if !self.blocks.iter().any(|b| b.start_offset == start)
+            && let Some(block) = self.block_at(start)
+        {
+            self.blocks.push(block.split_at(start));
+        }
+        self.blocks.sort_by_key(|b| b.start_offset);
+
+        for block in &mut self.blocks {
+            if block.start_offset >= start && end.is_none_or(|end| block.start_offset < end) {
+                *block = update(block);
+            }
+        }
+
+        // Drop blocks that no longer differ from the one before them
+        self.blocks
+            .dedup_by(|next, previous| next.same_attributes(previous));
+    }
+
+    /// Returns the block covering `offset`
+    fn block_at(&self, offset: usize) -> Option<&BlockInfo> {
+        self.blocks.iter().rev().find(|b| b.start_offset <= offset)
+    }
+
     /// Gets all block info (for serialization or inspection)
     pub fn get_blocks(&self) -> &[BlockInfo] {
         &self.blocks
@@ -489,12 +980,47 @@ impl FormatStorage {
     }
 
     /// Adjusts format positions after text insertion
+    ///
+    /// Text inserted at the end of a run extends it; see
+    /// `adjust_for_insert_with_affinity`.
     pub fn adjust_for_insert(&mut self, pos: Position, length: usize) {
+        self.adjust_for_insert_with_affinity(pos, length, &FormatAffinities::default());
+    }
+
+    /// Adjusts format positions after text insertion, giving text inserted
+    /// where runs meet the formats their affinities call for
+    ///
+    /// Text inserted inside a run extends the run.
+    pub fn adjust_for_insert_with_affinity(
+        &mut self,
+        pos: Position,
+        length: usize,
+        affinities: &FormatAffinities,
+    ) {
         // Invalidate cache since positions are changing
         self.invalidate_cache();
 
         let insert_offset = pos.offset();
 
+        // Runs ending and starting at the insertion point, unless it is
+        // inside a run
+        let inside = self.runs.iter().any(|run| {
+            run.range.start_offset() < insert_offset && insert_offset < run.range.end_offset()
+        });
+        let left = self
+            .runs
+            .iter()
+            .position(|run| run.range.end_offset() == insert_offset && !run.range.is_empty());
+        let right = self
+            .runs
+            .iter()
+            .position(|run| run.range.start_offset() == insert_offset && !run.range.is_empty());
+        let formats_of = |index: Option<usize>| {
+            index.map_or_else(HashSet::new, |index| self.runs[index].formats.clone())
+        };
+        let inherited = (!inside && (left.is_some() || right.is_some()))
+            .then(|| affinities.inherited(&formats_of(left), &formats_of(right)));
+
         // Adjust inline format runs
         for run in &mut self.runs {
             let start_offset = run.range.start.offset();
@@ -503,11 +1029,31 @@ impl FormatStorage {
             if start_offset >= insert_offset {
                 run.range.start = Position::new(start_offset + length);
             }
-            if end_offset >= insert_offset {
+            if end_offset > insert_offset {
                 run.range.end = Position::new(end_offset + length);
             }
         }
 
+        // Give the inserted text its formats by growing a neighbor when it
+        // has exactly those formats
+        if let Some(formats) = inherited.filter(|formats| !formats.is_empty()) {
+            let inserted = Range::from_offsets(insert_offset, insert_offset + length);
+            match (left, right) {
+                (Some(index), _) if self.runs[index].formats == formats => {
+                    self.runs[index].range.end = inserted.end;
+                }
+                (_, Some(index)) if self.runs[index].formats == formats => {
+                    self.runs[index].range.start = inserted.start;
+                }
+                _ => {
+                    let index = self
+                        .runs
+                        .partition_point(|run| run.range.start_offset() < insert_offset);
+                    self.runs.insert(index, FormatRun::new(inserted, formats));
+                }
+            }
+        }
+
         // Adjust block positions
         for block in &mut self.blocks {
             if block.start_offset > insert_offset {
@@ -580,9 +1126,21 @@ impl FormatStorage {
                 block.start_offset -= delete_length;
             }
 
+            // Deleting up to the start of this block moved it onto the one
+            // before, which no longer has any lines; this block applies
+            if j > 0 && self.blocks[j - 1].start_offset == self.blocks[j].start_offset {
+                self.blocks.remove(j - 1);
+                continue;
+            }
+
             j += 1;
         }
 
+        // Removing the lines between two blocks can leave them adjacent with
+        // identical attributes; keep only the first, as set_block_type does
+        self.blocks
+            .dedup_by(|next, previous| next.same_attributes(previous));
+
         // Ensure there's always a block at offset 0
         if self.blocks.is_empty() || self.blocks[0].start_offset != 0 {
             self.blocks
@@ -610,6 +1168,7 @@ impl FormatStorage {
                 let next_end = self.runs[i + 1].range.end;
                 self.runs[i].range.end = next_end;
                 self.runs.remove(i + 1);
+                self.merge_count += 1;
             } else {
                 i += 1;
             }
@@ -654,6 +1213,23 @@ impl Default for FormatStorage {
     }
 }
 
+/// Appends a styled segment, extending the previous one when the formats match
+fn push_segment(
+    segments: &mut Vec<(Range, HashSet<InlineFormat>)>,
+    start: usize,
+    end: usize,
+    formats: &HashSet<InlineFormat>,
+) {
+    if let Some((last_range, last_formats)) = segments.last_mut()
+        && last_range.end_offset() == start
+        && last_formats == formats
+    {
+        last_range.end = Position::new(end);
+        return;
+    }
+    segments.push((Range::from_offsets(start, end), formats.clone()));
+}
+
 /// Helper function to check if two formats match by type (ignoring values for complex formats)
 fn formats_match_type(a: &InlineFormat, b: &InlineFormat) -> bool {
     use InlineFormat::*;
@@ -666,6 +1242,11 @@ fn formats_match_type(a: &InlineFormat, b: &InlineFormat) -> bool {
         (Link { .. }, Link { .. }) => true,
         (TextColor { .. }, TextColor { .. }) => true,
         (BackgroundColor { .. }, BackgroundColor { .. }) => true,
+        (Highlight, Highlight) => true,
+        (SmallCaps, SmallCaps) => true,
+        (Subscript, Subscript) => true,
+        (Superscript, Superscript) => true,
+        (Math { .. }, Math { .. }) => true,
         _ => false,
     }
 }
@@ -673,6 +1254,7 @@ fn formats_match_type(a: &InlineFormat, b: &InlineFormat) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::formatting::affinity::FormatAffinity;
 
     #[test]
     fn test_format_run_creation() {
@@ -777,6 +1359,46 @@ mod tests {
         assert!(formats_at.contains(&InlineFormat::Bold));
     }
 
+    #[test]
+    fn test_adjust_for_insert_with_affinity() {
+        let link = InlineFormat::Link {
+            url: "https://example.com".to_string(),
+        };
+        let mut affinities = FormatAffinities::new();
+        affinities.set(&link, FormatAffinity::Neither);
+        affinities.set(&InlineFormat::Italic, FormatAffinity::Right);
+
+        let mut storage = FormatStorage::new();
+        storage.apply_format(Range::from_offsets(0, 4), InlineFormat::Bold);
+        storage.apply_format(Range::from_offsets(0, 4), link.clone());
+        storage.apply_format(Range::from_offsets(4, 8), InlineFormat::Italic);
+
+        // Between the runs: bold from the left, italic from the right
+        storage.adjust_for_insert_with_affinity(Position::new(4), 2, &affinities);
+        assert_eq!(
+            storage.get_formats_at(Position::new(4)),
+            HashSet::from([InlineFormat::Bold, InlineFormat::Italic])
+        );
+        assert!(storage.get_formats_at(Position::new(3)).contains(&link));
+        assert_eq!(
+            storage.get_formats_at(Position::new(6)),
+            HashSet::from([InlineFormat::Italic])
+        );
+
+        // Inside a run the text takes the run's formats
+        storage.adjust_for_insert_with_affinity(Position::new(2), 1, &affinities);
+        assert!(storage.get_formats_at(Position::new(2)).contains(&link));
+
+        // Past the end of the italic run the text stays plain
+        storage.adjust_for_insert_with_affinity(Position::new(11), 1, &affinities);
+        assert!(storage.get_formats_at(Position::new(11)).is_empty());
+        assert!(
+            storage
+                .get_formats_at(Position::new(10))
+                .contains(&InlineFormat::Italic)
+        );
+    }
+
     #[test]
     fn test_adjust_for_delete() {
         let mut storage = FormatStorage::new();
@@ -804,6 +1426,46 @@ mod tests {
         assert_eq!(storage.get_runs()[0].range.end_offset(), 10);
     }
 
+    #[test]
+    fn test_normalize_merges_runs_split_by_delete() {
+        let mut storage = FormatStorage::new();
+        storage.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+        storage.apply_format(Range::from_offsets(10, 15), InlineFormat::Bold);
+        storage.adjust_for_delete(Range::from_offsets(5, 10));
+        assert_eq!(storage.run_count(), 2);
+        assert_eq!(storage.fragmentation_stats(10).merge_count, 0);
+
+        assert_eq!(storage.normalize(), 1);
+        assert_eq!(storage.get_runs()[0].range, Range::from_offsets(0, 10));
+        let stats = storage.fragmentation_stats(10);
+        assert_eq!(stats.run_count, 1);
+        assert_eq!(stats.runs_per_thousand_chars, 100.0);
+        assert_eq!(stats.average_run_length, 10.0);
+        assert_eq!(stats.merge_count, 1);
+        assert_eq!(storage.normalize(), 0);
+    }
+
+    #[test]
+    fn test_fragmentation_threshold_ignores_small_documents() {
+        let mut storage = FormatStorage::new();
+        for i in 0..MIN_FRAGMENTED_RUNS {
+            storage.apply_format(Range::from_offsets(i * 2, i * 2 + 1), InlineFormat::Bold);
+        }
+        let length = MIN_FRAGMENTED_RUNS * 2;
+
+        assert!(
+            storage
+                .fragmentation_stats(length)
+                .exceeds(DEFAULT_FRAGMENTATION_THRESHOLD)
+        );
+        storage.remove_format(Range::from_offsets(0, 2), &InlineFormat::Bold);
+        assert!(
+            !storage
+                .fragmentation_stats(length)
+                .exceeds(DEFAULT_FRAGMENTATION_THRESHOLD)
+        );
+    }
+
     #[test]
     fn test_clear() {
         let mut storage = FormatStorage::new();
@@ -940,6 +1602,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_block_delete_merges_identical_neighbours() {
+        let mut storage = FormatStorage::new();
+        storage.set_block_type(Range::from_offsets(0, 20), BlockType::BulletList);
+        storage.set_block_type(Range::from_offsets(5, 10), BlockType::heading(2));
+
+        // Removing the heading's lines leaves one list
+        storage.adjust_for_delete(Range::from_offsets(3, 12));
+        assert_eq!(storage.get_blocks().len(), 1);
+        assert_eq!(storage.get_blocks()[0].block_type, BlockType::BulletList);
+    }
+
+    #[test]
+    fn test_set_block_type_empty_range_replaces_block() {
+        let mut storage = FormatStorage::new();
+        storage.set_block_type(Range::from_offsets(0, 0), BlockType::heading(3));
+
+        assert_eq!(storage.get_blocks().len(), 1);
+        assert_eq!(storage.get_blocks()[0].block_type, BlockType::heading(3));
+    }
+
     #[test]
     fn test_overwrite_block_type() {
         let mut storage = FormatStorage::new();
@@ -959,4 +1642,166 @@ mod tests {
         let blocks = storage.get_blocks();
         assert!(blocks.len() >= 2);
     }
+
+    #[test]
+    fn test_styled_runs_fills_gaps() {
+        let mut storage = FormatStorage::new();
+        storage.apply_format(Range::from_offsets(2, 4), InlineFormat::Bold);
+        storage.apply_format(Range::from_offsets(6, 8), InlineFormat::Italic);
+
+        let segments = storage.styled_runs(Range::from_offsets(0, 10));
+        let bounds: Vec<(usize, usize)> = segments
+            .iter()
+            .map(|(range, _)| (range.start_offset(), range.end_offset()))
+            .collect();
+        assert_eq!(bounds, vec![(0, 2), (2, 4), (4, 6), (6, 8), (8, 10)]);
+        assert!(segments[0].1.is_empty());
+        assert!(segments[1].1.contains(&InlineFormat::Bold));
+        assert!(segments[2].1.is_empty());
+        assert!(segments[3].1.contains(&InlineFormat::Italic));
+        assert!(segments[4].1.is_empty());
+    }
+
+    #[test]
+    fn test_styled_runs_clips_to_range() {
+        let mut storage = FormatStorage::new();
+        storage.apply_format(Range::from_offsets(0, 10), InlineFormat::Bold);
+
+        let segments = storage.styled_runs(Range::from_offsets(3, 5));
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].0, Range::from_offsets(3, 5));
+        assert!(segments[0].1.contains(&InlineFormat::Bold));
+
+        assert!(storage.styled_runs(Range::from_offsets(4, 4)).is_empty());
+    }
+
+    #[test]
+    fn test_styled_runs_matches_get_formats_at() {
+        let mut storage = FormatStorage::new();
+        storage.apply_format(Range::from_offsets(0, 6), InlineFormat::Bold);
+        storage.apply_format(Range::from_offsets(3, 9), InlineFormat::Italic);
+        storage.remove_format(Range::from_offsets(4, 5), &InlineFormat::Bold);
+
+        for (range, formats) in storage.styled_runs(Range::from_offsets(0, 12)) {
+            for offset in range.start_offset()..range.end_offset() {
+                assert_eq!(storage.get_formats_at(Position::new(offset)), formats);
+            }
+        }
+    }
+
+    #[test]
+    fn test_set_indent_splits_blocks() {
+        let mut storage = FormatStorage::new();
+        storage.set_block_type(Range::from_offsets(0, 11), BlockType::BulletList);
+
+        // Lines start at 0, 4 and 8
+        storage.set_indent(4, Some(8), 2);
+        assert_eq!(storage.get_indent_at(Position::new(0)), 0);
+        assert_eq!(storage.get_indent_at(Position::new(5)), 2);
+        assert_eq!(storage.get_indent_at(Position::new(8)), 0);
+
+        // Switching list type keeps the level, other block types drop it
+        storage.set_block_type(Range::from_offsets(4, 7), BlockType::NumberedList);
+        assert_eq!(storage.get_indent_at(Position::new(5)), 2);
+        storage.set_block_type(Range::from_offsets(4, 7), BlockType::Paragraph);
+        assert_eq!(storage.get_indent_at(Position::new(5)), 0);
+
+        storage.set_indent(0, None, 0);
+        assert_eq!(storage.get_blocks().len(), 3);
+    }
+
+    #[test]
+    fn test_set_list_style() {
+        let mut storage = FormatStorage::new();
+        storage.set_block_type(Range::from_offsets(0, 7), BlockType::NumberedList);
+        storage.set_block_type(Range::from_offsets(8, 11), BlockType::Paragraph);
+
+        storage.set_list_style(0, None, Some(ListStyle::LowerRoman));
+        assert_eq!(
+            storage.get_list_style_at(Position::new(5)),
+            Some(ListStyle::LowerRoman)
+        );
+        assert_eq!(storage.get_list_style_at(Position::new(9)), None);
+
+        // Indenting and re-applying the list type keep the style
+        storage.set_indent(4, Some(8), 1);
+        storage.set_block_type(Range::from_offsets(0, 7), BlockType::NumberedList);
+        assert_eq!(
+            storage.get_list_style_at(Position::new(5)),
+            Some(ListStyle::LowerRoman)
+        );
+
+        // Switching list type drops it
+        storage.set_block_type(Range::from_offsets(4, 7), BlockType::BulletList);
+        assert_eq!(storage.get_list_style_at(Position::new(5)), None);
+        assert_eq!(
+            storage.get_list_style_at(Position::new(0)),
+            Some(ListStyle::LowerRoman)
+        );
+    }
+
+    #[test]
+    fn test_paragraph_indent() {
+        let mut storage = FormatStorage::new();
+        storage.set_block_type(Range::from_offsets(4, 7), BlockType::heading(2));
+
+        // Lines start at 0, 4 and 8; the heading line stays unindented
+        storage.set_block_indent(0, None, 3);
+        storage.set_first_line_indent(0, Some(4), true);
+        assert_eq!(storage.get_block_indent_at(Position::new(1)), 3);
+        assert_eq!(storage.get_block_indent_at(Position::new(5)), 0);
+        assert_eq!(storage.get_block_indent_at(Position::new(9)), 3);
+        assert!(storage.has_first_line_indent_at(Position::new(1)));
+        assert!(!storage.has_first_line_indent_at(Position::new(9)));
+
+        storage.set_block_indent(8, None, 200);
+        assert_eq!(
+            storage.get_block_indent_at(Position::new(9)),
+            MAX_BLOCK_INDENT
+        );
+
+        // Re-applying the paragraph type keeps each line's indent; other
+        // types drop it
+        storage.set_block_type(Range::from_offsets(0, 11), BlockType::Paragraph);
+        assert_eq!(storage.get_block_indent_at(Position::new(1)), 3);
+        assert_eq!(
+            storage.get_block_indent_at(Position::new(9)),
+            MAX_BLOCK_INDENT
+        );
+        storage.set_block_type(Range::from_offsets(0, 3), BlockType::block_quote());
+        assert_eq!(storage.get_block_indent_at(Position::new(1)), 0);
+        assert!(!storage.has_first_line_indent_at(Position::new(1)));
+    }
+
+    #[test]
+    fn test_set_direction() {
+        let mut storage = FormatStorage::new();
+
+        // Lines start at 0, 4 and 8
+        storage.set_direction(4, None, Some(TextDirection::Rtl));
+        assert_eq!(storage.get_direction_at(Position::new(1)), None);
+        assert_eq!(
+            storage.get_direction_at(Position::new(9)),
+            Some(TextDirection::Rtl)
+        );
+
+        // Changing the block type keeps each line's direction
+        storage.set_block_type(Range::from_offsets(0, 11), BlockType::heading(1));
+        assert_eq!(storage.get_direction_at(Position::new(1)), None);
+        assert_eq!(
+            storage.get_direction_at(Position::new(5)),
+            Some(TextDirection::Rtl)
+        );
+        assert_eq!(
+            storage.get_block_type_at(Position::new(5)),
+            BlockType::heading(1)
+        );
+
+        storage.set_direction(0, Some(8), None);
+        assert_eq!(storage.get_direction_at(Position::new(5)), None);
+        assert_eq!(
+            storage.get_direction_at(Position::new(9)),
+            Some(TextDirection::Rtl)
+        );
+    }
 }
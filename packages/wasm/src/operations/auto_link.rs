@@ -0,0 +1,313 @@
+//! Link autodetection
+//!
+//! Turns bare URLs and email addresses into links as they are typed or
+//! pasted. A URL starts with "http://", "https://" or "www.", and links to
+//! "https://" plus the text in the last case; an email address links to
+//! "mailto:" plus the address. Punctuation around the text, such as the
+//! full stop ending a sentence or the parentheses around an aside, is left
+//! out of the link.
+//!
+//! Typed text is linked when whitespace is typed after it with
+//! `Document::insert_text`, pasted text once the paste is done, as long as
+//! whitespace or the start or end of the document surrounds it. Text that
+//! is already a link or in code is left alone. Linking typed text is its
+//! own undo step, so undoing right after it removes only the link; pasted
+//! text is linked in the paste's undo step.
+
+use crate::document::{Document, Position, Range};
+use crate::formatting::InlineFormat;
+
+/// Characters left out of the start of a link
+const OPENING_PUNCTUATION: &str = "([{<\"'“‘";
+
+/// Characters left out of the end of a link
+const CLOSING_PUNCTUATION: &str = ".,;:!?)]}>\"'”’";
+
+/// Finds the URL or email address `word` holds, ignoring punctuation
+/// around it
+///
+/// Returns the character offset and length of the linked text within
+/// `word` and the URL it links to.
+///
+/// # Example
+/// ```
+/// use rte_core::operations::auto_link::detect_link;
+///
+/// assert_eq!(
+///     detect_link("(www.example.com)."),
+///     Some((1, 15, "https://www.example.com".to_string()))
+/// );
+/// assert_eq!(
+///     detect_link("me@example.com"),
+///     Some((0, 14, "mailto:me@example.com".to_string()))
+/// );
+/// assert_eq!(detect_link("example.com"), None);
+/// ```
+pub fn detect_link(word: &str) -> Option<(usize, usize, String)> {
+    let start = word.len()
+        - word
+            .trim_start_matches(|c| OPENING_PUNCTUATION.contains(c))
+            .len();
+    let mut text = &word[start..];
+    // Closing parentheses are kept when they pair with one in the link,
+    // as in Wikipedia URLs
+    while let Some(last) = text.chars().next_back() {
+        let unpaired = match last {
+            ')' => text.matches('(').count() < text.matches(')').count(),
+            _ => CLOSING_PUNCTUATION.contains(last),
+        };
+        if !unpaired {
+            break;
+        }
+        text = &text[..text.len() - last.len_utf8()];
+    }
+
+    let lower = text.to_ascii_lowercase();
+    let url = if ["http://", "https://"]
+        .iter()
+        .any(|scheme| lower.strip_prefix(scheme).is_some_and(is_host_and_path))
+    {
+        text.to_string()
+    } else if lower.strip_prefix("www.").is_some_and(is_host_and_path) {
+        format!("https://{}", text)
+    } else if is_email(text) {
+        format!("mailto:{}", text)
+    } else {
+        return None;
+    };
+    let offset = word[..start].chars().count();
+    Some((offset, text.chars().count(), url))
+}
+
+/// Returns true if `rest` starts with a host name of at least one letter or
+/// digit
+fn is_host_and_path(rest: &str) -> bool {
+    rest.chars().next().is_some_and(char::is_alphanumeric)
+}
+
+/// Returns true if `text` is an email address with a dotted domain
+fn is_email(text: &str) -> bool {
+    let Some((local, domain)) = text.split_once('@') else {
+        return false;
+    };
+    let local_ok = !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_alphanumeric() || "._%+-".contains(c));
+    let domain_ok = domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        });
+    local_ok && domain_ok
+}
+
+impl Document {
+    /// Returns true if bare URLs and email addresses are linked as they are
+    /// typed or pasted (the default)
+    pub fn is_auto_link_enabled(&self) -> bool {
+        self.auto_link
+    }
+
+    /// Sets whether bare URLs and email addresses are linked as they are
+    /// typed or pasted
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    /// use rte_core::formatting::InlineFormat;
+    ///
+    /// let mut doc = Document::new();
+    /// for c in "See www.example.com ".chars() {
+    ///     let cursor = doc.get_selection().focus;
+    ///     doc.insert_text(cursor, &c.to_string()).unwrap();
+    /// }
+    /// let link = InlineFormat::Link { url: "https://www.example.com".to_string() };
+    /// assert!(doc.get_formats_at(Position::new(4)).contains(&link));
+    ///
+    /// // Undo removes only the link
+    /// doc.undo().unwrap();
+    /// assert_eq!(doc.get_content(), "See www.example.com ");
+    /// assert!(doc.get_formats_at(Position::new(4)).is_empty());
+    ///
+    /// doc.set_auto_link_enabled(false);
+    /// doc.paste_plain_text("me@example.com").unwrap();
+    /// assert!(doc.get_formats_at(Position::new(20)).is_empty());
+    /// ```
+    pub fn set_auto_link_enabled(&mut self, enabled: bool) {
+        self.auto_link = enabled;
+    }
+
+    /// Links the URL or email address ending at `pos` after whitespace was
+    /// typed there, returning true if one was linked
+    pub(crate) fn auto_link_typed(&mut self, pos: Position, text: &str) -> bool {
+        let mut chars = text.chars();
+        let (Some(typed), None) = (chars.next(), chars.next()) else {
+            return false;
+        };
+        if !self.auto_link || !typed.is_whitespace() {
+            return false;
+        }
+        let offset = pos.offset();
+        let line_start = self.line_start_before(offset);
+        let before = self.get_text_in_range(Range::from_offsets(line_start, offset));
+        let word = before
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or_default();
+        let start = offset - word.chars().count();
+        self.apply_links(self.find_link(start, word).into_iter().collect())
+    }
+
+    /// Links the URLs and email addresses pasted into `ranges`, returning
+    /// true if any was linked
+    pub(crate) fn auto_link_pasted(&mut self, ranges: &[Range]) -> bool {
+        if !self.auto_link {
+            return false;
+        }
+        let whitespace_at = |doc: &Self, offset: usize| {
+            offset >= doc.get_length()
+                || doc
+                    .get_text_in_range(Range::from_offsets(offset, offset + 1))
+                    .starts_with(char::is_whitespace)
+        };
+
+        let mut links = Vec::new();
+        for range in ranges {
+            let range = range.normalize();
+            let text = self.get_text_in_range(range);
+            let mut offset = range.start_offset();
+            for word in text.split(char::is_whitespace) {
+                let length = word.chars().count();
+                let end = offset + length;
+                if (offset > range.start_offset() || offset == 0 || whitespace_at(self, offset - 1))
+                    && (end < range.end_offset() || whitespace_at(self, end))
+                {
+                    links.extend(self.find_link(offset, word));
+                }
+                offset = end + 1;
+            }
+        }
+        self.apply_links(links)
+    }
+
+    /// Returns the link for `word`, which starts at `start`, unless it is
+    /// already linked or in code
+    fn find_link(&self, start: usize, word: &str) -> Option<(Range, InlineFormat)> {
+        let (offset, length, url) = detect_link(word)?;
+        let range = Range::from_offsets(start + offset, start + offset + length);
+        if self.get_block_type_at(range.start).is_code_block() {
+            return None;
+        }
+        let taken = (range.start_offset()..range.end_offset()).any(|i| {
+            self.get_formats_at(Position::new(i))
+                .iter()
+                .any(|format| matches!(format, InlineFormat::Link { .. } | InlineFormat::Code))
+        });
+        (!taken).then_some((range, InlineFormat::Link { url }))
+    }
+
+    /// Applies `links` as one undo step, returning true if there were any
+    fn apply_links(&mut self, links: Vec<(Range, InlineFormat)>) -> bool {
+        if links.is_empty() || self.is_read_only() {
+            return false;
+        }
+        self.begin_batch();
+        for (range, link) in links {
+            self.apply_format(range, link);
+        }
+        self.end_batch();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selection::Selection;
+
+    fn type_text(doc: &mut Document, text: &str) {
+        for c in text.chars() {
+            let pos = doc.get_selection().focus;
+            doc.insert_text(pos, &c.to_string()).unwrap();
+        }
+    }
+
+    fn link_at(doc: &Document, offset: usize) -> Option<String> {
+        doc.get_formats_at(Position::new(offset))
+            .into_iter()
+            .find_map(|format| match format {
+                InlineFormat::Link { url } => Some(url),
+                _ => None,
+            })
+    }
+
+    #[test]
+    fn test_detect_link() {
+        assert_eq!(
+            detect_link("https://en.wikipedia.org/wiki/Rust_(language)."),
+            Some((
+                0,
+                45,
+                "https://en.wikipedia.org/wiki/Rust_(language)".to_string()
+            ))
+        );
+        assert_eq!(
+            detect_link("\"HTTP://x.io\","),
+            Some((1, 11, "HTTP://x.io".to_string()))
+        );
+        for word in [
+            "https://",
+            "www.",
+            "a@b",
+            "@b.com",
+            "a@b..com",
+            "ftp://x.io",
+        ] {
+            assert_eq!(detect_link(word), None, "{word}");
+        }
+    }
+
+    #[test]
+    fn test_typed_links() {
+        let mut doc = Document::new();
+        assert!(doc.is_auto_link_enabled());
+        type_text(&mut doc, "Mail me@x.io, visit (https://x.io/a).\n");
+        assert_eq!(link_at(&doc, 5).as_deref(), Some("mailto:me@x.io"));
+        assert_eq!(link_at(&doc, 12), None);
+        assert_eq!(link_at(&doc, 21).as_deref(), Some("https://x.io/a"));
+        assert_eq!(link_at(&doc, 35), None);
+
+        // Nothing is linked in code or twice
+        let mut doc = Document::from_text("www.x.io www.y.io");
+        let link = InlineFormat::Link {
+            url: "https://z.io".to_string(),
+        };
+        doc.apply_format(Range::from_offsets(0, 8), link);
+        doc.apply_format(Range::from_offsets(9, 17), InlineFormat::Code);
+        doc.set_selection(Selection::collapsed(Position::new(8)));
+        type_text(&mut doc, " ");
+        doc.set_selection(Selection::collapsed(Position::new(18)));
+        type_text(&mut doc, " ");
+        assert_eq!(link_at(&doc, 0).as_deref(), Some("https://z.io"));
+        assert_eq!(link_at(&doc, 10), None);
+    }
+
+    #[test]
+    fn test_pasted_links() {
+        let mut doc = Document::from_text("ab");
+        doc.set_selection(Selection::collapsed(Position::new(1)));
+        doc.paste_plain_text("www.x.io").unwrap();
+        assert_eq!(link_at(&doc, 2), None);
+
+        let mut doc = Document::from_text("Go ");
+        doc.set_selection(Selection::collapsed(Position::new(3)));
+        doc.paste_plain_text("www.x.io or me@x.io").unwrap();
+        assert_eq!(link_at(&doc, 3).as_deref(), Some("https://www.x.io"));
+        assert_eq!(link_at(&doc, 15).as_deref(), Some("mailto:me@x.io"));
+
+        // One undo removes the paste along with its links
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "Go ");
+        assert!(!doc.can_undo());
+    }
+}
@@ -0,0 +1,246 @@
+//! Autocorrect
+//!
+//! Rewrites text as it is typed: "(c)" becomes "©", "--" an em dash and
+//! straight quotes curly ones. A rule applies when a character that cannot
+//! be part of a word, such as a space or punctuation, is typed right after
+//! its text. Quotes are replaced as soon as they are typed, opening or
+//! closing depending on the character before them.
+//!
+//! Only single characters inserted with `Document::insert_text`, as typing
+//! inserts them, are corrected, never pasted or loaded text, and nothing is
+//! corrected in code. Each correction is its own undo step, so undoing
+//! right after one brings back what was typed.
+
+use crate::document::{Document, Position, Range};
+use crate::formatting::InlineFormat;
+use crate::utils::segmenter::is_word_char;
+
+/// Rules used by `Autocorrect::new`
+pub const DEFAULT_RULES: &[(&str, &str)] = &[
+    ("(c)", "©"),
+    ("(r)", "®"),
+    ("(tm)", "™"),
+    ("--", "—"),
+    ("...", "…"),
+    ("->", "→"),
+    ("<-", "←"),
+];
+
+/// Text replacement rules applied as text is typed
+///
+/// Off by default; set on a document with `Document::set_autocorrect`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Autocorrect {
+    /// Whether corrections are made
+    pub enabled: bool,
+    /// Whether straight quotes are replaced with curly ones as they are
+    /// typed
+    pub smart_quotes: bool,
+    rules: Vec<(String, String)>,
+}
+
+impl Default for Autocorrect {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            smart_quotes: true,
+            rules: DEFAULT_RULES
+                .iter()
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl Autocorrect {
+    /// Creates enabled autocorrect with smart quotes and `DEFAULT_RULES`
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the replacement rules as `(from, to)` pairs
+    pub fn rules(&self) -> &[(String, String)] {
+        &self.rules
+    }
+
+    /// Adds a rule replacing `from` with `to`, or changes the replacement
+    /// of an existing rule for `from`
+    ///
+    /// A rule for text that starts with a word character only applies at
+    /// the start of a word, so a rule for "teh" leaves "Buteh" alone.
+    /// Rules with an empty `from` are ignored.
+    pub fn add_rule(&mut self, from: impl Into<String>, to: impl Into<String>) {
+        let from = from.into();
+        if from.is_empty() {
+            return;
+        }
+        match self
+            .rules
+            .iter_mut()
+            .find(|(existing, _)| *existing == from)
+        {
+            Some((_, replacement)) => *replacement = to.into(),
+            None => self.rules.push((from, to.into())),
+        }
+    }
+
+    /// Removes the rule for `from`, returning true if there was one
+    pub fn remove_rule(&mut self, from: &str) -> bool {
+        let before = self.rules.len();
+        self.rules.retain(|(existing, _)| existing != from);
+        self.rules.len() != before
+    }
+
+    /// Finds the longest rule whose text `before` ends with, returning the
+    /// length of that text in characters and its replacement
+    fn correction(&self, before: &str) -> Option<(usize, &str)> {
+        self.rules
+            .iter()
+            .filter(|(from, _)| before.ends_with(from.as_str()))
+            .filter(|(from, _)| {
+                let starts_word = from.chars().next().is_some_and(is_word_char);
+                let preceding = before[..before.len() - from.len()].chars().next_back();
+                !starts_word || !preceding.is_some_and(is_word_char)
+            })
+            .max_by_key(|(from, _)| from.chars().count())
+            .map(|(from, to)| (from.chars().count(), to.as_str()))
+    }
+
+    /// Returns the length in characters of the longest rule text
+    fn longest_rule(&self) -> usize {
+        self.rules
+            .iter()
+            .map(|(from, _)| from.chars().count())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Returns the curly quote replacing a straight `quote` typed after
+/// `previous`, or None if `quote` is not a straight quote
+fn smart_quote(quote: char, previous: Option<char>) -> Option<char> {
+    let opening = previous.is_none_or(|c| c.is_whitespace() || "([{“‘—".contains(c));
+    match quote {
+        '"' => Some(if opening { '“' } else { '”' }),
+        '\'' => Some(if opening { '‘' } else { '’' }),
+        _ => None,
+    }
+}
+
+impl Document {
+    /// Gets the autocorrect settings
+    pub fn autocorrect(&self) -> &Autocorrect {
+        &self.autocorrect
+    }
+
+    /// Gets the autocorrect settings for changing them in place
+    pub fn autocorrect_mut(&mut self) -> &mut Autocorrect {
+        &mut self.autocorrect
+    }
+
+    /// Sets the autocorrect settings
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    /// use rte_core::operations::autocorrect::Autocorrect;
+    ///
+    /// let mut doc = Document::new();
+    /// doc.set_autocorrect(Autocorrect::new());
+    /// for (i, c) in "\"Hi\" (c) ".chars().enumerate() {
+    ///     doc.insert_text(Position::new(i), &c.to_string()).unwrap();
+    /// }
+    /// assert_eq!(doc.get_content(), "“Hi” © ");
+    ///
+    /// doc.undo().unwrap();
+    /// assert_eq!(doc.get_content(), "“Hi” (c) ");
+    /// ```
+    pub fn set_autocorrect(&mut self, autocorrect: Autocorrect) {
+        self.autocorrect = autocorrect;
+    }
+
+    /// Makes the corrections due after `text` was typed at `pos`
+    pub(crate) fn autocorrect_typed(&mut self, pos: Position, text: &str) {
+        let mut chars = text.chars();
+        let (Some(typed), None) = (chars.next(), chars.next()) else {
+            return;
+        };
+        let offset = pos.offset();
+        if !self.autocorrect.enabled
+            || is_word_char(typed)
+            || self.get_block_type_at(pos).is_code_block()
+            || (offset > 0
+                && self
+                    .get_formats_at(Position::new(offset - 1))
+                    .contains(&InlineFormat::Code))
+        {
+            return;
+        }
+
+        let lookback = self.autocorrect.longest_rule() + 1;
+        let start = offset
+            .saturating_sub(lookback)
+            .max(self.line_start_before(offset));
+        let before = self.get_text_in_range(Range::from_offsets(start, offset));
+        let mut corrections = Vec::new();
+        if let Some((length, to)) = self.autocorrect.correction(&before) {
+            corrections.push((Range::from_offsets(offset - length, offset), to.to_string()));
+        }
+        if self.autocorrect.smart_quotes
+            && let Some(quote) = smart_quote(typed, before.chars().next_back())
+        {
+            corrections.push((Range::from_offsets(offset, offset + 1), quote.to_string()));
+        }
+        if corrections.is_empty() {
+            return;
+        }
+
+        // Later corrections first, so the offsets of earlier ones stay valid
+        self.begin_batch();
+        for (range, text) in corrections.into_iter().rev() {
+            let _ = self.replace_range(range, &text);
+        }
+        self.end_batch();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_text(doc: &mut Document, text: &str) {
+        for c in text.chars() {
+            let pos = doc.get_selection().focus;
+            doc.insert_text(pos, &c.to_string()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_rules_apply_at_word_boundaries() {
+        let mut doc = Document::new();
+        let mut autocorrect = Autocorrect::new();
+        autocorrect.add_rule("teh", "the");
+        doc.set_autocorrect(autocorrect);
+
+        type_text(&mut doc, "teh end -- it's Buteh. (tm) ");
+        assert_eq!(doc.get_content(), "the end — it’s Buteh. ™ ");
+
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "the end — it’s Buteh. (tm) ");
+
+        // Pasted text is left alone
+        doc.insert_text(Position::new(0), "(c) ").unwrap();
+        assert!(doc.get_content().starts_with("(c) "));
+
+        assert!(doc.autocorrect_mut().remove_rule("teh"));
+        assert!(!doc.autocorrect_mut().remove_rule("teh"));
+        doc.autocorrect_mut().enabled = false;
+        let end = Position::new(doc.get_length());
+        doc.set_selection(crate::selection::Selection::collapsed(end));
+        type_text(&mut doc, "\"(r) ");
+        assert!(doc.get_content().ends_with("\"(r) "));
+    }
+}
@@ -0,0 +1,274 @@
+use crate::document::{Document, Position, Range, SOFT_BREAK};
+use crate::formatting::BlockType;
+use crate::operations::CommandResult;
+
+/// What `Document::cleanup` tidies up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CleanupOptions {
+    /// Whether whitespace at the end of each line is removed
+    pub trim_trailing_whitespace: bool,
+    /// Whether runs of empty paragraphs are shortened
+    pub collapse_empty_paragraphs: bool,
+    /// The most consecutive empty paragraphs kept when collapsing
+    pub max_empty_paragraphs: usize,
+    /// Whether inline formats are removed from whitespace-only runs
+    pub clear_whitespace_formats: bool,
+}
+
+impl CleanupOptions {
+    /// Creates options with every cleanup enabled, keeping at most two
+    /// consecutive empty paragraphs
+    pub fn new() -> Self {
+        Self {
+            trim_trailing_whitespace: true,
+            collapse_empty_paragraphs: true,
+            max_empty_paragraphs: 2,
+            clear_whitespace_formats: true,
+        }
+    }
+
+    /// Sets whether trailing whitespace is removed
+    pub fn trim_trailing_whitespace(mut self, trim: bool) -> Self {
+        self.trim_trailing_whitespace = trim;
+        self
+    }
+
+    /// Sets whether runs of empty paragraphs are shortened
+    pub fn collapse_empty_paragraphs(mut self, collapse: bool) -> Self {
+        self.collapse_empty_paragraphs = collapse;
+        self
+    }
+
+    /// Sets the most consecutive empty paragraphs kept when collapsing
+    pub fn max_empty_paragraphs(mut self, max: usize) -> Self {
+        self.max_empty_paragraphs = max;
+        self
+    }
+
+    /// Sets whether formats are removed from whitespace-only runs
+    pub fn clear_whitespace_formats(mut self, clear: bool) -> Self {
+        self.clear_whitespace_formats = clear;
+        self
+    }
+}
+
+impl Default for CleanupOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Document {
+    /// Tidies the document before publishing, as a single undo step
+    ///
+    /// Trailing whitespace is removed before each newline and soft break
+    /// (code blocks are left alone), runs of empty paragraphs longer than
+    /// `max_empty_paragraphs` are shortened, and inline formats are removed
+    /// from runs that only cover whitespace.
+    ///
+    /// # Returns
+    /// true if the document changed
+    ///
+    /// # Errors
+    /// Returns an error if the document is read-only
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::Document;
+    /// use rte_core::operations::cleanup::CleanupOptions;
+    ///
+    /// let mut doc = Document::from_text("One  \n\n\n\n\nTwo");
+    /// assert!(doc.cleanup(&CleanupOptions::new()).unwrap());
+    /// assert_eq!(doc.get_content(), "One\n\n\nTwo");
+    /// ```
+    pub fn cleanup(&mut self, options: &CleanupOptions) -> CommandResult<bool> {
+        self.check_editable()?;
+        let version = self.version();
+
+        self.begin_batch();
+        let result = self.delete_all(self.cleanup_ranges(options));
+        if result.is_ok() && options.clear_whitespace_formats {
+            self.clear_whitespace_formats();
+        }
+        self.end_batch();
+
+        result.map(|()| self.version() != version)
+    }
+
+    /// Collects the ranges to delete, sorted and without overlaps
+    fn cleanup_ranges(&self, options: &CleanupOptions) -> Vec<Range> {
+        let content = self.get_content();
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for line in content.split('\n') {
+            let end = start + line.chars().count();
+            lines.push((start, end, line));
+            start = end + 1;
+        }
+
+        let mut ranges = Vec::new();
+        if options.trim_trailing_whitespace {
+            for &(start, _, line) in &lines {
+                if !self.get_block_type_at(Position::new(start)).is_code_block() {
+                    ranges.extend(trailing_whitespace(line, start));
+                }
+            }
+        }
+
+        if options.collapse_empty_paragraphs {
+            let is_empty = |&(start, _, line): &(usize, usize, &str)| {
+                line.chars().all(|c| c.is_whitespace() && c != SOFT_BREAK)
+                    && self.get_block_type_at(Position::new(start)) == BlockType::Paragraph
+            };
+            let max = options.max_empty_paragraphs;
+            let mut first = 0;
+            while first < lines.len() {
+                let count = lines[first..].iter().take_while(|l| is_empty(l)).count();
+                if count > max {
+                    let last = first + count - 1;
+                    let range = match lines.get(last + 1) {
+                        // Delete whole lines up to the next one
+                        Some(next) => Range::from_offsets(lines[first + max].0, next.0),
+                        // At the end, delete from the end of the last kept line
+                        None if first + max > 0 => {
+                            Range::from_offsets(lines[first + max - 1].1, lines[last].1)
+                        }
+                        None => Range::from_offsets(0, lines[last].1),
+                    };
+                    ranges.push(range);
+                }
+                first += count.max(1);
+            }
+        }
+
+        ranges.sort_by_key(|range| range.start_offset());
+        let mut merged: Vec<Range> = Vec::new();
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start_offset() <= last.end_offset() => {
+                    let end = last.end_offset().max(range.end_offset());
+                    *last = Range::from_offsets(last.start_offset(), end);
+                }
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+
+    /// Deletes sorted, non-overlapping ranges from last to first
+    fn delete_all(&mut self, ranges: Vec<Range>) -> CommandResult<()> {
+        ranges
+            .into_iter()
+            .rev()
+            .try_for_each(|range| self.delete_range(range))
+    }
+
+    /// Removes inline formats from runs that only cover whitespace
+    fn clear_whitespace_formats(&mut self) {
+        let runs = self.formats().get_runs().to_vec();
+        for run in runs {
+            if !self
+                .get_text_in_range(run.range)
+                .chars()
+                .all(char::is_whitespace)
+            {
+                continue;
+            }
+            for format in &run.formats {
+                self.remove_format(run.range, format);
+            }
+        }
+    }
+}
+
+/// Finds the whitespace before each soft break and at the end of `line`
+fn trailing_whitespace(line: &str, line_start: usize) -> Vec<Range> {
+    let mut ranges = Vec::new();
+    let mut run_start = None;
+    let chars = line.chars().chain(std::iter::once(SOFT_BREAK));
+    for (offset, c) in (line_start..).zip(chars) {
+        if c == SOFT_BREAK {
+            if let Some(start) = run_start.take() {
+                ranges.push(Range::from_offsets(start, offset));
+            }
+        } else if c.is_whitespace() {
+            run_start.get_or_insert(offset);
+        } else {
+            run_start = None;
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::InlineFormat;
+
+    #[test]
+    fn test_trim_trailing_whitespace() {
+        let mut doc = Document::from_text("a \t\nb\u{2028}  c  \ncode  ");
+        doc.set_block_type(Range::from_offsets(11, 17), BlockType::code_block());
+        let options = CleanupOptions::new().collapse_empty_paragraphs(false);
+
+        assert!(doc.cleanup(&options).unwrap());
+        assert_eq!(doc.get_content(), "a\nb\u{2028}  c\ncode  ");
+        assert!(!doc.cleanup(&options).unwrap());
+    }
+
+    #[test]
+    fn test_collapse_empty_paragraphs() {
+        let mut doc = Document::from_text("a\n\n \n\n\nb\n\n\n");
+        doc.cleanup(&CleanupOptions::new()).unwrap();
+        assert_eq!(doc.get_content(), "a\n\n\nb\n\n");
+
+        let mut doc = Document::from_text("\n\n\na\n\n\n\nb");
+        doc.cleanup(&CleanupOptions::new().max_empty_paragraphs(0))
+            .unwrap();
+        assert_eq!(doc.get_content(), "a\nb");
+
+        // Empty lines of other blocks are kept
+        let mut doc = Document::from_text("a\n\n\n\nb");
+        doc.set_block_type(Range::from_offsets(2, 4), BlockType::code_block());
+        doc.cleanup(&CleanupOptions::new().max_empty_paragraphs(1))
+            .unwrap();
+        assert_eq!(doc.get_content(), "a\n\n\n\nb");
+    }
+
+    #[test]
+    fn test_clear_whitespace_formats() {
+        let mut doc = Document::from_text("a b c");
+        doc.apply_format(Range::from_offsets(0, 1), InlineFormat::Bold);
+        doc.apply_format(Range::from_offsets(1, 2), InlineFormat::Underline);
+        doc.apply_format(Range::from_offsets(3, 5), InlineFormat::Italic);
+
+        doc.cleanup(&CleanupOptions::new()).unwrap();
+        assert!(doc.get_formats_at(Position::new(1)).is_empty());
+        assert!(
+            doc.get_formats_at(Position::new(0))
+                .contains(&InlineFormat::Bold)
+        );
+        assert!(
+            doc.get_formats_at(Position::new(3))
+                .contains(&InlineFormat::Italic)
+        );
+    }
+
+    #[test]
+    fn test_cleanup_is_one_undo_step() {
+        let mut doc = Document::from_text("a  \n\n\n\n\nb ");
+        doc.apply_format(Range::from_offsets(1, 3), InlineFormat::Bold);
+        doc.cleanup(&CleanupOptions::new()).unwrap();
+        assert_eq!(doc.get_content(), "a\n\n\nb");
+
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "a  \n\n\n\n\nb ");
+        assert!(
+            doc.get_formats_at(Position::new(2))
+                .contains(&InlineFormat::Bold)
+        );
+
+        doc.set_read_only(true);
+        assert!(doc.cleanup(&CleanupOptions::new()).is_err());
+    }
+}
@@ -1,20 +1,45 @@
 // Clipboard operations module
-use crate::document::{Document, Position, Range};
-use crate::formatting::InlineFormat;
+use crate::document::{
+    ControlCharPolicy, Document, EMBED_CHAR, Image, Position, Range, SOFT_BREAK,
+};
+use crate::formatting::{BlockType, InlineFormat};
+use crate::operations::history::now;
+use crate::selection::Selection;
+use crate::serialization::html::HtmlSanitizer;
+use crate::serialization::html_parser::{self, HtmlToken};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashSet;
+use std::time::Duration;
+
+/// Value of the `data-rte-source` attribute that marks HTML copied from
+/// this editor
+pub const CLIPBOARD_SOURCE: &str = "rich-text-editor-wasm";
+
+/// Start of the comment holding the JSON copy of clipboard HTML
+const PAYLOAD_START: &str = "<!--rte-clipboard ";
+
+/// End of the comment holding the JSON copy of clipboard HTML
+const PAYLOAD_END: &str = "-->";
 
 /// Represents clipboard content with text and formatting
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ClipboardContent {
     /// Plain text content
     pub text: String,
     /// Format runs that apply to the text
     pub formats: Vec<SerializableFormatRun>,
+    /// Inline images, keyed by offset relative to the clipboard content
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<(usize, Image)>,
+    /// Block types of lines that start inside the content, keyed by
+    /// offset relative to the clipboard content
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<(usize, BlockType)>,
 }
 
 /// Serializable version of FormatRun for clipboard operations
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SerializableFormatRun {
     /// Start offset relative to the clipboard content
     pub start: usize,
@@ -24,18 +49,99 @@ pub struct SerializableFormatRun {
     pub formats: HashSet<InlineFormat>,
 }
 
+/// A paste the duplicate paste guard suppressed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuppressedPaste {
+    /// Plain text of the suppressed payload
+    pub text: String,
+    /// Offset where the payload would have been pasted
+    pub offset: usize,
+    /// Time since the identical paste before it
+    pub elapsed: Duration,
+}
+
+/// Recognizes the same payload pasted twice in quick succession, which
+/// browsers do when they fire a paste event twice
+#[derive(Debug, Clone)]
+pub(crate) struct PasteGuard {
+    window: Duration,
+    /// The last paste, with the selection and version it left behind
+    last: Option<(ClipboardContent, Selection, u64, Duration)>,
+    suppressed: Vec<SuppressedPaste>,
+}
+
+impl PasteGuard {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last: None,
+            suppressed: Vec::new(),
+        }
+    }
+}
+
 impl ClipboardContent {
     /// Creates a new empty ClipboardContent
     pub fn new() -> Self {
         Self {
             text: String::new(),
             formats: Vec::new(),
+            images: Vec::new(),
+            blocks: Vec::new(),
         }
     }
 
     /// Creates ClipboardContent from text and format runs
     pub fn from_text_and_formats(text: String, formats: Vec<SerializableFormatRun>) -> Self {
-        Self { text, formats }
+        Self {
+            text,
+            formats,
+            images: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Applies a control character policy to the text, moving format,
+    /// image and block offsets to match
+    pub fn with_control_char_policy(&self, policy: ControlCharPolicy) -> Cow<'_, Self> {
+        if let Cow::Borrowed(_) = policy.apply(&self.text) {
+            return Cow::Borrowed(self);
+        }
+
+        // offsets[i] is the new offset of the i-th original character
+        let mut text = String::with_capacity(self.text.len());
+        let mut offsets = Vec::with_capacity(self.text.len() + 1);
+        let mut buf = [0; 4];
+        for c in self.text.chars() {
+            offsets.push(text.chars().count());
+            text.push_str(&policy.apply(c.encode_utf8(&mut buf)));
+        }
+        offsets.push(text.chars().count());
+        let map = |offset: usize| offsets[offset.min(offsets.len() - 1)];
+
+        Cow::Owned(Self {
+            formats: self
+                .formats
+                .iter()
+                .map(|run| SerializableFormatRun {
+                    start: map(run.start),
+                    end: map(run.end),
+                    formats: run.formats.clone(),
+                })
+                .filter(|run| run.start < run.end)
+                .collect(),
+            images: self
+                .images
+                .iter()
+                .map(|(offset, image)| (map(*offset), image.clone()))
+                .collect(),
+            blocks: self
+                .blocks
+                .iter()
+                .map(|(offset, block_type)| (map(*offset), block_type.clone()))
+                .collect(),
+            text,
+        })
     }
 
     /// Converts to HTML format for clipboard
@@ -85,10 +191,84 @@ impl ClipboardContent {
         result
     }
 
+    /// Converts to HTML for the system clipboard
+    ///
+    /// Wraps `to_html` in an element marked with `data-rte-source`, after a
+    /// `<meta charset>` so other applications decode it as UTF-8, and embeds
+    /// the content as JSON in a comment. Pasting the HTML back into this
+    /// editor uses the JSON, which keeps block types the markup loses.
+    pub fn to_clipboard_html(&self) -> String {
+        if self.text.is_empty() {
+            return String::new();
+        }
+
+        // The content always serializes; without it, paste reads the markup
+        let payload = serde_json::to_string(self)
+            .map(|json| {
+                format!(
+                    "{PAYLOAD_START}{}{PAYLOAD_END}",
+                    escape_json_for_comment(&json)
+                )
+            })
+            .unwrap_or_default();
+        format!(
+            "<meta charset=\"utf-8\"><span data-rte-source=\"{CLIPBOARD_SOURCE}\">{payload}{}</span>",
+            self.to_html()
+        )
+    }
+
+    /// Reads the content embedded by `to_clipboard_html`
+    ///
+    /// # Returns
+    /// None if `html` was not copied from this editor or the embedded JSON
+    /// is malformed
+    pub fn from_clipboard_html(html: &str) -> Option<Self> {
+        if !html.contains("data-rte-source") {
+            return None;
+        }
+        let start = html.find(PAYLOAD_START)? + PAYLOAD_START.len();
+        let end = start + html[start..].find(PAYLOAD_END)?;
+        serde_json::from_str(&html[start..end]).ok()
+    }
+
+    /// Returns true if every offset lies within the text and every link,
+    /// color and image would survive `sanitizer`
+    fn passes_sanitizer(&self, sanitizer: &HtmlSanitizer) -> bool {
+        let len = self.text.chars().count();
+        let formats_ok = self.formats.iter().all(|run| {
+            run.start <= run.end
+                && run.end <= len
+                && run.formats.iter().all(|format| match format {
+                    InlineFormat::Link { url } => {
+                        sanitizer.allows_tag("a") && sanitizer.is_safe_url(url)
+                    }
+                    InlineFormat::TextColor { color } | InlineFormat::BackgroundColor { color } => {
+                        sanitizer.is_valid_color(color)
+                    }
+                    _ => true,
+                })
+        });
+        let images_ok = self.images.iter().all(|(offset, image)| {
+            *offset < len && sanitizer.allows_tag("img") && image.has_safe_src()
+        });
+        let blocks_ok = self.blocks.iter().all(|(offset, _)| *offset <= len);
+        formats_ok && images_ok && blocks_ok
+    }
+
     /// Creates ClipboardContent from HTML
     pub fn from_html(html: &str) -> Result<Self, String> {
+        Self::from_html_with_sanitizer(html, &HtmlSanitizer::new())
+    }
+
+    /// Creates ClipboardContent from HTML using a custom sanitizer
+    ///
+    /// HTML copied from Word or Google Docs goes through
+    /// `normalize_office_html` first.
+    pub fn from_html_with_sanitizer(html: &str, sanitizer: &HtmlSanitizer) -> Result<Self, String> {
         // Use the Document's HTML parser to parse the content
-        let doc = Document::from_html(html).map_err(|e| e.to_string())?;
+        let html = normalize_office_html(html);
+        let doc =
+            Document::from_html_with_sanitizer(&html, sanitizer).map_err(|e| e.to_string())?;
 
         // Extract text and formats
         let text = doc.get_content();
@@ -103,13 +283,118 @@ impl ClipboardContent {
             })
             .collect();
 
-        Ok(Self { text, formats })
+        // Every line starts inside the content, as if copied whole
+        let mut offset = 0;
+        let mut blocks = Vec::new();
+        for line in text.split('\n') {
+            blocks.push((offset, doc.get_block_type_at(Position::new(offset))));
+            offset += line.chars().count() + 1;
+        }
+
+        Ok(Self {
+            text,
+            formats,
+            images: doc.embeds().images().to_vec(),
+            blocks,
+        })
+    }
+
+    /// Converts to plain text for clipboard, with soft breaks as newlines
+    ///
+    /// Lines the content starts keep their structure as plain text: list
+    /// items start with their bullet or number and quoted lines with `> `,
+    /// as from `Document::to_plain_text_structured`. Numbered lists count
+    /// from the first copied item.
+    pub fn to_plain_text(&self) -> String {
+        if self.blocks.is_empty() {
+            return self.text.replace(SOFT_BREAK, "\n");
+        }
+        self.to_document().to_plain_text_structured()
+    }
+
+    /// Converts to Markdown for clipboard
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    /// use rte_core::formatting::{BlockType, InlineFormat};
+    /// use rte_core::selection::Selection;
+    ///
+    /// let mut doc = Document::from_text("Milk\nEggs");
+    /// doc.set_block_type(Range::from_offsets(0, 9), BlockType::BulletList);
+    /// doc.apply_format(Range::from_offsets(5, 9), InlineFormat::Bold);
+    /// doc.set_selection(Selection::new(Position::new(0), Position::new(9)));
+    ///
+    /// let content = doc.copy();
+    /// assert_eq!(content.to_markdown(), "- Milk\n- **Eggs**");
+    /// assert_eq!(content.to_plain_text(), "• Milk\n• Eggs");
+    /// ```
+    pub fn to_markdown(&self) -> String {
+        if self.text.is_empty() {
+            return String::new();
+        }
+        self.to_document().to_markdown()
+    }
+
+    /// Builds a document holding the content, to export it with the
+    /// document's serializers
+    fn to_document(&self) -> Document {
+        let mut doc = Document::from_text(&self.text);
+        for run in &self.formats {
+            let range = Range::from_offsets(run.start, run.end);
+            for format in &run.formats {
+                doc.formats_mut().apply_format(range, format.clone());
+            }
+        }
+        for (offset, image) in &self.images {
+            doc.embeds_mut().insert(*offset, image.clone());
+        }
+        for (offset, block_type) in &self.blocks {
+            let end = doc.line_end_from(*offset);
+            doc.formats_mut()
+                .set_block_type(Range::from_offsets(*offset, end), block_type.clone());
+        }
+        doc
     }
 
     /// Returns true if the clipboard content is empty
     pub fn is_empty(&self) -> bool {
         self.text.is_empty()
     }
+
+    /// Splits the content into one piece per line, without line breaks
+    ///
+    /// Formats and images move with their line. Block types are dropped, as
+    /// each piece is pasted into an existing line.
+    fn split_lines(&self) -> Vec<ClipboardContent> {
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        for line in self.text.split('\n') {
+            let end = start + line.chars().count();
+            pieces.push(ClipboardContent {
+                text: line.to_string(),
+                formats: self
+                    .formats
+                    .iter()
+                    .filter(|run| run.start < end && run.end > start)
+                    .map(|run| SerializableFormatRun {
+                        start: run.start.max(start) - start,
+                        end: run.end.min(end) - start,
+                        formats: run.formats.clone(),
+                    })
+                    .collect(),
+                images: self
+                    .images
+                    .iter()
+                    .filter(|(offset, _)| (start..end).contains(offset))
+                    .map(|(offset, image)| (offset - start, image.clone()))
+                    .collect(),
+                blocks: Vec::new(),
+            });
+            start = end + 1;
+        }
+        pieces
+    }
 }
 
 impl Default for ClipboardContent {
@@ -128,6 +413,10 @@ fn apply_html_formats(text: &str, formats: &HashSet<InlineFormat>) -> String {
     let has_underline = formats.contains(&InlineFormat::Underline);
     let has_strikethrough = formats.contains(&InlineFormat::Strikethrough);
     let has_code = formats.contains(&InlineFormat::Code);
+    let has_highlight = formats.contains(&InlineFormat::Highlight);
+    let has_small_caps = formats.contains(&InlineFormat::SmallCaps);
+    let has_subscript = formats.contains(&InlineFormat::Subscript);
+    let has_superscript = formats.contains(&InlineFormat::Superscript);
 
     let link = formats.iter().find_map(|f| match f {
         InlineFormat::Link { url } => Some(url.clone()),
@@ -144,13 +433,28 @@ fn apply_html_formats(text: &str, formats: &HashSet<InlineFormat>) -> String {
         _ => None,
     });
 
-    // Apply formats in order: colors (outermost) -> link -> bold -> italic -> underline -> strikethrough -> code (innermost)
+    // Apply formats in order: colors (outermost) -> link -> highlight -> bold -> italic -> underline -> strikethrough -> small caps -> sub/sup -> code (innermost)
 
     // Code (innermost for inline)
     if has_code {
         result = format!("<code>{}</code>", result);
     }
 
+    // Subscript and superscript
+    if has_subscript {
+        result = format!("<sub>{}</sub>", result);
+    } else if has_superscript {
+        result = format!("<sup>{}</sup>", result);
+    }
+
+    // Small caps
+    if has_small_caps {
+        result = format!(
+            "<span style=\"font-variant: small-caps;\">{}</span>",
+            result
+        );
+    }
+
     // Strikethrough
     if has_strikethrough {
         result = format!("<del>{}</del>", result);
@@ -171,6 +475,11 @@ fn apply_html_formats(text: &str, formats: &HashSet<InlineFormat>) -> String {
         result = format!("<strong>{}</strong>", result);
     }
 
+    // Highlight
+    if has_highlight {
+        result = format!("<mark>{}</mark>", result);
+    }
+
     // Link
     if let Some(url) = link {
         let escaped_url = escape_html_attribute(&url);
@@ -198,13 +507,32 @@ fn apply_html_formats(text: &str, formats: &HashSet<InlineFormat>) -> String {
     result
 }
 
-/// Escapes special HTML characters in text content
+/// Escapes special HTML characters in text content, writing soft breaks
+/// as `<br>`
 fn escape_html(text: &str) -> String {
     text.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
         .replace('"', "&quot;")
         .replace('\'', "&#39;")
+        .replace(SOFT_BREAK, "<br>")
+}
+
+/// Escapes `-`, `<` and `>` so JSON can sit inside an HTML comment
+///
+/// Serialized clipboard content only has these characters inside strings,
+/// where the `\u` escapes decode back to them.
+fn escape_json_for_comment(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    for c in json.chars() {
+        match c {
+            '-' => escaped.push_str("\\u002d"),
+            '<' => escaped.push_str("\\u003c"),
+            '>' => escaped.push_str("\\u003e"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
 }
 
 /// Escapes special HTML characters in attribute values
@@ -216,6 +544,262 @@ fn escape_html_attribute(text: &str) -> String {
         .replace('\'', "&#39;")
 }
 
+/// Returns true if `html` looks copied from Word or Google Docs
+fn is_office_html(html: &str) -> bool {
+    [
+        "mso-",
+        "<o:p",
+        "urn:schemas-microsoft-com",
+        "docs-internal-guid",
+    ]
+    .iter()
+    .any(|marker| html.contains(marker))
+}
+
+/// Rewrites HTML copied from Word or Google Docs into the markup the HTML
+/// importer reads
+///
+/// Word writes list items as paragraphs styled with `mso-list`, their
+/// bullet or number as text in a span styled `mso-list:Ignore`; they become
+/// `<ul>` or `<ol>` items, nested by their level. Its `<o:p>` tags are
+/// unwrapped, and title, subtitle and quote paragraphs become headings and
+/// block quotes. Google Docs writes formatting as span styles, such as
+/// `font-weight:700`, which become the matching tags, and wraps the whole
+/// fragment in a `<b style="font-weight:normal">`, which is dropped. Other
+/// HTML is returned unchanged.
+///
+/// # Example
+/// ```
+/// use rte_core::operations::clipboard::normalize_office_html;
+///
+/// let html = "<p class=MsoListParagraph style='mso-list:l0 level1 lfo1'>\
+///     <span style='mso-list:Ignore'>1.&nbsp;</span>First<o:p></o:p></p>";
+/// assert_eq!(normalize_office_html(html), "<ol><li>First</li></ol>");
+/// ```
+pub fn normalize_office_html(html: &str) -> Cow<'_, str> {
+    if !is_office_html(html) {
+        return Cow::Borrowed(html);
+    }
+
+    let tokens = html_parser::parse(html);
+    let mut output = String::with_capacity(html.len());
+    // Tags to write when each open element closes, innermost first
+    let mut closes: Vec<Vec<&str>> = Vec::new();
+    // Lists opened for Word list paragraphs, and the depth of `closes` they
+    // sit at
+    let mut lists: Vec<&str> = Vec::new();
+    let mut list_depth = 0;
+    // Nesting inside a skipped `mso-list:Ignore` span
+    let mut skipped = 0;
+
+    for (index, token) in tokens.iter().enumerate() {
+        if skipped > 0 {
+            match token {
+                HtmlToken::OpenTag { .. } => skipped += 1,
+                HtmlToken::CloseTag { .. } => skipped -= 1,
+                _ => {}
+            }
+            continue;
+        }
+        // Anything but whitespace outside the list paragraphs ends the lists
+        let ends_lists = closes.len() == list_depth
+            && match token {
+                HtmlToken::Text { content } => !content.trim().is_empty(),
+                HtmlToken::OpenTag { attributes, .. } => word_list_level(attributes).is_none(),
+                _ => true,
+            };
+        if ends_lists {
+            close_lists(&mut output, &mut lists, 0);
+        }
+
+        match token {
+            HtmlToken::OpenTag { name, attributes } => {
+                let style = normalized_style(attributes);
+                let class = attributes
+                    .get("class")
+                    .map(|class| class.to_ascii_lowercase())
+                    .unwrap_or_default();
+                if style.contains("mso-list:ignore") {
+                    skipped = 1;
+                } else if name == "o:p" {
+                    closes.push(Vec::new());
+                } else if let Some(level) = word_list_level(attributes) {
+                    let list = if is_ordered_marker(&word_list_marker(&tokens[index + 1..])) {
+                        "ol"
+                    } else {
+                        "ul"
+                    };
+                    close_lists(&mut output, &mut lists, level);
+                    if lists.len() == level {
+                        if lists.last() == Some(&list) {
+                            output.push_str("</li>");
+                        } else {
+                            close_lists(&mut output, &mut lists, level - 1);
+                        }
+                    }
+                    if lists.is_empty() {
+                        list_depth = closes.len();
+                    }
+                    while lists.len() < level {
+                        output.push_str(&format!("<{list}>"));
+                        lists.push(list);
+                    }
+                    output.push_str("<li>");
+                    closes.push(Vec::new());
+                } else if matches!(name.as_str(), "b" | "strong")
+                    && style_declarations(&style).any(|(property, value)| {
+                        property == "font-weight" && font_weight(value) < 600
+                    })
+                {
+                    closes.push(Vec::new());
+                } else {
+                    let renamed = match class.as_str() {
+                        "msotitle" => "h1",
+                        "msosubtitle" => "h2",
+                        "msoquote" | "msointensequote" => "blockquote",
+                        _ => name.as_str(),
+                    };
+                    output.push_str(&open_tag(renamed, attributes));
+                    let mut close = vec![renamed];
+                    if name == "span" {
+                        for tag in style_tags(&style) {
+                            output.push_str(&format!("<{tag}>"));
+                            close.insert(0, tag);
+                        }
+                    }
+                    closes.push(close);
+                }
+            }
+            HtmlToken::CloseTag { .. } => {
+                for tag in closes.pop().unwrap_or_default() {
+                    output.push_str(&format!("</{tag}>"));
+                }
+            }
+            HtmlToken::SelfClosing { name, attributes } => {
+                output.push_str(&open_tag(name, attributes));
+            }
+            HtmlToken::Text { content } => output.push_str(content),
+        }
+    }
+    close_lists(&mut output, &mut lists, 0);
+    Cow::Owned(output)
+}
+
+/// Closes the open list items and lists nested deeper than `level`
+fn close_lists(output: &mut String, lists: &mut Vec<&str>, level: usize) {
+    while lists.len() > level {
+        let list = lists.pop().unwrap_or_default();
+        output.push_str(&format!("</li></{list}>"));
+    }
+}
+
+/// Writes an open tag, with the attribute values as they were parsed
+fn open_tag(name: &str, attributes: &html_parser::Attributes) -> String {
+    let mut attributes: Vec<_> = attributes.iter().collect();
+    attributes.sort();
+    let mut tag = format!("<{name}");
+    for (attribute, value) in attributes {
+        tag.push_str(&format!(
+            " {attribute}=\"{}\"",
+            value.replace('"', "&quot;")
+        ));
+    }
+    tag.push('>');
+    tag
+}
+
+/// Returns the style attribute lowercased and without whitespace
+fn normalized_style(attributes: &html_parser::Attributes) -> String {
+    attributes
+        .get("style")
+        .map(|style| style.to_ascii_lowercase().replace(char::is_whitespace, ""))
+        .unwrap_or_default()
+}
+
+/// Splits a normalized style into `(property, value)` pairs
+fn style_declarations(style: &str) -> impl Iterator<Item = (&str, &str)> {
+    style
+        .split(';')
+        .filter_map(|declaration| declaration.split_once(':'))
+}
+
+/// Returns the numeric weight of a CSS `font-weight` value
+fn font_weight(value: &str) -> u16 {
+    match value {
+        "bold" | "bolder" => 700,
+        value => value.parse().unwrap_or(400),
+    }
+}
+
+/// Returns the tags for the formats a normalized span style sets
+fn style_tags(style: &str) -> Vec<&'static str> {
+    let mut tags = Vec::new();
+    for (property, value) in style_declarations(style) {
+        match property {
+            "font-weight" if font_weight(value) >= 600 => tags.push("b"),
+            "font-style" if value == "italic" || value == "oblique" => tags.push("i"),
+            "text-decoration" | "text-decoration-line" => {
+                if value.contains("underline") {
+                    tags.push("u");
+                }
+                if value.contains("line-through") {
+                    tags.push("s");
+                }
+            }
+            "vertical-align" if value == "super" => tags.push("sup"),
+            "vertical-align" if value == "sub" => tags.push("sub"),
+            _ => {}
+        }
+    }
+    tags
+}
+
+/// Returns the level, from 1, of a Word list paragraph
+fn word_list_level(attributes: &html_parser::Attributes) -> Option<usize> {
+    let style = normalized_style(attributes);
+    let (_, list) = style_declarations(&style).find(|(property, _)| *property == "mso-list")?;
+    let level = &list[list.find("level")? + "level".len()..];
+    let digits = level.chars().take_while(char::is_ascii_digit).count();
+    level[..digits].parse().ok().filter(|&level| level > 0)
+}
+
+/// Returns the text of the `mso-list:Ignore` span in a Word list paragraph,
+/// given the tokens after the paragraph's open tag
+fn word_list_marker(tokens: &[HtmlToken]) -> String {
+    let mut marker = String::new();
+    let mut depth = 0;
+    for token in tokens {
+        match token {
+            HtmlToken::OpenTag { .. } if depth > 0 => depth += 1,
+            HtmlToken::OpenTag { attributes, .. }
+                if normalized_style(attributes).contains("mso-list:ignore") =>
+            {
+                depth = 1
+            }
+            HtmlToken::CloseTag { name } if depth == 0 && name == "p" => break,
+            HtmlToken::CloseTag { .. } if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            HtmlToken::Text { content } if depth > 0 => marker.push_str(content),
+            _ => {}
+        }
+    }
+    marker
+}
+
+/// Returns true if a Word list marker numbers its item, as `1.`, `a)` or
+/// `iv.` do, rather than being a bullet
+fn is_ordered_marker(marker: &str) -> bool {
+    let marker = marker.replace("&nbsp;", " ");
+    let marker = marker.trim_matches(|c: char| c.is_whitespace());
+    marker.chars().count() > 1
+        && marker.ends_with(['.', ')'])
+        && marker.chars().next().is_some_and(char::is_alphanumeric)
+}
+
 impl Document {
     /// Copies the current selection to clipboard content
     ///
@@ -261,7 +845,36 @@ impl Document {
             })
             .collect();
 
-        ClipboardContent::from_text_and_formats(text, formats)
+        let start = range.start_offset();
+        let images = self
+            .embeds()
+            .images_in(range)
+            .into_iter()
+            .map(|(offset, image)| (offset - start, image))
+            .collect();
+
+        // Record a block type for every line that starts inside the selection
+        let starts_line =
+            start == 0 || self.get_text_in_range(Range::from_offsets(start - 1, start)) == "\n";
+        let blocks = starts_line
+            .then_some(0)
+            .into_iter()
+            .chain(
+                text.chars()
+                    .enumerate()
+                    .filter(|(_, c)| *c == '\n')
+                    .map(|(i, _)| i + 1)
+                    .filter(|i| start + i < range.end_offset()),
+            )
+            .map(|i| (i, self.get_block_type_at(Position::new(start + i))))
+            .collect();
+
+        ClipboardContent {
+            text,
+            formats,
+            images,
+            blocks,
+        }
     }
 
     /// Cuts the current selection to clipboard content
@@ -298,8 +911,16 @@ impl Document {
     /// Pastes clipboard content at the current cursor position
     ///
     /// If there is a selection, it will be replaced with the pasted content.
-    /// The pasted content retains all formatting from the clipboard.
-    /// This operation is undoable.
+    /// The pasted content retains its formatting, images and the block types
+    /// of lines it starts. The whole paste is recorded as one undo step.
+    /// Control characters are handled according to the document's
+    /// `ControlCharPolicy`. Bare URLs and email addresses in the pasted
+    /// text are linked as part of the same undo step, unless link
+    /// autodetection is off.
+    ///
+    /// With multiple selections, the content is pasted at each of them. If
+    /// it has exactly one line per selection, the lines are distributed
+    /// instead, one per selection in document order.
     ///
     /// # Arguments
     ///
@@ -312,10 +933,158 @@ impl Document {
         &mut self,
         content: &ClipboardContent,
     ) -> Result<(), crate::operations::CommandError> {
-        if content.is_empty() {
+        if content.is_empty() || self.suppress_duplicate_paste(content) {
             return Ok(());
         }
 
+        let result = self.paste_unguarded(content);
+        let (selection, version) = (self.selection, self.version());
+        if result.is_ok()
+            && let Some(guard) = &mut self.paste_guard
+        {
+            guard.last = Some((content.clone(), selection, version, now()));
+        }
+        result
+    }
+
+    /// Pastes `content` without checking it against the duplicate paste
+    /// guard
+    fn paste_unguarded(
+        &mut self,
+        content: &ClipboardContent,
+    ) -> Result<(), crate::operations::CommandError> {
+        let content = content.with_control_char_policy(self.control_char_policy());
+        self.begin_batch();
+        let result = if self.has_multiple_selections() {
+            self.paste_at_selections(&content)
+        } else {
+            self.paste_content(&content).map(|pasted| vec![pasted])
+        };
+        if let Ok(pasted) = &result {
+            self.auto_link_pasted(pasted);
+        }
+        self.end_batch();
+        result.map(|_| ())
+    }
+
+    /// Sets how soon a repeated paste counts as a duplicate, or None to
+    /// paste every time (the default)
+    ///
+    /// A paste is dropped as a duplicate when it arrives within `window`
+    /// of an identical one, with the selection that paste left behind and
+    /// no edit in between. That is the signature of a browser firing the
+    /// same paste event twice; pasting again after moving the cursor or
+    /// typing is never suppressed. Dropped pastes are collected for
+    /// `take_suppressed_pastes`.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::Document;
+    /// use std::time::Duration;
+    ///
+    /// let mut doc = Document::new();
+    /// doc.set_paste_dedup_window(Some(Duration::from_millis(100)));
+    /// doc.paste_plain_text("Hi").unwrap();
+    /// doc.paste_plain_text("Hi").unwrap();
+    /// assert_eq!(doc.get_content(), "Hi");
+    /// assert_eq!(doc.take_suppressed_pastes()[0].offset, 2);
+    /// ```
+    pub fn set_paste_dedup_window(&mut self, window: Option<Duration>) {
+        self.paste_guard = window.map(PasteGuard::new);
+    }
+
+    /// Returns the window set by `set_paste_dedup_window`
+    pub fn get_paste_dedup_window(&self) -> Option<Duration> {
+        self.paste_guard.as_ref().map(|guard| guard.window)
+    }
+
+    /// Removes and returns the pastes suppressed as duplicates since the
+    /// last call, oldest first
+    pub fn take_suppressed_pastes(&mut self) -> Vec<SuppressedPaste> {
+        self.paste_guard
+            .as_mut()
+            .map(|guard| std::mem::take(&mut guard.suppressed))
+            .unwrap_or_default()
+    }
+
+    /// Records and returns true if pasting `content` now would repeat the
+    /// last paste
+    fn suppress_duplicate_paste(&mut self, content: &ClipboardContent) -> bool {
+        let (selection, version) = (self.selection, self.version());
+        let Some(guard) = &mut self.paste_guard else {
+            return false;
+        };
+        let Some((last, last_selection, last_version, at)) = &guard.last else {
+            return false;
+        };
+        let elapsed = now().saturating_sub(*at);
+        if last != content
+            || *last_selection != selection
+            || *last_version != version
+            || elapsed > guard.window
+        {
+            return false;
+        }
+        guard.suppressed.push(SuppressedPaste {
+            text: content.text.clone(),
+            offset: selection.range().normalize().start_offset(),
+            elapsed,
+        });
+        true
+    }
+
+    /// Pastes `content` at every selection, returning the pasted ranges
+    fn paste_at_selections(
+        &mut self,
+        content: &ClipboardContent,
+    ) -> Result<Vec<Range>, crate::operations::CommandError> {
+        let mut ranges = self.selection_ranges_descending();
+        ranges.reverse();
+        let primary = self.selection.range().normalize();
+
+        let lines = content.split_lines();
+        let pieces: Vec<&ClipboardContent> = if ranges.len() > 1 && lines.len() == ranges.len() {
+            lines.iter().collect()
+        } else {
+            vec![content; ranges.len()]
+        };
+
+        // Paste the last selection first so the others don't move
+        for (range, piece) in ranges.iter().zip(&pieces).rev() {
+            self.selection = crate::selection::Selection::new(range.start, range.end);
+            self.paste_content(piece)?;
+        }
+
+        // Each selection ends up collapsed after its pasted content
+        let mut shift = 0isize;
+        let mut carets = Vec::with_capacity(ranges.len());
+        let mut pasted = Vec::with_capacity(ranges.len());
+        for (range, piece) in ranges.iter().zip(&pieces) {
+            let length = piece.text.chars().count();
+            let end = range.start_offset().saturating_add_signed(shift) + length;
+            carets.push((*range == primary, Position::new(end)));
+            pasted.push(Range::from_offsets(end - length, end));
+            shift += length as isize - range.len() as isize;
+        }
+        let primary = carets
+            .iter()
+            .find(|(is_primary, _)| *is_primary)
+            .map_or(self.selection.anchor, |(_, caret)| *caret);
+        self.set_selections(
+            crate::selection::Selection::collapsed(primary),
+            carets
+                .into_iter()
+                .filter(|(is_primary, _)| !is_primary)
+                .map(|(_, caret)| crate::selection::Selection::collapsed(caret)),
+        );
+        Ok(pasted)
+    }
+
+    /// Pastes `content` at the selection, returning the pasted range
+    fn paste_content(
+        &mut self,
+        content: &ClipboardContent,
+    ) -> Result<Range, crate::operations::CommandError> {
         let insert_pos = if self.selection.is_collapsed() {
             self.selection.anchor
         } else {
@@ -325,11 +1094,30 @@ impl Document {
             self.selection.anchor
         };
 
-        // Insert the text
-        self.insert_text(insert_pos, &content.text)?;
+        // Insert the text, turning embed characters back into images
+        let base_offset = insert_pos.offset();
+        let mut segment = String::new();
+        let mut segment_start = base_offset;
+        for (i, ch) in content.text.chars().enumerate() {
+            let image = (ch == EMBED_CHAR)
+                .then(|| content.images.iter().find(|(offset, _)| *offset == i))
+                .flatten();
+            if let Some((_, image)) = image {
+                if !segment.is_empty() {
+                    self.insert_text(Position::new(segment_start), &segment)?;
+                }
+                self.insert_image(Position::new(base_offset + i), image.clone())?;
+                segment.clear();
+                segment_start = base_offset + i + 1;
+            } else {
+                segment.push(ch);
+            }
+        }
+        if !segment.is_empty() {
+            self.insert_text(Position::new(segment_start), &segment)?;
+        }
 
         // Apply the formats
-        let base_offset = insert_pos.offset();
         for format_run in &content.formats {
             let start = Position::new(base_offset + format_run.start);
             let end = Position::new(base_offset + format_run.end);
@@ -340,17 +1128,84 @@ impl Document {
             }
         }
 
+        // Lines the paste starts take their original block type; the line
+        // the cursor was in keeps its own unless the paste began at its start
+        let at_line_start = base_offset == 0
+            || self.get_text_in_range(Range::from_offsets(base_offset - 1, base_offset)) == "\n";
+        for (offset, block_type) in &content.blocks {
+            if *offset == 0 && !at_line_start {
+                continue;
+            }
+            let start = base_offset + offset;
+            let end = self.line_end_from(start);
+            self.set_block_type(Range::from_offsets(start, end), block_type.clone());
+        }
+
         // Move cursor to the end of pasted content
         let end_pos = Position::new(base_offset + content.text.chars().count());
         self.selection = crate::selection::Selection::collapsed(end_pos);
 
-        Ok(())
+        Ok(Range::new(insert_pos, end_pos))
+    }
+
+    /// Cuts the selection of `from` and pastes it at the cursor of `to`
+    ///
+    /// Formats, images and block types move with the text, and each document
+    /// records a single undo step. If pasting fails, the cut is undone so no
+    /// content is lost. The paste is never dropped as a duplicate by
+    /// `set_paste_dedup_window`, since the text has already left `from`.
+    ///
+    /// # Returns
+    ///
+    /// The transferred content, empty if `from` had no selection.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    /// use rte_core::formatting::InlineFormat;
+    /// use rte_core::selection::Selection;
+    ///
+    /// let mut from = Document::from_text("Hello World");
+    /// from.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+    /// from.set_selection(Selection::new(Position::new(0), Position::new(6)));
+    /// let mut to = Document::new();
+    ///
+    /// Document::transfer(&mut from, &mut to).unwrap();
+    /// assert_eq!(from.get_content(), "World");
+    /// assert_eq!(to.get_content(), "Hello ");
+    /// ```
+    pub fn transfer(
+        from: &mut Document,
+        to: &mut Document,
+    ) -> Result<ClipboardContent, crate::operations::CommandError> {
+        let content = from.cut()?;
+        if content.is_empty() {
+            return Ok(content);
+        }
+
+        let version = to.version();
+        if let Err(err) = to.paste_unguarded(&content) {
+            // Restore the source even if rolling back the target fails
+            let rolled_back = if to.version() != version {
+                to.undo()
+            } else {
+                Ok(())
+            };
+            from.undo()?;
+            rolled_back?;
+            return Err(err);
+        }
+
+        Ok(content)
     }
 
     /// Pastes HTML content from the clipboard
     ///
     /// Parses the HTML, sanitizes it, and pastes the resulting content.
     /// This is a convenience method for pasting from external applications.
+    /// HTML copied from this editor is pasted from its embedded JSON
+    /// instead, as long as every link, color and image in it passes the
+    /// sanitizer.
     ///
     /// # Arguments
     ///
@@ -360,7 +1215,12 @@ impl Document {
     ///
     /// Returns an error if HTML parsing or paste operation fails.
     pub fn paste_html(&mut self, html: &str) -> Result<(), String> {
-        let content = ClipboardContent::from_html(html)?;
+        let default_sanitizer = HtmlSanitizer::new();
+        let sanitizer = self.html_sanitizer().unwrap_or(&default_sanitizer);
+        let content = match ClipboardContent::from_clipboard_html(html) {
+            Some(content) if content.passes_sanitizer(sanitizer) => content,
+            _ => ClipboardContent::from_html_with_sanitizer(html, sanitizer)?,
+        };
         self.paste(&content)
             .map_err(|e| format!("Paste failed: {}", e))
     }
@@ -385,6 +1245,7 @@ impl Document {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::selection::Selection;
 
     #[test]
     fn test_clipboard_content_new() {
@@ -612,7 +1473,9 @@ mod tests {
         let mut doc = Document::from_text("Hello");
         doc.set_selection(crate::selection::Selection::collapsed(Position::new(5)));
 
-        doc.paste_html("<p><strong> World</strong></p>").unwrap();
+        // An inline fragment keeps its leading space; in a paragraph it would
+        // be collapsed away
+        doc.paste_html("<strong> World</strong>").unwrap();
         assert_eq!(doc.get_content(), "Hello World");
 
         let formats = doc.get_formats_at(Position::new(7));
@@ -693,4 +1556,372 @@ mod tests {
         assert!(!html.contains("<script>"));
         assert!(html.contains("&lt;script&gt;"));
     }
+
+    #[test]
+    fn test_clipboard_html_round_trips_through_payload() {
+        let mut source = Document::from_text("Title --> x\nbody");
+        source.set_block_type(Range::from_offsets(0, 11), BlockType::heading(2));
+        source.apply_format(
+            Range::from_offsets(12, 16),
+            InlineFormat::Link {
+                url: "https://example.com/a-b".to_string(),
+            },
+        );
+        source.select_all();
+        let html = source.copy().to_clipboard_html();
+        assert!(html.starts_with("<meta charset=\"utf-8\">"));
+        assert!(html.contains(&format!("data-rte-source=\"{CLIPBOARD_SOURCE}\"")));
+        // The payload comment ends only once, at its own end
+        assert_eq!(html.matches(PAYLOAD_END).count(), 1);
+
+        let mut doc = Document::new();
+        doc.paste_html(&html).unwrap();
+        assert_eq!(doc.get_content(), source.get_content());
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::heading(2)
+        );
+        assert_eq!(doc.to_json().unwrap(), source.to_json().unwrap());
+    }
+
+    #[test]
+    fn test_unsafe_clipboard_payload_falls_back_to_markup() {
+        let mut content = ClipboardContent::from_text_and_formats("hi".to_string(), Vec::new());
+        content.blocks.push((0, BlockType::heading(1)));
+        let html = content.to_clipboard_html().replace(
+            "\"formats\":[]",
+            r#""formats":[{"start":0,"end":2,"formats":[{"Link":{"url":"javascript:alert(1)"}}]}]"#,
+        );
+        let parsed = ClipboardContent::from_clipboard_html(&html).unwrap();
+        assert!(!parsed.passes_sanitizer(&HtmlSanitizer::new()));
+
+        let mut doc = Document::new();
+        doc.paste_html(&html).unwrap();
+        assert_eq!(doc.get_content(), "hi");
+        assert!(doc.get_formats_at(Position::new(1)).is_empty());
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::Paragraph
+        );
+
+        assert!(ClipboardContent::from_clipboard_html("<b>hi</b>").is_none());
+    }
+
+    #[test]
+    fn test_paste_is_single_undo_step() {
+        let mut source = Document::from_text("Hello www.x.io");
+        source.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+        source.set_selection(Selection::new(Position::new(0), Position::new(14)));
+        let content = source.copy();
+
+        let mut doc = Document::from_text("Start ");
+        doc.set_selection(Selection::collapsed(Position::new(6)));
+        doc.paste(&content).unwrap();
+        assert_eq!(doc.get_content(), "Start Hello www.x.io");
+        assert!(
+            doc.get_formats_at(Position::new(12))
+                .iter()
+                .any(|format| matches!(format, InlineFormat::Link { .. }))
+        );
+
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "Start ");
+        assert!(doc.get_formats_at(Position::new(0)).is_empty());
+        assert!(!doc.can_undo());
+    }
+
+    #[test]
+    fn test_copy_paste_images_and_blocks() {
+        let mut source = Document::from_text("Title\nab");
+        source.set_block_type(Range::from_offsets(0, 5), BlockType::heading(2));
+        source
+            .insert_image(Position::new(7), Image::new("a.png", "A"))
+            .unwrap();
+        source.select_all();
+        let content = source.copy();
+        assert_eq!(content.images.len(), 1);
+        assert_eq!(content.blocks.len(), 2);
+
+        let mut doc = Document::new();
+        doc.paste(&content).unwrap();
+        assert_eq!(doc.get_content(), source.get_content());
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::heading(2)
+        );
+        assert_eq!(doc.get_image_at(Position::new(7)).unwrap().src, "a.png");
+
+        doc.undo().unwrap();
+        doc.redo().unwrap();
+        assert_eq!(doc.get_image_at(Position::new(7)).unwrap().src, "a.png");
+    }
+
+    #[test]
+    fn test_paste_strips_control_characters() {
+        let mut bold = HashSet::new();
+        bold.insert(InlineFormat::Bold);
+        let content = ClipboardContent::from_text_and_formats(
+            "a\x07b\x0bcd".to_string(),
+            vec![SerializableFormatRun {
+                start: 2,
+                end: 5,
+                formats: bold,
+            }],
+        );
+
+        let mut doc = Document::new();
+        doc.paste(&content).unwrap();
+        assert_eq!(doc.get_content(), "ab\ncd");
+        assert!(doc.get_formats_at(Position::new(0)).is_empty());
+        assert!(
+            doc.get_formats_at(Position::new(3))
+                .contains(&InlineFormat::Bold)
+        );
+        assert!(doc.get_formats_at(Position::new(4)).is_empty());
+        assert_eq!(doc.get_selection(), Selection::collapsed(Position::new(5)));
+
+        let mut doc = Document::new();
+        doc.set_control_char_policy(ControlCharPolicy::Keep);
+        doc.paste(&content).unwrap();
+        assert_eq!(doc.get_content(), "a\x07b\x0bcd");
+    }
+
+    #[test]
+    fn test_paste_mid_line_keeps_current_block() {
+        let mut source = Document::from_text("Quote");
+        source.set_block_type(Range::from_offsets(0, 5), BlockType::block_quote());
+        source.select_all();
+        let content = source.copy();
+
+        let mut doc = Document::from_text("Text");
+        doc.set_selection(Selection::collapsed(Position::new(2)));
+        doc.paste(&content).unwrap();
+        assert_eq!(doc.get_content(), "TeQuotext");
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::Paragraph
+        );
+    }
+
+    #[test]
+    fn test_paste_distributes_lines_over_selections() {
+        let mut bold = HashSet::new();
+        bold.insert(InlineFormat::Bold);
+        let content = ClipboardContent::from_text_and_formats(
+            "one\ntwo\nthree".to_string(),
+            vec![SerializableFormatRun {
+                start: 2,
+                end: 5,
+                formats: bold,
+            }],
+        );
+
+        let mut doc = Document::from_text("a-\nb-\nc-x");
+        doc.set_selection(Selection::collapsed(Position::new(5)));
+        doc.add_selection(Selection::collapsed(Position::new(2)));
+        doc.add_selection(Selection::new(Position::new(8), Position::new(9)));
+        doc.paste(&content).unwrap();
+        assert_eq!(doc.get_content(), "a-one\nb-two\nc-three");
+        assert_eq!(
+            doc.get_selections(),
+            vec![
+                Selection::collapsed(Position::new(11)),
+                Selection::collapsed(Position::new(5)),
+                Selection::collapsed(Position::new(19)),
+            ]
+        );
+        assert!(
+            doc.get_formats_at(Position::new(4))
+                .contains(&InlineFormat::Bold)
+        );
+        assert!(
+            doc.get_formats_at(Position::new(8))
+                .contains(&InlineFormat::Bold)
+        );
+        assert!(doc.get_formats_at(Position::new(9)).is_empty());
+
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "a-\nb-\nc-x");
+    }
+
+    #[test]
+    fn test_paste_whole_content_at_each_selection() {
+        let mut doc = Document::from_text("ab");
+        doc.set_selection(Selection::collapsed(Position::new(1)));
+        doc.add_selection(Selection::collapsed(Position::new(2)));
+        doc.paste_plain_text("x\ny\nz").unwrap();
+        assert_eq!(doc.get_content(), "ax\ny\nzbx\ny\nz");
+        assert_eq!(
+            doc.get_selections(),
+            vec![
+                Selection::collapsed(Position::new(6)),
+                Selection::collapsed(Position::new(12)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transfer_between_documents() {
+        let mut from = Document::from_text("Hello World");
+        from.apply_format(Range::from_offsets(0, 5), InlineFormat::Italic);
+        from.clear_history();
+        from.set_selection(Selection::new(Position::new(0), Position::new(6)));
+        let mut to = Document::from_text("Say: ");
+        to.set_selection(Selection::collapsed(Position::new(5)));
+
+        let content = Document::transfer(&mut from, &mut to).unwrap();
+        assert_eq!(content.text, "Hello ");
+        assert_eq!(from.get_content(), "World");
+        assert_eq!(to.get_content(), "Say: Hello ");
+        assert!(
+            to.get_formats_at(Position::new(5))
+                .contains(&InlineFormat::Italic)
+        );
+
+        from.undo().unwrap();
+        assert_eq!(from.get_content(), "Hello World");
+        assert!(!from.can_undo());
+        to.undo().unwrap();
+        assert_eq!(to.get_content(), "Say: ");
+        assert!(!to.can_undo());
+    }
+
+    #[test]
+    fn test_repeated_transfer_is_not_deduplicated() {
+        let mut from = Document::from_text("abcabc");
+        let mut to = Document::new();
+        to.set_paste_dedup_window(Some(Duration::from_secs(60)));
+
+        for _ in 0..2 {
+            from.set_selection(Selection::new(Position::new(0), Position::new(3)));
+            Document::transfer(&mut from, &mut to).unwrap();
+        }
+        assert_eq!(from.get_content(), "");
+        assert_eq!(to.get_content(), "abcabc");
+        assert!(to.take_suppressed_pastes().is_empty());
+    }
+
+    #[test]
+    fn test_transfer_without_selection() {
+        let mut from = Document::from_text("Hello");
+        let mut to = Document::new();
+
+        let content = Document::transfer(&mut from, &mut to).unwrap();
+        assert!(content.is_empty());
+        assert!(!from.can_undo());
+        assert!(!to.can_undo());
+    }
+
+    #[test]
+    fn test_duplicate_paste_guard() {
+        let mut doc = Document::new();
+        doc.paste_plain_text("ab").unwrap();
+        doc.paste_plain_text("ab").unwrap();
+        assert_eq!(doc.get_content(), "abab");
+
+        doc.set_paste_dedup_window(Some(Duration::from_secs(60)));
+        doc.paste_plain_text("cd").unwrap();
+        doc.paste_plain_text("cd").unwrap();
+        assert_eq!(doc.get_content(), "ababcd");
+        // A different payload, a moved cursor or an edit in between is a
+        // deliberate paste
+        doc.paste_plain_text("cd!").unwrap();
+        doc.set_selection(Selection::collapsed(Position::new(0)));
+        doc.paste_plain_text("cd!").unwrap();
+        doc.insert_text(Position::new(3), "-").unwrap();
+        doc.paste_plain_text("cd!").unwrap();
+        assert_eq!(doc.get_content(), "cd!-cd!ababcdcd!");
+
+        let suppressed = doc.take_suppressed_pastes();
+        assert_eq!(suppressed.len(), 1);
+        assert_eq!(suppressed[0].text, "cd");
+        assert_eq!(suppressed[0].offset, 6);
+        assert!(doc.take_suppressed_pastes().is_empty());
+        assert!(doc.can_undo());
+    }
+
+    #[test]
+    fn test_normalize_word_html() {
+        let html = "<html xmlns:o=\"urn:schemas-microsoft-com:office:office\"><body>\
+            <p class=MsoTitle>Plan<o:p></o:p></p>\
+            <p class=MsoListParagraphCxSpFirst style='mso-list:l0 level1 lfo1'>\
+            <![if !supportLists]><span style='font-family:Symbol;mso-list:Ignore'>·\
+            <span style='font:7.0pt \"Times New Roman\"'>&nbsp;&nbsp; </span></span>\
+            <![endif]>Milk<o:p></o:p></p>\r\n\
+            <p class=MsoListParagraphCxSpMiddle style='mso-list:l0 level2 lfo1'>\
+            <span style='mso-list:Ignore'>a.<span>&nbsp; </span></span>Whole<o:p></o:p></p>\
+            <p class=MsoListParagraphCxSpLast style='mso-list:l0 level1 lfo1'>\
+            <span style='mso-list:Ignore'>·<span>&nbsp; </span></span>\
+            <b style='mso-bidi-font-weight:normal'>Eggs</b><o:p></o:p></p>\
+            <p class=MsoQuote>Quoted<o:p>&nbsp;</o:p></p></body></html>";
+        let content = ClipboardContent::from_html(html).unwrap();
+        assert_eq!(content.text, "Plan\nMilk\nWhole\nEggs\nQuoted\u{a0}");
+        assert_eq!(content.blocks[2], (10, BlockType::NumberedList));
+
+        let doc = Document::from_html(&normalize_office_html(html)).unwrap();
+        let block = |offset| doc.get_block_type_at(Position::new(offset));
+        assert_eq!(block(0), BlockType::heading(1));
+        assert_eq!(block(5), BlockType::BulletList);
+        assert_eq!(block(10), BlockType::NumberedList);
+        assert_eq!(doc.get_list_indent_at(Position::new(10)), 1);
+        assert_eq!(block(16), BlockType::BulletList);
+        assert_eq!(doc.get_list_indent_at(Position::new(16)), 0);
+        assert!(block(21).is_block_quote());
+        assert!(
+            doc.get_formats_at(Position::new(16))
+                .contains(&InlineFormat::Bold)
+        );
+    }
+
+    #[test]
+    fn test_normalize_google_docs_html() {
+        let html = "<meta charset=\"utf-8\">\
+            <b style=\"font-weight:normal;\" id=\"docs-internal-guid-1a2b\">\
+            <p dir=\"ltr\"><span style=\"font-weight:700;font-style:italic;\">Both</span>\
+            <span style=\"font-weight:400;text-decoration:underline line-through;\"> lines</span>\
+            <span style=\"vertical-align:super;\">2</span></p></b>";
+        let content = ClipboardContent::from_html(html).unwrap();
+        assert_eq!(content.text, "Both lines2");
+        let doc = Document::from_html(&normalize_office_html(html)).unwrap();
+        let formats = |offset| doc.get_formats_at(Position::new(offset));
+        assert_eq!(
+            formats(0),
+            [InlineFormat::Bold, InlineFormat::Italic].into()
+        );
+        assert_eq!(
+            formats(5),
+            [InlineFormat::Underline, InlineFormat::Strikethrough].into()
+        );
+        assert_eq!(formats(10), [InlineFormat::Superscript].into());
+
+        // Other HTML is left alone
+        let html = "<p style=\"font-weight:700\">Hi</p>";
+        assert!(matches!(normalize_office_html(html), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_copy_keeps_structure_in_plain_text_and_markdown() {
+        let mut doc = Document::from_text("Intro\nOne\nTwo\nQuote\nEnd");
+        doc.set_block_type(Range::from_offsets(6, 13), BlockType::NumberedList);
+        doc.set_block_type(
+            Range::from_offsets(14, 19),
+            BlockType::block_quote_with(None, 2),
+        );
+
+        // Only lines the selection starts get markers
+        doc.set_selection(Selection::new(Position::new(2), Position::new(22)));
+        let content = doc.copy();
+        assert_eq!(
+            content.to_plain_text(),
+            "tro\n1. One\n2. Two\n> > Quote\nEn"
+        );
+        assert_eq!(
+            content.to_markdown(),
+            "tro\n1. One\n2. Two\n> > Quote\n\nEn"
+        );
+
+        doc.set_selection(Selection::new(Position::new(8), Position::new(13)));
+        assert_eq!(doc.copy().to_plain_text(), "e\n1. Two");
+        assert_eq!(ClipboardContent::new().to_markdown(), "");
+    }
 }
@@ -0,0 +1,1305 @@
+//! Replicated document state for collaborative editing
+//!
+//! A `Replica` mirrors a `Document` as an RGA sequence: every character ever
+//! inserted is an item with a unique `OpId` and the id of the item it was
+//! typed after. Deleted characters stay behind as tombstones so concurrent
+//! inserts still find their anchor. Inline formats, images and block types
+//! are last-writer-wins registers stamped with Lamport ids, which makes
+//! merging commutative, associative and idempotent.
+//!
+//! Text edits are recorded as they reach the document's storage, so every
+//! command (including undo and redo) replicates without changes. Formats,
+//! images and block types are diffed against the document whenever state
+//! is encoded or an update arrives, which is exact because they are
+//! compared character by character.
+
+use crate::document::validation::{MAX_DOCUMENT_SIZE, validate_text_content};
+use crate::document::{Document, EMBED_CHAR, Image, Position, Range};
+use crate::formatting::storage::BlockInfo;
+use crate::formatting::{BlockType, InlineFormat};
+use crate::operations::Operation;
+use crate::serialization::varint::{self, VarintError};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+/// Version byte written at the start of every update
+const UPDATE_VERSION: u8 = 1;
+
+/// Errors that can occur while exchanging collaboration updates
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum CrdtError {
+    #[error("Collaboration is not enabled for this document")]
+    NotEnabled,
+
+    #[error("Unsupported update version: {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Malformed update: {0}")]
+    MalformedUpdate(String),
+}
+
+/// Globally unique operation id, ordered by Lamport clock then client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OpId {
+    pub clock: u64,
+    pub client: u32,
+}
+
+/// Last-writer-wins register
+#[derive(Debug, Clone, PartialEq)]
+struct Lww<T> {
+    stamp: OpId,
+    value: T,
+}
+
+impl<T> Lww<T> {
+    /// Keeps whichever write has the larger stamp, returning true if `other` won
+    fn merge(&mut self, other: Lww<T>) -> bool {
+        if other.stamp > self.stamp {
+            *self = other;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A single character in the replicated sequence
+#[derive(Debug, Clone)]
+struct Item {
+    id: OpId,
+    origin: Option<OpId>,
+    ch: char,
+    deleted: bool,
+    formats: HashMap<InlineFormat, Lww<bool>>,
+    image: Option<Lww<Option<Image>>>,
+}
+
+impl Item {
+    fn new(id: OpId, origin: Option<OpId>, ch: char) -> Self {
+        Self {
+            id,
+            origin,
+            ch,
+            deleted: false,
+            formats: HashMap::new(),
+            image: None,
+        }
+    }
+
+    fn has_format(&self, format: &InlineFormat) -> bool {
+        self.formats.get(format).is_some_and(|lww| lww.value)
+    }
+
+    fn current_image(&self) -> Option<&Image> {
+        self.image.as_ref().and_then(|lww| lww.value.as_ref())
+    }
+
+    /// Merges the state of the same item from another replica
+    fn merge(&mut self, other: Item) -> bool {
+        let mut changed = false;
+
+        if other.deleted && !self.deleted {
+            self.deleted = true;
+            changed = true;
+        }
+
+        for (format, lww) in other.formats {
+            match self.formats.entry(format) {
+                Entry::Occupied(mut entry) => changed |= entry.get_mut().merge(lww),
+                Entry::Vacant(entry) => {
+                    entry.insert(lww);
+                    changed = true;
+                }
+            }
+        }
+
+        if let Some(lww) = other.image {
+            match &mut self.image {
+                Some(current) => changed |= current.merge(lww),
+                None => {
+                    self.image = Some(lww);
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+/// Replicated state of one document
+///
+/// Block types are keyed by the newline that starts their line, or `None`
+/// for the first line, so they follow the line when text moves around it.
+#[derive(Debug, Clone)]
+pub struct Replica {
+    client_id: u32,
+    clock: u64,
+    items: Vec<Item>,
+    blocks: HashMap<Option<OpId>, Lww<BlockType>>,
+    /// Last resolved `(visible offset, item index)` pair, so local edits
+    /// near the previous one find their position without a full scan
+    marker: Option<(usize, usize)>,
+}
+
+impl Replica {
+    /// Creates an empty replica for the given client
+    pub fn new(client_id: u32) -> Self {
+        Self {
+            client_id,
+            clock: 0,
+            items: Vec::new(),
+            blocks: HashMap::new(),
+            marker: None,
+        }
+    }
+
+    /// Returns the client id used for local operations
+    pub fn client_id(&self) -> u32 {
+        self.client_id
+    }
+
+    /// Returns the number of items, including tombstones
+    pub fn item_count(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns the visible text of the replica
+    pub fn text(&self) -> String {
+        self.items
+            .iter()
+            .filter(|item| !item.deleted)
+            .map(|item| item.ch)
+            .collect()
+    }
+
+    /// Advances the Lamport clock and returns a fresh id
+    fn tick(&mut self) -> OpId {
+        self.clock += 1;
+        OpId {
+            clock: self.clock,
+            client: self.client_id,
+        }
+    }
+
+    fn visible_indices(&self) -> Vec<usize> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| !item.deleted)
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Returns the index of the item at a visible offset
+    ///
+    /// Walks from the marker left by the previous lookup, so typing and
+    /// deleting in one place costs time proportional to the distance moved
+    /// rather than to the document size.
+    fn visible_index(&mut self, offset: usize) -> Option<usize> {
+        let (mut at, mut idx) = match self.marker {
+            Some(marker) => marker,
+            None => (0, self.items.iter().position(|item| !item.deleted)?),
+        };
+        while at < offset {
+            idx += 1;
+            if !self.items.get(idx)?.deleted {
+                at += 1;
+            }
+        }
+        while at > offset {
+            idx -= 1;
+            if !self.items[idx].deleted {
+                at -= 1;
+            }
+        }
+        self.marker = Some((at, idx));
+        Some(idx)
+    }
+
+    fn block_at(&self, anchor: Option<OpId>) -> BlockType {
+        self.blocks
+            .get(&anchor)
+            .map(|lww| lww.value.clone())
+            .unwrap_or(BlockType::Paragraph)
+    }
+
+    /// Places an item after its origin, skipping concurrent siblings with
+    /// larger ids, and returns its index
+    ///
+    /// `hint` is checked first for the origin, which makes integrating runs
+    /// of consecutive characters linear instead of quadratic. The origin
+    /// must already be present.
+    fn integrate(&mut self, item: Item, hint: usize) -> usize {
+        let mut idx = match item.origin {
+            None => 0,
+            Some(origin) => {
+                let origin_idx = if self.items.get(hint).is_some_and(|i| i.id == origin) {
+                    hint
+                } else {
+                    self.items
+                        .iter()
+                        .position(|i| i.id == origin)
+                        .expect("origin is integrated before its dependants")
+                };
+                origin_idx + 1
+            }
+        };
+
+        while idx < self.items.len() && self.items[idx].id > item.id {
+            idx += 1;
+        }
+
+        self.items.insert(idx, item);
+        idx
+    }
+
+    /// Records a local insertion at a visible offset
+    pub(crate) fn local_insert(&mut self, offset: usize, text: &str) {
+        let (mut origin, mut hint) = match offset.checked_sub(1) {
+            Some(before) => match self.visible_index(before) {
+                Some(idx) => (Some(self.items[idx].id), idx),
+                None => return,
+            },
+            None => (None, 0),
+        };
+        // A fresh local id is larger than every known id, so each character
+        // lands directly after its origin and the marker stays exact
+        for (i, ch) in text.chars().enumerate() {
+            let id = self.tick();
+            hint = self.integrate(Item::new(id, origin, ch), hint);
+            origin = Some(id);
+            self.marker = Some((offset + i, hint));
+        }
+    }
+
+    /// Records a local deletion of a visible range
+    pub(crate) fn local_delete(&mut self, range: Range) {
+        let normalized = range.normalize();
+        let start = normalized.start_offset();
+        let mut remaining = normalized.end_offset() - start;
+        if remaining == 0 {
+            return;
+        }
+        let Some(first) = self.visible_index(start) else {
+            return;
+        };
+
+        let mut idx = first;
+        while remaining > 0 && idx < self.items.len() {
+            if !self.items[idx].deleted {
+                self.items[idx].deleted = true;
+                remaining -= 1;
+            }
+            idx += 1;
+        }
+
+        // The marker pointed at a deleted item; move it to the one before
+        self.marker = self.items[..first]
+            .iter()
+            .rposition(|item| !item.deleted)
+            .map(|idx| (start - 1, idx));
+    }
+
+    /// Records the differences between the document and this replica as
+    /// local operations, returning true if anything changed
+    ///
+    /// Text edits are normally recorded as they happen, so the text diff
+    /// here only catches changes that bypassed those hooks.
+    pub(crate) fn sync_from(&mut self, doc: &Document) -> bool {
+        let text: Vec<char> = doc.get_content().chars().collect();
+        let mut changed = self.sync_text(&text);
+
+        let visible = self.visible_indices();
+        changed |= self.sync_formats(doc, &visible);
+        changed |= self.sync_images(doc, &visible);
+        changed |= self.sync_blocks(doc, &text, &visible);
+        changed
+    }
+
+    fn sync_text(&mut self, text: &[char]) -> bool {
+        let visible = self.visible_indices();
+
+        let prefix = visible
+            .iter()
+            .zip(text)
+            .take_while(|(idx, ch)| self.items[**idx].ch == **ch)
+            .count();
+        let max_suffix = visible.len().min(text.len()) - prefix;
+        let suffix = visible
+            .iter()
+            .rev()
+            .zip(text.iter().rev())
+            .take(max_suffix)
+            .take_while(|(idx, ch)| self.items[**idx].ch == **ch)
+            .count();
+
+        let removed = &visible[prefix..visible.len() - suffix];
+        let inserted = &text[prefix..text.len() - suffix];
+        if removed.is_empty() && inserted.is_empty() {
+            return false;
+        }
+        self.marker = None;
+
+        for &idx in removed {
+            self.items[idx].deleted = true;
+        }
+
+        let (mut origin, mut hint) = match prefix.checked_sub(1) {
+            Some(i) => (Some(self.items[visible[i]].id), visible[i]),
+            None => (None, 0),
+        };
+        for &ch in inserted {
+            let id = self.tick();
+            hint = self.integrate(Item::new(id, origin, ch), hint);
+            origin = Some(id);
+        }
+
+        true
+    }
+
+    fn sync_formats(&mut self, doc: &Document, visible: &[usize]) -> bool {
+        let mut changed = false;
+        let full = Range::from_offsets(0, visible.len());
+
+        for (range, formats) in doc.formats().styled_runs(full) {
+            for &idx in &visible[range.start_offset()..range.end_offset()] {
+                let mut updates: Vec<(InlineFormat, bool)> = formats
+                    .iter()
+                    .filter(|f| !self.items[idx].has_format(f))
+                    .map(|f| (f.clone(), true))
+                    .collect();
+                updates.extend(
+                    self.items[idx]
+                        .formats
+                        .iter()
+                        .filter(|(f, lww)| lww.value && !formats.contains(f))
+                        .map(|(f, _)| (f.clone(), false)),
+                );
+
+                for (format, value) in updates {
+                    let stamp = self.tick();
+                    self.items[idx].formats.insert(format, Lww { stamp, value });
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+
+    fn sync_images(&mut self, doc: &Document, visible: &[usize]) -> bool {
+        let mut changed = false;
+
+        for (offset, &idx) in visible.iter().enumerate() {
+            if self.items[idx].ch != EMBED_CHAR {
+                continue;
+            }
+            let image = doc.embeds().get(offset);
+            if self.items[idx].current_image() != image {
+                let value = image.cloned();
+                let stamp = self.tick();
+                self.items[idx].image = Some(Lww { stamp, value });
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    fn sync_blocks(&mut self, doc: &Document, text: &[char], visible: &[usize]) -> bool {
+        let mut changed = false;
+
+        let line_starts = std::iter::once((None, 0)).chain(
+            text.iter()
+                .enumerate()
+                .filter(|(_, ch)| **ch == '\n')
+                .map(|(offset, _)| (Some(self.items[visible[offset]].id), offset + 1)),
+        );
+        let line_starts: Vec<_> = line_starts.collect();
+
+        for (anchor, start) in line_starts {
+            let block_type = doc.formats().get_block_type_at(Position::new(start));
+            if self.block_at(anchor) != block_type {
+                let stamp = self.tick();
+                self.blocks.insert(
+                    anchor,
+                    Lww {
+                        stamp,
+                        value: block_type,
+                    },
+                );
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// Merges another replica's state into this one, returning true if
+    /// anything changed
+    pub(crate) fn merge(&mut self, remote: Replica) -> bool {
+        self.clock = self.clock.max(remote.clock);
+        self.marker = None;
+
+        let positions: HashMap<OpId, usize> = self
+            .items
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| (item.id, idx))
+            .collect();
+
+        let mut changed = false;
+        let mut missing = Vec::new();
+        for item in remote.items {
+            match positions.get(&item.id) {
+                Some(&idx) => changed |= self.items[idx].merge(item),
+                None => missing.push(item),
+            }
+        }
+
+        // Lamport order guarantees every origin is integrated before the
+        // items that reference it
+        missing.sort_by_key(|item| item.id);
+        let mut hint = 0;
+        for item in missing {
+            hint = self.integrate(item, hint);
+            changed = true;
+        }
+
+        for (anchor, lww) in remote.blocks {
+            match self.blocks.entry(anchor) {
+                Entry::Occupied(mut entry) => changed |= entry.get_mut().merge(lww),
+                Entry::Vacant(entry) => {
+                    entry.insert(lww);
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Returns the ids of the characters currently in the document
+    fn visible_ids(&self) -> HashSet<OpId> {
+        self.items
+            .iter()
+            .filter(|item| !item.deleted)
+            .map(|item| item.id)
+            .collect()
+    }
+
+    /// Lists the text edits that turn the characters in `before` into the
+    /// current text, as operations applied in order
+    fn changes_since(&self, before: &HashSet<OpId>) -> Vec<Operation> {
+        #[derive(PartialEq)]
+        enum Step {
+            Keep,
+            Insert,
+            Delete,
+        }
+
+        let mut ops = Vec::new();
+        let mut offset = 0;
+        let mut last = Step::Keep;
+        for item in &self.items {
+            match (before.contains(&item.id), item.deleted) {
+                (true, false) => {
+                    offset += 1;
+                    last = Step::Keep;
+                }
+                (true, true) => {
+                    match ops.last_mut() {
+                        Some(Operation::Delete { end, .. }) if last == Step::Delete => *end += 1,
+                        _ => ops.push(Operation::Delete {
+                            start: offset,
+                            end: offset + 1,
+                        }),
+                    }
+                    last = Step::Delete;
+                }
+                (false, false) => {
+                    match ops.last_mut() {
+                        Some(Operation::Insert { text, .. }) if last == Step::Insert => {
+                            text.push(item.ch)
+                        }
+                        _ => ops.push(Operation::Insert {
+                            position: offset,
+                            text: item.ch.to_string(),
+                        }),
+                    }
+                    offset += 1;
+                    last = Step::Insert;
+                }
+                (false, true) => {}
+            }
+        }
+        ops
+    }
+
+    /// Rewrites the document's text, formats, images and blocks to match
+    /// this replica
+    pub(crate) fn write_to(&self, doc: &mut Document) {
+        let visible: Vec<&Item> = self.items.iter().filter(|item| !item.deleted).collect();
+        let new_text: Vec<char> = visible.iter().map(|item| item.ch).collect();
+        let old_text: Vec<char> = doc.get_content().chars().collect();
+
+        // Replace only the changed span so the selection stays in place
+        if new_text != old_text {
+            let prefix = old_text
+                .iter()
+                .zip(&new_text)
+                .take_while(|(a, b)| a == b)
+                .count();
+            let max_suffix = old_text.len().min(new_text.len()) - prefix;
+            let suffix = old_text
+                .iter()
+                .rev()
+                .zip(new_text.iter().rev())
+                .take(max_suffix)
+                .take_while(|(a, b)| a == b)
+                .count();
+            let replacement: String = new_text[prefix..new_text.len() - suffix].iter().collect();
+            // Remote edits are not local operations, so keep them out of
+            // the pending OT queue
+            let pending = doc.pending_ops.take();
+            doc.replace_range_direct(
+                Range::from_offsets(prefix, old_text.len() - suffix),
+                &replacement,
+            );
+            doc.pending_ops = pending;
+        }
+
+        // Inline formats, as maximal runs per format
+        let formats = doc.formats_mut();
+        formats.clear();
+        let mut open: HashMap<&InlineFormat, usize> = HashMap::new();
+        for (offset, item) in visible.iter().enumerate() {
+            let closed: Vec<&InlineFormat> = open
+                .keys()
+                .filter(|f| !item.has_format(f))
+                .copied()
+                .collect();
+            for format in closed {
+                let start = open.remove(format).unwrap_or(offset);
+                formats.apply_format(Range::from_offsets(start, offset), format.clone());
+            }
+            for (format, lww) in &item.formats {
+                if lww.value {
+                    open.entry(format).or_insert(offset);
+                }
+            }
+        }
+        for (format, start) in open {
+            formats.apply_format(Range::from_offsets(start, visible.len()), format.clone());
+        }
+
+        // Block types, one entry per change between consecutive lines
+        let mut blocks = vec![BlockInfo::new(0, self.block_at(None))];
+        for (offset, item) in visible.iter().enumerate() {
+            if item.ch == '\n' {
+                let block_type = self.block_at(Some(item.id));
+                if blocks.last().is_some_and(|b| b.block_type != block_type) {
+                    blocks.push(BlockInfo::new(offset + 1, block_type));
+                }
+            }
+        }
+        formats.set_blocks(blocks);
+
+        let embeds = doc.embeds_mut();
+        embeds.clear();
+        for (offset, item) in visible.iter().enumerate() {
+            if item.ch == EMBED_CHAR
+                && let Some(image) = item.current_image()
+            {
+                embeds.insert(offset, image.clone());
+            }
+        }
+
+        doc.mark_dirty(Range::from_offsets(0, visible.len()));
+        doc.increment_version();
+    }
+
+    /// Encodes the full replica state as a binary update
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Encoder::default();
+        out.byte(UPDATE_VERSION);
+        out.varint(self.clock);
+
+        out.varint(self.items.len() as u64);
+        for item in &self.items {
+            out.id(item.id);
+            out.optional_id(item.origin);
+            out.varint(item.ch as u64);
+            out.byte(item.deleted as u8);
+
+            out.varint(item.formats.len() as u64);
+            for (format, lww) in &item.formats {
+                out.json(format);
+                out.id(lww.stamp);
+                out.byte(lww.value as u8);
+            }
+
+            match &item.image {
+                Some(lww) => {
+                    out.byte(1);
+                    out.id(lww.stamp);
+                    out.json(&lww.value);
+                }
+                None => out.byte(0),
+            }
+        }
+
+        out.varint(self.blocks.len() as u64);
+        for (anchor, lww) in &self.blocks {
+            out.optional_id(*anchor);
+            out.id(lww.stamp);
+            out.json(&lww.value);
+        }
+
+        out.bytes
+    }
+
+    /// Decodes a binary update produced by `encode`
+    ///
+    /// The update is fully validated before it is returned, so merging it
+    /// cannot fail part way through.
+    pub fn decode(update: &[u8]) -> Result<Replica, CrdtError> {
+        let mut input = Decoder {
+            bytes: update,
+            pos: 0,
+        };
+
+        let version = input.byte()?;
+        if version != UPDATE_VERSION {
+            return Err(CrdtError::UnsupportedVersion(version));
+        }
+        let clock = input.varint()?;
+
+        let item_count = input.length()?;
+        let mut items: Vec<Item> = Vec::with_capacity(item_count);
+        let mut seen: HashSet<OpId> = HashSet::with_capacity(item_count);
+        let mut visible_count = 0;
+        for _ in 0..item_count {
+            let id = input.id()?;
+            let origin = input.optional_id()?;
+            let ch = u32::try_from(input.varint()?)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| malformed("invalid character"))?;
+            let deleted = input.flag()?;
+
+            if validate_text_content(ch.encode_utf8(&mut [0; 4])).is_err() {
+                return Err(malformed("invalid character"));
+            }
+            if !deleted {
+                visible_count += 1;
+                if visible_count > MAX_DOCUMENT_SIZE {
+                    return Err(malformed("document exceeds the maximum size"));
+                }
+            }
+
+            if id.clock > clock {
+                return Err(malformed("item clock is ahead of the update clock"));
+            }
+            if !seen.insert(id) {
+                return Err(malformed("duplicate item id"));
+            }
+            // Items always follow their origin in sequence order
+            if let Some(origin) = origin
+                && (!seen.contains(&origin) || origin.clock >= id.clock)
+            {
+                return Err(malformed(
+                    "item origin is missing or not causally before it",
+                ));
+            }
+
+            let mut item = Item::new(id, origin, ch);
+            item.deleted = deleted;
+
+            let format_count = input.length()?;
+            for _ in 0..format_count {
+                let format: InlineFormat = input.json()?;
+                let stamp = input.id()?;
+                let value = input.flag()?;
+                item.formats.insert(format, Lww { stamp, value });
+            }
+
+            if input.flag()? {
+                let stamp = input.id()?;
+                let value: Option<Image> = input.json()?;
+                if ch != EMBED_CHAR {
+                    return Err(malformed("image attached to a non-embed character"));
+                }
+                if value.as_ref().is_some_and(|image| !image.has_safe_src()) {
+                    return Err(malformed("unsafe image source"));
+                }
+                item.image = Some(Lww { stamp, value });
+            }
+
+            items.push(item);
+        }
+
+        let block_count = input.length()?;
+        let mut blocks = HashMap::with_capacity(block_count);
+        for _ in 0..block_count {
+            let anchor = input.optional_id()?;
+            let stamp = input.id()?;
+            let value: BlockType = input.json()?;
+            blocks.insert(anchor, Lww { stamp, value });
+        }
+
+        if input.pos != update.len() {
+            return Err(malformed("trailing bytes"));
+        }
+
+        Ok(Replica {
+            client_id: 0,
+            clock,
+            items,
+            blocks,
+            marker: None,
+        })
+    }
+}
+
+fn malformed(reason: &str) -> CrdtError {
+    CrdtError::MalformedUpdate(reason.to_string())
+}
+
+/// Writes LEB128 varints and length-prefixed JSON values
+#[derive(Default)]
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn byte(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn varint(&mut self, value: u64) {
+        varint::write(&mut self.bytes, value);
+    }
+
+    fn id(&mut self, id: OpId) {
+        self.varint(id.clock);
+        self.varint(id.client as u64);
+    }
+
+    fn optional_id(&mut self, id: Option<OpId>) {
+        match id {
+            Some(id) => {
+                self.byte(1);
+                self.id(id);
+            }
+            None => self.byte(0),
+        }
+    }
+
+    fn json<T: serde::Serialize>(&mut self, value: &T) {
+        let json = serde_json::to_vec(value).expect("editor types always serialize");
+        self.varint(json.len() as u64);
+        self.bytes.extend_from_slice(&json);
+    }
+}
+
+/// Reads values written by `Encoder`, failing on truncated input
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Decoder<'_> {
+    fn byte(&mut self) -> Result<u8, CrdtError> {
+        let value = *self
+            .bytes
+            .get(self.pos)
+            .ok_or_else(|| malformed("unexpected end of update"))?;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    fn flag(&mut self) -> Result<bool, CrdtError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(malformed("invalid flag")),
+        }
+    }
+
+    fn varint(&mut self) -> Result<u64, CrdtError> {
+        let (value, len) = varint::read(&self.bytes[self.pos..]).map_err(|err| match err {
+            VarintError::UnexpectedEnd => malformed("unexpected end of update"),
+            VarintError::Overflow => malformed("varint overflow"),
+        })?;
+        self.pos += len;
+        Ok(value)
+    }
+
+    /// Reads a count, rejecting values larger than the remaining input
+    fn length(&mut self) -> Result<usize, CrdtError> {
+        let value = self.varint()?;
+        usize::try_from(value)
+            .ok()
+            .filter(|len| *len <= self.bytes.len() - self.pos)
+            .ok_or_else(|| malformed("length exceeds update size"))
+    }
+
+    fn id(&mut self) -> Result<OpId, CrdtError> {
+        let clock = self.varint()?;
+        let client =
+            u32::try_from(self.varint()?).map_err(|_| malformed("client id out of range"))?;
+        Ok(OpId { clock, client })
+    }
+
+    fn optional_id(&mut self) -> Result<Option<OpId>, CrdtError> {
+        if self.flag()? {
+            Ok(Some(self.id()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn json<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, CrdtError> {
+        let len = self.length()?;
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        serde_json::from_slice(slice).map_err(|e| malformed(&e.to_string()))
+    }
+}
+
+impl Document {
+    /// Enables collaborative editing with the given client id
+    ///
+    /// Client ids must be unique among the documents exchanging updates.
+    /// The current content becomes this client's initial state; a document
+    /// joining an existing session should start empty and apply an update
+    /// from a peer. Has no effect if collaboration is already enabled.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    ///
+    /// let mut alice = Document::from_text("Hello");
+    /// alice.enable_collaboration(1);
+    /// let mut bob = Document::new();
+    /// bob.enable_collaboration(2);
+    /// bob.apply_update(&alice.encode_state_as_update().unwrap()).unwrap();
+    ///
+    /// alice.insert_text(Position::new(5), "!").unwrap();
+    /// bob.insert_text(Position::new(0), "Oh, ").unwrap();
+    /// bob.apply_update(&alice.encode_state_as_update().unwrap()).unwrap();
+    /// alice.apply_update(&bob.encode_state_as_update().unwrap()).unwrap();
+    ///
+    /// assert_eq!(alice.get_content(), "Oh, Hello!");
+    /// assert_eq!(bob.get_content(), "Oh, Hello!");
+    /// ```
+    pub fn enable_collaboration(&mut self, client_id: u32) {
+        if self.replica.is_some() {
+            return;
+        }
+        let mut replica = Replica::new(client_id);
+        replica.sync_from(self);
+        self.replica = Some(replica);
+    }
+
+    /// Returns true if collaborative editing is enabled
+    pub fn is_collaborative(&self) -> bool {
+        self.replica.is_some()
+    }
+
+    /// Encodes the full collaborative state, including local edits made
+    /// since the last exchange, as a binary update
+    ///
+    /// # Errors
+    /// Returns `CrdtError::NotEnabled` if collaboration is not enabled
+    pub fn encode_state_as_update(&mut self) -> Result<Vec<u8>, CrdtError> {
+        let mut replica = self.replica.take().ok_or(CrdtError::NotEnabled)?;
+        replica.sync_from(self);
+        let update = replica.encode();
+        self.replica = Some(replica);
+        Ok(update)
+    }
+
+    /// Merges a binary update from another client into this document
+    ///
+    /// Returns true if the document changed. Local undo steps are moved past
+    /// the remote changes so undo keeps reverting only local changes; a
+    /// step whose text the remote edits touched, or one that changed line
+    /// blocks, is dropped along with every older one, and redo steps are
+    /// dropped.
+    ///
+    /// # Errors
+    /// Returns `CrdtError::NotEnabled` if collaboration is not enabled, or
+    /// a decoding error if the update is malformed. A malformed update
+    /// leaves the document untouched.
+    pub fn apply_update(&mut self, update: &[u8]) -> Result<bool, CrdtError> {
+        if self.replica.is_none() {
+            return Err(CrdtError::NotEnabled);
+        }
+        let remote = Replica::decode(update)?;
+
+        let Some(mut replica) = self.replica.take() else {
+            return Err(CrdtError::NotEnabled);
+        };
+        replica.sync_from(self);
+        let before = replica.visible_ids();
+        let changed = replica.merge(remote);
+        if changed {
+            let ops = replica.changes_since(&before);
+            replica.write_to(self);
+            self.history.rebase(&ops);
+        }
+        self.replica = Some(replica);
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(client_id: u32, text: &str) -> Document {
+        let mut doc = Document::from_text(text);
+        doc.enable_collaboration(client_id);
+        doc
+    }
+
+    fn sync(a: &mut Document, b: &mut Document) {
+        let update = a.encode_state_as_update().unwrap();
+        b.apply_update(&update).unwrap();
+        let update = b.encode_state_as_update().unwrap();
+        a.apply_update(&update).unwrap();
+    }
+
+    #[test]
+    fn test_requires_collaboration() {
+        let mut doc = Document::from_text("Hello");
+        assert_eq!(doc.encode_state_as_update(), Err(CrdtError::NotEnabled));
+        assert_eq!(doc.apply_update(&[1, 0, 0, 0]), Err(CrdtError::NotEnabled));
+    }
+
+    #[test]
+    fn test_join_session() {
+        let mut alice = peer(1, "Hello");
+        let mut bob = peer(2, "");
+        sync(&mut alice, &mut bob);
+        assert_eq!(bob.get_content(), "Hello");
+    }
+
+    #[test]
+    fn test_concurrent_inserts_converge() {
+        let mut alice = peer(1, "ac");
+        let mut bob = peer(2, "");
+        sync(&mut alice, &mut bob);
+
+        alice.insert_text(Position::new(1), "b").unwrap();
+        bob.insert_text(Position::new(1), "x").unwrap();
+        bob.insert_text(Position::new(3), "d").unwrap();
+        sync(&mut alice, &mut bob);
+
+        assert_eq!(alice.get_content(), bob.get_content());
+        assert_eq!(alice.get_content().len(), 5);
+        assert!(alice.get_content().starts_with('a'));
+        assert!(alice.get_content().ends_with("cd"));
+    }
+
+    #[test]
+    fn test_concurrent_delete_and_insert() {
+        let mut alice = peer(1, "Hello World");
+        let mut bob = peer(2, "");
+        sync(&mut alice, &mut bob);
+
+        alice.delete_range(Range::from_offsets(5, 11)).unwrap();
+        bob.insert_text(Position::new(11), "!").unwrap();
+        sync(&mut alice, &mut bob);
+
+        assert_eq!(alice.get_content(), "Hello!");
+        assert_eq!(bob.get_content(), "Hello!");
+    }
+
+    #[test]
+    fn test_formats_converge() {
+        let mut alice = peer(1, "Hello World");
+        let mut bob = peer(2, "");
+        sync(&mut alice, &mut bob);
+
+        alice.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+        bob.apply_format(Range::from_offsets(6, 11), InlineFormat::Italic);
+        bob.insert_text(Position::new(5), ",").unwrap();
+        sync(&mut alice, &mut bob);
+
+        for doc in [&alice, &bob] {
+            assert_eq!(doc.get_content(), "Hello, World");
+            assert!(
+                doc.get_formats_at(Position::new(0))
+                    .contains(&InlineFormat::Bold)
+            );
+            assert!(doc.get_formats_at(Position::new(5)).is_empty());
+            assert!(
+                doc.get_formats_at(Position::new(7))
+                    .contains(&InlineFormat::Italic)
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_removal_wins_when_later() {
+        let mut alice = peer(1, "Hello");
+        alice.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+        let mut bob = peer(2, "");
+        sync(&mut alice, &mut bob);
+
+        bob.remove_format(Range::from_offsets(0, 5), &InlineFormat::Bold);
+        sync(&mut alice, &mut bob);
+
+        assert!(alice.get_formats_at(Position::new(0)).is_empty());
+        assert!(bob.get_formats_at(Position::new(0)).is_empty());
+    }
+
+    #[test]
+    fn test_block_types_follow_lines() {
+        let mut alice = peer(1, "Title\nBody");
+        alice.set_block_type(Range::from_offsets(0, 5), BlockType::heading(1));
+        let mut bob = peer(2, "");
+        sync(&mut alice, &mut bob);
+
+        bob.insert_text(Position::new(10), "\nFooter").unwrap();
+        bob.set_block_type(Range::from_offsets(11, 17), BlockType::BulletList);
+        alice.set_block_type(Range::from_offsets(6, 10), BlockType::code_block());
+        sync(&mut alice, &mut bob);
+
+        for doc in [&alice, &bob] {
+            assert_eq!(doc.get_content(), "Title\nBody\nFooter");
+            assert_eq!(
+                doc.get_block_type_at(Position::new(0)),
+                BlockType::heading(1)
+            );
+            assert_eq!(
+                doc.get_block_type_at(Position::new(6)),
+                BlockType::code_block()
+            );
+            assert_eq!(
+                doc.get_block_type_at(Position::new(11)),
+                BlockType::BulletList
+            );
+        }
+    }
+
+    #[test]
+    fn test_images_replicate() {
+        let mut alice = peer(1, "ab");
+        alice
+            .insert_image(Position::new(1), Image::new("a.png", "A"))
+            .unwrap();
+        let mut bob = peer(2, "");
+        sync(&mut alice, &mut bob);
+
+        assert_eq!(bob.get_image_at(Position::new(1)).unwrap().src, "a.png");
+    }
+
+    #[test]
+    fn test_undo_replicates() {
+        let mut alice = peer(1, "Hello");
+        let mut bob = peer(2, "");
+        sync(&mut alice, &mut bob);
+
+        alice.insert_text(Position::new(5), " World").unwrap();
+        sync(&mut alice, &mut bob);
+        alice.undo().unwrap();
+        sync(&mut alice, &mut bob);
+
+        assert_eq!(bob.get_content(), "Hello");
+    }
+
+    #[test]
+    fn test_apply_update_is_idempotent() {
+        let mut alice = peer(1, "Hello");
+        let mut bob = peer(2, "");
+        let update = alice.encode_state_as_update().unwrap();
+
+        assert!(bob.apply_update(&update).unwrap());
+        assert!(!bob.apply_update(&update).unwrap());
+        assert_eq!(bob.get_content(), "Hello");
+    }
+
+    #[test]
+    fn test_undo_after_remote_update_reverts_local_edits() {
+        let mut alice = peer(1, "Hello");
+        let mut bob = peer(2, "");
+        sync(&mut alice, &mut bob);
+
+        bob.insert_text(Position::new(5), " world").unwrap();
+        bob.apply_format(Range::from_offsets(6, 11), InlineFormat::Bold);
+        alice.insert_text(Position::new(0), "Oh, ").unwrap();
+        alice.delete_range(Range::from_offsets(7, 8)).unwrap();
+        sync(&mut alice, &mut bob);
+        assert_eq!(bob.get_content(), "Oh, Helo world");
+        assert_eq!(bob.history.undo_count(), 2);
+
+        bob.undo().unwrap();
+        assert!(bob.get_formats_at(Position::new(10)).is_empty());
+        bob.undo().unwrap();
+        assert_eq!(bob.get_content(), "Oh, Helo");
+        assert!(!bob.can_undo());
+
+        alice.undo().unwrap();
+        alice.undo().unwrap();
+        assert_eq!(alice.get_content(), "Hello world");
+    }
+
+    #[test]
+    fn test_remote_edit_drops_undo_steps_it_touches() {
+        let mut alice = peer(1, "");
+        let mut bob = peer(2, "");
+        bob.insert_text(Position::new(0), "ab").unwrap();
+        bob.insert_text(Position::new(2), " cd").unwrap();
+        sync(&mut alice, &mut bob);
+        assert_eq!(bob.history.undo_count(), 2);
+
+        // Deleting "b" touches the first step only
+        alice.delete_range(Range::from_offsets(1, 2)).unwrap();
+        sync(&mut alice, &mut bob);
+        assert_eq!(bob.history.undo_count(), 1);
+        bob.undo().unwrap();
+        assert_eq!(bob.get_content(), "a");
+        assert!(!bob.can_undo());
+
+        // Typing into the remaining text touches the step that added it
+        bob.redo().unwrap();
+        alice.insert_text(Position::new(0), "x").unwrap();
+        sync(&mut alice, &mut bob);
+        assert!(bob.can_undo());
+        alice.insert_text(Position::new(3), "y").unwrap();
+        sync(&mut alice, &mut bob);
+        assert!(!bob.can_undo());
+        assert_eq!(bob.get_content(), "xa ycd");
+    }
+
+    #[test]
+    fn test_undo_keeps_remote_block_changes() {
+        let heading = BlockType::heading(1);
+        let mut alice = peer(1, "one\ntwo");
+        let mut bob = peer(2, "");
+        sync(&mut alice, &mut bob);
+
+        alice.delete_range(Range::from_offsets(5, 6)).unwrap();
+        bob.set_block_type(Range::from_offsets(0, 0), heading.clone());
+        sync(&mut alice, &mut bob);
+        alice.undo().unwrap();
+        sync(&mut alice, &mut bob);
+
+        for doc in [&alice, &bob] {
+            assert_eq!(doc.get_content(), "one\ntwo");
+            assert_eq!(doc.get_block_type_at(Position::new(0)), heading);
+        }
+    }
+
+    #[test]
+    fn test_remote_block_change_drops_block_undo_steps() {
+        let mut alice = peer(1, "one\ntwo");
+        let mut bob = peer(2, "");
+        sync(&mut alice, &mut bob);
+
+        alice.set_block_type(Range::from_offsets(4, 4), BlockType::heading(2));
+        bob.set_block_type(Range::from_offsets(0, 0), BlockType::heading(1));
+        sync(&mut alice, &mut bob);
+
+        assert!(!alice.can_undo());
+        assert_eq!(
+            alice.get_block_type_at(Position::new(0)),
+            BlockType::heading(1)
+        );
+    }
+
+    #[test]
+    fn test_malformed_update_rejected() {
+        let mut doc = peer(1, "Hello");
+
+        assert!(matches!(
+            doc.apply_update(&[]),
+            Err(CrdtError::MalformedUpdate(_))
+        ));
+        assert_eq!(
+            doc.apply_update(&[9]),
+            Err(CrdtError::UnsupportedVersion(9))
+        );
+
+        let mut update = peer(2, "Hi").encode_state_as_update().unwrap();
+        update.truncate(update.len() - 1);
+        assert!(matches!(
+            doc.apply_update(&update),
+            Err(CrdtError::MalformedUpdate(_))
+        ));
+        assert_eq!(doc.get_content(), "Hello");
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let mut doc = peer(7, "Hi\nthere");
+        doc.apply_format(Range::from_offsets(0, 2), InlineFormat::Bold);
+        doc.delete_range(Range::from_offsets(0, 1)).unwrap();
+
+        let update = doc.encode_state_as_update().unwrap();
+        let decoded = Replica::decode(&update).unwrap();
+        assert_eq!(decoded.text(), "i\nthere");
+        assert_eq!(decoded.item_count(), 8);
+    }
+
+    #[test]
+    fn test_local_edits_track_document_text() {
+        let mut doc = peer(1, "Hello World");
+        doc.insert_text(Position::new(5), ",").unwrap();
+        doc.insert_text(Position::new(6), " there").unwrap();
+        doc.delete_range(Range::from_offsets(0, 1)).unwrap();
+        doc.insert_text(Position::new(0), "J").unwrap();
+        doc.delete_range(Range::from_offsets(8, 13)).unwrap();
+        doc.insert_text(Position::new(doc.get_length()), "!")
+            .unwrap();
+        doc.undo().unwrap();
+        doc.delete_range(Range::from_offsets(3, 4)).unwrap();
+        doc.insert_text(Position::new(1), "e").unwrap();
+
+        let replica = doc.replica.as_ref().unwrap();
+        assert_eq!(replica.text(), doc.get_content());
+    }
+
+    #[test]
+    fn test_remote_update_is_not_a_pending_op() {
+        let mut alice = peer(1, "Hello");
+        let mut bob = peer(2, "");
+        bob.enable_operational_transform();
+
+        bob.apply_update(&alice.encode_state_as_update().unwrap())
+            .unwrap();
+        assert_eq!(bob.get_content(), "Hello");
+        assert!(bob.pending_ops().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid_content() {
+        let mut replica = Replica::new(1);
+        replica.local_insert(0, "a\u{1}");
+        assert!(matches!(
+            Replica::decode(&replica.encode()),
+            Err(CrdtError::MalformedUpdate(_))
+        ));
+
+        let mut replica = Replica::new(1);
+        let stamp = replica.tick();
+        replica.blocks.insert(
+            None,
+            Lww {
+                stamp,
+                value: BlockType::Heading { level: 9 },
+            },
+        );
+        assert!(matches!(
+            Replica::decode(&replica.encode()),
+            Err(CrdtError::MalformedUpdate(_))
+        ));
+    }
+}
@@ -0,0 +1,180 @@
+//! Format migration
+//!
+//! Rewrites the inline formats of a whole document through a mapping, such
+//! as turning every red text color into a highlight or moving links to a
+//! new domain. The mapping decides for each distinct format whether it is
+//! kept, replaced or removed, and the whole migration is a single undo
+//! step.
+
+use crate::document::Document;
+use crate::formatting::InlineFormat;
+use crate::operations::CommandResult;
+use std::collections::HashMap;
+
+/// What `Document::migrate_formats` changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// Number of format runs whose formats changed
+    pub runs_changed: usize,
+    /// Number of formats replaced with another, counted once per run
+    pub formats_replaced: usize,
+    /// Number of formats removed, counted once per run
+    pub formats_removed: usize,
+    /// Number of characters whose formats changed
+    pub characters_changed: usize,
+}
+
+impl MigrationReport {
+    /// Returns true if nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.runs_changed == 0
+    }
+}
+
+impl Document {
+    /// Rewrites every inline format through `map`, as a single undo step
+    ///
+    /// `map` returns the format to keep it, another format to replace it or
+    /// None to remove it. It is called once for each distinct format, as
+    /// the runs are walked from the start of the document, and its answer
+    /// is used for every run with that format.
+    ///
+    /// # Errors
+    /// Returns an error if the document is read-only
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    /// use rte_core::formatting::InlineFormat;
+    ///
+    /// let mut doc = Document::from_text("Red and bold");
+    /// let red = InlineFormat::TextColor { color: "#FF0000".to_string() };
+    /// doc.apply_format(Range::from_offsets(0, 3), red.clone());
+    /// doc.apply_format(Range::from_offsets(8, 12), InlineFormat::Bold);
+    ///
+    /// let report = doc
+    ///     .migrate_formats(|format| match format {
+    ///         InlineFormat::TextColor { color } if color == "#FF0000" => {
+    ///             Some(InlineFormat::Highlight)
+    ///         }
+    ///         format => Some(format.clone()),
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(report.formats_replaced, 1);
+    /// assert_eq!(report.characters_changed, 3);
+    /// assert!(doc.get_formats_at(Position::new(1)).contains(&InlineFormat::Highlight));
+    ///
+    /// doc.undo().unwrap();
+    /// assert!(doc.get_formats_at(Position::new(1)).contains(&red));
+    /// ```
+    pub fn migrate_formats(
+        &mut self,
+        mut map: impl FnMut(&InlineFormat) -> Option<InlineFormat>,
+    ) -> CommandResult<MigrationReport> {
+        self.check_editable()?;
+
+        let runs = self.formats().get_runs().to_vec();
+        let mut mapped: HashMap<InlineFormat, Option<InlineFormat>> = HashMap::new();
+        let mut report = MigrationReport::default();
+        self.begin_batch();
+        for run in runs {
+            // Sorted so `map` is called in the same order every time
+            let mut formats: Vec<&InlineFormat> = run.formats.iter().collect();
+            formats.sort_by_key(|format| format!("{:?}", format));
+
+            let mut removed = Vec::new();
+            let mut added = Vec::new();
+            for format in formats {
+                let target = mapped.entry(format.clone()).or_insert_with(|| map(format));
+                match target {
+                    Some(target) if target == format => continue,
+                    Some(target) => {
+                        added.push(target.clone());
+                        report.formats_replaced += 1;
+                    }
+                    None => report.formats_removed += 1,
+                }
+                removed.push(format.clone());
+            }
+            if removed.is_empty() {
+                continue;
+            }
+
+            // Everything is removed before anything is added, so formats
+            // swapped with each other survive
+            for format in &removed {
+                self.remove_format(run.range, format);
+            }
+            for format in added {
+                self.apply_format(run.range, format);
+            }
+            report.runs_changed += 1;
+            report.characters_changed += run.range.len();
+        }
+        self.end_batch();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{Position, Range};
+
+    #[test]
+    fn test_migrate_formats_rewrites_links_and_swaps() {
+        let mut doc = Document::from_text("old new both");
+        let link = |url: &str| InlineFormat::Link {
+            url: url.to_string(),
+        };
+        doc.apply_format(Range::from_offsets(0, 3), link("https://old.example/a"));
+        doc.apply_format(Range::from_offsets(4, 7), link("https://new.example/b"));
+        doc.apply_format(Range::from_offsets(8, 12), InlineFormat::Subscript);
+        doc.apply_format(Range::from_offsets(8, 12), InlineFormat::Underline);
+
+        let mut calls = 0;
+        let report = doc
+            .migrate_formats(|format| {
+                calls += 1;
+                match format {
+                    InlineFormat::Link { url } => Some(link(
+                        &url.replace("https://old.example", "https://new.example"),
+                    )),
+                    InlineFormat::Subscript => Some(InlineFormat::Superscript),
+                    InlineFormat::Underline => None,
+                    format => Some(format.clone()),
+                }
+            })
+            .unwrap();
+        assert_eq!(calls, 4);
+        assert_eq!(
+            report,
+            MigrationReport {
+                runs_changed: 2,
+                formats_replaced: 2,
+                formats_removed: 1,
+                characters_changed: 7,
+            }
+        );
+        assert_eq!(
+            doc.get_formats_at(Position::new(1)),
+            [link("https://new.example/a")].into()
+        );
+        assert_eq!(
+            doc.get_formats_at(Position::new(10)),
+            [InlineFormat::Superscript].into()
+        );
+
+        // Nothing to change leaves the history alone
+        let report = doc.migrate_formats(|format| Some(format.clone())).unwrap();
+        assert!(report.is_empty());
+        doc.undo().unwrap();
+        assert_eq!(
+            doc.get_formats_at(Position::new(10)),
+            [InlineFormat::Subscript, InlineFormat::Underline].into()
+        );
+
+        doc.set_read_only(true);
+        assert!(doc.migrate_formats(|_| None).is_err());
+    }
+}
@@ -0,0 +1,395 @@
+//! Random edit sequences for fuzzing the document model
+//!
+//! Available with the `fuzzing` feature. Operations are drawn from an
+//! `arbitrary::Unstructured`, so the same generator serves cargo-fuzz
+//! targets, proptest byte strategies and seeded test loops. Each operation
+//! is valid for the document state it is drawn against, and
+//! `check_invariants` replays a sequence to verify that undoing everything
+//! restores the original document and that JSON round trips are stable.
+//!
+//! # Example
+//! ```ignore
+//! // fuzz/fuzz_targets/edits.rs
+//! libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+//!     rte_core::operations::fuzz::fuzz_document(data).unwrap();
+//! });
+//! ```
+
+use crate::document::{Document, Position, Range, SOFT_BREAK};
+use crate::formatting::{BlockType, InlineFormat};
+use crate::operations::{CommandError, CommandResult};
+use arbitrary::Unstructured;
+
+/// Characters generated text is drawn from: ASCII, whitespace, line breaks
+/// and multi-byte characters, including one outside the BMP
+const ALPHABET: &[char] = &[
+    'a', 'b', 'Z', '0', ' ', '\t', '\n', SOFT_BREAK, 'é', 'ß', 'ش', '中', '😀',
+];
+
+/// Longest text a single insert or replace generates, in characters
+const MAX_TEXT_LEN: usize = 8;
+
+/// A single edit drawn by the generator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditOp {
+    Insert {
+        position: usize,
+        text: String,
+    },
+    Delete {
+        start: usize,
+        end: usize,
+    },
+    Replace {
+        start: usize,
+        end: usize,
+        text: String,
+    },
+    ApplyFormat {
+        start: usize,
+        end: usize,
+        format: InlineFormat,
+    },
+    RemoveFormat {
+        start: usize,
+        end: usize,
+        format: InlineFormat,
+    },
+    SetBlockType {
+        start: usize,
+        end: usize,
+        block_type: BlockType,
+    },
+    Undo,
+    Redo,
+}
+
+impl EditOp {
+    /// Draws an operation that is valid for the current state of `doc`
+    pub fn arbitrary(u: &mut Unstructured, doc: &Document) -> arbitrary::Result<Self> {
+        let len = doc.get_length();
+        Ok(match u.int_in_range(0..=7u8)? {
+            0 => EditOp::Insert {
+                position: u.int_in_range(0..=len)?,
+                text: arbitrary_text(u)?,
+            },
+            1 => {
+                let (start, end) = arbitrary_range(u, len)?;
+                EditOp::Delete { start, end }
+            }
+            2 => {
+                let (start, end) = arbitrary_range(u, len)?;
+                EditOp::Replace {
+                    start,
+                    end,
+                    text: arbitrary_text(u)?,
+                }
+            }
+            3 => {
+                let (start, end) = arbitrary_range(u, len)?;
+                EditOp::ApplyFormat {
+                    start,
+                    end,
+                    format: arbitrary_format(u)?,
+                }
+            }
+            4 => {
+                let (start, end) = arbitrary_range(u, len)?;
+                EditOp::RemoveFormat {
+                    start,
+                    end,
+                    format: arbitrary_format(u)?,
+                }
+            }
+            5 => {
+                let (start, end) = arbitrary_range(u, len)?;
+                EditOp::SetBlockType {
+                    start,
+                    end,
+                    block_type: arbitrary_block_type(u)?,
+                }
+            }
+            6 => EditOp::Undo,
+            _ => EditOp::Redo,
+        })
+    }
+
+    /// Applies the operation to `doc`
+    ///
+    /// Undo and redo with nothing to undo or redo succeed without effect.
+    pub fn apply(&self, doc: &mut Document) -> CommandResult<()> {
+        match self {
+            EditOp::Insert { position, text } => doc.insert_text(Position::new(*position), text),
+            EditOp::Delete { start, end } => doc.delete_range(Range::from_offsets(*start, *end)),
+            EditOp::Replace { start, end, text } => {
+                doc.replace_range(Range::from_offsets(*start, *end), text)
+            }
+            EditOp::ApplyFormat { start, end, format } => {
+                doc.apply_format(Range::from_offsets(*start, *end), format.clone());
+                Ok(())
+            }
+            EditOp::RemoveFormat { start, end, format } => {
+                doc.remove_format(Range::from_offsets(*start, *end), format);
+                Ok(())
+            }
+            EditOp::SetBlockType {
+                start,
+                end,
+                block_type,
+            } => {
+                doc.set_block_type(Range::from_offsets(*start, *end), block_type.clone());
+                Ok(())
+            }
+            EditOp::Undo => match doc.undo() {
+                Err(CommandError::NothingToUndo) => Ok(()),
+                result => result,
+            },
+            EditOp::Redo => match doc.redo() {
+                Err(CommandError::NothingToRedo) => Ok(()),
+                result => result,
+            },
+        }
+    }
+}
+
+/// Draws up to `max_ops` operations, applying each to `doc` before drawing
+/// the next so that every one is valid where it runs
+///
+/// Stops early when `u` runs out of data or an operation fails; the failing
+/// operation is still returned last so `check_invariants` reports it.
+pub fn arbitrary_sequence(
+    u: &mut Unstructured,
+    doc: &mut Document,
+    max_ops: usize,
+) -> arbitrary::Result<Vec<EditOp>> {
+    let mut ops = Vec::new();
+    while ops.len() < max_ops && !u.is_empty() {
+        let op = EditOp::arbitrary(u, doc)?;
+        let failed = op.apply(doc).is_err();
+        ops.push(op);
+        if failed {
+            break;
+        }
+    }
+    Ok(ops)
+}
+
+/// An invariant broken by an edit sequence
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum InvariantError {
+    /// An operation that is valid for the document returned an error
+    #[error("Operation {index} ({op:?}) failed: {message}")]
+    OperationFailed {
+        index: usize,
+        op: EditOp,
+        message: String,
+    },
+    /// Undoing every operation did not restore the original document
+    #[error("Undoing every operation produced {actual} instead of {expected}")]
+    UndoMismatch { expected: String, actual: String },
+    /// Loading a document's JSON and saving it again changed the JSON
+    #[error("JSON round trip after operation {index} changed {before} to {after}")]
+    JsonRoundTrip {
+        index: usize,
+        before: String,
+        after: String,
+    },
+    /// The document could not be serialized or loaded
+    #[error("Serialization failed: {0}")]
+    Serialization(String),
+}
+
+/// Replays `ops` on a copy of `original` and checks that:
+/// - every operation succeeds
+/// - the document survives a JSON round trip unchanged after each one
+/// - undoing everything afterwards restores `original`
+///
+/// Documents are compared through their JSON, and the copy is made by
+/// loading `original`'s JSON. The copy keeps its whole history so that
+/// undo can reach the start.
+pub fn check_invariants(original: &Document, ops: &[EditOp]) -> Result<(), InvariantError> {
+    let expected = to_json(original)?;
+    let mut doc = from_json(&expected)?;
+    doc.set_history_limit(ops.len() + 1);
+
+    for (index, op) in ops.iter().enumerate() {
+        op.apply(&mut doc)
+            .map_err(|e| InvariantError::OperationFailed {
+                index,
+                op: op.clone(),
+                message: e.to_string(),
+            })?;
+
+        let before = to_json(&doc)?;
+        let after = to_json(&from_json(&before)?)?;
+        if before != after {
+            return Err(InvariantError::JsonRoundTrip {
+                index,
+                before,
+                after,
+            });
+        }
+    }
+
+    while doc.can_undo() {
+        doc.undo().map_err(|e| InvariantError::OperationFailed {
+            index: ops.len(),
+            op: EditOp::Undo,
+            message: e.to_string(),
+        })?;
+    }
+    let actual = to_json(&doc)?;
+    if actual != expected {
+        return Err(InvariantError::UndoMismatch { expected, actual });
+    }
+    Ok(())
+}
+
+/// Builds a document and an edit sequence from raw fuzzer input and checks
+/// the sequence's invariants
+///
+/// Input too short to draw from is accepted without checking anything.
+pub fn fuzz_document(data: &[u8]) -> Result<(), InvariantError> {
+    let mut u = Unstructured::new(data);
+    let Ok(text) = arbitrary_text(&mut u) else {
+        return Ok(());
+    };
+    let original = Document::from_text(&text);
+    let mut doc = from_json(&to_json(&original)?)?;
+    let Ok(ops) = arbitrary_sequence(&mut u, &mut doc, 64) else {
+        return Ok(());
+    };
+    check_invariants(&original, &ops)
+}
+
+/// Draws a string of up to `MAX_TEXT_LEN` characters from `ALPHABET`
+fn arbitrary_text(u: &mut Unstructured) -> arbitrary::Result<String> {
+    let len = u.int_in_range(0..=MAX_TEXT_LEN)?;
+    (0..len).map(|_| u.choose(ALPHABET).copied()).collect()
+}
+
+/// Draws a normalized range within a document of `len` characters
+fn arbitrary_range(u: &mut Unstructured, len: usize) -> arbitrary::Result<(usize, usize)> {
+    let start = u.int_in_range(0..=len)?;
+    let end = u.int_in_range(start..=len)?;
+    Ok((start, end))
+}
+
+/// Draws an inline format, using fixed values for formats that take one
+fn arbitrary_format(u: &mut Unstructured) -> arbitrary::Result<InlineFormat> {
+    Ok(match u.int_in_range(0..=12u8)? {
+        0 => InlineFormat::Bold,
+        1 => InlineFormat::Italic,
+        2 => InlineFormat::Underline,
+        3 => InlineFormat::Strikethrough,
+        4 => InlineFormat::Code,
+        5 => InlineFormat::Link {
+            url: "https://example.com".to_string(),
+        },
+        6 => InlineFormat::TextColor {
+            color: "#ff0000".to_string(),
+        },
+        7 => InlineFormat::BackgroundColor {
+            color: "yellow".to_string(),
+        },
+        8 => InlineFormat::Highlight,
+        9 => InlineFormat::SmallCaps,
+        10 => InlineFormat::Subscript,
+        11 => InlineFormat::Superscript,
+        _ => InlineFormat::Math { display: false },
+    })
+}
+
+/// Draws a block type
+fn arbitrary_block_type(u: &mut Unstructured) -> arbitrary::Result<BlockType> {
+    Ok(match u.int_in_range(0..=5u8)? {
+        0 => BlockType::Paragraph,
+        1 => BlockType::heading(u.int_in_range(1..=6)?),
+        2 => BlockType::BulletList,
+        3 => BlockType::NumberedList,
+        4 => BlockType::BlockQuote {
+            cite: None,
+            depth: 1,
+        },
+        _ => BlockType::code_block(),
+    })
+}
+
+/// Serializes `doc` to JSON with the formats of each run sorted and touching
+/// runs with the same formats merged, since neither the order nor where a
+/// run is split carries any meaning
+fn to_json(doc: &Document) -> Result<String, InvariantError> {
+    let json = doc
+        .to_json()
+        .map_err(|e| InvariantError::Serialization(e.to_string()))?;
+    let mut value: serde_json::Value =
+        serde_json::from_str(&json).map_err(|e| InvariantError::Serialization(e.to_string()))?;
+    if let Some(runs) = value["formats"].as_array_mut() {
+        for run in runs.iter_mut() {
+            if let Some(formats) = run["formats"].as_array_mut() {
+                formats.sort_by_cached_key(|format| format.to_string());
+            }
+        }
+        runs.dedup_by(|next, previous| {
+            let touching = previous["end"] == next["start"];
+            if touching && previous["formats"] == next["formats"] {
+                previous["end"] = next["end"].take();
+                return true;
+            }
+            false
+        });
+    }
+    Ok(value.to_string())
+}
+
+fn from_json(json: &str) -> Result<Document, InvariantError> {
+    Document::from_json(json).map_err(|e| InvariantError::Serialization(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fills a buffer with bytes from a fixed linear congruential generator
+    fn seeded_bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_generated_ops_are_valid_where_they_run() {
+        let mut u = Unstructured::new(&[7, 200, 3, 90, 1, 0, 44, 5, 6, 250, 9, 12]);
+        let mut doc = Document::from_text("Hello");
+        let ops = arbitrary_sequence(&mut u, &mut doc, 10).unwrap();
+        assert!(!ops.is_empty());
+        assert!(check_invariants(&Document::from_text("Hello"), &ops).is_ok());
+    }
+
+    #[test]
+    fn test_seeded_sequences_keep_invariants() {
+        for seed in 0..200 {
+            let data = seeded_bytes(seed, 512);
+            if let Err(e) = fuzz_document(&data) {
+                panic!("seed {}: {}", seed, e);
+            }
+        }
+    }
+
+    #[test]
+    fn test_failing_operation_is_reported() {
+        let original = Document::from_text("abc");
+        // A sequence drawn against another document is not valid here
+        let ops = vec![EditOp::Delete { start: 0, end: 10 }];
+        assert!(matches!(
+            check_invariants(&original, &ops),
+            Err(InvariantError::OperationFailed { index: 0, .. })
+        ));
+    }
+}
@@ -1,19 +1,26 @@
 // Command history implementation
 
-use super::{Command, CommandResult};
+use super::{Command, CommandResult, CompositeCommand, Operation};
 use crate::document::Document;
+use std::collections::BTreeMap;
+use std::time::Duration;
 
 /// Manages undo/redo history for document commands.
 ///
+/// History is kept as a tree: undoing moves to the parent state and a new
+/// command after an undo starts a branch instead of discarding the undone
+/// commands. Redo follows the most recently visited branch, and
+/// `nodes` lists every state so a history browser can jump to any of them.
+///
 /// # Performance Characteristics
 ///
 /// ## Time Complexity
-/// - **Push command**: O(1) amortized
-///   - May be O(n) when max size is reached and oldest command is removed
+/// - **Push command**: O(log n)
+///   - May be O(n) when max size is reached and the oldest command is removed
 /// - **Undo**: O(c) where c is the cost of undoing the specific command
 ///   - Typically O(n) where n is the size of text affected
 /// - **Redo**: O(c) where c is the cost of redoing the specific command
-/// - **Can undo/redo**: O(1) - just checks if stack is empty
+/// - **Can undo/redo**: O(log n)
 /// - **Clear**: O(n) where n is the number of commands
 ///
 /// ## Space Complexity
@@ -23,13 +30,21 @@ use crate::document::Document;
 /// - Each command stores the text it affected for undo
 ///
 /// ## Performance Notes
-/// - Commands are stored in a Vec for O(1) push/pop
-/// - Redo stack is cleared when a new command is executed
-/// - Max size limit prevents unbounded memory growth
+/// - Commands are stored in a map keyed by node id
+/// - Max size limits the commands across all branches; the oldest branch
+///   tips are dropped first, then the oldest undo steps
 /// - Large operations (e.g., paste 10KB text) store full text for undo
 /// - Consider reducing max_size for memory-constrained environments
 /// - Undo/redo of format operations is faster than text operations
 ///
+/// # Coalescing
+///
+/// Characters typed one at a time are merged into a single undo entry
+/// while each follows the previous one within the coalescing window, so
+/// undo removes a burst of typing instead of one character. Whitespace
+/// typed after a word, a pause longer than the window, or any other
+/// command starts a new entry.
+///
 /// # Example
 /// ```
 /// use rich_text_editor_wasm::operations::history::CommandHistory;
@@ -39,15 +54,67 @@ use crate::document::Document;
 /// assert!(!history.can_undo());
 /// ```
 pub struct CommandHistory {
-    undo_stack: Vec<Box<dyn Command>>,
-    redo_stack: Vec<Box<dyn Command>>,
+    /// States of the undo tree by id; the root has no command
+    nodes: BTreeMap<usize, TreeNode>,
+    root: usize,
+    current: usize,
+    next_id: usize,
     max_size: usize,
+    /// Commands collected while a batch is open
+    batch: Vec<Box<dyn Command>>,
+    /// Nesting depth of `begin_batch` calls
+    batch_depth: usize,
+    /// Longest pause between typed characters that still merges them
+    coalesce_window: Option<Duration>,
+    /// When the top undo entry last took a typed character
+    last_typed: Option<Duration>,
+}
+
+/// A state in the undo tree, reached by executing `command` from its parent
+struct TreeNode {
+    parent: Option<usize>,
+    /// Taken out while the command runs, and None for the root
+    command: Option<Box<dyn Command>>,
+    description: String,
+    children: Vec<usize>,
+    /// Child that redo moves to
+    redo_child: Option<usize>,
+}
+
+impl TreeNode {
+    fn root() -> Self {
+        Self {
+            parent: None,
+            command: None,
+            description: String::new(),
+            children: Vec::new(),
+            redo_child: None,
+        }
+    }
+}
+
+/// A state in the undo tree, as listed by `CommandHistory::nodes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryNode {
+    pub id: usize,
+    /// The state this one was reached from, or None for the initial state
+    pub parent: Option<usize>,
+    /// Description of the command leading here; empty for the initial state
+    pub description: String,
+    /// States reached from this one, oldest first
+    pub children: Vec<usize>,
 }
 
 impl CommandHistory {
     /// Default maximum size for the undo stack
     pub const DEFAULT_MAX_SIZE: usize = 100;
 
+    /// Default coalescing window for typed characters
+    pub const DEFAULT_COALESCE_WINDOW: Duration = Duration::from_millis(500);
+
+    /// Default number of newest undo steps that compaction leaves alone
+    pub const DEFAULT_KEEP_RECENT: usize = 20;
+
     /// Creates a new CommandHistory with the default maximum size
     pub fn new() -> Self {
         Self::with_max_size(Self::DEFAULT_MAX_SIZE)
@@ -56,9 +123,15 @@ impl CommandHistory {
     /// Creates a new CommandHistory with a specified maximum size
     pub fn with_max_size(max_size: usize) -> Self {
         Self {
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            nodes: BTreeMap::from([(0, TreeNode::root())]),
+            root: 0,
+            current: 0,
+            next_id: 1,
             max_size,
+            batch: Vec::new(),
+            batch_depth: 0,
+            coalesce_window: Some(Self::DEFAULT_COALESCE_WINDOW),
+            last_typed: None,
         }
     }
 
@@ -67,104 +140,375 @@ impl CommandHistory {
         // Execute the command
         cmd.execute(doc)?;
 
-        // Clear the redo stack when a new command is executed
-        self.redo_stack.clear();
-
-        // Add to undo stack
-        self.undo_stack.push(cmd);
-
-        // Enforce max size by removing oldest commands
-        if self.undo_stack.len() > self.max_size {
-            self.undo_stack.remove(0);
-        }
+        // Record it on the undo stack (or in the open batch)
+        self.push_command(cmd);
 
         Ok(())
     }
 
     /// Undoes the most recent command
+    ///
+    /// An open batch is closed first so its commands are undone together.
     pub fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
-        if let Some(mut cmd) = self.undo_stack.pop() {
-            cmd.undo(doc)?;
-            self.redo_stack.push(cmd);
-            Ok(())
-        } else {
-            Err(super::CommandError::NothingToUndo)
-        }
+        self.end_all_batches();
+        let (id, mut cmd) = self.take_undo().ok_or(super::CommandError::NothingToUndo)?;
+        let result = cmd.undo(doc);
+        self.finish_undo(id, cmd, result.is_ok());
+        result
     }
 
     /// Redoes the most recently undone command
     pub fn redo(&mut self, doc: &mut Document) -> CommandResult<()> {
-        if let Some(mut cmd) = self.redo_stack.pop() {
-            cmd.execute(doc)?;
-            self.undo_stack.push(cmd);
-            Ok(())
-        } else {
-            Err(super::CommandError::NothingToRedo)
-        }
+        self.end_all_batches();
+        let (id, mut cmd) = self
+            .take_redo(None)
+            .ok_or(super::CommandError::NothingToRedo)?;
+        let result = cmd.execute(doc);
+        self.finish_redo(id, cmd, result.is_ok());
+        result
     }
 
     /// Returns true if there are commands that can be undone
     pub fn can_undo(&self) -> bool {
-        !self.undo_stack.is_empty()
+        self.current != self.root || !self.batch.is_empty()
     }
 
     /// Returns true if there are commands that can be redone
     pub fn can_redo(&self) -> bool {
-        !self.redo_stack.is_empty()
+        self.nodes[&self.current].redo_child.is_some()
     }
 
     /// Clears all undo and redo history
+    ///
+    /// Commands collected by an open batch are discarded as well; the batch
+    /// itself stays open.
     pub fn clear(&mut self) {
-        self.undo_stack.clear();
-        self.redo_stack.clear();
+        self.nodes = BTreeMap::from([(self.next_id, TreeNode::root())]);
+        self.root = self.next_id;
+        self.current = self.next_id;
+        self.next_id += 1;
+        self.batch.clear();
+        self.last_typed = None;
+    }
+
+    /// Moves the undo steps past changes made without going through the
+    /// history, such as remote edits
+    ///
+    /// `ops` are the text edits among the changes, applied in order to the
+    /// current state; they may be empty when only formatting changed. Steps
+    /// are moved newest first; the first one that cannot be moved is dropped
+    /// along with every older step. Steps that restore whole block
+    /// snapshots cannot be moved, so they never undo a remote block change.
+    /// Redo steps and other branches are dropped, since the changes never
+    /// reached the states they lead to.
+    pub(crate) fn rebase(&mut self, ops: &[Operation]) {
+        self.last_typed = None;
+
+        let mut ops = ops.to_vec();
+        for cmd in self.batch.iter_mut().rev() {
+            match cmd.rebase(&ops) {
+                Some(moved) => ops = moved,
+                None => {
+                    self.clear();
+                    return;
+                }
+            }
+        }
+
+        let path: Vec<usize> = self.ancestors(self.current).collect();
+        let mut kept = 0;
+        for &id in &path {
+            let node = self.nodes.get_mut(&id).unwrap();
+            let Some(moved) = node.command.as_mut().and_then(|cmd| cmd.rebase(&ops)) else {
+                break;
+            };
+            if let Some(cmd) = &node.command {
+                node.description = cmd.description();
+            }
+            ops = moved;
+            kept += 1;
+        }
+
+        // Keep only the moved steps, below the state of the first one dropped
+        let mut nodes = BTreeMap::new();
+        let mut child = None;
+        for &id in &path[..=kept] {
+            let mut node = self.nodes.remove(&id).unwrap();
+            node.children = child.into_iter().collect();
+            node.redo_child = child;
+            nodes.insert(id, node);
+            child = Some(id);
+        }
+        let root = path[kept];
+        let node = nodes.get_mut(&root).unwrap();
+        node.parent = None;
+        node.command = None;
+        node.description.clear();
+        self.nodes = nodes;
+        self.root = root;
+    }
+
+    /// Starts grouping pushed commands into a single undo entry
+    ///
+    /// Batches nest: only the outermost `end_batch` records the entry.
+    pub fn begin_batch(&mut self) {
+        self.batch_depth += 1;
+    }
+
+    /// Ends the current batch
+    ///
+    /// When the outermost batch ends, the collected commands are pushed as
+    /// one `CompositeCommand`. Empty batches leave no entry.
+    ///
+    /// # Returns
+    /// false if no batch was open
+    pub fn end_batch(&mut self) -> bool {
+        if self.batch_depth == 0 {
+            return false;
+        }
+
+        self.batch_depth -= 1;
+        if self.batch_depth == 0 {
+            self.commit_batch();
+        }
+        true
+    }
+
+    /// Closes every open batch, recording the collected commands
+    pub(crate) fn end_all_batches(&mut self) {
+        if self.batch_depth > 0 {
+            self.batch_depth = 0;
+            self.commit_batch();
+        }
     }
 
-    /// Returns the number of commands in the undo stack
+    /// Returns true if a batch is currently open
+    pub fn is_batching(&self) -> bool {
+        self.batch_depth > 0
+    }
+
+    /// Pushes the collected batch commands as one undo entry
+    fn commit_batch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let commands = std::mem::take(&mut self.batch);
+        self.push_command(Box::new(CompositeCommand::new(commands)));
+    }
+
+    /// Returns the number of commands that can be undone
     pub fn undo_count(&self) -> usize {
-        self.undo_stack.len()
+        self.ancestors(self.current).count() - 1
     }
 
-    /// Returns the number of commands in the redo stack
+    /// Returns the number of commands redo can step through
     pub fn redo_count(&self) -> usize {
-        self.redo_stack.len()
+        std::iter::successors(self.nodes[&self.current].redo_child, |id| {
+            self.nodes[id].redo_child
+        })
+        .count()
     }
 
-    /// Pushes a command onto the undo stack (used by Document)
-    pub(crate) fn push_command(&mut self, cmd: Box<dyn Command>) {
-        // Clear the redo stack when a new command is executed
-        self.redo_stack.clear();
+    /// Returns the label of the change undo would revert, such as "typing"
+    pub fn undo_label(&self) -> Option<&'static str> {
+        if !self.batch.is_empty() {
+            return Some(super::shared_label(&self.batch));
+        }
+        self.nodes[&self.current]
+            .command
+            .as_ref()
+            .map(|cmd| cmd.label())
+    }
 
-        // Add to undo stack
-        self.undo_stack.push(cmd);
+    /// Returns the label of the change redo would reapply
+    pub fn redo_label(&self) -> Option<&'static str> {
+        let id = self.nodes[&self.current].redo_child?;
+        self.nodes[&id].command.as_ref().map(|cmd| cmd.label())
+    }
+
+    /// Returns the id of the current state
+    pub fn current_node(&self) -> usize {
+        self.current
+    }
 
-        // Enforce max size by removing oldest commands
-        if self.undo_stack.len() > self.max_size {
-            self.undo_stack.remove(0);
+    /// Lists every state in the undo tree, oldest first
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    ///
+    /// let mut doc = Document::new();
+    /// doc.insert_text(Position::new(0), "a").unwrap();
+    /// doc.undo().unwrap();
+    /// doc.insert_text(Position::new(0), "b").unwrap();
+    ///
+    /// // Both edits branch off the initial state
+    /// let nodes = doc.history_nodes();
+    /// assert_eq!(nodes.len(), 3);
+    /// assert_eq!(nodes[0].children.len(), 2);
+    /// ```
+    pub fn nodes(&self) -> Vec<HistoryNode> {
+        self.nodes
+            .iter()
+            .map(|(&id, node)| HistoryNode {
+                id,
+                parent: node.parent,
+                description: node.description.clone(),
+                children: node.children.clone(),
+            })
+            .collect()
+    }
+
+    /// Finds how to move from the current state to `target`
+    ///
+    /// # Returns
+    /// The number of undo steps, then the states to redo into in order, or
+    /// None if `target` is not in the tree
+    pub(crate) fn path_to(&self, target: usize) -> Option<(usize, Vec<usize>)> {
+        if !self.nodes.contains_key(&target) {
+            return None;
+        }
+        let current_path: Vec<usize> = self.ancestors(self.current).collect();
+        let mut redo = Vec::new();
+        for id in self.ancestors(target) {
+            if let Some(undo) = current_path.iter().position(|&a| a == id) {
+                redo.reverse();
+                return Some((undo, redo));
+            }
+            redo.push(id);
+        }
+        None
+    }
+
+    /// Iterates over a state and its ancestors up to the root
+    fn ancestors(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(Some(id), |id| self.nodes[id].parent)
+    }
+
+    /// Pushes a command as a new state after the current one (used by Document)
+    pub(crate) fn push_command(&mut self, cmd: Box<dyn Command>) {
+        // Collect into the open batch instead of creating an undo entry
+        if self.batch_depth > 0 {
+            self.batch.push(cmd);
+            return;
+        }
+
+        // Merge a typed character into the entry typed just before it
+        let now = now();
+        let typed = cmd
+            .as_insertion()
+            .is_some_and(|(_, text)| text.chars().count() == 1);
+        if let (Some(window), Some(last)) = (self.coalesce_window, self.last_typed.take())
+            && typed
+            && now.saturating_sub(last) < window
+            && let Some(top) = self
+                .nodes
+                .get_mut(&self.current)
+                .and_then(|node| node.command.as_mut())
+            && top.merge(cmd.as_ref())
+        {
+            let description = top.description();
+            self.nodes.get_mut(&self.current).unwrap().description = description;
+            self.last_typed = Some(now);
+            return;
         }
+        if typed {
+            self.last_typed = Some(now);
+        }
+
+        // Branch off the current state; redo now follows the new branch
+        let id = self.next_id;
+        self.next_id += 1;
+        self.nodes.insert(
+            id,
+            TreeNode {
+                parent: Some(self.current),
+                description: cmd.description(),
+                command: Some(cmd),
+                children: Vec::new(),
+                redo_child: None,
+            },
+        );
+        let parent = self.nodes.get_mut(&self.current).unwrap();
+        parent.children.push(id);
+        parent.redo_child = Some(id);
+        self.current = id;
+
+        self.prune();
     }
 
-    /// Pops a command from the undo stack (used by Document)
-    pub(crate) fn pop_undo(&mut self) -> Option<Box<dyn Command>> {
-        self.undo_stack.pop()
+    /// Takes the command leading to the current state, for undoing it
+    pub(crate) fn take_undo(&mut self) -> Option<(usize, Box<dyn Command>)> {
+        self.last_typed = None;
+        let id = self.current;
+        let cmd = self.nodes.get_mut(&id)?.command.take()?;
+        Some((id, cmd))
     }
 
-    /// Pushes a command onto the redo stack (used by Document)
-    pub(crate) fn push_redo(&mut self, cmd: Box<dyn Command>) {
-        self.redo_stack.push(cmd);
+    /// Returns a command taken by `take_undo`, moving to the state before
+    /// it if it was undone
+    pub(crate) fn finish_undo(&mut self, id: usize, cmd: Box<dyn Command>, undone: bool) {
+        let node = self.nodes.get_mut(&id).unwrap();
+        node.command = Some(cmd);
+        if undone && let Some(parent) = node.parent {
+            self.nodes.get_mut(&parent).unwrap().redo_child = Some(id);
+            self.current = parent;
+        }
     }
 
-    /// Pops a command from the redo stack (used by Document)
-    pub(crate) fn pop_redo(&mut self) -> Option<Box<dyn Command>> {
-        self.redo_stack.pop()
+    /// Takes the command leading to a child of the current state, for
+    /// redoing it
+    ///
+    /// `child` selects the branch; None follows the most recent one.
+    pub(crate) fn take_redo(&mut self, child: Option<usize>) -> Option<(usize, Box<dyn Command>)> {
+        self.last_typed = None;
+        let id = child.or(self.nodes[&self.current].redo_child)?;
+        let node = self.nodes.get_mut(&id)?;
+        if node.parent != Some(self.current) {
+            return None;
+        }
+        Some((id, node.command.take()?))
     }
 
-    /// Pushes a command onto the undo stack (used by Document)
-    pub(crate) fn push_undo(&mut self, cmd: Box<dyn Command>) {
-        self.undo_stack.push(cmd);
+    /// Returns a command taken by `take_redo`, moving to the state after it
+    /// if it was executed
+    pub(crate) fn finish_redo(&mut self, id: usize, cmd: Box<dyn Command>, executed: bool) {
+        self.nodes.get_mut(&id).unwrap().command = Some(cmd);
+        if executed {
+            self.nodes.get_mut(&self.current).unwrap().redo_child = Some(id);
+            self.current = id;
+        }
+    }
 
-        // Enforce max size by removing oldest commands
-        if self.undo_stack.len() > self.max_size {
-            self.undo_stack.remove(0);
+    /// Drops commands until at most `max_size` are kept
+    ///
+    /// Branch tips away from the current state go first, oldest first. Once
+    /// only the path to the current state is left, its oldest command is
+    /// folded into the initial state.
+    fn prune(&mut self) {
+        while self.nodes.len() - 1 > self.max_size {
+            let tip = self.nodes.iter().find_map(|(&id, node)| {
+                (node.children.is_empty() && id != self.current && id != self.root).then_some(id)
+            });
+            if let Some(tip) = tip {
+                let node = self.nodes.remove(&tip).unwrap();
+                if let Some(parent) = node.parent.and_then(|p| self.nodes.get_mut(&p)) {
+                    parent.children.retain(|&child| child != tip);
+                    if parent.redo_child == Some(tip) {
+                        parent.redo_child = parent.children.last().copied();
+                    }
+                }
+                continue;
+            }
+
+            // Everything left is one path from the root to the current state
+            let old_root = self.nodes.remove(&self.root).unwrap();
+            let new_root = old_root.children[0];
+            let node = self.nodes.get_mut(&new_root).unwrap();
+            node.parent = None;
+            node.command = None;
+            node.description.clear();
+            self.root = new_root;
         }
     }
 
@@ -204,12 +548,138 @@ impl CommandHistory {
         self.max_size = max_size;
 
         // Remove oldest commands if we exceed the new limit
-        while self.undo_stack.len() > self.max_size {
-            self.undo_stack.remove(0);
+        self.prune();
+    }
+
+    /// Merges runs of older undo steps that have the same label
+    ///
+    /// Only steps on the path to the current state are merged, and only
+    /// where no other branch leaves between them. Within a merged step,
+    /// commands that can absorb the one after them do so, so a morning of
+    /// typing becomes a few insertions rather than one per word. The
+    /// `keep_recent` newest steps are left alone so recent edits still undo
+    /// one at a time.
+    ///
+    /// Compaction frees slots under the history limit, letting long
+    /// sessions keep more history. It is meant to run while the editor is
+    /// idle; with a `budget`, it stops once that much time has passed.
+    ///
+    /// # Returns
+    /// true if the budget ran out before every step was looked at
+    pub fn compact(&mut self, keep_recent: usize, budget: Option<Duration>) -> bool {
+        let started = now();
+        let mut path: Vec<usize> = self.ancestors(self.current).collect();
+        path.reverse();
+        let end = path.len().saturating_sub(keep_recent);
+
+        // Index 0 is the root, which has no command
+        let mut i = 1;
+        while i < end {
+            let label = self.nodes[&path[i]].command.as_ref().map(|cmd| cmd.label());
+            let mut j = i + 1;
+            while j < end
+                && self.nodes[&path[j - 1]].children.len() == 1
+                && self.nodes[&path[j]].command.as_ref().map(|cmd| cmd.label()) == label
+            {
+                j += 1;
+            }
+            if label.is_some() && j - i > 1 {
+                self.merge_steps(&path[i..j]);
+            }
+            i = j;
+
+            if budget.is_some_and(|budget| now().saturating_sub(started) >= budget) {
+                return i < end;
+            }
+        }
+        false
+    }
+
+    /// Replaces consecutive states with the last of them, reached from the
+    /// first one's parent by a single command
+    fn merge_steps(&mut self, ids: &[usize]) {
+        let mut commands: Vec<Box<dyn Command>> = Vec::new();
+        for id in ids {
+            let mut cmd = self.nodes.get_mut(id).unwrap().command.take().unwrap();
+            for cmd in cmd.take_commands().unwrap_or_else(|| vec![cmd]) {
+                if let Some(last) = commands.last_mut()
+                    && last.absorb(cmd.as_ref())
+                {
+                    continue;
+                }
+                commands.push(cmd);
+            }
+        }
+        let command: Box<dyn Command> = if commands.len() == 1 {
+            commands.pop().unwrap()
+        } else {
+            Box::new(CompositeCommand::new(commands))
+        };
+
+        let (first, last) = (ids[0], ids[ids.len() - 1]);
+        let parent = self.nodes[&first].parent;
+        for id in &ids[..ids.len() - 1] {
+            self.nodes.remove(id);
+        }
+        let node = self.nodes.get_mut(&last).unwrap();
+        node.parent = parent;
+        node.description = command.description();
+        node.command = Some(command);
+        if let Some(parent) = parent.and_then(|p| self.nodes.get_mut(&p)) {
+            for child in &mut parent.children {
+                if *child == first {
+                    *child = last;
+                }
+            }
+            if parent.redo_child == Some(first) {
+                parent.redo_child = Some(last);
+            }
         }
     }
 }
 
+impl CommandHistory {
+    /// Gets the coalescing window for typed characters
+    ///
+    /// # Returns
+    /// None if every typed character is its own undo entry
+    pub fn get_coalesce_window(&self) -> Option<Duration> {
+        self.coalesce_window
+    }
+
+    /// Sets the coalescing window for typed characters
+    ///
+    /// Pass None to record every typed character as its own undo entry.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::operations::history::CommandHistory;
+    /// use std::time::Duration;
+    ///
+    /// let mut history = CommandHistory::new();
+    /// history.set_coalesce_window(Some(Duration::from_secs(1)));
+    /// assert_eq!(history.get_coalesce_window(), Some(Duration::from_secs(1)));
+    /// ```
+    pub fn set_coalesce_window(&mut self, window: Option<Duration>) {
+        self.coalesce_window = window;
+        self.last_typed = None;
+    }
+}
+
+/// Time since the Unix epoch, from the browser clock under WebAssembly
+pub(crate) fn now() -> Duration {
+    #[cfg(target_arch = "wasm32")]
+    {
+        Duration::from_secs_f64(js_sys::Date::now() / 1000.0)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+}
+
 impl Default for CommandHistory {
     fn default() -> Self {
         Self::new()
@@ -324,7 +794,7 @@ mod tests {
 
         assert!(history.can_redo());
 
-        // Execute a new command - should clear redo stack
+        // A new command starts a branch, so there is nothing to redo
         history
             .execute(
                 Box::new(InsertCommand::new(Position::new(0), "World".to_string())),
@@ -342,10 +812,10 @@ mod tests {
         let mut doc = Document::new();
 
         // Add 5 commands
-        for i in 0..5 {
+        for _ in 0..5 {
             history
                 .execute(
-                    Box::new(InsertCommand::new(Position::new(i), "a".to_string())),
+                    Box::new(InsertCommand::new(Position::new(0), "a".to_string())),
                     &mut doc,
                 )
                 .unwrap();
@@ -431,10 +901,10 @@ mod tests {
         let mut doc = Document::new();
 
         // Add 10 commands
-        for i in 0..10 {
+        for _ in 0..10 {
             history
                 .execute(
-                    Box::new(InsertCommand::new(Position::new(i), "a".to_string())),
+                    Box::new(InsertCommand::new(Position::new(0), "a".to_string())),
                     &mut doc,
                 )
                 .unwrap();
@@ -466,4 +936,293 @@ mod tests {
         assert_eq!(history.get_max_size(), 50);
         assert_eq!(history.undo_count(), 0);
     }
+
+    #[test]
+    fn test_batch_records_single_entry() {
+        let mut history = CommandHistory::new();
+        let mut doc = Document::new();
+
+        history.begin_batch();
+        history
+            .execute(
+                Box::new(InsertCommand::new(Position::new(0), "Hello".to_string())),
+                &mut doc,
+            )
+            .unwrap();
+        history
+            .execute(
+                Box::new(InsertCommand::new(Position::new(5), " World".to_string())),
+                &mut doc,
+            )
+            .unwrap();
+        assert!(history.is_batching());
+        assert_eq!(history.undo_count(), 0);
+        assert!(history.end_batch());
+
+        assert_eq!(history.undo_count(), 1);
+        history.undo(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "");
+        history.redo(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "Hello World");
+    }
+
+    #[test]
+    fn test_nested_and_empty_batches() {
+        let mut history = CommandHistory::new();
+        let mut doc = Document::new();
+
+        assert!(!history.end_batch());
+
+        history.begin_batch();
+        history.begin_batch();
+        history
+            .execute(
+                Box::new(InsertCommand::new(Position::new(0), "a".to_string())),
+                &mut doc,
+            )
+            .unwrap();
+        history.end_batch();
+        assert_eq!(history.undo_count(), 0);
+        history.end_batch();
+        assert_eq!(history.undo_count(), 1);
+
+        history.begin_batch();
+        history.end_batch();
+        assert_eq!(history.undo_count(), 1);
+    }
+
+    #[test]
+    fn test_undo_closes_open_batch() {
+        let mut history = CommandHistory::new();
+        let mut doc = Document::new();
+
+        history.begin_batch();
+        history
+            .execute(
+                Box::new(InsertCommand::new(Position::new(0), "ab".to_string())),
+                &mut doc,
+            )
+            .unwrap();
+        assert!(history.can_undo());
+
+        history.undo(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "");
+        assert!(!history.is_batching());
+    }
+
+    fn type_text(history: &mut CommandHistory, doc: &mut Document, at: usize, text: &str) {
+        for (i, c) in text.chars().enumerate() {
+            let cmd = InsertCommand::new(Position::new(at + i), c.to_string());
+            history.execute(Box::new(cmd), doc).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_typing_is_coalesced() {
+        let mut history = CommandHistory::new();
+        history.set_coalesce_window(Some(Duration::from_secs(3600)));
+        let mut doc = Document::new();
+
+        type_text(&mut history, &mut doc, 0, "ab  cd");
+        // Whitespace after a word starts a new step
+        assert_eq!(history.undo_count(), 2);
+
+        // Typing elsewhere starts a new step too
+        type_text(&mut history, &mut doc, 0, "x");
+        assert_eq!(history.undo_count(), 3);
+        history.undo(&mut doc).unwrap();
+        history.undo(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "ab");
+
+        // Typing after an undo does not merge into the entry below it
+        type_text(&mut history, &mut doc, 2, "c");
+        assert_eq!(history.undo_count(), 2);
+
+        // Pasted text is its own step
+        let cmd = InsertCommand::new(Position::new(3), "de".to_string());
+        history.execute(Box::new(cmd), &mut doc).unwrap();
+        type_text(&mut history, &mut doc, 5, "f");
+        assert_eq!(history.undo_count(), 4);
+    }
+
+    #[test]
+    fn test_coalescing_disabled() {
+        let mut history = CommandHistory::new();
+        assert_eq!(
+            history.get_coalesce_window(),
+            Some(CommandHistory::DEFAULT_COALESCE_WINDOW)
+        );
+        history.set_coalesce_window(None);
+        let mut doc = Document::new();
+
+        type_text(&mut history, &mut doc, 0, "abc");
+        assert_eq!(history.undo_count(), 3);
+    }
+
+    fn insert(doc: &mut Document, offset: usize, text: &str) -> usize {
+        doc.insert_text(Position::new(offset), text).unwrap();
+        doc.current_history_node()
+    }
+
+    #[test]
+    fn test_new_edit_keeps_undone_branch() {
+        let mut doc = Document::new();
+        let a = insert(&mut doc, 0, "a ");
+        let b = insert(&mut doc, 2, "b ");
+        doc.undo().unwrap();
+        let c = insert(&mut doc, 2, "c ");
+
+        let nodes = doc.history_nodes();
+        assert_eq!(nodes.len(), 4);
+        let node_a = nodes.iter().find(|n| n.id == a).unwrap();
+        assert_eq!(node_a.children, vec![b, c]);
+        assert_eq!(nodes.iter().find(|n| n.id == b).unwrap().parent, Some(a));
+
+        // Jump across to the undone branch, then back
+        doc.undo_to_node(b).unwrap();
+        assert_eq!(doc.get_content(), "a b ");
+        assert_eq!(doc.current_history_node(), b);
+        doc.undo_to_node(c).unwrap();
+        assert_eq!(doc.get_content(), "a c ");
+
+        // Redo follows the branch visited last
+        doc.undo_to_node(nodes[0].id).unwrap();
+        assert_eq!(doc.get_content(), "");
+        doc.redo().unwrap();
+        doc.redo().unwrap();
+        assert_eq!(doc.get_content(), "a c ");
+
+        assert!(matches!(
+            doc.undo_to_node(99),
+            Err(crate::operations::CommandError::UnknownHistoryNode { id: 99 })
+        ));
+    }
+
+    #[test]
+    fn test_rebase_moves_undo_steps_and_drops_redo() {
+        let mut doc = Document::new();
+        let a = insert(&mut doc, 0, "a ");
+        insert(&mut doc, 2, "b ");
+        doc.undo().unwrap();
+
+        // An edit made outside the history, as a remote one would be
+        let remote = Operation::Insert {
+            position: 0,
+            text: "x".to_string(),
+        };
+        doc.insert_text_direct(Position::new(0), "x");
+        doc.history.rebase(&[remote]);
+
+        assert!(!doc.can_redo());
+        let nodes = doc.history_nodes();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[1].id, a);
+        assert_eq!(nodes[1].description, "Insert 'a ' at position 1");
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "x");
+    }
+
+    #[test]
+    fn test_pruning_drops_old_branches_first() {
+        let mut doc = Document::new();
+        doc.set_history_limit(3);
+        let a = insert(&mut doc, 0, "a ");
+        let b = insert(&mut doc, 2, "b ");
+        doc.undo().unwrap();
+        insert(&mut doc, 2, "c ");
+        assert_eq!(doc.history_nodes().len(), 4);
+
+        // The undone branch goes before any undo step
+        insert(&mut doc, 4, "d ");
+        let nodes = doc.history_nodes();
+        assert!(nodes.iter().all(|n| n.id != b));
+        assert_eq!(nodes.iter().find(|n| n.id == a).unwrap().children.len(), 1);
+
+        // Then the oldest undo step
+        insert(&mut doc, 6, "e ");
+        assert_eq!(doc.history_nodes().len(), 4);
+        let mut undone = 0;
+        while doc.can_undo() {
+            doc.undo().unwrap();
+            undone += 1;
+        }
+        assert_eq!(undone, 3);
+        assert_eq!(doc.get_content(), "a ");
+    }
+
+    #[test]
+    fn test_undo_and_redo_labels() {
+        let mut doc = Document::new();
+        assert_eq!(doc.undo_label(), None);
+
+        doc.insert_text(Position::new(0), "ab").unwrap();
+        doc.delete_range(crate::document::Range::from_offsets(0, 1))
+            .unwrap();
+        assert_eq!(doc.undo_label(), Some("deletion"));
+        doc.undo().unwrap();
+        assert_eq!(doc.undo_label(), Some("typing"));
+        assert_eq!(doc.redo_label(), Some("deletion"));
+
+        // A batch is labelled by its commands
+        doc.begin_batch();
+        doc.insert_text(Position::new(0), "c").unwrap();
+        doc.delete_range(crate::document::Range::from_offsets(0, 1))
+            .unwrap();
+        assert_eq!(doc.undo_label(), Some("edit"));
+        doc.end_batch();
+        assert_eq!(doc.undo_label(), Some("edit"));
+        assert_eq!(doc.redo_label(), None);
+    }
+
+    #[test]
+    fn test_compact_merges_older_steps() {
+        let mut history = CommandHistory::new();
+        history.set_coalesce_window(None);
+        let mut doc = Document::new();
+        type_text(&mut history, &mut doc, 0, "abcd");
+        let cmd = crate::operations::DeleteCommand::new(crate::document::Range::from_offsets(0, 1));
+        history.execute(Box::new(cmd), &mut doc).unwrap();
+        type_text(&mut history, &mut doc, 3, "ef");
+        assert_eq!(history.undo_count(), 7);
+
+        // The two newest steps stay; the typing before the delete becomes
+        // one insertion
+        assert!(!history.compact(2, None));
+        assert_eq!(history.undo_count(), 4);
+        assert_eq!(
+            history.nodes()[1].description,
+            "Insert 'abcd' at position 0"
+        );
+
+        history.undo(&mut doc).unwrap();
+        history.undo(&mut doc).unwrap();
+        history.undo(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "abcd");
+        history.undo(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "");
+        assert!(!history.can_undo());
+
+        while history.can_redo() {
+            history.redo(&mut doc).unwrap();
+        }
+        assert_eq!(doc.get_content(), "bcdef");
+    }
+
+    #[test]
+    fn test_compact_keeps_branches() {
+        let mut doc = Document::new();
+        let a = insert(&mut doc, 0, "a ");
+        let b = insert(&mut doc, 2, "b ");
+        doc.undo().unwrap();
+        insert(&mut doc, 2, "c ");
+        insert(&mut doc, 4, "d ");
+
+        // "a " has two children, so only "c " and "d " merge
+        doc.compact_history(0, None);
+        let nodes = doc.history_nodes();
+        assert_eq!(nodes.len(), 4);
+        assert_eq!(nodes.iter().find(|n| n.id == a).unwrap().children.len(), 2);
+        doc.undo_to_node(b).unwrap();
+        assert_eq!(doc.get_content(), "a b ");
+    }
 }
@@ -0,0 +1,281 @@
+//! Input rules
+//!
+//! Markdown-style shortcuts applied as text is typed. At the start of a
+//! paragraph, "# " to "###### " turn it into a heading, "- ", "* " or "+ "
+//! into a bullet list item, "> " into a block quote and "``` " into a code
+//! block, with the language after the backticks if there is one. Typing the
+//! closing "**" of "**text**" makes the text bold. The Markdown syntax is
+//! removed once the rule applies. Whether text typed after the new bold
+//! text is bold too follows the format affinity of bold, as at the end of
+//! any bold run.
+//!
+//! Like autocorrect, only single characters inserted with
+//! `Document::insert_text` trigger a rule, never pasted or loaded text, and
+//! no rule applies in code. Each rule is its own undo step, so undoing
+//! right after one brings back the Markdown as typed.
+
+use crate::document::{Document, Position, Range};
+use crate::formatting::{BlockType, InlineFormat};
+use std::collections::HashSet;
+
+/// A Markdown-style shortcut
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputRule {
+    /// "# " to "###### " make a heading of that level
+    Heading,
+    /// "- ", "* " or "+ " make a bullet list item
+    BulletList,
+    /// "> " makes a block quote
+    BlockQuote,
+    /// "``` " makes a code block, "```rust " one in that language
+    CodeBlock,
+    /// "**text**" makes the text bold
+    Bold,
+}
+
+impl InputRule {
+    /// Every rule, in the order they are documented
+    pub const ALL: [InputRule; 5] = [
+        InputRule::Heading,
+        InputRule::BulletList,
+        InputRule::BlockQuote,
+        InputRule::CodeBlock,
+        InputRule::Bold,
+    ];
+}
+
+/// The input rules turned on for a document
+///
+/// The default has every rule off; set rules on a document with
+/// `Document::set_input_rules`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputRules {
+    enabled: HashSet<InputRule>,
+}
+
+impl InputRules {
+    /// Creates input rules with every rule on
+    pub fn new() -> Self {
+        Self {
+            enabled: InputRule::ALL.into_iter().collect(),
+        }
+    }
+
+    /// Returns true if `rule` is on
+    pub fn is_enabled(&self, rule: InputRule) -> bool {
+        self.enabled.contains(&rule)
+    }
+
+    /// Turns `rule` on or off
+    pub fn set_enabled(&mut self, rule: InputRule, enabled: bool) {
+        if enabled {
+            self.enabled.insert(rule);
+        } else {
+            self.enabled.remove(&rule);
+        }
+    }
+
+    /// Returns the block type the Markdown `prefix` of a paragraph asks for,
+    /// if its rule is on
+    fn block_type(&self, prefix: &str) -> Option<BlockType> {
+        let level = prefix.chars().take_while(|&c| c == '#').count();
+        let (rule, block_type) = match prefix {
+            _ if (1..=6).contains(&level) && level == prefix.len() => {
+                (InputRule::Heading, BlockType::heading(level as u8))
+            }
+            "-" | "*" | "+" => (InputRule::BulletList, BlockType::BulletList),
+            ">" => (InputRule::BlockQuote, BlockType::block_quote()),
+            _ => {
+                let language = prefix.strip_prefix("```")?;
+                if language.contains('`') {
+                    return None;
+                }
+                let language = (!language.is_empty()).then_some(language);
+                (InputRule::CodeBlock, BlockType::code_block_with(language))
+            }
+        };
+        self.is_enabled(rule).then_some(block_type)
+    }
+}
+
+/// Returns the length in characters of the text between a pair of "**"
+/// that `before` ends with, the closing one included
+fn bold_text_length(before: &str) -> Option<usize> {
+    let inner = before.strip_suffix("**")?;
+    let open = inner.rfind("**")?;
+    let text = &inner[open + 2..];
+    let trimmed = !text.starts_with(char::is_whitespace) && !text.ends_with(char::is_whitespace);
+    (!text.is_empty() && !text.starts_with('*') && trimmed).then(|| text.chars().count())
+}
+
+impl Document {
+    /// Gets the input rules
+    pub fn input_rules(&self) -> &InputRules {
+        &self.input_rules
+    }
+
+    /// Gets the input rules for changing them in place
+    pub fn input_rules_mut(&mut self) -> &mut InputRules {
+        &mut self.input_rules
+    }
+
+    /// Sets the input rules
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    /// use rte_core::formatting::{BlockType, InlineFormat};
+    /// use rte_core::operations::input_rules::InputRules;
+    ///
+    /// let mut doc = Document::new();
+    /// doc.set_input_rules(InputRules::new());
+    /// for c in "## Some **bold**".chars() {
+    ///     let cursor = doc.get_selection().focus;
+    ///     doc.insert_text(cursor, &c.to_string()).unwrap();
+    /// }
+    /// assert_eq!(doc.get_content(), "Some bold");
+    /// assert_eq!(doc.get_block_type_at(Position::new(0)), BlockType::heading(2));
+    /// assert!(doc.get_formats_at(Position::new(6)).contains(&InlineFormat::Bold));
+    ///
+    /// doc.undo().unwrap();
+    /// assert_eq!(doc.get_content(), "Some **bold**");
+    /// ```
+    pub fn set_input_rules(&mut self, input_rules: InputRules) {
+        self.input_rules = input_rules;
+    }
+
+    /// Applies the input rule due after `text` was typed at `pos`, returning
+    /// true if one applied
+    pub(crate) fn apply_input_rules(&mut self, pos: Position, text: &str) -> bool {
+        let mut chars = text.chars();
+        let (Some(typed), None) = (chars.next(), chars.next()) else {
+            return false;
+        };
+        let offset = pos.offset();
+        let line_start = self.line_start_before(offset);
+        let block_type = self.get_block_type_at(pos);
+        if block_type.is_code_block()
+            || (offset > 0
+                && self
+                    .get_formats_at(Position::new(offset - 1))
+                    .contains(&InlineFormat::Code))
+        {
+            return false;
+        }
+
+        match typed {
+            ' ' if block_type == BlockType::Paragraph => {
+                let prefix = self.get_text_in_range(Range::from_offsets(line_start, offset));
+                let Some(new_type) = self.input_rules.block_type(&prefix) else {
+                    return false;
+                };
+                self.begin_batch();
+                let _ = self.delete_range(Range::from_offsets(line_start, offset + 1));
+                let _ = self
+                    .set_block_type_lines(Range::from_offsets(line_start, line_start), new_type);
+                self.end_batch();
+                true
+            }
+            '*' if self.input_rules.is_enabled(InputRule::Bold) => {
+                let before = self.get_text_in_range(Range::from_offsets(line_start, offset + 1));
+                let Some(length) = bold_text_length(&before) else {
+                    return false;
+                };
+                // The closing markers end at the typed character
+                let close = offset - 1;
+                let open = close - length - 2;
+                self.begin_batch();
+                let _ = self.delete_range(Range::from_offsets(close, offset + 1));
+                let _ = self.delete_range(Range::from_offsets(open, open + 2));
+                self.apply_format(Range::from_offsets(open, open + length), InlineFormat::Bold);
+                self.end_batch();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::FormatAffinity;
+
+    fn type_text(doc: &mut Document, text: &str) {
+        for c in text.chars() {
+            let pos = doc.get_selection().focus;
+            doc.insert_text(pos, &c.to_string()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_block_rules_at_paragraph_start() {
+        for (typed, block_type) in [
+            ("### x", BlockType::heading(3)),
+            ("- x", BlockType::BulletList),
+            ("> x", BlockType::block_quote()),
+            ("``` x", BlockType::code_block()),
+            ("```rust x", BlockType::code_block_with(Some("rust"))),
+        ] {
+            let mut doc = Document::new();
+            doc.set_input_rules(InputRules::new());
+            type_text(&mut doc, typed);
+            assert_eq!(doc.get_content(), "x", "{typed}");
+            assert_eq!(doc.get_block_type_at(Position::new(0)), block_type);
+        }
+
+        // Nothing applies mid-line, in code or in a list item
+        let mut doc = Document::new();
+        doc.set_input_rules(InputRules::new());
+        type_text(&mut doc, "####### x - y");
+        assert_eq!(doc.get_content(), "####### x - y");
+        type_text(&mut doc, "\n``` # x **y** ");
+        assert_eq!(doc.get_content(), "####### x - y\n# x **y** ");
+        let mut doc = Document::new();
+        doc.set_input_rules(InputRules::new());
+        type_text(&mut doc, "- > x");
+        assert_eq!(doc.get_content(), "> x");
+
+        // Undo brings back the Markdown
+        let mut doc = Document::new();
+        doc.set_input_rules(InputRules::new());
+        type_text(&mut doc, "# ");
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "# ");
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::Paragraph
+        );
+    }
+
+    #[test]
+    fn test_rules_can_be_turned_off() {
+        let mut doc = Document::new();
+        let mut input_rules = InputRules::new();
+        input_rules.set_enabled(InputRule::Heading, false);
+        doc.set_input_rules(input_rules);
+        assert!(!doc.input_rules().is_enabled(InputRule::Heading));
+        type_text(&mut doc, "# x");
+        assert_eq!(doc.get_content(), "# x");
+
+        doc.set_input_rules(InputRules::default());
+        type_text(&mut doc, " **y**");
+        assert_eq!(doc.get_content(), "# x **y**");
+    }
+
+    #[test]
+    fn test_bold_rule() {
+        let mut doc = Document::new();
+        doc.set_input_rules(InputRules::new());
+        doc.set_format_affinity(&InlineFormat::Bold, FormatAffinity::Neither);
+        type_text(&mut doc, "a **b c** d ** e** **f ** ***");
+        assert_eq!(doc.get_content(), "a b c d ** e** **f ** ***");
+        let bold: Vec<bool> = (0..8)
+            .map(|i| {
+                doc.get_formats_at(Position::new(i))
+                    .contains(&InlineFormat::Bold)
+            })
+            .collect();
+        assert_eq!(bold, [false, false, true, true, true, false, false, false]);
+    }
+}
@@ -0,0 +1,281 @@
+//! Line-based diffs for code block edits
+//!
+//! Apps that embed a code editor usually write a code block back by
+//! replacing its whole text, even when only a line or two changed. Stored as
+//! is, every such replace keeps two copies of the sample in the history.
+//! `Document::replace_range` uses `line_hunks` to turn a replace inside code
+//! blocks into one small replace per changed run of lines, so undo data only
+//! holds the lines that actually changed.
+
+use crate::document::{Document, Position, Range};
+use crate::operations::{Command, CommandResult, ReplaceCommand};
+
+/// Most lines compared against each other before the changed lines are
+/// treated as a single run, bounding the diff to about 4 MB of scratch space
+const MAX_DIFF_CELLS: usize = 1 << 20;
+
+/// A changed part of the text: character offsets into the old text and the
+/// text that replaces them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LineHunk {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Diffs `old` and `new` line by line
+///
+/// Returns the changed runs of lines in order, each trimmed to the
+/// characters that differ. Applying them from last to first turns `old`
+/// into `new`.
+pub(crate) fn line_hunks(old: &str, new: &str) -> Vec<LineHunk> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+
+    let prefix = old_lines
+        .iter()
+        .zip(&new_lines)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old_lines[prefix..old_lines.len() - suffix];
+    let new_mid = &new_lines[prefix..new_lines.len() - suffix];
+
+    // Character offset where each old line starts
+    let mut offsets = Vec::with_capacity(old_lines.len() + 1);
+    let mut offset = 0;
+    offsets.push(0);
+    for line in &old_lines {
+        offset += line.chars().count();
+        offsets.push(offset);
+    }
+
+    changed_runs(old_mid, new_mid)
+        .into_iter()
+        .filter_map(|(old_run, new_run)| {
+            let start = offsets[prefix + old_run.start];
+            let end = offsets[prefix + old_run.end];
+            let old_text: String = old_mid[old_run].concat();
+            trim_hunk(start, end, &old_text, &new_mid[new_run].concat())
+        })
+        .collect()
+}
+
+/// Pairs the runs of lines that differ between `old` and `new`, using the
+/// longest common subsequence of lines
+fn changed_runs(
+    old: &[&str],
+    new: &[&str],
+) -> Vec<(std::ops::Range<usize>, std::ops::Range<usize>)> {
+    if old.is_empty() && new.is_empty() {
+        return Vec::new();
+    }
+    if old.is_empty() || new.is_empty() || (old.len() + 1) * (new.len() + 1) > MAX_DIFF_CELLS {
+        return vec![(0..old.len(), 0..new.len())];
+    }
+
+    // lcs[i][j] is the length of the longest common subsequence of
+    // old[i..] and new[j..]
+    let width = new.len() + 1;
+    let mut lcs = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i * width + j] = if old[i] == new[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut runs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let (mut run_i, mut run_j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            if (run_i, run_j) != (i, j) {
+                runs.push((run_i..i, run_j..j));
+            }
+            i += 1;
+            j += 1;
+            (run_i, run_j) = (i, j);
+        } else if j < new.len()
+            && (i == old.len() || lcs[i * width + j + 1] >= lcs[(i + 1) * width + j])
+        {
+            j += 1;
+        } else {
+            i += 1;
+        }
+    }
+    if (run_i, run_j) != (i, j) {
+        runs.push((run_i..i, run_j..j));
+    }
+    runs
+}
+
+/// Narrows a changed run to the characters that differ, or returns None if
+/// the texts are equal
+fn trim_hunk(start: usize, end: usize, old: &str, new: &str) -> Option<LineHunk> {
+    let old_chars: Vec<char> = old.chars().collect();
+    let new_chars: Vec<char> = new.chars().collect();
+    let prefix = old_chars
+        .iter()
+        .zip(&new_chars)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_chars[prefix..]
+        .iter()
+        .rev()
+        .zip(new_chars[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if prefix + suffix == old_chars.len() && old_chars.len() == new_chars.len() {
+        return None;
+    }
+    Some(LineHunk {
+        start: start + prefix,
+        end: end - suffix,
+        text: new_chars[prefix..new_chars.len() - suffix].iter().collect(),
+    })
+}
+
+impl Document {
+    /// Returns true if every line the range touches is in a code block
+    pub(crate) fn is_code_block_range(&self, range: Range) -> bool {
+        let start = range.start_offset();
+        let end = range.end_offset();
+        let mut line_start = self.line_start_before(start);
+        loop {
+            if !self
+                .get_block_type_at(Position::new(line_start))
+                .is_code_block()
+            {
+                return false;
+            }
+            let next = self.line_end_from(line_start) + 1;
+            if next >= end || next > self.get_length() {
+                return true;
+            }
+            line_start = next;
+        }
+    }
+
+    /// Replaces the normalized `range` with `text` as one replace per
+    /// changed run of lines, recorded as a single undo step
+    ///
+    /// The caller has already checked that the document is editable and
+    /// that the range is in bounds.
+    pub(crate) fn replace_changed_lines(&mut self, range: Range, text: &str) -> CommandResult<()> {
+        let old = self.get_text_in_range(range);
+        let base = range.start_offset();
+
+        // Later hunks first, so the offsets of earlier ones stay valid
+        let mut applied: Vec<Box<dyn Command>> = Vec::new();
+        for hunk in line_hunks(&old, text).into_iter().rev() {
+            let hunk_range = Range::from_offsets(base + hunk.start, base + hunk.end);
+            let mut cmd = Box::new(ReplaceCommand::new(hunk_range, hunk.text));
+            if let Err(e) = cmd.execute(self) {
+                for mut cmd in applied.into_iter().rev() {
+                    let _ = cmd.undo(self);
+                }
+                return Err(e);
+            }
+            applied.push(cmd);
+        }
+
+        self.begin_batch();
+        for cmd in applied {
+            self.history.push_command(cmd);
+        }
+        self.end_batch();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::{BlockType, InlineFormat};
+
+    fn apply(old: &str, hunks: &[LineHunk]) -> String {
+        let mut chars: Vec<char> = old.chars().collect();
+        for hunk in hunks.iter().rev() {
+            chars.splice(hunk.start..hunk.end, hunk.text.chars());
+        }
+        chars.into_iter().collect()
+    }
+
+    #[test]
+    fn test_line_hunks_keep_only_changed_lines() {
+        let old = "fn main() {\n    let x = 1;\n    println!(\"{x}\");\n}\n";
+        let new = "fn main() {\n    let x = 2;\n    println!(\"{x}\");\n    done();\n}\n";
+        let hunks = line_hunks(old, new);
+        assert_eq!(
+            hunks,
+            [
+                LineHunk {
+                    start: 24,
+                    end: 25,
+                    text: "2".to_string(),
+                },
+                LineHunk {
+                    start: 48,
+                    end: 48,
+                    text: "    done();\n".to_string(),
+                },
+            ]
+        );
+        assert_eq!(apply(old, &hunks), new);
+        assert!(line_hunks(old, old).is_empty());
+    }
+
+    #[test]
+    fn test_line_hunks_handle_removed_and_unterminated_lines() {
+        for (old, new) in [
+            ("a\nb\nc", "a\nc"),
+            ("a\nb", "a\nb\n"),
+            ("", "x\ny"),
+            ("x\ny", ""),
+            ("a\nb\na\nb", "b\na\nb\na"),
+        ] {
+            assert_eq!(apply(old, &line_hunks(old, new)), new, "{old:?} -> {new:?}");
+        }
+    }
+
+    #[test]
+    fn test_replace_in_code_block_keeps_unchanged_lines() {
+        let code = "let a = 1;\nlet b = 2;\nlet c = 3;";
+        let mut doc = Document::from_text(&format!("Intro\n{code}"));
+        doc.set_block_type_lines(Range::from_offsets(6, 38), BlockType::code_block())
+            .unwrap();
+        doc.apply_format(Range::from_offsets(6, 9), InlineFormat::Bold);
+        assert!(doc.is_code_block_range(Range::from_offsets(6, 38)));
+        assert!(!doc.is_code_block_range(Range::from_offsets(4, 38)));
+
+        let edited = "let a = 1;\nlet b = 20;\nlet c = 3;";
+        doc.replace_range(Range::from_offsets(6, 38), edited)
+            .unwrap();
+        assert_eq!(doc.get_content(), format!("Intro\n{edited}"));
+        // Only the changed digit was replaced, so the format survives
+        assert!(
+            doc.get_formats_at(Position::new(6))
+                .contains(&InlineFormat::Bold)
+        );
+        assert_eq!(doc.undo_label(), Some("replacement"));
+
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), format!("Intro\n{code}"));
+        doc.redo().unwrap();
+        assert_eq!(doc.get_content(), format!("Intro\n{edited}"));
+
+        let version = doc.version();
+        doc.replace_range(Range::from_offsets(6, 39), edited)
+            .unwrap();
+        assert_eq!(doc.version(), version);
+    }
+}
@@ -10,6 +10,7 @@
 //! - Manage command history for undo/redo functionality
 //! - Provide clipboard operations with format preservation
 //! - Implement search and replace functionality
+//! - Tidy documents before publishing (trailing whitespace, empty paragraphs)
 //!
 //! # Key Types
 //!
@@ -17,17 +18,46 @@
 //! - `CommandHistory`: Manages undo/redo stacks
 //! - `InsertCommand`, `DeleteCommand`, `ReplaceCommand`: Text operations
 //! - `ApplyFormatCommand`, `RemoveFormatCommand`: Format operations
+//! - `InsertImageCommand`, `UpdateImageCommand`: Inline image operations
+//! - `AddAnnotationCommand`, `RemoveAnnotationCommand`: Annotation operations
+//! - `RenumberListsCommand`: Normalizes where numbered lists restart their count
+//! - `SetMetadataCommand`: Document metadata changes
+//! - `CompositeCommand`: Groups several commands into one undo step
+//! - `Replica`: Replicated document state for collaborative editing
+//! - `Operation`: Text operation transformed against pending local edits
 //! - `ClipboardContent`: Represents clipboard data with formatting
-
+//! - `FormattedSegment`: Text inserted with its formats in one step
+//! - `CleanupOptions`: Selects what `Document::cleanup` tidies up
+//! - `MigrationReport`: What `Document::migrate_formats` changed
+//! - `Autocorrect`: Text replacement rules applied as text is typed
+//! - `InputRules`: Markdown-style shortcuts applied as text is typed
+//! - `EditOp`: Randomly generated edit for fuzzing (`fuzzing` feature)
+
+pub mod auto_link;
+pub mod autocorrect;
+pub mod cleanup;
 pub mod clipboard;
+pub mod crdt;
+pub mod format_migration;
+pub mod formatted_text;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
 pub mod history;
+pub mod input_rules;
+mod line_diff;
+pub mod ot;
 pub mod search;
 
-use crate::document::{Document, Position, Range};
+use crate::document::{Annotation, Document, EMBED_CHAR, Image, Metadata, Position, Range};
+use crate::formatting::block::{MAX_BLOCK_INDENT, MAX_LIST_INDENT};
+use crate::formatting::{BlockType, FormatStorage, ListStyle, TextDirection};
 
 // Re-export commonly used types
 pub use clipboard::{ClipboardContent, SerializableFormatRun};
+pub use crdt::{CrdtError, Replica};
+pub use formatted_text::FormattedSegment;
 pub use history::CommandHistory;
+pub use ot::{Operation, OtError};
 
 /// Result type for command operations
 pub type CommandResult<T> = Result<T, CommandError>;
@@ -105,6 +135,33 @@ pub enum CommandError {
     /// - `limit`: The maximum number of commands allowed in history
     #[error("Command history limit reached: {limit} commands")]
     HistoryLimitReached { limit: usize },
+
+    /// Document is read-only
+    ///
+    /// Occurs when a local edit is attempted on a read-only document.
+    #[error("Document is read-only")]
+    ReadOnly,
+
+    /// Maximum document length exceeded
+    ///
+    /// Occurs when an edit would make the document longer than the
+    /// configured maximum length.
+    ///
+    /// # Context
+    /// - `length`: The length the document would have after the edit
+    /// - `limit`: The configured maximum length
+    #[error("Document length {length} would exceed the maximum of {limit} characters")]
+    MaxLengthExceeded { length: usize, limit: usize },
+
+    /// Unknown history node
+    ///
+    /// Occurs when moving to an undo history state that does not exist,
+    /// for example one dropped by the history limit.
+    ///
+    /// # Context
+    /// - `id`: The requested history node id
+    #[error("History node {id} does not exist")]
+    UnknownHistoryNode { id: usize },
 }
 
 impl CommandError {
@@ -137,6 +194,16 @@ impl CommandError {
     pub fn history_limit_reached(limit: usize) -> Self {
         Self::HistoryLimitReached { limit }
     }
+
+    /// Creates a max length exceeded error
+    pub fn max_length_exceeded(length: usize, limit: usize) -> Self {
+        Self::MaxLengthExceeded { length, limit }
+    }
+
+    /// Creates an unknown history node error
+    pub fn unknown_history_node(id: usize) -> Self {
+        Self::UnknownHistoryNode { id }
+    }
 }
 
 /// Trait for commands that can be executed and undone
@@ -149,6 +216,145 @@ pub trait Command: Send + Sync {
 
     /// Get a description of the command
     fn description(&self) -> String;
+
+    /// Get a short user-facing name for the change, such as "typing" or
+    /// "formatting", for labels like "Undo typing"
+    fn label(&self) -> &'static str {
+        "edit"
+    }
+
+    /// Returns the position and text if the command only inserts text
+    fn as_insertion(&self) -> Option<(Position, &str)> {
+        None
+    }
+
+    /// Folds `next`, executed right after this command, into it so both
+    /// are undone together
+    ///
+    /// # Returns
+    /// false if the two commands cannot be combined
+    fn merge(&mut self, _next: &dyn Command) -> bool {
+        false
+    }
+
+    /// Folds `next` into this command when history is compacted
+    ///
+    /// Unlike `merge`, this is not limited to live typing, so a command can
+    /// take in any neighbour it can represent together with itself.
+    ///
+    /// # Returns
+    /// false if the two commands cannot be combined
+    fn absorb(&mut self, next: &dyn Command) -> bool {
+        self.merge(next)
+    }
+
+    /// Takes out the commands of a group, so compaction can regroup them
+    ///
+    /// # Returns
+    /// None if the command is not a group
+    fn take_commands(&mut self) -> Option<Vec<Box<dyn Command>>> {
+        None
+    }
+
+    /// Moves the command past text edits made after it without going
+    /// through the history, such as remote edits
+    ///
+    /// `ops` apply in order to the document this command left. The command
+    /// is adjusted to apply to the edited document, and the edits are
+    /// returned as they would apply before the command. The command is
+    /// dropped from the history when this returns None, so it may be left
+    /// half adjusted.
+    ///
+    /// # Returns
+    /// None if the edits touch text the command changed, or the command
+    /// cannot follow text edits
+    fn rebase(&mut self, _ops: &[Operation]) -> Option<Vec<Operation>> {
+        None
+    }
+}
+
+/// Puts back the format runs saved from `range` after its text has been
+/// reinserted
+///
+/// Reinserted text picks up the formats of the run it lands in, so those
+/// are cleared before the saved runs are applied again.
+fn restore_runs(doc: &mut Document, range: Range, runs: &[crate::formatting::FormatRun]) {
+    for (segment, formats) in doc.formats().styled_runs(range) {
+        for format in &formats {
+            doc.formats_mut().remove_format(segment, format);
+        }
+    }
+    for run in runs {
+        for format in &run.formats {
+            doc.formats_mut().apply_format(run.range, format.clone());
+        }
+    }
+}
+
+/// Returns the format runs overlapping `range`, cut to it, for undo
+///
+/// Formats outside the range are left alone by the commands that save
+/// these, so restoring only the part inside keeps later changes to the
+/// rest, such as remote ones.
+fn runs_in(doc: &Document, range: Range) -> Vec<crate::formatting::FormatRun> {
+    let (start, end) = (range.start_offset(), range.end_offset());
+    doc.formats()
+        .get_runs()
+        .iter()
+        .filter(|run| run.range.overlaps(&range))
+        .map(|run| {
+            let mut run = run.clone();
+            run.range = Range::from_offsets(
+                run.range.start_offset().max(start),
+                run.range.end_offset().min(end),
+            );
+            run
+        })
+        .collect()
+}
+
+/// Saves the blocks of the lines that replacing `range` can change, for
+/// undo
+///
+/// Only removing or inserting a newline changes blocks, and then only on
+/// the line `range` starts on and the lines starting inside it.
+fn save_line_blocks(
+    doc: &Document,
+    range: Range,
+    removed: &str,
+    inserted: &str,
+) -> Vec<crate::formatting::storage::BlockInfo> {
+    if !removed.contains('\n') && !inserted.contains('\n') {
+        return Vec::new();
+    }
+    let start = range.start_offset();
+    let mut line_starts = vec![doc.line_start_before(start)];
+    line_starts.extend(
+        removed
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| *c == '\n')
+            .map(|(i, _)| start + i + 1),
+    );
+    doc.formats().line_blocks(&line_starts)
+}
+
+/// Gives the lines saved by `save_line_blocks` their blocks again, once
+/// their text is back
+fn restore_line_blocks(doc: &mut Document, blocks: &[crate::formatting::storage::BlockInfo]) {
+    let Some(last) = blocks.last() else {
+        return;
+    };
+    let line_end = doc.line_end_from(last.start_offset);
+    let next_line = (line_end < doc.get_length()).then_some(line_end + 1);
+    doc.formats_mut().restore_line_blocks(blocks, next_line);
+}
+
+/// Maps saved format runs through text edits, for `Command::rebase`
+fn rebase_runs(runs: &mut Option<Vec<crate::formatting::FormatRun>>, ops: &[Operation]) {
+    for run in runs.iter_mut().flatten() {
+        run.range = ot::map_range(run.range, ops);
+    }
 }
 
 /// Command that inserts text at a position
@@ -204,6 +410,54 @@ impl Command for InsertCommand {
             self.position.offset()
         )
     }
+
+    fn label(&self) -> &'static str {
+        "typing"
+    }
+
+    fn as_insertion(&self) -> Option<(Position, &str)> {
+        Some((self.position, &self.text))
+    }
+
+    /// Merges a single character typed right after the inserted text
+    ///
+    /// Whitespace after a word starts a new undo step, so undo removes
+    /// typed text a word at a time.
+    fn merge(&mut self, next: &dyn Command) -> bool {
+        let Some((position, text)) = next.as_insertion() else {
+            return false;
+        };
+        let mut chars = text.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            return false;
+        };
+        let end = self.position.offset() + self.text.chars().count();
+        let word_ends = c.is_whitespace() && !self.text.ends_with(char::is_whitespace);
+        if position.offset() != end || word_ends {
+            return false;
+        }
+        self.text.push(c);
+        true
+    }
+
+    /// Absorbs any insertion made right after the inserted text
+    fn absorb(&mut self, next: &dyn Command) -> bool {
+        let Some((position, text)) = next.as_insertion() else {
+            return false;
+        };
+        if position.offset() != self.position.offset() + self.text.chars().count() {
+            return false;
+        }
+        self.text.push_str(text);
+        true
+    }
+
+    fn rebase(&mut self, ops: &[Operation]) -> Option<Vec<Operation>> {
+        let inserted = self.text.chars().count();
+        let (start, moved) = ot::rebase_past_edit(self.position.offset(), 0, inserted, ops)?;
+        self.position = Position::new(start);
+        Some(moved)
+    }
 }
 
 /// Command that deletes text in a range
@@ -214,6 +468,12 @@ pub struct DeleteCommand {
     deleted_text: Option<String>,
     /// Stores the deleted format runs for undo
     deleted_formats: Option<Vec<crate::formatting::FormatRun>>,
+    /// Stores the blocks of the lines the delete joins, for undo
+    previous_blocks: Vec<crate::formatting::storage::BlockInfo>,
+    /// Stores the deleted images for undo
+    deleted_images: Vec<(usize, Image)>,
+    /// Stores the annotations overlapping the deleted range for undo
+    deleted_annotations: Vec<(Range, Annotation)>,
 }
 
 impl DeleteCommand {
@@ -223,6 +483,9 @@ impl DeleteCommand {
             range,
             deleted_text: None,
             deleted_formats: None,
+            previous_blocks: Vec::new(),
+            deleted_images: Vec::new(),
+            deleted_annotations: Vec::new(),
         }
     }
 }
@@ -241,15 +504,16 @@ impl Command for DeleteCommand {
         // Store the text being deleted for undo
         self.deleted_text = Some(doc.get_text_in_range(normalized));
 
-        // Store the format runs that overlap with the deleted range
-        let overlapping_runs: Vec<_> = doc
-            .formats()
-            .get_runs()
-            .iter()
-            .filter(|run| run.range.overlaps(&normalized))
-            .cloned()
-            .collect();
-        self.deleted_formats = Some(overlapping_runs);
+        // Store the formats and line blocks the delete can change
+        self.deleted_formats = Some(runs_in(doc, normalized));
+        self.previous_blocks = save_line_blocks(
+            doc,
+            normalized,
+            self.deleted_text.as_deref().unwrap_or_default(),
+            "",
+        );
+        self.deleted_images = doc.embeds().images_in(normalized);
+        self.deleted_annotations = doc.annotations().annotations_in(normalized);
 
         doc.delete_range_direct(self.range);
         Ok(())
@@ -260,13 +524,17 @@ impl Command for DeleteCommand {
             let normalized = self.range.normalize();
             doc.insert_text_direct(normalized.start, text);
 
-            // Restore the deleted format runs
+            // Restore the deleted format runs and the lines they started
             if let Some(ref format_runs) = self.deleted_formats {
-                for run in format_runs {
-                    for format in &run.formats {
-                        doc.formats_mut().apply_format(run.range, format.clone());
-                    }
-                }
+                restore_runs(doc, normalized, format_runs);
+            }
+            restore_line_blocks(doc, &self.previous_blocks);
+
+            for (offset, image) in &self.deleted_images {
+                doc.embeds_mut().insert(*offset, image.clone());
+            }
+            for (range, annotation) in &self.deleted_annotations {
+                doc.annotations_mut().insert(*range, annotation.clone());
             }
 
             Ok(())
@@ -282,6 +550,28 @@ impl Command for DeleteCommand {
             self.range.end.offset()
         )
     }
+
+    fn label(&self) -> &'static str {
+        "deletion"
+    }
+
+    fn rebase(&mut self, ops: &[Operation]) -> Option<Vec<Operation>> {
+        let range = self.range.normalize();
+        let removed = range.len();
+        let (start, moved) = ot::rebase_past_edit(range.start.offset(), removed, 0, ops)?;
+        self.range = Range::from_offsets(start, start + removed);
+        rebase_runs(&mut self.deleted_formats, &moved);
+        for block in &mut self.previous_blocks {
+            block.start_offset = ot::map_offset(block.start_offset, &moved, false);
+        }
+        for (offset, _) in &mut self.deleted_images {
+            *offset = ot::map_offset(*offset, &moved, true);
+        }
+        for (range, _) in &mut self.deleted_annotations {
+            *range = ot::map_range(*range, &moved);
+        }
+        Some(moved)
+    }
 }
 
 /// Command that replaces text in a range with new text
@@ -293,6 +583,12 @@ pub struct ReplaceCommand {
     old_text: Option<String>,
     /// Stores the old format runs for undo
     old_formats: Option<Vec<crate::formatting::FormatRun>>,
+    /// Stores the blocks of the lines the replace joins or splits, for undo
+    previous_blocks: Vec<crate::formatting::storage::BlockInfo>,
+    /// Stores the replaced images for undo
+    old_images: Vec<(usize, Image)>,
+    /// Stores the annotations overlapping the replaced range for undo
+    old_annotations: Vec<(Range, Annotation)>,
 }
 
 impl ReplaceCommand {
@@ -303,6 +599,9 @@ impl ReplaceCommand {
             new_text,
             old_text: None,
             old_formats: None,
+            previous_blocks: Vec::new(),
+            old_images: Vec::new(),
+            old_annotations: Vec::new(),
         }
     }
 }
@@ -321,15 +620,16 @@ impl Command for ReplaceCommand {
         // Store the old text for undo
         self.old_text = Some(doc.get_text_in_range(normalized));
 
-        // Store the format runs that overlap with the replaced range
-        let overlapping_runs: Vec<_> = doc
-            .formats()
-            .get_runs()
-            .iter()
-            .filter(|run| run.range.overlaps(&normalized))
-            .cloned()
-            .collect();
-        self.old_formats = Some(overlapping_runs);
+        // Store the formats and line blocks the replace can change
+        self.old_formats = Some(runs_in(doc, normalized));
+        self.previous_blocks = save_line_blocks(
+            doc,
+            normalized,
+            self.old_text.as_deref().unwrap_or_default(),
+            &self.new_text,
+        );
+        self.old_images = doc.embeds().images_in(normalized);
+        self.old_annotations = doc.annotations().annotations_in(normalized);
 
         doc.replace_range_direct(self.range, &self.new_text);
         Ok(())
@@ -342,13 +642,17 @@ impl Command for ReplaceCommand {
             let current_range = Range::new(normalized.start, new_end);
             doc.replace_range_direct(current_range, old_text);
 
-            // Restore the old format runs
+            // Restore the old format runs and the lines they started
             if let Some(ref format_runs) = self.old_formats {
-                for run in format_runs {
-                    for format in &run.formats {
-                        doc.formats_mut().apply_format(run.range, format.clone());
-                    }
-                }
+                restore_runs(doc, normalized, format_runs);
+            }
+            restore_line_blocks(doc, &self.previous_blocks);
+
+            for (offset, image) in &self.old_images {
+                doc.embeds_mut().insert(*offset, image.clone());
+            }
+            for (range, annotation) in &self.old_annotations {
+                doc.annotations_mut().insert(*range, annotation.clone());
             }
 
             Ok(())
@@ -365,6 +669,29 @@ impl Command for ReplaceCommand {
             self.new_text
         )
     }
+
+    fn label(&self) -> &'static str {
+        "replacement"
+    }
+
+    fn rebase(&mut self, ops: &[Operation]) -> Option<Vec<Operation>> {
+        let range = self.range.normalize();
+        let removed = range.len();
+        let inserted = self.new_text.chars().count();
+        let (start, moved) = ot::rebase_past_edit(range.start.offset(), removed, inserted, ops)?;
+        self.range = Range::from_offsets(start, start + removed);
+        rebase_runs(&mut self.old_formats, &moved);
+        for block in &mut self.previous_blocks {
+            block.start_offset = ot::map_offset(block.start_offset, &moved, false);
+        }
+        for (offset, _) in &mut self.old_images {
+            *offset = ot::map_offset(*offset, &moved, true);
+        }
+        for (range, _) in &mut self.old_annotations {
+            *range = ot::map_range(*range, &moved);
+        }
+        Some(moved)
+    }
 }
 
 /// Command that applies formatting to a range
@@ -398,15 +725,8 @@ impl Command for ApplyFormatCommand {
             return Err(CommandError::invalid_range(start, end, length));
         }
 
-        // Store the current format runs that overlap with the range
-        let overlapping_runs: Vec<_> = doc
-            .formats()
-            .get_runs()
-            .iter()
-            .filter(|run| run.range.overlaps(&normalized))
-            .cloned()
-            .collect();
-        self.previous_state = Some(overlapping_runs);
+        // Store the current format runs within the range
+        self.previous_state = Some(runs_in(doc, normalized));
 
         // Apply the format
         doc.formats_mut()
@@ -446,6 +766,19 @@ impl Command for ApplyFormatCommand {
             self.range.end.offset()
         )
     }
+
+    fn label(&self) -> &'static str {
+        "formatting"
+    }
+
+    fn rebase(&mut self, ops: &[Operation]) -> Option<Vec<Operation>> {
+        let range = self.range.normalize();
+        let length = range.len();
+        let (start, moved) = ot::rebase_past_edit(range.start.offset(), length, length, ops)?;
+        self.range = Range::from_offsets(start, start + length);
+        rebase_runs(&mut self.previous_state, &moved);
+        Some(moved)
+    }
 }
 
 /// Command that removes formatting from a range
@@ -479,15 +812,8 @@ impl Command for RemoveFormatCommand {
             return Err(CommandError::invalid_range(start, end, length));
         }
 
-        // Store the current format runs that overlap with the range
-        let overlapping_runs: Vec<_> = doc
-            .formats()
-            .get_runs()
-            .iter()
-            .filter(|run| run.range.overlaps(&normalized))
-            .cloned()
-            .collect();
-        self.previous_state = Some(overlapping_runs);
+        // Store the current format runs within the range
+        self.previous_state = Some(runs_in(doc, normalized));
 
         // Remove the format
         doc.formats_mut().remove_format(self.range, &self.format);
@@ -523,6 +849,19 @@ impl Command for RemoveFormatCommand {
             self.range.end.offset()
         )
     }
+
+    fn label(&self) -> &'static str {
+        "formatting"
+    }
+
+    fn rebase(&mut self, ops: &[Operation]) -> Option<Vec<Operation>> {
+        let range = self.range.normalize();
+        let length = range.len();
+        let (start, moved) = ot::rebase_past_edit(range.start.offset(), length, length, ops)?;
+        self.range = Range::from_offsets(start, start + length);
+        rebase_runs(&mut self.previous_state, &moved);
+        Some(moved)
+    }
 }
 
 /// Command that sets block type for a range
@@ -531,7 +870,7 @@ pub struct SetBlockTypeCommand {
     range: Range,
     block_type: crate::formatting::BlockType,
     /// Snapshot of previous blocks for undo
-    previous_blocks: Option<Vec<crate::formatting::storage::BlockInfo>>, 
+    previous_blocks: Option<Vec<crate::formatting::storage::BlockInfo>>,
 }
 
 impl SetBlockTypeCommand {
@@ -560,7 +899,8 @@ impl Command for SetBlockTypeCommand {
         self.previous_blocks = Some(doc.formats().get_blocks().to_vec());
 
         // Apply new block type
-        doc.formats_mut().set_block_type(self.range, self.block_type.clone());
+        doc.formats_mut()
+            .set_block_type(self.range, self.block_type.clone());
         doc.mark_dirty(self.range);
         doc.increment_version();
         Ok(())
@@ -587,103 +927,1137 @@ impl Command for SetBlockTypeCommand {
             self.range.end.offset()
         )
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    #[test]
-    fn test_insert_command() {
-        let mut doc = Document::new();
-        let mut cmd = InsertCommand::new(Position::new(0), "Hello".to_string());
+    fn label(&self) -> &'static str {
+        "formatting"
+    }
+}
 
-        cmd.execute(&mut doc).unwrap();
-        assert_eq!(doc.get_content(), "Hello");
+/// Command that sets the block type of each line touched by a range, giving
+/// every line its own block
+#[derive(Debug, Clone)]
+pub struct SetLineBlockTypesCommand {
+    range: Range,
+    block_type: crate::formatting::BlockType,
+    /// Snapshot of previous blocks for undo
+    previous_blocks: Option<Vec<crate::formatting::storage::BlockInfo>>,
+}
 
-        cmd.undo(&mut doc).unwrap();
-        assert_eq!(doc.get_content(), "");
+impl SetLineBlockTypesCommand {
+    /// Creates a new SetLineBlockTypesCommand
+    pub fn new(range: Range, block_type: crate::formatting::BlockType) -> Self {
+        Self {
+            range,
+            block_type,
+            previous_blocks: None,
+        }
     }
+}
 
-    #[test]
-    fn test_insert_command_in_middle() {
-        let mut doc = Document::from_text("Hello World");
-        let mut cmd = InsertCommand::new(Position::new(5), " Beautiful".to_string());
+impl Command for SetLineBlockTypesCommand {
+    fn execute(&mut self, doc: &mut Document) -> CommandResult<()> {
+        let normalized = self.range.normalize();
+        let start = normalized.start.offset();
+        let end = normalized.end.offset();
+        let length = doc.get_length();
 
-        cmd.execute(&mut doc).unwrap();
-        assert_eq!(doc.get_content(), "Hello Beautiful World");
+        if end > length {
+            return Err(CommandError::invalid_range(start, end, length));
+        }
 
-        cmd.undo(&mut doc).unwrap();
-        assert_eq!(doc.get_content(), "Hello World");
+        let mut line_starts = Vec::new();
+        let mut line_start = doc.line_start_before(start);
+        let next_line = loop {
+            line_starts.push(line_start);
+            let line_end = doc.line_end_from(line_start);
+            let next_line = (line_end < length).then_some(line_end + 1);
+            match next_line {
+                Some(next) if next < end => line_start = next,
+                _ => break next_line,
+            }
+        };
+
+        self.previous_blocks = Some(doc.formats().get_blocks().to_vec());
+        doc.formats_mut()
+            .set_line_block_types(&line_starts, next_line, self.block_type.clone());
+        let dirty_start = line_starts[0];
+        doc.mark_dirty(Range::from_offsets(dirty_start, doc.line_end_from(end)));
+        doc.increment_version();
+        Ok(())
     }
 
-    #[test]
-    fn test_delete_command() {
-        let mut doc = Document::from_text("Hello World");
-        let mut cmd = DeleteCommand::new(Range::new(Position::new(5), Position::new(11)));
+    fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
+        if let Some(ref snapshot) = self.previous_blocks {
+            doc.formats_mut().set_blocks(snapshot.clone());
+            let normalized = self.range.normalize();
+            doc.mark_dirty(normalized);
+            doc.increment_version();
+            Ok(())
+        } else {
+            Err(CommandError::command_not_executed(
+                "SetLineBlockTypesCommand",
+            ))
+        }
+    }
 
-        cmd.execute(&mut doc).unwrap();
-        assert_eq!(doc.get_content(), "Hello");
+    fn description(&self) -> String {
+        format!(
+            "Set block type {:?} for lines of range {}..{}",
+            self.block_type,
+            self.range.start.offset(),
+            self.range.end.offset()
+        )
+    }
 
-        cmd.undo(&mut doc).unwrap();
-        assert_eq!(doc.get_content(), "Hello World");
+    fn label(&self) -> &'static str {
+        "formatting"
     }
+}
 
-    #[test]
-    fn test_replace_command() {
-        let mut doc = Document::from_text("Hello World");
-        let mut cmd = ReplaceCommand::new(
-            Range::new(Position::new(6), Position::new(11)),
-            "Rust".to_string(),
-        );
+/// Command that moves the list items on a range of lines in or out by one level
+#[derive(Debug, Clone)]
+pub struct ChangeListIndentCommand {
+    range: Range,
+    delta: i8,
+    /// Snapshot of previous blocks for undo, taken only if a line changed
+    previous_blocks: Option<Vec<crate::formatting::storage::BlockInfo>>,
+}
 
-        cmd.execute(&mut doc).unwrap();
-        assert_eq!(doc.get_content(), "Hello Rust");
+impl ChangeListIndentCommand {
+    /// Creates a new ChangeListIndentCommand
+    /// A positive `delta` indents, a negative one outdents
+    pub fn new(range: Range, delta: i8) -> Self {
+        Self {
+            range,
+            delta,
+            previous_blocks: None,
+        }
+    }
 
-        cmd.undo(&mut doc).unwrap();
-        assert_eq!(doc.get_content(), "Hello World");
+    /// Returns true if the last execution changed any line
+    pub fn changed(&self) -> bool {
+        self.previous_blocks.is_some()
     }
+}
 
-    #[test]
-    fn test_command_description() {
-        let insert_cmd = InsertCommand::new(Position::new(0), "test".to_string());
-        assert!(insert_cmd.description().contains("Insert"));
+impl Command for ChangeListIndentCommand {
+    fn execute(&mut self, doc: &mut Document) -> CommandResult<()> {
+        let normalized = self.range.normalize();
+        let start = normalized.start.offset();
+        let end = normalized.end.offset();
+        let length = doc.get_length();
 
-        let delete_cmd = DeleteCommand::new(Range::new(Position::new(0), Position::new(5)));
-        assert!(delete_cmd.description().contains("Delete"));
+        if end > length {
+            return Err(CommandError::invalid_range(start, end, length));
+        }
 
-        let replace_cmd = ReplaceCommand::new(
-            Range::new(Position::new(0), Position::new(5)),
-            "new".to_string(),
-        );
-        assert!(replace_cmd.description().contains("Replace"));
-    }
+        let snapshot = doc.formats().get_blocks().to_vec();
+        let mut line_start = doc.line_start_before(start);
+        let mut changed = false;
+        loop {
+            let line_end = doc.line_end_from(line_start);
+            let next_line = (line_end < length).then_some(line_end + 1);
+            let position = Position::new(line_start);
+
+            if matches!(
+                doc.get_block_type_at(position),
+                BlockType::BulletList | BlockType::NumberedList
+            ) {
+                let indent = doc.get_list_indent_at(position);
+                let target = indent
+                    .saturating_add_signed(self.delta)
+                    .min(MAX_LIST_INDENT);
+                if target != indent {
+                    doc.formats_mut().set_indent(line_start, next_line, target);
+                    changed = true;
+                }
+            }
 
-    #[test]
-    fn test_invalid_position() {
-        let mut doc = Document::from_text("Hello");
-        let mut cmd = InsertCommand::new(Position::new(100), "test".to_string());
+            match next_line {
+                Some(next) if next < end => line_start = next,
+                _ => break,
+            }
+        }
 
-        let result = cmd.execute(&mut doc);
-        assert!(result.is_err());
+        self.previous_blocks = None;
+        if changed {
+            self.previous_blocks = Some(snapshot);
+            let dirty_start = doc.line_start_before(start);
+            doc.mark_dirty(Range::from_offsets(dirty_start, doc.line_end_from(end)));
+            doc.increment_version();
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_undo_without_execute() {
-        let mut doc = Document::new();
-        let mut cmd = InsertCommand::new(Position::new(0), "test".to_string());
+    fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
+        if let Some(ref snapshot) = self.previous_blocks {
+            doc.formats_mut().set_blocks(snapshot.clone());
+            let normalized = self.range.normalize();
+            doc.mark_dirty(normalized);
+            doc.increment_version();
+            Ok(())
+        } else {
+            Err(CommandError::command_not_executed(
+                "ChangeListIndentCommand",
+            ))
+        }
+    }
 
-        let result = cmd.undo(&mut doc);
-        assert!(result.is_err());
+    fn description(&self) -> String {
+        format!(
+            "Change list indent by {} for range {}..{}",
+            self.delta,
+            self.range.start.offset(),
+            self.range.end.offset()
+        )
     }
 
-    #[test]
-    fn test_apply_format_command() {
-        use crate::formatting::InlineFormat;
+    fn label(&self) -> &'static str {
+        "indentation"
+    }
+}
 
-        let mut doc = Document::from_text("Hello World");
-        let range = Range::from_offsets(0, 5);
-        let mut cmd = ApplyFormatCommand::new(range, InlineFormat::Bold);
+/// Command that sets the marker style of the list items on a range of lines
+#[derive(Debug, Clone)]
+pub struct SetListStyleCommand {
+    range: Range,
+    style: Option<ListStyle>,
+    /// Snapshot of previous blocks for undo, taken only if a line changed
+    previous_blocks: Option<Vec<crate::formatting::storage::BlockInfo>>,
+}
+
+impl SetListStyleCommand {
+    /// Creates a new SetListStyleCommand
+    /// A `style` of None restores the default bullet or decimal marker
+    pub fn new(range: Range, style: Option<ListStyle>) -> Self {
+        Self {
+            range,
+            style,
+            previous_blocks: None,
+        }
+    }
+
+    /// Returns true if the last execution changed any line
+    pub fn changed(&self) -> bool {
+        self.previous_blocks.is_some()
+    }
+}
+
+impl Command for SetListStyleCommand {
+    fn execute(&mut self, doc: &mut Document) -> CommandResult<()> {
+        let normalized = self.range.normalize();
+        let start = normalized.start.offset();
+        let end = normalized.end.offset();
+        let length = doc.get_length();
+
+        if end > length {
+            return Err(CommandError::invalid_range(start, end, length));
+        }
+
+        let snapshot = doc.formats().get_blocks().to_vec();
+        let mut line_start = doc.line_start_before(start);
+        let mut changed = false;
+        loop {
+            let line_end = doc.line_end_from(line_start);
+            let next_line = (line_end < length).then_some(line_end + 1);
+            let position = Position::new(line_start);
+
+            if matches!(
+                doc.get_block_type_at(position),
+                BlockType::BulletList | BlockType::NumberedList
+            ) && doc.get_list_style_at(position) != self.style
+            {
+                doc.formats_mut()
+                    .set_list_style(line_start, next_line, self.style);
+                changed = true;
+            }
+
+            match next_line {
+                Some(next) if next < end => line_start = next,
+                _ => break,
+            }
+        }
+
+        self.previous_blocks = None;
+        if changed {
+            self.previous_blocks = Some(snapshot);
+            let dirty_start = doc.line_start_before(start);
+            doc.mark_dirty(Range::from_offsets(dirty_start, doc.line_end_from(end)));
+            doc.increment_version();
+        }
+        Ok(())
+    }
+
+    fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
+        if let Some(ref snapshot) = self.previous_blocks {
+            doc.formats_mut().set_blocks(snapshot.clone());
+            let normalized = self.range.normalize();
+            doc.mark_dirty(normalized);
+            doc.increment_version();
+            Ok(())
+        } else {
+            Err(CommandError::command_not_executed("SetListStyleCommand"))
+        }
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Set list style {:?} for range {}..{}",
+            self.style,
+            self.range.start.offset(),
+            self.range.end.offset()
+        )
+    }
+
+    fn label(&self) -> &'static str {
+        "formatting"
+    }
+}
+
+/// Command that normalizes where numbered lists restart their count
+///
+/// Moving list items around can leave an item in the middle of a list
+/// restarting its count. Restart numbers are cleared from every item but
+/// the first of each list, and from first items restarting at 1, the
+/// default.
+#[derive(Debug, Clone, Default)]
+pub struct RenumberListsCommand {
+    /// Snapshot of previous blocks for undo, taken only if a line changed
+    previous_blocks: Option<Vec<crate::formatting::storage::BlockInfo>>,
+}
+
+impl RenumberListsCommand {
+    /// Creates a new RenumberListsCommand
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true if the last execution changed any line
+    pub fn changed(&self) -> bool {
+        self.previous_blocks.is_some()
+    }
+}
+
+impl Command for RenumberListsCommand {
+    fn execute(&mut self, doc: &mut Document) -> CommandResult<()> {
+        let snapshot = doc.formats().get_blocks().to_vec();
+        let changed = doc.normalize_list_starts();
+
+        self.previous_blocks = None;
+        if changed {
+            self.previous_blocks = Some(snapshot);
+            doc.mark_dirty(Range::from_offsets(0, doc.get_length()));
+            doc.increment_version();
+        }
+        Ok(())
+    }
+
+    fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
+        if let Some(ref snapshot) = self.previous_blocks {
+            doc.formats_mut().set_blocks(snapshot.clone());
+            doc.mark_dirty(Range::from_offsets(0, doc.get_length()));
+            doc.increment_version();
+            Ok(())
+        } else {
+            Err(CommandError::command_not_executed("RenumberListsCommand"))
+        }
+    }
+
+    fn description(&self) -> String {
+        "Renumber lists".to_string()
+    }
+
+    fn label(&self) -> &'static str {
+        "formatting"
+    }
+}
+
+/// Command that moves the paragraphs on a range of lines in or out by one
+/// indent step
+#[derive(Debug, Clone)]
+pub struct ChangeBlockIndentCommand {
+    range: Range,
+    delta: i8,
+    /// Snapshot of previous blocks for undo, taken only if a line changed
+    previous_blocks: Option<Vec<crate::formatting::storage::BlockInfo>>,
+}
+
+impl ChangeBlockIndentCommand {
+    /// Creates a new ChangeBlockIndentCommand
+    /// A positive `delta` indents, a negative one outdents
+    pub fn new(range: Range, delta: i8) -> Self {
+        Self {
+            range,
+            delta,
+            previous_blocks: None,
+        }
+    }
+
+    /// Returns true if the last execution changed any line
+    pub fn changed(&self) -> bool {
+        self.previous_blocks.is_some()
+    }
+}
+
+impl Command for ChangeBlockIndentCommand {
+    fn execute(&mut self, doc: &mut Document) -> CommandResult<()> {
+        let delta = self.delta;
+        self.previous_blocks =
+            update_paragraph_lines(doc, self.range, |formats, start, end, pos| {
+                let indent = formats.get_block_indent_at(pos);
+                let target = indent.saturating_add_signed(delta).min(MAX_BLOCK_INDENT);
+                if target == indent {
+                    return false;
+                }
+                formats.set_block_indent(start, end, target);
+                true
+            })?;
+        Ok(())
+    }
+
+    fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
+        if let Some(ref snapshot) = self.previous_blocks {
+            doc.formats_mut().set_blocks(snapshot.clone());
+            let normalized = self.range.normalize();
+            doc.mark_dirty(normalized);
+            doc.increment_version();
+            Ok(())
+        } else {
+            Err(CommandError::command_not_executed(
+                "ChangeBlockIndentCommand",
+            ))
+        }
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Change block indent by {} for range {}..{}",
+            self.delta,
+            self.range.start.offset(),
+            self.range.end.offset()
+        )
+    }
+
+    fn label(&self) -> &'static str {
+        "indentation"
+    }
+}
+
+/// Command that turns the first-line indent of the paragraphs on a range of
+/// lines on or off
+#[derive(Debug, Clone)]
+pub struct SetFirstLineIndentCommand {
+    range: Range,
+    indented: bool,
+    /// Snapshot of previous blocks for undo, taken only if a line changed
+    previous_blocks: Option<Vec<crate::formatting::storage::BlockInfo>>,
+}
+
+impl SetFirstLineIndentCommand {
+    /// Creates a new SetFirstLineIndentCommand
+    pub fn new(range: Range, indented: bool) -> Self {
+        Self {
+            range,
+            indented,
+            previous_blocks: None,
+        }
+    }
+
+    /// Returns true if the last execution changed any line
+    pub fn changed(&self) -> bool {
+        self.previous_blocks.is_some()
+    }
+}
+
+impl Command for SetFirstLineIndentCommand {
+    fn execute(&mut self, doc: &mut Document) -> CommandResult<()> {
+        let indented = self.indented;
+        self.previous_blocks =
+            update_paragraph_lines(doc, self.range, |formats, start, end, pos| {
+                if formats.has_first_line_indent_at(pos) == indented {
+                    return false;
+                }
+                formats.set_first_line_indent(start, end, indented);
+                true
+            })?;
+        Ok(())
+    }
+
+    fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
+        if let Some(ref snapshot) = self.previous_blocks {
+            doc.formats_mut().set_blocks(snapshot.clone());
+            let normalized = self.range.normalize();
+            doc.mark_dirty(normalized);
+            doc.increment_version();
+            Ok(())
+        } else {
+            Err(CommandError::command_not_executed(
+                "SetFirstLineIndentCommand",
+            ))
+        }
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Set first-line indent {} for range {}..{}",
+            self.indented,
+            self.range.start.offset(),
+            self.range.end.offset()
+        )
+    }
+
+    fn label(&self) -> &'static str {
+        "indentation"
+    }
+}
+
+/// Command that sets the base text direction of a range of lines
+#[derive(Debug, Clone)]
+pub struct SetTextDirectionCommand {
+    range: Range,
+    direction: Option<TextDirection>,
+    /// Snapshot of previous blocks for undo, taken only if a line changed
+    previous_blocks: Option<Vec<crate::formatting::storage::BlockInfo>>,
+}
+
+impl SetTextDirectionCommand {
+    /// Creates a new SetTextDirectionCommand
+    /// A `direction` of None detects the direction from the text
+    pub fn new(range: Range, direction: Option<TextDirection>) -> Self {
+        Self {
+            range,
+            direction,
+            previous_blocks: None,
+        }
+    }
+
+    /// Returns true if the last execution changed any line
+    pub fn changed(&self) -> bool {
+        self.previous_blocks.is_some()
+    }
+}
+
+impl Command for SetTextDirectionCommand {
+    fn execute(&mut self, doc: &mut Document) -> CommandResult<()> {
+        let direction = self.direction;
+        self.previous_blocks = update_lines(doc, self.range, |formats, start, end, pos| {
+            if formats.get_direction_at(pos) == direction {
+                return false;
+            }
+            formats.set_direction(start, end, direction);
+            true
+        })?;
+        Ok(())
+    }
+
+    fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
+        if let Some(ref snapshot) = self.previous_blocks {
+            doc.formats_mut().set_blocks(snapshot.clone());
+            let normalized = self.range.normalize();
+            doc.mark_dirty(normalized);
+            doc.increment_version();
+            Ok(())
+        } else {
+            Err(CommandError::command_not_executed(
+                "SetTextDirectionCommand",
+            ))
+        }
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Set text direction {:?} for range {}..{}",
+            self.direction,
+            self.range.start.offset(),
+            self.range.end.offset()
+        )
+    }
+
+    fn label(&self) -> &'static str {
+        "formatting"
+    }
+}
+
+/// Calls `update` with the bounds of each paragraph line touched by `range`
+///
+/// Works like `update_lines`, skipping lines that are not paragraphs.
+fn update_paragraph_lines(
+    doc: &mut Document,
+    range: Range,
+    mut update: impl FnMut(&mut FormatStorage, usize, Option<usize>, Position) -> bool,
+) -> CommandResult<Option<Vec<crate::formatting::storage::BlockInfo>>> {
+    update_lines(doc, range, |formats, start, end, pos| {
+        formats.get_block_type_at(pos) == BlockType::Paragraph && update(formats, start, end, pos)
+    })
+}
+
+/// Calls `update` with the bounds of each line touched by `range`
+///
+/// `update` receives the format storage, the line start, the start of the
+/// next line (None on the last line) and the line start position, and
+/// returns true if it changed the line. Returns the blocks from before the
+/// update if any line changed, after marking the lines dirty.
+fn update_lines(
+    doc: &mut Document,
+    range: Range,
+    mut update: impl FnMut(&mut FormatStorage, usize, Option<usize>, Position) -> bool,
+) -> CommandResult<Option<Vec<crate::formatting::storage::BlockInfo>>> {
+    let normalized = range.normalize();
+    let start = normalized.start.offset();
+    let end = normalized.end.offset();
+    let length = doc.get_length();
+
+    if end > length {
+        return Err(CommandError::invalid_range(start, end, length));
+    }
+
+    let snapshot = doc.formats().get_blocks().to_vec();
+    let mut line_start = doc.line_start_before(start);
+    let mut changed = false;
+    loop {
+        let line_end = doc.line_end_from(line_start);
+        let next_line = (line_end < length).then_some(line_end + 1);
+        let position = Position::new(line_start);
+
+        changed |= update(doc.formats_mut(), line_start, next_line, position);
+
+        match next_line {
+            Some(next) if next < end => line_start = next,
+            _ => break,
+        }
+    }
+
+    if !changed {
+        return Ok(None);
+    }
+    let dirty_start = doc.line_start_before(start);
+    doc.mark_dirty(Range::from_offsets(dirty_start, doc.line_end_from(end)));
+    doc.increment_version();
+    Ok(Some(snapshot))
+}
+
+/// Command that inserts an inline image at a position
+#[derive(Debug, Clone)]
+pub struct InsertImageCommand {
+    position: Position,
+    image: Image,
+    executed: bool,
+}
+
+impl InsertImageCommand {
+    /// Creates a new InsertImageCommand
+    pub fn new(position: Position, image: Image) -> Self {
+        Self {
+            position,
+            image,
+            executed: false,
+        }
+    }
+}
+
+impl Command for InsertImageCommand {
+    fn execute(&mut self, doc: &mut Document) -> CommandResult<()> {
+        let pos = self.position.offset();
+        let length = doc.get_length();
+
+        if pos > length {
+            return Err(CommandError::invalid_position(pos, length));
+        }
+        if !self.image.has_safe_src() {
+            return Err(CommandError::execution_failed(
+                "InsertImageCommand",
+                format!("unsafe image source '{}'", self.image.src),
+            ));
+        }
+
+        doc.insert_text_direct(self.position, &EMBED_CHAR.to_string());
+        // The direct insert is skipped when it would exceed the size limit
+        if doc.get_length() != length + 1 {
+            return Err(CommandError::execution_failed(
+                "InsertImageCommand",
+                "document size limit reached",
+            ));
+        }
+        doc.embeds_mut().insert(pos, self.image.clone());
+        self.executed = true;
+        Ok(())
+    }
+
+    fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
+        if !self.executed {
+            return Err(CommandError::command_not_executed("InsertImageCommand"));
+        }
+
+        let end_pos = Position::new(self.position.offset() + 1);
+        doc.delete_range_direct(Range::new(self.position, end_pos));
+        self.executed = false;
+        Ok(())
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Insert image '{}' at position {}",
+            self.image.src,
+            self.position.offset()
+        )
+    }
+
+    fn label(&self) -> &'static str {
+        "image"
+    }
+}
+
+/// Command that replaces the attributes of an existing inline image
+#[derive(Debug, Clone)]
+pub struct UpdateImageCommand {
+    position: Position,
+    image: Image,
+    /// Stores the previous attributes for undo
+    previous: Option<Image>,
+}
+
+impl UpdateImageCommand {
+    /// Creates a new UpdateImageCommand
+    pub fn new(position: Position, image: Image) -> Self {
+        Self {
+            position,
+            image,
+            previous: None,
+        }
+    }
+
+    fn set_image(doc: &mut Document, position: Position, image: Image) -> CommandResult<Image> {
+        // The image may have moved since the command was recorded, e.g.
+        // after a remote edit
+        if doc.get_image_at(position).is_none() {
+            return Err(CommandError::execution_failed(
+                "UpdateImageCommand",
+                format!("no image at position {}", position.offset()),
+            ));
+        }
+        let previous = doc.embeds_mut().insert(position.offset(), image);
+        doc.mark_dirty(Range::new(position, Position::new(position.offset() + 1)));
+        doc.increment_version();
+        previous.ok_or_else(|| {
+            CommandError::execution_failed(
+                "UpdateImageCommand",
+                format!("no image at position {}", position.offset()),
+            )
+        })
+    }
+}
+
+impl Command for UpdateImageCommand {
+    fn execute(&mut self, doc: &mut Document) -> CommandResult<()> {
+        if !self.image.has_safe_src() {
+            return Err(CommandError::execution_failed(
+                "UpdateImageCommand",
+                format!("unsafe image source '{}'", self.image.src),
+            ));
+        }
+
+        self.previous = Some(Self::set_image(doc, self.position, self.image.clone())?);
+        Ok(())
+    }
+
+    fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
+        match self.previous.take() {
+            Some(previous) => {
+                if let Err(e) = Self::set_image(doc, self.position, previous.clone()) {
+                    self.previous = Some(previous);
+                    return Err(e);
+                }
+                Ok(())
+            }
+            None => Err(CommandError::command_not_executed("UpdateImageCommand")),
+        }
+    }
+
+    fn description(&self) -> String {
+        format!("Update image at position {}", self.position.offset())
+    }
+
+    fn label(&self) -> &'static str {
+        "image"
+    }
+}
+
+/// Command that attaches an annotation to a range
+#[derive(Debug, Clone)]
+pub struct AddAnnotationCommand {
+    range: Range,
+    annotation: Annotation,
+    /// Stores the annotation with the same id that was replaced, for undo
+    previous: Option<(Range, Annotation)>,
+    executed: bool,
+}
+
+impl AddAnnotationCommand {
+    /// Creates a new AddAnnotationCommand
+    pub fn new(range: Range, annotation: Annotation) -> Self {
+        Self {
+            range,
+            annotation,
+            previous: None,
+            executed: false,
+        }
+    }
+}
+
+impl Command for AddAnnotationCommand {
+    fn execute(&mut self, doc: &mut Document) -> CommandResult<()> {
+        let normalized = self.range.normalize();
+        let start = normalized.start_offset();
+        let end = normalized.end_offset();
+        let length = doc.get_length();
+
+        if end > length || start == end {
+            return Err(CommandError::invalid_range(start, end, length));
+        }
+
+        self.previous = doc
+            .annotations_mut()
+            .insert(normalized, self.annotation.clone());
+        doc.mark_dirty(normalized);
+        doc.increment_version();
+        self.executed = true;
+        Ok(())
+    }
+
+    fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
+        if !self.executed {
+            return Err(CommandError::command_not_executed("AddAnnotationCommand"));
+        }
+
+        if let Some((range, _)) = doc.annotations_mut().remove(&self.annotation.id) {
+            doc.mark_dirty(range);
+        }
+        if let Some((range, annotation)) = self.previous.take() {
+            doc.annotations_mut().insert(range, annotation);
+            doc.mark_dirty(range);
+        }
+        doc.increment_version();
+        self.executed = false;
+        Ok(())
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Add annotation '{}' to range {}..{}",
+            self.annotation.id,
+            self.range.start.offset(),
+            self.range.end.offset()
+        )
+    }
+
+    fn label(&self) -> &'static str {
+        "comment"
+    }
+}
+
+/// Command that removes an annotation by id
+#[derive(Debug, Clone)]
+pub struct RemoveAnnotationCommand {
+    id: String,
+    /// Stores the removed annotation for undo
+    removed: Option<(Range, Annotation)>,
+}
+
+impl RemoveAnnotationCommand {
+    /// Creates a new RemoveAnnotationCommand
+    pub fn new(id: String) -> Self {
+        Self { id, removed: None }
+    }
+}
+
+impl Command for RemoveAnnotationCommand {
+    fn execute(&mut self, doc: &mut Document) -> CommandResult<()> {
+        let (range, annotation) = doc.annotations_mut().remove(&self.id).ok_or_else(|| {
+            CommandError::execution_failed(
+                "RemoveAnnotationCommand",
+                format!("no annotation with id '{}'", self.id),
+            )
+        })?;
+        doc.mark_dirty(range);
+        doc.increment_version();
+        self.removed = Some((range, annotation));
+        Ok(())
+    }
+
+    fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
+        match self.removed.take() {
+            Some((range, annotation)) => {
+                doc.annotations_mut().insert(range, annotation);
+                doc.mark_dirty(range);
+                doc.increment_version();
+                Ok(())
+            }
+            None => Err(CommandError::command_not_executed(
+                "RemoveAnnotationCommand",
+            )),
+        }
+    }
+
+    fn description(&self) -> String {
+        format!("Remove annotation '{}'", self.id)
+    }
+
+    fn label(&self) -> &'static str {
+        "comment"
+    }
+}
+
+/// Command that sets or removes document metadata values
+///
+/// Each change sets a key to a value, or removes it if the value is None.
+/// All changes are undone together.
+#[derive(Debug, Clone)]
+pub struct SetMetadataCommand {
+    changes: Vec<(String, Option<String>)>,
+    /// Stores the metadata before the changes for undo
+    previous: Option<Metadata>,
+}
+
+impl SetMetadataCommand {
+    /// Creates a new SetMetadataCommand
+    pub fn new(changes: Vec<(String, Option<String>)>) -> Self {
+        Self {
+            changes,
+            previous: None,
+        }
+    }
+}
+
+impl Command for SetMetadataCommand {
+    fn execute(&mut self, doc: &mut Document) -> CommandResult<()> {
+        self.previous = Some(doc.metadata().clone());
+        let metadata = doc.metadata_mut();
+        for (key, value) in &self.changes {
+            match value {
+                Some(value) => metadata.set(key.as_str(), value.as_str()),
+                None => metadata.remove(key),
+            };
+        }
+        doc.increment_version();
+        Ok(())
+    }
+
+    fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
+        match self.previous.take() {
+            Some(previous) => {
+                *doc.metadata_mut() = previous;
+                doc.increment_version();
+                Ok(())
+            }
+            None => Err(CommandError::command_not_executed("SetMetadataCommand")),
+        }
+    }
+
+    fn description(&self) -> String {
+        let keys: Vec<&str> = self.changes.iter().map(|(key, _)| key.as_str()).collect();
+        format!("Set metadata {}", keys.join(", "))
+    }
+
+    fn label(&self) -> &'static str {
+        "document properties"
+    }
+}
+
+/// Command that groups several commands into a single undo step
+///
+/// Created by `CommandHistory` when a batch ends. Executing (redo) replays
+/// the commands in order; undoing reverts them in reverse order.
+pub struct CompositeCommand {
+    commands: Vec<Box<dyn Command>>,
+}
+
+impl CompositeCommand {
+    /// Creates a new CompositeCommand from already executed commands
+    pub fn new(commands: Vec<Box<dyn Command>>) -> Self {
+        Self { commands }
+    }
+
+    /// Returns the number of commands in the group
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Returns true if the group contains no commands
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+/// Returns the label shared by all `commands`, or "edit" if they differ
+pub(crate) fn shared_label(commands: &[Box<dyn Command>]) -> &'static str {
+    let mut labels = commands.iter().map(|cmd| cmd.label());
+    match labels.next() {
+        Some(first) if labels.all(|label| label == first) => first,
+        _ => "edit",
+    }
+}
+
+impl Command for CompositeCommand {
+    fn execute(&mut self, doc: &mut Document) -> CommandResult<()> {
+        for cmd in &mut self.commands {
+            cmd.execute(doc)?;
+        }
+        Ok(())
+    }
+
+    fn undo(&mut self, doc: &mut Document) -> CommandResult<()> {
+        for cmd in self.commands.iter_mut().rev() {
+            cmd.undo(doc)?;
+        }
+        Ok(())
+    }
+
+    fn description(&self) -> String {
+        format!("Batch of {} commands", self.commands.len())
+    }
+
+    /// The shared label of the grouped commands, or "edit" if they differ
+    fn label(&self) -> &'static str {
+        shared_label(&self.commands)
+    }
+
+    fn take_commands(&mut self) -> Option<Vec<Box<dyn Command>>> {
+        Some(std::mem::take(&mut self.commands))
+    }
+
+    fn rebase(&mut self, ops: &[Operation]) -> Option<Vec<Operation>> {
+        self.commands
+            .iter_mut()
+            .rev()
+            .try_fold(ops.to_vec(), |ops, cmd| cmd.rebase(&ops))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_command() {
+        let mut doc = Document::new();
+        let mut cmd = InsertCommand::new(Position::new(0), "Hello".to_string());
+
+        cmd.execute(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "Hello");
+
+        cmd.undo(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "");
+    }
+
+    #[test]
+    fn test_insert_command_in_middle() {
+        let mut doc = Document::from_text("Hello World");
+        let mut cmd = InsertCommand::new(Position::new(5), " Beautiful".to_string());
+
+        cmd.execute(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "Hello Beautiful World");
+
+        cmd.undo(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "Hello World");
+    }
+
+    #[test]
+    fn test_delete_command() {
+        let mut doc = Document::from_text("Hello World");
+        let mut cmd = DeleteCommand::new(Range::new(Position::new(5), Position::new(11)));
+
+        cmd.execute(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "Hello");
+
+        cmd.undo(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "Hello World");
+    }
+
+    #[test]
+    fn test_delete_undo_restores_formats_and_blocks() {
+        let mut doc = Document::from_text("ab\ncd\nef");
+        doc.formats_mut().apply_format(
+            Range::from_offsets(0, 2),
+            crate::formatting::InlineFormat::Bold,
+        );
+        doc.formats_mut()
+            .set_block_type(Range::from_offsets(3, 5), BlockType::heading(1));
+
+        let mut cmd = DeleteCommand::new(Range::from_offsets(2, 6));
+        cmd.execute(&mut doc).unwrap();
+        cmd.undo(&mut doc).unwrap();
+
+        assert_eq!(doc.get_content(), "ab\ncd\nef");
+        let line_types: Vec<_> = [0, 3, 6]
+            .map(|offset| doc.get_block_type_at(Position::new(offset)))
+            .into();
+        assert_eq!(
+            line_types,
+            [
+                BlockType::Paragraph,
+                BlockType::heading(1),
+                BlockType::Paragraph
+            ]
+        );
+        // The reinserted text doesn't pick up the bold run before it
+        assert!(doc.formats().get_formats_at(Position::new(3)).is_empty());
+    }
+
+    #[test]
+    fn test_replace_command() {
+        let mut doc = Document::from_text("Hello World");
+        let mut cmd = ReplaceCommand::new(
+            Range::new(Position::new(6), Position::new(11)),
+            "Rust".to_string(),
+        );
+
+        cmd.execute(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "Hello Rust");
+
+        cmd.undo(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "Hello World");
+    }
+
+    #[test]
+    fn test_command_description() {
+        let insert_cmd = InsertCommand::new(Position::new(0), "test".to_string());
+        assert!(insert_cmd.description().contains("Insert"));
+
+        let delete_cmd = DeleteCommand::new(Range::new(Position::new(0), Position::new(5)));
+        assert!(delete_cmd.description().contains("Delete"));
+
+        let replace_cmd = ReplaceCommand::new(
+            Range::new(Position::new(0), Position::new(5)),
+            "new".to_string(),
+        );
+        assert!(replace_cmd.description().contains("Replace"));
+    }
+
+    #[test]
+    fn test_invalid_position() {
+        let mut doc = Document::from_text("Hello");
+        let mut cmd = InsertCommand::new(Position::new(100), "test".to_string());
+
+        let result = cmd.execute(&mut doc);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_undo_without_execute() {
+        let mut doc = Document::new();
+        let mut cmd = InsertCommand::new(Position::new(0), "test".to_string());
+
+        let result = cmd.undo(&mut doc);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_apply_format_command() {
+        use crate::formatting::InlineFormat;
+
+        let mut doc = Document::from_text("Hello World");
+        let range = Range::from_offsets(0, 5);
+        let mut cmd = ApplyFormatCommand::new(range, InlineFormat::Bold);
 
         cmd.execute(&mut doc).unwrap();
         let formats = doc.get_formats_at(Position::new(2));
@@ -745,4 +2119,25 @@ mod tests {
         let formats = doc.get_formats_at(Position::new(2));
         assert!(formats.contains(&InlineFormat::Bold));
     }
+
+    #[test]
+    fn test_composite_command_undo_order() {
+        let mut doc = Document::new();
+        let mut first: Box<dyn Command> =
+            Box::new(InsertCommand::new(Position::new(0), "Hello".to_string()));
+        let mut second: Box<dyn Command> =
+            Box::new(InsertCommand::new(Position::new(5), " World".to_string()));
+        first.execute(&mut doc).unwrap();
+        second.execute(&mut doc).unwrap();
+
+        let mut composite = CompositeCommand::new(vec![first, second]);
+        assert_eq!(composite.len(), 2);
+        assert_eq!(composite.description(), "Batch of 2 commands");
+
+        composite.undo(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "");
+
+        composite.execute(&mut doc).unwrap();
+        assert_eq!(doc.get_content(), "Hello World");
+    }
 }
@@ -0,0 +1,722 @@
+//! Operational transform for server-authoritative editing
+//!
+//! While tracking is enabled, every local text edit is recorded as a
+//! pending `Operation` until the server acknowledges it. An operation
+//! arriving from the server was applied before the pending ones in the
+//! server's order, so it is transformed against them before being applied
+//! here, and the pending operations are rebased on top of it so they can
+//! still be sent.
+//!
+//! Only text edits are transformed; formatting is not tracked.
+
+use crate::document::{Document, Position, Range};
+use crate::operations::CommandResult;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Errors that can occur while transforming operations
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum OtError {
+    #[error("Operational transform is not enabled for this document")]
+    NotEnabled,
+
+    #[error("Invalid operation: {0}")]
+    InvalidOperation(String),
+}
+
+/// A text operation expressed in character offsets
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Operation {
+    /// Inserts `text` at `position`
+    Insert { position: usize, text: String },
+    /// Deletes the characters in `start..end`
+    Delete { start: usize, end: usize },
+}
+
+impl Operation {
+    /// Returns the change in document length caused by the operation
+    fn length_delta(&self) -> isize {
+        match self {
+            Operation::Insert { text, .. } => text.chars().count() as isize,
+            Operation::Delete { start, end } => -((end - start) as isize),
+        }
+    }
+}
+
+/// Transforms `op` so it applies after `against`
+///
+/// Both operations must apply to the same document state. When both insert
+/// at the same position, the one with `op_first` set ends up on the left.
+/// A deletion spanning an insertion is split so the inserted text survives;
+/// the pieces are returned in an order that can be applied sequentially.
+pub fn transform(op: &Operation, against: &Operation, op_first: bool) -> Vec<Operation> {
+    use Operation::*;
+
+    match (op, against) {
+        (
+            Insert { position, text },
+            Insert {
+                position: other,
+                text: other_text,
+            },
+        ) => {
+            let position = if *position < *other || (*position == *other && op_first) {
+                *position
+            } else {
+                *position + other_text.chars().count()
+            };
+            vec![Insert {
+                position,
+                text: text.clone(),
+            }]
+        }
+        (Insert { position, text }, Delete { start, end }) => {
+            let position = if *position <= *start {
+                *position
+            } else if *position >= *end {
+                *position - (end - start)
+            } else {
+                *start
+            };
+            vec![Insert {
+                position,
+                text: text.clone(),
+            }]
+        }
+        (Delete { start, end }, Insert { position, text }) => {
+            let len = text.chars().count();
+            if *position <= *start {
+                vec![Delete {
+                    start: start + len,
+                    end: end + len,
+                }]
+            } else if *position >= *end {
+                vec![op.clone()]
+            } else {
+                // Delete the later piece first so the earlier offsets stay valid
+                vec![
+                    Delete {
+                        start: position + len,
+                        end: end + len,
+                    },
+                    Delete {
+                        start: *start,
+                        end: *position,
+                    },
+                ]
+            }
+        }
+        (
+            Delete { start, end },
+            Delete {
+                start: other_start,
+                end: other_end,
+            },
+        ) => {
+            let map = |offset: usize| {
+                if offset <= *other_start {
+                    offset
+                } else if offset >= *other_end {
+                    offset - (other_end - other_start)
+                } else {
+                    *other_start
+                }
+            };
+            let (start, end) = (map(*start), map(*end));
+            if start < end {
+                vec![Delete { start, end }]
+            } else {
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Transforms two sequences of operations that apply to the same state
+///
+/// Returns `(a', b')` such that applying `b` then `a'` gives the same
+/// document as applying `a` then `b'`.
+pub fn transform_sequences(
+    a: Vec<Operation>,
+    b: Vec<Operation>,
+    a_first: bool,
+) -> (Vec<Operation>, Vec<Operation>) {
+    match (a.len(), b.len()) {
+        (0, _) | (_, 0) => (a, b),
+        (1, 1) => (
+            transform(&a[0], &b[0], a_first),
+            transform(&b[0], &a[0], !a_first),
+        ),
+        (1, _) => {
+            let mut b = b;
+            let rest = b.split_off(1);
+            let (a, mut b_head) = transform_sequences(a, b, a_first);
+            let (a, b_rest) = transform_sequences(a, rest, a_first);
+            b_head.extend(b_rest);
+            (a, b_head)
+        }
+        _ => {
+            let mut a = a;
+            let rest = a.split_off(1);
+            let (mut a_head, b) = transform_sequences(a, b, a_first);
+            let (a_rest, b) = transform_sequences(rest, b, a_first);
+            a_head.extend(a_rest);
+            (a_head, b)
+        }
+    }
+}
+
+/// Moves `ops` from after an edit to before it
+///
+/// The edit replaced `removed` characters at `start` with `inserted` ones,
+/// and `ops` apply in order to the document it left. Text inserted right at
+/// the start of the edit is taken to come before it.
+///
+/// # Returns
+/// The new start of the edit and the moved operations, or None if an
+/// operation changes the inserted characters
+pub(crate) fn rebase_past_edit(
+    start: usize,
+    removed: usize,
+    inserted: usize,
+    ops: &[Operation],
+) -> Option<(usize, Vec<Operation>)> {
+    let mut start = start;
+    let mut moved = Vec::with_capacity(ops.len());
+    for op in ops {
+        let end = start + inserted;
+        match op {
+            Operation::Insert { position, text } if *position <= start => {
+                start += text.chars().count();
+                moved.push(op.clone());
+            }
+            Operation::Insert { position, text } if *position >= end => {
+                moved.push(Operation::Insert {
+                    position: position - inserted + removed,
+                    text: text.clone(),
+                });
+            }
+            Operation::Delete {
+                start: op_start,
+                end: op_end,
+            } if *op_end <= start => {
+                start -= op_end - op_start;
+                moved.push(op.clone());
+            }
+            Operation::Delete {
+                start: op_start,
+                end: op_end,
+            } if *op_start >= end => {
+                moved.push(Operation::Delete {
+                    start: op_start - inserted + removed,
+                    end: op_end - inserted + removed,
+                });
+            }
+            _ => return None,
+        }
+    }
+    Some((start, moved))
+}
+
+/// Maps an offset through `ops`
+///
+/// Text inserted right at the offset moves it along when `before_inserts`
+/// is set. An offset inside deleted text moves to the start of the deletion.
+pub(crate) fn map_offset(offset: usize, ops: &[Operation], before_inserts: bool) -> usize {
+    ops.iter().fold(offset, |offset, op| match op {
+        Operation::Insert { position, text }
+            if *position < offset || (*position == offset && before_inserts) =>
+        {
+            offset + text.chars().count()
+        }
+        Operation::Delete { start, end } if offset >= *end => offset - (end - start),
+        Operation::Delete { start, .. } if offset > *start => *start,
+        _ => offset,
+    })
+}
+
+/// Maps a range through `ops`, leaving out text inserted at either end
+pub(crate) fn map_range(range: Range, ops: &[Operation]) -> Range {
+    let range = range.normalize();
+    let start = map_offset(range.start.offset(), ops, true);
+    let end = map_offset(range.end.offset(), ops, false);
+    Range::from_offsets(start, end.max(start))
+}
+
+/// Local operations not yet acknowledged by the server
+#[derive(Debug, Clone, Default)]
+pub struct PendingOps {
+    ops: Vec<Operation>,
+    /// Number of pieces in `ops` for each recorded op, oldest first
+    ///
+    /// A remote edit can split a recorded op in two; its pieces stay one
+    /// unit, since the server acknowledges the op as it was sent.
+    units: Vec<usize>,
+    /// Number of leading units already handed out for sending, which must
+    /// not change any more
+    sent: usize,
+}
+
+impl PendingOps {
+    /// Creates an empty pending list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the pending operations in the order they were made
+    pub fn ops(&self) -> &[Operation] {
+        &self.ops
+    }
+
+    /// Records a local insertion, extending the previous one when typing
+    /// continues where it left off and that op has not been sent yet
+    pub(crate) fn record_insert(&mut self, position: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if self.units.len() > self.sent
+            && self.units.last() == Some(&1)
+            && let Some(Operation::Insert {
+                position: last,
+                text: last_text,
+            }) = self.ops.last_mut()
+            && *last + last_text.chars().count() == position
+        {
+            last_text.push_str(text);
+            return;
+        }
+        self.ops.push(Operation::Insert {
+            position,
+            text: text.to_string(),
+        });
+        self.units.push(1);
+    }
+
+    /// Records a local deletion
+    pub(crate) fn record_delete(&mut self, range: Range) {
+        let normalized = range.normalize();
+        if normalized.is_empty() {
+            return;
+        }
+        self.ops.push(Operation::Delete {
+            start: normalized.start_offset(),
+            end: normalized.end_offset(),
+        });
+        self.units.push(1);
+    }
+
+    /// Drops the first `count` units
+    fn acknowledge(&mut self, count: usize) {
+        let count = count.min(self.units.len());
+        let pieces: usize = self.units.drain(..count).sum();
+        self.ops.drain(..pieces);
+        self.sent = self.sent.saturating_sub(count);
+    }
+
+    /// Transforms `op` against the pending ops and rebases them on top of it
+    ///
+    /// Returns `op` as it applies after the pending ops. Sent units are kept
+    /// even when nothing is left of them, so acknowledgements still line up.
+    fn rebase(&mut self, op: Operation) -> Vec<Operation> {
+        let mut applied = vec![op];
+        let mut ops = Vec::with_capacity(self.ops.len());
+        let mut units = Vec::with_capacity(self.units.len());
+        let mut pieces = std::mem::take(&mut self.ops).into_iter();
+        for (index, &size) in self.units.iter().enumerate() {
+            let unit = pieces.by_ref().take(size).collect();
+            let (rest, unit) = transform_sequences(applied, unit, true);
+            applied = rest;
+            if unit.is_empty() && index >= self.sent {
+                continue;
+            }
+            units.push(unit.len());
+            ops.extend(unit);
+        }
+        self.ops = ops;
+        self.units = units;
+        applied
+    }
+}
+
+impl Document {
+    /// Starts recording local text edits as pending operations
+    ///
+    /// Has no effect if tracking is already enabled.
+    pub fn enable_operational_transform(&mut self) {
+        if self.pending_ops.is_none() {
+            self.pending_ops = Some(PendingOps::new());
+        }
+    }
+
+    /// Returns the local operations not yet acknowledged by the server
+    ///
+    /// The returned operations are considered sent: later typing is recorded
+    /// as new operations instead of extending them, so acknowledging them
+    /// never drops unsent text.
+    ///
+    /// # Errors
+    /// Returns `OtError::NotEnabled` if tracking is not enabled
+    pub fn pending_ops(&mut self) -> Result<&[Operation], OtError> {
+        let pending = self.pending_ops.as_mut().ok_or(OtError::NotEnabled)?;
+        pending.sent = pending.units.len();
+        Ok(&pending.ops)
+    }
+
+    /// Drops the first `count` pending operations once the server has
+    /// acknowledged them
+    ///
+    /// `count` counts operations as `pending_ops` returned them. An
+    /// operation a remote edit has since split in two, or removed, is still
+    /// acknowledged as one.
+    ///
+    /// # Errors
+    /// Returns `OtError::NotEnabled` if tracking is not enabled
+    pub fn acknowledge_ops(&mut self, count: usize) -> Result<(), OtError> {
+        let pending = self.pending_ops.as_mut().ok_or(OtError::NotEnabled)?;
+        pending.acknowledge(count);
+        Ok(())
+    }
+
+    /// Transforms an operation from the server against the pending local
+    /// operations and applies it
+    ///
+    /// The pending operations are rebased on top of the remote one, and so
+    /// are local undo steps; a step whose text the remote edit touched is
+    /// dropped along with every older one, and redo steps are dropped.
+    ///
+    /// # Returns
+    /// The operations actually applied to this document
+    ///
+    /// # Errors
+    /// Returns `OtError::NotEnabled` if tracking is not enabled, or
+    /// `OtError::InvalidOperation` if the transformed operation does not fit
+    /// the document. Nothing is changed in either case.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    /// use rte_core::operations::ot::Operation;
+    ///
+    /// let mut doc = Document::from_text("world");
+    /// doc.enable_operational_transform();
+    /// doc.insert_text(Position::new(5), "!").unwrap();
+    ///
+    /// let remote = Operation::Insert { position: 0, text: "Hello ".to_string() };
+    /// doc.transform_remote_op(remote).unwrap();
+    /// assert_eq!(doc.get_content(), "Hello world!");
+    /// assert_eq!(
+    ///     doc.pending_ops().unwrap(),
+    ///     [Operation::Insert { position: 11, text: "!".to_string() }]
+    /// );
+    /// ```
+    pub fn transform_remote_op(&mut self, op: Operation) -> Result<Vec<Operation>, OtError> {
+        let pending = self.pending_ops.as_ref().ok_or(OtError::NotEnabled)?;
+        if let Operation::Delete { start, end } = op
+            && start > end
+        {
+            return Err(OtError::InvalidOperation(format!(
+                "delete start {} is after end {}",
+                start, end
+            )));
+        }
+
+        // Pending ops are undone on top of the server state, so check the
+        // remote op against the length the server saw
+        let pending_delta: isize = pending.ops.iter().map(Operation::length_delta).sum();
+        let server_length = (self.get_length() as isize - pending_delta) as usize;
+        let fits = match &op {
+            Operation::Insert { position, .. } => *position <= server_length,
+            Operation::Delete { end, .. } => *end <= server_length,
+        };
+        if !fits {
+            return Err(OtError::InvalidOperation(format!(
+                "{:?} is out of bounds (length: {})",
+                op, server_length
+            )));
+        }
+
+        // Apply without recording the remote edits as local ones
+        let mut pending = self.pending_ops.take().unwrap_or_default();
+        let applied = pending.rebase(op);
+        for op in &applied {
+            match op {
+                Operation::Insert { position, text } => {
+                    self.insert_text_direct(Position::new(*position), text)
+                }
+                Operation::Delete { start, end } => {
+                    self.delete_range_direct(Range::from_offsets(*start, *end))
+                }
+            }
+        }
+        self.pending_ops = Some(pending);
+
+        if !applied.is_empty() {
+            self.history.rebase(&applied);
+        }
+        Ok(applied)
+    }
+
+    /// Applies an operation as a local edit, without transforming it
+    ///
+    /// The edit is undoable and recorded as a pending operation like typed
+    /// text, so applying the operations returned by `pending_ops`, in
+    /// order, to a copy of the document they started from reproduces it.
+    ///
+    /// # Errors
+    /// Returns an error if the operation does not fit the document or the
+    /// document is read-only or full
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    ///
+    /// let mut doc = Document::from_text("Hi");
+    /// doc.enable_operational_transform();
+    /// doc.insert_text(Position::new(2), " there").unwrap();
+    /// let log = doc.pending_ops().unwrap().to_vec();
+    ///
+    /// let mut replay = Document::from_text("Hi");
+    /// for op in &log {
+    ///     replay.apply_operation(op).unwrap();
+    /// }
+    /// assert_eq!(replay.get_content(), "Hi there");
+    /// ```
+    pub fn apply_operation(&mut self, op: &Operation) -> CommandResult<()> {
+        match op {
+            Operation::Insert { position, text } => {
+                self.insert_text(Position::new(*position), text)
+            }
+            Operation::Delete { start, end } => {
+                self.delete_range(Range::from_offsets(*start, *end))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(position: usize, text: &str) -> Operation {
+        Operation::Insert {
+            position,
+            text: text.to_string(),
+        }
+    }
+
+    fn delete(start: usize, end: usize) -> Operation {
+        Operation::Delete { start, end }
+    }
+
+    fn apply(text: &str, ops: &[Operation]) -> String {
+        let mut chars: Vec<char> = text.chars().collect();
+        for op in ops {
+            match op {
+                Operation::Insert { position, text } => {
+                    chars.splice(*position..*position, text.chars());
+                }
+                Operation::Delete { start, end } => {
+                    chars.drain(*start..*end);
+                }
+            }
+        }
+        chars.into_iter().collect()
+    }
+
+    /// Checks that both application orders converge
+    fn assert_converges(base: &str, a: Vec<Operation>, b: Vec<Operation>) -> String {
+        let (a_prime, b_prime) = transform_sequences(a.clone(), b.clone(), true);
+        let left = apply(&apply(base, &b), &a_prime);
+        let right = apply(&apply(base, &a), &b_prime);
+        assert_eq!(left, right);
+        left
+    }
+
+    #[test]
+    fn test_apply_operation_replays_log() {
+        let mut doc = Document::from_text("Hello");
+        doc.enable_operational_transform();
+        doc.insert_text(Position::new(5), " world").unwrap();
+        doc.delete_range(Range::from_offsets(0, 1)).unwrap();
+        doc.insert_text(Position::new(0), "J").unwrap();
+        let log = doc.pending_ops().unwrap().to_vec();
+
+        let mut replay = Document::from_text("Hello");
+        for op in &log {
+            replay.apply_operation(op).unwrap();
+        }
+        assert_eq!(replay.get_content(), "Jello world");
+        assert!(replay.can_undo());
+        assert!(replay.apply_operation(&insert(40, "x")).is_err());
+        assert!(replay.apply_operation(&delete(3, 40)).is_err());
+        assert_eq!(replay.get_content(), "Jello world");
+    }
+
+    #[test]
+    fn test_concurrent_inserts() {
+        assert_eq!(
+            assert_converges("abc", vec![insert(1, "X")], vec![insert(1, "Y")]),
+            "aXYbc"
+        );
+        assert_eq!(
+            assert_converges("abc", vec![insert(0, "X")], vec![insert(3, "Y")]),
+            "XabcY"
+        );
+    }
+
+    #[test]
+    fn test_insert_inside_delete() {
+        assert_eq!(
+            assert_converges("abcdef", vec![delete(1, 5)], vec![insert(3, "XY")]),
+            "aXYf"
+        );
+    }
+
+    #[test]
+    fn test_overlapping_deletes() {
+        assert_eq!(
+            assert_converges("abcdef", vec![delete(1, 4)], vec![delete(2, 6)]),
+            "a"
+        );
+        assert_eq!(
+            assert_converges("abcdef", vec![delete(1, 3)], vec![delete(1, 3)]),
+            "adef"
+        );
+    }
+
+    #[test]
+    fn test_sequences_converge() {
+        assert_converges(
+            "Hello World",
+            vec![delete(0, 6), insert(5, "!")],
+            vec![insert(5, ","), delete(7, 9), insert(0, ">> ")],
+        );
+    }
+
+    #[test]
+    fn test_requires_tracking() {
+        let mut doc = Document::from_text("Hello");
+        assert_eq!(doc.pending_ops(), Err(OtError::NotEnabled));
+        assert_eq!(
+            doc.transform_remote_op(insert(0, "x")),
+            Err(OtError::NotEnabled)
+        );
+    }
+
+    #[test]
+    fn test_records_pending_ops() {
+        let mut doc = Document::from_text("Hello");
+        doc.enable_operational_transform();
+        doc.insert_text(Position::new(5), " W").unwrap();
+        doc.insert_text(Position::new(7), "orld").unwrap();
+        doc.delete_range(Range::from_offsets(0, 1)).unwrap();
+
+        assert_eq!(
+            doc.pending_ops().unwrap(),
+            [insert(5, " World"), delete(0, 1)]
+        );
+
+        doc.acknowledge_ops(1).unwrap();
+        assert_eq!(doc.pending_ops().unwrap(), [delete(0, 1)]);
+    }
+
+    #[test]
+    fn test_typing_after_get_is_not_merged_into_sent_op() {
+        let mut doc = Document::from_text("Hello");
+        doc.enable_operational_transform();
+        doc.insert_text(Position::new(5), " W").unwrap();
+        assert_eq!(doc.pending_ops().unwrap(), [insert(5, " W")]);
+
+        doc.insert_text(Position::new(7), "orld").unwrap();
+        assert_eq!(
+            doc.pending_ops().unwrap(),
+            [insert(5, " W"), insert(7, "orld")]
+        );
+
+        // The server acknowledges the first op as it was sent
+        doc.acknowledge_ops(1).unwrap();
+        assert_eq!(doc.pending_ops().unwrap(), [insert(7, "orld")]);
+    }
+
+    #[test]
+    fn test_remote_delete_around_local_insert() {
+        let mut doc = Document::from_text("Hello World");
+        doc.enable_operational_transform();
+        doc.insert_text(Position::new(8), "!!").unwrap();
+
+        let applied = doc.transform_remote_op(delete(5, 11)).unwrap();
+        assert_eq!(applied.len(), 2);
+        assert_eq!(doc.get_content(), "Hello!!");
+        assert_eq!(doc.pending_ops().unwrap(), [insert(5, "!!")]);
+    }
+
+    #[test]
+    fn test_split_sent_op_is_acknowledged_as_one() {
+        let mut doc = Document::from_text("abcdef");
+        doc.enable_operational_transform();
+        doc.delete_range(Range::from_offsets(1, 5)).unwrap();
+        assert_eq!(doc.pending_ops().unwrap(), [delete(1, 5)]);
+
+        doc.transform_remote_op(insert(3, "X")).unwrap();
+        assert_eq!(doc.get_content(), "aXf");
+        assert_eq!(doc.pending_ops().unwrap().len(), 2);
+
+        doc.acknowledge_ops(1).unwrap();
+        assert!(doc.pending_ops().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_removed_sent_op_is_still_acknowledged() {
+        let mut doc = Document::from_text("abcdef");
+        doc.enable_operational_transform();
+        doc.delete_range(Range::from_offsets(1, 2)).unwrap();
+        doc.pending_ops().unwrap();
+        doc.insert_text(Position::new(4), "!").unwrap();
+
+        // The server already deleted the same text
+        doc.transform_remote_op(delete(0, 3)).unwrap();
+        assert_eq!(doc.get_content(), "de!f");
+        assert_eq!(doc.pending_ops().unwrap(), [insert(2, "!")]);
+
+        doc.acknowledge_ops(1).unwrap();
+        assert_eq!(doc.pending_ops().unwrap(), [insert(2, "!")]);
+    }
+
+    #[test]
+    fn test_remote_op_keeps_local_undo() {
+        let mut doc = Document::from_text("world");
+        doc.enable_operational_transform();
+        doc.insert_text(Position::new(5), "!").unwrap();
+        doc.transform_remote_op(insert(0, "Hello ")).unwrap();
+
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "Hello world");
+        doc.redo().unwrap();
+        assert_eq!(doc.get_content(), "Hello world!");
+    }
+
+    #[test]
+    fn test_remote_op_out_of_bounds() {
+        let mut doc = Document::from_text("Hi");
+        doc.enable_operational_transform();
+        doc.insert_text(Position::new(2), " there").unwrap();
+
+        assert!(matches!(
+            doc.transform_remote_op(delete(0, 5)),
+            Err(OtError::InvalidOperation(_))
+        ));
+        assert_eq!(doc.get_content(), "Hi there");
+    }
+
+    #[test]
+    fn test_operation_json() {
+        let op: Operation =
+            serde_json::from_str(r#"{"type":"insert","position":2,"text":"ab"}"#).unwrap();
+        assert_eq!(op, insert(2, "ab"));
+        assert_eq!(
+            serde_json::to_string(&delete(1, 3)).unwrap(),
+            r#"{"type":"delete","start":1,"end":3}"#
+        );
+    }
+}
@@ -1,11 +1,15 @@
 use crate::{
-    document::{Document, Range},
-    operations::Command,
+    document::{Document, Position, Range},
+    formatting::storage::BlockInfo,
+    operations::{Command, history::now},
+    selection::Selection,
+    utils::segmenter::is_word_char,
 };
 use regex::Regex;
+use std::time::Duration;
 
 /// Query parameters for searching text in a document
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct SearchQuery {
     /// The pattern to search for (literal string or regex pattern)
     pub pattern: String,
@@ -13,6 +17,10 @@ pub struct SearchQuery {
     pub case_sensitive: bool,
     /// Whether to interpret the pattern as a regular expression
     pub use_regex: bool,
+    /// Whether accented letters match their unaccented base letter
+    pub ignore_accents: bool,
+    /// Whether matches must start and end at word boundaries
+    pub whole_word: bool,
 }
 
 impl SearchQuery {
@@ -22,6 +30,8 @@ impl SearchQuery {
             pattern,
             case_sensitive: false,
             use_regex: false,
+            ignore_accents: false,
+            whole_word: false,
         }
     }
 
@@ -36,6 +46,51 @@ impl SearchQuery {
         self.use_regex = use_regex;
         self
     }
+
+    /// Sets whether accents are ignored, so "resume" matches "résumé"
+    ///
+    /// Letters are compared by their canonical decomposition with the
+    /// combining marks dropped. Case sensitivity still applies to the base
+    /// letter, so a case-sensitive "Resume" matches "Résumé" but not "résumé".
+    pub fn ignore_accents(mut self, ignore_accents: bool) -> Self {
+        self.ignore_accents = ignore_accents;
+        self
+    }
+
+    /// Sets whether only whole words match, so "cat" does not match the
+    /// start of "catch"
+    ///
+    /// A match counts as a whole word when it does not continue a word on
+    /// either side: the characters just outside it are not letters, digits
+    /// or underscores, or the match itself starts or ends with one that
+    /// is not.
+    pub fn whole_word(mut self, whole_word: bool) -> Self {
+        self.whole_word = whole_word;
+        self
+    }
+}
+
+/// Search state kept by a document between find operations
+///
+/// It lets a find bar restore its query and options when the document
+/// regains focus, and lets `search_next` and `search_previous` continue
+/// from the last match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchState {
+    /// The last query searched for
+    pub query: SearchQuery,
+    /// Index of the match last selected, or None if none was
+    pub current_match: Option<usize>,
+}
+
+impl SearchState {
+    /// Creates a search state for `query` with no current match
+    pub fn new(query: SearchQuery) -> Self {
+        Self {
+            query,
+            current_match: None,
+        }
+    }
 }
 
 /// Result of a search operation containing all matches
@@ -75,13 +130,79 @@ impl Default for SearchResult {
     }
 }
 
+/// A search that finds its matches a batch at a time
+///
+/// Finding every match of a large document in one call can block an
+/// editor's UI thread. `Document::start_search` copies the text into an
+/// `IncrementalSearch`, whose `next_batch` finds matches until a count or
+/// time budget runs out and picks up where it stopped on the next call.
+/// Matches are offsets into the text as it was when the search started.
+#[derive(Debug, Clone)]
+pub struct IncrementalSearch {
+    scanner: MatchScanner,
+    /// Version of the document when the search started
+    version: u64,
+    /// Number of matches found so far
+    found: usize,
+}
+
+impl IncrementalSearch {
+    /// Finds up to `max_matches` more matches, stopping early once `budget`
+    /// has passed
+    ///
+    /// The budget is checked after each match, so a batch holds at least
+    /// one match unless the search is done or `max_matches` is 0.
+    ///
+    /// # Returns
+    /// The matches found, in document order
+    pub fn next_batch(
+        &mut self,
+        max_matches: Option<usize>,
+        budget: Option<Duration>,
+    ) -> Vec<Range> {
+        let started = now();
+        let mut batch = Vec::new();
+        while max_matches.is_none_or(|max| batch.len() < max) {
+            let Some(m) = self.scanner.next_match() else {
+                break;
+            };
+            batch.push(m);
+            if budget.is_some_and(|budget| now().saturating_sub(started) >= budget) {
+                break;
+            }
+        }
+        self.found += batch.len();
+        batch
+    }
+
+    /// Returns true once the search has reached the end of the text
+    pub fn is_done(&self) -> bool {
+        self.scanner.is_done()
+    }
+
+    /// Returns the number of matches found so far
+    pub fn match_count(&self) -> usize {
+        self.found
+    }
+
+    /// Returns true if `doc` has not changed since the search started, so
+    /// the matches still point at the text they matched
+    pub fn is_current(&self, doc: &Document) -> bool {
+        doc.version() == self.version
+    }
+}
+
 /// Command that performs find and replace operation
 #[derive(Debug, Clone)]
 pub struct FindAndReplaceCommand {
     query: SearchQuery,
     replacement: String,
+    /// Text to search, or None for the whole document
+    range: Option<Range>,
     /// Stores the ranges and original text for undo
     replaced_ranges: Option<Vec<(Range, String)>>,
+    /// Stores the blocks before the replace, which may add or remove lines
+    previous_blocks: Vec<BlockInfo>,
 }
 
 impl FindAndReplaceCommand {
@@ -90,15 +211,27 @@ impl FindAndReplaceCommand {
         Self {
             query,
             replacement,
+            range: None,
             replaced_ranges: None,
+            previous_blocks: Vec::new(),
         }
     }
+
+    /// Limits the command to matches inside `range`
+    pub fn with_range(mut self, range: Range) -> Self {
+        self.range = Some(range);
+        self
+    }
 }
 
 impl crate::operations::Command for FindAndReplaceCommand {
     fn execute(&mut self, doc: &mut Document) -> crate::operations::CommandResult<()> {
         // Find all matches
-        let result = doc.find(&self.query).map_err(|e| {
+        let result = match self.range {
+            Some(range) => doc.find_in_range(&self.query, range),
+            None => doc.find(&self.query),
+        }
+        .map_err(|e| {
             crate::operations::CommandError::execution_failed("FindAndReplaceCommand", e)
         })?;
 
@@ -118,8 +251,9 @@ impl crate::operations::Command for FindAndReplaceCommand {
         let mut matches = result.matches;
         matches.sort_by(|a, b| b.start_offset().cmp(&a.start_offset()));
 
+        self.previous_blocks = doc.formats().get_blocks().to_vec();
         for range in matches {
-            doc.replace_range_direct(range, &self.replacement);
+            replace_match(doc, range, &self.replacement);
         }
 
         self.replaced_ranges = Some(replaced);
@@ -128,19 +262,29 @@ impl crate::operations::Command for FindAndReplaceCommand {
 
     fn undo(&mut self, doc: &mut Document) -> crate::operations::CommandResult<()> {
         if let Some(ref replaced) = self.replaced_ranges {
-            // Restore original text in reverse order
+            // Find where each replacement ended up: earlier replacements of
+            // a different length moved it
             let mut ranges: Vec<_> = replaced.iter().collect();
-            ranges.sort_by(|a, b| b.0.start_offset().cmp(&a.0.start_offset()));
-
+            ranges.sort_by_key(|(range, _)| range.start_offset());
+            let replacement_len = self.replacement.chars().count();
+            let mut shift = 0isize;
+            let mut current: Vec<(Range, &String)> = Vec::with_capacity(ranges.len());
             for (original_range, original_text) in ranges {
-                // Calculate the current range (after replacement)
-                let replacement_len = self.replacement.chars().count();
-                let current_range = Range::from_offsets(
-                    original_range.start_offset(),
-                    original_range.start_offset() + replacement_len,
-                );
+                let start = original_range.start_offset().saturating_add_signed(shift);
+                current.push((
+                    Range::from_offsets(start, start + replacement_len),
+                    original_text,
+                ));
+                shift += replacement_len as isize - original_range.len() as isize;
+            }
+
+            // Restore original text in reverse order
+            for (current_range, original_text) in current.into_iter().rev() {
                 doc.replace_range_direct(current_range, original_text);
             }
+            if !replaced.is_empty() {
+                doc.formats_mut().set_blocks(self.previous_blocks.clone());
+            }
 
             Ok(())
         } else {
@@ -156,6 +300,10 @@ impl crate::operations::Command for FindAndReplaceCommand {
             self.query.pattern, self.replacement
         )
     }
+
+    fn label(&self) -> &'static str {
+        "replacement"
+    }
 }
 
 impl Document {
@@ -170,65 +318,185 @@ impl Document {
     /// # Errors
     /// Returns an error if the regex pattern is invalid
     pub fn find(&self, query: &SearchQuery) -> Result<SearchResult, String> {
-        let content = self.get_content();
-        let mut matches = Vec::new();
-
-        // Handle empty pattern - return no matches to avoid infinite loops
-        if query.pattern.is_empty() {
-            return Ok(SearchResult::new());
-        }
-
-        if query.use_regex {
-            // Use regex pattern matching
-            let pattern = if query.case_sensitive {
-                &query.pattern
-            } else {
-                // Prepend case-insensitive flag
-                &format!("(?i){}", query.pattern)
-            };
+        find_in_text(&self.get_content(), query).map(SearchResult::with_matches)
+    }
 
-            let regex = Regex::new(pattern).map_err(|e| format!("Invalid regex pattern: {}", e))?;
+    /// Finds the occurrences of the search query inside a range, such as
+    /// the selection
+    ///
+    /// Only the text of the range is searched, so matches never extend
+    /// past it and regex anchors such as `^` match at its start.
+    ///
+    /// # Errors
+    /// Returns an error if the regex pattern is invalid
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Range};
+    /// use rte_core::operations::search::SearchQuery;
+    ///
+    /// let doc = Document::from_text("cat dog cat dog");
+    /// let query = SearchQuery::new("dog".to_string());
+    /// let result = doc.find_in_range(&query, Range::from_offsets(6, 15)).unwrap();
+    /// assert_eq!(result.matches, [Range::from_offsets(12, 15)]);
+    /// ```
+    pub fn find_in_range(&self, query: &SearchQuery, range: Range) -> Result<SearchResult, String> {
+        let normalized = range.normalize();
+        let start = normalized.start_offset().min(self.get_length());
+        let end = normalized.end_offset().min(self.get_length());
+        let text = self.get_text_in_range(Range::from_offsets(start, end));
+        let matches = find_in_text(&text, query)?
+            .into_iter()
+            .map(|m| Range::from_offsets(m.start_offset() + start, m.end_offset() + start))
+            .collect();
+        Ok(SearchResult::with_matches(matches))
+    }
 
-            for mat in regex.find_iter(&content) {
-                let start_offset = content[..mat.start()].chars().count();
-                let end_offset = start_offset + mat.as_str().chars().count();
-                matches.push(Range::from_offsets(start_offset, end_offset));
+    /// Finds the match nearest to an offset in one direction, such as the
+    /// next match for a find bar to step to
+    ///
+    /// Searching forward returns the first match starting at or after
+    /// `from`; searching backward returns the last match ending at or
+    /// before it. Pass the end of the current match to step forward and
+    /// its start to step back. With `wrap`, the search continues from the
+    /// other end of the document when nothing is found. Matches are scanned
+    /// in order only up to the one returned, and the selection is left
+    /// alone.
+    ///
+    /// # Errors
+    /// Returns an error if the regex pattern is invalid
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position, Range};
+    /// use rte_core::operations::search::SearchQuery;
+    ///
+    /// let doc = Document::from_text("cat catch cat");
+    /// let query = SearchQuery::new("cat".to_string()).whole_word(true);
+    /// let next = doc.find_from(&query, Position::new(3), true, false).unwrap();
+    /// assert_eq!(next, Some(Range::from_offsets(10, 13)));
+    /// assert_eq!(doc.find_from(&query, Position::new(13), true, false).unwrap(), None);
+    /// let wrapped = doc.find_from(&query, Position::new(13), true, true).unwrap();
+    /// assert_eq!(wrapped, Some(Range::from_offsets(0, 3)));
+    /// ```
+    pub fn find_from(
+        &self,
+        query: &SearchQuery,
+        from: Position,
+        forward: bool,
+        wrap: bool,
+    ) -> Result<Option<Range>, String> {
+        let content = self.get_content();
+        let from = from.offset().min(self.get_length());
+        let mut found = None;
+        if forward {
+            scan_matches(&content, query, |m| {
+                if m.start_offset() < from {
+                    return true;
+                }
+                found = Some(m);
+                false
+            })?;
+            if found.is_none() && wrap {
+                scan_matches(&content, query, |m| {
+                    found = Some(m);
+                    false
+                })?;
             }
         } else {
-            // Use literal string matching
-            let search_text = if query.case_sensitive {
-                content.clone()
-            } else {
-                content.to_lowercase()
-            };
-
-            let pattern = if query.case_sensitive {
-                query.pattern.clone()
-            } else {
-                query.pattern.to_lowercase()
-            };
+            scan_matches(&content, query, |m| {
+                if m.end_offset() > from {
+                    return false;
+                }
+                found = Some(m);
+                true
+            })?;
+            if found.is_none() && wrap {
+                scan_matches(&content, query, |m| {
+                    found = Some(m);
+                    true
+                })?;
+            }
+        }
+        Ok(found)
+    }
 
-            let pattern_len = pattern.chars().count();
-            let mut start_pos = 0;
+    /// Starts a search that finds its matches in batches, for documents
+    /// too large to search in one call
+    ///
+    /// See `IncrementalSearch`.
+    ///
+    /// # Errors
+    /// Returns an error if the regex pattern is invalid
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Range};
+    /// use rte_core::operations::search::SearchQuery;
+    ///
+    /// let doc = Document::from_text("one two one two one");
+    /// let mut search = doc.start_search(&SearchQuery::new("one".to_string())).unwrap();
+    /// assert_eq!(
+    ///     search.next_batch(Some(2), None),
+    ///     [Range::from_offsets(0, 3), Range::from_offsets(8, 11)]
+    /// );
+    /// assert_eq!(search.next_batch(Some(2), None), [Range::from_offsets(16, 19)]);
+    /// assert!(search.is_done());
+    /// assert_eq!(search.match_count(), 3);
+    /// ```
+    pub fn start_search(&self, query: &SearchQuery) -> Result<IncrementalSearch, String> {
+        Ok(IncrementalSearch {
+            scanner: MatchScanner::new(&self.get_content(), query)?,
+            version: self.version(),
+            found: 0,
+        })
+    }
 
-            while let Some(relative_pos) = search_text[start_pos..].find(&pattern) {
-                // Convert byte position to character position
-                let byte_pos = start_pos + relative_pos;
-                let char_pos = content[..byte_pos].chars().count();
-                let end_pos = char_pos + pattern_len;
+    /// Gets the search state of the document
+    pub fn search_state(&self) -> &SearchState {
+        &self.search_state
+    }
 
-                matches.push(Range::from_offsets(char_pos, end_pos));
+    /// Replaces the search state, such as to restore a find bar's query
+    /// and options
+    pub fn set_search_state(&mut self, state: SearchState) {
+        self.search_state = state;
+    }
 
-                // Move past this match
-                start_pos = byte_pos + pattern.len();
-            }
-        }
+    /// Selects the next match of the query in the search state
+    ///
+    /// See `find_next`.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Range};
+    /// use rte_core::operations::search::{SearchQuery, SearchState};
+    ///
+    /// let mut doc = Document::from_text("cat dog cat");
+    /// doc.set_search_state(SearchState::new(SearchQuery::new("cat".to_string())));
+    /// assert_eq!(doc.search_next().unwrap(), Some(Range::from_offsets(0, 3)));
+    /// assert_eq!(doc.search_next().unwrap(), Some(Range::from_offsets(8, 11)));
+    /// assert_eq!(doc.search_state().current_match, Some(1));
+    /// ```
+    pub fn search_next(&mut self) -> Result<Option<Range>, String> {
+        let query = self.search_state.query.clone();
+        self.find_next(&query)
+    }
 
-        Ok(SearchResult::with_matches(matches))
+    /// Selects the previous match of the query in the search state
+    ///
+    /// See `find_previous`.
+    pub fn search_previous(&mut self) -> Result<Option<Range>, String> {
+        let query = self.search_state.query.clone();
+        self.find_previous(&query)
     }
 
     /// Finds and replaces all occurrences of the search query with the replacement text
     ///
+    /// Matches and replacements may span lines. Lines a replacement adds
+    /// take the block of the line the match started on, while the text after
+    /// a match and the lines below it keep their blocks.
+    ///
     /// # Arguments
     /// * `query` - The search query containing pattern and options
     /// * `replacement` - The text to replace matches with
@@ -254,6 +522,414 @@ impl Document {
         self.history.push_command(cmd);
         Ok(count)
     }
+
+    /// Replaces the occurrences of the search query inside a range, such
+    /// as the selection, with the replacement text
+    ///
+    /// Matches are found as by `find_in_range` and replaced as by
+    /// `find_and_replace`, as one undo step. A selection that was the range
+    /// is resized to cover the replaced text, so it can be searched again.
+    ///
+    /// # Returns
+    /// The number of replacements made
+    ///
+    /// # Errors
+    /// Returns an error if the regex pattern is invalid or replacement fails
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Range};
+    /// use rte_core::operations::search::SearchQuery;
+    ///
+    /// let mut doc = Document::from_text("a-b a-b");
+    /// let query = SearchQuery::new("-".to_string());
+    /// let count = doc
+    ///     .find_and_replace_in_range(&query, "+", Range::from_offsets(4, 7))
+    ///     .unwrap();
+    /// assert_eq!(count, 1);
+    /// assert_eq!(doc.get_content(), "a-b a+b");
+    /// ```
+    pub fn find_and_replace_in_range(
+        &mut self,
+        query: &SearchQuery,
+        replacement: &str,
+        range: Range,
+    ) -> crate::operations::CommandResult<usize> {
+        let normalized = range.normalize();
+        let selection = self.selection;
+        let length = self.get_length();
+        let mut cmd = Box::new(
+            FindAndReplaceCommand::new(query.clone(), replacement.to_string())
+                .with_range(normalized),
+        );
+        cmd.execute(self)?;
+
+        let count = cmd.replaced_ranges.as_ref().map(|r| r.len()).unwrap_or(0);
+        if count > 0 && selection.range().normalize() == normalized {
+            let end = (normalized.end_offset() + self.get_length()).saturating_sub(length);
+            let (anchor, focus) = if selection.anchor.offset() <= selection.focus.offset() {
+                (normalized.start_offset(), end)
+            } else {
+                (end, normalized.start_offset())
+            };
+            self.selection = Selection::new(Position::new(anchor), Position::new(focus));
+        }
+
+        self.history.push_command(cmd);
+        Ok(count)
+    }
+}
+
+/// Finds the matches of `query` in `content`, as character offsets into it
+fn find_in_text(content: &str, query: &SearchQuery) -> Result<Vec<Range>, String> {
+    let mut matches = Vec::new();
+    scan_matches(content, query, |m| {
+        matches.push(m);
+        true
+    })?;
+    Ok(matches)
+}
+
+/// Calls `visit` with the matches of `query` in `content` in order, as
+/// character offsets into it, until `visit` returns false
+fn scan_matches(
+    content: &str,
+    query: &SearchQuery,
+    mut visit: impl FnMut(Range) -> bool,
+) -> Result<(), String> {
+    let mut scanner = MatchScanner::new(content, query)?;
+    while let Some(m) = scanner.next_match() {
+        if !visit(m) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// What a `MatchScanner` looks for in its haystack
+#[derive(Debug, Clone)]
+enum Matcher {
+    Literal(String),
+    Regex(Regex),
+}
+
+/// The matches of a query in a text, found one at a time
+///
+/// The scanner owns a copy of the text, so a search can stop after any
+/// match and resume later.
+#[derive(Debug, Clone)]
+struct MatchScanner {
+    /// Text the pattern is matched against: the content, lowercased for a
+    /// case-insensitive literal search, or with its accents folded out
+    haystack: String,
+    /// None when nothing can match, such as for an empty pattern
+    matcher: Option<Matcher>,
+    /// For folded text, the character offset in the content each haystack
+    /// character came from, and the characters of the content
+    folded: Option<(Vec<usize>, Vec<char>)>,
+    whole_word: bool,
+    /// Byte offset in the haystack where the next search starts
+    next: usize,
+    /// Byte offset in the haystack where the last match ended
+    last_end: Option<usize>,
+    /// A byte offset in the haystack and its character offset, so offsets
+    /// are converted with a single forward scan
+    cursor: (usize, usize),
+}
+
+impl MatchScanner {
+    /// Prepares to scan `content` for `query`
+    ///
+    /// # Errors
+    /// Returns an error if the regex pattern is invalid
+    fn new(content: &str, query: &SearchQuery) -> Result<Self, String> {
+        // Regex patterns are never lowercased, since that would change
+        // escapes such as \W; case folding is left to the (?i) flag
+        let lowercase = !query.case_sensitive && !query.use_regex;
+        let (haystack, pattern, folded) = if query.ignore_accents {
+            let (folded, origins) = fold_accents(content, lowercase);
+            let pattern = fold_accents(&query.pattern, lowercase).0;
+            (folded, pattern, Some((origins, content.chars().collect())))
+        } else if lowercase {
+            (content.to_lowercase(), query.pattern.to_lowercase(), None)
+        } else {
+            (content.to_string(), query.pattern.clone(), None)
+        };
+
+        // An empty pattern matches nothing, rather than everywhere
+        let matcher = if query.pattern.is_empty() || pattern.is_empty() {
+            None
+        } else if query.use_regex {
+            let pattern = if query.case_sensitive {
+                pattern
+            } else {
+                format!("(?i){}", pattern)
+            };
+            let regex =
+                Regex::new(&pattern).map_err(|e| format!("Invalid regex pattern: {}", e))?;
+            Some(Matcher::Regex(regex))
+        } else {
+            Some(Matcher::Literal(pattern))
+        };
+
+        Ok(Self {
+            haystack,
+            matcher,
+            folded,
+            whole_word: query.whole_word,
+            next: 0,
+            last_end: None,
+            cursor: (0, 0),
+        })
+    }
+
+    /// Returns true once every match has been returned
+    fn is_done(&self) -> bool {
+        self.matcher.is_none() || self.next > self.haystack.len()
+    }
+
+    /// Returns the next match, as character offsets into the content
+    fn next_match(&mut self) -> Option<Range> {
+        loop {
+            let (start, end) = self.find_next()?;
+            self.last_end = Some(end);
+            self.next = if end > start {
+                end
+            } else {
+                end + self.char_len_at(end)
+            };
+
+            if self.folded.is_none() {
+                if self.whole_word && !is_whole_word(&self.haystack, start, end) {
+                    if matches!(self.matcher, Some(Matcher::Literal(_))) {
+                        // A whole word may start inside the rejected match
+                        self.next = start + self.char_len_at(start);
+                    }
+                    continue;
+                }
+                let start_char = self.char_offset(start);
+                let end_char = start_char + self.haystack[start..end].chars().count();
+                return Some(Range::from_offsets(start_char, end_char));
+            }
+
+            // Map folded characters back to the content
+            let (first, last) = (self.char_offset(start), self.char_offset(end));
+            let (origins, chars) = self.folded.as_ref()?;
+            let total = chars.len();
+            let start = origins.get(first).copied().unwrap_or(total);
+            let end = if last == first {
+                start
+            } else {
+                // Extends over combining marks dropped after the last character
+                origins
+                    .get(last)
+                    .copied()
+                    .unwrap_or(total)
+                    .max(origins[last - 1] + 1)
+            };
+            if self.whole_word {
+                let matched = &chars[start..end];
+                if !(is_word_boundary(chars[..start].last(), matched.first())
+                    && is_word_boundary(matched.last(), chars.get(end)))
+                {
+                    continue;
+                }
+            }
+            return Some(Range::from_offsets(start, end));
+        }
+    }
+
+    /// Finds the next match in the haystack as byte offsets, skipping an
+    /// empty match right where the previous match ended
+    fn find_next(&mut self) -> Option<(usize, usize)> {
+        if self.is_done() {
+            return None;
+        }
+        let found = match self.matcher.as_ref()? {
+            Matcher::Literal(pattern) => self.haystack[self.next..]
+                .find(pattern.as_str())
+                .map(|pos| (self.next + pos, self.next + pos + pattern.len())),
+            Matcher::Regex(regex) => {
+                let mut at = self.next;
+                loop {
+                    match regex.find_at(&self.haystack, at) {
+                        Some(m) if m.is_empty() && Some(m.start()) == self.last_end => {
+                            at = m.start() + self.char_len_at(m.start());
+                            if at > self.haystack.len() {
+                                break None;
+                            }
+                        }
+                        found => break found.map(|m| (m.start(), m.end())),
+                    }
+                }
+            }
+        };
+        if found.is_none() {
+            self.next = self.haystack.len() + 1;
+        }
+        found
+    }
+
+    /// Returns the byte length of the haystack character at `byte`, or 1
+    /// at the end so scanning moves past it
+    fn char_len_at(&self, byte: usize) -> usize {
+        self.haystack[byte..]
+            .chars()
+            .next()
+            .map_or(1, char::len_utf8)
+    }
+
+    /// Converts a byte offset in the haystack to a character offset
+    ///
+    /// Offsets must not go backwards between calls.
+    fn char_offset(&mut self, byte: usize) -> usize {
+        let (cursor_byte, cursor_char) = self.cursor;
+        let offset = cursor_char + self.haystack[cursor_byte..byte].chars().count();
+        self.cursor = (byte, offset);
+        offset
+    }
+}
+/// Returns true if the text between byte offsets `start` and `end` does not
+/// continue a word on either side
+fn is_whole_word(text: &str, start: usize, end: usize) -> bool {
+    let matched = &text[start..end];
+    is_word_boundary(
+        text[..start].chars().next_back().as_ref(),
+        matched.chars().next().as_ref(),
+    ) && is_word_boundary(
+        matched.chars().next_back().as_ref(),
+        text[end..].chars().next().as_ref(),
+    )
+}
+
+/// Returns true unless both characters around a position are word
+/// characters
+fn is_word_boundary(before: Option<&char>, after: Option<&char>) -> bool {
+    !(before.is_some_and(|c| is_word_char(*c)) && after.is_some_and(|c| is_word_char(*c)))
+}
+
+/// Replaces the text of one match, keeping the blocks of the lines around it
+///
+/// Lines the replacement adds take the block of the line the match starts
+/// on, except the line holding the text after the match, which keeps the
+/// block that text was in. When the replacement removes line breaks, the
+/// text after the match joins the match's first line and takes its block,
+/// unless the match started the line; either way the lines after it keep
+/// their own blocks.
+fn replace_match(doc: &mut Document, range: Range, replacement: &str) {
+    let start = range.start_offset();
+    let end = range.end_offset();
+    let inserted = replacement.chars().count();
+    let starts_line = doc.line_start_before(start) == start;
+    let blocks = doc.formats().get_blocks().to_vec();
+    let block_at = |offset: usize| blocks.iter().rev().find(|b| b.start_offset <= offset);
+    doc.replace_range_direct(range, replacement);
+
+    let mut rebuilt: Vec<BlockInfo> = blocks
+        .iter()
+        .filter(|b| b.start_offset <= start)
+        .cloned()
+        .chain(blocks.iter().filter(|b| b.start_offset > end).map(|b| {
+            let mut shifted = b.clone();
+            shifted.start_offset = b.start_offset - end + start + inserted;
+            shifted
+        }))
+        .collect();
+
+    // The line holding the text after the match
+    let tail_start = match replacement.chars().rev().position(|c| c == '\n') {
+        Some(from_end) => Some(start + inserted - from_end),
+        None if starts_line => Some(start),
+        None => None,
+    };
+    if let Some(tail_start) = tail_start
+        && let Some(tail) = block_at(end)
+    {
+        let mut block = tail.clone();
+        block.start_offset = tail_start;
+        rebuilt.retain(|b| b.start_offset != tail_start);
+        rebuilt.push(block);
+    }
+
+    // The line after it keeps its block, which may have started above it
+    let next_line = doc.line_end_from(start + inserted) + 1;
+    if next_line <= doc.get_length()
+        && !rebuilt.iter().any(|b| b.start_offset == next_line)
+        && let Some(block) = block_at(next_line - start - inserted + end)
+    {
+        let mut block = block.clone();
+        block.start_offset = next_line;
+        rebuilt.push(block);
+    }
+
+    rebuilt.sort_by_key(|b| b.start_offset);
+    rebuilt.dedup_by(|next, previous| next.same_attributes(previous));
+    doc.formats_mut().set_blocks(rebuilt);
+}
+
+/// Folds `text` for accent-insensitive matching
+///
+/// Returns the folded text and, for each of its characters, the character
+/// offset in `text` it came from.
+fn fold_accents(text: &str, lowercase: bool) -> (String, Vec<usize>) {
+    let mut folded = String::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(text.len());
+    for (offset, c) in text.chars().enumerate() {
+        if is_combining_mark(c) {
+            continue;
+        }
+        let base = strip_accent(c);
+        if lowercase {
+            for lower in base.to_lowercase().filter(|l| !is_combining_mark(*l)) {
+                folded.push(lower);
+                origins.push(offset);
+            }
+        } else {
+            folded.push(base);
+            origins.push(offset);
+        }
+    }
+    (folded, origins)
+}
+
+/// Checks if `c` is in the Combining Diacritical Marks block
+fn is_combining_mark(c: char) -> bool {
+    ('\u{0300}'..='\u{036F}').contains(&c)
+}
+
+/// Returns the base letter of a precomposed Latin letter, keeping its case
+///
+/// Covers the canonical decompositions in Latin-1 Supplement and Latin
+/// Extended-A. Letters without one, such as "ø", "ł" or "ß", are returned
+/// unchanged.
+fn strip_accent(c: char) -> char {
+    let base = match c {
+        'À'..='Å' | 'à'..='å' | 'Ā'..='ą' => 'a',
+        'Ç' | 'ç' | 'Ć'..='č' => 'c',
+        'Ď' | 'ď' => 'd',
+        'È'..='Ë' | 'è'..='ë' | 'Ē'..='ě' => 'e',
+        'Ĝ'..='ģ' => 'g',
+        'Ĥ' | 'ĥ' => 'h',
+        'Ì'..='Ï' | 'ì'..='ï' | 'Ĩ'..='İ' => 'i',
+        'Ĵ' | 'ĵ' => 'j',
+        'Ķ' | 'ķ' => 'k',
+        'Ĺ'..='ľ' => 'l',
+        'Ñ' | 'ñ' | 'Ń'..='ň' => 'n',
+        'Ò'..='Ö' | 'ò'..='ö' | 'Ō'..='ő' => 'o',
+        'Ŕ'..='ř' => 'r',
+        'Ś'..='š' => 's',
+        'Ţ'..='ť' => 't',
+        'Ù'..='Ü' | 'ù'..='ü' | 'Ũ'..='ų' => 'u',
+        'Ŵ' | 'ŵ' => 'w',
+        'Ý' | 'ý' | 'ÿ' | 'Ŷ'..='Ÿ' => 'y',
+        'Ź'..='ž' => 'z',
+        _ => return c,
+    };
+    if c.is_uppercase() {
+        base.to_ascii_uppercase()
+    } else {
+        base
+    }
 }
 
 #[cfg(test)]
@@ -411,6 +1087,60 @@ mod tests {
         assert_eq!(result.matches[0], Range::from_offsets(6, 8));
     }
 
+    #[test]
+    fn test_find_ignoring_accents() {
+        let doc = Document::from_text("Résumé, resume, RÉSUMÉ");
+        let query = SearchQuery::new("resume".to_string()).ignore_accents(true);
+        let result = doc.find(&query).unwrap();
+        assert_eq!(
+            result.matches,
+            vec![
+                Range::from_offsets(0, 6),
+                Range::from_offsets(8, 14),
+                Range::from_offsets(16, 22)
+            ]
+        );
+
+        // Accents in the pattern are folded too
+        let query = SearchQuery::new("rèsume".to_string()).ignore_accents(true);
+        assert_eq!(doc.find(&query).unwrap().count(), 3);
+
+        // Case sensitivity still applies to the base letters
+        let query = SearchQuery::new("Resume".to_string())
+            .case_sensitive(true)
+            .ignore_accents(true);
+        assert_eq!(
+            doc.find(&query).unwrap().matches,
+            vec![Range::from_offsets(0, 6)]
+        );
+
+        let query = SearchQuery::new("resume".to_string());
+        assert_eq!(doc.find(&query).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_find_ignoring_accents_decomposed_text() {
+        // "e" followed by a combining acute accent
+        let doc = Document::from_text("cafe\u{301} bar");
+        let query = SearchQuery::new("café".to_string()).ignore_accents(true);
+        let result = doc.find(&query).unwrap();
+        assert_eq!(result.matches, vec![Range::from_offsets(0, 5)]);
+
+        let query = SearchQuery::new(r"caf\w\b".to_string())
+            .use_regex(true)
+            .ignore_accents(true);
+        let result = doc.find(&query).unwrap();
+        assert_eq!(result.matches, vec![Range::from_offsets(0, 5)]);
+    }
+
+    #[test]
+    fn test_find_and_replace_ignoring_accents() {
+        let mut doc = Document::from_text("Naïve and naive");
+        let query = SearchQuery::new("naive".to_string()).ignore_accents(true);
+        assert_eq!(doc.find_and_replace(&query, "simple").unwrap(), 2);
+        assert_eq!(doc.get_content(), "simple and simple");
+    }
+
     #[test]
     fn test_find_at_document_boundaries() {
         let doc = Document::from_text("test");
@@ -518,6 +1248,82 @@ mod tests {
         assert_eq!(doc.get_content(), "Hello Rust");
     }
 
+    #[test]
+    fn test_find_and_replace_across_lines_keeps_blocks() {
+        use crate::document::Position;
+        use crate::formatting::BlockType;
+
+        let mut doc = Document::from_text("two\nthree\nthree b\nfour");
+        doc.set_block_type(Range::from_offsets(0, 3), BlockType::BulletList);
+        doc.set_block_type(Range::from_offsets(4, 17), BlockType::NumberedList);
+        doc.set_block_type(Range::from_offsets(18, 22), BlockType::Paragraph);
+        let block_types = |doc: &Document| -> Vec<BlockType> {
+            (0..doc.line_count())
+                .map(|line| {
+                    let pos = doc.line_column_to_position(line, 0).unwrap();
+                    doc.get_block_type_at(pos)
+                })
+                .collect()
+        };
+
+        // Joining lines keeps the block of the lines after the join
+        let query = SearchQuery::new(r"o\nt".to_string()).use_regex(true);
+        doc.find_and_replace(&query, "o t").unwrap();
+        assert_eq!(doc.get_content(), "two three\nthree b\nfour");
+        assert_eq!(
+            block_types(&doc),
+            [
+                BlockType::BulletList,
+                BlockType::NumberedList,
+                BlockType::Paragraph
+            ]
+        );
+        assert_eq!(
+            doc.get_block_type_at(Position::new(6)),
+            BlockType::BulletList
+        );
+
+        // Splitting lines keeps the block of the text after the match
+        doc.undo().unwrap();
+        doc.find_and_replace(&query, "o\n\nt").unwrap();
+        assert_eq!(doc.get_content(), "two\n\nthree\nthree b\nfour");
+        assert_eq!(
+            block_types(&doc),
+            [
+                BlockType::BulletList,
+                BlockType::BulletList,
+                BlockType::NumberedList,
+                BlockType::NumberedList,
+                BlockType::Paragraph
+            ]
+        );
+
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "two\nthree\nthree b\nfour");
+        assert_eq!(
+            block_types(&doc),
+            [
+                BlockType::BulletList,
+                BlockType::NumberedList,
+                BlockType::NumberedList,
+                BlockType::Paragraph
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_and_replace_removing_whole_lines() {
+        use crate::formatting::BlockType;
+
+        let mut doc = Document::from_text("draft\nTitle\nbody");
+        doc.set_block_type(Range::from_offsets(6, 11), BlockType::heading(1));
+        let query = SearchQuery::new(r"(?m)^draft\n".to_string()).use_regex(true);
+
+        doc.find_and_replace(&query, "").unwrap();
+        assert_eq!(doc.get_content(), "Title\nbody");
+        assert_eq!(doc.to_html(), "<h1>Title</h1>\n<p>body</p>\n");
+    }
+
     #[test]
     fn test_find_and_replace_different_lengths() {
         let mut doc = Document::from_text("a b a c a");
@@ -557,4 +1363,138 @@ mod tests {
         assert!(desc.contains("test"));
         assert!(desc.contains("replacement"));
     }
+
+    #[test]
+    fn test_find_whole_words() {
+        let doc = Document::from_text("cat catch concat cat_x a-cat ümcat");
+        let query = SearchQuery::new("cat".to_string()).whole_word(true);
+        assert_eq!(
+            doc.find(&query).unwrap().matches,
+            [Range::from_offsets(0, 3), Range::from_offsets(25, 28)]
+        );
+
+        let regex = SearchQuery::new("ca\\w*".to_string())
+            .use_regex(true)
+            .whole_word(true);
+        assert_eq!(
+            doc.find(&regex).unwrap().matches,
+            [
+                Range::from_offsets(0, 3),
+                Range::from_offsets(4, 9),
+                Range::from_offsets(17, 22),
+                Range::from_offsets(25, 28)
+            ]
+        );
+
+        // A rejected match does not hide a whole word overlapping it
+        let doc = Document::from_text("aa a");
+        let query = SearchQuery::new("a".to_string()).whole_word(true);
+        assert_eq!(
+            doc.find(&query).unwrap().matches,
+            [Range::from_offsets(3, 4)]
+        );
+
+        let doc = Document::from_text("Café cafés");
+        let query = SearchQuery::new("cafe".to_string())
+            .ignore_accents(true)
+            .whole_word(true);
+        assert_eq!(
+            doc.find(&query).unwrap().matches,
+            [Range::from_offsets(0, 4)]
+        );
+    }
+
+    #[test]
+    fn test_incremental_search_matches_find() {
+        let doc = Document::from_text("Résumé resume\nRESUME a  b résumés");
+        let queries = [
+            SearchQuery::new("resume".to_string()),
+            SearchQuery::new("resume".to_string()).ignore_accents(true),
+            SearchQuery::new("r.sum.".to_string())
+                .use_regex(true)
+                .whole_word(true),
+            SearchQuery::new(" *".to_string()).use_regex(true),
+            SearchQuery::new("".to_string()),
+        ];
+        for query in queries {
+            let mut search = doc.start_search(&query).unwrap();
+            let mut matches = Vec::new();
+            while !search.is_done() {
+                let batch = search.next_batch(Some(1), None);
+                assert!(batch.len() <= 1);
+                matches.extend(batch);
+            }
+            assert_eq!(matches, doc.find(&query).unwrap().matches, "{query:?}");
+            assert_eq!(search.match_count(), matches.len());
+        }
+
+        let mut doc = Document::from_text("a a a");
+        let mut search = doc
+            .start_search(&SearchQuery::new("a".to_string()))
+            .unwrap();
+        assert_eq!(search.next_batch(None, Some(Duration::ZERO)).len(), 1);
+        assert!(search.is_current(&doc));
+        doc.insert_text(Position::new(0), "a").unwrap();
+        assert!(!search.is_current(&doc));
+        assert!(
+            doc.start_search(&SearchQuery::new("(".to_string()).use_regex(true))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_find_from_steps_in_both_directions() {
+        let doc = Document::from_text("one two one two one");
+        let query = SearchQuery::new("one".to_string());
+        let find = |from, forward, wrap| {
+            doc.find_from(&query, Position::new(from), forward, wrap)
+                .unwrap()
+        };
+        assert_eq!(find(0, true, false), Some(Range::from_offsets(0, 3)));
+        assert_eq!(find(3, true, false), Some(Range::from_offsets(8, 11)));
+        assert_eq!(find(17, true, false), None);
+        assert_eq!(find(17, true, true), Some(Range::from_offsets(0, 3)));
+        assert_eq!(find(16, false, false), Some(Range::from_offsets(8, 11)));
+        assert_eq!(find(2, false, false), None);
+        assert_eq!(find(2, false, true), Some(Range::from_offsets(16, 19)));
+        assert_eq!(find(99, false, false), Some(Range::from_offsets(16, 19)));
+
+        let missing = SearchQuery::new("six".to_string());
+        assert_eq!(
+            doc.find_from(&missing, Position::new(0), true, true)
+                .unwrap(),
+            None
+        );
+        let invalid = SearchQuery::new("(".to_string()).use_regex(true);
+        assert!(
+            doc.find_from(&invalid, Position::new(0), true, true)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_find_and_replace_in_range() {
+        let mut doc = Document::from_text("one two\none two\none two");
+        let query = SearchQuery::new("^one".to_string()).use_regex(true);
+        let found = doc
+            .find_in_range(&query, Range::from_offsets(8, 30))
+            .unwrap();
+        assert_eq!(found.matches, [Range::from_offsets(8, 11)]);
+
+        // The selection is the range, backwards
+        doc.set_selection(Selection::new(Position::new(23), Position::new(8)));
+        let query = SearchQuery::new("two".to_string());
+        let count = doc
+            .find_and_replace_in_range(&query, "2!", Range::from_offsets(8, 23))
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(doc.get_content(), "one two\none 2!\none 2!");
+        assert_eq!(
+            doc.get_selection(),
+            Selection::new(Position::new(21), Position::new(8))
+        );
+
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "one two\none two\none two");
+    }
 }
@@ -1,7 +1,9 @@
 // Cursor movement functionality
 
-use crate::document::{Document, Position};
+use crate::document::{Document, Position, Range};
+use crate::operations::CommandResult;
 use crate::selection::Selection;
+use crate::utils::segmenter::word_segments;
 
 /// Helper functions for cursor movement
 impl Document {
@@ -24,13 +26,55 @@ impl Document {
         }
     }
 
+    /// Moves the cursor one character to the left or right on screen
+    ///
+    /// In right-to-left text the cursor moves backwards through the text, so
+    /// it follows the display order of lines that mix directions. Past the
+    /// edge of a line it continues on the next line when moving in the
+    /// line's direction, or the previous line otherwise, entering that line
+    /// at the edge it moves away from.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    /// use rte_core::selection::Selection;
+    ///
+    /// let mut doc = Document::from_text("שלום");
+    /// doc.set_selection(Selection::collapsed(Position::new(0)));
+    /// doc.move_cursor_visually(false);
+    /// assert_eq!(doc.get_selection().focus.offset(), 1);
+    /// ```
+    pub fn move_cursor_visually(&mut self, right: bool) {
+        let current_pos = self.selection.focus.offset();
+        let line = self.bidi_line_at(self.selection.focus);
+        let new_pos = match line.move_visually(current_pos, right) {
+            Some(pos) => pos,
+            None => {
+                let line_end = line.start + line.len();
+                let next_line = if right != line.direction.is_rtl() {
+                    (line_end < self.get_length()).then_some(line_end + 1)
+                } else {
+                    line.start.checked_sub(1)
+                };
+                match next_line {
+                    Some(pos) => {
+                        let next = self.bidi_line_at(Position::new(pos));
+                        next.logical_caret(if right { 0 } else { next.len() })
+                    }
+                    None => current_pos,
+                }
+            }
+        };
+        self.selection = Selection::collapsed(Position::new(new_pos));
+    }
+
     /// Moves the cursor up by one line
+    ///
+    /// The cursor keeps its column, or moves to the end of the previous line
+    /// if that is shorter. On the first line it moves to the document start.
     pub fn move_cursor_up(&mut self) {
         let current_pos = self.selection.focus.offset();
-        let content = self.get_content();
-
-        // Find the start of the current line
-        let line_start = self.find_line_start(current_pos, &content);
+        let line_start = self.line_start_before(current_pos);
 
         if line_start == 0 {
             // Already at the first line, move to document start
@@ -38,32 +82,22 @@ impl Document {
             return;
         }
 
-        // Find the start of the previous line
-        let prev_line_start = self.find_line_start(line_start - 1, &content);
-
-        // Calculate column offset in current line
+        let prev_line_start = self.line_start_before(line_start - 1);
+        let prev_line_length = line_start - 1 - prev_line_start;
         let column = current_pos - line_start;
-
-        // Find the end of the previous line
-        let prev_line_end = line_start - 1;
-        let prev_line_length = prev_line_end - prev_line_start;
-
-        // Move to the same column in the previous line, or to the end if the line is shorter
         let new_pos = prev_line_start + column.min(prev_line_length);
         self.selection = Selection::collapsed(Position::new(new_pos));
     }
 
     /// Moves the cursor down by one line
+    ///
+    /// The cursor keeps its column, or moves to the end of the next line if
+    /// that is shorter. On the last line it moves to the document end.
     pub fn move_cursor_down(&mut self) {
         let current_pos = self.selection.focus.offset();
-        let content = self.get_content();
         let max_pos = self.get_length();
-
-        // Find the start of the current line
-        let line_start = self.find_line_start(current_pos, &content);
-
-        // Find the end of the current line
-        let line_end = self.find_line_end(current_pos, &content);
+        let line_start = self.line_start_before(current_pos);
+        let line_end = self.line_end_from(current_pos);
 
         if line_end >= max_pos {
             // Already at the last line, move to document end
@@ -71,34 +105,23 @@ impl Document {
             return;
         }
 
-        // Calculate column offset in current line
-        let column = current_pos - line_start;
-
-        // Find the start of the next line (skip the newline character)
+        // Skip the newline character
         let next_line_start = line_end + 1;
-
-        // Find the end of the next line
-        let next_line_end = self.find_line_end(next_line_start, &content);
-        let next_line_length = next_line_end - next_line_start;
-
-        // Move to the same column in the next line, or to the end if the line is shorter
+        let next_line_length = self.line_end_from(next_line_start) - next_line_start;
+        let column = current_pos - line_start;
         let new_pos = next_line_start + column.min(next_line_length);
         self.selection = Selection::collapsed(Position::new(new_pos));
     }
 
     /// Moves the cursor to the start of the current line
     pub fn move_to_line_start(&mut self) {
-        let current_pos = self.selection.focus.offset();
-        let content = self.get_content();
-        let line_start = self.find_line_start(current_pos, &content);
+        let line_start = self.line_start_before(self.selection.focus.offset());
         self.selection = Selection::collapsed(Position::new(line_start));
     }
 
     /// Moves the cursor to the end of the current line
     pub fn move_to_line_end(&mut self) {
-        let current_pos = self.selection.focus.offset();
-        let content = self.get_content();
-        let line_end = self.find_line_end(current_pos, &content);
+        let line_end = self.line_end_from(self.selection.focus.offset());
         self.selection = Selection::collapsed(Position::new(line_end));
     }
 
@@ -128,34 +151,71 @@ impl Document {
         self.selection = Selection::collapsed(Position::new(new_pos));
     }
 
-    // Helper methods for line and word boundary detection
-
-    /// Finds the start of the line containing the given position
-    fn find_line_start(&self, pos: usize, content: &str) -> usize {
-        let chars: Vec<char> = content.chars().collect();
-        let mut line_start = pos;
-
-        while line_start > 0 && chars[line_start - 1] != '\n' {
-            line_start -= 1;
-        }
-
-        line_start
-    }
-
-    /// Finds the end of the line containing the given position
-    fn find_line_end(&self, pos: usize, content: &str) -> usize {
-        let chars: Vec<char> = content.chars().collect();
-        let mut line_end = pos;
-
-        while line_end < chars.len() && chars[line_end] != '\n' {
-            line_end += 1;
-        }
-
-        line_end
-    }
-
-    /// Finds the next word boundary after the given position
-    fn find_next_word_boundary(&self, pos: usize, content: &str) -> usize {
+    /// Deletes back to the start of the word before each caret, like
+    /// Ctrl+Backspace, as a single undo step
+    ///
+    /// Non-collapsed selections are deleted as they are.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::Document;
+    ///
+    /// let mut doc = Document::from_text("Don't stop");
+    /// doc.move_to_document_end();
+    /// doc.delete_word_backward().unwrap();
+    /// assert_eq!(doc.get_content(), "Don't ");
+    /// doc.delete_word_backward().unwrap();
+    /// assert_eq!(doc.get_content(), "");
+    /// ```
+    pub fn delete_word_backward(&mut self) -> CommandResult<()> {
+        self.delete_word(false)
+    }
+
+    /// Deletes up to the start of the word after each caret, like
+    /// Ctrl+Delete, as a single undo step
+    ///
+    /// Non-collapsed selections are deleted as they are.
+    pub fn delete_word_forward(&mut self) -> CommandResult<()> {
+        self.delete_word(true)
+    }
+
+    fn delete_word(&mut self, forward: bool) -> CommandResult<()> {
+        self.check_editable()?;
+        let content = self.get_content();
+        let ranges: Vec<Range> = self
+            .selection_ranges_descending()
+            .into_iter()
+            .map(|range| {
+                if !range.is_empty() {
+                    range
+                } else if forward {
+                    let end = self.find_next_word_boundary(range.end_offset(), &content);
+                    Range::from_offsets(range.start_offset(), end)
+                } else {
+                    let start = self.find_previous_word_boundary(range.start_offset(), &content);
+                    Range::from_offsets(start, range.end_offset())
+                }
+            })
+            .filter(|range| !range.is_empty())
+            .collect();
+
+        self.begin_batch();
+        let result = ranges
+            .into_iter()
+            .try_for_each(|range| self.delete_range(range));
+        self.end_batch();
+        self.merge_selections();
+        result
+    }
+
+    // Helper methods for word boundary detection
+
+    /// Finds the start of the next word after the given position
+    ///
+    /// Words follow the Unicode word boundary rules, so spaces and
+    /// punctuation are skipped, "don't" is one word and each CJK ideograph
+    /// is a word of its own.
+    pub(crate) fn find_next_word_boundary(&self, pos: usize, content: &str) -> usize {
         let chars: Vec<char> = content.chars().collect();
         let len = chars.len();
 
@@ -163,61 +223,31 @@ impl Document {
             return len;
         }
 
-        let mut i = pos;
-
-        // Skip current word characters
-        while i < len && Self::is_word_char(chars[i]) {
-            i += 1;
-        }
-
-        // Skip non-word characters (whitespace and punctuation)
-        while i < len && !Self::is_word_char(chars[i]) {
-            i += 1;
-        }
-
-        i
+        word_segments(&chars, self.word_segmenter.as_deref())
+            .into_iter()
+            .find(|segment| segment.is_word && segment.start > pos)
+            .map_or(len, |segment| segment.start)
     }
 
-    /// Finds the previous word boundary before the given position
-    fn find_previous_word_boundary(&self, pos: usize, content: &str) -> usize {
-        let chars: Vec<char> = content.chars().collect();
-
+    /// Finds the start of the word before the given position
+    pub(crate) fn find_previous_word_boundary(&self, pos: usize, content: &str) -> usize {
         if pos == 0 {
             return 0;
         }
 
-        let mut i = pos;
-
-        // Move back one position
-        i -= 1;
-
-        // Skip whitespace
-        while i > 0 && Self::is_whitespace(chars[i]) {
-            i -= 1;
-        }
-
-        // Skip word characters to find the start of the word
-        while i > 0 && Self::is_word_char(chars[i - 1]) {
-            i -= 1;
-        }
-
-        i
-    }
-
-    /// Returns true if the character is a word character (alphanumeric or underscore)
-    fn is_word_char(c: char) -> bool {
-        c.is_alphanumeric() || c == '_'
-    }
-
-    /// Returns true if the character is whitespace
-    fn is_whitespace(c: char) -> bool {
-        c.is_whitespace()
+        let chars: Vec<char> = content.chars().collect();
+        word_segments(&chars, self.word_segmenter.as_deref())
+            .into_iter()
+            .rev()
+            .find(|segment| segment.is_word && segment.start < pos)
+            .map_or(0, |segment| segment.start)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::document::StorageBackend;
 
     #[test]
     fn test_move_cursor_left() {
@@ -361,6 +391,31 @@ mod tests {
         assert_eq!(doc.get_selection().focus.offset(), 20); // End of "Short"
     }
 
+    #[test]
+    fn test_line_movement_follows_edits() {
+        for backend in [StorageBackend::GapBuffer, StorageBackend::Rope] {
+            let mut doc = Document::from_text_with_backend("one\ntwo", backend);
+            doc.insert_text(Position::new(3), "\nnew line").unwrap();
+            doc.delete_range(Range::from_offsets(0, 2)).unwrap();
+            // "e\nnew line\ntwo"
+            doc.set_selection(Selection::collapsed(Position::new(6)));
+
+            doc.move_cursor_down();
+            assert_eq!(doc.get_selection().focus.offset(), 14);
+            doc.move_cursor_up();
+            assert_eq!(doc.get_selection().focus.offset(), 5);
+            doc.move_cursor_up();
+            assert_eq!(doc.get_selection().focus.offset(), 1);
+            doc.move_to_line_start();
+            assert_eq!(doc.get_selection().focus.offset(), 0);
+
+            doc.undo().unwrap();
+            doc.set_selection(Selection::collapsed(Position::new(5)));
+            doc.move_to_line_end();
+            assert_eq!(doc.get_selection().focus.offset(), 12);
+        }
+    }
+
     #[test]
     fn test_move_by_word_forward() {
         let mut doc = Document::from_text("Hello World Test");
@@ -426,6 +481,91 @@ mod tests {
         assert_eq!(doc.get_selection().focus.offset(), 8); // After "Hello   "
     }
 
+    #[test]
+    fn test_move_by_word_thai() {
+        // "ภาษาไทย" ("Thai language"); the vowel sign in "ไทย" is not a
+        // word break on its own
+        let mut doc = Document::from_text("ภาษาไทย ดี");
+        doc.move_by_word(true);
+        assert_eq!(doc.get_selection().focus.offset(), 8);
+
+        doc.set_word_segmenter(Some(Box::new(|text: &str| {
+            if text == "ภาษาไทย" {
+                vec![4]
+            } else {
+                Vec::new()
+            }
+        })));
+        doc.set_selection(Selection::collapsed(Position::new(0)));
+        doc.move_by_word(true);
+        assert_eq!(doc.get_selection().focus.offset(), 4);
+        doc.move_by_word(true);
+        assert_eq!(doc.get_selection().focus.offset(), 8);
+
+        doc.set_selection(Selection::collapsed(Position::new(7)));
+        doc.move_by_word(false);
+        assert_eq!(doc.get_selection().focus.offset(), 4);
+        doc.move_by_word(false);
+        assert_eq!(doc.get_selection().focus.offset(), 0);
+    }
+
+    #[test]
+    fn test_move_by_word_unicode() {
+        let mut doc = Document::from_text("Don't stop — 3.14 你好");
+        doc.move_by_word(true);
+        assert_eq!(doc.get_selection().focus.offset(), 6); // Start of "stop"
+        doc.move_by_word(true);
+        assert_eq!(doc.get_selection().focus.offset(), 13); // Start of "3.14"
+        doc.move_by_word(true);
+        assert_eq!(doc.get_selection().focus.offset(), 18); // Start of "你"
+        doc.move_by_word(true);
+        assert_eq!(doc.get_selection().focus.offset(), 19); // Start of "好"
+
+        doc.move_by_word(false);
+        doc.move_by_word(false);
+        assert_eq!(doc.get_selection().focus.offset(), 13);
+    }
+
+    #[test]
+    fn test_delete_word() {
+        let mut doc = Document::from_text("one two, three");
+        doc.set_selection(Selection::collapsed(Position::new(4)));
+        doc.delete_word_forward().unwrap();
+        assert_eq!(doc.get_content(), "one three");
+
+        doc.set_selection(Selection::collapsed(Position::new(9)));
+        doc.add_selection(Selection::collapsed(Position::new(3)));
+        doc.delete_word_backward().unwrap();
+        assert_eq!(doc.get_content(), " ");
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "one three");
+
+        doc.set_read_only(true);
+        assert!(doc.delete_word_backward().is_err());
+    }
+
+    #[test]
+    fn test_move_cursor_visually() {
+        // "ab " followed by a Hebrew word, then a right-to-left line
+        let mut doc = Document::from_text("ab אבג\nשל");
+        doc.set_selection(Selection::collapsed(Position::new(2)));
+        let mut visited = Vec::new();
+        for _ in 0..6 {
+            doc.move_cursor_visually(true);
+            visited.push(doc.get_selection().focus.offset());
+        }
+        // The second line is entered at its left edge, which is its end
+        assert_eq!(visited, vec![6, 5, 4, 3, 9, 8]);
+
+        doc.set_selection(Selection::collapsed(Position::new(7)));
+        doc.move_cursor_visually(false);
+        assert_eq!(doc.get_selection().focus.offset(), 8);
+        doc.move_cursor_visually(false);
+        assert_eq!(doc.get_selection().focus.offset(), 9);
+        doc.move_cursor_visually(false);
+        assert_eq!(doc.get_selection().focus.offset(), 9);
+    }
+
     #[test]
     fn test_cursor_movement_preserves_collapsed_selection() {
         let mut doc = Document::from_text("Hello World");
@@ -0,0 +1,238 @@
+use crate::document::{Document, Position, Range};
+use crate::formatting::BlockType;
+use crate::selection::Selection;
+use crate::utils::segmenter::word_segments;
+
+/// Selection helpers for double/triple-click and semantic expansion
+impl Document {
+    /// Selects the word at `pos`, as on double-click
+    ///
+    /// Between words, the run of spaces or the punctuation character at
+    /// `pos` is selected instead. Scripts written without spaces are split
+    /// with the word segmenter.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    ///
+    /// let mut doc = Document::from_text("Hello world");
+    /// doc.select_word(Position::new(8));
+    /// assert_eq!(doc.get_selected_text(), "world");
+    /// ```
+    pub fn select_word(&mut self, pos: Position) {
+        let range = self.word_range_at(pos.offset());
+        self.set_selection(Selection::new(range.start, range.end));
+    }
+
+    /// Selects the line at `pos`, without its line break, as on triple-click
+    pub fn select_line(&mut self, pos: Position) {
+        let range = self.line_range_at(pos.offset());
+        self.set_selection(Selection::new(range.start, range.end));
+    }
+
+    /// Selects the block at `pos`
+    ///
+    /// Consecutive lines of the same list, block quote or code block form
+    /// one block; paragraphs and headings are blocks of their own.
+    pub fn select_block(&mut self, pos: Position) {
+        let range = self.block_range_at(pos.offset());
+        self.set_selection(Selection::new(range.start, range.end));
+    }
+
+    /// Grows the selection to the enclosing word, line, block or the whole
+    /// document, whichever is the smallest that is larger than it
+    ///
+    /// # Returns
+    /// false if the whole document is already selected
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    /// use rte_core::selection::Selection;
+    ///
+    /// let mut doc = Document::from_text("Hello world\nBye");
+    /// doc.set_selection(Selection::collapsed(Position::new(2)));
+    /// doc.expand_selection();
+    /// assert_eq!(doc.get_selected_text(), "Hello");
+    /// doc.expand_selection();
+    /// assert_eq!(doc.get_selected_text(), "Hello world");
+    /// doc.shrink_selection();
+    /// assert_eq!(doc.get_selected_text(), "Hello");
+    /// ```
+    pub fn expand_selection(&mut self) -> bool {
+        if self.selection_expansion.last().map(|(_, to)| *to) != Some(self.selection) {
+            self.selection_expansion.clear();
+        }
+
+        let current = self.selection.range().normalize();
+        let start = current.start_offset();
+        let candidates = [
+            self.word_range_at(start),
+            self.line_range_at(start),
+            self.block_range_at(start),
+            Range::from_offsets(0, self.get_length()),
+        ];
+        let Some(target) = candidates.into_iter().find(|candidate| {
+            candidate.start_offset() <= start
+                && candidate.end_offset() >= current.end_offset()
+                && candidate.len() > current.len()
+        }) else {
+            return false;
+        };
+
+        let previous = self.selection;
+        self.set_selection(Selection::new(target.start, target.end));
+        self.selection_expansion.push((previous, self.selection));
+        true
+    }
+
+    /// Undoes the last `expand_selection`
+    ///
+    /// # Returns
+    /// false if the selection was not expanded, or has changed since
+    pub fn shrink_selection(&mut self) -> bool {
+        match self.selection_expansion.pop() {
+            Some((previous, expanded)) if expanded == self.selection => {
+                self.set_selection(previous);
+                true
+            }
+            _ => {
+                self.selection_expansion.clear();
+                false
+            }
+        }
+    }
+
+    /// Returns the range of the word, space run or punctuation at `offset`
+    pub(crate) fn word_range_at(&self, offset: usize) -> Range {
+        let chars: Vec<char> = self.get_content().chars().collect();
+        let offset = offset.min(chars.len());
+        let segments = word_segments(&chars, self.word_segmenter.as_deref());
+        let containing = |i: usize| segments.iter().find(|s| s.start <= i && i < s.end);
+
+        let at = containing(offset);
+        let before = offset.checked_sub(1).and_then(containing);
+        let segment = match (at, before) {
+            (Some(s), _) | (_, Some(s)) if s.is_word => Some(s),
+            (Some(s), _) if !matches!(chars[s.start], '\n' | '\r') => Some(s),
+            _ => None,
+        };
+        segment.map_or(Range::from_offsets(offset, offset), |s| {
+            Range::from_offsets(s.start, s.end)
+        })
+    }
+
+    /// Returns the range of the line at `offset`, without its line break
+    fn line_range_at(&self, offset: usize) -> Range {
+        Range::from_offsets(self.line_start_before(offset), self.line_end_from(offset))
+    }
+
+    /// Returns the range of the block at `offset`
+    fn block_range_at(&self, offset: usize) -> Range {
+        let line = self.line_range_at(offset);
+        let block_type = self.get_block_type_at(line.start);
+        if matches!(block_type, BlockType::Paragraph | BlockType::Heading { .. }) {
+            return line;
+        }
+
+        let mut start = line.start_offset();
+        while start > 0 {
+            let previous = self.line_range_at(start - 1);
+            if self.get_block_type_at(previous.start) != block_type {
+                break;
+            }
+            start = previous.start_offset();
+        }
+        let mut end = line.end_offset();
+        while end < self.get_length() {
+            let next = self.line_range_at(end + 1);
+            if self.get_block_type_at(next.start) != block_type {
+                break;
+            }
+            end = next.end_offset();
+        }
+        Range::from_offsets(start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selected(doc: &Document) -> (usize, usize) {
+        let range = doc.get_selection().range().normalize();
+        (range.start_offset(), range.end_offset())
+    }
+
+    #[test]
+    fn test_select_word() {
+        let mut doc = Document::from_text("Hello,  world");
+        doc.select_word(Position::new(2));
+        assert_eq!(selected(&doc), (0, 5));
+        // At the end of a word
+        doc.select_word(Position::new(13));
+        assert_eq!(selected(&doc), (8, 13));
+        doc.select_word(Position::new(5));
+        assert_eq!(selected(&doc), (0, 5));
+        doc.select_word(Position::new(6));
+        assert_eq!(selected(&doc), (6, 8));
+
+        let mut doc = Document::from_text("I can't 你好");
+        doc.select_word(Position::new(4));
+        assert_eq!(selected(&doc), (2, 7));
+        doc.select_word(Position::new(9));
+        assert_eq!(selected(&doc), (9, 10));
+    }
+
+    #[test]
+    fn test_select_word_uses_segmenter() {
+        let mut doc = Document::from_text("ภาษาไทย");
+        doc.select_word(Position::new(5));
+        assert_eq!(selected(&doc), (0, 7));
+
+        doc.set_word_segmenter(Some(Box::new(|_: &str| vec![4])));
+        doc.select_word(Position::new(5));
+        assert_eq!(selected(&doc), (4, 7));
+    }
+
+    #[test]
+    fn test_select_line_and_block() {
+        let mut doc = Document::from_text("Intro\none\ntwo\nEnd");
+        doc.set_block_type(Range::from_offsets(6, 13), BlockType::BulletList);
+        doc.set_block_type(Range::from_offsets(14, 17), BlockType::Paragraph);
+
+        doc.select_line(Position::new(11));
+        assert_eq!(selected(&doc), (10, 13));
+        doc.select_block(Position::new(11));
+        assert_eq!(selected(&doc), (6, 13));
+        doc.select_block(Position::new(2));
+        assert_eq!(selected(&doc), (0, 5));
+    }
+
+    #[test]
+    fn test_expand_and_shrink() {
+        let mut doc = Document::from_text("Intro\none two\nthree\nEnd");
+        doc.set_block_type(Range::from_offsets(6, 19), BlockType::BulletList);
+        doc.set_block_type(Range::from_offsets(20, 23), BlockType::Paragraph);
+        doc.set_selection(Selection::collapsed(Position::new(7)));
+
+        let mut steps = Vec::new();
+        while doc.expand_selection() {
+            steps.push(selected(&doc));
+        }
+        assert_eq!(steps, vec![(6, 9), (6, 13), (6, 19), (0, 23)]);
+
+        assert!(doc.shrink_selection());
+        assert_eq!(selected(&doc), (6, 19));
+        assert!(doc.shrink_selection());
+        assert!(doc.shrink_selection());
+        assert!(doc.shrink_selection());
+        assert_eq!(doc.get_selection(), Selection::collapsed(Position::new(7)));
+        assert!(!doc.shrink_selection());
+
+        // Moving the selection forgets the expansion
+        doc.expand_selection();
+        doc.set_selection(Selection::collapsed(Position::new(0)));
+        assert!(!doc.shrink_selection());
+    }
+}
@@ -10,13 +10,24 @@
 //! - Provide cursor movement operations (word, line, document boundaries)
 //! - Support both forward and backward selections
 //! - Handle collapsed selections (cursor positions)
+//! - Record significant selection jumps for back/forward navigation
+//! - Hold secondary selections and edit at all of them for multi-cursor editing
+//! - Select words, lines and blocks, and grow or shrink the selection between them
 //!
 //! # Key Types
 //!
 //! - `Selection`: Represents a text selection with anchor and focus
 //! - `Cursor`: Provides cursor movement operations
+//! - `NavigationHistory`: Opt-in back/forward history of selection jumps
+//! - `SecondarySelections`: Extra selections kept alongside the primary one
 
 pub mod cursor;
+pub mod expand;
+pub mod multi;
+pub mod navigation;
+
+pub use multi::SecondarySelections;
+pub use navigation::NavigationHistory;
 
 use crate::document::{Position, Range};
 
@@ -0,0 +1,491 @@
+use crate::document::{Document, Position, Range, SOFT_BREAK};
+use crate::formatting::InlineFormat;
+use crate::operations::CommandResult;
+use crate::operations::search::SearchQuery;
+use crate::selection::Selection;
+use crate::utils::segmenter::word_segments;
+
+/// Extra selections kept alongside the primary one, for multi-cursor editing
+///
+/// The primary selection stays in `Document::selection`, so everything that
+/// works on a single selection keeps doing so. Secondary selections follow
+/// edits the same way the primary one does.
+#[derive(Debug, Clone, Default)]
+pub struct SecondarySelections {
+    selections: Vec<Selection>,
+}
+
+impl SecondarySelections {
+    /// Creates an empty set of secondary selections
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of secondary selections
+    pub fn len(&self) -> usize {
+        self.selections.len()
+    }
+
+    /// Returns true if there are no secondary selections
+    pub fn is_empty(&self) -> bool {
+        self.selections.is_empty()
+    }
+
+    /// Iterates over the secondary selections in document order
+    pub fn iter(&self) -> impl Iterator<Item = &Selection> {
+        self.selections.iter()
+    }
+
+    /// Removes all secondary selections
+    pub fn clear(&mut self) {
+        self.selections.clear();
+    }
+
+    /// Merges overlapping or touching selections into `primary` and each
+    /// other, returning the merged primary
+    ///
+    /// A secondary that merges into the primary is absorbed by it; other
+    /// merged selections keep the direction of the earlier one.
+    pub(crate) fn merge_into(&mut self, primary: Selection) -> Selection {
+        let mut all: Vec<(Selection, bool)> = self
+            .selections
+            .drain(..)
+            .map(|s| (s, false))
+            .chain(std::iter::once((primary, true)))
+            .collect();
+        all.sort_by_key(|(s, is_primary)| (s.start().offset(), !is_primary));
+
+        let mut merged: Vec<(Selection, bool)> = Vec::with_capacity(all.len());
+        for (selection, is_primary) in all {
+            match merged.last_mut() {
+                Some((last, last_primary)) if selection.start() <= last.end() => {
+                    let start = last.start().min(selection.start());
+                    let end = last.end().max(selection.end());
+                    let forward = if is_primary {
+                        selection.is_forward()
+                    } else {
+                        last.is_forward()
+                    };
+                    *last = if forward {
+                        Selection::new(start, end)
+                    } else {
+                        Selection::new(end, start)
+                    };
+                    *last_primary |= is_primary;
+                }
+                _ => merged.push((selection, is_primary)),
+            }
+        }
+
+        let mut primary = primary;
+        for (selection, is_primary) in merged {
+            if is_primary {
+                primary = selection;
+            } else {
+                self.selections.push(selection);
+            }
+        }
+        primary
+    }
+
+    /// Shifts secondary selections after text insertion
+    pub(crate) fn adjust_for_insert(&mut self, pos: Position, length: usize) {
+        for selection in &mut self.selections {
+            *selection = selection.adjust_for_insert(pos, length);
+        }
+    }
+
+    /// Shifts secondary selections after text deletion
+    pub(crate) fn adjust_for_delete(&mut self, range: Range) {
+        for selection in &mut self.selections {
+            *selection = selection.adjust_for_delete(range);
+        }
+    }
+}
+
+impl Document {
+    /// Adds a secondary selection, such as a caret placed with Alt+Click
+    ///
+    /// Selections that overlap or touch are merged, so adding one inside the
+    /// primary selection has no effect.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    /// use rte_core::selection::Selection;
+    ///
+    /// let mut doc = Document::from_text("one\ntwo");
+    /// doc.add_selection(Selection::collapsed(Position::new(4)));
+    /// doc.insert_text_at_selections("- ").unwrap();
+    /// assert_eq!(doc.get_content(), "- one\n- two");
+    /// ```
+    pub fn add_selection(&mut self, selection: Selection) {
+        let selection = selection.normalize(self.get_length());
+        self.secondary_selections.selections.push(selection);
+        self.merge_selections();
+    }
+
+    /// Selects every occurrence of the selected text, or of the word at the
+    /// caret, for editing them all at once
+    ///
+    /// Occurrences are matched case-sensitively. When there is only a caret,
+    /// only whole words match, so "cat" does not select the start of "catch".
+    /// The selection the search started from stays the primary one.
+    ///
+    /// # Returns
+    /// The number of selections, or 0 if the caret is not on a word and the
+    /// selection is left as it was
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    /// use rte_core::selection::Selection;
+    ///
+    /// let mut doc = Document::from_text("cat catch cat");
+    /// doc.set_selection(Selection::collapsed(Position::new(1)));
+    /// assert_eq!(doc.select_all_occurrences(), 2);
+    /// doc.insert_text_at_selections("dog").unwrap();
+    /// assert_eq!(doc.get_content(), "dog catch dog");
+    /// ```
+    pub fn select_all_occurrences(&mut self) -> usize {
+        let chars: Vec<char> = self.get_content().chars().collect();
+        let segments = word_segments(&chars, self.word_segmenter.as_deref());
+        let selected = self.selection.range().normalize();
+        let whole_words = selected.is_empty();
+        let target = if whole_words {
+            let word = self.word_range_at(selected.start_offset());
+            let is_word = segments
+                .iter()
+                .any(|s| s.is_word && s.start == word.start_offset() && s.end == word.end_offset());
+            if !is_word {
+                return 0;
+            }
+            word
+        } else {
+            selected
+        };
+
+        let query = SearchQuery::new(self.get_text_in_range(target)).case_sensitive(true);
+        let matches = self
+            .find(&query)
+            .map(|result| result.matches)
+            .unwrap_or_default();
+        let is_whole_word = |range: &Range| {
+            segments.iter().any(|s| s.start == range.start_offset())
+                && segments.iter().any(|s| s.end == range.end_offset())
+        };
+
+        self.secondary_selections.clear();
+        self.selection = if whole_words || self.selection.is_forward() {
+            Selection::new(target.start, target.end)
+        } else {
+            Selection::new(target.end, target.start)
+        };
+        for range in matches {
+            if range != target && (!whole_words || is_whole_word(&range)) {
+                let selection = Selection::new(range.start, range.end);
+                self.secondary_selections.selections.push(selection);
+            }
+        }
+        self.merge_selections();
+        self.get_selections().len()
+    }
+
+    /// Removes all secondary selections, keeping the primary one
+    pub fn clear_secondary_selections(&mut self) {
+        self.secondary_selections.clear();
+    }
+
+    /// Returns true if there are secondary selections
+    pub fn has_multiple_selections(&self) -> bool {
+        !self.secondary_selections.is_empty()
+    }
+
+    /// Gets all selections, primary first, then the secondary ones in
+    /// document order
+    pub fn get_selections(&self) -> Vec<Selection> {
+        std::iter::once(self.selection)
+            .chain(self.secondary_selections.iter().copied())
+            .collect()
+    }
+
+    /// Gets the secondary selections
+    pub fn secondary_selections(&self) -> &SecondarySelections {
+        &self.secondary_selections
+    }
+
+    /// Replaces every selection with `text`, or inserts it at every caret,
+    /// as a single undo step
+    ///
+    /// Each selection ends up collapsed after its inserted text.
+    pub fn insert_text_at_selections(&mut self, text: &str) -> CommandResult<()> {
+        self.check_editable()?;
+        let text = self.control_char_policy().apply(text).into_owned();
+        let ranges = self.selection_ranges_descending();
+        let removed: usize = ranges.iter().map(|r| r.len()).sum();
+        self.check_max_length(text.chars().count() * ranges.len(), removed)?;
+
+        self.begin_batch();
+        let result = ranges.into_iter().try_for_each(|range| {
+            if range.is_empty() {
+                self.insert_text(range.start, &text)
+            } else {
+                self.replace_range(range, &text)
+            }
+        });
+        self.end_batch();
+        self.merge_selections();
+        result
+    }
+
+    /// Inserts a soft line break at every selection, replacing selected
+    /// text, as a single undo step
+    ///
+    /// Unlike a newline, a soft break does not end the block, so the line
+    /// keeps its block type; it exports as `<br>` in HTML and as a hard line
+    /// break in markdown.
+    pub fn insert_soft_break(&mut self) -> CommandResult<()> {
+        self.insert_text_at_selections(&SOFT_BREAK.to_string())
+    }
+
+    /// Deletes the content of every selection as a single undo step
+    ///
+    /// Collapsed carets delete the character before them, or after them when
+    /// `forward` is true, like Backspace and Delete.
+    pub fn delete_at_selections(&mut self, forward: bool) -> CommandResult<()> {
+        self.check_editable()?;
+        let length = self.get_length();
+        let ranges: Vec<Range> = self
+            .selection_ranges_descending()
+            .into_iter()
+            .filter_map(|range| {
+                if !range.is_empty() {
+                    Some(range)
+                } else if forward && range.end_offset() < length {
+                    Some(Range::from_offsets(
+                        range.start_offset(),
+                        range.end_offset() + 1,
+                    ))
+                } else if !forward && range.start_offset() > 0 {
+                    Some(Range::from_offsets(
+                        range.start_offset() - 1,
+                        range.end_offset(),
+                    ))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        self.begin_batch();
+        let result = ranges
+            .into_iter()
+            .try_for_each(|range| self.delete_range(range));
+        self.end_batch();
+        self.merge_selections();
+        result
+    }
+
+    /// Applies a format to every non-collapsed selection as a single undo step
+    pub fn apply_format_to_selections(&mut self, format: InlineFormat) -> CommandResult<()> {
+        let formats = self
+            .selection_ranges_descending()
+            .into_iter()
+            .rev()
+            .filter(|range| !range.is_empty())
+            .map(|range| (range, format.clone()))
+            .collect();
+        self.apply_formats(formats)
+    }
+
+    /// Removes a format from every non-collapsed selection as a single undo step
+    pub fn remove_format_from_selections(&mut self, format: &InlineFormat) -> CommandResult<()> {
+        self.check_editable()?;
+        let ranges = self.selection_ranges_descending();
+        self.begin_batch();
+        for range in ranges.into_iter().filter(|range| !range.is_empty()) {
+            self.remove_format(range, format);
+        }
+        self.end_batch();
+        Ok(())
+    }
+
+    /// Replaces all selections, merging any that overlap or touch
+    pub(crate) fn set_selections(
+        &mut self,
+        primary: Selection,
+        secondary: impl IntoIterator<Item = Selection>,
+    ) {
+        self.selection = primary;
+        self.secondary_selections.selections = secondary.into_iter().collect();
+        self.merge_selections();
+    }
+
+    /// Merges overlapping or touching selections, keeping the primary one
+    pub(crate) fn merge_selections(&mut self) {
+        self.selection = self.secondary_selections.merge_into(self.selection);
+    }
+
+    /// Returns the normalized range of every selection, last one first, so
+    /// editing them in order doesn't shift the ones still to be edited
+    pub(crate) fn selection_ranges_descending(&mut self) -> Vec<Range> {
+        self.merge_selections();
+        let mut ranges: Vec<Range> = self
+            .get_selections()
+            .iter()
+            .map(|s| s.range().normalize())
+            .collect();
+        ranges.sort_by_key(|range| std::cmp::Reverse(range.start_offset()));
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::BlockType;
+
+    fn cursor(offset: usize) -> Selection {
+        Selection::collapsed(Position::new(offset))
+    }
+
+    #[test]
+    fn test_add_selection_merges_overlaps() {
+        let mut doc = Document::from_text("Hello world");
+        doc.set_selection(Selection::new(Position::new(0), Position::new(5)));
+        doc.add_selection(cursor(3));
+        assert!(!doc.has_multiple_selections());
+
+        doc.add_selection(cursor(8));
+        doc.add_selection(Selection::new(Position::new(11), Position::new(7)));
+        doc.add_selection(cursor(2));
+        assert_eq!(
+            doc.get_selections(),
+            vec![
+                Selection::new(Position::new(0), Position::new(5)),
+                Selection::new(Position::new(11), Position::new(7)),
+            ]
+        );
+
+        doc.clear_secondary_selections();
+        assert_eq!(doc.get_selections().len(), 1);
+    }
+
+    #[test]
+    fn test_insert_at_selections() {
+        let mut doc = Document::from_text("a b c");
+        doc.set_selection(cursor(0));
+        doc.add_selection(cursor(2));
+        doc.add_selection(Selection::new(Position::new(4), Position::new(5)));
+
+        doc.insert_text_at_selections("xy").unwrap();
+        assert_eq!(doc.get_content(), "xya xyb xy");
+        assert_eq!(doc.get_selections(), vec![cursor(2), cursor(6), cursor(10)]);
+
+        // All carets were edited in one undo step
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "a b c");
+    }
+
+    #[test]
+    fn test_insert_soft_break() {
+        let mut doc = Document::from_text("ab\ncd");
+        doc.set_block_type(Range::from_offsets(0, 2), BlockType::heading(1));
+        doc.set_selection(cursor(1));
+        doc.add_selection(cursor(4));
+
+        doc.insert_soft_break().unwrap();
+        assert_eq!(doc.get_content(), "a\u{2028}b\nc\u{2028}d");
+        assert_eq!(doc.line_count(), 2);
+        assert_eq!(
+            doc.get_block_type_at(Position::new(2)),
+            BlockType::heading(1)
+        );
+        assert_eq!(doc.get_selections(), vec![cursor(2), cursor(6)]);
+    }
+
+    #[test]
+    fn test_delete_at_selections() {
+        let mut doc = Document::from_text("abcdef");
+        doc.set_selection(cursor(2));
+        doc.add_selection(cursor(3));
+        doc.add_selection(Selection::new(Position::new(5), Position::new(6)));
+
+        doc.delete_at_selections(false).unwrap();
+        assert_eq!(doc.get_content(), "ade");
+        // The two carets ended up at the same place and were merged
+        assert_eq!(doc.get_selections(), vec![cursor(1), cursor(3)]);
+
+        doc.delete_at_selections(true).unwrap();
+        assert_eq!(doc.get_content(), "ae");
+        doc.undo().unwrap();
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "abcdef");
+    }
+
+    #[test]
+    fn test_format_at_selections() {
+        let mut doc = Document::from_text("one two three");
+        doc.set_selection(Selection::new(Position::new(0), Position::new(3)));
+        doc.add_selection(Selection::new(Position::new(8), Position::new(13)));
+        doc.add_selection(cursor(5));
+
+        doc.apply_format_to_selections(InlineFormat::Bold).unwrap();
+        assert!(
+            doc.get_formats_at(Position::new(1))
+                .contains(&InlineFormat::Bold)
+        );
+        assert!(
+            doc.get_formats_at(Position::new(9))
+                .contains(&InlineFormat::Bold)
+        );
+        assert!(doc.get_formats_at(Position::new(5)).is_empty());
+
+        doc.remove_format_from_selections(&InlineFormat::Bold)
+            .unwrap();
+        assert!(doc.get_formats_at(Position::new(1)).is_empty());
+        assert!(doc.get_formats_at(Position::new(9)).is_empty());
+    }
+
+    #[test]
+    fn test_select_all_occurrences() {
+        let mut doc = Document::from_text("Cat cat catch cat.");
+        doc.set_selection(cursor(5));
+        assert_eq!(doc.select_all_occurrences(), 2);
+        assert_eq!(
+            doc.get_selections(),
+            vec![
+                Selection::new(Position::new(4), Position::new(7)),
+                Selection::new(Position::new(14), Position::new(17)),
+            ]
+        );
+
+        // A selection matches anywhere, keeping its direction
+        doc.set_selection(Selection::new(Position::new(7), Position::new(4)));
+        assert_eq!(doc.select_all_occurrences(), 3);
+        assert_eq!(
+            doc.get_selection(),
+            Selection::new(Position::new(7), Position::new(4))
+        );
+        assert_eq!(doc.get_selections()[2].start().offset(), 14);
+
+        // Not on a word
+        doc.clear_secondary_selections();
+        doc.set_selection(cursor(18));
+        doc.add_selection(cursor(8));
+        assert_eq!(doc.select_all_occurrences(), 0);
+        assert_eq!(doc.get_selections().len(), 2);
+    }
+
+    #[test]
+    fn test_secondary_selections_follow_edits() {
+        let mut doc = Document::from_text("Hello world");
+        doc.add_selection(cursor(6));
+        doc.insert_text(Position::new(0), ">> ").unwrap();
+        assert_eq!(doc.get_selections(), vec![cursor(3), cursor(9)]);
+
+        doc.set_read_only(true);
+        assert!(doc.insert_text_at_selections("x").is_err());
+        assert!(doc.delete_at_selections(false).is_err());
+    }
+}
@@ -0,0 +1,364 @@
+use crate::document::{Document, Position, Range};
+use crate::operations::search::{SearchQuery, SearchState};
+use crate::selection::Selection;
+
+/// Default number of jumps kept by the navigation history
+pub const DEFAULT_NAVIGATION_LIMIT: usize = 50;
+
+/// Records significant selection jumps so they can be retraced
+///
+/// This is separate from the edit history: navigating never changes the
+/// document and undo never moves through jumps.
+#[derive(Debug, Clone)]
+pub struct NavigationHistory {
+    back: Vec<Selection>,
+    forward: Vec<Selection>,
+    limit: usize,
+}
+
+impl NavigationHistory {
+    /// Creates an empty history that keeps at most `limit` jumps
+    pub fn new(limit: usize) -> Self {
+        Self {
+            back: Vec::new(),
+            forward: Vec::new(),
+            limit: limit.max(1),
+        }
+    }
+
+    /// Records a jump away from `from`, dropping any forward entries
+    pub fn record(&mut self, from: Selection) {
+        self.forward.clear();
+        if self.back.last() != Some(&from) {
+            self.back.push(from);
+        }
+        if self.back.len() > self.limit {
+            self.back.remove(0);
+        }
+    }
+
+    /// Returns the selection to go back to from `current`, if any
+    pub fn back(&mut self, current: Selection) -> Option<Selection> {
+        let target = self.back.pop()?;
+        self.forward.push(current);
+        Some(target)
+    }
+
+    /// Returns the selection to go forward to from `current`, if any
+    pub fn forward(&mut self, current: Selection) -> Option<Selection> {
+        let target = self.forward.pop()?;
+        self.back.push(current);
+        Some(target)
+    }
+
+    /// Returns true if there is a jump to go back to
+    pub fn can_go_back(&self) -> bool {
+        !self.back.is_empty()
+    }
+
+    /// Returns true if there is a jump to go forward to
+    pub fn can_go_forward(&self) -> bool {
+        !self.forward.is_empty()
+    }
+
+    /// Removes all recorded jumps
+    pub fn clear(&mut self) {
+        self.back.clear();
+        self.forward.clear();
+    }
+
+    /// Shifts recorded selections after text insertion
+    pub(crate) fn adjust_for_insert(&mut self, pos: Position, length: usize) {
+        for selection in self.back.iter_mut().chain(self.forward.iter_mut()) {
+            *selection = selection.adjust_for_insert(pos, length);
+        }
+    }
+
+    /// Shifts recorded selections after text deletion
+    pub(crate) fn adjust_for_delete(&mut self, range: Range) {
+        for selection in self.back.iter_mut().chain(self.forward.iter_mut()) {
+            *selection = selection.adjust_for_delete(range);
+        }
+    }
+}
+
+impl Document {
+    /// Starts recording selection jumps, keeping at most `limit` of them
+    ///
+    /// Has no effect if recording is already enabled.
+    pub fn enable_navigation_history(&mut self, limit: usize) {
+        if self.navigation.is_none() {
+            self.navigation = Some(NavigationHistory::new(limit));
+        }
+    }
+
+    /// Stops recording selection jumps and forgets the recorded ones
+    pub fn disable_navigation_history(&mut self) {
+        self.navigation = None;
+    }
+
+    /// Returns true if selection jumps are being recorded
+    pub fn is_navigation_history_enabled(&self) -> bool {
+        self.navigation.is_some()
+    }
+
+    /// Moves the selection as a significant jump, such as going to a heading
+    ///
+    /// Behaves like `set_selection`, and also records the previous selection
+    /// in the navigation history when it is enabled.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    /// use rte_core::selection::Selection;
+    ///
+    /// let mut doc = Document::from_text("Intro\nDetails");
+    /// doc.enable_navigation_history(50);
+    /// doc.jump_to(Selection::collapsed(Position::new(6)));
+    /// assert!(doc.navigate_back());
+    /// assert_eq!(doc.get_selection(), Selection::collapsed(Position::new(0)));
+    /// assert!(doc.navigate_forward());
+    /// assert_eq!(doc.get_selection(), Selection::collapsed(Position::new(6)));
+    /// ```
+    pub fn jump_to(&mut self, selection: Selection) {
+        let target = selection.normalize(self.get_length());
+        if target == self.selection {
+            return;
+        }
+        if let Some(navigation) = &mut self.navigation {
+            navigation.record(self.selection);
+        }
+        self.selection = target;
+    }
+
+    /// Selects the next match of `query` after the current selection,
+    /// wrapping around to the start of the document
+    ///
+    /// The move is recorded as a jump in the navigation history, and the
+    /// query and match become the document's search state.
+    ///
+    /// # Returns
+    /// The selected match, or None if the query matches nothing
+    ///
+    /// # Errors
+    /// Returns an error if the regex pattern is invalid
+    pub fn find_next(&mut self, query: &SearchQuery) -> Result<Option<Range>, String> {
+        self.find_adjacent(query, true)
+    }
+
+    /// Selects the last match of `query` before the current selection,
+    /// wrapping around to the end of the document
+    ///
+    /// Behaves like `find_next` otherwise.
+    pub fn find_previous(&mut self, query: &SearchQuery) -> Result<Option<Range>, String> {
+        self.find_adjacent(query, false)
+    }
+
+    fn find_adjacent(
+        &mut self,
+        query: &SearchQuery,
+        forward: bool,
+    ) -> Result<Option<Range>, String> {
+        let matches = self.find(query)?.matches;
+        let current = self.selection.range().normalize();
+        let index = if forward {
+            matches
+                .iter()
+                .position(|m| m.start_offset() >= current.end_offset())
+                .or((!matches.is_empty()).then_some(0))
+        } else {
+            matches
+                .iter()
+                .rposition(|m| m.end_offset() <= current.start_offset())
+                .or(matches.len().checked_sub(1))
+        };
+
+        self.search_state = SearchState {
+            query: query.clone(),
+            current_match: index,
+        };
+        let Some(found) = index.map(|i| matches[i]) else {
+            return Ok(None);
+        };
+        self.jump_to(Selection::new(found.start, found.end));
+        Ok(Some(found))
+    }
+
+    /// Moves the selection back to where it was before the last jump
+    ///
+    /// # Returns
+    /// false if navigation history is disabled or there is nothing to go back to
+    pub fn navigate_back(&mut self) -> bool {
+        let current = self.selection;
+        let target = self.navigation.as_mut().and_then(|n| n.back(current));
+        self.move_to_recorded(target)
+    }
+
+    /// Re-does the jump undone by the last `navigate_back`
+    ///
+    /// # Returns
+    /// false if navigation history is disabled or there is nothing to go forward to
+    pub fn navigate_forward(&mut self) -> bool {
+        let current = self.selection;
+        let target = self.navigation.as_mut().and_then(|n| n.forward(current));
+        self.move_to_recorded(target)
+    }
+
+    /// Returns the navigation history, if enabled
+    pub fn navigation_history(&self) -> Option<&NavigationHistory> {
+        self.navigation.as_ref()
+    }
+
+    fn move_to_recorded(&mut self, target: Option<Selection>) -> bool {
+        match target {
+            Some(selection) => {
+                self.selection = selection.normalize(self.get_length());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(offset: usize) -> Selection {
+        Selection::collapsed(Position::new(offset))
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        let mut doc = Document::from_text("Hello world");
+        doc.jump_to(cursor(6));
+        assert_eq!(doc.get_selection(), cursor(6));
+        assert!(!doc.navigate_back());
+        assert_eq!(doc.get_selection(), cursor(6));
+    }
+
+    #[test]
+    fn test_back_and_forward() {
+        let mut doc = Document::from_text("one two three");
+        doc.enable_navigation_history(DEFAULT_NAVIGATION_LIMIT);
+        doc.jump_to(cursor(4));
+        doc.jump_to(cursor(8));
+
+        assert!(doc.navigate_back());
+        assert_eq!(doc.get_selection(), cursor(4));
+        assert!(doc.navigate_back());
+        assert_eq!(doc.get_selection(), cursor(0));
+        assert!(!doc.navigate_back());
+
+        assert!(doc.navigate_forward());
+        assert_eq!(doc.get_selection(), cursor(4));
+
+        // A new jump drops the forward entries
+        doc.jump_to(cursor(13));
+        assert!(!doc.navigate_forward());
+    }
+
+    #[test]
+    fn test_plain_selection_changes_are_not_recorded() {
+        let mut doc = Document::from_text("one two three");
+        doc.enable_navigation_history(DEFAULT_NAVIGATION_LIMIT);
+        doc.set_selection(cursor(3));
+        doc.jump_to(cursor(8));
+        doc.set_selection(cursor(9));
+
+        assert!(doc.navigate_back());
+        assert_eq!(doc.get_selection(), cursor(3));
+        assert!(doc.navigate_forward());
+        assert_eq!(doc.get_selection(), cursor(9));
+    }
+
+    #[test]
+    fn test_recorded_jumps_follow_edits_and_stay_out_of_undo() {
+        let mut doc = Document::from_text("alpha beta");
+        doc.enable_navigation_history(DEFAULT_NAVIGATION_LIMIT);
+        doc.set_selection(cursor(6));
+        doc.jump_to(cursor(0));
+        doc.insert_text(Position::new(0), ">> ").unwrap();
+
+        assert!(doc.navigate_back());
+        assert_eq!(doc.get_selection(), cursor(9));
+
+        // Undo reverts the edit, not the navigation
+        doc.undo().unwrap();
+        assert_eq!(doc.get_content(), "alpha beta");
+        assert!(doc.navigate_forward());
+    }
+
+    #[test]
+    fn test_find_next_wraps_and_records() {
+        let mut doc = Document::from_text("cat dog cat");
+        doc.enable_navigation_history(DEFAULT_NAVIGATION_LIMIT);
+        let query = SearchQuery::new("cat".to_string());
+
+        assert_eq!(
+            doc.find_next(&query).unwrap(),
+            Some(Range::from_offsets(0, 3))
+        );
+        assert_eq!(
+            doc.find_next(&query).unwrap(),
+            Some(Range::from_offsets(8, 11))
+        );
+        assert_eq!(
+            doc.find_next(&query).unwrap(),
+            Some(Range::from_offsets(0, 3))
+        );
+        assert_eq!(
+            doc.find_next(&SearchQuery::new("bird".to_string()))
+                .unwrap(),
+            None
+        );
+
+        assert!(doc.navigate_back());
+        assert_eq!(doc.get_selection().range(), Range::from_offsets(8, 11));
+    }
+
+    #[test]
+    fn test_search_state_is_kept() {
+        let mut doc = Document::from_text("Cat dog cat cat");
+        let query = SearchQuery::new("cat".to_string()).case_sensitive(true);
+        assert_eq!(
+            doc.find_previous(&query).unwrap(),
+            Some(Range::from_offsets(12, 15))
+        );
+        assert_eq!(doc.search_state().current_match, Some(1));
+        assert_eq!(
+            doc.search_previous().unwrap(),
+            Some(Range::from_offsets(8, 11))
+        );
+        assert_eq!(
+            doc.search_previous().unwrap(),
+            Some(Range::from_offsets(12, 15))
+        );
+        assert_eq!(doc.search_next().unwrap(), Some(Range::from_offsets(8, 11)));
+        assert_eq!(doc.search_state().query, query);
+
+        // Toggling case sensitivity off also finds "Cat"
+        let mut state = doc.search_state().clone();
+        state.query.case_sensitive = false;
+        state.current_match = None;
+        doc.set_search_state(state);
+        doc.set_selection(cursor(15));
+        assert_eq!(doc.search_next().unwrap(), Some(Range::from_offsets(0, 3)));
+        assert_eq!(doc.search_state().current_match, Some(0));
+
+        doc.set_search_state(SearchState::new(SearchQuery::new("bird".to_string())));
+        assert_eq!(doc.search_next().unwrap(), None);
+        assert_eq!(doc.search_state().current_match, None);
+    }
+
+    #[test]
+    fn test_limit_drops_oldest_jumps() {
+        let mut history = NavigationHistory::new(2);
+        for offset in 1..=3 {
+            history.record(cursor(offset));
+        }
+        assert_eq!(history.back(cursor(9)), Some(cursor(3)));
+        assert_eq!(history.back(cursor(3)), Some(cursor(2)));
+        assert_eq!(history.back(cursor(2)), None);
+    }
+}
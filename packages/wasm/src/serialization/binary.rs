@@ -0,0 +1,695 @@
+//! Compact binary serialization
+//!
+//! Stores the same content as the JSON format in a length-prefixed layout
+//! that is much faster to write and read for large documents. Lengths and
+//! offsets are LEB128 varints, and offsets are stored relative to the
+//! previous entry so they stay small.
+//!
+//! # Layout (version 4)
+//!
+//! ```text
+//! magic       "RTEB"
+//! version     u8
+//! text        string
+//! formats     count, then each distinct inline format as a JSON string
+//! runs        count, then per run: gap from the previous run's end,
+//!             length, format count and format table indices
+//! block types count, then each distinct block (attributes without the
+//!             start) as a JSON string
+//! blocks      count, then per block: gap from the previous start, table index
+//! images      count, then per image: offset and image as a JSON string
+//! annotations count, then per annotation: start, length and annotation
+//!             as a JSON string
+//! bookmarks   count, then per bookmark: id and offset
+//! selections  count, then per selection: anchor and focus
+//! metadata    0, or 1 followed by the metadata as a JSON string (since 2)
+//! unchecked   count, then per no-spellcheck range: start and length
+//!             (since 3)
+//! decorations count, then per decoration: start, length and decoration
+//!             as a JSON string (since 4)
+//! extensions  count, then per extension: name and value as a JSON string
+//!             (since 4)
+//! required    count, then each required section name (since 4)
+//! ```
+//!
+//! Strings are a byte length followed by UTF-8. The format and block tables
+//! hold JSON, so new format or block variants need no new layout version;
+//! there are only ever a few distinct entries. Sections added by a later
+//! version come last, so older layouts are read by stopping early.
+
+use crate::document::Document;
+use crate::serialization::json::{
+    JSON_VERSION, JsonError, SerializableAnnotation, SerializableBlock, SerializableDecoration,
+    SerializableDocument, SerializableFormatRun, SerializableImage, SerializableRange,
+    SerializableSelection,
+};
+use crate::serialization::varint::{self, VarintError};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Bytes every binary document starts with
+pub const BINARY_MAGIC: &[u8; 4] = b"RTEB";
+
+/// Layout version written by `Document::to_binary`
+pub const BINARY_VERSION: u8 = 4;
+
+/// Errors that can occur during binary serialization/deserialization
+#[derive(Debug, Error)]
+pub enum BinaryError {
+    #[error("Not a binary document")]
+    InvalidMagic,
+
+    #[error("Unsupported binary version: {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Unexpected end of data at byte {0}")]
+    UnexpectedEnd(usize),
+
+    #[error("Invalid binary data at byte {offset}: {reason}")]
+    InvalidData { offset: usize, reason: String },
+
+    #[error("Table entry encoding error: {0}")]
+    Encoding(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Content(#[from] JsonError),
+}
+
+impl Document {
+    /// Serializes the document to the compact binary format
+    ///
+    /// Holds everything `to_json` does; with `include_selection`, the
+    /// selections are recorded too.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a format or block cannot be encoded.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rte_core::document::{Document, Range};
+    /// use rte_core::formatting::InlineFormat;
+    ///
+    /// let mut doc = Document::from_text("Hello World");
+    /// doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+    ///
+    /// let bytes = doc.to_binary(false).unwrap();
+    /// let restored = Document::from_binary(&bytes).unwrap();
+    /// assert_eq!(restored.to_json().unwrap(), doc.to_json().unwrap());
+    /// ```
+    pub fn to_binary(&self, include_selection: bool) -> Result<Vec<u8>, BinaryError> {
+        let doc = self.to_serializable(include_selection);
+        let mut writer = Writer::default();
+        writer.bytes.extend_from_slice(BINARY_MAGIC);
+        writer.bytes.push(BINARY_VERSION);
+        writer.str(&doc.text);
+
+        let mut formats = Table::default();
+        let runs: Vec<(usize, usize, Vec<usize>)> = doc
+            .formats
+            .iter()
+            .map(|run| {
+                let indices = run
+                    .formats
+                    .iter()
+                    .map(|format| formats.index(format))
+                    .collect::<Result<_, _>>()?;
+                Ok((run.start, run.end, indices))
+            })
+            .collect::<Result<_, BinaryError>>()?;
+        writer.table(&formats);
+        writer.usize(runs.len());
+        let mut previous_end = 0;
+        for (start, end, indices) in runs {
+            writer.usize(start.saturating_sub(previous_end));
+            writer.usize(end - start);
+            writer.usize(indices.len());
+            for index in indices {
+                writer.usize(index);
+            }
+            previous_end = end;
+        }
+
+        let mut blocks = Table::default();
+        let block_indices: Vec<(usize, usize)> = doc
+            .blocks
+            .iter()
+            .map(|block| {
+                let attributes = SerializableBlock {
+                    start: 0,
+                    ..block.clone()
+                };
+                Ok((block.start, blocks.index(&attributes)?))
+            })
+            .collect::<Result<_, BinaryError>>()?;
+        writer.table(&blocks);
+        writer.usize(block_indices.len());
+        let mut previous_start = 0;
+        for (start, index) in block_indices {
+            writer.usize(start.saturating_sub(previous_start));
+            writer.usize(index);
+            previous_start = start;
+        }
+
+        writer.usize(doc.images.len());
+        for entry in &doc.images {
+            writer.usize(entry.offset);
+            writer.json(&entry.image)?;
+        }
+
+        writer.usize(doc.annotations.len());
+        for entry in &doc.annotations {
+            writer.usize(entry.start);
+            writer.usize(entry.end.saturating_sub(entry.start));
+            writer.json(&entry.annotation)?;
+        }
+
+        writer.usize(doc.bookmarks.len());
+        for (id, offset) in &doc.bookmarks {
+            writer.str(id);
+            writer.usize(*offset);
+        }
+
+        writer.usize(doc.selections.len());
+        for selection in &doc.selections {
+            writer.usize(selection.anchor);
+            writer.usize(selection.focus);
+        }
+
+        match &doc.metadata {
+            Some(metadata) => {
+                writer.usize(1);
+                writer.json(metadata)?;
+            }
+            None => writer.usize(0),
+        }
+
+        writer.usize(doc.no_spellcheck.len());
+        for range in &doc.no_spellcheck {
+            writer.usize(range.start);
+            writer.usize(range.end.saturating_sub(range.start));
+        }
+
+        writer.usize(doc.decorations.len());
+        for entry in &doc.decorations {
+            writer.usize(entry.start);
+            writer.usize(entry.end.saturating_sub(entry.start));
+            writer.json(&entry.decoration)?;
+        }
+
+        writer.usize(doc.extensions.len());
+        for (name, value) in &doc.extensions {
+            writer.str(name);
+            writer.json(value)?;
+        }
+
+        writer.usize(doc.required.len());
+        for section in &doc.required {
+            writer.str(section);
+        }
+
+        Ok(writer.bytes)
+    }
+
+    /// Deserializes a document from the binary format
+    ///
+    /// JSON written by `to_json` is accepted too, so documents saved before
+    /// switching to the binary format keep loading; save them again with
+    /// `to_binary` to migrate them. Offsets are validated as in `from_json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data is neither a binary document nor JSON,
+    /// was written by a newer layout version, is truncated or malformed, or
+    /// holds offsets outside the text.
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, BinaryError> {
+        if bytes.trim_ascii_start().starts_with(b"{") {
+            let json = std::str::from_utf8(bytes).map_err(|e| BinaryError::InvalidData {
+                offset: e.valid_up_to(),
+                reason: "JSON is not valid UTF-8".to_string(),
+            })?;
+            return Ok(Self::from_json(json)?);
+        }
+
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(BINARY_MAGIC.len()).ok() != Some(BINARY_MAGIC.as_slice()) {
+            return Err(BinaryError::InvalidMagic);
+        }
+        let version = reader.byte()?;
+        if !(1..=BINARY_VERSION).contains(&version) {
+            return Err(BinaryError::UnsupportedVersion(version));
+        }
+        let text = reader.string()?;
+
+        let formats: Vec<crate::formatting::InlineFormat> = reader.table()?;
+        let mut runs = Vec::new();
+        let mut previous_end = 0;
+        for _ in 0..reader.usize()? {
+            let start = reader.offset(previous_end)?;
+            let end = reader.offset(start)?;
+            let mut run = SerializableFormatRun {
+                start,
+                end,
+                formats: Vec::new(),
+            };
+            for _ in 0..reader.usize()? {
+                run.formats.push(reader.entry(&formats)?.clone());
+            }
+            runs.push(run);
+            previous_end = end;
+        }
+
+        let block_types: Vec<SerializableBlock> = reader.table()?;
+        let mut blocks = Vec::new();
+        let mut previous_start = 0;
+        for _ in 0..reader.usize()? {
+            let start = reader.offset(previous_start)?;
+            blocks.push(SerializableBlock {
+                start,
+                ..reader.entry(&block_types)?.clone()
+            });
+            previous_start = start;
+        }
+
+        let mut images = Vec::new();
+        for _ in 0..reader.usize()? {
+            images.push(SerializableImage {
+                offset: reader.usize()?,
+                image: reader.json()?,
+            });
+        }
+
+        let mut annotations = Vec::new();
+        for _ in 0..reader.usize()? {
+            let start = reader.usize()?;
+            annotations.push(SerializableAnnotation {
+                start,
+                end: reader.offset(start)?,
+                annotation: reader.json()?,
+            });
+        }
+
+        let mut bookmarks = BTreeMap::new();
+        for _ in 0..reader.usize()? {
+            let id = reader.string()?;
+            bookmarks.insert(id, reader.usize()?);
+        }
+
+        let mut selections = Vec::new();
+        for _ in 0..reader.usize()? {
+            selections.push(SerializableSelection {
+                anchor: reader.usize()?,
+                focus: reader.usize()?,
+            });
+        }
+
+        let metadata = if version >= 2 && reader.usize()? != 0 {
+            Some(reader.json()?)
+        } else {
+            None
+        };
+
+        let mut no_spellcheck = Vec::new();
+        if version >= 3 {
+            for _ in 0..reader.usize()? {
+                let start = reader.usize()?;
+                no_spellcheck.push(SerializableRange {
+                    start,
+                    end: reader.offset(start)?,
+                });
+            }
+        }
+
+        let mut decorations = Vec::new();
+        let mut extensions = BTreeMap::new();
+        let mut required = Vec::new();
+        if version >= 4 {
+            for _ in 0..reader.usize()? {
+                let start = reader.usize()?;
+                decorations.push(SerializableDecoration {
+                    start,
+                    end: reader.offset(start)?,
+                    decoration: reader.json()?,
+                });
+            }
+            for _ in 0..reader.usize()? {
+                let name = reader.string()?;
+                extensions.insert(name, reader.json()?);
+            }
+            for _ in 0..reader.usize()? {
+                required.push(reader.string()?);
+            }
+        }
+
+        if reader.pos != bytes.len() {
+            return Err(reader.invalid("unexpected data after the document"));
+        }
+
+        Ok(Self::from_serializable(SerializableDocument {
+            version: JSON_VERSION.to_string(),
+            text,
+            formats: runs,
+            blocks,
+            images,
+            annotations,
+            bookmarks,
+            no_spellcheck,
+            decorations,
+            extensions,
+            required,
+            selections,
+            metadata,
+        })?)
+    }
+}
+
+/// Distinct values in the order they were first seen, keyed by their JSON
+#[derive(Default)]
+struct Table {
+    entries: Vec<String>,
+    indices: BTreeMap<String, usize>,
+}
+
+impl Table {
+    /// Returns the index of `value`, adding it if it is new
+    fn index<T: Serialize>(&mut self, value: &T) -> Result<usize, BinaryError> {
+        let json = serde_json::to_string(value)?;
+        if let Some(&index) = self.indices.get(&json) {
+            return Ok(index);
+        }
+        let index = self.entries.len();
+        self.entries.push(json.clone());
+        self.indices.insert(json, index);
+        Ok(index)
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    /// Writes an unsigned LEB128 varint
+    fn usize(&mut self, value: usize) {
+        varint::write(&mut self.bytes, value as u64);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.usize(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn json<T: Serialize>(&mut self, value: &T) -> Result<(), BinaryError> {
+        self.str(&serde_json::to_string(value)?);
+        Ok(())
+    }
+
+    fn table(&mut self, table: &Table) {
+        self.usize(table.entries.len());
+        for entry in &table.entries {
+            self.str(entry);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn invalid(&self, reason: &str) -> BinaryError {
+        BinaryError::InvalidData {
+            offset: self.pos,
+            reason: reason.to_string(),
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BinaryError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(BinaryError::UnexpectedEnd(self.bytes.len()))?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, BinaryError> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Reads an unsigned LEB128 varint
+    fn usize(&mut self) -> Result<usize, BinaryError> {
+        let (value, len) = varint::read(&self.bytes[self.pos..]).map_err(|err| match err {
+            VarintError::UnexpectedEnd => BinaryError::UnexpectedEnd(self.bytes.len()),
+            VarintError::Overflow => self.invalid("number too large"),
+        })?;
+        self.pos += len;
+        usize::try_from(value).map_err(|_| self.invalid("number too large"))
+    }
+
+    /// Reads a value stored relative to `base`
+    fn offset(&mut self, base: usize) -> Result<usize, BinaryError> {
+        base.checked_add(self.usize()?)
+            .ok_or_else(|| self.invalid("offset too large"))
+    }
+
+    fn string(&mut self) -> Result<String, BinaryError> {
+        let len = self.usize()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| self.invalid("string is not valid UTF-8"))
+    }
+
+    fn json<T: DeserializeOwned>(&mut self) -> Result<T, BinaryError> {
+        let json = self.string()?;
+        serde_json::from_str(&json).map_err(|e| self.invalid(&e.to_string()))
+    }
+
+    fn table<T: DeserializeOwned>(&mut self) -> Result<Vec<T>, BinaryError> {
+        (0..self.usize()?).map(|_| self.json()).collect()
+    }
+
+    /// Reads a table index and returns the entry it refers to
+    fn entry<'t, T>(&mut self, table: &'t [T]) -> Result<&'t T, BinaryError> {
+        let index = self.usize()?;
+        table
+            .get(index)
+            .ok_or_else(|| self.invalid("table index out of range"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{Annotation, Decoration, Image, Position, Range};
+    use crate::formatting::{BlockType, InlineFormat, ListStyle};
+    use crate::selection::Selection;
+    use crate::serialization::json::JsonOptions;
+
+    fn sample() -> Document {
+        let mut doc = Document::from_text("Title\nfirst item\nsecond item\n");
+        doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+        doc.apply_format(
+            Range::from_offsets(6, 11),
+            InlineFormat::Link {
+                url: "https://example.com".to_string(),
+            },
+        );
+        doc.apply_format(Range::from_offsets(17, 23), InlineFormat::Bold);
+        doc.set_block_type(Range::from_offsets(0, 5), BlockType::heading(1));
+        doc.set_block_type(Range::from_offsets(6, 28), BlockType::NumberedList);
+        doc.formats_mut()
+            .set_list_style(6, None, Some(ListStyle::LowerRoman));
+        doc.insert_image(
+            Position::new(29),
+            Image::new("https://example.com/a.png", "logo"),
+        )
+        .unwrap();
+        doc.add_annotation(Range::from_offsets(0, 5), Annotation::new("c1"))
+            .unwrap();
+        doc.set_bookmark("item", Position::new(6)).unwrap();
+        doc.set_selection(Selection::new(Position::new(1), Position::new(4)));
+        doc
+    }
+
+    #[test]
+    fn test_binary_round_trip_matches_json() {
+        let doc = sample();
+        let options = JsonOptions::new().include_selection(true);
+
+        let restored = Document::from_binary(&doc.to_binary(true).unwrap()).unwrap();
+        assert_eq!(
+            restored.to_json_with_options(&options).unwrap(),
+            doc.to_json_with_options(&options).unwrap()
+        );
+        assert_eq!(restored.get_selection(), doc.get_selection());
+    }
+
+    #[test]
+    fn test_binary_round_trip_preserves_metadata() {
+        let mut doc = sample();
+        doc.update_metadata(vec![
+            ("title".to_string(), Some("Notes".to_string())),
+            ("status".to_string(), Some("draft".to_string())),
+        ])
+        .unwrap();
+
+        let restored = Document::from_binary(&doc.to_binary(false).unwrap()).unwrap();
+        assert_eq!(restored.to_json().unwrap(), doc.to_json().unwrap());
+        assert_eq!(restored.get_metadata("title"), Some("Notes"));
+        assert_eq!(restored.get_metadata("status"), Some("draft"));
+    }
+
+    #[test]
+    fn test_binary_round_trip_preserves_no_spellcheck() {
+        let mut doc = sample();
+        doc.mark_no_spellcheck(Range::from_offsets(4, 11)).unwrap();
+        doc.mark_no_spellcheck(Range::from_offsets(17, 23)).unwrap();
+
+        let restored = Document::from_binary(&doc.to_binary(false).unwrap()).unwrap();
+        assert_eq!(restored.to_json().unwrap(), doc.to_json().unwrap());
+        assert_eq!(
+            restored.no_spellcheck_ranges(),
+            [Range::from_offsets(4, 11), Range::from_offsets(17, 23)]
+        );
+    }
+
+    #[test]
+    fn test_binary_round_trip_preserves_decorations_and_extensions() {
+        let mut doc = sample();
+        doc.add_decoration(
+            Range::from_offsets(6, 11),
+            Decoration::new("lint").with_attribute("message", "Typo"),
+        )
+        .unwrap();
+        doc.set_json_extension("com.example.outline", r#"{"collapsed":[2]}"#)
+            .unwrap();
+
+        let restored = Document::from_binary(&doc.to_binary(false).unwrap()).unwrap();
+        assert_eq!(restored.to_json().unwrap(), doc.to_json().unwrap());
+        let decorations = restored.get_decorations_at(Position::new(7));
+        assert_eq!(decorations.len(), 1);
+        assert_eq!(decorations[0].0, Range::from_offsets(6, 11));
+        assert_eq!(decorations[0].1.attributes["message"], "Typo");
+        assert_eq!(
+            restored
+                .get_json_extension("com.example.outline")
+                .as_deref(),
+            Some(r#"{"collapsed":[2]}"#)
+        );
+    }
+
+    #[test]
+    fn test_from_binary_checks_required_sections() {
+        let mut bytes = Document::from_text("Hi").to_binary(false).unwrap();
+        // Replace the empty required list with one unknown section
+        bytes.pop();
+        let mut writer = Writer { bytes };
+        writer.usize(1);
+        writer.str("comments_v2");
+
+        match Document::from_binary(&writer.bytes) {
+            Err(BinaryError::Content(JsonError::UnsupportedSection(section))) => {
+                assert_eq!(section, "comments_v2")
+            }
+            other => panic!("Expected UnsupportedSection error, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_from_binary_reads_version_1() {
+        let doc = sample();
+        let mut bytes = doc.to_binary(false).unwrap();
+        // Version 1 ended after the selections
+        bytes[4] = 1;
+        bytes.truncate(bytes.len() - 5);
+
+        let restored = Document::from_binary(&bytes).unwrap();
+        assert_eq!(restored.to_json().unwrap(), doc.to_json().unwrap());
+    }
+
+    #[test]
+    fn test_binary_is_smaller_than_json() {
+        let doc = sample();
+        assert!(doc.to_binary(false).unwrap().len() < doc.to_json().unwrap().len());
+    }
+
+    #[test]
+    fn test_from_binary_accepts_json() {
+        let doc = sample();
+        let json = doc.to_json().unwrap();
+
+        let restored = Document::from_binary(json.as_bytes()).unwrap();
+        assert_eq!(restored.to_json().unwrap(), json);
+    }
+
+    #[test]
+    fn test_from_binary_rejects_bad_data() {
+        let bytes = sample().to_binary(false).unwrap();
+
+        assert!(matches!(
+            Document::from_binary(b"nope"),
+            Err(BinaryError::InvalidMagic)
+        ));
+        let mut newer = bytes.clone();
+        newer[4] = BINARY_VERSION + 1;
+        assert!(matches!(
+            Document::from_binary(&newer),
+            Err(BinaryError::UnsupportedVersion(_))
+        ));
+        for len in 5..bytes.len() {
+            assert!(Document::from_binary(&bytes[..len]).is_err());
+        }
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(matches!(
+            Document::from_binary(&trailing),
+            Err(BinaryError::InvalidData { .. })
+        ));
+    }
+
+    #[test]
+    fn test_from_binary_validates_offsets() {
+        let mut writer = Writer::default();
+        writer.bytes.extend_from_slice(BINARY_MAGIC);
+        writer.bytes.push(BINARY_VERSION);
+        writer.str("Hi");
+        let mut formats = Table::default();
+        formats.index(&InlineFormat::Bold).unwrap();
+        writer.table(&formats);
+        // One run from 1 to 9, past the end of the text
+        for value in [1, 1, 8, 1, 0] {
+            writer.usize(value);
+        }
+        for _ in 0..11 {
+            writer.usize(0);
+        }
+
+        assert!(matches!(
+            Document::from_binary(&writer.bytes),
+            Err(BinaryError::Content(JsonError::InvalidFormat(_)))
+        ));
+    }
+
+    #[test]
+    fn test_varint_round_trip() {
+        let mut writer = Writer::default();
+        let values = [0, 1, 127, 128, 300, 16_384, usize::MAX];
+        for value in values {
+            writer.usize(value);
+        }
+        let mut reader = Reader {
+            bytes: &writer.bytes,
+            pos: 0,
+        };
+        for value in values {
+            assert_eq!(reader.usize().unwrap(), value);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! CommonMark conformance harness for the Markdown importer
+//!
+//! Run with `cargo test --features commonmark-spec`. Examples come from the
+//! bundled fixture, all 652 examples of the CommonMark 0.31.2 `spec.json`,
+//! or from another version's `spec.json` when the `COMMONMARK_SPEC`
+//! environment variable points at one.
+//!
+//! Each example is imported with `Document::from_markdown_with_options` and
+//! compared with its expected HTML imported through `Document::from_html`,
+//! so only what the document model can represent is checked. Empty paragraph
+//! lines are ignored, since the importer keeps blank lines as empty
+//! paragraphs. Examples whose HTML uses other elements (thematic breaks, raw
+//! HTML, hard breaks, images, ...) are skipped.
+
+use crate::document::{Document, Position};
+use crate::formatting::BlockType;
+use crate::serialization::html::{HtmlImportOptions, HtmlSanitizer, WhitespaceHandling};
+use crate::serialization::markdown::MarkdownOptions;
+
+use serde::Deserialize;
+
+/// The CommonMark 0.31.2 `spec.json`, without the source line numbers
+const FIXTURE: &str = include_str!("fixtures/commonmark_spec.json");
+
+/// Share of the checked spec examples the importer must get right
+///
+/// 266 of the 535 examples that use only supported elements passed (49.7%)
+/// when this was set; raise it as the importer improves. Keep in sync with
+/// the figure in the `from_markdown` docs.
+const MIN_PASS_RATE: f64 = 0.49;
+
+/// Elements the document model can represent
+const SUPPORTED_TAGS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+    "pre",
+    "code",
+    "em",
+    "strong",
+    "a",
+];
+
+#[derive(Debug, Deserialize)]
+struct Example {
+    markdown: String,
+    html: String,
+    example: usize,
+    section: String,
+}
+
+/// Returns true if every element in `html` is in `SUPPORTED_TAGS`
+fn uses_supported_tags(html: &str) -> bool {
+    html.split('<').skip(1).all(|tag| {
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+        SUPPORTED_TAGS.contains(&name.as_str())
+    })
+}
+
+/// Describes each non-empty line by its block, list indent and formats
+fn canonical_lines(doc: &Document) -> Vec<String> {
+    let content = doc.get_content();
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    for line in content.split('\n') {
+        let len = line.chars().count();
+        let block = doc.get_block_type_at(Position::new(offset));
+        if len > 0 || block != BlockType::Paragraph {
+            let runs: Vec<String> = line
+                .chars()
+                .enumerate()
+                .map(|(i, c)| {
+                    let mut formats: Vec<String> = doc
+                        .get_formats_at(Position::new(offset + i))
+                        .iter()
+                        .map(|format| format!("{:?}", format))
+                        .collect();
+                    formats.sort();
+                    format!("{}{:?}", c, formats)
+                })
+                .collect();
+            lines.push(format!(
+                "{:?}/{} {}",
+                block,
+                doc.get_list_indent_at(Position::new(offset)),
+                runs.concat()
+            ));
+        }
+        offset += len + 1;
+    }
+
+    lines
+}
+
+#[test]
+fn test_commonmark_conformance() {
+    let spec = std::env::var("COMMONMARK_SPEC")
+        .map(|path| std::fs::read_to_string(path).expect("COMMONMARK_SPEC is readable"))
+        .unwrap_or_else(|_| FIXTURE.to_string());
+    let examples: Vec<Example> = serde_json::from_str(&spec).expect("spec examples parse");
+    let options = MarkdownOptions::new().strikethrough(false);
+    // Soft line breaks in the expected HTML are lines in the document model
+    let html_options = HtmlImportOptions::new().whitespace(WhitespaceHandling::Preserve);
+    let sanitizer = HtmlSanitizer::new();
+
+    let mut checked = 0;
+    let mut failures = Vec::new();
+    for example in &examples {
+        if !uses_supported_tags(&example.html) {
+            continue;
+        }
+        checked += 1;
+
+        let expected = Document::from_html_with_options(&example.html, &sanitizer, &html_options)
+            .expect("expected HTML imports");
+        let actual = Document::from_markdown_with_options(&example.markdown, &options)
+            .expect("Markdown imports");
+        if canonical_lines(&actual) != canonical_lines(&expected) {
+            failures.push(format!(
+                "#{} ({}): {:?}",
+                example.example, example.section, example.markdown
+            ));
+        }
+    }
+
+    let pass_rate = (checked - failures.len()) as f64 / checked as f64;
+    println!(
+        "CommonMark: {}/{} examples pass ({:.1}%), {} skipped",
+        checked - failures.len(),
+        checked,
+        pass_rate * 100.0,
+        examples.len() - checked
+    );
+    for failure in &failures {
+        println!("  failed {}", failure);
+    }
+
+    if std::env::var("COMMONMARK_SPEC").is_err() {
+        assert!(
+            pass_rate >= MIN_PASS_RATE,
+            "pass rate {:.1}% is below {:.1}%",
+            pass_rate * 100.0,
+            MIN_PASS_RATE * 100.0
+        );
+    }
+}
@@ -0,0 +1,716 @@
+use crate::document::embeds::is_safe_image_src;
+use crate::document::validation::{validate_size_limit, validate_text_content};
+use crate::document::{Document, EMBED_CHAR, Image, Position, Range};
+use crate::formatting::{BlockType, InlineFormat};
+use crate::serialization::html::HtmlSanitizer;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// Errors that can occur during Quill Delta serialization/deserialization
+#[derive(Debug, Error)]
+pub enum DeltaError {
+    #[error("Delta parsing error: {0}")]
+    ParseError(#[from] serde_json::Error),
+
+    #[error("Invalid delta operation: {0}")]
+    InvalidOp(String),
+
+    #[error("Invalid delta text: {0}")]
+    InvalidText(String),
+}
+
+impl Document {
+    /// Exports the document as Quill Delta JSON
+    ///
+    /// Produces `{"ops": [...]}` made only of insert operations. Inline
+    /// formats become attributes on text inserts, block types become
+    /// attributes on the newline ending each line, and images become
+    /// `{"image": src}` embeds. As Quill requires, the delta always ends with
+    /// a newline.
+    ///
+    /// Quill has no soft line break, so soft breaks stay in the text insert
+    /// as U+2028 (line separator); browsers lay it out as a line break and
+    /// `from_delta` reads it back as a soft break rather than a new line.
+    ///
+    /// Attributes without a Quill equivalent are written as `highlight` and
+    /// `smallCaps`; quote citations and nesting depth are not exported.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Range};
+    /// use rte_core::formatting::InlineFormat;
+    ///
+    /// let mut doc = Document::from_text("Hello World");
+    /// doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+    /// assert_eq!(
+    ///     doc.to_delta(),
+    ///     r#"{"ops":[{"attributes":{"bold":true},"insert":"Hello"},{"insert":" World\n"}]}"#
+    /// );
+    /// ```
+    pub fn to_delta(&self) -> String {
+        let text: Vec<char> = self.get_content().chars().collect();
+        let mut ops: Vec<Value> = Vec::new();
+        let mut line_start = 0;
+
+        loop {
+            let line_end = self.line_end_from(line_start);
+
+            for (range, formats) in self
+                .formats()
+                .styled_runs(Range::from_offsets(line_start, line_end))
+            {
+                let attributes = inline_attributes(&formats);
+                let mut segment = String::new();
+
+                let start = range.start_offset();
+                for (offset, &ch) in text[start..range.end_offset()].iter().enumerate() {
+                    let offset = start + offset;
+                    if ch != EMBED_CHAR {
+                        segment.push(ch);
+                        continue;
+                    }
+                    push_insert(
+                        &mut ops,
+                        Value::String(std::mem::take(&mut segment)),
+                        &attributes,
+                    );
+                    if let Some(image) = self.embeds().get(offset) {
+                        let mut image_attributes = attributes.clone();
+                        image_attributes.extend(image_attributes_of(image));
+                        let mut embed = Map::new();
+                        embed.insert("image".to_string(), Value::String(image.src.clone()));
+                        push_insert(&mut ops, Value::Object(embed), &image_attributes);
+                    }
+                }
+                push_insert(&mut ops, Value::String(segment), &attributes);
+            }
+
+            let block_type = self.get_block_type_at(Position::new(line_start));
+            push_insert(
+                &mut ops,
+                Value::String("\n".to_string()),
+                &block_attributes(&block_type),
+            );
+
+            if line_end >= text.len() {
+                break;
+            }
+            line_start = line_end + 1;
+        }
+
+        let mut delta = Map::new();
+        delta.insert("ops".to_string(), Value::Array(ops));
+        Value::Object(delta).to_string()
+    }
+
+    /// Imports a document from Quill Delta JSON
+    ///
+    /// Accepts either `{"ops": [...]}` or a bare array of operations. Only
+    /// insert operations are allowed, since a document delta describes
+    /// content rather than changes. Unknown attributes and embeds other than
+    /// images are ignored, as are images with unsafe sources and links or
+    /// colors the HTML sanitizer would strip. The newline Quill keeps at the
+    /// end of every document is dropped. U+2028 in a text insert is a soft
+    /// line break within the current line.
+    ///
+    /// # Errors
+    /// Returns `DeltaError` if the JSON is malformed, contains non-insert
+    /// operations, or inserts control characters or more text than a
+    /// document may hold
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    /// use rte_core::formatting::{BlockType, InlineFormat};
+    ///
+    /// let delta = r#"{"ops":[
+    ///     {"insert":"Title"},{"insert":"\n","attributes":{"header":1}},
+    ///     {"insert":"Body","attributes":{"italic":true}},{"insert":"\n"}
+    /// ]}"#;
+    /// let doc = Document::from_delta(delta).unwrap();
+    /// assert_eq!(doc.get_content(), "Title\nBody");
+    /// assert_eq!(doc.get_block_type_at(Position::new(0)), BlockType::heading(1));
+    /// assert!(doc.get_formats_at(Position::new(6)).contains(&InlineFormat::Italic));
+    /// ```
+    pub fn from_delta(json: &str) -> Result<Self, DeltaError> {
+        let value: Value = serde_json::from_str(json)?;
+        let ops = match &value {
+            Value::Array(ops) => ops,
+            Value::Object(delta) => delta
+                .get("ops")
+                .and_then(Value::as_array)
+                .ok_or_else(|| DeltaError::InvalidOp("missing 'ops' array".to_string()))?,
+            _ => {
+                return Err(DeltaError::InvalidOp(
+                    "expected an object or array".to_string(),
+                ));
+            }
+        };
+
+        let mut text = String::new();
+        let mut length = 0;
+        let mut formats: Vec<(usize, usize, InlineFormat)> = Vec::new();
+        let mut images: Vec<(usize, Image)> = Vec::new();
+        let mut blocks: Vec<(usize, usize, BlockType)> = Vec::new();
+        let mut line_start = 0;
+        let sanitizer = HtmlSanitizer::new();
+
+        for (index, op) in ops.iter().enumerate() {
+            let op = op
+                .as_object()
+                .ok_or_else(|| DeltaError::InvalidOp(format!("op {} is not an object", index)))?;
+            let Some(insert) = op.get("insert") else {
+                return Err(DeltaError::InvalidOp(format!(
+                    "op {} is not an insert; documents may only contain inserts",
+                    index
+                )));
+            };
+            let attributes = match op.get("attributes") {
+                None | Some(Value::Null) => Map::new(),
+                Some(Value::Object(attributes)) => attributes.clone(),
+                Some(_) => {
+                    return Err(DeltaError::InvalidOp(format!(
+                        "op {} has non-object attributes",
+                        index
+                    )));
+                }
+            };
+            let inline = parse_inline_attributes(&attributes, &sanitizer);
+
+            match insert {
+                Value::String(s) => {
+                    validate_text_content(s).map_err(|e| DeltaError::InvalidText(e.to_string()))?;
+                    let mut piece_start = length;
+                    for ch in s.chars() {
+                        match ch {
+                            '\n' => {
+                                push_formats(&mut formats, piece_start, length, &inline);
+                                let block_type = parse_block_attributes(&attributes);
+                                if block_type != BlockType::Paragraph {
+                                    blocks.push((line_start, length, block_type));
+                                }
+                                text.push('\n');
+                                length += 1;
+                                line_start = length;
+                                piece_start = length;
+                            }
+                            // Embed characters only stand for images
+                            EMBED_CHAR => {}
+                            _ => {
+                                text.push(ch);
+                                length += 1;
+                            }
+                        }
+                    }
+                    push_formats(&mut formats, piece_start, length, &inline);
+                }
+                Value::Object(embed) => {
+                    let Some(src) = embed.get("image").and_then(Value::as_str) else {
+                        continue;
+                    };
+                    if !is_safe_image_src(src) {
+                        continue;
+                    }
+                    let alt = attributes.get("alt").and_then(Value::as_str).unwrap_or("");
+                    let image = Image::new(src, alt).with_size(
+                        dimension(&attributes, "width"),
+                        dimension(&attributes, "height"),
+                    );
+                    images.push((length, image));
+                    text.push(EMBED_CHAR);
+                    push_formats(&mut formats, length, length + 1, &inline);
+                    length += 1;
+                }
+                _ => {
+                    return Err(DeltaError::InvalidOp(format!(
+                        "op {} inserts neither text nor an embed",
+                        index
+                    )));
+                }
+            }
+        }
+
+        // Quill terminates the last line with a newline the model doesn't keep
+        if text.ends_with('\n') {
+            text.pop();
+            length -= 1;
+            if let Some(last) = blocks.last_mut() {
+                last.1 = last.1.min(length);
+            }
+        }
+
+        validate_size_limit(0, length).map_err(|e| DeltaError::InvalidText(e.to_string()))?;
+
+        let mut doc = Document::from_text(&text);
+        for (start, end, format) in formats {
+            doc.apply_format(Range::from_offsets(start, end), format);
+        }
+        for (start, end, block_type) in blocks {
+            doc.set_block_type(Range::from_offsets(start, end), block_type);
+        }
+        for (offset, image) in images {
+            doc.embeds_mut().insert(offset, image);
+        }
+
+        // Clear undo/redo history since this is a freshly loaded document
+        doc.history.clear();
+
+        Ok(doc)
+    }
+}
+
+/// Appends an insert op, merging it into the previous op when both are
+/// text with the same attributes
+fn push_insert(ops: &mut Vec<Value>, insert: Value, attributes: &Map<String, Value>) {
+    if insert.as_str().is_some_and(str::is_empty) {
+        return;
+    }
+
+    if let (Value::String(text), Some(Value::Object(last))) = (&insert, ops.last_mut())
+        && last
+            .get("attributes")
+            .and_then(Value::as_object)
+            .unwrap_or(&Map::new())
+            == attributes
+        && let Some(Value::String(last_text)) = last.get_mut("insert")
+    {
+        last_text.push_str(text);
+        return;
+    }
+
+    let mut op = Map::new();
+    if !attributes.is_empty() {
+        op.insert("attributes".to_string(), Value::Object(attributes.clone()));
+    }
+    op.insert("insert".to_string(), insert);
+    ops.push(Value::Object(op));
+}
+
+/// Converts inline formats to Quill attributes
+fn inline_attributes(formats: &HashSet<InlineFormat>) -> Map<String, Value> {
+    let mut attributes = Map::new();
+    for format in formats {
+        let (key, value) = match format {
+            InlineFormat::Bold => ("bold", Value::Bool(true)),
+            InlineFormat::Italic => ("italic", Value::Bool(true)),
+            InlineFormat::Underline => ("underline", Value::Bool(true)),
+            InlineFormat::Strikethrough => ("strike", Value::Bool(true)),
+            InlineFormat::Code => ("code", Value::Bool(true)),
+            InlineFormat::Link { url } => ("link", Value::String(url.clone())),
+            InlineFormat::TextColor { color } => ("color", Value::String(color.clone())),
+            InlineFormat::BackgroundColor { color } => ("background", Value::String(color.clone())),
+            InlineFormat::Highlight => ("highlight", Value::Bool(true)),
+            InlineFormat::SmallCaps => ("smallCaps", Value::Bool(true)),
+            InlineFormat::Subscript => ("script", Value::String("sub".to_string())),
+            InlineFormat::Superscript => ("script", Value::String("super".to_string())),
+            InlineFormat::Math { display } => {
+                let mode = if *display { "display" } else { "inline" };
+                ("math", Value::String(mode.to_string()))
+            }
+        };
+        attributes.insert(key.to_string(), value);
+    }
+    attributes
+}
+
+/// Converts Quill attributes to inline formats, ignoring unknown ones and
+/// links or colors the sanitizer rejects
+fn parse_inline_attributes(
+    attributes: &Map<String, Value>,
+    sanitizer: &HtmlSanitizer,
+) -> Vec<InlineFormat> {
+    let flag = |key: &str| attributes.get(key).and_then(Value::as_bool) == Some(true);
+    let string = |key: &str| {
+        attributes
+            .get(key)
+            .and_then(Value::as_str)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+
+    let mut formats = Vec::new();
+    if flag("bold") {
+        formats.push(InlineFormat::Bold);
+    }
+    if flag("italic") {
+        formats.push(InlineFormat::Italic);
+    }
+    if flag("underline") {
+        formats.push(InlineFormat::Underline);
+    }
+    if flag("strike") {
+        formats.push(InlineFormat::Strikethrough);
+    }
+    if flag("code") {
+        formats.push(InlineFormat::Code);
+    }
+    if flag("highlight") {
+        formats.push(InlineFormat::Highlight);
+    }
+    if flag("smallCaps") {
+        formats.push(InlineFormat::SmallCaps);
+    }
+    match string("script").as_deref() {
+        Some("sub") => formats.push(InlineFormat::Subscript),
+        Some("super") => formats.push(InlineFormat::Superscript),
+        _ => {}
+    }
+    match string("math").as_deref() {
+        Some("inline") => formats.push(InlineFormat::Math { display: false }),
+        Some("display") => formats.push(InlineFormat::Math { display: true }),
+        _ => {}
+    }
+    if let Some(url) = string("link").filter(|url| sanitizer.is_safe_url(url)) {
+        formats.push(InlineFormat::Link { url });
+    }
+    if let Some(color) = string("color").filter(|color| sanitizer.is_valid_color(color)) {
+        formats.push(InlineFormat::TextColor { color });
+    }
+    if let Some(color) = string("background").filter(|color| sanitizer.is_valid_color(color)) {
+        formats.push(InlineFormat::BackgroundColor { color });
+    }
+    formats
+}
+
+/// Converts a block type to attributes for the newline ending its line
+fn block_attributes(block_type: &BlockType) -> Map<String, Value> {
+    let mut attributes = Map::new();
+    match block_type {
+        BlockType::Paragraph => {}
+        BlockType::Heading { level } => {
+            attributes.insert("header".to_string(), Value::from(*level));
+        }
+        BlockType::BulletList => {
+            attributes.insert("list".to_string(), Value::from("bullet"));
+        }
+        BlockType::NumberedList => {
+            attributes.insert("list".to_string(), Value::from("ordered"));
+        }
+        BlockType::BlockQuote { .. } => {
+            attributes.insert("blockquote".to_string(), Value::Bool(true));
+        }
+        BlockType::CodeBlock { language } => {
+            let value = language.as_deref().map_or(Value::Bool(true), Value::from);
+            attributes.insert("code-block".to_string(), value);
+        }
+    }
+    attributes
+}
+
+/// Converts newline attributes to a block type, defaulting to paragraph
+fn parse_block_attributes(attributes: &Map<String, Value>) -> BlockType {
+    if let Some(level) = attributes.get("header").and_then(Value::as_u64)
+        && (1..=6).contains(&level)
+    {
+        return BlockType::heading(level as u8);
+    }
+    match attributes.get("list").and_then(Value::as_str) {
+        Some("bullet") => return BlockType::BulletList,
+        Some("ordered") => return BlockType::NumberedList,
+        _ => {}
+    }
+    if attributes.get("blockquote").and_then(Value::as_bool) == Some(true) {
+        return BlockType::block_quote();
+    }
+    // Quill 2 stores the language as the value, with "plain" for none;
+    // older versions use `true`
+    match attributes.get("code-block") {
+        Some(Value::Bool(true)) => return BlockType::code_block(),
+        Some(Value::String(language)) => {
+            return BlockType::code_block_with(Some(language.as_str()).filter(|l| *l != "plain"));
+        }
+        _ => {}
+    }
+    BlockType::Paragraph
+}
+
+/// Converts an image's alt text and size to Quill attributes
+fn image_attributes_of(image: &Image) -> Map<String, Value> {
+    let mut attributes = Map::new();
+    if !image.alt.is_empty() {
+        attributes.insert("alt".to_string(), Value::String(image.alt.clone()));
+    }
+    if let Some(width) = image.width {
+        attributes.insert("width".to_string(), Value::String(width.to_string()));
+    }
+    if let Some(height) = image.height {
+        attributes.insert("height".to_string(), Value::String(height.to_string()));
+    }
+    attributes
+}
+
+/// Reads an image dimension, which Quill stores as a string
+fn dimension(attributes: &Map<String, Value>, key: &str) -> Option<u32> {
+    match attributes.get(key)? {
+        Value::Number(n) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+        Value::String(s) => s.trim().trim_end_matches("px").parse().ok(),
+        _ => None,
+    }
+}
+
+fn push_formats(
+    formats: &mut Vec<(usize, usize, InlineFormat)>,
+    start: usize,
+    end: usize,
+    inline: &[InlineFormat],
+) {
+    if start < end {
+        formats.extend(inline.iter().map(|f| (start, end, f.clone())));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(doc: &Document) -> Vec<Value> {
+        let delta: Value = serde_json::from_str(&doc.to_delta()).unwrap();
+        delta["ops"].as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn test_export_plain_text() {
+        let doc = Document::from_text("Hello\nWorld");
+        assert_eq!(
+            ops(&doc),
+            vec![serde_json::json!({"insert": "Hello\nWorld\n"})]
+        );
+    }
+
+    #[test]
+    fn test_export_empty_document() {
+        let doc = Document::new();
+        assert_eq!(doc.to_delta(), r#"{"ops":[{"insert":"\n"}]}"#);
+    }
+
+    #[test]
+    fn test_export_blocks() {
+        let mut doc = Document::from_text("Title\nItem\nCode");
+        doc.set_block_type(Range::from_offsets(0, 5), BlockType::heading(2));
+        doc.set_block_type(Range::from_offsets(6, 10), BlockType::BulletList);
+        doc.set_block_type(Range::from_offsets(11, 15), BlockType::code_block());
+
+        assert_eq!(
+            ops(&doc),
+            vec![
+                serde_json::json!({"insert": "Title"}),
+                serde_json::json!({"insert": "\n", "attributes": {"header": 2}}),
+                serde_json::json!({"insert": "Item"}),
+                serde_json::json!({"insert": "\n", "attributes": {"list": "bullet"}}),
+                serde_json::json!({"insert": "Code"}),
+                serde_json::json!({"insert": "\n", "attributes": {"code-block": true}}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export_link_and_image() {
+        let mut doc = Document::from_text("a\u{FFFC}b");
+        doc.embeds_mut().insert(
+            1,
+            Image::new("https://example.com/x.png", "X").with_size(Some(10), None),
+        );
+        doc.apply_format(
+            Range::from_offsets(2, 3),
+            InlineFormat::Link {
+                url: "https://example.com".to_string(),
+            },
+        );
+
+        assert_eq!(
+            ops(&doc),
+            vec![
+                serde_json::json!({"insert": "a"}),
+                serde_json::json!({
+                    "insert": {"image": "https://example.com/x.png"},
+                    "attributes": {"alt": "X", "width": "10"}
+                }),
+                serde_json::json!({"insert": "b", "attributes": {"link": "https://example.com"}}),
+                serde_json::json!({"insert": "\n"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_inline_attributes() {
+        let doc = Document::from_delta(
+            r##"[{"insert":"Bold","attributes":{"bold":true,"color":"#ff0000"}},{"insert":" plain\n"}]"##,
+        )
+        .unwrap();
+
+        assert_eq!(doc.get_content(), "Bold plain");
+        let formats = doc.get_formats_at(Position::new(0));
+        assert!(formats.contains(&InlineFormat::Bold));
+        assert!(formats.contains(&InlineFormat::TextColor {
+            color: "#ff0000".to_string()
+        }));
+        assert!(doc.get_formats_at(Position::new(5)).is_empty());
+        assert!(!doc.can_undo());
+    }
+
+    #[test]
+    fn test_script_attribute() {
+        let doc = Document::from_delta(
+            r#"[{"insert":"H"},{"insert":"2","attributes":{"script":"sub"}},{"insert":"O x"},{"insert":"2","attributes":{"script":"super"}},{"insert":"\n"}]"#,
+        )
+        .unwrap();
+        assert!(
+            doc.get_formats_at(Position::new(1))
+                .contains(&InlineFormat::Subscript)
+        );
+        assert!(
+            doc.get_formats_at(Position::new(5))
+                .contains(&InlineFormat::Superscript)
+        );
+        assert_eq!(
+            Document::from_delta(&doc.to_delta())
+                .unwrap()
+                .get_formats_at(Position::new(1)),
+            doc.get_formats_at(Position::new(1))
+        );
+    }
+
+    #[test]
+    fn test_import_blocks_in_one_insert() {
+        let doc = Document::from_delta(
+            r#"{"ops":[{"insert":"One"},{"insert":"\n\n","attributes":{"list":"ordered"}},{"insert":"Quote"},{"insert":"\n","attributes":{"blockquote":true}}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(doc.get_content(), "One\n\nQuote");
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::NumberedList
+        );
+        assert_eq!(
+            doc.get_block_type_at(Position::new(4)),
+            BlockType::NumberedList
+        );
+        assert_eq!(
+            doc.get_block_type_at(Position::new(5)),
+            BlockType::block_quote()
+        );
+    }
+
+    #[test]
+    fn test_code_block_language() {
+        let doc = Document::from_delta(
+            r#"{"ops":[{"insert":"a"},{"insert":"\n","attributes":{"code-block":"rust"}},{"insert":"b"},{"insert":"\n","attributes":{"code-block":"plain"}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::code_block_with(Some("rust"))
+        );
+        assert_eq!(
+            doc.get_block_type_at(Position::new(2)),
+            BlockType::code_block()
+        );
+        assert_eq!(
+            ops(&doc)[1],
+            serde_json::json!({"insert": "\n", "attributes": {"code-block": "rust"}})
+        );
+    }
+
+    #[test]
+    fn test_import_images() {
+        let doc = Document::from_delta(
+            r#"[{"insert":{"image":"https://example.com/a.png"},"attributes":{"alt":"A","width":"20px"}},{"insert":{"image":"javascript:alert(1)"}},{"insert":{"video":"https://example.com/v"}},{"insert":"\n"}]"#,
+        )
+        .unwrap();
+
+        assert_eq!(doc.get_content(), "\u{FFFC}");
+        let image = doc.embeds().get(0).unwrap();
+        assert_eq!(image.src, "https://example.com/a.png");
+        assert_eq!(image.alt, "A");
+        assert_eq!(image.width, Some(20));
+    }
+
+    #[test]
+    fn test_import_rejects_non_insert_ops() {
+        assert!(matches!(
+            Document::from_delta(r#"{"ops":[{"retain":3}]}"#),
+            Err(DeltaError::InvalidOp(_))
+        ));
+        assert!(matches!(
+            Document::from_delta(r#"[{"insert":"a","attributes":true}]"#),
+            Err(DeltaError::InvalidOp(_))
+        ));
+        assert!(matches!(
+            Document::from_delta("not json"),
+            Err(DeltaError::ParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_import_drops_unsafe_attributes() {
+        let doc = Document::from_delta(
+            r##"[{"insert":"a","attributes":{"link":"javascript:alert(1)","color":"red;x:url(y)"}},{"insert":"b","attributes":{"link":"https://example.com","background":"#ff0"}}]"##,
+        )
+        .unwrap();
+
+        assert!(doc.get_formats_at(Position::new(0)).is_empty());
+        let formats = doc.get_formats_at(Position::new(1));
+        assert!(formats.contains(&InlineFormat::Link {
+            url: "https://example.com".to_string()
+        }));
+        assert!(formats.contains(&InlineFormat::BackgroundColor {
+            color: "#ff0".to_string()
+        }));
+    }
+
+    #[test]
+    fn test_import_rejects_control_characters() {
+        assert!(matches!(
+            Document::from_delta(r#"[{"insert":"a\u0000b"}]"#),
+            Err(DeltaError::InvalidText(_))
+        ));
+    }
+
+    #[test]
+    fn test_soft_breaks() {
+        let mut doc = Document::from_text("one\u{2028}two\nItem");
+        doc.set_block_type(Range::from_offsets(8, 12), BlockType::BulletList);
+        assert_eq!(
+            ops(&doc),
+            vec![
+                serde_json::json!({"insert": "one\u{2028}two\nItem"}),
+                serde_json::json!({"insert": "\n", "attributes": {"list": "bullet"}}),
+            ]
+        );
+
+        let imported = Document::from_delta(
+            r#"[{"insert":"a\u2028b"},{"insert":"\n","attributes":{"header":1}}]"#,
+        )
+        .unwrap();
+        assert_eq!(imported.get_content(), "a\u{2028}b");
+        assert_eq!(
+            imported.get_block_type_at(Position::new(2)),
+            BlockType::heading(1)
+        );
+        assert_eq!(
+            Document::from_delta(&doc.to_delta()).unwrap().get_content(),
+            doc.get_content()
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut doc = Document::from_text("Heading\nSome bold and italic text\nItem");
+        doc.set_block_type(Range::from_offsets(0, 7), BlockType::heading(1));
+        doc.apply_format(Range::from_offsets(13, 17), InlineFormat::Bold);
+        doc.apply_format(Range::from_offsets(13, 28), InlineFormat::Italic);
+        doc.apply_format(Range::from_offsets(8, 12), InlineFormat::Highlight);
+        doc.set_block_type(Range::from_offsets(34, 38), BlockType::BulletList);
+
+        let restored = Document::from_delta(&doc.to_delta()).unwrap();
+        assert_eq!(restored.get_content(), doc.get_content());
+        assert_eq!(restored.to_delta(), doc.to_delta());
+        for offset in 0..doc.get_length() {
+            let pos = Position::new(offset);
+            assert_eq!(restored.get_formats_at(pos), doc.get_formats_at(pos));
+            assert_eq!(restored.get_block_type_at(pos), doc.get_block_type_at(pos));
+        }
+    }
+}
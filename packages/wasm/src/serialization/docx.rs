@@ -0,0 +1,518 @@
+use crate::document::{Document, EMBED_CHAR, Position, Range, SOFT_BREAK};
+use crate::formatting::{BlockType, InlineFormat};
+use crate::utils::zip::write_stored;
+use std::collections::HashSet;
+
+/// Number definition used for bullet list paragraphs
+const BULLET_NUM_ID: u32 = 1;
+/// Number definition of the first numbered list; each later list gets the
+/// next id so its numbering restarts at 1
+const FIRST_DECIMAL_NUM_ID: u32 = 2;
+
+const CONTENT_TYPES_XML: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/><Override PartName="/word/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml"/><Override PartName="/word/numbering.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml"/></Types>"#;
+
+const PACKAGE_RELS_XML: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/></Relationships>"#;
+
+/// Start of `word/numbering.xml`: the bullet and decimal definitions and the
+/// bullet number; `numbering_xml` appends one number per numbered list
+const NUMBERING_XML_START: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:numbering xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:abstractNum w:abstractNumId="0"><w:lvl w:ilvl="0"><w:start w:val="1"/><w:numFmt w:val="bullet"/><w:lvlText w:val="•"/><w:lvlJc w:val="left"/><w:pPr><w:ind w:left="720" w:hanging="360"/></w:pPr></w:lvl></w:abstractNum><w:abstractNum w:abstractNumId="1"><w:lvl w:ilvl="0"><w:start w:val="1"/><w:numFmt w:val="decimal"/><w:lvlText w:val="%1."/><w:lvlJc w:val="left"/><w:pPr><w:ind w:left="720" w:hanging="360"/></w:pPr></w:lvl></w:abstractNum><w:num w:numId="1"><w:abstractNumId w:val="0"/></w:num>"#;
+
+/// Font sizes in half-points for heading levels 1-6
+pub(crate) const HEADING_SIZES: [u32; 6] = [48, 36, 28, 24, 22, 20];
+
+impl Document {
+    /// Exports the document as a minimal Word (.docx) file
+    ///
+    /// Writes headings, block quotes and code blocks as paragraph styles,
+    /// bullet and numbered lists with Word numbering (each separate numbered
+    /// list starts again at 1), inline formats as run properties and soft
+    /// line breaks as `<w:br/>` within the run. Links become external
+    /// hyperlinks. Colors are written when they are hex, `rgb()` or one of
+    /// the common named colors; other values are dropped. Images are not
+    /// exported.
+    ///
+    /// # Returns
+    /// The bytes of the .docx (ZIP) package
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Range};
+    /// use rte_core::formatting::InlineFormat;
+    ///
+    /// let mut doc = Document::from_text("Hello World");
+    /// doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+    /// let docx = doc.to_docx();
+    /// assert_eq!(&docx[..2], b"PK");
+    /// ```
+    pub fn to_docx(&self) -> Vec<u8> {
+        let mut links: Vec<String> = Vec::new();
+        let mut numbered_lists = 0;
+        let body = self.docx_body(&mut links, &mut numbered_lists);
+
+        let document_xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><w:body>{}<w:sectPr><w:pgSz w:w="12240" w:h="15840"/><w:pgMar w:top="1440" w:right="1440" w:bottom="1440" w:left="1440" w:header="720" w:footer="720" w:gutter="0"/></w:sectPr></w:body></w:document>"#,
+            body
+        );
+
+        let mut document_rels = String::from(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering" Target="numbering.xml"/>"#,
+        );
+        for (index, url) in links.iter().enumerate() {
+            document_rels.push_str(&format!(
+                r#"<Relationship Id="{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink" Target="{}" TargetMode="External"/>"#,
+                link_relationship_id(index),
+                escape_xml(url)
+            ));
+        }
+        document_rels.push_str("</Relationships>");
+
+        let styles_xml = styles_xml();
+        let numbering_xml = numbering_xml(numbered_lists);
+        write_stored(&[
+            ("[Content_Types].xml", CONTENT_TYPES_XML.as_bytes()),
+            ("_rels/.rels", PACKAGE_RELS_XML.as_bytes()),
+            ("word/document.xml", document_xml.as_bytes()),
+            ("word/_rels/document.xml.rels", document_rels.as_bytes()),
+            ("word/styles.xml", styles_xml.as_bytes()),
+            ("word/numbering.xml", numbering_xml.as_bytes()),
+        ])
+    }
+
+    /// Builds the paragraphs of `word/document.xml`, collecting link targets
+    /// and counting numbered lists
+    fn docx_body(&self, links: &mut Vec<String>, numbered_lists: &mut u32) -> String {
+        let text: Vec<char> = self.get_content().chars().collect();
+        let mut body = String::new();
+        let mut line_start = 0;
+        let mut previous_block = BlockType::Paragraph;
+
+        loop {
+            let line_end = self.line_end_from(line_start);
+            let block_type = self.get_block_type_at(Position::new(line_start));
+            if block_type == BlockType::NumberedList && previous_block != BlockType::NumberedList {
+                *numbered_lists += 1;
+            }
+
+            body.push_str("<w:p>");
+            body.push_str(&paragraph_properties(
+                &block_type,
+                FIRST_DECIMAL_NUM_ID + numbered_lists.saturating_sub(1),
+            ));
+            previous_block = block_type;
+
+            // Consecutive runs with the same link share one hyperlink element
+            let mut open_link: Option<String> = None;
+            for (range, formats) in self
+                .formats()
+                .styled_runs(Range::from_offsets(line_start, line_end))
+            {
+                let link = formats.iter().find_map(|f| match f {
+                    InlineFormat::Link { url } => Some(url.clone()),
+                    _ => None,
+                });
+                if link != open_link {
+                    if open_link.is_some() {
+                        body.push_str("</w:hyperlink>");
+                    }
+                    if let Some(url) = &link {
+                        let index = links.iter().position(|l| l == url).unwrap_or_else(|| {
+                            links.push(url.clone());
+                            links.len() - 1
+                        });
+                        body.push_str(&format!(
+                            r#"<w:hyperlink r:id="{}">"#,
+                            link_relationship_id(index)
+                        ));
+                    }
+                    open_link = link;
+                }
+
+                let run_text: String = text[range.start_offset()..range.end_offset()]
+                    .iter()
+                    .collect();
+                body.push_str(&run_xml(&run_text, &formats));
+            }
+            if open_link.is_some() {
+                body.push_str("</w:hyperlink>");
+            }
+            body.push_str("</w:p>");
+
+            if line_end >= text.len() {
+                break;
+            }
+            line_start = line_end + 1;
+        }
+
+        body
+    }
+}
+
+/// Relationship id of the link at `index`; rId1 and rId2 are taken by the
+/// styles and numbering parts
+fn link_relationship_id(index: usize) -> String {
+    format!("rId{}", index + 3)
+}
+
+/// Builds `word/numbering.xml` with a restarting number for each numbered list
+fn numbering_xml(numbered_lists: u32) -> String {
+    let mut numbering = String::from(NUMBERING_XML_START);
+    for num_id in (FIRST_DECIMAL_NUM_ID..).take(numbered_lists as usize) {
+        numbering.push_str(&format!(
+            r#"<w:num w:numId="{}"><w:abstractNumId w:val="1"/><w:lvlOverride w:ilvl="0"><w:startOverride w:val="1"/></w:lvlOverride></w:num>"#,
+            num_id
+        ));
+    }
+    numbering.push_str("</w:numbering>");
+    numbering
+}
+
+/// Builds the paragraph properties for a block type, numbering list items
+/// with `decimal_num_id`
+fn paragraph_properties(block_type: &BlockType, decimal_num_id: u32) -> String {
+    match block_type {
+        BlockType::Paragraph => String::new(),
+        BlockType::Heading { level } => {
+            format!(r#"<w:pPr><w:pStyle w:val="Heading{}"/></w:pPr>"#, level)
+        }
+        BlockType::BulletList => format!(
+            r#"<w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="{}"/></w:numPr></w:pPr>"#,
+            BULLET_NUM_ID
+        ),
+        BlockType::NumberedList => format!(
+            r#"<w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="{}"/></w:numPr></w:pPr>"#,
+            decimal_num_id
+        ),
+        BlockType::BlockQuote { .. } => r#"<w:pPr><w:pStyle w:val="Quote"/></w:pPr>"#.to_string(),
+        BlockType::CodeBlock { .. } => r#"<w:pPr><w:pStyle w:val="Code"/></w:pPr>"#.to_string(),
+    }
+}
+
+/// Builds a run for `text`, with properties in the order the schema requires
+fn run_xml(text: &str, formats: &HashSet<InlineFormat>) -> String {
+    let mut properties = String::new();
+    if formats
+        .iter()
+        .any(|f| matches!(f, InlineFormat::Link { .. }))
+    {
+        properties.push_str(r#"<w:rStyle w:val="Hyperlink"/>"#);
+    }
+    if formats.contains(&InlineFormat::Code) {
+        properties.push_str(r#"<w:rFonts w:ascii="Courier New" w:hAnsi="Courier New"/>"#);
+    }
+    if formats.contains(&InlineFormat::Bold) {
+        properties.push_str("<w:b/>");
+    }
+    if formats.contains(&InlineFormat::Italic) {
+        properties.push_str("<w:i/>");
+    }
+    if formats.contains(&InlineFormat::SmallCaps) {
+        properties.push_str("<w:smallCaps/>");
+    }
+    if formats.contains(&InlineFormat::Strikethrough) {
+        properties.push_str("<w:strike/>");
+    }
+    if formats.contains(&InlineFormat::Subscript) {
+        properties.push_str(r#"<w:vertAlign w:val="subscript"/>"#);
+    } else if formats.contains(&InlineFormat::Superscript) {
+        properties.push_str(r#"<w:vertAlign w:val="superscript"/>"#);
+    }
+    for format in formats {
+        if let InlineFormat::TextColor { color } = format
+            && let Some(hex) = hex_color(color)
+        {
+            properties.push_str(&format!(r#"<w:color w:val="{}"/>"#, hex));
+        }
+    }
+    if formats.contains(&InlineFormat::Highlight) {
+        properties.push_str(r#"<w:highlight w:val="yellow"/>"#);
+    }
+    if formats.contains(&InlineFormat::Underline) {
+        properties.push_str(r#"<w:u w:val="single"/>"#);
+    }
+    for format in formats {
+        if let InlineFormat::BackgroundColor { color } = format
+            && let Some(hex) = hex_color(color)
+        {
+            properties.push_str(&format!(
+                r#"<w:shd w:val="clear" w:color="auto" w:fill="{}"/>"#,
+                hex
+            ));
+        }
+    }
+
+    let mut xml = String::from("<w:r>");
+    if !properties.is_empty() {
+        xml.push_str("<w:rPr>");
+        xml.push_str(&properties);
+        xml.push_str("</w:rPr>");
+    }
+
+    let mut segment = String::new();
+    for ch in text.chars() {
+        match ch {
+            '\t' => {
+                push_text(&mut xml, &mut segment);
+                xml.push_str("<w:tab/>");
+            }
+            SOFT_BREAK => {
+                push_text(&mut xml, &mut segment);
+                xml.push_str("<w:br/>");
+            }
+            // Images are not exported; other control characters are not
+            // allowed in XML
+            EMBED_CHAR => {}
+            c if c.is_control() => {}
+            c => segment.push(c),
+        }
+    }
+    push_text(&mut xml, &mut segment);
+    xml.push_str("</w:r>");
+    xml
+}
+
+/// Appends a text element for the pending segment and clears it
+fn push_text(xml: &mut String, segment: &mut String) {
+    if !segment.is_empty() {
+        xml.push_str(&format!(
+            r#"<w:t xml:space="preserve">{}</w:t>"#,
+            escape_xml(segment)
+        ));
+        segment.clear();
+    }
+}
+
+/// Builds `word/styles.xml` with heading, quote, code and hyperlink styles
+fn styles_xml() -> String {
+    let mut styles = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:style w:type="paragraph" w:default="1" w:styleId="Normal"><w:name w:val="Normal"/><w:pPr><w:spacing w:after="120"/></w:pPr></w:style>"#,
+    );
+    for (index, size) in HEADING_SIZES.iter().enumerate() {
+        let level = index + 1;
+        styles.push_str(&format!(
+            r#"<w:style w:type="paragraph" w:styleId="Heading{level}"><w:name w:val="heading {level}"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="240"/><w:outlineLvl w:val="{outline}"/></w:pPr><w:rPr><w:b/><w:sz w:val="{size}"/></w:rPr></w:style>"#,
+            level = level,
+            outline = index,
+            size = size
+        ));
+    }
+    styles.push_str(r#"<w:style w:type="paragraph" w:styleId="Quote"><w:name w:val="Quote"/><w:basedOn w:val="Normal"/><w:qFormat/><w:pPr><w:ind w:left="720"/></w:pPr><w:rPr><w:i/><w:color w:val="595959"/></w:rPr></w:style>"#);
+    styles.push_str(r#"<w:style w:type="paragraph" w:styleId="Code"><w:name w:val="Code"/><w:basedOn w:val="Normal"/><w:pPr><w:spacing w:after="0"/></w:pPr><w:rPr><w:rFonts w:ascii="Courier New" w:hAnsi="Courier New"/></w:rPr></w:style>"#);
+    styles.push_str(r#"<w:style w:type="character" w:styleId="Hyperlink"><w:name w:val="Hyperlink"/><w:rPr><w:color w:val="0563C1"/><w:u w:val="single"/></w:rPr></w:style>"#);
+    styles.push_str("</w:styles>");
+    styles
+}
+
+/// Converts a CSS color to the `RRGGBB` form Word expects
+pub(crate) fn hex_color(color: &str) -> Option<String> {
+    let color = color.trim().to_lowercase();
+
+    if let Some(hex) = color.strip_prefix('#') {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        return match hex.len() {
+            3 => Some(
+                hex.chars()
+                    .flat_map(|c| [c, c])
+                    .collect::<String>()
+                    .to_uppercase(),
+            ),
+            // Word has no alpha channel, so drop it
+            6 | 8 => Some(hex[..6].to_uppercase()),
+            _ => None,
+        };
+    }
+
+    if let Some(args) = color
+        .strip_prefix("rgba(")
+        .or_else(|| color.strip_prefix("rgb("))
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let channels: Vec<u8> = args
+            .split(',')
+            .take(3)
+            .map(|c| c.trim().parse::<u8>().ok())
+            .collect::<Option<_>>()?;
+        return (channels.len() == 3)
+            .then(|| format!("{:02X}{:02X}{:02X}", channels[0], channels[1], channels[2]));
+    }
+
+    let hex = match color.as_str() {
+        "black" => "000000",
+        "white" => "FFFFFF",
+        "red" => "FF0000",
+        "green" => "008000",
+        "blue" => "0000FF",
+        "yellow" => "FFFF00",
+        "cyan" | "aqua" => "00FFFF",
+        "magenta" | "fuchsia" => "FF00FF",
+        "gray" | "grey" => "808080",
+        "orange" => "FFA500",
+        "purple" => "800080",
+        "pink" => "FFC0CB",
+        "brown" => "A52A2A",
+        "navy" => "000080",
+        "teal" => "008080",
+        "olive" => "808000",
+        "lime" => "00FF00",
+        "silver" => "C0C0C0",
+        "maroon" => "800000",
+        _ => return None,
+    };
+    Some(hex.to_string())
+}
+
+/// Escapes special XML characters
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads the stored entry at `path` back out of a .docx package
+    fn read_part(docx: &[u8], path: &str) -> String {
+        let mut offset = 0;
+        while offset + 30 <= docx.len() && docx[offset..offset + 4] == [0x50, 0x4b, 0x03, 0x04] {
+            let size =
+                u32::from_le_bytes(docx[offset + 18..offset + 22].try_into().unwrap()) as usize;
+            let name_len = u16::from_le_bytes([docx[offset + 26], docx[offset + 27]]) as usize;
+            let name = std::str::from_utf8(&docx[offset + 30..offset + 30 + name_len]).unwrap();
+            let data_start = offset + 30 + name_len;
+            if name == path {
+                return String::from_utf8(docx[data_start..data_start + size].to_vec()).unwrap();
+            }
+            offset = data_start + size;
+        }
+        panic!("part {} not found", path);
+    }
+
+    #[test]
+    fn test_package_parts() {
+        let docx = Document::from_text("Hello").to_docx();
+        for part in [
+            "[Content_Types].xml",
+            "_rels/.rels",
+            "word/_rels/document.xml.rels",
+            "word/styles.xml",
+            "word/numbering.xml",
+        ] {
+            assert!(read_part(&docx, part).starts_with("<?xml"));
+        }
+        assert!(
+            read_part(&docx, "word/document.xml")
+                .contains(r#"<w:p><w:r><w:t xml:space="preserve">Hello</w:t></w:r></w:p>"#)
+        );
+    }
+
+    #[test]
+    fn test_inline_formats() {
+        let mut doc = Document::from_text("a<b&c");
+        doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+        doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Italic);
+        doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Underline);
+        doc.apply_format(
+            Range::from_offsets(0, 5),
+            InlineFormat::TextColor {
+                color: "#f00".to_string(),
+            },
+        );
+
+        let xml = read_part(&doc.to_docx(), "word/document.xml");
+        assert!(xml.contains(
+            r#"<w:rPr><w:b/><w:i/><w:color w:val="FF0000"/><w:u w:val="single"/></w:rPr><w:t xml:space="preserve">a&lt;b&amp;c</w:t>"#
+        ));
+    }
+
+    #[test]
+    fn test_soft_breaks() {
+        let mut doc = Document::from_text("one\u{2028}two\nthree");
+        doc.apply_format(Range::from_offsets(0, 7), InlineFormat::Bold);
+
+        let xml = read_part(&doc.to_docx(), "word/document.xml");
+        assert!(xml.contains(
+            r#"<w:p><w:r><w:rPr><w:b/></w:rPr><w:t xml:space="preserve">one</w:t><w:br/><w:t xml:space="preserve">two</w:t></w:r></w:p>"#
+        ));
+        assert!(xml.contains(r#"<w:p><w:r><w:t xml:space="preserve">three</w:t></w:r></w:p>"#));
+    }
+
+    #[test]
+    fn test_headings_and_lists() {
+        let mut doc = Document::from_text("Title\nOne\nTwo");
+        doc.set_block_type(Range::from_offsets(0, 5), BlockType::heading(2));
+        doc.set_block_type(Range::from_offsets(6, 9), BlockType::BulletList);
+        doc.set_block_type(Range::from_offsets(10, 13), BlockType::NumberedList);
+
+        let xml = read_part(&doc.to_docx(), "word/document.xml");
+        assert!(xml.contains(r#"<w:pStyle w:val="Heading2"/>"#));
+        assert!(xml.contains(r#"<w:numId w:val="1"/>"#));
+        assert!(xml.contains(r#"<w:numId w:val="2"/>"#));
+        assert_eq!(xml.matches("<w:p>").count(), 3);
+    }
+
+    #[test]
+    fn test_numbered_lists_restart() {
+        let mut doc = Document::from_text("One\nTwo\nBreak\nThree");
+        doc.set_block_type(Range::from_offsets(0, 7), BlockType::NumberedList);
+        doc.set_block_type(Range::from_offsets(8, 13), BlockType::Paragraph);
+        doc.set_block_type(Range::from_offsets(14, 19), BlockType::NumberedList);
+
+        let docx = doc.to_docx();
+        let xml = read_part(&docx, "word/document.xml");
+        assert_eq!(xml.matches(r#"<w:numId w:val="2"/>"#).count(), 2);
+        assert_eq!(xml.matches(r#"<w:numId w:val="3"/>"#).count(), 1);
+
+        let numbering = read_part(&docx, "word/numbering.xml");
+        assert!(numbering.contains(r#"<w:num w:numId="3"><w:abstractNumId w:val="1"/><w:lvlOverride w:ilvl="0"><w:startOverride w:val="1"/></w:lvlOverride></w:num>"#));
+        assert!(!numbering.contains(r#"w:numId="4""#));
+    }
+
+    #[test]
+    fn test_links() {
+        let mut doc = Document::from_text("see here and there");
+        let link = |url: &str| InlineFormat::Link {
+            url: url.to_string(),
+        };
+        doc.apply_format(
+            Range::from_offsets(4, 8),
+            link("https://a.example/?x=1&y=2"),
+        );
+        doc.apply_format(Range::from_offsets(6, 8), InlineFormat::Bold);
+        doc.apply_format(
+            Range::from_offsets(13, 18),
+            link("https://a.example/?x=1&y=2"),
+        );
+
+        let docx = doc.to_docx();
+        let xml = read_part(&docx, "word/document.xml");
+        // The bold part of the first link stays inside the same hyperlink
+        assert_eq!(xml.matches(r#"<w:hyperlink r:id="rId3">"#).count(), 2);
+        assert_eq!(xml.matches("</w:hyperlink>").count(), 2);
+
+        let rels = read_part(&docx, "word/_rels/document.xml.rels");
+        assert!(rels.contains(r#"Id="rId3""#));
+        assert!(rels.contains("https://a.example/?x=1&amp;y=2"));
+        assert!(!rels.contains(r#"Id="rId4""#));
+    }
+
+    #[test]
+    fn test_hex_color() {
+        assert_eq!(hex_color("#abc").as_deref(), Some("AABBCC"));
+        assert_eq!(hex_color("#11223344").as_deref(), Some("112233"));
+        assert_eq!(hex_color("rgb(255, 0, 16)").as_deref(), Some("FF0010"));
+        assert_eq!(hex_color("Navy").as_deref(), Some("000080"));
+        assert_eq!(hex_color("hsl(0, 100%, 50%)"), None);
+        assert_eq!(hex_color("#zzz"), None);
+    }
+}
@@ -0,0 +1,287 @@
+//! Front matter at the start of a Markdown file
+//!
+//! YAML front matter sits between `---` lines and TOML front matter between
+//! `+++` lines. Only flat key/value pairs are understood: quoted strings are
+//! unquoted, other scalars are kept as written, and nested YAML or
+//! multi-line TOML values are kept as their raw text so they are written
+//! back unchanged.
+
+use crate::document::{FrontMatterFormat, Metadata};
+
+/// Splits front matter off the start of `markdown`
+///
+/// # Returns
+/// The metadata, the Markdown after the front matter, and the number of
+/// source lines the front matter took up, or None if `markdown` does not
+/// start with front matter
+pub(crate) fn split(markdown: &str) -> Option<(Metadata, &str, usize)> {
+    let mut lines = markdown.split('\n');
+    let (format, fence) = match lines.next()?.trim_end() {
+        "---" => (FrontMatterFormat::Yaml, "---"),
+        "+++" => (FrontMatterFormat::Toml, "+++"),
+        _ => return None,
+    };
+
+    let mut body: Vec<&str> = Vec::new();
+    let mut consumed = fence.len() + 1;
+    let mut closed = false;
+    for line in lines {
+        consumed += line.len() + 1;
+        let trimmed = line.trim_end();
+        if trimmed == fence || (format == FrontMatterFormat::Yaml && trimmed == "...") {
+            closed = true;
+            break;
+        }
+        body.push(trimmed);
+    }
+    if !closed {
+        return None;
+    }
+
+    let mut metadata = match format {
+        FrontMatterFormat::Yaml => parse_yaml(&body)?,
+        FrontMatterFormat::Toml => parse_toml(&body)?,
+    };
+    metadata.set_format(format);
+    let rest = markdown.get(consumed..).unwrap_or("");
+    Some((metadata, rest, body.len() + 2))
+}
+
+/// Writes metadata as front matter, ending with a newline, or returns an
+/// empty string if there is no metadata
+pub(crate) fn render(metadata: &Metadata) -> String {
+    if metadata.is_empty() {
+        return String::new();
+    }
+    let (fence, separator) = match metadata.format() {
+        FrontMatterFormat::Yaml => ("---", ":"),
+        FrontMatterFormat::Toml => ("+++", " ="),
+    };
+
+    let mut result = format!("{}\n", fence);
+    for (key, value) in metadata.iter() {
+        let key = if is_bare_key(key) {
+            key.to_string()
+        } else {
+            quote(key)
+        };
+        let value = match metadata.format() {
+            FrontMatterFormat::Yaml => yaml_value(value),
+            FrontMatterFormat::Toml => toml_value(value),
+        };
+        result.push_str(&key);
+        result.push_str(separator);
+        if !value.starts_with('\n') {
+            result.push(' ');
+        }
+        result.push_str(&value);
+        result.push('\n');
+    }
+    result.push_str(fence);
+    result.push('\n');
+    result
+}
+
+/// Reads `key: value` lines; indented lines and `- ` items continue the
+/// value before them
+///
+/// Returns None if the block does not start with a key, so a thematic break
+/// followed by text is not taken for front matter.
+fn parse_yaml(lines: &[&str]) -> Option<Metadata> {
+    let mut metadata = Metadata::new();
+    let mut last_key: Option<String> = None;
+
+    for &line in lines {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with([' ', '\t', '-']) {
+            let key = last_key.as_deref()?;
+            let value = format!("{}\n{}", metadata.get(key).unwrap_or(""), line);
+            metadata.set(key, value);
+            continue;
+        }
+
+        let (key, value) = split_yaml_pair(line)?;
+        let key = unquote(key.trim());
+        metadata.set(key.clone(), unquote(value.trim()));
+        last_key = Some(key);
+    }
+    Some(metadata)
+}
+
+/// Splits a YAML line at the first `:` that ends the key
+fn split_yaml_pair(line: &str) -> Option<(&str, &str)> {
+    let index = line.char_indices().find_map(|(i, c)| {
+        let rest = &line[i + 1..];
+        (c == ':' && (rest.is_empty() || rest.starts_with([' ', '\t']))).then_some(i)
+    })?;
+    Some((&line[..index], &line[index + 1..]))
+}
+
+/// Reads `key = value` lines, prefixing keys with the `[table]` they are in;
+/// lines that are not pairs continue the value before them
+fn parse_toml(lines: &[&str]) -> Option<Metadata> {
+    let mut metadata = Metadata::new();
+    let mut table = String::new();
+    let mut last_key: Option<String> = None;
+
+    for &line in lines {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if trimmed.starts_with('[')
+            && trimmed.ends_with(']')
+            && !trimmed.starts_with("[[")
+            && !trimmed.contains('=')
+        {
+            table = format!("{}.", trimmed[1..trimmed.len() - 1].trim());
+            last_key = None;
+            continue;
+        }
+
+        match trimmed.split_once('=') {
+            Some((key, value)) if is_toml_key(key.trim()) => {
+                let key = format!("{}{}", table, unquote(key.trim()));
+                metadata.set(key.clone(), unquote(value.trim()));
+                last_key = Some(key);
+            }
+            _ => {
+                let key = last_key.as_deref()?;
+                let value = format!("{}\n{}", metadata.get(key).unwrap_or(""), line);
+                metadata.set(key, value);
+            }
+        }
+    }
+    Some(metadata)
+}
+
+/// Checks if text before a `=` is a bare or quoted TOML key
+fn is_toml_key(key: &str) -> bool {
+    let quoted = key.len() >= 2
+        && ((key.starts_with('"') && key.ends_with('"'))
+            || (key.starts_with('\'') && key.ends_with('\'')));
+    quoted || is_bare_key(key)
+}
+
+/// Removes the quotes around a double- or single-quoted string, keeping
+/// anything else as written
+fn unquote(text: &str) -> String {
+    if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        if let Ok(unquoted) = serde_json::from_str::<String>(text) {
+            return unquoted;
+        }
+    } else if text.len() >= 2 && text.starts_with('\'') && text.ends_with('\'') {
+        return text[1..text.len() - 1].replace("''", "'");
+    }
+    text.to_string()
+}
+
+/// Double-quotes a string, escaping it as JSON, which both YAML and TOML
+/// accept
+fn quote(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_default()
+}
+
+/// Checks if a key can be written without quotes
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        && !key.starts_with(['-', '.'])
+}
+
+/// Writes a YAML value, quoting it unless it reads back as the same string
+fn yaml_value(value: &str) -> String {
+    if is_raw_block(value) {
+        return value.to_string();
+    }
+    let plain = !value.is_empty()
+        && value.trim() == value
+        && !value.contains(['\n', '\r'])
+        && !value.contains(": ")
+        && !value.contains(" #")
+        && !value.ends_with(':')
+        && !value.starts_with([
+            '-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%',
+            '@', '`',
+        ]);
+    if plain {
+        value.to_string()
+    } else {
+        quote(value)
+    }
+}
+
+/// Writes a TOML value, leaving numbers, booleans, dates and arrays bare
+fn toml_value(value: &str) -> String {
+    if is_raw_block(value) {
+        return value.to_string();
+    }
+    let bytes = value.as_bytes();
+    let is_date = bytes.len() >= 10
+        && bytes[..4].iter().all(u8::is_ascii_digit)
+        && bytes[4] == b'-'
+        && !value.contains(char::is_whitespace);
+    let bare = matches!(value, "true" | "false")
+        || value.parse::<i64>().is_ok()
+        || (value.parse::<f64>().is_ok() && value.contains(|c: char| c.is_ascii_digit()))
+        || is_date
+        || (value.starts_with('[') && value.ends_with(']'))
+        || (value.starts_with('{') && value.ends_with('}'));
+    if bare {
+        value.to_string()
+    } else {
+        quote(value)
+    }
+}
+
+/// Checks if a value is nested YAML or a multi-line TOML value kept as it
+/// was imported: every line after the first is indented or a list item
+fn is_raw_block(value: &str) -> bool {
+    let mut lines = value.split('\n');
+    lines.next();
+    let mut rest = lines.peekable();
+    rest.peek().is_some() && rest.all(|line| line.starts_with([' ', '\t', '-', ']', '}']))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yaml_round_trip() {
+        let markdown =
+            "---\ntitle: \"Notes: day 1\"\ndraft: true\ntags:\n  - rust\n  - wasm\n---\n# Hi";
+        let (metadata, rest, lines) = split(markdown).unwrap();
+        assert_eq!(rest, "# Hi");
+        assert_eq!(lines, 7);
+        assert_eq!(metadata.get("title"), Some("Notes: day 1"));
+        assert_eq!(metadata.get("draft"), Some("true"));
+        assert_eq!(metadata.get("tags"), Some("\n  - rust\n  - wasm"));
+        assert_eq!(render(&metadata), &markdown[..markdown.len() - 4]);
+    }
+
+    #[test]
+    fn test_toml_tables_become_dotted_keys() {
+        let markdown = "+++\ntitle = 'It''s here'\ncount = 3\n\n[author]\nname = \"Ada\"\n+++\n";
+        let (metadata, rest, _) = split(markdown).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(metadata.format(), FrontMatterFormat::Toml);
+        assert_eq!(metadata.get("title"), Some("It's here"));
+        assert_eq!(metadata.get("author.name"), Some("Ada"));
+        assert_eq!(
+            render(&metadata),
+            "+++\ntitle = \"It's here\"\ncount = 3\nauthor.name = \"Ada\"\n+++\n"
+        );
+    }
+
+    #[test]
+    fn test_thematic_break_is_not_front_matter() {
+        assert!(split("---\nSome text\n---\n").is_none());
+        assert!(split("---\ntitle: never closed").is_none());
+        assert!(split("Text\n---\na: b\n---").is_none());
+    }
+}
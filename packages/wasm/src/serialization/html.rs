@@ -1,5 +1,13 @@
-use crate::document::{Document, Position, Range};
-use crate::formatting::{BlockType, InlineFormat};
+use crate::document::embeds::is_safe_image_src;
+use crate::document::metadata;
+use crate::document::{DirtyRegion, Document, EMBED_CHAR, Image, Position, Range, SOFT_BREAK};
+use crate::formatting::block::MAX_BLOCK_INDENT;
+use crate::formatting::{BlockType, InlineFormat, ListStyle, TextDirection};
+use crate::serialization::entities;
+use crate::serialization::html_parser::{self, Attributes, HtmlToken, VOID_TAGS};
+use crate::serialization::limits::{ImportLimit, ImportLimits, LimitExceeded};
+use crate::serialization::rtf::decode_cp1252;
+use std::cmp::Reverse;
 use std::collections::HashSet;
 use thiserror::Error;
 
@@ -14,19 +22,261 @@ pub enum HtmlError {
 
     #[error("Sanitization error: {0}")]
     SanitizationError(String),
+
+    #[error(transparent)]
+    LimitExceeded(#[from] LimitExceeded),
+}
+
+/// Incremental state for rendering a document to HTML one line at a time
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HtmlRenderer {
+    /// HTML produced so far
+    output: String,
+    /// Index of the next line to render
+    line_idx: usize,
+    /// Character offset where the next line starts
+    offset: usize,
+    /// Types and styles of the `<ul>`/`<ol>` elements currently open,
+    /// outermost first
+    ///
+    /// Each open list also has an open `<li>` that a deeper list nests in.
+    open_lists: Vec<(BlockType, Option<ListStyle>)>,
+    /// How formats and blocks are written
+    options: HtmlExportOptions,
+}
+
+impl HtmlRenderer {
+    /// Creates a renderer positioned at the start of the document
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a renderer that writes the document with `options`
+    fn with_options(options: HtmlExportOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Advances past a line ending at `line_end` without rendering it
+    fn skip_line(&mut self, line_end: usize) {
+        self.line_idx += 1;
+        self.offset = line_end + 1; // +1 for the newline character
+    }
+
+    /// Closes open lists until only `depth` remain
+    fn close_lists_to(&mut self, depth: usize) {
+        while self.open_lists.len() > depth {
+            match self.open_lists.pop() {
+                Some((BlockType::NumberedList, _)) => self.output.push_str("</li>\n</ol>\n"),
+                _ => self.output.push_str("</li>\n</ul>\n"),
+            }
+        }
+    }
+
+    /// Opens or closes lists so a list item of `list_type` and `style` at
+    /// nesting level `indent` can be written next
+    ///
+    /// An item is nested at most one level deeper than the item before it.
+    /// A new list opened for an item restarting the count at `start` starts
+    /// there. Returns true if the item continues an open list.
+    fn enter_list_item(
+        &mut self,
+        list_type: &BlockType,
+        style: Option<ListStyle>,
+        indent: u8,
+        start: Option<usize>,
+    ) -> bool {
+        let depth = (indent as usize).min(self.open_lists.len());
+        self.close_lists_to(depth + 1);
+
+        if self.open_lists.len() == depth + 1 {
+            if self.open_lists[depth] == (list_type.clone(), style) {
+                // Another item in the same list
+                self.output.push_str("</li>\n");
+                return true;
+            }
+            self.close_lists_to(depth);
+        }
+        if !self.output.is_empty() && !self.output.ends_with('\n') {
+            // A nested list starts on its own line inside the parent item
+            self.output.push('\n');
+        }
+
+        let tag = match list_type {
+            BlockType::NumberedList => "ol",
+            _ => "ul",
+        };
+        let start = start
+            .map(|start| format!(" start=\"{}\"", start))
+            .unwrap_or_default();
+        match style {
+            Some(style) => self.output.push_str(&format!(
+                "<{}{} style=\"list-style-type: {}\">\n",
+                tag,
+                start,
+                escape_html_attribute(&style.css_value())
+            )),
+            None => self.output.push_str(&format!("<{}{}>\n", tag, start)),
+        }
+        self.open_lists.push((list_type.clone(), style));
+        false
+    }
+
+    /// Closes any open list and returns the rendered HTML
+    fn finish(mut self) -> String {
+        self.close_lists_to(0);
+        self.output
+    }
+}
+
+/// Document HTML rendered ahead of time by `prerender_html_chunk`
+///
+/// Entries are stamped with the document version they were rendered for
+/// and are ignored once the document changes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HtmlCache {
+    version: u64,
+    /// Partially rendered output while pre-rendering is in progress
+    renderer: Option<HtmlRenderer>,
+    /// Full document HTML once pre-rendering has finished
+    html: Option<String>,
+}
+
+impl HtmlCache {
+    /// Creates an empty cache
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Discards cached output and starts a fresh render for `version`
+    fn reset(&mut self, version: u64) {
+        self.version = version;
+        self.renderer = Some(HtmlRenderer::new());
+        self.html = None;
+    }
+
+    /// Stores the finished HTML for the current version
+    fn complete(&mut self, html: String) {
+        self.renderer = None;
+        self.html = Some(html);
+    }
+}
+
+/// A stretch of document text and where it appears in exported HTML
+///
+/// Offsets and indices count characters. Each mapping covers text that
+/// appears literally in the HTML, a single escaped character, an image, or
+/// (with an empty model range) an empty block element.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlSourceMapping {
+    /// Document offset where the mapped text starts
+    pub model_start: usize,
+    /// Document offset where the mapped text ends
+    pub model_end: usize,
+    /// Index in the HTML string where the text starts
+    pub html_start: usize,
+    /// Index in the HTML string where the text ends
+    pub html_end: usize,
+    /// Child node indexes from the HTML root to the node holding the text
+    ///
+    /// Indexes count every child node, including whitespace text between
+    /// elements, so they match `childNodes` of an element whose
+    /// `innerHTML` is the exported HTML.
+    pub node_path: Vec<usize>,
+    /// Offset of the text within its text node (0 for elements)
+    pub node_offset: usize,
+}
+
+/// Mapping between document offsets and exported HTML
+///
+/// Returned by `Document::to_html_with_source_map`. Mappings are ordered by
+/// both document offset and HTML index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HtmlSourceMap {
+    mappings: Vec<HtmlSourceMapping>,
+}
+
+impl HtmlSourceMap {
+    /// Returns the mappings in document order
+    pub fn mappings(&self) -> &[HtmlSourceMapping] {
+        &self.mappings
+    }
+
+    /// Returns the HTML index of a document offset
+    ///
+    /// Offsets without text in the HTML map to the end of the preceding
+    /// mapping.
+    pub fn html_index(&self, offset: usize) -> usize {
+        self.translate(
+            offset,
+            |m| (m.model_start, m.model_end),
+            |m| (m.html_start, m.html_end),
+        )
+    }
+
+    /// Returns the document offset of an HTML index
+    ///
+    /// Indices inside markup map to the end of the preceding mapping.
+    pub fn model_offset(&self, html_index: usize) -> usize {
+        self.translate(
+            html_index,
+            |m| (m.html_start, m.html_end),
+            |m| (m.model_start, m.model_end),
+        )
+    }
+
+    /// Translates a position from one side of the mappings to the other
+    ///
+    /// At a boundary between two mappings the later one wins, so the start
+    /// of a paragraph maps into that paragraph rather than the one before.
+    fn translate(
+        &self,
+        position: usize,
+        from: impl Fn(&HtmlSourceMapping) -> (usize, usize),
+        to: impl Fn(&HtmlSourceMapping) -> (usize, usize),
+    ) -> usize {
+        let index = self.mappings.partition_point(|m| from(m).1 < position);
+        let candidates = self.mappings[index..]
+            .iter()
+            .take_while(|m| from(m).0 <= position);
+        let chosen = candidates
+            .clone()
+            .find(|m| {
+                let (start, end) = from(m);
+                start == end || position < end
+            })
+            .or(candidates.last());
+
+        let Some(mapping) = chosen else {
+            return index
+                .checked_sub(1)
+                .map_or(0, |previous| to(&self.mappings[previous]).1);
+        };
+        let (from_start, from_end) = from(mapping);
+        let (to_start, to_end) = to(mapping);
+        if from_end - from_start == to_end - to_start {
+            to_start + (position - from_start)
+        } else if position == from_start {
+            to_start
+        } else {
+            to_end
+        }
+    }
 }
 
 impl Document {
     /// Exports the document to plain text format, stripping all formatting
     ///
     /// This method returns the raw text content without any inline or block formatting.
-    /// Line breaks are preserved.
+    /// Line breaks are preserved; soft breaks become newlines.
     ///
     /// # Returns
     ///
     /// A plain text string representation of the document.
     pub fn to_plain_text(&self) -> String {
-        self.get_content()
+        self.get_content().replace(SOFT_BREAK, "\n")
     }
 
     /// Imports a document from plain text
@@ -56,24 +306,118 @@ impl Document {
     /// - Link: `<a href="url">`
     /// - TextColor: `<span style="color: ...">`
     /// - BackgroundColor: `<span style="background-color: ...">`
+    /// - Highlight: `<mark>`
+    /// - SmallCaps: `<span style="font-variant: small-caps;">`
+    /// - Subscript: `<sub>`
+    /// - Superscript: `<sup>`
+    /// - Images: `<img src="..." alt="..." width="..." height="...">`
     ///
     /// Maps block types to HTML tags:
     /// - Paragraph: `<p>`
     /// - Heading: `<h1>` through `<h6>`
     /// - BulletList: `<ul><li>`
     /// - NumberedList: `<ol><li>`
-    /// - BlockQuote: `<blockquote>`
-    /// - CodeBlock: `<pre><code>`
+    /// - Indented list items: a `<ul>`/`<ol>` nested in the parent `<li>`
+    /// - List styles: a `list-style-type` style on the `<ul>`/`<ol>`
+    /// - Paragraph indents: `margin-left` and `text-indent` styles on the `<p>`
+    /// - Text directions set on a line: a `dir` attribute on its element
+    /// - BlockQuote: `<blockquote cite="...">`, nested once per depth level
+    /// - CodeBlock: `<pre><code>`, with a `language-*` class for the language
     ///
     /// # Returns
     ///
     /// An HTML string representation of the document with semantic tags.
     pub fn to_html(&self) -> String {
+        if let Some(html) = &self.html_cache.html
+            && self.html_cache.version == self.version()
+        {
+            return html.clone();
+        }
+
         let content = self.get_content();
         if content.is_empty() {
             return "<p></p>\n".to_string();
         }
-        self.to_html_range(None)
+        self.render_html_range(None, HtmlExportOptions::default())
+    }
+
+    /// Exports the document as a complete HTML page
+    ///
+    /// The body holds the same markup as `to_html`. The head carries the
+    /// document metadata: the title as `<title>`, and every entry as a
+    /// `<meta name="..." content="...">` tag.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rte_core::document::Document;
+    ///
+    /// let mut doc = Document::from_text("Hello");
+    /// doc.set_metadata("author", "Ada").unwrap();
+    /// let html = doc.to_html_document();
+    /// assert!(html.contains("<meta name=\"author\" content=\"Ada\">"));
+    /// assert!(html.contains("<body>\n<p>Hello</p>\n</body>"));
+    /// ```
+    pub fn to_html_document(&self) -> String {
+        self.to_html_with_options(&HtmlExportOptions::new().standalone(true))
+    }
+
+    /// Exports the document to HTML with the given options
+    ///
+    /// The default options give the same output as `to_html`. Formats can
+    /// instead be written as `rte-*` classes or inline styles on `<span>`
+    /// elements, blocks can carry a `data-offset` attribute with the
+    /// document offset where they start, and the output can be a complete
+    /// page as written by `to_html_document`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rte_core::document::{Document, Range};
+    /// use rte_core::formatting::InlineFormat;
+    /// use rte_core::serialization::html::{HtmlExportOptions, HtmlFormatStyle};
+    ///
+    /// let mut doc = Document::from_text("Hello\nworld");
+    /// doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+    /// let options = HtmlExportOptions::new()
+    ///     .format_style(HtmlFormatStyle::Classes)
+    ///     .data_offsets(true);
+    /// assert_eq!(
+    ///     doc.to_html_with_options(&options),
+    ///     "<p data-offset=\"0\"><span class=\"rte-bold\">Hello</span></p>\n<p data-offset=\"6\">world</p>\n"
+    /// );
+    /// ```
+    pub fn to_html_with_options(&self, options: &HtmlExportOptions) -> String {
+        let body = if options.format_style == HtmlFormatStyle::Semantic
+            && !options.data_offsets
+            && !options.decoration_marks
+        {
+            self.to_html()
+        } else if self.is_empty() {
+            format!("<p{}></p>\n", offset_attribute(options, 0))
+        } else {
+            self.render_html_range(None, *options)
+        };
+        if !options.standalone {
+            return body;
+        }
+
+        let mut result =
+            String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        if let Some(title) = self.get_metadata(metadata::TITLE) {
+            result.push_str(&format!("<title>{}</title>\n", escape_html(title)));
+        }
+        for (key, value) in self.metadata().iter() {
+            result.push_str(&format!(
+                "<meta name=\"{}\" content=\"{}\">\n",
+                escape_html_attribute(key),
+                escape_html_attribute(value)
+            ));
+        }
+        result.push_str("</head>\n<body>\n");
+        result.push_str(&body);
+        result.push_str("</body>\n</html>\n");
+        result
     }
 
     /// Exports a specific range of the document to HTML format
@@ -88,6 +432,9 @@ impl Document {
     /// # Returns
     ///
     /// An HTML string representation of the specified range with semantic tags.
+    /// Text covered by transient decorations is wrapped in
+    /// `<mark data-deco-id="...">` elements, so the output is meant for
+    /// display rather than saving.
     ///
     /// # Example
     ///
@@ -99,6 +446,11 @@ impl Document {
     /// let html = doc.to_html_range(Some(Range::from_offsets(0, 5)));
     /// ```
     pub fn to_html_range(&self, range: Option<Range>) -> String {
+        self.render_html_range(range, HtmlExportOptions::new().decoration_marks(true))
+    }
+
+    /// Renders a range of the document, or all of it, with `options`
+    fn render_html_range(&self, range: Option<Range>, options: HtmlExportOptions) -> String {
         let content = self.get_content();
         if content.is_empty() {
             return String::new();
@@ -112,139 +464,265 @@ impl Document {
             (0, self.get_length())
         };
 
-        let mut result = String::new();
+        let mut renderer = HtmlRenderer::with_options(options);
         let lines: Vec<&str> = content.split('\n').collect();
-        let mut current_offset = 0;
-        let mut in_list = false;
-        let mut current_list_type: Option<BlockType> = None;
 
         for (line_idx, line) in lines.iter().enumerate() {
-            let line_start = current_offset;
+            let line_start = renderer.offset;
             let line_end = line_start + line.chars().count();
 
             // Skip lines outside the render range
             if line_end < render_start {
-                current_offset = line_end + 1;
+                renderer.skip_line(line_end);
                 continue;
             }
             if line_start >= render_end {
                 break;
             }
 
-            // Get block type for this line
-            let block_type = if line_start < self.get_length() {
-                self.get_block_type_at(Position::new(line_start))
-            } else {
-                BlockType::Paragraph
-            };
+            let is_last_line = line_idx == lines.len() - 1;
+            self.render_html_line(&mut renderer, line, is_last_line);
+        }
+
+        renderer.finish()
+    }
 
-            // Handle list transitions
-            let is_list_item =
-                matches!(block_type, BlockType::BulletList | BlockType::NumberedList);
-
-            if is_list_item {
-                if !in_list || current_list_type != Some(block_type.clone()) {
-                    // Close previous list if different type
-                    if in_list {
-                        if let Some(BlockType::BulletList) = current_list_type {
-                            result.push_str("</ul>\n");
-                        } else if let Some(BlockType::NumberedList) = current_list_type {
-                            result.push_str("</ol>\n");
+    /// Exports a single block to HTML
+    ///
+    /// Blocks have no identifiers of their own: every line is a block, so
+    /// `index` is the line index. Lets a framework re-render just the
+    /// component for a changed block. A list item is rendered inside its
+    /// own `<ul>` or `<ol>`, and a code block line as a complete `<pre>`.
+    ///
+    /// # Returns
+    ///
+    /// The HTML for the block, or None if there is no line `index`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rte_core::document::Document;
+    ///
+    /// let doc = Document::from_text("One\nTwo");
+    /// assert_eq!(doc.block_to_html(1).as_deref(), Some("<p>Two</p>\n"));
+    /// assert_eq!(doc.block_to_html(2), None);
+    /// ```
+    pub fn block_to_html(&self, index: usize) -> Option<String> {
+        let line_start = self.line_column_to_position(index, 0)?.offset();
+        let line_end = self.line_end_from(line_start);
+        let line = self.get_text_in_range(Range::from_offsets(line_start, line_end));
+
+        let block_type = self.get_block_type_at(Position::new(line_start));
+        if block_type.is_code_block() {
+            let dir = dir_attribute(self.get_text_direction_at(Position::new(line_start)));
+            return Some(format!(
+                "<pre{}><code{}>{}</code></pre>\n",
+                dir,
+                code_language_attribute(&block_type),
+                escape_html(&line)
+            ));
+        }
+
+        let mut renderer = HtmlRenderer {
+            line_idx: index,
+            offset: line_start,
+            ..HtmlRenderer::default()
+        };
+        self.render_html_line(&mut renderer, &line, line_end >= self.get_length());
+        Some(renderer.finish())
+    }
+
+    /// Renders one line into the renderer and advances it to the next line
+    ///
+    /// Opens and closes list wrappers and merges consecutive code block lines
+    /// into a single `<pre><code>` element based on the renderer's state.
+    fn render_html_line(&self, renderer: &mut HtmlRenderer, line: &str, is_last_line: bool) {
+        let line_idx = renderer.line_idx;
+        let line_start = renderer.offset;
+        let line_end = line_start + line.chars().count();
+        let options = renderer.options;
+
+        // Get block type for this line
+        let block_type = if line_start < self.get_length() {
+            self.get_block_type_at(Position::new(line_start))
+        } else {
+            BlockType::Paragraph
+        };
+
+        // Handle list transitions
+        let mut item_value = None;
+        if matches!(block_type, BlockType::BulletList | BlockType::NumberedList) {
+            let indent = self.get_list_indent_at(Position::new(line_start));
+            let style = self.get_list_style_at(Position::new(line_start));
+            let start = self.get_list_start_at(Position::new(line_start));
+            // An item restarting the count in the middle of a list keeps
+            // its number
+            if renderer.enter_list_item(&block_type, style, indent, start) {
+                item_value = start;
+            }
+        } else {
+            renderer.close_lists_to(0);
+        }
+        let result = &mut renderer.output;
+        let dir = dir_attribute(self.get_text_direction_at(Position::new(line_start)))
+            + &offset_attribute(&options, line_start);
+
+        // Add opening block tag
+        match &block_type {
+            BlockType::Paragraph => {
+                let block_indent = self.get_block_indent_at(Position::new(line_start));
+                let first_line_indent = self.has_first_line_indent_at(Position::new(line_start));
+                result.push_str(&paragraph_open_tag(block_indent, first_line_indent, &dir));
+            }
+            BlockType::Heading { level } => {
+                result.push_str(&format!("<h{}{}>", level, dir));
+            }
+            BlockType::BulletList | BlockType::NumberedList => match item_value {
+                Some(value) => result.push_str(&format!("<li value=\"{}\"{}>", value, dir)),
+                None => result.push_str(&format!("<li{}>", dir)),
+            },
+            BlockType::BlockQuote { cite, depth } => {
+                // The citation and direction belong to the innermost quote
+                for level in 1..=*depth {
+                    match cite {
+                        Some(url) if level == *depth => {
+                            result.push_str(&format!(
+                                "<blockquote cite=\"{}\"{}>",
+                                escape_html_attribute(url),
+                                dir
+                            ));
                         }
+                        _ if level == *depth => {
+                            result.push_str(&format!("<blockquote{}>", dir));
+                        }
+                        _ => result.push_str("<blockquote>"),
                     }
-                    // Open new list
-                    match block_type {
-                        BlockType::BulletList => result.push_str("<ul>\n"),
-                        BlockType::NumberedList => result.push_str("<ol>\n"),
-                        _ => {}
-                    }
-                    in_list = true;
-                    current_list_type = Some(block_type.clone());
-                }
-            } else if in_list {
-                // Close list when transitioning to non-list block
-                if let Some(BlockType::BulletList) = current_list_type {
-                    result.push_str("</ul>\n");
-                } else if let Some(BlockType::NumberedList) = current_list_type {
-                    result.push_str("</ol>\n");
                 }
-                in_list = false;
-                current_list_type = None;
             }
-
-            // Add opening block tag
-            match block_type {
-                BlockType::Paragraph => result.push_str("<p>"),
-                BlockType::Heading { level } => {
-                    result.push_str(&format!("<h{}>", level));
-                }
-                BlockType::BulletList | BlockType::NumberedList => {
-                    result.push_str("<li>");
-                }
-                BlockType::BlockQuote => result.push_str("<blockquote>"),
-                BlockType::CodeBlock => {
-                    if line_idx == 0
-                        || self.get_block_type_at(Position::new(line_start.saturating_sub(1)))
-                            != BlockType::CodeBlock
-                    {
-                        result.push_str("<pre><code>");
-                    }
+            BlockType::CodeBlock { .. } => {
+                // Lines of the same code block share one element
+                if line_idx == 0
+                    || self.get_block_type_at(Position::new(line_start.saturating_sub(1)))
+                        != block_type
+                {
+                    result.push_str(&format!(
+                        "<pre{}><code{}>",
+                        dir,
+                        code_language_attribute(&block_type)
+                    ));
                 }
             }
+        }
 
-            // Process inline formats for this line
-            if block_type == BlockType::CodeBlock {
-                // In code blocks, escape HTML but don't process inline formats
-                result.push_str(&escape_html(line));
-            } else {
-                result.push_str(&self.format_line_with_html(line, line_start));
-            }
+        // Process inline formats for this line
+        if block_type.is_code_block() {
+            // In code blocks, escape HTML but don't process inline formats
+            result.push_str(&escape_html(line));
+        } else {
+            result.push_str(&self.format_line_with_html(line, line_start, options));
+        }
 
-            // Add closing block tag
-            match block_type {
-                BlockType::Paragraph => result.push_str("</p>\n"),
-                BlockType::Heading { level } => {
-                    result.push_str(&format!("</h{}>\n", level));
-                }
-                BlockType::BulletList | BlockType::NumberedList => {
-                    result.push_str("</li>\n");
-                }
-                BlockType::BlockQuote => result.push_str("</blockquote>\n"),
-                BlockType::CodeBlock => {
-                    let is_last_line = line_idx == lines.len() - 1;
-                    let next_is_code = if !is_last_line {
-                        let next_offset = line_end + 1;
-                        if next_offset < self.get_length() {
-                            self.get_block_type_at(Position::new(next_offset))
-                                == BlockType::CodeBlock
-                        } else {
-                            false
-                        }
+        // Add closing block tag
+        match &block_type {
+            BlockType::Paragraph => result.push_str("</p>\n"),
+            BlockType::Heading { level } => {
+                result.push_str(&format!("</h{}>\n", level));
+            }
+            // The item stays open for nested lists; `enter_list_item` or
+            // `close_lists_to` closes it
+            BlockType::BulletList | BlockType::NumberedList => {}
+            BlockType::BlockQuote { depth, .. } => {
+                result.push_str(&"</blockquote>".repeat(*depth as usize));
+                result.push('\n');
+            }
+            BlockType::CodeBlock { .. } => {
+                let next_is_code = if !is_last_line {
+                    let next_offset = line_end + 1;
+                    if next_offset < self.get_length() {
+                        self.get_block_type_at(Position::new(next_offset)) == block_type
                     } else {
                         false
-                    };
-
-                    if !next_is_code {
-                        result.push_str("</code></pre>\n");
-                    } else {
-                        result.push('\n');
                     }
+                } else {
+                    false
+                };
+
+                if !next_is_code {
+                    result.push_str("</code></pre>\n");
+                } else {
+                    result.push('\n');
                 }
             }
+        }
 
-            current_offset = line_end + 1; // +1 for the newline character
+        renderer.skip_line(line_end);
+    }
+
+    /// Pre-renders the next chunk of the document into the HTML cache
+    ///
+    /// Renders up to `max_lines` lines, continuing where the previous call
+    /// stopped. Once every line has been rendered, `to_html` returns the
+    /// cached output instead of rendering the document again. Any edit
+    /// invalidates the cache, and the next call starts over from the top.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_lines` - Maximum number of lines to render in this call
+    ///
+    /// # Returns
+    ///
+    /// `true` once the whole document has been rendered and cached.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rte_core::document::Document;
+    ///
+    /// let mut doc = Document::from_text("One\nTwo\nThree");
+    /// while !doc.prerender_html_chunk(1) {}
+    /// assert!(doc.is_html_prerendered());
+    /// assert_eq!(doc.to_html(), "<p>One</p>\n<p>Two</p>\n<p>Three</p>\n");
+    /// ```
+    pub fn prerender_html_chunk(&mut self, max_lines: usize) -> bool {
+        if self.html_cache.version != self.version() || self.html_cache.renderer.is_none() {
+            if self.is_html_prerendered() {
+                return true;
+            }
+            self.html_cache.reset(self.version());
         }
 
-        // Close any open lists at the end
-        if in_list {
-            if let Some(BlockType::BulletList) = current_list_type {
-                result.push_str("</ul>\n");
-            } else if let Some(BlockType::NumberedList) = current_list_type {
-                result.push_str("</ol>\n");
+        if self.is_empty() {
+            self.html_cache.complete("<p></p>\n".to_string());
+            return true;
+        }
+
+        let Some(mut renderer) = self.html_cache.renderer.take() else {
+            return true;
+        };
+        let length = self.get_length();
+
+        for _ in 0..max_lines {
+            let line_start = renderer.offset;
+            let line_end = self.line_end_from(line_start);
+            let line = self.get_text_in_range(Range::from_offsets(line_start, line_end));
+            let is_last_line = line_end >= length;
+
+            self.render_html_line(&mut renderer, &line, is_last_line);
+
+            // A trailing empty line is not rendered, matching `to_html_range`
+            if is_last_line || renderer.offset >= length {
+                self.html_cache.complete(renderer.finish());
+                return true;
             }
         }
 
-        result
+        self.html_cache.renderer = Some(renderer);
+        false
+    }
+
+    /// Returns true if the HTML cache holds the current document in full
+    pub fn is_html_prerendered(&self) -> bool {
+        self.html_cache.version == self.version() && self.html_cache.html.is_some()
     }
 
     /// Exports HTML for all dirty regions in the document
@@ -282,56 +760,425 @@ impl Document {
             .collect()
     }
 
+    /// Exports HTML for each dirty region along with the region itself
+    ///
+    /// Like `to_html_dirty_regions`, but keeps each region's id and
+    /// version so renderers can acknowledge exactly what they painted.
+    pub fn to_html_dirty_region_entries(&self) -> Vec<(DirtyRegion, String)> {
+        self.dirty_regions()
+            .iter()
+            .map(|region| (region.clone(), self.to_html_range(Some(region.range))))
+            .collect()
+    }
+
+    /// Exports the document to HTML along with a source map
+    ///
+    /// The map relates document offsets to indices in the returned HTML and
+    /// to DOM node paths, so a preview pane can follow the editor's scroll
+    /// position and selection, and clicks in the preview can be mapped back.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rte_core::document::Document;
+    ///
+    /// let doc = Document::from_text("One\nTwo");
+    /// let (html, map) = doc.to_html_with_source_map();
+    /// assert_eq!(html, "<p>One</p>\n<p>Two</p>\n");
+    ///
+    /// // "Two" starts at offset 4 and is the text of the second <p>
+    /// assert_eq!(map.html_index(4), 14);
+    /// assert_eq!(map.model_offset(15), 5);
+    /// assert_eq!(map.mappings()[2].node_path, vec![2, 0]);
+    /// ```
+    pub fn to_html_with_source_map(&self) -> (String, HtmlSourceMap) {
+        let html = self.to_html();
+        let map = build_html_source_map(&html, &self.get_content());
+        (html, map)
+    }
+
     /// Formats a single line with HTML inline formatting
-    fn format_line_with_html(&self, line: &str, line_start: usize) -> String {
+    fn format_line_with_html(
+        &self,
+        line: &str,
+        line_start: usize,
+        options: HtmlExportOptions,
+    ) -> String {
         if line.is_empty() {
             return String::new();
         }
 
-        let line_len = line.chars().count();
+        let chars: Vec<char> = line.chars().collect();
+        let line_range = Range::from_offsets(line_start, line_start + chars.len());
         let mut result = String::new();
-        let mut pos = 0;
+        let marks: Vec<&(Range, String)> = if options.decoration_marks {
+            self.transient_decorations()
+                .iter()
+                .take_while(|(range, _)| range.start_offset() < line_range.end_offset())
+                .filter(|(range, _)| range.end_offset() > line_start)
+                .collect()
+        } else {
+            Vec::new()
+        };
 
-        while pos < line_len {
-            let abs_pos = line_start + pos;
-            let formats = if abs_pos < self.get_length() {
-                self.get_formats_at(Position::new(abs_pos))
-            } else {
-                HashSet::new()
+        // Walk the run boundaries once instead of querying formats per character
+        for (range, formats) in self.formats().styled_runs(line_range) {
+            let render_formats = |start: usize, end: usize| {
+                let run_text: String = chars[start - line_start..end - line_start].iter().collect();
+                match options.format_style {
+                    HtmlFormatStyle::Semantic => apply_html_formats(&run_text, &formats),
+                    format_style => apply_span_formats(&run_text, &formats, format_style),
+                }
             };
+            // Split where marks start or end, wrapping each piece in the
+            // marks covering it
+            let render = |start: usize, end: usize| {
+                let mut cuts: Vec<usize> = marks
+                    .iter()
+                    .flat_map(|(range, _)| [range.start_offset(), range.end_offset()])
+                    .filter(|&offset| start < offset && offset < end)
+                    .chain([end])
+                    .collect();
+                cuts.sort_unstable();
+                cuts.dedup();
+
+                let mut html = String::new();
+                let mut from = start;
+                for to in cuts {
+                    let covering: Vec<&String> = marks
+                        .iter()
+                        .filter(|(range, _)| {
+                            range.start_offset() <= from && to <= range.end_offset()
+                        })
+                        .map(|(_, id)| id)
+                        .collect();
+                    for id in &covering {
+                        html.push_str(&format!(
+                            "<mark data-deco-id=\"{}\">",
+                            escape_html_attribute(id)
+                        ));
+                    }
+                    html.push_str(&render_formats(from, to));
+                    html.push_str(&"</mark>".repeat(covering.len()));
+                    from = to;
+                }
+                html
+            };
+            // Text marked to skip spellchecking gets its own span
+            let mut start = range.start_offset();
+            for skipped in self.no_spellcheck().ranges_in(range) {
+                if start < skipped.start_offset() {
+                    result.push_str(&render(start, skipped.start_offset()));
+                }
+                result.push_str("<span spellcheck=\"false\">");
+                result.push_str(&render(skipped.start_offset(), skipped.end_offset()));
+                result.push_str("</span>");
+                start = skipped.end_offset();
+            }
+            if start < range.end_offset() {
+                result.push_str(&render(start, range.end_offset()));
+            }
+        }
+
+        self.embeds()
+            .render_line(result, line, line_start, image_to_html)
+    }
+}
+
+/// Builds a source map by walking exported HTML alongside the document text
+///
+/// The exporter writes document text in order, escaping only `&<>"'`, so
+/// each text character in the HTML is matched to the next document
+/// character when they are equal. Unmatched text is formatting whitespace
+/// between elements.
+fn build_html_source_map(html: &str, content: &str) -> HtmlSourceMap {
+    let html: Vec<char> = html.chars().collect();
+    let content: Vec<char> = content.chars().collect();
+    let mut mappings: Vec<HtmlSourceMapping> = Vec::new();
+    // Path of the open element and the number of children seen at each level
+    let mut path: Vec<usize> = Vec::new();
+    let mut child_counts: Vec<usize> = vec![0];
+    let mut model = 0;
+    let mut i = 0;
+    // Whitespace after an opening `<ul>`/`<ol>` is never document text
+    let mut after_list_open = false;
+
+    while i < html.len() {
+        if html[i] == '<' {
+            let end = html[i..]
+                .iter()
+                .position(|&c| c == '>')
+                .map_or(html.len(), |end| i + end + 1);
+            let tag: String = html[i + 1..end.saturating_sub(1)].iter().collect();
+            let name: String = tag
+                .trim_start_matches('/')
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .collect();
+            let child = *child_counts.last().unwrap_or(&0);
+
+            if tag.starts_with('/') {
+                path.pop();
+                if child_counts.len() > 1 {
+                    child_counts.pop();
+                }
+            } else if VOID_TAGS.contains(&name.as_str()) {
+                if let Some(count) = child_counts.last_mut() {
+                    *count += 1;
+                }
+                if name == "img" && content.get(model) == Some(&EMBED_CHAR) {
+                    let mut node_path = path.clone();
+                    node_path.push(child);
+                    mappings.push(HtmlSourceMapping {
+                        model_start: model,
+                        model_end: model + 1,
+                        html_start: i,
+                        html_end: end,
+                        node_path,
+                        node_offset: 0,
+                    });
+                    model += 1;
+                }
+            } else {
+                if let Some(count) = child_counts.last_mut() {
+                    *count += 1;
+                }
+                path.push(child);
+                child_counts.push(0);
+
+                // An empty block still gives the cursor somewhere to go
+                let is_block = matches!(name.as_str(), "p" | "li" | "code")
+                    || (name.len() == 2 && name.starts_with('h'));
+                if is_block && html.get(end) == Some(&'<') && html.get(end + 1) == Some(&'/') {
+                    mappings.push(HtmlSourceMapping {
+                        model_start: model,
+                        model_end: model,
+                        html_start: end,
+                        html_end: end,
+                        node_path: path.clone(),
+                        node_offset: 0,
+                    });
+                }
+            }
+            after_list_open = matches!(name.as_str(), "ul" | "ol") && !tag.starts_with('/');
+            i = end;
+            continue;
+        }
 
-            // Find the end of this format run
-            let mut run_end = pos + 1;
-            while run_end < line_len {
-                let next_abs_pos = line_start + run_end;
-                let next_formats = if next_abs_pos < self.get_length() {
-                    self.get_formats_at(Position::new(next_abs_pos))
+        // A text node runs until the next tag
+        let child = *child_counts.last().unwrap_or(&0);
+        if let Some(count) = child_counts.last_mut() {
+            *count += 1;
+        }
+        let mut node_path = path.clone();
+        node_path.push(child);
+        let mut node_offset = 0;
+
+        while i < html.len() && html[i] != '<' {
+            let (c, width) = decode_exported_char(&html[i..]);
+            if !after_list_open && content.get(model) == Some(&c) {
+                // Literal characters extend the previous mapping
+                let extends = width == 1
+                    && mappings.last().is_some_and(|last| {
+                        last.node_path == node_path
+                            && last.model_end == model
+                            && last.html_end == i
+                            && last.model_end - last.model_start == last.html_end - last.html_start
+                    });
+                if extends {
+                    if let Some(last) = mappings.last_mut() {
+                        last.model_end += 1;
+                        last.html_end += 1;
+                    }
                 } else {
-                    HashSet::new()
-                };
-                if next_formats != formats {
-                    break;
+                    mappings.push(HtmlSourceMapping {
+                        model_start: model,
+                        model_end: model + 1,
+                        html_start: i,
+                        html_end: i + width,
+                        node_path: node_path.clone(),
+                        node_offset,
+                    });
                 }
-                run_end += 1;
+                model += 1;
             }
+            node_offset += 1;
+            i += width;
+        }
+        after_list_open = false;
+    }
 
-            // Extract the text for this run
-            let run_text: String = line.chars().skip(pos).take(run_end - pos).collect();
+    HtmlSourceMap { mappings }
+}
 
-            // Apply formats
-            let formatted = apply_html_formats(&run_text, &formats);
-            result.push_str(&formatted);
+/// Decodes the character at the start of exported HTML text
+///
+/// Returns the character and the number of HTML characters it takes up.
+/// Only the references written by `escape_html` are recognized.
+fn decode_exported_char(text: &[char]) -> (char, usize) {
+    if text[0] == '&' {
+        for (reference, c) in [
+            ("&amp;", '&'),
+            ("&lt;", '<'),
+            ("&gt;", '>'),
+            ("&quot;", '"'),
+            ("&#39;", '\''),
+        ] {
+            let len = reference.len();
+            if text.len() >= len && text[..len].iter().copied().eq(reference.chars()) {
+                return (c, len);
+            }
+        }
+    }
+    (text[0], 1)
+}
 
-            pos = run_end;
+/// Renders an inline image as an `<img>` tag
+fn image_to_html(image: &Image) -> String {
+    let mut tag = format!(
+        "<img src=\"{}\" alt=\"{}\"",
+        escape_html_attribute(&image.src),
+        escape_html_attribute(&image.alt)
+    );
+    if let Some(width) = image.width {
+        tag.push_str(&format!(" width=\"{}\"", width));
+    }
+    if let Some(height) = image.height {
+        tag.push_str(&format!(" height=\"{}\"", height));
+    }
+    tag.push('>');
+    tag
+}
+
+/// Prefix of the class names written for formats with `HtmlFormatStyle::Classes`
+const CLASS_PREFIX: &str = "rte-";
+
+/// Applies formats to text as one `<span>` with classes or inline styles
+///
+/// Links stay `<a>` elements around the span. Colors have no class of
+/// their own, so they are written as inline styles in both modes.
+fn apply_span_formats(
+    text: &str,
+    formats: &HashSet<InlineFormat>,
+    format_style: HtmlFormatStyle,
+) -> String {
+    let mut result = math_span(escape_html(text), formats);
+    let mut classes: Vec<&str> = Vec::new();
+    let mut styles: Vec<String> = Vec::new();
+    let mut decorations: Vec<&str> = Vec::new();
+
+    let has_background = formats
+        .iter()
+        .any(|f| matches!(f, InlineFormat::BackgroundColor { .. }));
+    // Listed in the order the semantic tags nest, outermost first
+    let flags = [
+        (InlineFormat::Highlight, "highlight"),
+        (InlineFormat::Bold, "bold"),
+        (InlineFormat::Italic, "italic"),
+        (InlineFormat::Underline, "underline"),
+        (InlineFormat::Strikethrough, "strikethrough"),
+        (InlineFormat::SmallCaps, "small-caps"),
+        (InlineFormat::Subscript, "subscript"),
+        (InlineFormat::Superscript, "superscript"),
+        (InlineFormat::Code, "code"),
+    ];
+    for (format, class) in flags {
+        if !formats.contains(&format) {
+            continue;
         }
+        if format_style == HtmlFormatStyle::Classes {
+            classes.push(class);
+            continue;
+        }
+        match format {
+            InlineFormat::Highlight if !has_background => {
+                styles.push("background-color: yellow".to_string())
+            }
+            InlineFormat::Bold => styles.push("font-weight: bold".to_string()),
+            InlineFormat::Italic => styles.push("font-style: italic".to_string()),
+            InlineFormat::Underline => decorations.push("underline"),
+            InlineFormat::Strikethrough => decorations.push("line-through"),
+            InlineFormat::SmallCaps => styles.push("font-variant: small-caps".to_string()),
+            InlineFormat::Subscript => {
+                styles.push("vertical-align: sub; font-size: smaller".to_string())
+            }
+            InlineFormat::Superscript => {
+                styles.push("vertical-align: super; font-size: smaller".to_string())
+            }
+            InlineFormat::Code => styles.push("font-family: monospace".to_string()),
+            _ => {}
+        }
+    }
+    if !decorations.is_empty() {
+        styles.push(format!("text-decoration: {}", decorations.join(" ")));
+    }
 
-        result
+    let mut link = None;
+    for format in formats {
+        match format {
+            InlineFormat::TextColor { color } => {
+                styles.push(format!("color: {}", escape_html_attribute(color)))
+            }
+            InlineFormat::BackgroundColor { color } => styles.push(format!(
+                "background-color: {}",
+                escape_html_attribute(color)
+            )),
+            InlineFormat::Link { url } => link = Some(url),
+            _ => {}
+        }
+    }
+    // Colors come from a set, so sort them after the fixed formats
+    let fixed = styles.len()
+        - formats
+            .iter()
+            .filter(|f| {
+                matches!(
+                    f,
+                    InlineFormat::TextColor { .. } | InlineFormat::BackgroundColor { .. }
+                )
+            })
+            .count();
+    styles[fixed..].sort();
+
+    if !classes.is_empty() || !styles.is_empty() {
+        let mut tag = String::from("<span");
+        if !classes.is_empty() {
+            let classes: Vec<String> = classes
+                .iter()
+                .map(|class| format!("{}{}", CLASS_PREFIX, class))
+                .collect();
+            tag.push_str(&format!(" class=\"{}\"", classes.join(" ")));
+        }
+        if !styles.is_empty() {
+            tag.push_str(&format!(" style=\"{}\"", styles.join("; ")));
+        }
+        result = format!("{}>{}</span>", tag, result);
+    }
+    if let Some(url) = link {
+        result = format!("<a href=\"{}\">{}</a>", escape_html_attribute(url), result);
+    }
+    result
+}
+
+/// Wraps escaped text in `<span class="math">` if it is a math formula,
+/// adding the `math-display` class for display math
+///
+/// The span holds the LaTeX source for a renderer such as KaTeX or MathJax
+/// to typeset, so it is the innermost element in every format style.
+fn math_span(html: String, formats: &HashSet<InlineFormat>) -> String {
+    match formats.iter().find_map(|f| match f {
+        InlineFormat::Math { display } => Some(*display),
+        _ => None,
+    }) {
+        Some(false) => format!("<span class=\"math\">{}</span>", html),
+        Some(true) => format!("<span class=\"math math-display\">{}</span>", html),
+        None => html,
     }
 }
 
 /// Applies HTML formatting tags to text based on the given formats
 fn apply_html_formats(text: &str, formats: &HashSet<InlineFormat>) -> String {
-    let mut result = escape_html(text);
+    let mut result = math_span(escape_html(text), formats);
 
     // Collect formats in a specific order for proper nesting
     let has_bold = formats.contains(&InlineFormat::Bold);
@@ -339,6 +1186,10 @@ fn apply_html_formats(text: &str, formats: &HashSet<InlineFormat>) -> String {
     let has_underline = formats.contains(&InlineFormat::Underline);
     let has_strikethrough = formats.contains(&InlineFormat::Strikethrough);
     let has_code = formats.contains(&InlineFormat::Code);
+    let has_highlight = formats.contains(&InlineFormat::Highlight);
+    let has_small_caps = formats.contains(&InlineFormat::SmallCaps);
+    let has_subscript = formats.contains(&InlineFormat::Subscript);
+    let has_superscript = formats.contains(&InlineFormat::Superscript);
 
     let link = formats.iter().find_map(|f| match f {
         InlineFormat::Link { url } => Some(url.clone()),
@@ -355,13 +1206,28 @@ fn apply_html_formats(text: &str, formats: &HashSet<InlineFormat>) -> String {
         _ => None,
     });
 
-    // Apply formats in order: colors (outermost) -> link -> bold -> italic -> underline -> strikethrough -> code (innermost)
+    // Apply formats in order: colors (outermost) -> link -> highlight -> bold -> italic -> underline -> strikethrough -> small caps -> sub/sup -> code (innermost)
 
     // Code (innermost for inline)
     if has_code {
         result = format!("<code>{}</code>", result);
     }
 
+    // Subscript and superscript
+    if has_subscript {
+        result = format!("<sub>{}</sub>", result);
+    } else if has_superscript {
+        result = format!("<sup>{}</sup>", result);
+    }
+
+    // Small caps
+    if has_small_caps {
+        result = format!(
+            "<span style=\"font-variant: small-caps;\">{}</span>",
+            result
+        );
+    }
+
     // Strikethrough
     if has_strikethrough {
         result = format!("<s>{}</s>", result);
@@ -382,6 +1248,11 @@ fn apply_html_formats(text: &str, formats: &HashSet<InlineFormat>) -> String {
         result = format!("<strong>{}</strong>", result);
     }
 
+    // Highlight
+    if has_highlight {
+        result = format!("<mark>{}</mark>", result);
+    }
+
     // Link
     if let Some(url) = link {
         let escaped_url = escape_html_attribute(&url);
@@ -409,13 +1280,15 @@ fn apply_html_formats(text: &str, formats: &HashSet<InlineFormat>) -> String {
     result
 }
 
-/// Escapes special HTML characters in text content
+/// Escapes special HTML characters in text content, writing soft breaks
+/// as `<br>`
 fn escape_html(text: &str) -> String {
     text.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
         .replace('"', "&quot;")
         .replace('\'', "&#39;")
+        .replace(SOFT_BREAK, "<br>")
 }
 
 /// Escapes special HTML characters in attribute values
@@ -439,15 +1312,39 @@ fn escape_html_attribute(text: &str) -> String {
 /// # Security Features
 ///
 /// - **Tag Whitelist**: Only semantic HTML tags are allowed
-/// - **Attribute Whitelist**: Only href and style attributes are allowed
-/// - **URL Validation**: Blocks javascript:, data:, vbscript:, and file: protocols
+/// - **Attribute Whitelist**: Only href, cite, style, dir and image attributes are allowed
+/// - **URL Validation**: Only http:, https:, mailto: and relative URLs are allowed
 /// - **Color Validation**: Only hex (#RRGGBB), rgb(), rgba(), and named colors allowed
-/// - **CSS Property Whitelist**: Only color and background-color properties allowed
+/// - **CSS Property Whitelist**: Only color, background-color, small-caps
+///   font-variant, and margin-left and text-indent lengths are allowed
+///
+/// Each whitelist can be narrowed with the `without_*` methods and widened
+/// with the `with_*` methods. Tags that can run script or load other
+/// documents, event handler attributes, and the `javascript:`,
+/// `vbscript:` and `data:` URL schemes are never allowed.
+///
+/// The importer keeps the text of added tags but only understands the
+/// default ones, and the document has nowhere to keep added attributes;
+/// `sanitize` returns the cleaned markup with everything the policy allows.
+#[derive(Debug, Clone)]
 pub struct HtmlSanitizer {
     allowed_tags: HashSet<String>,
     allowed_attributes: HashSet<String>,
+    allowed_url_schemes: HashSet<String>,
+    allowed_css_properties: HashSet<String>,
 }
 
+/// Tags that can run script, load other documents or submit data, which a
+/// sanitizer never allows
+const FORBIDDEN_TAGS: &[&str] = &[
+    "script", "style", "iframe", "frame", "frameset", "object", "embed", "applet", "base", "link",
+    "meta", "form", "template", "noscript", "svg", "math",
+];
+
+/// URL schemes that run script or embed content, which a sanitizer never
+/// allows in links
+const FORBIDDEN_URL_SCHEMES: &[&str] = &["javascript", "vbscript", "data"];
+
 impl HtmlSanitizer {
     /// Creates a new HtmlSanitizer with default safe tags and attributes
     pub fn new() -> Self {
@@ -465,6 +1362,19 @@ impl HtmlSanitizer {
         allowed_tags.insert("ul".to_string());
         allowed_tags.insert("ol".to_string());
         allowed_tags.insert("li".to_string());
+        // Generic containers, imported as paragraph breaks
+        allowed_tags.insert("div".to_string());
+        allowed_tags.insert("section".to_string());
+        allowed_tags.insert("article".to_string());
+        // Tables, imported as one line per row with tab-separated cells
+        allowed_tags.insert("table".to_string());
+        allowed_tags.insert("caption".to_string());
+        allowed_tags.insert("thead".to_string());
+        allowed_tags.insert("tbody".to_string());
+        allowed_tags.insert("tfoot".to_string());
+        allowed_tags.insert("tr".to_string());
+        allowed_tags.insert("td".to_string());
+        allowed_tags.insert("th".to_string());
 
         // Inline tags
         allowed_tags.insert("strong".to_string());
@@ -478,16 +1388,220 @@ impl HtmlSanitizer {
         allowed_tags.insert("code".to_string());
         allowed_tags.insert("a".to_string());
         allowed_tags.insert("span".to_string());
+        allowed_tags.insert("mark".to_string());
+        allowed_tags.insert("sub".to_string());
+        allowed_tags.insert("sup".to_string());
         allowed_tags.insert("br".to_string());
+        allowed_tags.insert("img".to_string());
 
         let mut allowed_attributes = HashSet::new();
         allowed_attributes.insert("href".to_string());
+        allowed_attributes.insert("cite".to_string());
         allowed_attributes.insert("style".to_string());
+        allowed_attributes.insert("src".to_string());
+        allowed_attributes.insert("alt".to_string());
+        allowed_attributes.insert("width".to_string());
+        allowed_attributes.insert("height".to_string());
+        allowed_attributes.insert("dir".to_string());
+
+        let allowed_url_schemes = ["http", "https", "mailto"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        let allowed_css_properties = [
+            "color",
+            "background-color",
+            "font-variant",
+            "margin-left",
+            "text-indent",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
 
         Self {
             allowed_tags,
             allowed_attributes,
+            allowed_url_schemes,
+            allowed_css_properties,
+        }
+    }
+
+    /// Adds a tag to the whitelist
+    ///
+    /// Tags that can run script or load other documents, such as `script`,
+    /// `iframe` or `object`, are ignored.
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        let tag = tag.to_lowercase();
+        if !FORBIDDEN_TAGS.contains(&tag.as_str()) {
+            self.allowed_tags.insert(tag);
         }
+        self
+    }
+
+    /// Removes a tag from the whitelist
+    ///
+    /// Content inside a disallowed tag is dropped along with the tag.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::Document;
+    /// use rte_core::serialization::html::HtmlSanitizer;
+    ///
+    /// let sanitizer = HtmlSanitizer::new().without_tag("img");
+    /// let doc = Document::from_html_with_sanitizer(
+    ///     r#"<p>a<img src="https://example.com/x.png">b</p>"#,
+    ///     &sanitizer,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(doc.get_content(), "ab");
+    /// ```
+    pub fn without_tag(mut self, tag: &str) -> Self {
+        self.allowed_tags.remove(&tag.to_lowercase());
+        self
+    }
+
+    /// Adds an attribute to the whitelist
+    ///
+    /// A name ending in `*` allows every attribute with that prefix, so
+    /// `data-*` allows all custom data attributes. Event handler attributes
+    /// and `srcdoc` are ignored.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::serialization::html::HtmlSanitizer;
+    ///
+    /// let sanitizer = HtmlSanitizer::new()
+    ///     .with_attribute("target")
+    ///     .with_attribute("rel")
+    ///     .with_attribute("data-*");
+    /// assert_eq!(
+    ///     sanitizer.sanitize(r#"<a href="/a" target="_blank" rel="noopener" data-id="7" onclick="x()">a</a>"#),
+    ///     r#"<a data-id="7" href="/a" rel="noopener" target="_blank">a</a>"#
+    /// );
+    /// ```
+    pub fn with_attribute(mut self, attribute: &str) -> Self {
+        let attribute = attribute.to_lowercase();
+        let name = attribute.trim_end_matches('*');
+        if !name.starts_with("on") && name != "srcdoc" && !name.is_empty() {
+            self.allowed_attributes.insert(attribute);
+        }
+        self
+    }
+
+    /// Removes an attribute from the whitelist
+    pub fn without_attribute(mut self, attribute: &str) -> Self {
+        self.allowed_attributes.remove(&attribute.to_lowercase());
+        self
+    }
+
+    /// Adds a URL scheme, such as `tel`, to those allowed in links and
+    /// citations
+    ///
+    /// The `javascript`, `vbscript` and `data` schemes are ignored.
+    pub fn with_url_scheme(mut self, scheme: &str) -> Self {
+        let scheme = scheme.trim_end_matches(':').to_lowercase();
+        if !FORBIDDEN_URL_SCHEMES.contains(&scheme.as_str()) {
+            self.allowed_url_schemes.insert(scheme);
+        }
+        self
+    }
+
+    /// Removes a URL scheme from those allowed in links and citations
+    pub fn without_url_scheme(mut self, scheme: &str) -> Self {
+        self.allowed_url_schemes
+            .remove(&scheme.trim_end_matches(':').to_lowercase());
+        self
+    }
+
+    /// Adds a CSS property to those allowed in `style` attributes
+    ///
+    /// Values of added properties are limited to words, numbers, lengths,
+    /// colors and plain functions; URLs, escapes and quotes are stripped.
+    pub fn with_css_property(mut self, property: &str) -> Self {
+        self.allowed_css_properties.insert(property.to_lowercase());
+        self
+    }
+
+    /// Removes a CSS property from those allowed in `style` attributes
+    pub fn without_css_property(mut self, property: &str) -> Self {
+        self.allowed_css_properties.remove(&property.to_lowercase());
+        self
+    }
+
+    /// Returns true if the tag is in the whitelist
+    pub(crate) fn allows_tag(&self, tag: &str) -> bool {
+        self.allowed_tags.contains(tag)
+    }
+
+    /// Returns true if the attribute is in the whitelist, directly or by a
+    /// prefix pattern such as `data-*`
+    fn allows_attribute(&self, name: &str) -> bool {
+        self.allowed_attributes.contains(name)
+            || self.allowed_attributes.iter().any(|allowed| {
+                allowed
+                    .strip_suffix('*')
+                    .is_some_and(|prefix| name.len() > prefix.len() && name.starts_with(prefix))
+            })
+    }
+
+    /// Sanitizes HTML, returning markup with only what the whitelists allow
+    ///
+    /// Elements left open are closed, disallowed elements are dropped with
+    /// their content, and disallowed attributes and unsafe values are
+    /// stripped. Comments are removed and text is re-escaped.
+    pub fn sanitize(&self, html: &str) -> String {
+        let mut output = String::new();
+        let mut disallowed_depth = 0;
+
+        let write_tag = |output: &mut String, name: &str, attributes: &Attributes| {
+            output.push('<');
+            output.push_str(name);
+            let mut names: Vec<&String> = attributes.keys().collect();
+            names.sort();
+            for attribute in names {
+                let value = decode_html_entities(&attributes[attribute]);
+                if let Some(value) = self.sanitize_attribute(attribute, &value) {
+                    output.push_str(&format!(
+                        " {}=\"{}\"",
+                        attribute,
+                        escape_html_attribute(&value)
+                    ));
+                }
+            }
+            output.push('>');
+        };
+
+        for token in html_parser::parse(html) {
+            match token {
+                HtmlToken::OpenTag { name, attributes } => {
+                    if disallowed_depth > 0 || !self.allows_tag(&name) {
+                        disallowed_depth += 1;
+                    } else {
+                        write_tag(&mut output, &name, &attributes);
+                    }
+                }
+                HtmlToken::CloseTag { name } => {
+                    if disallowed_depth > 0 {
+                        disallowed_depth -= 1;
+                    } else {
+                        output.push_str(&format!("</{}>", name));
+                    }
+                }
+                HtmlToken::SelfClosing { name, attributes } => {
+                    if disallowed_depth == 0 && self.allows_tag(&name) {
+                        write_tag(&mut output, &name, &attributes);
+                    }
+                }
+                HtmlToken::Text { content } => {
+                    if disallowed_depth == 0 {
+                        output.push_str(&escape_html_attribute(&decode_html_entities(&content)));
+                    }
+                }
+            }
+        }
+        output
     }
 
     /// Checks if a URL is safe (not javascript:, data:, vbscript:, or file: protocol)
@@ -500,8 +1614,9 @@ impl HtmlSanitizer {
     /// - `vbscript:` - Executes VBScript code (IE)
     /// - `file:` - Accesses local file system
     ///
-    /// Only http:, https:, mailto:, and relative URLs are considered safe.
-    fn is_safe_url(&self, url: &str) -> bool {
+    /// Only relative URLs and the allowed schemes, by default http:,
+    /// https: and mailto:, are considered safe.
+    pub(crate) fn is_safe_url(&self, url: &str) -> bool {
         let trimmed = url.trim();
         if trimmed.is_empty() {
             return false;
@@ -509,23 +1624,14 @@ impl HtmlSanitizer {
 
         let lower = trimmed.to_lowercase();
 
-        // Block dangerous protocols
-        if lower.starts_with("javascript:")
-            || lower.starts_with("data:")
-            || lower.starts_with("vbscript:")
-            || lower.starts_with("file:")
-        {
-            return false;
+        // Allow relative URLs
+        if lower.starts_with('/') || lower.starts_with('#') || !lower.contains(':') {
+            return true;
         }
 
-        // Allow safe protocols and relative URLs
-        lower.starts_with("http:")
-            || lower.starts_with("https:")
-            || lower.starts_with("mailto:")
-            || lower.starts_with("//")
-            || lower.starts_with('/')
-            || lower.starts_with('#')
-            || !lower.contains(':') // Relative URLs without protocol
+        // Allow whitelisted protocols, never dangerous ones
+        let scheme = &lower[..lower.find(':').unwrap_or(0)];
+        !FORBIDDEN_URL_SCHEMES.contains(&scheme) && self.allowed_url_schemes.contains(scheme)
     }
 
     /// Validates a color value
@@ -539,7 +1645,7 @@ impl HtmlSanitizer {
     /// - Invalid formats
     /// - Expressions or functions (except rgb/rgba)
     /// - URLs or imports
-    fn is_valid_color(&self, color: &str) -> bool {
+    pub(crate) fn is_valid_color(&self, color: &str) -> bool {
         let trimmed = color.trim();
         if trimmed.is_empty() {
             return false;
@@ -611,18 +1717,23 @@ impl HtmlSanitizer {
     ///
     /// Returns None if the attribute should be stripped.
     fn sanitize_attribute(&self, name: &str, value: &str) -> Option<String> {
-        if !self.allowed_attributes.contains(name) {
+        if !self.allows_attribute(name) {
             return None;
         }
 
         match name {
-            "href" => {
+            "src" => is_safe_image_src(value).then(|| value.to_string()),
+            "href" | "cite" => {
                 if self.is_safe_url(value) {
                     Some(value.to_string())
                 } else {
                     None
                 }
             }
+            "dir" => {
+                let value = value.trim().to_ascii_lowercase();
+                matches!(value.as_str(), "ltr" | "rtl" | "auto").then_some(value)
+            }
             "style" => {
                 // Only allow color and background-color properties
                 let sanitized = self.sanitize_style(value);
@@ -638,8 +1749,10 @@ impl HtmlSanitizer {
 
     /// Sanitizes CSS style attribute
     ///
-    /// Only allows color and background-color properties with validated values.
-    /// All other CSS properties are stripped for security.
+    /// Only allows color and background-color properties with validated
+    /// values, small-caps, plain lengths for paragraph indents, and plain
+    /// values of added properties. All other CSS properties are stripped for
+    /// security.
     fn sanitize_style(&self, style: &str) -> String {
         let mut result = Vec::new();
 
@@ -649,11 +1762,22 @@ impl HtmlSanitizer {
                 let property = parts[0].trim().to_lowercase();
                 let value = parts[1].trim();
 
+                if !self.allowed_css_properties.contains(&property) {
+                    continue;
+                }
                 if property == "color" || property == "background-color" {
                     // Validate color value
                     if self.is_valid_color(value) {
                         result.push(format!("{}: {}", property, value));
                     }
+                } else if property == "font-variant" && value.eq_ignore_ascii_case("small-caps") {
+                    result.push("font-variant: small-caps".to_string());
+                } else if property == "margin-left" || property == "text-indent" {
+                    if parse_css_length_em(value).is_some() {
+                        result.push(format!("{}: {}", property, value));
+                    }
+                } else if property != "font-variant" && is_plain_css_value(value) {
+                    result.push(format!("{}: {}", property, value));
                 }
             }
         }
@@ -662,16 +1786,154 @@ impl HtmlSanitizer {
     }
 }
 
+/// Checks that a CSS value holds only words, numbers, lengths, colors and
+/// plain functions such as `rgb()`, with no URLs, escapes or quotes
+fn is_plain_css_value(value: &str) -> bool {
+    let lower = value.to_ascii_lowercase();
+    !value.is_empty()
+        && !lower.contains("url(")
+        && !lower.contains("expression(")
+        && !lower.contains("image(")
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || " #%.,()-+_".contains(c))
+}
+
 impl Default for HtmlSanitizer {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// How exported HTML writes inline formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HtmlFormatStyle {
+    /// Semantic tags such as `<strong>`, `<em>` and `<mark>`
+    #[default]
+    Semantic,
+    /// A `<span>` with `rte-*` classes, such as `class="rte-bold"`, for
+    /// apps that style formats with their own stylesheet
+    Classes,
+    /// A `<span>` with inline styles, such as `font-weight: bold`, for
+    /// output that must look right without a stylesheet
+    InlineStyles,
+}
+
+/// Options for exporting HTML
+///
+/// The default writes the same fragment as `Document::to_html`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HtmlExportOptions {
+    /// How inline formats are written
+    ///
+    /// Links are `<a>` elements in every style, and colors are inline
+    /// styles because classes cannot carry their values.
+    pub format_style: HtmlFormatStyle,
+    /// Whether each block element carries a `data-offset` attribute with
+    /// the document offset where it starts, for hydrating server-rendered
+    /// HTML
+    pub data_offsets: bool,
+    /// Whether the output is a complete page with the metadata in its head,
+    /// as written by `Document::to_html_document`, instead of a fragment
+    pub standalone: bool,
+    /// Whether text covered by transient decorations is wrapped in
+    /// `<mark data-deco-id="...">` elements, as `Document::to_html_range`
+    /// does
+    pub decoration_marks: bool,
+}
+
+impl HtmlExportOptions {
+    /// Creates options that write a semantic HTML fragment
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how inline formats are written
+    pub fn format_style(mut self, format_style: HtmlFormatStyle) -> Self {
+        self.format_style = format_style;
+        self
+    }
+
+    /// Sets whether blocks carry `data-offset` attributes
+    pub fn data_offsets(mut self, data_offsets: bool) -> Self {
+        self.data_offsets = data_offsets;
+        self
+    }
+
+    /// Sets whether the output is a complete page
+    pub fn standalone(mut self, standalone: bool) -> Self {
+        self.standalone = standalone;
+        self
+    }
+
+    /// Sets whether transient decorations are written as `<mark>` elements
+    pub fn decoration_marks(mut self, decoration_marks: bool) -> Self {
+        self.decoration_marks = decoration_marks;
+        self
+    }
+}
+
+/// How the HTML importer treats whitespace in text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WhitespaceHandling {
+    /// Collapses runs of spaces, tabs and newlines to one space and drops
+    /// whitespace at line starts and ends, as browsers render it. Text in
+    /// `<pre>` or under a `white-space: pre` style is kept as written.
+    ///
+    /// A fragment with no block elements keeps one space at each end, so
+    /// pasting `<b> world</b>` after "Hello" does not join the words.
+    #[default]
+    Collapse,
+    /// Keeps text exactly as written in the source
+    Preserve,
+}
+
+/// Options for importing HTML
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HtmlImportOptions {
+    /// How whitespace in text is treated
+    pub whitespace: WhitespaceHandling,
+    /// Caps on the tokens, nesting and size of the import
+    pub limits: ImportLimits,
+}
+
+impl HtmlImportOptions {
+    /// Creates options with the default whitespace handling and no limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how whitespace in text is treated
+    pub fn whitespace(mut self, whitespace: WhitespaceHandling) -> Self {
+        self.whitespace = whitespace;
+        self
+    }
+
+    /// Sets the caps on the tokens, nesting and size of the import
+    pub fn limits(mut self, limits: ImportLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+/// Whitespace rendering of an element, from `<pre>` or its `white-space` style
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WhiteSpace {
+    /// Whitespace collapses
+    Normal,
+    /// Spaces collapse but newlines are kept (`pre-line`)
+    PreLine,
+    /// Whitespace is kept (`pre`, `pre-wrap`, `break-spaces`)
+    Pre,
+}
+
 impl Document {
     /// Imports a document from HTML format with sanitization
     ///
     /// Parses HTML and converts tags to internal format representation.
+    /// Elements left open are closed the way browsers close them, so
+    /// unclosed paragraphs and list items keep their nesting. Table rows
+    /// become lines with tab-separated cells.
     /// Strips dangerous content like javascript: URLs and event handlers.
     ///
     /// # Arguments
@@ -691,7 +1953,46 @@ impl Document {
     }
 
     /// Imports a document from HTML format with a custom sanitizer
-    fn from_html_with_sanitizer(html: &str, sanitizer: &HtmlSanitizer) -> Result<Self, HtmlError> {
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if HTML parsing fails.
+    pub fn from_html_with_sanitizer(
+        html: &str,
+        sanitizer: &HtmlSanitizer,
+    ) -> Result<Self, HtmlError> {
+        Self::from_html_with_options(html, sanitizer, &HtmlImportOptions::default())
+    }
+
+    /// Imports a document from HTML with a custom sanitizer and options
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rte_core::document::Document;
+    /// use rte_core::serialization::html::{HtmlImportOptions, HtmlSanitizer, WhitespaceHandling};
+    ///
+    /// let html = "<p>\n    Indented\n    text\n</p>";
+    /// let sanitizer = HtmlSanitizer::new();
+    ///
+    /// let doc = Document::from_html(html).unwrap();
+    /// assert_eq!(doc.get_content(), "Indented text");
+    ///
+    /// let options = HtmlImportOptions::new().whitespace(WhitespaceHandling::Preserve);
+    /// let doc = Document::from_html_with_options(html, &sanitizer, &options).unwrap();
+    /// assert_eq!(doc.get_content(), "\n    Indented\n    text");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if HTML parsing fails, or `HtmlError::LimitExceeded`
+    /// if the input goes over `options.limits`, such as elements nested
+    /// deeper than `max_depth`.
+    pub fn from_html_with_options(
+        html: &str,
+        sanitizer: &HtmlSanitizer,
+        options: &HtmlImportOptions,
+    ) -> Result<Self, HtmlError> {
         if html.is_empty() {
             return Ok(Document::new());
         }
@@ -699,15 +2000,23 @@ impl Document {
         let mut plain_text = String::new();
         let mut format_instructions: Vec<FormatInstruction> = Vec::new();
         let mut block_instructions: Vec<BlockInstruction> = Vec::new();
+        // Text directions from `dir` attributes, innermost element first
+        let mut direction_instructions: Vec<(usize, usize, TextDirection)> = Vec::new();
+        let mut image_instructions: Vec<(usize, Image)> = Vec::new();
         let mut current_offset = 0;
 
-        // Parse HTML into a simple token stream
-        let tokens = parse_html_tokens(html)?;
+        // Parse HTML into balanced tokens, closing elements left open
+        let tokens = parse_html_tokens(html, &options.limits)?;
 
         // Process tokens
         let mut tag_stack: Vec<HtmlTag> = Vec::new();
         let mut in_pre = false;
         let mut disallowed_tag_depth = 0; // Track depth of disallowed tags
+        // Collapsed whitespace not yet written, dropped at line ends
+        let mut pending_space = false;
+        let collapse = options.whitespace == WhitespaceHandling::Collapse;
+        // Until a block element appears the text may be an inline fragment
+        let mut inline_fragment = true;
 
         for token in tokens {
             match token {
@@ -729,6 +2038,18 @@ impl Document {
                         in_pre = true;
                     }
 
+                    // Whitespace before an inline element stays outside it
+                    if BLOCK_TAGS.contains(&tag_name.as_str()) {
+                        pending_space = false;
+                        inline_fragment = false;
+                        end_soft_break(&mut plain_text);
+                    } else if std::mem::take(&mut pending_space)
+                        && space_allowed(&plain_text, inline_fragment)
+                    {
+                        plain_text.push(' ');
+                        current_offset += 1;
+                    }
+
                     // Handle block-level tags
                     let block_type = match tag_name.as_str() {
                         "h1" => Some(BlockType::heading(1)),
@@ -737,25 +2058,73 @@ impl Document {
                         "h4" => Some(BlockType::heading(4)),
                         "h5" => Some(BlockType::heading(5)),
                         "h6" => Some(BlockType::heading(6)),
-                        "blockquote" => Some(BlockType::BlockQuote),
+                        "blockquote" => {
+                            let depth =
+                                tag_stack.iter().filter(|t| t.name == "blockquote").count() + 1;
+                            let cite = attributes.get("cite").and_then(|url| {
+                                sanitizer.sanitize_attribute("cite", &decode_html_entities(url))
+                            });
+                            Some(BlockType::block_quote_with(
+                                cite,
+                                u8::try_from(depth).unwrap_or(u8::MAX),
+                            ))
+                        }
                         "li" => {
-                            // Determine list type from parent
-                            if tag_stack.iter().any(|t| t.name == "ul") {
-                                Some(BlockType::BulletList)
-                            } else if tag_stack.iter().any(|t| t.name == "ol") {
-                                Some(BlockType::NumberedList)
-                            } else {
-                                None
+                            // Determine list type from the innermost parent list
+                            match tag_stack
+                                .iter()
+                                .rev()
+                                .find(|t| t.name == "ul" || t.name == "ol")
+                            {
+                                Some(list) if list.name == "ul" => Some(BlockType::BulletList),
+                                Some(_) => Some(BlockType::NumberedList),
+                                None => None,
                             }
                         }
+                        // Marks where a top-level paragraph starts, so it does
+                        // not continue a heading or list before it
+                        "p" | "div" | "section" | "article"
+                            if !tag_stack.iter().any(|t| t.block_type.is_some()) =>
+                        {
+                            Some(BlockType::Paragraph)
+                        }
                         _ => None,
                     };
 
+                    // A list nested in an item, or a container after text,
+                    // starts on its own line; inside a cell the cell's text
+                    // stays on its row
+                    let in_cell = tag_stack.iter().any(|t| t.name == "td" || t.name == "th");
+                    if matches!(
+                        tag_name.as_str(),
+                        "ul" | "ol" | "div" | "section" | "article" | "table"
+                    ) && !in_cell
+                        && !plain_text.is_empty()
+                        && !plain_text.ends_with('\n')
+                    {
+                        plain_text.push('\n');
+                        current_offset += 1;
+                    }
+
+                    // Cells after the first in a row start with a tab
+                    if matches!(tag_name.as_str(), "td" | "th")
+                        && let Some(row) = tag_stack.iter_mut().rev().find(|t| t.name == "tr")
+                    {
+                        if row.cells > 0 {
+                            plain_text.push('\t');
+                            current_offset += 1;
+                        }
+                        row.cells += 1;
+                    }
+
+                    let white_space = attributes.get("style").and_then(|s| parse_white_space(s));
                     tag_stack.push(HtmlTag {
                         name: tag_name.clone(),
                         start_offset: current_offset,
                         attributes: attributes.clone(),
                         block_type,
+                        white_space,
+                        cells: 0,
                     });
                 }
                 HtmlToken::CloseTag { name } => {
@@ -777,18 +2146,50 @@ impl Document {
                     if tag_name == "pre" {
                         in_pre = false;
                     }
+                    if BLOCK_TAGS.contains(&tag_name.as_str()) {
+                        pending_space = false;
+                        inline_fragment = false;
+                    }
 
                     // Find matching open tag
                     if let Some(pos) = tag_stack.iter().rposition(|t| t.name == tag_name) {
                         let tag = tag_stack.remove(pos);
 
+                        if BLOCK_TAGS.contains(&tag.name.as_str())
+                            && current_offset > tag.start_offset
+                            && let Some(direction) = tag
+                                .attributes
+                                .get("dir")
+                                .and_then(|dir| sanitizer.sanitize_attribute("dir", dir))
+                                .and_then(|dir| TextDirection::from_html_value(&dir))
+                        {
+                            direction_instructions.push((
+                                tag.start_offset,
+                                current_offset,
+                                direction,
+                            ));
+                        }
+
                         // Handle block-level closing
                         if let Some(block_type) = tag.block_type {
                             if current_offset > tag.start_offset {
+                                let lists = tag_stack
+                                    .iter()
+                                    .filter(|t| t.name == "ul" || t.name == "ol")
+                                    .count();
+                                let (block_indent, first_line_indent) = tag
+                                    .attributes
+                                    .get("style")
+                                    .and_then(|style| sanitizer.sanitize_attribute("style", style))
+                                    .map_or((0, false), |style| parse_paragraph_indent(&style));
                                 block_instructions.push(BlockInstruction {
                                     start: tag.start_offset,
                                     end: current_offset,
                                     block_type,
+                                    indent: u8::try_from(lists.saturating_sub(1))
+                                        .unwrap_or(u8::MAX),
+                                    block_indent,
+                                    first_line_indent,
                                 });
                             }
                         }
@@ -796,51 +2197,70 @@ impl Document {
                         // Handle inline formatting closing
                         if current_offset > tag.start_offset {
                             let format = match tag.name.as_str() {
-                                "strong" | "b" => Some(InlineFormat::Bold),
+                                // Google Docs wraps copied content in
+                                // `<b style="font-weight:normal">`
+                                "strong" | "b" if !has_normal_weight(&tag.attributes) => {
+                                    Some(InlineFormat::Bold)
+                                }
                                 "em" | "i" => Some(InlineFormat::Italic),
                                 "u" => Some(InlineFormat::Underline),
                                 "del" | "s" | "strike" => Some(InlineFormat::Strikethrough),
                                 "code" => Some(InlineFormat::Code),
+                                "mark" => Some(InlineFormat::Highlight),
+                                "sub" => Some(InlineFormat::Subscript),
+                                "sup" => Some(InlineFormat::Superscript),
                                 "a" => {
-                                    // Extract href attribute
+                                    // Extract href attribute, decoded before it is checked
                                     tag.attributes.get("href").and_then(|url| {
                                         sanitizer
-                                            .sanitize_attribute("href", url)
+                                            .sanitize_attribute("href", &decode_html_entities(url))
                                             .map(|safe_url| InlineFormat::Link { url: safe_url })
                                     })
                                 }
-                                "span" => {
+                                "span" => math_format(&tag.attributes).or_else(|| {
                                     // Extract style attribute
                                     tag.attributes
                                         .get("style")
                                         .and_then(|style| parse_style_to_formats(style, sanitizer))
-                                }
+                                }),
                                 _ => None,
                             };
 
                             if let Some(fmt) = format {
+                                // A code block's language is a class on the
+                                // `<code>` or the `<pre>` around it
+                                let language = (in_pre && tag.name == "code")
+                                    .then(|| {
+                                        code_language(&tag.attributes).or_else(|| {
+                                            tag_stack
+                                                .iter()
+                                                .rev()
+                                                .find(|t| t.name == "pre")
+                                                .and_then(|pre| code_language(&pre.attributes))
+                                        })
+                                    })
+                                    .flatten();
                                 format_instructions.push(FormatInstruction {
                                     start: tag.start_offset,
                                     end: current_offset,
                                     format: fmt,
                                     in_pre,
+                                    language,
                                 });
                             }
                         }
 
-                        // Add newline after block elements
-                        if matches!(
-                            tag.name.as_str(),
-                            "p" | "h1"
-                                | "h2"
-                                | "h3"
-                                | "h4"
-                                | "h5"
-                                | "h6"
-                                | "li"
-                                | "blockquote"
-                                | "br"
-                        ) {
+                        // Add newline after block elements, except for blocks
+                        // inside a table cell
+                        let in_cell = tag_stack.iter().any(|t| t.name == "td" || t.name == "th");
+                        let ends_line = match tag.name.as_str() {
+                            "tr" | "caption" => true,
+                            "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "li" | "blockquote"
+                            | "pre" | "div" | "section" | "article" | "br" => !in_cell,
+                            _ => false,
+                        };
+                        if ends_line {
+                            end_soft_break(&mut plain_text);
                             if !plain_text.is_empty() && !plain_text.ends_with('\n') {
                                 plain_text.push('\n');
                                 current_offset += 1;
@@ -855,27 +2275,70 @@ impl Document {
                     }
 
                     let decoded = decode_html_entities(&content);
-                    plain_text.push_str(&decoded);
-                    current_offset += decoded.chars().count();
+                    let white_space = if in_pre {
+                        WhiteSpace::Pre
+                    } else {
+                        tag_stack
+                            .iter()
+                            .rev()
+                            .find_map(|tag| tag.white_space)
+                            .unwrap_or(WhiteSpace::Normal)
+                    };
+                    if collapse && white_space != WhiteSpace::Pre {
+                        current_offset += push_collapsed(
+                            &mut plain_text,
+                            &decoded,
+                            white_space == WhiteSpace::PreLine,
+                            inline_fragment,
+                            &mut pending_space,
+                        );
+                    } else {
+                        plain_text.push_str(&decoded);
+                        current_offset += decoded.chars().count();
+                    }
                 }
-                HtmlToken::SelfClosing { name, .. } => {
-                    // Skip if we're inside a disallowed tag
-                    if disallowed_tag_depth > 0 {
+                HtmlToken::SelfClosing { name, attributes } => {
+                    // Skip if disallowed or inside a disallowed tag
+                    if disallowed_tag_depth > 0
+                        || !sanitizer.allowed_tags.contains(&name.to_lowercase())
+                    {
                         continue;
                     }
 
                     if name.to_lowercase() == "br" {
-                        plain_text.push('\n');
+                        pending_space = false;
+                        inline_fragment = false;
+                        // A break inside text is a soft break; on an empty
+                        // line it is the line itself
+                        if in_pre || plain_text.is_empty() || plain_text.ends_with('\n') {
+                            plain_text.push('\n');
+                        } else {
+                            plain_text.push(SOFT_BREAK);
+                        }
+                        current_offset += 1;
+                    } else if name.eq_ignore_ascii_case("img")
+                        && let Some(image) = parse_image_attributes(&attributes, sanitizer)
+                    {
+                        image_instructions.push((current_offset, image));
+                        plain_text.push(EMBED_CHAR);
                         current_offset += 1;
                     }
                 }
             }
         }
 
+        if pending_space && inline_fragment && !plain_text.ends_with(' ') {
+            plain_text.push(' ');
+        }
+
         // Remove trailing newline if present
+        end_soft_break(&mut plain_text);
         if plain_text.ends_with('\n') {
             plain_text.pop();
         }
+        options
+            .limits
+            .check(ImportLimit::OutputSize, plain_text.chars().count())?;
 
         // Create document with plain text
         let mut doc = Document::from_text(&plain_text);
@@ -883,34 +2346,85 @@ impl Document {
         // Apply inline formats and track code blocks
         let mut code_block_ranges = Vec::new();
         for instruction in format_instructions {
-            if instruction.end <= doc.get_length() {
-                let range = Range::from_offsets(instruction.start, instruction.end);
+            // A format that closes after the last line ends past the trailing
+            // newline that was dropped above
+            let end = instruction.end.min(doc.get_length());
+            if instruction.start < end {
+                let range = Range::from_offsets(instruction.start, end);
                 doc.apply_format(range, instruction.format.clone());
 
                 // Track code formats that were in <pre> tags for conversion to code blocks
                 if matches!(instruction.format, InlineFormat::Code) && instruction.in_pre {
-                    code_block_ranges.push((instruction.start, instruction.end));
+                    code_block_ranges.push((instruction.start, end, instruction.language));
                 }
             }
         }
 
-        // Apply block types
+        // Apply block types outermost first so nested blocks (e.g. an inner
+        // blockquote) are not overwritten when their parent closes later
+        block_instructions.sort_by_key(|instruction| (instruction.start, Reverse(instruction.end)));
         for instruction in block_instructions {
-            if instruction.end <= doc.get_length() {
-                let range = Range::from_offsets(instruction.start, instruction.end);
+            // A block that closes after a nested block ends past the trailing
+            // newline that was dropped above
+            let end = instruction.end.min(doc.get_length());
+            if instruction.start < end {
+                let range = Range::from_offsets(instruction.start, end);
+                let is_list = matches!(
+                    instruction.block_type,
+                    BlockType::BulletList | BlockType::NumberedList
+                );
+                let is_paragraph = instruction.block_type == BlockType::Paragraph;
                 doc.set_block_type(range, instruction.block_type);
+
+                // Nested items are applied after their parent, so each item
+                // only sets the level of the lines it covers
+                let line_end = doc.line_end_from(end - 1);
+                let next_line = (line_end < doc.get_length()).then_some(line_end + 1);
+                if is_list {
+                    doc.formats_mut()
+                        .set_indent(instruction.start, next_line, instruction.indent);
+                } else if is_paragraph {
+                    doc.formats_mut().set_block_indent(
+                        instruction.start,
+                        next_line,
+                        instruction.block_indent,
+                    );
+                    doc.formats_mut().set_first_line_indent(
+                        instruction.start,
+                        next_line,
+                        instruction.first_line_indent,
+                    );
+                }
+            }
+        }
+
+        for (offset, image) in image_instructions {
+            if offset < doc.get_length() {
+                doc.embeds_mut().insert(offset, image);
             }
         }
 
         // Convert code formats that were in <pre> tags to code blocks
-        for (start, end) in code_block_ranges {
+        for (start, end, language) in code_block_ranges {
             if end <= doc.get_length() {
                 let range = Range::from_offsets(start, end);
-                doc.set_block_type(range, BlockType::CodeBlock);
+                doc.set_block_type(range, BlockType::code_block_with(language.as_deref()));
                 doc.remove_format(range, &InlineFormat::Code);
             }
         }
 
+        // Apply directions outermost first, so inner elements override them
+        for (start, end, direction) in direction_instructions.into_iter().rev() {
+            let end = end.min(doc.get_length());
+            if start < end {
+                let line_start = doc.line_start_before(start);
+                let line_end = doc.line_end_from(end - 1);
+                let next_line = (line_end < doc.get_length()).then_some(line_end + 1);
+                doc.formats_mut()
+                    .set_direction(line_start, next_line, Some(direction));
+            }
+        }
+
         // Clear history since this is a freshly loaded document
         doc.history.clear();
 
@@ -925,6 +2439,32 @@ struct FormatInstruction {
     end: usize,
     format: InlineFormat,
     in_pre: bool, // Track if this format was inside a <pre> tag
+    /// Language of a code block, from a `<code>` in a `<pre>`
+    language: Option<String>,
+}
+
+/// Reads a code block language from a `language-*` or `lang-*` class
+fn code_language(attributes: &Attributes) -> Option<String> {
+    attributes
+        .get("class")?
+        .split_whitespace()
+        .find_map(|class| {
+            class
+                .strip_prefix("language-")
+                .or_else(|| class.strip_prefix("lang-"))
+                .map(str::to_string)
+        })
+}
+
+/// Reads a math formula from the `math` class of a `<span>`, which is
+/// display math if the span also has the `math-display` class
+fn math_format(attributes: &Attributes) -> Option<InlineFormat> {
+    let mut classes = attributes.get("class")?.split_whitespace();
+    if !classes.clone().any(|class| class == "math") {
+        return None;
+    }
+    let display = classes.any(|class| class == "math-display");
+    Some(InlineFormat::Math { display })
 }
 
 /// Instruction for applying a block type after parsing
@@ -933,6 +2473,12 @@ struct BlockInstruction {
     start: usize,
     end: usize,
     block_type: BlockType,
+    /// List nesting level, 0 outside of nested lists
+    indent: u8,
+    /// Paragraph indent in steps, from a `margin-left` style
+    block_indent: u8,
+    /// Whether the paragraph has a positive `text-indent` style
+    first_line_indent: bool,
 }
 
 /// Represents an HTML tag in the stack
@@ -940,93 +2486,246 @@ struct BlockInstruction {
 struct HtmlTag {
     name: String,
     start_offset: usize,
-    attributes: std::collections::HashMap<String, String>,
+    attributes: Attributes,
     block_type: Option<BlockType>,
+    /// Whitespace rendering set by the element's style, if any
+    white_space: Option<WhiteSpace>,
+    /// Number of cells opened so far, for a table row
+    cells: usize,
 }
 
-/// HTML token types
-#[derive(Debug, Clone)]
-enum HtmlToken {
-    OpenTag {
-        name: String,
-        attributes: std::collections::HashMap<String, String>,
-    },
-    CloseTag {
-        name: String,
-    },
-    SelfClosing {
-        name: String,
-    },
-    Text {
-        content: String,
-    },
+/// Turns a soft break at the end of the text into a newline
+///
+/// A `<br>` right before a block boundary ends the line rather than
+/// breaking it. Both are one character, so offsets stay valid.
+fn end_soft_break(plain_text: &mut String) {
+    if plain_text.ends_with(SOFT_BREAK) {
+        plain_text.pop();
+        plain_text.push('\n');
+    }
 }
 
-/// Simple HTML tokenizer
-fn parse_html_tokens(html: &str) -> Result<Vec<HtmlToken>, HtmlError> {
-    let mut tokens = Vec::new();
-    let chars: Vec<char> = html.chars().collect();
-    let mut i = 0;
+/// Block-level tags; whitespace-only text between them is dropped
+const BLOCK_TAGS: [&str; 24] = [
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+    "pre",
+    "div",
+    "section",
+    "article",
+    "br",
+    "table",
+    "caption",
+    "thead",
+    "tbody",
+    "tfoot",
+    "tr",
+    "td",
+    "th",
+];
+
+/// Width of one paragraph indent step, and of a first-line indent, in `em`
+const INDENT_STEP_EM: f32 = 2.0;
+
+/// Builds the ` dir="..."` attribute for a text direction, or an empty
+/// string when the direction is detected from the text
+fn dir_attribute(direction: Option<TextDirection>) -> String {
+    direction.map_or(String::new(), |direction| {
+        format!(" dir=\"{}\"", direction.html_value())
+    })
+}
 
-    while i < chars.len() {
-        if chars[i] == '<' {
-            // Find the end of the tag
-            if let Some(end) = chars[i..].iter().position(|&c| c == '>') {
-                let tag_content: String = chars[i + 1..i + end].iter().collect();
-
-                if tag_content.starts_with('/') {
-                    // Closing tag
-                    let name = tag_content[1..].trim().to_string();
-                    tokens.push(HtmlToken::CloseTag { name });
-                } else if tag_content.ends_with('/') {
-                    // Self-closing tag
-                    let content = tag_content[..tag_content.len() - 1].trim();
-                    let (name, _attributes) = parse_tag_and_attributes(content);
-                    tokens.push(HtmlToken::SelfClosing { name });
-                } else {
-                    // Opening tag
-                    let (name, attributes) = parse_tag_and_attributes(&tag_content);
-                    tokens.push(HtmlToken::OpenTag { name, attributes });
-                }
+/// Builds the ` class="language-..."` attribute of a code block's `<code>`
+/// element, if the block has a language
+fn code_language_attribute(block_type: &BlockType) -> String {
+    block_type
+        .code_language()
+        .map(|language| format!(" class=\"language-{}\"", escape_html_attribute(language)))
+        .unwrap_or_default()
+}
 
-                i += end + 1;
-            } else {
-                // Malformed tag, treat as text
-                tokens.push(HtmlToken::Text {
-                    content: chars[i].to_string(),
-                });
-                i += 1;
+/// Builds the ` data-offset="..."` attribute of a block starting at `offset`,
+/// if the options ask for one
+fn offset_attribute(options: &HtmlExportOptions, offset: usize) -> String {
+    if options.data_offsets {
+        format!(" data-offset=\"{}\"", offset)
+    } else {
+        String::new()
+    }
+}
+
+/// Builds the opening `<p>` tag, with the paragraph's `dir` attribute and a
+/// style for its indents
+fn paragraph_open_tag(block_indent: u8, first_line_indent: bool, dir: &str) -> String {
+    let mut style = Vec::new();
+    if block_indent > 0 {
+        style.push(format!(
+            "margin-left: {}em",
+            f32::from(block_indent) * INDENT_STEP_EM
+        ));
+    }
+    if first_line_indent {
+        style.push(format!("text-indent: {}em", INDENT_STEP_EM));
+    }
+    if style.is_empty() {
+        format!("<p{}>", dir)
+    } else {
+        format!("<p{} style=\"{}\">", dir, style.join("; "))
+    }
+}
+
+/// Reads the paragraph indent in steps and whether the first line is
+/// indented from a sanitized style attribute
+fn parse_paragraph_indent(style: &str) -> (u8, bool) {
+    let mut block_indent = 0;
+    let mut first_line_indent = false;
+    for declaration in style.split(';') {
+        let Some((property, value)) = declaration.split_once(':') else {
+            continue;
+        };
+        let Some(em) = parse_css_length_em(value.trim()) else {
+            continue;
+        };
+        match property.trim() {
+            "margin-left" => {
+                let steps = (em / INDENT_STEP_EM)
+                    .round()
+                    .clamp(0.0, f32::from(MAX_BLOCK_INDENT));
+                block_indent = steps as u8;
             }
+            "text-indent" => first_line_indent = em > 0.0,
+            _ => {}
+        }
+    }
+    (block_indent, first_line_indent)
+}
+
+/// Parses a CSS length in `em`, `rem`, `px` or `pt` (or a bare `0`) and
+/// converts it to `em`, assuming a 16px font
+fn parse_css_length_em(value: &str) -> Option<f32> {
+    if value == "0" {
+        return Some(0.0);
+    }
+    let unit_start = value.find(|c: char| c.is_ascii_alphabetic())?;
+    let (number, unit) = value.split_at(unit_start);
+    if number.is_empty()
+        || !number
+            .trim_start_matches('-')
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.')
+    {
+        return None;
+    }
+    let number: f32 = number.parse().ok()?;
+    match unit.to_ascii_lowercase().as_str() {
+        "em" | "rem" => Some(number),
+        "px" => Some(number / 16.0),
+        "pt" => Some(number / 12.0),
+        _ => None,
+    }
+}
+
+/// Returns true if a style attribute resets the font weight to normal
+fn has_normal_weight(attributes: &Attributes) -> bool {
+    attributes.get("style").is_some_and(|style| {
+        style.split(';').any(|declaration| {
+            declaration
+                .split_once(':')
+                .is_some_and(|(property, value)| {
+                    property.trim().eq_ignore_ascii_case("font-weight")
+                        && matches!(value.trim(), "normal" | "400")
+                })
+        })
+    })
+}
+
+/// Returns true if a collapsed space may be written after `text`
+///
+/// No space is written at the start of a line or after another space. The
+/// start of an inline fragment is not a line start, since the fragment may
+/// be pasted mid-line.
+fn space_allowed(text: &str, inline_fragment: bool) -> bool {
+    !text.ends_with([' ', '\n']) && (inline_fragment || !text.is_empty())
+}
+
+/// Appends text with whitespace runs collapsed to single spaces
+///
+/// A space is only written once the next visible character arrives, so
+/// whitespace at the start or end of a line is dropped. With
+/// `keep_newlines`, line breaks in the text are kept (`white-space:
+/// pre-line`). Returns the number of characters appended.
+fn push_collapsed(
+    plain_text: &mut String,
+    text: &str,
+    keep_newlines: bool,
+    inline_fragment: bool,
+    pending_space: &mut bool,
+) -> usize {
+    let mut pushed = 0;
+    for c in text.chars() {
+        if keep_newlines && c == '\n' {
+            *pending_space = false;
+            plain_text.push('\n');
+            pushed += 1;
+        } else if c.is_ascii_whitespace() {
+            *pending_space = true;
         } else {
-            // Text content
-            let mut text = String::new();
-            while i < chars.len() && chars[i] != '<' {
-                text.push(chars[i]);
-                i += 1;
-            }
-            if !text.is_empty() {
-                tokens.push(HtmlToken::Text { content: text });
+            if std::mem::take(pending_space) && space_allowed(plain_text, inline_fragment) {
+                plain_text.push(' ');
+                pushed += 1;
             }
+            plain_text.push(c);
+            pushed += 1;
+        }
+    }
+    pushed
+}
+
+/// Reads the `white-space` property from a style attribute
+fn parse_white_space(style: &str) -> Option<WhiteSpace> {
+    style.split(';').find_map(|declaration| {
+        let (property, value) = declaration.split_once(':')?;
+        if !property.trim().eq_ignore_ascii_case("white-space") {
+            return None;
+        }
+        match value.trim().to_ascii_lowercase().as_str() {
+            "pre" | "pre-wrap" | "break-spaces" => Some(WhiteSpace::Pre),
+            "pre-line" => Some(WhiteSpace::PreLine),
+            "normal" | "nowrap" => Some(WhiteSpace::Normal),
+            _ => None,
         }
-    }
+    })
+}
 
-    // Post-process: remove whitespace-only text tokens that appear between block-level tags
-    let block_tags = [
-        "p",
-        "h1",
-        "h2",
-        "h3",
-        "h4",
-        "h5",
-        "h6",
-        "ul",
-        "ol",
-        "li",
-        "blockquote",
-        "pre",
-        "div",
-    ];
+/// Builds an image from `<img>` attributes, or None if the source is unsafe
+fn parse_image_attributes(attributes: &Attributes, sanitizer: &HtmlSanitizer) -> Option<Image> {
+    let attribute = |name: &str| {
+        attributes
+            .get(name)
+            .map(|value| decode_html_entities(value))
+    };
+    let src = attribute("src").and_then(|src| sanitizer.sanitize_attribute("src", &src))?;
+    let alt = attribute("alt").unwrap_or_default();
+    let width = attribute("width").and_then(|w| w.trim().parse().ok());
+    let height = attribute("height").and_then(|h| h.trim().parse().ok());
+    Some(Image::new(src, alt).with_size(width, height))
+}
+
+/// Parses HTML into balanced tokens, dropping whitespace-only text between
+/// block-level tags
+fn parse_html_tokens(html: &str, limits: &ImportLimits) -> Result<Vec<HtmlToken>, LimitExceeded> {
+    let tokens = html_parser::parse_with_limits(html, limits)?;
 
+    // Post-process: remove whitespace-only text tokens that appear between block-level tags
     let mut filtered_tokens = Vec::new();
     for (i, token) in tokens.iter().enumerate() {
         match token {
@@ -1035,7 +2734,7 @@ fn parse_html_tokens(html: &str) -> Result<Vec<HtmlToken>, HtmlError> {
                 let prev_is_block = if i > 0 {
                     match &tokens[i - 1] {
                         HtmlToken::CloseTag { name } | HtmlToken::OpenTag { name, .. } => {
-                            block_tags.contains(&name.as_str())
+                            BLOCK_TAGS.contains(&name.as_str())
                         }
                         _ => false,
                     }
@@ -1046,7 +2745,7 @@ fn parse_html_tokens(html: &str) -> Result<Vec<HtmlToken>, HtmlError> {
                 let next_is_block = if i + 1 < tokens.len() {
                     match &tokens[i + 1] {
                         HtmlToken::CloseTag { name } | HtmlToken::OpenTag { name, .. } => {
-                            block_tags.contains(&name.as_str())
+                            BLOCK_TAGS.contains(&name.as_str())
                         }
                         _ => false,
                     }
@@ -1066,72 +2765,78 @@ fn parse_html_tokens(html: &str) -> Result<Vec<HtmlToken>, HtmlError> {
     Ok(filtered_tokens)
 }
 
-/// Parses tag name and attributes from tag content
-fn parse_tag_and_attributes(content: &str) -> (String, std::collections::HashMap<String, String>) {
-    let mut parts = content.split_whitespace();
-    let name = parts.next().unwrap_or("").to_string();
-
-    let mut attributes = std::collections::HashMap::new();
-    let remainder: String = parts.collect::<Vec<_>>().join(" ");
-
-    // Simple attribute parsing (handles key="value" and key='value')
-    let mut chars = remainder.chars().peekable();
-    while let Some(c) = chars.next() {
-        if c.is_whitespace() {
-            continue;
-        }
-
-        // Read attribute name
-        let mut attr_name = String::new();
-        attr_name.push(c);
-        while let Some(&next) = chars.peek() {
-            if next == '=' || next.is_whitespace() {
-                break;
-            }
-            attr_name.push(chars.next().unwrap());
-        }
+/// Decodes HTML character references
+///
+/// Handles every named reference ending in `;` and decimal or hex numeric
+/// references. Unknown or malformed references are kept as text.
+fn decode_html_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
 
-        // Skip whitespace and '='
-        while let Some(&next) = chars.peek() {
-            if next == '=' {
-                chars.next();
-                break;
-            } else if next.is_whitespace() {
-                chars.next();
-            } else {
-                break;
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        result.push_str(&rest[..amp]);
+        let reference = &rest[amp + 1..];
+        match decode_reference(reference) {
+            Some((decoded, len)) => {
+                result.push_str(&decoded);
+                rest = &reference[len..];
             }
-        }
-
-        // Read attribute value
-        let mut attr_value = String::new();
-        if let Some(&quote) = chars.peek() {
-            if quote == '"' || quote == '\'' {
-                chars.next(); // consume opening quote
-                while let Some(c) = chars.next() {
-                    if c == quote {
-                        break;
-                    }
-                    attr_value.push(c);
-                }
+            None => {
+                result.push('&');
+                rest = reference;
             }
         }
+    }
+    result.push_str(rest);
+    result
+}
 
-        if !attr_name.is_empty() {
-            attributes.insert(attr_name.to_lowercase(), attr_value);
+/// Decodes the character reference at the start of `reference`, which
+/// follows an `&`
+///
+/// Returns the decoded text and the number of bytes consumed.
+fn decode_reference(reference: &str) -> Option<(String, usize)> {
+    if let Some(numeric) = reference.strip_prefix('#') {
+        let (digits, radix, prefix) = match numeric.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16, 2),
+            None => (numeric, 10, 1),
+        };
+        let len = digits
+            .find(|c: char| !c.is_digit(radix))
+            .unwrap_or(digits.len());
+        if len == 0 {
+            return None;
         }
+        // Out of range values decode to the replacement character
+        let code = u32::from_str_radix(&digits[..len], radix).unwrap_or(u32::MAX);
+        // The semicolon is optional for numeric references
+        let semicolon = usize::from(digits[len..].starts_with(';'));
+        return Some((
+            numeric_reference(code).to_string(),
+            prefix + len + semicolon,
+        ));
     }
 
-    (name, attributes)
+    let len = reference
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|&len| reference[len..].starts_with(';'))?;
+    let decoded = entities::lookup(&reference[..len])?;
+    Some((decoded.to_string(), len + 1))
 }
 
-/// Decodes HTML entities
-fn decode_html_entities(text: &str) -> String {
-    text.replace("&lt;", "<")
-        .replace("&gt;", ">")
-        .replace("&quot;", "\"")
-        .replace("&#39;", "'")
-        .replace("&amp;", "&")
+/// Maps a numeric character reference to the character browsers show
+///
+/// C1 control codes are read as Windows-1252, as in the HTML spec; NUL,
+/// surrogates and values past U+10FFFF become U+FFFD.
+fn numeric_reference(code: u32) -> char {
+    match code {
+        0 => char::REPLACEMENT_CHARACTER,
+        0x80..=0x9f => decode_cp1252(code as u8).unwrap_or_else(|| char::from(code as u8)),
+        _ => char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER),
+    }
 }
 
 /// Parses style attribute and returns the first applicable format
@@ -1154,6 +2859,7 @@ fn parse_style_to_formats(style: &str, sanitizer: &HtmlSanitizer) -> Option<Inli
                             color: value.to_string(),
                         });
                     }
+                    "font-variant" => return Some(InlineFormat::SmallCaps),
                     _ => {}
                 }
             }
@@ -1165,9 +2871,32 @@ fn parse_style_to_formats(style: &str, sanitizer: &HtmlSanitizer) -> Option<Inli
 #[cfg(test)]
 mod tests {
     use crate::document::Range;
+    use crate::selection::Selection;
 
     use super::*;
 
+    #[test]
+    fn test_block_to_html() {
+        let mut doc = Document::from_text("Title\nbody\nitem\nlet x;\nlet y;");
+        doc.set_block_type(Range::from_offsets(0, 5), BlockType::heading(2));
+        doc.set_block_type(Range::from_offsets(6, 10), BlockType::Paragraph);
+        doc.set_block_type(Range::from_offsets(11, 15), BlockType::NumberedList);
+        doc.set_block_type(Range::from_offsets(16, 29), BlockType::code_block());
+        doc.apply_format(Range::from_offsets(11, 13), InlineFormat::Bold);
+
+        assert_eq!(doc.block_to_html(0).as_deref(), Some("<h2>Title</h2>\n"));
+        assert_eq!(doc.block_to_html(1).as_deref(), Some("<p>body</p>\n"));
+        assert_eq!(
+            doc.block_to_html(2).as_deref(),
+            Some("<ol>\n<li><strong>it</strong>em</li>\n</ol>\n")
+        );
+        assert_eq!(
+            doc.block_to_html(4).as_deref(),
+            Some("<pre><code>let y;</code></pre>\n")
+        );
+        assert_eq!(doc.block_to_html(5), None);
+    }
+
     #[test]
     fn test_to_html_empty() {
         let doc = Document::new();
@@ -1230,6 +2959,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_to_html_image() {
+        let mut doc = Document::from_text("ab");
+        doc.insert_image(
+            Position::new(1),
+            Image::new("https://example.com/a.png?x=1&y=2", "A \"quoted\"")
+                .with_size(Some(64), Some(32)),
+        )
+        .unwrap();
+        assert_eq!(
+            doc.to_html(),
+            "<p>a<img src=\"https://example.com/a.png?x=1&amp;y=2\" alt=\"A &quot;quoted&quot;\" width=\"64\" height=\"32\">b</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_to_html_linked_image() {
+        let mut doc = Document::new();
+        doc.insert_image(Position::new(0), Image::new("a.png", ""))
+            .unwrap();
+        doc.apply_format(
+            Range::from_offsets(0, 1),
+            InlineFormat::Link {
+                url: "https://example.com".to_string(),
+            },
+        );
+        assert_eq!(
+            doc.to_html(),
+            "<p><a href=\"https://example.com\"><img src=\"a.png\" alt=\"\"></a></p>\n"
+        );
+    }
+
     #[test]
     fn test_to_html_text_color() {
         let mut doc = Document::from_text("Red text");
@@ -1281,6 +3042,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_to_html_highlight() {
+        let mut doc = Document::from_text("Marked");
+        doc.apply_format(Range::from_offsets(0, 6), InlineFormat::Highlight);
+        assert_eq!(doc.to_html(), "<p><mark>Marked</mark></p>\n");
+    }
+
+    #[test]
+    fn test_to_html_small_caps() {
+        let mut doc = Document::from_text("Caps");
+        doc.apply_format(Range::from_offsets(0, 4), InlineFormat::SmallCaps);
+        doc.apply_format(Range::from_offsets(0, 4), InlineFormat::Bold);
+        assert_eq!(
+            doc.to_html(),
+            "<p><strong><span style=\"font-variant: small-caps;\">Caps</span></strong></p>\n"
+        );
+    }
+
     #[test]
     fn test_to_html_heading() {
         let mut doc = Document::from_text("Heading");
@@ -1320,25 +3099,432 @@ mod tests {
     }
 
     #[test]
-    fn test_to_html_block_quote() {
-        let mut doc = Document::from_text("Quote");
-        doc.set_block_type(Range::from_offsets(0, 5), BlockType::BlockQuote);
-        assert_eq!(doc.to_html(), "<blockquote>Quote</blockquote>\n");
+    fn test_to_html_list_start() {
+        let mut doc = Document::from_markdown("3. Three\n4. Four").unwrap();
+        assert_eq!(
+            doc.to_html(),
+            "<ol start=\"3\">\n<li>Three</li>\n<li>Four</li>\n</ol>\n"
+        );
+
+        doc.formats_mut().set_list_start(6, None, Some(7));
+        assert_eq!(
+            doc.to_html(),
+            "<ol start=\"3\">\n<li>Three</li>\n<li value=\"7\">Four</li>\n</ol>\n"
+        );
+    }
+
+    #[test]
+    fn test_to_html_list_style() {
+        let mut doc = Document::from_text("One\nTwo\nThree");
+        doc.set_block_type(Range::from_offsets(0, 13), BlockType::NumberedList);
+        doc.set_list_style(Range::from_offsets(0, 7), Some(ListStyle::LowerAlpha))
+            .unwrap();
+        assert_eq!(
+            doc.to_html(),
+            "<ol style=\"list-style-type: lower-alpha\">\n<li>One</li>\n<li>Two</li>\n</ol>\n\
+             <ol>\n<li>Three</li>\n</ol>\n"
+        );
+
+        doc.set_block_type(Range::from_offsets(0, 13), BlockType::BulletList);
+        doc.set_list_style(Range::from_offsets(0, 13), Some(ListStyle::Bullet('"')))
+            .unwrap();
+        assert!(
+            doc.to_html()
+                .starts_with("<ul style=\"list-style-type: &#39;&quot; &#39;\">\n<li>One</li>")
+        );
+    }
+
+    #[test]
+    fn test_paragraph_indent_roundtrip() {
+        let mut doc = Document::from_text("One\nTwo");
+        doc.increase_block_indent(Range::from_offsets(0, 0))
+            .unwrap();
+        doc.increase_block_indent(Range::from_offsets(0, 0))
+            .unwrap();
+        doc.set_first_line_indent(Range::from_offsets(0, 7), true)
+            .unwrap();
+        let html = doc.to_html();
+        assert_eq!(
+            html,
+            "<p style=\"margin-left: 4em; text-indent: 2em\">One</p>\n\
+             <p style=\"text-indent: 2em\">Two</p>\n"
+        );
+
+        let restored = Document::from_html(&html).unwrap();
+        assert_eq!(restored.get_block_indent_at(Position::new(0)), 2);
+        assert!(restored.has_first_line_indent_at(Position::new(0)));
+        assert_eq!(restored.get_block_indent_at(Position::new(4)), 0);
+        assert!(restored.has_first_line_indent_at(Position::new(4)));
+    }
+
+    #[test]
+    fn test_from_html_paragraph_indent_units() {
+        let html = "<p style=\"margin-left: 40px\">A</p>\
+                    <div style=\"margin-left: 72pt; text-indent: -1em\">B</div>\
+                    <p style=\"margin-left: calc(1em + 2px); text-indent: 0\">C</p>";
+        let doc = Document::from_html(html).unwrap();
+        assert_eq!(doc.get_content(), "A\nB\nC");
+        assert_eq!(doc.get_block_indent_at(Position::new(0)), 1);
+        assert_eq!(doc.get_block_indent_at(Position::new(2)), 3);
+        assert!(!doc.has_first_line_indent_at(Position::new(2)));
+        assert_eq!(doc.get_block_indent_at(Position::new(4)), 0);
+
+        // Removing the style attribute from the whitelist drops the indents
+        let sanitizer = HtmlSanitizer::new().without_attribute("style");
+        let doc =
+            Document::from_html_with_sanitizer("<p style=\"margin-left: 4em\">A</p>", &sanitizer)
+                .unwrap();
+        assert_eq!(doc.get_block_indent_at(Position::new(0)), 0);
+    }
+
+    #[test]
+    fn test_text_direction_roundtrip() {
+        let mut doc = Document::from_text("مرحبا\nTitle\nItem\nQuote\ncode");
+        doc.set_block_type(Range::from_offsets(6, 11), BlockType::heading(2));
+        doc.set_block_type(Range::from_offsets(12, 16), BlockType::BulletList);
+        doc.set_block_type(Range::from_offsets(17, 22), BlockType::block_quote());
+        doc.set_block_type(Range::from_offsets(23, 27), BlockType::code_block());
+        doc.set_text_direction(Range::from_offsets(0, 27), Some(TextDirection::Rtl))
+            .unwrap();
+        doc.set_text_direction(Range::from_offsets(6, 6), Some(TextDirection::Ltr))
+            .unwrap();
+
+        let html = doc.to_html();
+        assert_eq!(
+            html,
+            "<p dir=\"rtl\">مرحبا</p>\n<h2 dir=\"ltr\">Title</h2>\n<ul>\n<li dir=\"rtl\">Item</li>\n</ul>\n\
+             <blockquote dir=\"rtl\">Quote</blockquote>\n<pre dir=\"rtl\"><code>code</code></pre>\n"
+        );
+
+        let restored = Document::from_html(&html).unwrap();
+        assert_eq!(restored.get_content(), doc.get_content());
+        for offset in [0, 12, 17, 23] {
+            assert_eq!(
+                restored.get_text_direction_at(Position::new(offset)),
+                Some(TextDirection::Rtl)
+            );
+        }
+        assert_eq!(
+            restored.get_text_direction_at(Position::new(6)),
+            Some(TextDirection::Ltr)
+        );
+    }
+
+    #[test]
+    fn test_from_html_inherited_direction() {
+        let html = "<div dir=\"RTL\"><p>א</p><p dir=\"ltr\">b</p></div>\
+                    <ul dir=\"rtl\"><li>c</li></ul><p dir=\"auto\">d</p><p dir=\"up\">e</p>";
+        let doc = Document::from_html(html).unwrap();
+        assert_eq!(doc.get_content(), "א\nb\nc\nd\ne");
+        let directions: Vec<_> = [0, 2, 4, 6, 8]
+            .into_iter()
+            .map(|offset| doc.get_text_direction_at(Position::new(offset)))
+            .collect();
+        assert_eq!(
+            directions,
+            vec![
+                Some(TextDirection::Rtl),
+                Some(TextDirection::Ltr),
+                Some(TextDirection::Rtl),
+                None,
+                None
+            ]
+        );
+        assert!(
+            doc.to_html()
+                .starts_with("<p dir=\"rtl\">א</p>\n<p dir=\"ltr\">b</p>")
+        );
+    }
+
+    #[test]
+    fn test_to_html_nested_list() {
+        let mut doc = Document::from_text("One\nTwo\nThree\nFour");
+        doc.set_block_type(Range::from_offsets(0, 18), BlockType::BulletList);
+        doc.set_block_type(Range::from_offsets(8, 13), BlockType::NumberedList);
+        doc.indent_list_items(Range::from_offsets(4, 13)).unwrap();
+        doc.indent_list_items(Range::from_offsets(8, 13)).unwrap();
+
+        assert_eq!(
+            doc.to_html(),
+            "<ul>\n<li>One\n<ul>\n<li>Two\n<ol>\n<li>Three</li>\n</ol>\n</li>\n</ul>\n</li>\n\
+             <li>Four</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn test_to_html_block_quote() {
+        let mut doc = Document::from_text("Quote");
+        doc.set_block_type(Range::from_offsets(0, 5), BlockType::block_quote());
+        assert_eq!(doc.to_html(), "<blockquote>Quote</blockquote>\n");
+    }
+
+    #[test]
+    fn test_to_html_nested_block_quote_with_cite() {
+        let mut doc = Document::from_text("Quote");
+        doc.set_block_type(
+            Range::from_offsets(0, 5),
+            BlockType::block_quote_with(Some("https://example.com/?a=1&b=2".to_string()), 2),
+        );
+        assert_eq!(
+            doc.to_html(),
+            "<blockquote><blockquote cite=\"https://example.com/?a=1&amp;b=2\">Quote</blockquote></blockquote>\n"
+        );
+    }
+
+    #[test]
+    fn test_from_html_deep_block_quote_is_clamped() {
+        let html = format!(
+            "{}Deep{}",
+            "<blockquote>".repeat(40),
+            "</blockquote>".repeat(40)
+        );
+        let doc = Document::from_html(&html).unwrap();
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)).quote_depth(),
+            Some(crate::formatting::block::MAX_QUOTE_DEPTH)
+        );
+    }
+
+    #[test]
+    fn test_to_html_no_spellcheck_splits_runs() {
+        let mut doc = Document::from_text("use serde_json here");
+        doc.apply_format(Range::from_offsets(0, 9), InlineFormat::Bold);
+        let version = doc.version();
+        let unmarked = doc.to_html();
+        doc.mark_no_spellcheck(Range::from_offsets(4, 14)).unwrap();
+        assert_ne!(doc.version(), version);
+        assert_eq!(
+            doc.to_html(),
+            "<p><strong>use </strong><span spellcheck=\"false\"><strong>serde</strong></span>\
+             <span spellcheck=\"false\">_json</span> here</p>\n"
+        );
+        doc.clear_no_spellcheck(Range::from_offsets(4, 14)).unwrap();
+        assert_eq!(doc.to_html(), unmarked);
+        doc.mark_no_spellcheck(Range::from_offsets(4, 14)).unwrap();
+
+        doc.delete_range(Range::from_offsets(4, 14)).unwrap();
+        assert!(!doc.to_html().contains("spellcheck"));
+    }
+
+    #[test]
+    fn test_to_html_with_format_styles() {
+        let mut doc = Document::from_text("Hi there\nnext");
+        doc.apply_format(Range::from_offsets(0, 8), InlineFormat::Bold);
+        doc.apply_format(Range::from_offsets(3, 8), InlineFormat::Underline);
+        doc.apply_format(Range::from_offsets(3, 8), InlineFormat::Strikethrough);
+        doc.apply_format(
+            Range::from_offsets(3, 8),
+            InlineFormat::Link {
+                url: "https://example.com".to_string(),
+            },
+        );
+        doc.set_block_type(Range::from_offsets(9, 13), BlockType::heading(2));
+
+        let classes = HtmlExportOptions::new().format_style(HtmlFormatStyle::Classes);
+        assert_eq!(
+            doc.to_html_with_options(&classes),
+            "<p><span class=\"rte-bold\">Hi </span><a href=\"https://example.com\">\
+             <span class=\"rte-bold rte-underline rte-strikethrough\">there</span></a></p>\n\
+             <h2>next</h2>\n"
+        );
+
+        let inline = HtmlExportOptions::new()
+            .format_style(HtmlFormatStyle::InlineStyles)
+            .data_offsets(true);
+        assert_eq!(
+            doc.to_html_with_options(&inline),
+            "<p data-offset=\"0\"><span style=\"font-weight: bold\">Hi </span>\
+             <a href=\"https://example.com\"><span style=\"font-weight: bold; \
+             text-decoration: underline line-through\">there</span></a></p>\n\
+             <h2 data-offset=\"9\">next</h2>\n"
+        );
+        assert_eq!(
+            doc.to_html_with_options(&HtmlExportOptions::new()),
+            doc.to_html()
+        );
+    }
+
+    #[test]
+    fn test_to_html_with_options_standalone() {
+        let mut doc = Document::from_text("Body");
+        doc.set_metadata(metadata::TITLE, "Notes").unwrap();
+        let standalone = HtmlExportOptions::new().standalone(true);
+        assert_eq!(
+            doc.to_html_with_options(&standalone),
+            doc.to_html_document()
+        );
+
+        let page = doc.to_html_with_options(&standalone.data_offsets(true));
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("<title>Notes</title>"));
+        assert!(page.contains("<p data-offset=\"0\">Body</p>"));
+        assert_eq!(
+            Document::new().to_html_with_options(&HtmlExportOptions::new().data_offsets(true)),
+            "<p data-offset=\"0\"></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_to_html_code_block() {
+        let mut doc = Document::from_text("code");
+        doc.set_block_type(Range::from_offsets(0, 4), BlockType::code_block());
+        assert_eq!(doc.to_html(), "<pre><code>code</code></pre>\n");
+    }
+
+    #[test]
+    fn test_math_round_trip() {
+        let mut doc = Document::from_text("Let x<1\nE=mc^2");
+        doc.apply_format(
+            Range::from_offsets(4, 7),
+            InlineFormat::Math { display: false },
+        );
+        doc.apply_format(
+            Range::from_offsets(8, 14),
+            InlineFormat::Math { display: true },
+        );
+        let html = doc.to_html();
+        assert_eq!(
+            html,
+            "<p>Let <span class=\"math\">x&lt;1</span></p>\n\
+             <p><span class=\"math math-display\">E=mc^2</span></p>\n"
+        );
+        let options = HtmlExportOptions::new().format_style(HtmlFormatStyle::Classes);
+        assert!(
+            doc.to_html_with_options(&options)
+                .contains("<span class=\"math\">x&lt;1</span>")
+        );
+
+        let restored = Document::from_html(&html).unwrap();
+        assert_eq!(restored.get_content(), "Let x<1\nE=mc^2");
+        assert!(
+            restored
+                .get_formats_at(Position::new(5))
+                .contains(&InlineFormat::Math { display: false })
+        );
+        assert!(
+            restored
+                .get_formats_at(Position::new(9))
+                .contains(&InlineFormat::Math { display: true })
+        );
+    }
+
+    #[test]
+    fn test_code_block_language_round_trip() {
+        let mut doc = Document::from_text("a\nb\nc");
+        doc.set_block_type(
+            Range::from_offsets(0, 3),
+            BlockType::code_block_with(Some("rust")),
+        );
+        doc.set_block_type(Range::from_offsets(4, 5), BlockType::code_block());
+        let html = doc.to_html();
+        assert_eq!(
+            html,
+            "<pre><code class=\"language-rust\">a\nb</code></pre>\n<pre><code>c</code></pre>\n"
+        );
+        assert_eq!(
+            doc.block_to_html(1).as_deref(),
+            Some("<pre><code class=\"language-rust\">b</code></pre>\n")
+        );
+
+        let restored = Document::from_html(&html).unwrap();
+        assert_eq!(
+            restored.get_block_type_at(Position::new(2)),
+            BlockType::code_block_with(Some("rust"))
+        );
+        assert_eq!(
+            restored.get_block_type_at(Position::new(4)),
+            BlockType::code_block()
+        );
+
+        let doc = Document::from_html("<pre class=\"lang-go\"><code>x</code></pre>").unwrap();
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)).code_language(),
+            Some("go")
+        );
+    }
+
+    #[test]
+    fn test_to_html_multiple_formats() {
+        let mut doc = Document::from_text("Hello");
+        doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+        doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Italic);
+        assert_eq!(doc.to_html(), "<p><strong><em>Hello</em></strong></p>\n");
+    }
+
+    #[test]
+    fn test_to_html_overlapping_runs_across_lines() {
+        let mut doc = Document::from_text("abcd\nefgh");
+        doc.apply_format(Range::from_offsets(1, 7), InlineFormat::Bold);
+        doc.apply_format(Range::from_offsets(2, 3), InlineFormat::Italic);
+        assert_eq!(
+            doc.to_html(),
+            "<p>a<strong>b</strong><strong><em>c</em></strong><strong>d</strong></p>\n<p><strong>ef</strong>gh</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_to_html_range_marks_transient_decorations() {
+        let mut doc = Document::from_text("Say hello\nworld");
+        doc.apply_format(Range::from_offsets(4, 9), InlineFormat::Bold);
+        doc.set_transient_decorations([
+            (Range::from_offsets(2, 6), "x".to_string()),
+            (Range::from_offsets(8, 12), "a\"b".to_string()),
+        ])
+        .unwrap();
+        assert!(
+            doc.set_transient_decorations([(Range::from_offsets(3, 3), "y".to_string())])
+                .is_err()
+        );
+
+        doc.delete_range(Range::from_offsets(0, 1)).unwrap();
+        assert_eq!(
+            doc.to_html_range(None),
+            "<p>a<mark data-deco-id=\"x\">y </mark><mark data-deco-id=\"x\"><strong>he</strong></mark><strong>ll</strong><mark data-deco-id=\"a&quot;b\"><strong>o</strong></mark></p>\n<p><mark data-deco-id=\"a&quot;b\">wo</mark>rld</p>\n"
+        );
+        assert_eq!(
+            doc.to_html(),
+            "<p>ay <strong>hello</strong></p>\n<p>world</p>\n"
+        );
+        assert!(!doc.to_json().unwrap().contains("a\\\"b"));
+    }
+
+    #[test]
+    fn test_prerender_html_matches_full_render() {
+        let mut doc = Document::from_text("Title\none\ntwo\nlet x = 1;\nlet y = 2;\nEnd\n");
+        doc.set_block_type(Range::from_offsets(0, 5), BlockType::heading(1));
+        doc.set_block_type(Range::from_offsets(6, 13), BlockType::BulletList);
+        doc.set_block_type(Range::from_offsets(14, 35), BlockType::code_block());
+        doc.apply_format(Range::from_offsets(36, 39), InlineFormat::Bold);
+        let expected = doc.to_html_range(None);
+
+        let mut chunks = 1;
+        while !doc.prerender_html_chunk(2) {
+            chunks += 1;
+        }
+        assert_eq!(chunks, 3);
+        assert!(doc.is_html_prerendered());
+        assert_eq!(doc.to_html(), expected);
     }
 
     #[test]
-    fn test_to_html_code_block() {
-        let mut doc = Document::from_text("code");
-        doc.set_block_type(Range::from_offsets(0, 4), BlockType::CodeBlock);
-        assert_eq!(doc.to_html(), "<pre><code>code</code></pre>\n");
+    fn test_prerender_html_invalidated_by_edit() {
+        let mut doc = Document::from_text("Hello");
+        assert!(doc.prerender_html_chunk(10));
+        assert_eq!(doc.to_html(), "<p>Hello</p>\n");
+
+        doc.insert_text(Position::new(5), " World").unwrap();
+        assert!(!doc.is_html_prerendered());
+        assert_eq!(doc.to_html(), "<p>Hello World</p>\n");
+
+        assert!(doc.prerender_html_chunk(10));
+        assert_eq!(doc.to_html(), "<p>Hello World</p>\n");
     }
 
     #[test]
-    fn test_to_html_multiple_formats() {
-        let mut doc = Document::from_text("Hello");
-        doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
-        doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Italic);
-        assert_eq!(doc.to_html(), "<p><strong><em>Hello</em></strong></p>\n");
+    fn test_prerender_html_empty_document() {
+        let mut doc = Document::new();
+        assert!(doc.prerender_html_chunk(1));
+        assert_eq!(doc.to_html(), "<p></p>\n");
     }
 
     #[test]
@@ -1449,6 +3635,67 @@ mod tests {
         assert!(formats.contains(&InlineFormat::Strikethrough));
     }
 
+    #[test]
+    fn test_html_subscript_and_superscript() {
+        let mut doc = Document::from_text("H2O x2");
+        doc.apply_format(Range::from_offsets(1, 2), InlineFormat::Subscript);
+        doc.apply_format(Range::from_offsets(5, 6), InlineFormat::Superscript);
+        let html = doc.to_html();
+        assert_eq!(html, "<p>H<sub>2</sub>O x<sup>2</sup></p>\n");
+
+        let imported = Document::from_html(&html).unwrap();
+        assert_eq!(imported.get_content(), "H2O x2");
+        assert!(
+            imported
+                .get_formats_at(Position::new(1))
+                .contains(&InlineFormat::Subscript)
+        );
+        assert!(
+            imported
+                .get_formats_at(Position::new(5))
+                .contains(&InlineFormat::Superscript)
+        );
+    }
+
+    #[test]
+    fn test_from_html_highlight_and_small_caps() {
+        let html = "<p><mark>Hi</mark> <span style=\"font-variant: small-caps\">Caps</span></p>";
+        let doc = Document::from_html(html).unwrap();
+        assert_eq!(doc.get_content(), "Hi Caps");
+
+        let formats = doc.get_formats_at(Position::new(1));
+        assert!(formats.contains(&InlineFormat::Highlight));
+        assert!(
+            !formats
+                .iter()
+                .any(|f| matches!(f, InlineFormat::BackgroundColor { .. }))
+        );
+
+        let formats = doc.get_formats_at(Position::new(4));
+        assert!(formats.contains(&InlineFormat::SmallCaps));
+    }
+
+    #[test]
+    fn test_html_roundtrip_highlight_with_background_color() {
+        let mut doc = Document::from_text("Both");
+        doc.apply_format(Range::from_offsets(0, 4), InlineFormat::Highlight);
+        doc.apply_format(
+            Range::from_offsets(0, 4),
+            InlineFormat::BackgroundColor {
+                color: "yellow".to_string(),
+            },
+        );
+        doc.apply_format(Range::from_offsets(0, 4), InlineFormat::SmallCaps);
+
+        let restored = Document::from_html(&doc.to_html()).unwrap();
+        let formats = restored.get_formats_at(Position::new(1));
+        assert!(formats.contains(&InlineFormat::Highlight));
+        assert!(formats.contains(&InlineFormat::SmallCaps));
+        assert!(formats.contains(&InlineFormat::BackgroundColor {
+            color: "yellow".to_string(),
+        }));
+    }
+
     #[test]
     fn test_from_html_code() {
         let doc = Document::from_html("<p><code>code</code></p>").unwrap();
@@ -1470,6 +3717,41 @@ mod tests {
         }));
     }
 
+    #[test]
+    fn test_from_html_image() {
+        let doc =
+            Document::from_html("<p>a<img src=\"a.png?x=1&amp;y=2\" alt=\"A\" width=\"64\">b</p>")
+                .unwrap();
+        assert_eq!(doc.get_content(), format!("a{}b", EMBED_CHAR));
+
+        let image = doc.get_image_at(Position::new(1)).unwrap();
+        assert_eq!(image.src, "a.png?x=1&y=2");
+        assert_eq!(image.alt, "A");
+        assert_eq!(image.width, Some(64));
+        assert_eq!(image.height, None);
+    }
+
+    #[test]
+    fn test_from_html_image_strips_unsafe_src() {
+        let doc = Document::from_html("<p>a<img src=\"javascript:alert(1)\" />b</p>").unwrap();
+        assert_eq!(doc.get_content(), "ab");
+
+        let doc =
+            Document::from_html("<p><img src=\"data:image/svg+xml;base64,PHN2Zz4=\"></p>").unwrap();
+        assert_eq!(doc.get_content(), "");
+    }
+
+    #[test]
+    fn test_html_roundtrip_image() {
+        let mut doc = Document::from_text("ab");
+        let image = Image::new("https://example.com/a.png", "A").with_size(Some(10), None);
+        doc.insert_image(Position::new(1), image.clone()).unwrap();
+
+        let restored = Document::from_html(&doc.to_html()).unwrap();
+        assert_eq!(restored.get_content(), doc.get_content());
+        assert_eq!(restored.get_image_at(Position::new(1)), Some(&image));
+    }
+
     #[test]
     fn test_from_html_sanitize_javascript_url() {
         let doc =
@@ -1528,13 +3810,132 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_from_html_nested_list() {
+        let html = "<ul><li>One<ol><li>Two</li><li>Three</li></ol></li><li>Four</li></ul>";
+        let doc = Document::from_html(html).unwrap();
+        assert_eq!(doc.get_content(), "One\nTwo\nThree\nFour");
+
+        for (offset, block_type, indent) in [
+            (0, BlockType::BulletList, 0),
+            (4, BlockType::NumberedList, 1),
+            (8, BlockType::NumberedList, 1),
+            (14, BlockType::BulletList, 0),
+        ] {
+            assert_eq!(doc.get_block_type_at(Position::new(offset)), block_type);
+            assert_eq!(doc.get_list_indent_at(Position::new(offset)), indent);
+        }
+        assert_eq!(
+            Document::from_html(&doc.to_html()).unwrap().to_html(),
+            doc.to_html()
+        );
+    }
+
+    #[test]
+    fn test_from_html_paragraph_after_blocks() {
+        let html = "<h1>Title</h1><ul><li>Item</li></ul><p>Text</p><pre><code>x\n</code></pre>";
+        let doc = Document::from_html(html).unwrap();
+        assert_eq!(doc.get_content(), "Title\nItem\nText\nx");
+        assert_eq!(
+            doc.get_block_type_at(Position::new(6)),
+            BlockType::BulletList
+        );
+        assert_eq!(
+            doc.get_block_type_at(Position::new(11)),
+            BlockType::Paragraph
+        );
+        assert_eq!(
+            doc.get_block_type_at(Position::new(16)),
+            BlockType::code_block()
+        );
+    }
+
+    #[test]
+    fn test_from_html_collapses_indented_markup() {
+        let html = "<ul>\n  <li>\n    One\n  </li>\n  <li>Two  <b> three </b>  four</li>\n</ul>\n<p>\n  Tail\t text\n</p>\n";
+        let doc = Document::from_html(html).unwrap();
+        assert_eq!(doc.get_content(), "One\nTwo three four\nTail text");
+        assert!(
+            doc.get_formats_at(Position::new(8))
+                .contains(&InlineFormat::Bold)
+        );
+        assert!(
+            !doc.get_formats_at(Position::new(7))
+                .contains(&InlineFormat::Bold)
+        );
+    }
+
+    #[test]
+    fn test_from_html_keeps_preformatted_whitespace() {
+        let html = "<pre><code>fn  main() {\n    x\n}</code></pre><p style=\"white-space: pre\">a   b</p><p style=\"white-space: pre-line\">c   d\ne</p>";
+        let doc = Document::from_html(html).unwrap();
+        assert_eq!(doc.get_content(), "fn  main() {\n    x\n}\na   b\nc d\ne");
+    }
+
+    #[test]
+    fn test_from_html_preserve_whitespace_option() {
+        let options = HtmlImportOptions::new().whitespace(WhitespaceHandling::Preserve);
+        let doc = Document::from_html_with_options("<p>a  b</p>", &HtmlSanitizer::new(), &options)
+            .unwrap();
+        assert_eq!(doc.get_content(), "a  b");
+    }
+
     #[test]
     fn test_from_html_block_quote() {
         let doc = Document::from_html("<blockquote>Quote</blockquote>").unwrap();
         assert_eq!(doc.get_content(), "Quote");
         assert_eq!(
             doc.get_block_type_at(Position::new(0)),
-            BlockType::BlockQuote
+            BlockType::block_quote()
+        );
+    }
+
+    #[test]
+    fn test_from_html_nested_block_quote_with_cite() {
+        let html = "<blockquote cite=\"https://example.com\"><p>Outer</p><blockquote>Inner</blockquote></blockquote>";
+        let doc = Document::from_html(html).unwrap();
+        assert_eq!(doc.get_content(), "Outer\nInner");
+
+        // The inner quote must not be flattened when the outer one closes
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::block_quote_with(Some("https://example.com".to_string()), 1)
+        );
+        assert_eq!(
+            doc.get_block_type_at(Position::new(6)),
+            BlockType::block_quote_with(None, 2)
+        );
+    }
+
+    #[test]
+    fn test_from_html_block_quote_strips_unsafe_cite() {
+        let doc =
+            Document::from_html("<blockquote cite=\"javascript:alert(1)\">Quote</blockquote>")
+                .unwrap();
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::block_quote()
+        );
+    }
+
+    #[test]
+    fn test_html_roundtrip_nested_block_quote() {
+        let mut doc = Document::from_text("Top\nNested");
+        doc.set_block_type(Range::from_offsets(0, 3), BlockType::block_quote());
+        doc.set_block_type(
+            Range::from_offsets(4, 10),
+            BlockType::block_quote_with(Some("https://example.com".to_string()), 2),
+        );
+
+        let restored = Document::from_html(&doc.to_html()).unwrap();
+        assert_eq!(restored.get_content(), "Top\nNested");
+        assert_eq!(
+            restored.get_block_type_at(Position::new(0)),
+            BlockType::block_quote()
+        );
+        assert_eq!(
+            restored.get_block_type_at(Position::new(4)),
+            BlockType::block_quote_with(Some("https://example.com".to_string()), 2)
         );
     }
 
@@ -1544,7 +3945,7 @@ mod tests {
         assert_eq!(doc.get_content(), "code");
         assert_eq!(
             doc.get_block_type_at(Position::new(0)),
-            BlockType::CodeBlock
+            BlockType::code_block()
         );
     }
 
@@ -1566,6 +3967,47 @@ mod tests {
         assert_eq!(doc.get_content(), "<script>alert('xss')</script>");
     }
 
+    #[test]
+    fn test_from_html_decode_named_and_numeric_entities() {
+        let html = "<p>a&nbsp;b&mdash;c&#x27;d&#8364;&#169&hellip;&NotEqualTilde;</p>";
+        let doc = Document::from_html(html).unwrap();
+        assert_eq!(
+            doc.get_content(),
+            "a\u{a0}b\u{2014}c'd\u{20ac}\u{a9}\u{2026}\u{2242}\u{338}"
+        );
+
+        // C1 codes read as Windows-1252; invalid code points are replaced
+        let doc = Document::from_html("<p>&#150;&#0;&#x110000;&#xD800;</p>").unwrap();
+        assert_eq!(doc.get_content(), "\u{2013}\u{fffd}\u{fffd}\u{fffd}");
+
+        // Unknown or unterminated references stay as text
+        let doc = Document::from_html("<p>&bogus; &amp AT&T &#; &#xZ;</p>").unwrap();
+        assert_eq!(doc.get_content(), "&bogus; &amp AT&T &#; &#xZ;");
+    }
+
+    #[test]
+    fn test_from_html_decodes_link_href() {
+        let doc = Document::from_html(r#"<a href="/search?a=1&amp;b=2">Go</a>"#).unwrap();
+        assert!(
+            doc.get_formats_at(Position::new(0))
+                .contains(&InlineFormat::Link {
+                    url: "/search?a=1&b=2".to_string()
+                })
+        );
+    }
+
+    #[test]
+    fn test_from_html_entity_encoded_javascript_url() {
+        let html = r#"<a href="&#106;avascript&colon;alert(1)">Click</a>"#;
+        let doc = Document::from_html(html).unwrap();
+        assert_eq!(doc.get_content(), "Click");
+        assert!(
+            !doc.get_formats_at(Position::new(0))
+                .iter()
+                .any(|f| matches!(f, InlineFormat::Link { .. }))
+        );
+    }
+
     #[test]
     fn test_from_html_strip_disallowed_tags() {
         let doc = Document::from_html("<p>Hello <script>alert('xss')</script>World</p>").unwrap();
@@ -1586,7 +4028,32 @@ mod tests {
     #[test]
     fn test_from_html_br_tag() {
         let doc = Document::from_html("<p>Line 1<br/>Line 2</p>").unwrap();
-        assert_eq!(doc.get_content(), "Line 1\nLine 2");
+        assert_eq!(doc.get_content(), "Line 1\u{2028}Line 2");
+        assert_eq!(doc.line_count(), 1);
+
+        // A break at the end of a block, or on its own line, ends the line
+        let doc = Document::from_html("<p>a<br></p><p>b</p><div><br></div><p>c</p>").unwrap();
+        assert_eq!(doc.get_content(), "a\nb\n\nc");
+        let doc = Document::from_html("<pre>a<br>b</pre>").unwrap();
+        assert_eq!(doc.get_content(), "a\nb");
+    }
+
+    #[test]
+    fn test_soft_break_roundtrip_html() {
+        let mut doc = Document::from_text("ab\ncd");
+        doc.set_block_type(Range::from_offsets(0, 2), BlockType::heading(2));
+        doc.set_selection(Selection::collapsed(Position::new(1)));
+        doc.insert_soft_break().unwrap();
+
+        let html = doc.to_html();
+        assert!(html.starts_with("<h2>a<br>b</h2>"), "{html}");
+        let imported = Document::from_html(&html).unwrap();
+        assert_eq!(imported.get_content(), doc.get_content());
+        assert_eq!(
+            imported.get_block_type_at(Position::new(2)),
+            BlockType::heading(2)
+        );
+        assert_eq!(imported.to_plain_text(), "a\nb\ncd");
     }
 
     #[test]
@@ -1696,6 +4163,62 @@ mod tests {
         assert!(!sanitizer.is_safe_url("   "));
     }
 
+    #[test]
+    fn test_sanitizer_url_schemes() {
+        let sanitizer = HtmlSanitizer::new()
+            .with_url_scheme("tel:")
+            .with_url_scheme("javascript")
+            .without_url_scheme("mailto");
+        assert!(sanitizer.is_safe_url("TEL:+1-555-0100"));
+        assert!(!sanitizer.is_safe_url("mailto:user@example.com"));
+        assert!(!sanitizer.is_safe_url("javascript:alert(1)"));
+        assert!(sanitizer.is_safe_url("/relative/path"));
+
+        let doc = Document::from_html_with_sanitizer(r#"<a href="tel:123">Call</a>"#, &sanitizer)
+            .unwrap();
+        assert!(
+            doc.get_formats_at(Position::new(0))
+                .contains(&InlineFormat::Link {
+                    url: "tel:123".to_string()
+                })
+        );
+    }
+
+    #[test]
+    fn test_sanitizer_policy_cannot_allow_script() {
+        let sanitizer = HtmlSanitizer::new()
+            .with_tag("script")
+            .with_tag("IFRAME")
+            .with_tag("abbr")
+            .with_attribute("onclick")
+            .with_attribute("on*")
+            .with_attribute("title");
+        assert_eq!(
+            sanitizer.sanitize(
+                "<abbr title=\"a &amp; b\" onclick=\"x()\">A&B</abbr><script>x()</script><iframe src=\"/\"></iframe>"
+            ),
+            "<abbr title=\"a &amp; b\">A&amp;B</abbr>"
+        );
+    }
+
+    #[test]
+    fn test_sanitizer_css_properties() {
+        let sanitizer = HtmlSanitizer::new()
+            .with_css_property("Font-Weight")
+            .with_css_property("background-image")
+            .without_css_property("color");
+        assert_eq!(
+            sanitizer.sanitize(
+                "<span style=\"color: red; font-weight: 700; background-image: url(x.png)\">a</span>"
+            ),
+            "<span style=\"font-weight: 700\">a</span>"
+        );
+        assert_eq!(
+            HtmlSanitizer::new().sanitize("<p style=\"color: red; position: fixed\">a<b>b</p>"),
+            "<p style=\"color: red\">a<b>b</b></p>"
+        );
+    }
+
     #[test]
     fn test_sanitizer_valid_color_hex() {
         let sanitizer = HtmlSanitizer::new();
@@ -1789,6 +4312,19 @@ mod tests {
         assert!(result.is_empty());
     }
 
+    #[test]
+    fn test_sanitizer_style_allows_indent_lengths() {
+        let sanitizer = HtmlSanitizer::new();
+        assert_eq!(
+            sanitizer.sanitize_style("margin-left: 2em; text-indent: -12pt; margin-right: 1em"),
+            "margin-left: 2em; text-indent: -12pt"
+        );
+        assert_eq!(
+            sanitizer.sanitize_style("margin-left: expression(alert(1)); text-indent: 1e3px"),
+            ""
+        );
+    }
+
     #[test]
     fn test_sanitizer_attribute_href_safe() {
         let sanitizer = HtmlSanitizer::new();
@@ -1972,12 +4508,202 @@ mod tests {
 
     #[test]
     fn test_from_html_nested_disallowed_tags() {
-        let doc = Document::from_html("<p>Hello <div><script>alert('xss')</script></div>World</p>")
-            .unwrap();
+        let doc =
+            Document::from_html("<p>Hello <object><script>alert('xss')</script></object>World</p>")
+                .unwrap();
         // Nested disallowed tags should be stripped
         assert_eq!(doc.get_content(), "Hello World");
     }
 
+    #[test]
+    fn test_from_html_closes_open_elements() {
+        let doc = Document::from_html(
+            "<html><body><!--StartFragment--><p>One<p>Two<ul><li>a<li>b<ul><li>c</ul><li>d</ul>",
+        )
+        .unwrap();
+        assert_eq!(doc.get_content(), "One\nTwo\na\nb\nc\nd");
+        assert_eq!(
+            doc.get_block_type_at(Position::new(8)),
+            BlockType::BulletList
+        );
+        assert_eq!(doc.get_list_indent_at(Position::new(10)), 0);
+        assert_eq!(doc.get_list_indent_at(Position::new(12)), 1);
+        assert_eq!(doc.get_list_indent_at(Position::new(14)), 0);
+
+        let doc =
+            Document::from_html(r#"<p title="1 > 0"><b>bo<i>th</b> it</i> 1 < 2</p>"#).unwrap();
+        assert_eq!(doc.get_content(), "both it 1 < 2");
+        assert!(
+            doc.get_formats_at(Position::new(1))
+                .contains(&InlineFormat::Bold)
+        );
+        assert!(
+            !doc.get_formats_at(Position::new(6))
+                .contains(&InlineFormat::Bold)
+        );
+        assert!(
+            doc.get_formats_at(Position::new(6))
+                .contains(&InlineFormat::Italic)
+        );
+    }
+
+    #[test]
+    fn test_from_html_table_rows() {
+        let doc = Document::from_html(
+            "<p>Before</p><table><tbody><tr><th>Name<th>Qty\
+             <tr><td><p>Apples</p><td><b>3</b></table><p>After</p>",
+        )
+        .unwrap();
+        assert_eq!(doc.get_content(), "Before\nName\tQty\nApples\t3\nAfter");
+        assert!(
+            doc.get_formats_at(Position::new(23))
+                .contains(&InlineFormat::Bold)
+        );
+
+        let sanitizer = HtmlSanitizer::new().without_tag("table");
+        let doc =
+            Document::from_html_with_sanitizer("<table><tr><td>x</table>y", &sanitizer).unwrap();
+        assert_eq!(doc.get_content(), "y");
+    }
+
+    #[test]
+    fn test_from_html_div_lines() {
+        let doc =
+            Document::from_html("<div>One</div><section>Two</section><article>Three</article>")
+                .unwrap();
+        assert_eq!(doc.get_content(), "One\nTwo\nThree");
+
+        let doc = Document::from_html("<h1>Title</h1><div>Body</div>").unwrap();
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::heading(1)
+        );
+        assert_eq!(
+            doc.get_block_type_at(Position::new(6)),
+            BlockType::Paragraph
+        );
+    }
+
+    #[test]
+    fn test_from_html_gmail_fragment() {
+        let html = "<meta charset=\"utf-8\"><div dir=\"ltr\">Hi team,<div><br></div>\
+            <div>See the <b>notes</b> below.</div><div><br></div><div>Thanks</div></div>";
+        let doc = Document::from_html(html).unwrap();
+        assert_eq!(
+            doc.get_content(),
+            "Hi team,\n\nSee the notes below.\n\nThanks"
+        );
+        assert!(
+            doc.get_formats_at(Position::new(18))
+                .contains(&InlineFormat::Bold)
+        );
+        assert!(
+            !doc.get_formats_at(Position::new(0))
+                .contains(&InlineFormat::Bold)
+        );
+    }
+
+    #[test]
+    fn test_from_html_google_docs_fragment() {
+        let html = "<meta charset=\"utf-8\"><!--StartFragment-->\
+            <b style=\"font-weight:normal;\" id=\"docs-internal-guid-1a2b\">\
+            <div dir=\"ltr\" style=\"margin-left:0pt;\" align=\"left\">\
+            <p dir=\"ltr\" style=\"line-height:1.38;\"><span style=\"font-size:11pt;\">First</span></p>\
+            <p dir=\"ltr\" style=\"line-height:1.38;\"><span style=\"font-size:11pt;\">Second</span></p>\
+            </div><br><div dir=\"ltr\"><span>Third</span></div></b><!--EndFragment-->";
+        let doc = Document::from_html(html).unwrap();
+        assert_eq!(doc.get_content(), "First\nSecond\n\nThird");
+        assert!(
+            !doc.get_formats_at(Position::new(0))
+                .contains(&InlineFormat::Bold)
+        );
+    }
+
+    #[test]
+    fn test_html_source_map_text_matches_document() {
+        let mut doc = Document::from_text("Tom & <Jerry>\n\nItem\nfn main() {}");
+        doc.apply_format(Range::from_offsets(0, 3), InlineFormat::Bold);
+        doc.set_block_type(Range::from_offsets(15, 19), BlockType::BulletList);
+        doc.set_block_type(Range::from_offsets(20, 32), BlockType::code_block());
+        let (html, map) = doc.to_html_with_source_map();
+        let html: Vec<char> = html.chars().collect();
+        let content: Vec<char> = doc.get_content().chars().collect();
+
+        let mut covered = 0;
+        for mapping in map.mappings() {
+            let model: String = content[mapping.model_start..mapping.model_end]
+                .iter()
+                .collect();
+            let text: String = html[mapping.html_start..mapping.html_end].iter().collect();
+            if model.len() == text.len() {
+                assert_eq!(model, text);
+            } else {
+                assert_eq!(text, escape_html(&model));
+            }
+            covered += mapping.model_end - mapping.model_start;
+        }
+        assert_eq!(covered, content.len());
+    }
+
+    #[test]
+    fn test_html_source_map_positions() {
+        let mut doc = Document::from_text("a <b\n\nxyz");
+        doc.apply_format(Range::from_offsets(7, 9), InlineFormat::Italic);
+        let (html, map) = doc.to_html_with_source_map();
+        assert_eq!(html, "<p>a &lt;b</p>\n<p></p>\n<p>x<em>yz</em></p>\n");
+
+        // The escaped "<" spans four HTML characters
+        assert_eq!(map.html_index(2), 5);
+        assert_eq!(map.html_index(3), 9);
+        assert_eq!(map.model_offset(7), 3);
+        // The empty paragraph is a zero-length mapping inside its element
+        assert_eq!(map.html_index(5), 18);
+        assert_eq!(map.model_offset(18), 5);
+        // Markup maps to the end of the text before it
+        assert_eq!(map.model_offset(12), 4);
+
+        let y = map
+            .mappings()
+            .iter()
+            .find(|mapping| mapping.model_start == 7)
+            .unwrap();
+        assert_eq!(y.node_path, vec![4, 1, 0]);
+        assert_eq!(y.node_offset, 0);
+        assert_eq!(map.html_index(8), 32);
+        assert_eq!(map.model_offset(32), 8);
+    }
+
+    #[test]
+    fn test_html_source_map_image_and_lists() {
+        let mut doc = Document::from_text("ab\nOne\nTwo");
+        doc.insert_image(
+            Position::new(1),
+            Image::new("https://example.com/x.png", ""),
+        )
+        .unwrap();
+        doc.set_block_type(Range::from_offsets(4, 11), BlockType::BulletList);
+        let (html, map) = doc.to_html_with_source_map();
+
+        let image = &map.mappings()[1];
+        assert_eq!((image.model_start, image.model_end), (1, 2));
+        assert!(
+            html.chars()
+                .skip(image.html_start)
+                .collect::<String>()
+                .starts_with("<img")
+        );
+        assert_eq!(image.node_path, vec![0, 1]);
+
+        let two = map
+            .mappings()
+            .iter()
+            .find(|mapping| mapping.model_start == 8)
+            .unwrap();
+        // <ul> is the third node: <p>, "\n", <ul>; its second <li> follows
+        // "\n", <li>One</li>, "\n"
+        assert_eq!(two.node_path, vec![2, 3, 0]);
+    }
+
     #[test]
     fn test_to_plain_text_empty() {
         let doc = Document::new();
@@ -2071,4 +4797,43 @@ mod tests {
             BlockType::Paragraph
         );
     }
+
+    #[test]
+    fn test_from_html_with_limits() {
+        let sanitizer = HtmlSanitizer::new();
+        let deep = format!("{}text", "<div>".repeat(300));
+        let options = HtmlImportOptions::new().limits(ImportLimits::safe());
+        assert!(matches!(
+            Document::from_html_with_options(&deep, &sanitizer, &options),
+            Err(HtmlError::LimitExceeded(LimitExceeded {
+                limit: ImportLimit::Depth,
+                max: 256,
+            }))
+        ));
+        // Without limits the same input imports
+        assert_eq!(Document::from_html(&deep).unwrap().get_content(), "text");
+
+        let html = "<p><b>one</b> two</p><p>three</p>";
+        let doc = Document::from_html_with_options(html, &sanitizer, &options).unwrap();
+        assert_eq!(doc.get_content(), "one two\nthree");
+
+        let options = HtmlImportOptions::new().limits(ImportLimits::new().max_tokens(Some(5)));
+        assert!(matches!(
+            Document::from_html_with_options(html, &sanitizer, &options),
+            Err(HtmlError::LimitExceeded(LimitExceeded {
+                limit: ImportLimit::Tokens,
+                ..
+            }))
+        ));
+
+        let options =
+            HtmlImportOptions::new().limits(ImportLimits::new().max_output_size(Some(12)));
+        assert!(matches!(
+            Document::from_html_with_options(html, &sanitizer, &options),
+            Err(HtmlError::LimitExceeded(LimitExceeded {
+                limit: ImportLimit::OutputSize,
+                max: 12,
+            }))
+        ));
+    }
 }
@@ -0,0 +1,679 @@
+//! HTML parsing for import
+//!
+//! Follows the HTML5 tokenizer and tree construction rules closely enough
+//! for pasted content: comments and doctypes are skipped, quoted attribute
+//! values may contain `>`, a `<` that does not start a tag is text, and the
+//! contents of `<script>` and `<style>` are never read as markup.
+//!
+//! The tree builder closes elements the markup leaves open the way browsers
+//! do: a new paragraph or list item ends the previous one, a cell ends the
+//! cell before it, and formatting cut off by a block boundary is reopened in
+//! the next block. Its output is a balanced stream of tags, so the importer
+//! can read nesting from a simple stack.
+
+use crate::serialization::limits::{ImportLimit, ImportLimits, LimitExceeded};
+use std::collections::HashMap;
+
+/// Attributes of an element, by lowercase name
+pub(crate) type Attributes = HashMap<String, String>;
+
+/// A token of balanced HTML, in document order
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum HtmlToken {
+    OpenTag {
+        name: String,
+        attributes: Attributes,
+    },
+    CloseTag {
+        name: String,
+    },
+    SelfClosing {
+        name: String,
+        attributes: Attributes,
+    },
+    Text {
+        content: String,
+    },
+}
+
+/// Elements without content, which may be written without a trailing slash
+pub(super) const VOID_TAGS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose content is text up to their end tag, never markup
+const RAW_TEXT_TAGS: [&str; 8] = [
+    "script", "style", "textarea", "title", "xmp", "iframe", "noembed", "noframes",
+];
+
+/// Elements whose start tag closes an open paragraph
+const CLOSES_PARAGRAPH: [&str; 35] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dd",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+/// Elements that are reopened when a block boundary cuts them off
+const FORMATTING_TAGS: [&str; 14] = [
+    "a", "b", "big", "code", "em", "font", "i", "nobr", "s", "small", "strike", "strong", "tt", "u",
+];
+
+/// Structural elements that stop the search for an open list item, and
+/// that a formatting element's end tag cannot close over
+const SPECIAL_TAGS: [&str; 40] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "caption",
+    "center",
+    "dd",
+    "details",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "li",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+const HEADING_TAGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Table parts, which close everything open inside them
+const TABLE_TAGS: [&str; 8] = [
+    "caption", "table", "tbody", "td", "tfoot", "th", "thead", "tr",
+];
+
+/// Elements that bound the search for an open table part
+const TABLE_SCOPE: [&str; 2] = ["html", "table"];
+
+/// Elements that bound the search for an open element
+const DEFAULT_SCOPE: [&str; 7] = ["caption", "html", "marquee", "object", "table", "td", "th"];
+
+/// Parses HTML into balanced tokens
+///
+/// Every `OpenTag` has a matching `CloseTag`. `html` and `body` tags are
+/// dropped, since their content is the document itself, and tag and
+/// attribute names are lowercase.
+pub(crate) fn parse(html: &str) -> Vec<HtmlToken> {
+    // Without limits there is nothing to exceed
+    parse_with_limits(html, &ImportLimits::new()).unwrap_or_default()
+}
+
+/// Parses HTML into balanced tokens, stopping once the tokens or the
+/// nesting of open elements go over `limits`
+pub(super) fn parse_with_limits(
+    html: &str,
+    limits: &ImportLimits,
+) -> Result<Vec<HtmlToken>, LimitExceeded> {
+    let mut builder = TreeBuilder::default();
+    for token in tokenize(html, limits)? {
+        match token {
+            RawToken::Start {
+                name,
+                attributes,
+                self_closing,
+            } => builder.start_tag(name, attributes, self_closing),
+            RawToken::End { name } => builder.end_tag(&name),
+            RawToken::Text(content) => builder.text(content),
+        }
+        // Reopened formatting can add tokens and depth the input lacks
+        limits.check(ImportLimit::Tokens, builder.tokens.len())?;
+        limits.check(ImportLimit::Depth, builder.open.len())?;
+    }
+    let tokens = builder.finish();
+    limits.check(ImportLimit::Tokens, tokens.len())?;
+    Ok(tokens)
+}
+
+/// A token as written, before elements are balanced
+#[derive(Debug, PartialEq)]
+enum RawToken {
+    Start {
+        name: String,
+        attributes: Attributes,
+        self_closing: bool,
+    },
+    End {
+        name: String,
+    },
+    Text(String),
+}
+
+/// Splits HTML into tags and text; text is not decoded
+fn tokenize(html: &str, limits: &ImportLimits) -> Result<Vec<RawToken>, LimitExceeded> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut rest = html;
+
+    while let Some(lt) = rest.find('<') {
+        text.push_str(&rest[..lt]);
+        let markup = &rest[lt..];
+        let Some((token, len)) = read_markup(markup) else {
+            text.push('<');
+            rest = &markup[1..];
+            continue;
+        };
+        rest = &markup[len..];
+
+        let Some(token) = token else {
+            continue;
+        };
+        if !text.is_empty() {
+            tokens.push(RawToken::Text(std::mem::take(&mut text)));
+        }
+        limits.check(ImportLimit::Tokens, tokens.len() + 1)?;
+        let raw_text = match &token {
+            RawToken::Start {
+                name,
+                self_closing: false,
+                ..
+            } if RAW_TEXT_TAGS.contains(&name.as_str()) => Some(name.clone()),
+            _ => None,
+        };
+        tokens.push(token);
+
+        if let Some(name) = raw_text {
+            let end = find_end_tag(rest, &name);
+            if end > 0 {
+                tokens.push(RawToken::Text(rest[..end].to_string()));
+            }
+            rest = &rest[end..];
+        }
+    }
+
+    text.push_str(rest);
+    if !text.is_empty() {
+        tokens.push(RawToken::Text(text));
+    }
+    limits.check(ImportLimit::Tokens, tokens.len())?;
+    Ok(tokens)
+}
+
+/// Reads the markup at the start of `markup`, which begins with `<`
+///
+/// # Returns
+/// The token, or None for comments, doctypes and other markup without
+/// content, and the number of bytes read; None if the `<` is text
+fn read_markup(markup: &str) -> Option<(Option<RawToken>, usize)> {
+    let after = &markup[1..];
+    if let Some(comment) = after.strip_prefix("!--") {
+        // `<!-->` and `<!--->` are empty comments
+        let len = if comment.starts_with('>') {
+            1
+        } else if comment.starts_with("->") {
+            2
+        } else {
+            comment.find("-->").map_or(comment.len(), |end| end + 3)
+        };
+        return Some((None, 4 + len));
+    }
+
+    let starts_name = |text: &str| text.starts_with(|c: char| c.is_ascii_alphabetic());
+    if starts_name(after) {
+        return Some(read_tag(markup, false));
+    }
+    match after.strip_prefix('/') {
+        Some(name) if starts_name(name) => Some(read_tag(markup, true)),
+        Some("") => None,
+        Some(_) | None if after.starts_with(['/', '!', '?']) => {
+            // Doctypes, processing instructions, conditional comments and
+            // end tags without a name run to the next `>`
+            Some((None, after.find('>').map_or(markup.len(), |end| end + 2)))
+        }
+        _ => None,
+    }
+}
+
+/// Reads a start or end tag with its attributes
+///
+/// A tag cut off by the end of the input is dropped, as browsers do.
+fn read_tag(markup: &str, end_tag: bool) -> (Option<RawToken>, usize) {
+    let bytes = markup.as_bytes();
+    let is_space = |b: u8| b.is_ascii_whitespace();
+    let mut i = if end_tag { 2 } else { 1 };
+
+    let name_start = i;
+    while i < bytes.len() && !is_space(bytes[i]) && !matches!(bytes[i], b'/' | b'>') {
+        i += 1;
+    }
+    let name = markup[name_start..i].to_ascii_lowercase();
+
+    let mut attributes = Attributes::new();
+    let mut self_closing = false;
+    loop {
+        while i < bytes.len() && (is_space(bytes[i]) || bytes[i] == b'/') {
+            self_closing = bytes[i] == b'/';
+            i += 1;
+        }
+        if i >= bytes.len() {
+            return (None, markup.len());
+        }
+        if bytes[i] == b'>' {
+            i += 1;
+            break;
+        }
+        self_closing = false;
+
+        let attr_start = i;
+        i += 1;
+        while i < bytes.len() && !is_space(bytes[i]) && !matches!(bytes[i], b'/' | b'>' | b'=') {
+            i += 1;
+        }
+        let attr_name = markup[attr_start..i].to_lowercase();
+        while i < bytes.len() && is_space(bytes[i]) {
+            i += 1;
+        }
+
+        let mut value = String::new();
+        if bytes.get(i) == Some(&b'=') {
+            i += 1;
+            while i < bytes.len() && is_space(bytes[i]) {
+                i += 1;
+            }
+            match bytes.get(i) {
+                Some(&quote @ (b'"' | b'\'')) => {
+                    let Some(len) = markup[i + 1..].find(char::from(quote)) else {
+                        return (None, markup.len());
+                    };
+                    value = markup[i + 1..i + 1 + len].to_string();
+                    i += len + 2;
+                }
+                _ => {
+                    let value_start = i;
+                    while i < bytes.len() && !is_space(bytes[i]) && bytes[i] != b'>' {
+                        i += 1;
+                    }
+                    value = markup[value_start..i].to_string();
+                }
+            }
+        }
+        // The first of two attributes with the same name wins
+        attributes.entry(attr_name).or_insert(value);
+    }
+
+    let token = if end_tag {
+        RawToken::End { name }
+    } else {
+        RawToken::Start {
+            name,
+            attributes,
+            self_closing,
+        }
+    };
+    (Some(token), i)
+}
+
+/// Finds the end tag of a raw text element, returning the byte offset
+/// where it starts or the length of `text` if it is never closed
+fn find_end_tag(text: &str, name: &str) -> usize {
+    let mut from = 0;
+    while let Some(found) = text[from..].find("</") {
+        let start = from + found;
+        let after = &text[start + 2..];
+        if after
+            .get(..name.len())
+            .is_some_and(|tag| tag.eq_ignore_ascii_case(name))
+            && after[name.len()..]
+                .starts_with(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+        {
+            return start;
+        }
+        from = start + 2;
+    }
+    text.len()
+}
+
+/// Balances tags with the stack of open elements
+#[derive(Default)]
+struct TreeBuilder {
+    tokens: Vec<HtmlToken>,
+    /// Open elements, innermost last
+    open: Vec<(String, Attributes)>,
+    /// Formatting elements closed by a block boundary rather than their own
+    /// end tag, outermost first; they are reopened before the next content
+    reopen: Vec<(String, Attributes)>,
+}
+
+impl TreeBuilder {
+    fn start_tag(&mut self, name: String, attributes: Attributes, self_closing: bool) {
+        if matches!(name.as_str(), "html" | "body") {
+            return;
+        }
+
+        if CLOSES_PARAGRAPH.contains(&name.as_str()) {
+            self.close_paragraph();
+        }
+        match name.as_str() {
+            // Headings do not nest
+            heading
+                if HEADING_TAGS.contains(&heading)
+                    && self
+                        .current()
+                        .is_some_and(|current| HEADING_TAGS.contains(&current)) =>
+            {
+                self.close_to(self.open.len() - 1);
+            }
+            "li" => self.close_list_item(&["li"]),
+            "dd" | "dt" => self.close_list_item(&["dd", "dt"]),
+            "a" => {
+                // Links do not nest
+                self.reopen.retain(|(open, _)| open != "a");
+                if let Some(index) = self.find_in_scope(&["a"], &DEFAULT_SCOPE) {
+                    self.close_to(index);
+                }
+            }
+            "tr" => self.close_in_table(&["tr"]),
+            "td" | "th" => self.close_in_table(&["td", "th"]),
+            "thead" | "tbody" | "tfoot" => self.close_in_table(&["thead", "tbody", "tfoot"]),
+            _ => {}
+        }
+
+        if !SPECIAL_TAGS.contains(&name.as_str()) {
+            self.reconstruct();
+        }
+        if VOID_TAGS.contains(&name.as_str()) {
+            self.tokens
+                .push(HtmlToken::SelfClosing { name, attributes });
+        } else if self_closing {
+            // Kept as an empty element, so an unknown `<o:p/>` hides nothing
+            self.tokens.push(HtmlToken::OpenTag {
+                name: name.clone(),
+                attributes,
+            });
+            self.tokens.push(HtmlToken::CloseTag { name });
+        } else {
+            self.tokens.push(HtmlToken::OpenTag {
+                name: name.clone(),
+                attributes: attributes.clone(),
+            });
+            self.open.push((name, attributes));
+        }
+    }
+
+    fn end_tag(&mut self, name: &str) {
+        match name {
+            "html" | "body" => {}
+            // Browsers read `</br>` as `<br>`
+            "br" => self.start_tag(name.to_string(), Attributes::new(), false),
+            "p" => self.close_paragraph(),
+            "li" => {
+                let scope = [&DEFAULT_SCOPE[..], &["ol", "ul"]].concat();
+                if let Some(index) = self.find_in_scope(&["li"], &scope) {
+                    self.close_to(index);
+                }
+            }
+            heading if HEADING_TAGS.contains(&heading) => {
+                // Any open heading is closed, whatever its level
+                if let Some(index) = self.find_in_scope(&HEADING_TAGS, &DEFAULT_SCOPE) {
+                    self.close_to(index);
+                }
+            }
+            formatting if FORMATTING_TAGS.contains(&formatting) => self.end_formatting(name),
+            table if TABLE_TAGS.contains(&table) => {
+                if let Some(index) = self.find_in_scope(&[name], &TABLE_SCOPE) {
+                    self.close_to(index);
+                }
+                // Formatting does not leak out of a cell
+                self.reopen.clear();
+            }
+            _ => {
+                // A block closes whatever is open inside it; an inline
+                // element cannot close over a block
+                let scope: &[&str] = if SPECIAL_TAGS.contains(&name) {
+                    &DEFAULT_SCOPE
+                } else {
+                    &SPECIAL_TAGS
+                };
+                if let Some(index) = self.find_in_scope(&[name], scope) {
+                    self.close_to(index);
+                }
+            }
+        }
+    }
+
+    fn text(&mut self, content: String) {
+        if !content.trim().is_empty() {
+            self.reconstruct();
+        }
+        self.tokens.push(HtmlToken::Text { content });
+    }
+
+    /// Closes every open element and returns the tokens
+    fn finish(mut self) -> Vec<HtmlToken> {
+        while let Some((name, _)) = self.open.pop() {
+            self.tokens.push(HtmlToken::CloseTag { name });
+        }
+        self.tokens
+    }
+
+    fn current(&self) -> Option<&str> {
+        self.open.last().map(|(name, _)| name.as_str())
+    }
+
+    /// Finds the innermost open element named in `names`, unless an element
+    /// in `scope` is open inside it
+    fn find_in_scope(&self, names: &[&str], scope: &[&str]) -> Option<usize> {
+        for (index, (name, _)) in self.open.iter().enumerate().rev() {
+            if names.contains(&name.as_str()) {
+                return Some(index);
+            }
+            if scope.contains(&name.as_str()) {
+                return None;
+            }
+        }
+        None
+    }
+
+    /// Closes the element at `index` and everything open inside it,
+    /// remembering formatting elements inside it to reopen later
+    fn close_to(&mut self, index: usize) {
+        let mut cut_off = Vec::new();
+        while self.open.len() > index {
+            let Some((name, attributes)) = self.open.pop() else {
+                break;
+            };
+            self.tokens.push(HtmlToken::CloseTag { name: name.clone() });
+            if self.open.len() > index && FORMATTING_TAGS.contains(&name.as_str()) {
+                cut_off.push((name, attributes));
+            }
+        }
+        self.reopen.extend(cut_off.into_iter().rev());
+    }
+
+    /// Reopens formatting elements cut off by a block boundary
+    fn reconstruct(&mut self) {
+        for (name, attributes) in std::mem::take(&mut self.reopen) {
+            self.tokens.push(HtmlToken::OpenTag {
+                name: name.clone(),
+                attributes: attributes.clone(),
+            });
+            self.open.push((name, attributes));
+        }
+    }
+
+    fn close_paragraph(&mut self) {
+        let scope = [&DEFAULT_SCOPE[..], &["button"]].concat();
+        if let Some(index) = self.find_in_scope(&["p"], &scope) {
+            self.close_to(index);
+        }
+    }
+
+    /// Closes an open item of the same kind before a new list item, unless
+    /// a nested list or other block is open inside it
+    fn close_list_item(&mut self, items: &[&str]) {
+        for (index, (name, _)) in self.open.iter().enumerate().rev() {
+            if items.contains(&name.as_str()) {
+                self.close_to(index);
+                return;
+            }
+            if SPECIAL_TAGS.contains(&name.as_str())
+                && !matches!(name.as_str(), "address" | "div" | "p")
+            {
+                return;
+            }
+        }
+    }
+
+    /// Closes an open table part of one of the given kinds, and everything
+    /// inside it, within the current table
+    fn close_in_table(&mut self, names: &[&str]) {
+        if let Some(index) = self.find_in_scope(names, &TABLE_SCOPE) {
+            self.close_to(index);
+            self.reopen.clear();
+        }
+    }
+
+    /// Handles the end tag of a formatting element, which may close it out
+    /// of order
+    ///
+    /// `<b>1<i>2</b>3</i>` closes both elements and reopens the `<i>`, so
+    /// "3" stays italic but not bold. An end tag cannot close over a block
+    /// opened inside the element and is ignored instead.
+    fn end_formatting(&mut self, name: &str) {
+        let Some(index) = self.find_in_scope(&[name], &DEFAULT_SCOPE) else {
+            if let Some(index) = self.reopen.iter().rposition(|(open, _)| open == name) {
+                self.reopen.remove(index);
+            }
+            return;
+        };
+        if self.open[index + 1..]
+            .iter()
+            .any(|(open, _)| SPECIAL_TAGS.contains(&open.as_str()))
+        {
+            return;
+        }
+        self.close_to(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes tokens back as markup, without attributes
+    fn markup(html: &str) -> String {
+        parse(html)
+            .into_iter()
+            .map(|token| match token {
+                HtmlToken::OpenTag { name, .. } => format!("<{}>", name),
+                HtmlToken::CloseTag { name } => format!("</{}>", name),
+                HtmlToken::SelfClosing { name, .. } => format!("<{}/>", name),
+                HtmlToken::Text { content } => content,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenizer_edge_cases() {
+        assert_eq!(markup("a<!-- x > <p> -->b<!---->c"), "abc");
+        assert_eq!(markup("<!DOCTYPE html><?xml x?>1 < 2 </ >"), "1 < 2 ");
+        assert_eq!(
+            markup("<script>if (a<b) '</p>'</script>x"),
+            "<script>if (a<b) '</p>'</script>x"
+        );
+        assert_eq!(markup("<P>x<BR>y</P>"), "<p>x<br/>y</p>");
+        assert_eq!(markup("<p>cut <b"), "<p>cut </p>");
+
+        let tokens = parse(r#"<a href="x>y" HREF=z title=t class>l</a>"#);
+        let HtmlToken::OpenTag { attributes, .. } = &tokens[0] else {
+            panic!("expected a start tag");
+        };
+        assert_eq!(attributes.get("href").map(String::as_str), Some("x>y"));
+        assert_eq!(attributes.get("title").map(String::as_str), Some("t"));
+        assert_eq!(attributes.get("class").map(String::as_str), Some(""));
+    }
+
+    #[test]
+    fn test_implied_end_tags() {
+        assert_eq!(
+            markup("<html><body><p>one<p>two<div>three</div></body></html>"),
+            "<p>one</p><p>two</p><div>three</div>"
+        );
+        assert_eq!(
+            markup("<ul><li>a<li>b<ul><li>c</ul><li>d</ul>"),
+            "<ul><li>a</li><li>b<ul><li>c</li></ul></li><li>d</li></ul>"
+        );
+        assert_eq!(
+            markup("<h1>Title<h2>Sub</h1>x"),
+            "<h1>Title</h1><h2>Sub</h2>x"
+        );
+        assert_eq!(
+            markup("<table><tr><td>a<td>b<tr><td>c</table>"),
+            "<table><tr><td>a</td><td>b</td></tr><tr><td>c</td></tr></table>"
+        );
+    }
+
+    #[test]
+    fn test_misnested_formatting_is_reopened() {
+        assert_eq!(markup("<b>1<i>2</b>3</i>"), "<b>1<i>2</i></b><i>3</i>");
+        assert_eq!(
+            markup("<p><b>bold<p>still bold</b> plain"),
+            "<p><b>bold</b></p><p><b>still bold</b> plain</p>"
+        );
+        assert_eq!(markup("<a>1<a>2</a>"), "<a>1</a><a>2</a>");
+        assert_eq!(
+            markup("<table><tr><td><b>x</td><td>y</td></tr></table>"),
+            "<table><tr><td><b>x</b></td><td>y</td></tr></table>"
+        );
+    }
+}
@@ -1,8 +1,39 @@
-use crate::document::{Document, Range};
-use crate::formatting::{BlockType, InlineFormat};
+use crate::document::metadata::{self, Metadata};
+use crate::document::{Annotation, Decoration, Document, EMBED_CHAR, Image, Position, Range};
+use crate::formatting::{BlockType, InlineFormat, ListStyle, TextDirection};
+use crate::selection::Selection;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use thiserror::Error;
 
+/// Schema version written for documents that use only the sections of 1.0
+pub const JSON_BASE_VERSION: &str = "1.0";
+
+/// Newest schema version, written for documents with decorations or
+/// extensions
+pub const JSON_VERSION: &str = "1.1";
+
+/// Top-level sections this reader understands
+///
+/// A document may list sections in `required`; reading fails if one of them
+/// is not in this list.
+pub const KNOWN_SECTIONS: &[&str] = &[
+    "version",
+    "text",
+    "content",
+    "formats",
+    "blocks",
+    "images",
+    "annotations",
+    "bookmarks",
+    "no_spellcheck",
+    "decorations",
+    "extensions",
+    "required",
+    "selections",
+    "metadata",
+];
+
 /// Errors that can occur during JSON serialization/deserialization
 #[derive(Debug, Error)]
 pub enum JsonError {
@@ -17,6 +48,27 @@ pub enum JsonError {
 
     #[error("Invalid block data: {0}")]
     InvalidBlock(String),
+
+    #[error("Invalid image data: {0}")]
+    InvalidImage(String),
+
+    #[error("Invalid annotation data: {0}")]
+    InvalidAnnotation(String),
+
+    #[error("Invalid bookmark data: {0}")]
+    InvalidBookmark(String),
+
+    #[error("Invalid selection data: {0}")]
+    InvalidSelection(String),
+
+    #[error("Invalid spellcheck range: {0}")]
+    InvalidNoSpellcheck(String),
+
+    #[error("Invalid decoration data: {0}")]
+    InvalidDecoration(String),
+
+    #[error("Unsupported required section: {0}")]
+    UnsupportedSection(String),
 }
 
 /// Serializable representation of a format run
@@ -28,19 +80,135 @@ pub struct SerializableFormatRun {
 }
 
 /// Serializable representation of a block
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SerializableBlock {
     pub start: usize,
     pub block_type: BlockType,
+    /// List nesting level; omitted for top-level items and non-list blocks
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub indent: u8,
+    /// List marker style; omitted for the default style and non-list blocks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub list_style: Option<ListStyle>,
+    /// Number a numbered list item restarts its list at; omitted when it
+    /// continues the count and for other blocks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub list_start: Option<usize>,
+    /// Paragraph indent in steps; omitted when 0
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub block_indent: u8,
+    /// Whether a paragraph's first line is indented; omitted when false
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub first_line_indent: bool,
+    /// Base text direction; omitted when detected from the text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<TextDirection>,
 }
 
-/// Serializable representation of document metadata
+fn is_zero(indent: &u8) -> bool {
+    *indent == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Serializable representation of an inline image and its text offset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerializableImage {
+    pub offset: usize,
+    pub image: Image,
+}
+
+/// Serializable representation of an annotation and the range it covers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerializableAnnotation {
+    pub start: usize,
+    pub end: usize,
+    pub annotation: Annotation,
+}
+
+/// Serializable representation of a decoration and the range it covers
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerializableDecoration {
+    pub start: usize,
+    pub end: usize,
+    pub decoration: Decoration,
+}
+
+/// Serializable representation of a range of text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Serializable representation of a selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableSelection {
+    pub anchor: usize,
+    pub focus: usize,
+}
+
+/// Serializable representation of document metadata
+///
+/// The well-known keys have fields of their own; any other keys go in
+/// `custom`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DocumentMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub modified: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom: BTreeMap<String, String>,
+}
+
+impl DocumentMetadata {
+    /// Collects document metadata for serialization, or None if there is none
+    fn from_metadata(metadata: &Metadata) -> Option<Self> {
+        if metadata.is_empty() {
+            return None;
+        }
+        let mut result = Self::default();
+        for (key, value) in metadata.iter() {
+            let value = value.to_string();
+            match key {
+                metadata::TITLE => result.title = Some(value),
+                metadata::AUTHOR => result.author = Some(value),
+                metadata::CREATED => result.created = Some(value),
+                metadata::MODIFIED => result.modified = Some(value),
+                _ => {
+                    result.custom.insert(key.to_string(), value);
+                }
+            }
+        }
+        Some(result)
+    }
+
+    /// Converts back to document metadata, well-known keys first
+    fn into_metadata(self) -> Metadata {
+        let mut result = Metadata::new();
+        let known = [
+            (metadata::TITLE, self.title),
+            (metadata::AUTHOR, self.author),
+            (metadata::CREATED, self.created),
+            (metadata::MODIFIED, self.modified),
+        ];
+        for (key, value) in known {
+            if let Some(value) = value {
+                result.set(key, value);
+            }
+        }
+        for (key, value) in self.custom {
+            result.set(key, value);
+        }
+        result
+    }
 }
 
 /// Serializable representation of a complete document
@@ -67,9 +235,37 @@ pub struct DocumentMetadata {
 ///       "block_type": "Paragraph"
 ///     }
 ///   ],
+///   "images": [
+///     {
+///       "offset": 6,
+///       "image": {"src": "https://example.com/a.png", "alt": "A", "width": 64}
+///     }
+///   ],
+///   "annotations": [
+///     {
+///       "start": 0,
+///       "end": 8,
+///       "annotation": {"id": "c1", "author": "ann", "payload": "{\"text\":\"Typo\"}"}
+///     }
+///   ],
+///   "bookmarks": {"details": 9},
+///   "no_spellcheck": [{"start": 9, "end": 16}],
+///   "decorations": [
+///     {
+///       "start": 0,
+///       "end": 8,
+///       "decoration": {"kind": "lint", "attributes": {"message": "Typo"}}
+///     }
+///   ],
+///   "extensions": {"com.example.outline": {"collapsed": [2, 5]}},
+///   "required": [],
+///   "selections": [{"anchor": 9, "focus": 16}],
 ///   "metadata": {
+///     "title": "Notes",
+///     "author": "Ada",
 ///     "created": "2024-01-01T00:00:00Z",
-///     "modified": "2024-01-01T00:00:00Z"
+///     "modified": "2024-01-01T00:00:00Z",
+///     "custom": {"status": "draft"}
 ///   }
 /// }
 /// ```
@@ -77,11 +273,22 @@ pub struct DocumentMetadata {
 /// ## Version History
 ///
 /// - **1.0**: Initial format with text/content, formats, blocks, and optional metadata
+/// - **1.1**: Adds `decorations`, `extensions` and `required`. Documents
+///   without decorations or extensions are still written as 1.0.
+///
+/// ## Compatibility
+///
+/// Minor versions only add optional sections, so a reader accepts any 1.x
+/// document and ignores the sections it does not understand. A writer whose
+/// document cannot be read correctly without some section lists it in
+/// `required`; a reader that does not understand a required section
+/// rejects the document instead of silently dropping it. A new major
+/// version is rejected.
 ///
 /// ## Format Types
 ///
 /// Inline formats can be:
-/// - Simple: `"Bold"`, `"Italic"`, `"Underline"`, `"Strikethrough"`, `"Code"`
+/// - Simple: `"Bold"`, `"Italic"`, `"Underline"`, `"Strikethrough"`, `"Code"`, `"Highlight"`, `"SmallCaps"`, `"Subscript"`, `"Superscript"`
 /// - Complex: `{"Link": {"url": "..."}}`, `{"TextColor": {"color": "..."}}`, `{"BackgroundColor": {"color": "..."}}`
 ///
 /// Block types can be:
@@ -89,8 +296,47 @@ pub struct DocumentMetadata {
 /// - `{"Heading": {"level": 1-6}}`
 /// - `"BulletList"`
 /// - `"NumberedList"`
-/// - `"BlockQuote"`
-/// - `"CodeBlock"`
+/// - `{"BlockQuote": {"cite": "...", "depth": 1}}` (`cite` is optional; the
+///   bare `"BlockQuote"` string from older documents is still accepted)
+/// - `"CodeBlock"` or `{"CodeBlock": {"language": "rust"}}`
+///
+/// List blocks may carry an `indent` nesting level, where 0 (the default
+/// when omitted) is a top-level item, and a `list_style` of `"Decimal"`,
+/// `"LowerAlpha"`, `"UpperAlpha"`, `"LowerRoman"`, `"UpperRoman"` or
+/// `{"Bullet": "–"}` (the default marker when omitted). A numbered list
+/// item may carry a `list_start` number its list restarts at. Paragraphs may
+/// carry a `block_indent` in indent steps and a `first_line_indent` flag,
+/// both off when omitted. Any block may carry a `direction` of `"Ltr"` or
+/// `"Rtl"`; when omitted it is detected from the text.
+///
+/// `images` is optional. Each entry points at an embed character
+/// (U+FFFC) in `text`; `width` and `height` may be omitted.
+///
+/// `annotations` is optional. Each entry covers a non-empty range of `text`;
+/// `author` and `payload` may be omitted.
+///
+/// `bookmarks` is optional and maps bookmark ids to offsets in `text`.
+///
+/// `no_spellcheck` is optional and lists the ranges of `text` that
+/// spellcheckers should skip.
+///
+/// `decorations` is optional. Each entry covers a non-empty range of `text`
+/// with an application-defined `kind`; `attributes` may be omitted.
+///
+/// `extensions` is optional and maps names chosen by applications to any
+/// JSON value. Readers keep the values as they are, so features built on
+/// top of the editor can store their data in the document. Names should be
+/// namespaced, such as `com.example.outline`.
+///
+/// `required` is optional and lists the top-level sections a reader must
+/// understand to read the document correctly.
+///
+/// `metadata` is optional, and so is each of its fields. Keys other than
+/// `title`, `author`, `created` and `modified` are kept in `custom`.
+///
+/// `selections` is optional and only written when requested through
+/// `JsonOptions`. The first entry is the primary selection and the rest are
+/// secondary selections.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SerializableDocument {
     pub version: String,
@@ -98,6 +344,22 @@ pub struct SerializableDocument {
     pub text: String,
     pub formats: Vec<SerializableFormatRun>,
     pub blocks: Vec<SerializableBlock>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<SerializableImage>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<SerializableAnnotation>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub bookmarks: BTreeMap<String, usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub no_spellcheck: Vec<SerializableRange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub decorations: Vec<SerializableDecoration>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extensions: BTreeMap<String, serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub selections: Vec<SerializableSelection>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<DocumentMetadata>,
 }
@@ -113,17 +375,25 @@ pub struct SerializableDocument {
 ///
 /// # Returns
 ///
-/// A SerializableDocument upgraded to version 1.0
+/// A SerializableDocument readable as the current version
 ///
 /// # Errors
 ///
 /// Returns an error if the version is not supported or migration fails
 fn migrate_document_version(doc: SerializableDocument) -> Result<SerializableDocument, JsonError> {
     match doc.version.as_str() {
-        "1.0" => {
+        "1.0" | "1.1" => {
             // Already current version, no migration needed
             Ok(doc)
         }
+        // Newer minor versions only add optional sections
+        version
+            if version
+                .strip_prefix("1.")
+                .is_some_and(|minor| minor.parse::<u32>().is_ok()) =>
+        {
+            Ok(doc)
+        }
         // Future versions would be handled here
         // "0.9" => migrate_from_0_9_to_1_0(doc),
         // "0.8" => migrate_from_0_8_to_1_0(doc),
@@ -138,6 +408,36 @@ fn migrate_document_version(doc: SerializableDocument) -> Result<SerializableDoc
 //     Ok(doc)
 // }
 
+/// What `Document::to_json_with_options` writes
+///
+/// The default writes compact JSON without the selection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JsonOptions {
+    /// Whether the primary and secondary selections are recorded
+    pub include_selection: bool,
+    /// Whether the output is indented
+    pub pretty: bool,
+}
+
+impl JsonOptions {
+    /// Creates the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether the selections are recorded
+    pub fn include_selection(mut self, include: bool) -> Self {
+        self.include_selection = include;
+        self
+    }
+
+    /// Sets whether the output is indented
+    pub fn pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+}
+
 impl Document {
     /// Serializes the document to JSON format
     ///
@@ -150,42 +450,7 @@ impl Document {
     ///
     /// Returns an error if JSON serialization fails.
     pub fn to_json(&self) -> Result<String, JsonError> {
-        // Convert format runs to serializable format
-        let formats: Vec<SerializableFormatRun> = self
-            .formats()
-            .get_runs()
-            .iter()
-            .map(|run| {
-                let normalized = run.range.normalize();
-                SerializableFormatRun {
-                    start: normalized.start_offset(),
-                    end: normalized.end_offset(),
-                    formats: run.formats.iter().cloned().collect(),
-                }
-            })
-            .collect();
-
-        // Convert blocks to serializable format
-        let blocks: Vec<SerializableBlock> = self
-            .formats()
-            .get_blocks()
-            .iter()
-            .map(|block| SerializableBlock {
-                start: block.start_offset,
-                block_type: block.block_type.clone(),
-            })
-            .collect();
-
-        // Create serializable document
-        let serializable = SerializableDocument {
-            version: "1.0".to_string(),
-            text: self.get_content(),
-            formats,
-            blocks,
-            metadata: None, // Can be extended in the future
-        };
-
-        Ok(serde_json::to_string(&serializable)?)
+        self.to_json_with_options(&JsonOptions::new())
     }
 
     /// Serializes the document to pretty-printed JSON format
@@ -198,6 +463,44 @@ impl Document {
     ///
     /// Returns an error if JSON serialization fails.
     pub fn to_json_pretty(&self) -> Result<String, JsonError> {
+        self.to_json_with_options(&JsonOptions::new().pretty(true))
+    }
+
+    /// Serializes the document to JSON with the given options
+    ///
+    /// With `include_selection`, the snapshot also records every selection so
+    /// `from_json` can put the user back where they were. Bookmarks are
+    /// always included; an application can keep its scroll position in one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if JSON serialization fails.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    /// use rte_core::selection::Selection;
+    /// use rte_core::serialization::json::JsonOptions;
+    ///
+    /// let mut doc = Document::from_text("Hello World");
+    /// doc.set_selection(Selection::new(Position::new(6), Position::new(11)));
+    ///
+    /// let json = doc.to_json_with_options(&JsonOptions::new().include_selection(true)).unwrap();
+    /// let restored = Document::from_json(&json).unwrap();
+    /// assert_eq!(restored.get_selection(), doc.get_selection());
+    /// ```
+    pub fn to_json_with_options(&self, options: &JsonOptions) -> Result<String, JsonError> {
+        let serializable = self.to_serializable(options.include_selection);
+        if options.pretty {
+            Ok(serde_json::to_string_pretty(&serializable)?)
+        } else {
+            Ok(serde_json::to_string(&serializable)?)
+        }
+    }
+
+    /// Collects everything the JSON and binary formats store
+    pub(crate) fn to_serializable(&self, include_selection: bool) -> SerializableDocument {
         // Convert format runs to serializable format
         let formats: Vec<SerializableFormatRun> = self
             .formats()
@@ -221,19 +524,60 @@ impl Document {
             .map(|block| SerializableBlock {
                 start: block.start_offset,
                 block_type: block.block_type.clone(),
+                indent: block.indent,
+                list_style: block.list_style,
+                list_start: block.list_start,
+                block_indent: block.block_indent,
+                first_line_indent: block.first_line_indent,
+                direction: block.direction,
             })
             .collect();
 
-        // Create serializable document
-        let serializable = SerializableDocument {
-            version: "1.0".to_string(),
+        let selections = if include_selection {
+            self.serializable_selections()
+        } else {
+            Vec::new()
+        };
+
+        let decorations: Vec<SerializableDecoration> = self
+            .decorations()
+            .decorations()
+            .iter()
+            .map(|(range, decoration)| SerializableDecoration {
+                start: range.start_offset(),
+                end: range.end_offset(),
+                decoration: decoration.clone(),
+            })
+            .collect();
+        // Documents that need nothing from 1.1 stay readable by 1.0 readers
+        let version = if decorations.is_empty() && self.extensions().is_empty() {
+            JSON_BASE_VERSION
+        } else {
+            JSON_VERSION
+        };
+
+        SerializableDocument {
+            version: version.to_string(),
             text: self.get_content(),
             formats,
             blocks,
-            metadata: None,
-        };
-
-        Ok(serde_json::to_string_pretty(&serializable)?)
+            images: self.serializable_images(),
+            annotations: self.serializable_annotations(),
+            bookmarks: self.serializable_bookmarks(),
+            no_spellcheck: self
+                .no_spellcheck_ranges()
+                .iter()
+                .map(|range| SerializableRange {
+                    start: range.start_offset(),
+                    end: range.end_offset(),
+                })
+                .collect(),
+            decorations,
+            extensions: self.extensions().clone(),
+            required: Vec::new(),
+            selections,
+            metadata: DocumentMetadata::from_metadata(self.metadata()),
+        }
     }
 
     /// Deserializes a document from JSON format with version migration support
@@ -272,14 +616,25 @@ impl Document {
     /// ```
     pub fn from_json(json: &str) -> Result<Self, JsonError> {
         // Parse JSON into serializable structure
-        let mut serializable: SerializableDocument = serde_json::from_str(json)?;
+        let serializable: SerializableDocument = serde_json::from_str(json)?;
+        Self::from_serializable(serializable)
+    }
 
+    /// Builds a document from what the JSON and binary formats store,
+    /// validating every offset against the text
+    pub(crate) fn from_serializable(
+        mut serializable: SerializableDocument,
+    ) -> Result<Self, JsonError> {
         // Perform version migration if needed
         serializable = migrate_document_version(serializable)?;
 
-        // Validate schema version after migration
-        if serializable.version != "1.0" {
-            return Err(JsonError::UnsupportedVersion(serializable.version));
+        // Refuse documents that need a section this reader would drop
+        if let Some(section) = serializable
+            .required
+            .iter()
+            .find(|section| !KNOWN_SECTIONS.contains(&section.as_str()))
+        {
+            return Err(JsonError::UnsupportedSection(section.clone()));
         }
 
         // Create document with content (support both text and content fields)
@@ -322,10 +677,137 @@ impl Document {
                 )));
             }
 
-            // Find the end of this block (start of next block or document end)
-            let block_end = doc.get_length();
-            let range = Range::from_offsets(block.start, block_end);
-            doc.set_block_type(range, block.block_type);
+            // Leave the end open: the block runs until the next one, which
+            // is restored after it. Ending it at the document length would
+            // restore the previous block type there as an extra block.
+            let range = Range::from_offsets(block.start, usize::MAX);
+            doc.formats_mut().set_block_type(range, block.block_type);
+            doc.formats_mut()
+                .set_indent(block.start, None, block.indent);
+            doc.formats_mut()
+                .set_list_style(block.start, None, block.list_style);
+            doc.formats_mut()
+                .set_list_start(block.start, None, block.list_start);
+            doc.formats_mut()
+                .set_block_indent(block.start, None, block.block_indent);
+            doc.formats_mut()
+                .set_first_line_indent(block.start, None, block.first_line_indent);
+            doc.formats_mut()
+                .set_direction(block.start, None, block.direction);
+        }
+
+        // Restore images
+        for entry in serializable.images {
+            if entry.offset >= doc.get_length() {
+                return Err(JsonError::InvalidImage(format!(
+                    "Image offset {} exceeds document length {}",
+                    entry.offset,
+                    doc.get_length()
+                )));
+            }
+            if doc.get_text_in_range(Range::from_offsets(entry.offset, entry.offset + 1))
+                != EMBED_CHAR.to_string()
+            {
+                return Err(JsonError::InvalidImage(format!(
+                    "No embed character at offset {}",
+                    entry.offset
+                )));
+            }
+            if !entry.image.has_safe_src() {
+                return Err(JsonError::InvalidImage(format!(
+                    "Unsafe image source '{}'",
+                    entry.image.src
+                )));
+            }
+            doc.embeds_mut().insert(entry.offset, entry.image);
+        }
+
+        // Restore annotations
+        for entry in serializable.annotations {
+            if entry.start >= entry.end || entry.end > doc.get_length() {
+                return Err(JsonError::InvalidAnnotation(format!(
+                    "Invalid range {}..{} for annotation '{}' (document length: {})",
+                    entry.start,
+                    entry.end,
+                    entry.annotation.id,
+                    doc.get_length()
+                )));
+            }
+            let range = Range::from_offsets(entry.start, entry.end);
+            if let Some((_, duplicate)) = doc.annotations_mut().insert(range, entry.annotation) {
+                return Err(JsonError::InvalidAnnotation(format!(
+                    "Duplicate annotation id '{}'",
+                    duplicate.id
+                )));
+            }
+        }
+
+        // Restore bookmarks
+        for (id, offset) in serializable.bookmarks {
+            doc.set_bookmark(&id, Position::new(offset)).map_err(|_| {
+                JsonError::InvalidBookmark(format!(
+                    "Offset {} for bookmark '{}' exceeds document length {}",
+                    offset,
+                    id,
+                    doc.get_length()
+                ))
+            })?;
+        }
+
+        // Restore ranges spellcheckers should skip
+        for entry in serializable.no_spellcheck {
+            doc.mark_no_spellcheck(Range::from_offsets(entry.start, entry.end))
+                .map_err(|_| {
+                    JsonError::InvalidNoSpellcheck(format!(
+                        "Range {}..{} exceeds document length {}",
+                        entry.start,
+                        entry.end,
+                        doc.get_length()
+                    ))
+                })?;
+        }
+
+        // Restore decorations
+        for entry in serializable.decorations {
+            if entry.start >= entry.end || entry.end > doc.get_length() {
+                return Err(JsonError::InvalidDecoration(format!(
+                    "Invalid range {}..{} for decoration '{}' (document length: {})",
+                    entry.start,
+                    entry.end,
+                    entry.decoration.kind,
+                    doc.get_length()
+                )));
+            }
+            doc.decorations_mut().add(
+                Range::from_offsets(entry.start, entry.end),
+                entry.decoration,
+            );
+        }
+        *doc.extensions_mut() = serializable.extensions;
+
+        // Restore selections, primary first
+        let length = doc.get_length();
+        let mut selections = serializable.selections.into_iter().map(|entry| {
+            if entry.anchor > length || entry.focus > length {
+                return Err(JsonError::InvalidSelection(format!(
+                    "Selection {}..{} exceeds document length {}",
+                    entry.anchor, entry.focus, length
+                )));
+            }
+            Ok(Selection::new(
+                Position::new(entry.anchor),
+                Position::new(entry.focus),
+            ))
+        });
+        if let Some(primary) = selections.next() {
+            doc.set_selection(primary?);
+        }
+        for secondary in selections {
+            doc.add_selection(secondary?);
+        }
+
+        if let Some(metadata) = serializable.metadata {
+            *doc.metadata_mut() = metadata.into_metadata();
         }
 
         // Clear undo/redo history since this is a freshly loaded document
@@ -333,6 +815,93 @@ impl Document {
 
         Ok(doc)
     }
+
+    /// Stores application data under `name` in the JSON `extensions`
+    /// section, replacing any data stored under that name
+    ///
+    /// The data is kept as given through saving and loading, so features
+    /// built on top of the editor need no file of their own. It is not part
+    /// of the undo history.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `data` is not valid JSON.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rte_core::document::Document;
+    ///
+    /// let mut doc = Document::from_text("Outline");
+    /// doc.set_json_extension("com.example.outline", r#"{"collapsed":[2]}"#)
+    ///     .unwrap();
+    ///
+    /// let restored = Document::from_json(&doc.to_json().unwrap()).unwrap();
+    /// assert_eq!(
+    ///     restored.get_json_extension("com.example.outline").as_deref(),
+    ///     Some(r#"{"collapsed":[2]}"#)
+    /// );
+    /// ```
+    pub fn set_json_extension(&mut self, name: &str, data: &str) -> Result<(), JsonError> {
+        let value: serde_json::Value = serde_json::from_str(data)?;
+        self.extensions_mut().insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Gets the application data stored under `name` as compact JSON
+    pub fn get_json_extension(&self, name: &str) -> Option<String> {
+        self.extensions().get(name).map(|value| value.to_string())
+    }
+
+    /// Removes the application data stored under `name`
+    /// Returns true if there was any
+    pub fn remove_json_extension(&mut self, name: &str) -> bool {
+        self.extensions_mut().remove(name).is_some()
+    }
+
+    /// Collects the document's annotations for serialization
+    fn serializable_annotations(&self) -> Vec<SerializableAnnotation> {
+        self.annotations()
+            .annotations()
+            .iter()
+            .map(|(range, annotation)| SerializableAnnotation {
+                start: range.start_offset(),
+                end: range.end_offset(),
+                annotation: annotation.clone(),
+            })
+            .collect()
+    }
+
+    /// Collects the document's bookmarks for serialization
+    fn serializable_bookmarks(&self) -> BTreeMap<String, usize> {
+        self.bookmarks()
+            .iter()
+            .map(|(id, pos)| (id.to_string(), pos.offset()))
+            .collect()
+    }
+
+    /// Collects the document's selections for serialization
+    fn serializable_selections(&self) -> Vec<SerializableSelection> {
+        self.get_selections()
+            .into_iter()
+            .map(|selection| SerializableSelection {
+                anchor: selection.anchor.offset(),
+                focus: selection.focus.offset(),
+            })
+            .collect()
+    }
+
+    /// Collects the document's images for serialization
+    fn serializable_images(&self) -> Vec<SerializableImage> {
+        self.embeds()
+            .images()
+            .iter()
+            .map(|(offset, image)| SerializableImage {
+                offset: *offset,
+                image: image.clone(),
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -629,6 +1198,178 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_roundtrip_block_quote_attributes() {
+        let mut doc = Document::from_text("Quote");
+        doc.set_block_type(
+            Range::from_offsets(0, 5),
+            BlockType::block_quote_with(Some("https://example.com".to_string()), 2),
+        );
+
+        let restored = Document::from_json(&doc.to_json().unwrap()).unwrap();
+        assert_eq!(
+            restored.get_block_type_at(Position::new(0)),
+            BlockType::block_quote_with(Some("https://example.com".to_string()), 2)
+        );
+    }
+
+    #[test]
+    fn test_from_json_legacy_block_quote() {
+        let json = r#"{"version":"1.0","text":"Quote","formats":[],"blocks":[{"start":0,"block_type":"BlockQuote"}]}"#;
+        let doc = Document::from_json(json).unwrap();
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::block_quote()
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_highlight_and_small_caps() {
+        let mut doc = Document::from_text("Hello World");
+        doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Highlight);
+        doc.apply_format(Range::from_offsets(6, 11), InlineFormat::SmallCaps);
+
+        let json = doc.to_json().unwrap();
+        assert!(json.contains("\"Highlight\""));
+        assert!(json.contains("\"SmallCaps\""));
+
+        let restored = Document::from_json(&json).unwrap();
+        assert!(
+            restored
+                .get_formats_at(Position::new(2))
+                .contains(&InlineFormat::Highlight)
+        );
+        assert!(
+            restored
+                .get_formats_at(Position::new(8))
+                .contains(&InlineFormat::SmallCaps)
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_image() {
+        let mut doc = Document::from_text("ab");
+        let image = Image::new("https://example.com/a.png", "A").with_size(Some(64), Some(32));
+        doc.insert_image(Position::new(1), image.clone()).unwrap();
+
+        let json = doc.to_json().unwrap();
+        let restored = Document::from_json(&json).unwrap();
+        assert_eq!(restored.get_content(), doc.get_content());
+        assert_eq!(restored.get_image_at(Position::new(1)), Some(&image));
+    }
+
+    #[test]
+    fn test_from_json_rejects_invalid_image() {
+        let json = r#"{"version":"1.0","text":"ab","formats":[],"blocks":[],"images":[{"offset":1,"image":{"src":"a.png"}}]}"#;
+        assert!(matches!(
+            Document::from_json(json),
+            Err(JsonError::InvalidImage(_))
+        ));
+
+        let json = r#"{"version":"1.0","text":"a\uFFFC","formats":[],"blocks":[],"images":[{"offset":1,"image":{"src":"javascript:alert(1)"}}]}"#;
+        assert!(matches!(
+            Document::from_json(json),
+            Err(JsonError::InvalidImage(_))
+        ));
+
+        let json = r#"{"version":"1.0","text":"a\uFFFC","formats":[],"blocks":[],"images":[{"offset":999,"image":{"src":"a.png"}}]}"#;
+        assert!(matches!(
+            Document::from_json(json),
+            Err(JsonError::InvalidImage(_))
+        ));
+    }
+
+    #[test]
+    fn test_roundtrip_annotations() {
+        let mut doc = Document::from_text("Hello World");
+        let comment = Annotation::new("c1")
+            .with_author("ann")
+            .with_payload(r#"{"text":"Typo"}"#);
+        doc.add_annotation(Range::from_offsets(0, 5), comment.clone())
+            .unwrap();
+        doc.add_annotation(Range::from_offsets(3, 8), Annotation::new("c2"))
+            .unwrap();
+
+        let json = doc.to_json().unwrap();
+        let restored = Document::from_json(&json).unwrap();
+        assert_eq!(
+            restored.get_annotation("c1"),
+            Some((Range::from_offsets(0, 5), &comment))
+        );
+        assert_eq!(restored.get_annotations_at(Position::new(4)).len(), 2);
+
+        // Documents without annotations omit the field
+        assert!(
+            !Document::from_text("a")
+                .to_json()
+                .unwrap()
+                .contains("annotations")
+        );
+    }
+
+    #[test]
+    fn test_from_json_rejects_invalid_annotation() {
+        let json = r#"{"version":"1.0","text":"ab","formats":[],"blocks":[],"annotations":[{"start":1,"end":5,"annotation":{"id":"c1"}}]}"#;
+        assert!(matches!(
+            Document::from_json(json),
+            Err(JsonError::InvalidAnnotation(_))
+        ));
+
+        let json = r#"{"version":"1.0","text":"ab","formats":[],"blocks":[],"annotations":[{"start":0,"end":1,"annotation":{"id":"c1"}},{"start":1,"end":2,"annotation":{"id":"c1"}}]}"#;
+        assert!(matches!(
+            Document::from_json(json),
+            Err(JsonError::InvalidAnnotation(_))
+        ));
+    }
+
+    #[test]
+    fn test_roundtrip_bookmarks() {
+        let mut doc = Document::from_text("Intro\nDetails");
+        doc.set_bookmark("details", Position::new(6)).unwrap();
+        doc.set_bookmark("cursor", Position::new(13)).unwrap();
+
+        let json = doc.to_json().unwrap();
+        assert!(json.contains(r#""bookmarks":{"cursor":13,"details":6}"#));
+        let restored = Document::from_json(&json).unwrap();
+        assert_eq!(restored.get_bookmark("details"), Some(Position::new(6)));
+        assert_eq!(restored.get_bookmark("cursor"), Some(Position::new(13)));
+
+        assert!(
+            !Document::from_text("x")
+                .to_json()
+                .unwrap()
+                .contains("bookmarks")
+        );
+        let invalid =
+            r#"{"version":"1.0","text":"ab","formats":[],"blocks":[],"bookmarks":{"end":3}}"#;
+        assert!(matches!(
+            Document::from_json(invalid),
+            Err(JsonError::InvalidBookmark(_))
+        ));
+    }
+
+    #[test]
+    fn test_roundtrip_selections() {
+        let mut doc = Document::from_text("one two one");
+        doc.set_selection(Selection::new(Position::new(3), Position::new(0)));
+        doc.add_selection(Selection::new(Position::new(8), Position::new(11)));
+
+        assert!(!doc.to_json().unwrap().contains("selections"));
+        let json = doc
+            .to_json_with_options(&JsonOptions::new().include_selection(true))
+            .unwrap();
+        assert!(json.contains(r#""selections":[{"anchor":3,"focus":0},{"anchor":8,"focus":11}]"#));
+        let restored = Document::from_json(&json).unwrap();
+        assert_eq!(restored.get_selections(), doc.get_selections());
+        assert!(!restored.can_undo());
+
+        let invalid = r#"{"version":"1.0","text":"ab","formats":[],"blocks":[],"selections":[{"anchor":0,"focus":3}]}"#;
+        assert!(matches!(
+            Document::from_json(invalid),
+            Err(JsonError::InvalidSelection(_))
+        ));
+    }
+
     #[test]
     fn test_from_json_with_link() {
         let json = r#"{
@@ -871,6 +1612,14 @@ mod tests {
             text: "Test".to_string(),
             formats: vec![],
             blocks: vec![],
+            images: vec![],
+            annotations: vec![],
+            bookmarks: BTreeMap::new(),
+            no_spellcheck: vec![],
+            decorations: vec![],
+            extensions: BTreeMap::new(),
+            required: vec![],
+            selections: vec![],
             metadata: None,
         };
 
@@ -886,6 +1635,14 @@ mod tests {
             text: "Test".to_string(),
             formats: vec![],
             blocks: vec![],
+            images: vec![],
+            annotations: vec![],
+            bookmarks: BTreeMap::new(),
+            no_spellcheck: vec![],
+            decorations: vec![],
+            extensions: BTreeMap::new(),
+            required: vec![],
+            selections: vec![],
             metadata: None,
         };
 
@@ -896,4 +1653,172 @@ mod tests {
             _ => panic!("Expected UnsupportedVersion error"),
         }
     }
+
+    #[test]
+    fn test_roundtrip_preserves_list_indent() {
+        let mut doc = Document::from_text("One\nTwo");
+        doc.set_block_type(Range::from_offsets(0, 7), BlockType::NumberedList);
+        doc.indent_list_items(Range::from_offsets(4, 7)).unwrap();
+
+        let json = doc.to_json().unwrap();
+        assert!(json.contains(r#""indent":1"#));
+        let restored = Document::from_json(&json).unwrap();
+        assert_eq!(restored.get_list_indent_at(Position::new(0)), 0);
+        assert_eq!(restored.get_list_indent_at(Position::new(4)), 1);
+    }
+
+    #[test]
+    fn test_roundtrip_preserves_list_style() {
+        let mut doc = Document::from_text("One\nTwo\nThree");
+        doc.set_block_type(Range::from_offsets(0, 13), BlockType::NumberedList);
+        doc.set_list_style(Range::from_offsets(4, 7), Some(ListStyle::UpperRoman))
+            .unwrap();
+
+        let json = doc.to_json().unwrap();
+        assert!(json.contains(r#""list_style":"UpperRoman""#));
+        let restored = Document::from_json(&json).unwrap();
+        assert_eq!(restored.get_list_style_at(Position::new(0)), None);
+        assert_eq!(
+            restored.get_list_style_at(Position::new(4)),
+            Some(ListStyle::UpperRoman)
+        );
+        assert_eq!(restored.get_list_style_at(Position::new(8)), None);
+
+        let bullet = r#"{"version":"1.0","text":"A","formats":[],"blocks":[{"start":0,"block_type":"BulletList","list_style":{"Bullet":"–"}}]}"#;
+        let restored = Document::from_json(bullet).unwrap();
+        assert_eq!(
+            restored.get_list_marker_at(Position::new(0)).as_deref(),
+            Some("–")
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_preserves_paragraph_indent() {
+        let mut doc = Document::from_text("One\nTwo");
+        doc.increase_block_indent(Range::from_offsets(4, 4))
+            .unwrap();
+        doc.set_first_line_indent(Range::from_offsets(0, 0), true)
+            .unwrap();
+
+        let json = doc.to_json().unwrap();
+        assert!(json.contains(r#""block_indent":1"#));
+        assert!(json.contains(r#""first_line_indent":true"#));
+        let restored = Document::from_json(&json).unwrap();
+        assert!(restored.has_first_line_indent_at(Position::new(0)));
+        assert_eq!(restored.get_block_indent_at(Position::new(0)), 0);
+        assert_eq!(restored.get_block_indent_at(Position::new(4)), 1);
+        assert!(!restored.has_first_line_indent_at(Position::new(4)));
+    }
+
+    #[test]
+    fn test_roundtrip_preserves_text_direction() {
+        let mut doc = Document::from_text("One\nTwo\nThree");
+        doc.set_text_direction(Range::from_offsets(4, 4), Some(TextDirection::Rtl))
+            .unwrap();
+
+        let json = doc.to_json().unwrap();
+        assert!(json.contains(r#""direction":"Rtl""#));
+        let restored = Document::from_json(&json).unwrap();
+        assert_eq!(restored.get_text_direction_at(Position::new(0)), None);
+        assert_eq!(
+            restored.get_text_direction_at(Position::new(4)),
+            Some(TextDirection::Rtl)
+        );
+        assert_eq!(restored.get_text_direction_at(Position::new(8)), None);
+    }
+
+    #[test]
+    fn test_roundtrip_preserves_no_spellcheck() {
+        let mut doc = Document::from_text("Call Siobhan at noon");
+        doc.mark_no_spellcheck(Range::from_offsets(5, 12)).unwrap();
+
+        let json = doc.to_json().unwrap();
+        assert!(json.contains(r#""no_spellcheck":[{"start":5,"end":12}]"#));
+        let restored = Document::from_json(&json).unwrap();
+        assert_eq!(
+            restored.no_spellcheck_ranges(),
+            [Range::from_offsets(5, 12)]
+        );
+
+        let json = r#"{"version":"1.0","text":"ab","formats":[],"blocks":[],"no_spellcheck":[{"start":1,"end":5}]}"#;
+        assert!(matches!(
+            Document::from_json(json),
+            Err(JsonError::InvalidNoSpellcheck(_))
+        ));
+    }
+
+    #[test]
+    fn test_roundtrip_preserves_metadata() {
+        let mut doc = Document::from_text("Body");
+        doc.update_metadata(vec![
+            ("title".to_string(), Some("Notes".to_string())),
+            ("status".to_string(), Some("draft".to_string())),
+        ])
+        .unwrap();
+
+        let json = doc.to_json().unwrap();
+        assert!(json.contains(r#""title":"Notes""#));
+        let restored = Document::from_json(&json).unwrap();
+        assert_eq!(restored.get_metadata("title"), Some("Notes"));
+        assert_eq!(restored.get_metadata("status"), Some("draft"));
+        assert!(!restored.can_undo());
+
+        doc.undo().unwrap();
+        assert!(doc.metadata().is_empty());
+    }
+
+    #[test]
+    fn test_json_decorations_and_extensions_round_trip() {
+        let mut doc = Document::from_text("teh cat");
+        assert!(doc.to_json().unwrap().contains("\"version\":\"1.0\""));
+        doc.add_decoration(
+            Range::from_offsets(0, 3),
+            Decoration::new("lint").with_attribute("message", "Typo"),
+        )
+        .unwrap();
+        doc.set_json_extension("com.example.outline", r#"{"collapsed":[2]}"#)
+            .unwrap();
+        assert!(doc.set_json_extension("bad", "{").is_err());
+
+        let json = doc.to_json().unwrap();
+        assert!(json.contains("\"version\":\"1.1\""));
+        let restored = Document::from_json(&json).unwrap();
+        let decorations = restored.get_decorations_at(Position::new(1));
+        assert_eq!(decorations.len(), 1);
+        assert_eq!(decorations[0].0, Range::from_offsets(0, 3));
+        assert_eq!(decorations[0].1.attributes["message"], "Typo");
+        assert_eq!(
+            restored
+                .get_json_extension("com.example.outline")
+                .as_deref(),
+            Some(r#"{"collapsed":[2]}"#)
+        );
+
+        let invalid = r#"{"version":"1.1","text":"ab","formats":[],"blocks":[],"decorations":[{"start":1,"end":5,"decoration":{"kind":"lint"}}]}"#;
+        assert!(matches!(
+            Document::from_json(invalid),
+            Err(JsonError::InvalidDecoration(_))
+        ));
+    }
+
+    #[test]
+    fn test_json_newer_minor_version_and_required_sections() {
+        // A newer minor version is read, ignoring sections it added
+        let json = r#"{"version":"1.4","text":"Hi","formats":[],"blocks":[],"comments_v2":[1],"required":["decorations"]}"#;
+        let doc = Document::from_json(json).unwrap();
+        assert_eq!(doc.get_content(), "Hi");
+
+        // unless the writer marked them as required
+        let json = r#"{"version":"1.4","text":"Hi","formats":[],"blocks":[],"comments_v2":[1],"required":["comments_v2"]}"#;
+        match Document::from_json(json) {
+            Err(JsonError::UnsupportedSection(section)) => assert_eq!(section, "comments_v2"),
+            other => panic!("Expected UnsupportedSection error, got {:?}", other.err()),
+        }
+
+        let json = r#"{"version":"1.x","text":"Hi","formats":[],"blocks":[]}"#;
+        assert!(matches!(
+            Document::from_json(json),
+            Err(JsonError::UnsupportedVersion(_))
+        ));
+    }
 }
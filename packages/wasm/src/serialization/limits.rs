@@ -0,0 +1,138 @@
+//! Limits for importing untrusted HTML and Markdown
+//!
+//! Pasted or uploaded content can make an importer do far more work than
+//! its size suggests: elements nested thousands deep, millions of tags, or
+//! emphasis nested deep enough to exhaust the stack. `ImportLimits` caps
+//! what an import may produce. An import that goes over a limit stops with
+//! a `LimitExceeded` error naming the limit instead of running on.
+
+use crate::document::validation::MAX_DOCUMENT_SIZE;
+use std::fmt;
+use thiserror::Error;
+
+/// Most tokens allowed by `ImportLimits::safe`
+pub const SAFE_MAX_TOKENS: usize = 1_000_000;
+
+/// Deepest nesting allowed by `ImportLimits::safe`
+pub const SAFE_MAX_DEPTH: usize = 256;
+
+/// Caps on the work done importing HTML or Markdown
+///
+/// Each limit is off when None. The default has no limits, matching
+/// imports without options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImportLimits {
+    /// Most tokens the parser may produce: tags and text runs for HTML,
+    /// lines and inline spans for Markdown
+    pub max_tokens: Option<usize>,
+    /// Deepest nesting of elements or inline formatting
+    pub max_depth: Option<usize>,
+    /// Most characters in the imported document
+    pub max_output_size: Option<usize>,
+}
+
+impl ImportLimits {
+    /// Creates limits with every limit off
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates limits suited to untrusted input
+    ///
+    /// Allows `SAFE_MAX_TOKENS` tokens, nesting `SAFE_MAX_DEPTH` deep and
+    /// documents up to the maximum document size.
+    pub fn safe() -> Self {
+        Self {
+            max_tokens: Some(SAFE_MAX_TOKENS),
+            max_depth: Some(SAFE_MAX_DEPTH),
+            max_output_size: Some(MAX_DOCUMENT_SIZE),
+        }
+    }
+
+    /// Sets the most tokens the parser may produce
+    pub fn max_tokens(mut self, max_tokens: Option<usize>) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Sets the deepest nesting allowed
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets the most characters the imported document may have
+    pub fn max_output_size(mut self, max_output_size: Option<usize>) -> Self {
+        self.max_output_size = max_output_size;
+        self
+    }
+
+    /// Returns an error if `value` is over the given limit
+    pub(crate) fn check(&self, limit: ImportLimit, value: usize) -> Result<(), LimitExceeded> {
+        let max = match limit {
+            ImportLimit::Tokens => self.max_tokens,
+            ImportLimit::Depth => self.max_depth,
+            ImportLimit::OutputSize => self.max_output_size,
+        };
+        match max {
+            Some(max) if value > max => Err(LimitExceeded { limit, max }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// One of the limits in `ImportLimits`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportLimit {
+    Tokens,
+    Depth,
+    OutputSize,
+}
+
+impl fmt::Display for ImportLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ImportLimit::Tokens => "token count",
+            ImportLimit::Depth => "nesting depth",
+            ImportLimit::OutputSize => "output size",
+        })
+    }
+}
+
+/// An import stopped because its input went over a limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("import limit exceeded: {limit} is over {max}")]
+pub struct LimitExceeded {
+    pub limit: ImportLimit,
+    /// The limit that was exceeded
+    pub max: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_limits() {
+        let limits = ImportLimits::new().max_depth(Some(2));
+        assert!(limits.check(ImportLimit::Depth, 2).is_ok());
+        assert!(limits.check(ImportLimit::Tokens, usize::MAX).is_ok());
+
+        let err = limits.check(ImportLimit::Depth, 3).unwrap_err();
+        assert_eq!(
+            err,
+            LimitExceeded {
+                limit: ImportLimit::Depth,
+                max: 2,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "import limit exceeded: nesting depth is over 2"
+        );
+        assert_eq!(
+            ImportLimits::safe().max_output_size,
+            Some(MAX_DOCUMENT_SIZE)
+        );
+    }
+}
@@ -1,30 +1,55 @@
 //! Serialization module
 //!
 //! This module handles import and export of documents in multiple formats.
-//! It provides serialization to and from JSON, Markdown, and HTML.
+//! It provides serialization to and from JSON, Markdown, HTML, Quill Delta and
+//! RTF, and export to Word (.docx).
 //!
 //! # Responsibilities
 //!
 //! - Serialize documents to JSON with full fidelity
 //! - Deserialize documents from JSON
+//! - Serialize documents to a compact, versioned binary format
 //! - Export documents to Markdown format
 //! - Import documents from Markdown
+//! - Read and write YAML/TOML front matter as document metadata
 //! - Export documents to HTML with proper escaping
 //! - Import documents from HTML with sanitization
 //! - Prevent XSS vulnerabilities in HTML import
+//! - Cap the work done importing untrusted HTML and Markdown
+//! - Export and import Quill Delta JSON for interop with Quill
+//! - Export documents to Word (.docx) packages
+//! - Export and import RTF for word processor interop
+//! - Infer headings, lists and quotes when importing plain text
+//! - Report what a format loses by exporting and re-importing a document
 //!
 //! # Key Types
 //!
 //! - JSON serialization/deserialization functions
+//! - `BinaryError`: Errors from the binary format
 //! - Markdown export/import functions
 //! - HTML export/import functions with sanitization
 //! - `HtmlSanitizer`: Prevents XSS attacks in HTML import
+//! - `ImportLimits`: Caps tokens, nesting depth and output size of imports
 //! - `SerializationError`: Comprehensive error type for all serialization operations
 
+pub mod binary;
+pub mod delta;
+pub mod docx;
+mod entities;
 pub mod errors;
+mod front_matter;
 pub mod html;
+pub(crate) mod html_parser;
 pub mod json;
+pub mod limits;
 pub mod markdown;
+pub mod plain_text;
+pub mod round_trip;
+pub mod rtf;
+pub(crate) mod varint;
+
+#[cfg(all(test, feature = "commonmark-spec"))]
+mod commonmark_spec;
 
 // Re-export error types
 pub use errors::SerializationError;
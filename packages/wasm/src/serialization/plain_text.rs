@@ -0,0 +1,259 @@
+//! Plain text with structure
+//!
+//! Pasted notes often carry their structure as plain text conventions:
+//! `- ` bullets, `1. ` numbering, `> ` quotes and short title lines set off
+//! by a blank line. `Document::from_plain_text_smart` turns those into
+//! blocks instead of flat paragraphs, and
+//! `Document::to_plain_text_structured` writes lists and quotes back out
+//! the same way.
+
+use crate::document::{Document, Position, SOFT_BREAK};
+use crate::formatting::BlockType;
+use crate::formatting::block::MAX_QUOTE_DEPTH;
+use crate::formatting::storage::BlockInfo;
+
+/// Longest line, in characters, that may be taken for a heading
+const MAX_HEADING_LENGTH: usize = 60;
+
+impl Document {
+    /// Imports plain text, inferring blocks from common conventions
+    ///
+    /// - Lines starting with `- `, `* ` or `• ` become bullet list items
+    /// - Lines starting with `1. ` or `1) ` become numbered list items
+    /// - Lines starting with `> ` become block quotes, one level per `>`
+    /// - Leading whitespace before a list marker nests the item, one level
+    ///   per two columns (a tab counts as four)
+    /// - A short line without closing punctuation, with a blank line (or the
+    ///   start of the text) before it and a blank line after it, becomes a
+    ///   heading: level 1 at the start of the text, level 2 elsewhere
+    ///
+    /// Markers are removed from the text; everything else is kept as typed,
+    /// including blank lines.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    /// use rte_core::formatting::BlockType;
+    ///
+    /// let doc = Document::from_plain_text_smart("Groceries\n\n- milk\n- eggs");
+    /// assert_eq!(doc.get_content(), "Groceries\n\nmilk\neggs");
+    /// assert_eq!(doc.get_block_type_at(Position::new(0)), BlockType::heading(1));
+    /// assert_eq!(doc.get_block_type_at(Position::new(11)), BlockType::BulletList);
+    /// ```
+    pub fn from_plain_text_smart(text: &str) -> Self {
+        let text = text.replace("\r\n", "\n");
+        let lines: Vec<&str> = text.split('\n').collect();
+        let is_blank = |index: usize| lines.get(index).is_some_and(|l| l.trim().is_empty());
+
+        let mut content = String::new();
+        let mut blocks: Vec<BlockInfo> = Vec::new();
+        let mut offset = 0;
+        for (index, &line) in lines.iter().enumerate() {
+            let (block_type, indent, text) = match infer_block(line) {
+                Some(inferred) => inferred,
+                None if is_heading(line)
+                    && (index == 0 || is_blank(index - 1))
+                    && is_blank(index + 1) =>
+                {
+                    let level = if index == 0 { 1 } else { 2 };
+                    (BlockType::heading(level), 0, line.trim())
+                }
+                None => (BlockType::Paragraph, 0, line),
+            };
+
+            let same_as_previous = blocks
+                .last()
+                .is_some_and(|b| b.block_type == block_type && b.indent == indent);
+            if !same_as_previous {
+                blocks.push(BlockInfo::new(offset, block_type).with_indent(indent));
+            }
+
+            if index > 0 {
+                content.push('\n');
+            }
+            content.push_str(text);
+            offset += text.chars().count() + 1;
+        }
+
+        let mut doc = Document::from_text(&content);
+        doc.formats_mut().set_blocks(blocks);
+        doc
+    }
+
+    /// Exports plain text with list markers and quote markers
+    ///
+    /// List items start with their marker, as from `get_list_marker_at`,
+    /// indented two spaces per nesting level, and quoted lines with one
+    /// `> ` per quote level. Lines continued by a soft break are indented
+    /// under the text of a list item, or quoted again. Other blocks are
+    /// plain lines, so `from_plain_text_smart` reads the lists and quotes
+    /// back.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Range};
+    /// use rte_core::formatting::BlockType;
+    ///
+    /// let mut doc = Document::from_text("Steps\nMix\nBake\nEnjoy");
+    /// doc.set_block_type(Range::from_offsets(6, 14), BlockType::NumberedList);
+    /// doc.set_block_type(Range::from_offsets(15, 20), BlockType::block_quote());
+    /// assert_eq!(
+    ///     doc.to_plain_text_structured(),
+    ///     "Steps\n1. Mix\n2. Bake\n> Enjoy"
+    /// );
+    /// ```
+    pub fn to_plain_text_structured(&self) -> String {
+        let content = self.get_content();
+        let mut result = String::with_capacity(content.len());
+        let mut line_start = 0;
+        for (index, line) in content.split('\n').enumerate() {
+            if index > 0 {
+                result.push('\n');
+            }
+            let position = Position::new(line_start);
+            line_start += line.chars().count() + 1;
+
+            let (prefix, continuation) = match self.get_list_marker_at(position) {
+                Some(marker) => {
+                    let indent = "  ".repeat(self.get_list_indent_at(position) as usize);
+                    let prefix = format!("{indent}{marker} ");
+                    let continuation = " ".repeat(prefix.chars().count());
+                    (prefix, continuation)
+                }
+                None => match self.get_block_type_at(position).quote_depth() {
+                    Some(depth) => {
+                        let prefix = "> ".repeat(depth as usize);
+                        (prefix.clone(), prefix)
+                    }
+                    None => (String::new(), String::new()),
+                },
+            };
+            if line.is_empty() {
+                result.push_str(prefix.trim_end());
+                continue;
+            }
+            result.push_str(&prefix);
+            result.push_str(&line.replace(SOFT_BREAK, &format!("\n{continuation}")));
+        }
+        result
+    }
+}
+
+/// Recognizes a list item or quote line, returning its block, nesting level
+/// and text without the marker
+fn infer_block(line: &str) -> Option<(BlockType, u8, &str)> {
+    let trimmed = line.trim_start();
+    let columns: usize = line[..line.len() - trimmed.len()]
+        .chars()
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum();
+    let indent = u8::try_from(columns / 2).unwrap_or(u8::MAX);
+
+    for marker in ["- ", "* ", "• "] {
+        if let Some(rest) = trimmed.strip_prefix(marker) {
+            return Some((BlockType::BulletList, indent, rest));
+        }
+    }
+
+    let digits = trimmed.chars().take_while(|c| c.is_ascii_digit()).count();
+    if (1..=9).contains(&digits) {
+        let rest = &trimmed[digits..];
+        if let Some(rest) = rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") ")) {
+            return Some((BlockType::NumberedList, indent, rest));
+        }
+    }
+
+    if trimmed.starts_with("> ") || trimmed == ">" {
+        let mut rest = trimmed;
+        let mut depth: u8 = 0;
+        while let Some(after) = rest.strip_prefix('>') {
+            depth = depth.saturating_add(1);
+            rest = after.strip_prefix(' ').unwrap_or(after);
+        }
+        let quote = BlockType::block_quote_with(None, depth.min(MAX_QUOTE_DEPTH));
+        return Some((quote, 0, rest));
+    }
+
+    None
+}
+
+/// Returns true if a line is short enough, and unpunctuated, to be a title
+fn is_heading(line: &str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.is_empty()
+        && trimmed.chars().count() <= MAX_HEADING_LENGTH
+        && !trimmed.ends_with(['.', ',', ';', '!', '?'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Position;
+
+    fn block_types(doc: &Document) -> Vec<BlockType> {
+        let mut offset = 0;
+        doc.get_content()
+            .split('\n')
+            .map(|line| {
+                let block_type = doc.get_block_type_at(Position::new(offset));
+                offset += line.chars().count() + 1;
+                block_type
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_infer_lists_and_quotes() {
+        let doc = Document::from_plain_text_smart(
+            "Notes from today:\n- one\n  * nested\n2) two\n> quoted\n> > deeper",
+        );
+        assert_eq!(
+            doc.get_content(),
+            "Notes from today:\none\nnested\ntwo\nquoted\ndeeper"
+        );
+        assert_eq!(
+            block_types(&doc),
+            vec![
+                BlockType::Paragraph,
+                BlockType::BulletList,
+                BlockType::BulletList,
+                BlockType::NumberedList,
+                BlockType::block_quote(),
+                BlockType::block_quote_with(None, 2),
+            ]
+        );
+        assert_eq!(doc.get_list_indent_at(Position::new(22)), 1);
+    }
+
+    #[test]
+    fn test_infer_headings() {
+        let doc = Document::from_plain_text_smart(
+            "Title\n\nA sentence.\n\nSection\n\nBody\nNot a heading\n\nEnd.\n\nLast",
+        );
+        assert_eq!(
+            block_types(&doc),
+            vec![
+                BlockType::heading(1),
+                BlockType::Paragraph,
+                BlockType::Paragraph,
+                BlockType::Paragraph,
+                BlockType::heading(2),
+                BlockType::Paragraph,
+                BlockType::Paragraph,
+                BlockType::Paragraph,
+                BlockType::Paragraph,
+                BlockType::Paragraph,
+                BlockType::Paragraph,
+                BlockType::Paragraph,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plain_paragraphs_are_unchanged() {
+        let text = "Just text\r\nwith lines";
+        let doc = Document::from_plain_text_smart(text);
+        assert_eq!(doc.get_content(), "Just text\nwith lines");
+        assert!(block_types(&doc).iter().all(|b| *b == BlockType::Paragraph));
+    }
+}
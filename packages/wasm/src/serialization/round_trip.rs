@@ -0,0 +1,330 @@
+//! Round-trip fidelity checks
+//!
+//! Exports a document, imports the result again and compares the two, so an
+//! app can tell the user what a format will lose for this particular
+//! document, such as "Markdown will drop underline and colors", instead of
+//! listing everything the format cannot express.
+
+use crate::document::{Document, Range};
+use crate::formatting::{BlockType, InlineFormat};
+use crate::serialization::delta::DeltaError;
+use crate::serialization::html::HtmlError;
+use crate::serialization::json::JsonError;
+use crate::serialization::markdown::MarkdownError;
+use crate::serialization::rtf::RtfError;
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// Errors that can occur while checking a round trip
+#[derive(Debug, Error)]
+pub enum RoundTripError {
+    #[error(transparent)]
+    Json(#[from] JsonError),
+
+    #[error(transparent)]
+    Html(#[from] HtmlError),
+
+    #[error(transparent)]
+    Markdown(#[from] MarkdownError),
+
+    #[error(transparent)]
+    Rtf(#[from] RtfError),
+
+    #[error(transparent)]
+    Delta(#[from] DeltaError),
+}
+
+/// A format that can be both exported and imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundTripFormat {
+    Json,
+    Html,
+    Markdown,
+    Rtf,
+    Delta,
+    PlainText,
+}
+
+impl RoundTripFormat {
+    /// Parses a format name: "json", "html", "markdown" (or "md"), "rtf",
+    /// "delta", or "text" (or "plain")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "html" => Some(Self::Html),
+            "markdown" | "md" => Some(Self::Markdown),
+            "rtf" => Some(Self::Rtf),
+            "delta" => Some(Self::Delta),
+            "text" | "plain" => Some(Self::PlainText),
+            _ => None,
+        }
+    }
+}
+
+/// An inline format the round trip dropped or changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatLoss {
+    pub format: InlineFormat,
+    /// Text of the original document that lost the format, in order
+    pub ranges: Vec<Range>,
+}
+
+/// A line whose block type did not survive the round trip
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockLoss {
+    /// Line of the original document
+    pub line: usize,
+    pub block_type: BlockType,
+    /// Block type the line came back with, or None if the text changed too
+    /// much to match lines and no line came back with this block type
+    pub result: Option<BlockType>,
+}
+
+/// Differences between a document and the same document after a round trip
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundTripReport {
+    pub format: RoundTripFormat,
+    /// True if the text came back unchanged
+    pub text_preserved: bool,
+    /// Inline formats that were lost, ordered by where they first occur
+    pub lost_formats: Vec<FormatLoss>,
+    /// Lines whose block type was lost, in order
+    pub lost_blocks: Vec<BlockLoss>,
+}
+
+impl RoundTripReport {
+    /// Returns true if the text, inline formats and block types all survived
+    pub fn is_lossless(&self) -> bool {
+        self.text_preserved && self.lost_formats.is_empty() && self.lost_blocks.is_empty()
+    }
+}
+
+impl Document {
+    /// Exports the document to `format`, imports it again and reports what
+    /// was lost
+    ///
+    /// When the text comes back unchanged, formats and block types are
+    /// compared character by character and line by line. Otherwise only
+    /// formats and block types that are missing from the result entirely
+    /// are reported.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if exporting or importing fails.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Range};
+    /// use rte_core::formatting::InlineFormat;
+    /// use rte_core::serialization::round_trip::RoundTripFormat;
+    ///
+    /// let mut doc = Document::from_text("Hello world");
+    /// doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+    /// doc.apply_format(Range::from_offsets(6, 11), InlineFormat::Underline);
+    ///
+    /// let report = doc.verify_round_trip(RoundTripFormat::Markdown).unwrap();
+    /// assert_eq!(report.lost_formats.len(), 1);
+    /// assert_eq!(report.lost_formats[0].format, InlineFormat::Underline);
+    /// assert!(doc.verify_round_trip(RoundTripFormat::Json).unwrap().is_lossless());
+    /// ```
+    pub fn verify_round_trip(
+        &self,
+        format: RoundTripFormat,
+    ) -> Result<RoundTripReport, RoundTripError> {
+        let result = match format {
+            RoundTripFormat::Json => Document::from_json(&self.to_json()?)?,
+            RoundTripFormat::Html => Document::from_html(&self.to_html())?,
+            RoundTripFormat::Markdown => Document::from_markdown(&self.to_markdown())?,
+            RoundTripFormat::Rtf => Document::from_rtf(&self.to_rtf())?,
+            RoundTripFormat::Delta => Document::from_delta(&self.to_delta())?,
+            RoundTripFormat::PlainText => Document::from_plain_text(&self.to_plain_text()),
+        };
+
+        let text_preserved = self.get_content() == result.get_content();
+        Ok(RoundTripReport {
+            format,
+            text_preserved,
+            lost_formats: lost_formats(self, &result, text_preserved),
+            lost_blocks: lost_blocks(self, &result, text_preserved),
+        })
+    }
+}
+
+/// Finds the inline formats of `original` that `result` does not have
+///
+/// With `aligned` text the formats are compared at each offset.
+fn lost_formats(original: &Document, result: &Document, aligned: bool) -> Vec<FormatLoss> {
+    let kept = result
+        .formats()
+        .styled_runs(Range::from_offsets(0, result.get_length()));
+    let kept_anywhere: HashSet<&InlineFormat> =
+        kept.iter().flat_map(|(_, formats)| formats).collect();
+
+    let mut losses: Vec<FormatLoss> = Vec::new();
+    for (range, formats) in original
+        .formats()
+        .styled_runs(Range::from_offsets(0, original.get_length()))
+    {
+        for format in formats {
+            let lost = if aligned {
+                missing_ranges(&kept, range, &format)
+            } else if kept_anywhere.contains(&format) {
+                Vec::new()
+            } else {
+                vec![range]
+            };
+            for range in lost {
+                push_loss(&mut losses, &format, range);
+            }
+        }
+    }
+
+    // Formats first lost in the same run come out of a set, so give them
+    // a stable order
+    losses.sort_by_cached_key(|loss| (loss.ranges[0].start_offset(), format!("{:?}", loss.format)));
+    losses
+}
+
+/// Returns the parts of `range` where the runs in `kept` lack `format`
+fn missing_ranges(
+    kept: &[(Range, HashSet<InlineFormat>)],
+    range: Range,
+    format: &InlineFormat,
+) -> Vec<Range> {
+    let start = range.start_offset();
+    let end = range.end_offset();
+    let first = kept.partition_point(|(run, _)| run.end_offset() <= start);
+    kept[first..]
+        .iter()
+        .take_while(|(run, _)| run.start_offset() < end)
+        .filter(|(_, formats)| !formats.contains(format))
+        .map(|(run, _)| {
+            Range::from_offsets(run.start_offset().max(start), run.end_offset().min(end))
+        })
+        .collect()
+}
+
+/// Records that `format` was lost over `range`, extending the last range
+/// recorded for it when the two touch
+fn push_loss(losses: &mut Vec<FormatLoss>, format: &InlineFormat, range: Range) {
+    match losses.iter_mut().find(|loss| loss.format == *format) {
+        Some(loss) => match loss.ranges.last_mut() {
+            Some(last) if last.end_offset() == range.start_offset() => {
+                *last = Range::from_offsets(last.start_offset(), range.end_offset());
+            }
+            _ => loss.ranges.push(range),
+        },
+        None => losses.push(FormatLoss {
+            format: format.clone(),
+            ranges: vec![range],
+        }),
+    }
+}
+
+/// Finds the lines of `original` whose block type `result` does not have
+///
+/// With `aligned` text the block types are compared line by line.
+fn lost_blocks(original: &Document, result: &Document, aligned: bool) -> Vec<BlockLoss> {
+    let block_types = |doc: &Document| -> Vec<BlockType> {
+        (0..doc.line_count())
+            .filter_map(|line| doc.line_column_to_position(line, 0))
+            .map(|pos| doc.get_block_type_at(pos))
+            .collect()
+    };
+    let expected = block_types(original);
+    let actual = block_types(result);
+
+    expected
+        .into_iter()
+        .enumerate()
+        .filter_map(|(line, block_type)| {
+            let result = if aligned {
+                let result = actual.get(line).cloned().unwrap_or(BlockType::Paragraph);
+                (result != block_type).then_some(Some(result))?
+            } else if actual.contains(&block_type) {
+                return None;
+            } else {
+                None
+            };
+            Some(BlockLoss {
+                line,
+                block_type,
+                result,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_reports_lost_formats_and_blocks() {
+        let mut doc = Document::from_text("Title\nSome colored text\nquote");
+        doc.set_block_type(Range::from_offsets(0, 5), BlockType::heading(1));
+        doc.set_block_type(Range::from_offsets(6, 23), BlockType::Paragraph);
+        doc.set_block_type(Range::from_offsets(24, 29), BlockType::block_quote());
+        doc.apply_format(Range::from_offsets(6, 10), InlineFormat::Bold);
+        let red = InlineFormat::TextColor {
+            color: "red".to_string(),
+        };
+        doc.apply_format(Range::from_offsets(8, 18), red.clone());
+
+        let report = doc.verify_round_trip(RoundTripFormat::Markdown).unwrap();
+        assert!(report.text_preserved);
+        assert_eq!(
+            report.lost_formats,
+            [FormatLoss {
+                format: red,
+                ranges: vec![Range::from_offsets(8, 18)],
+            }]
+        );
+        assert!(report.lost_blocks.is_empty());
+
+        let report = doc.verify_round_trip(RoundTripFormat::PlainText).unwrap();
+        assert_eq!(report.lost_formats.len(), 2);
+        assert_eq!(
+            report.lost_blocks,
+            [
+                BlockLoss {
+                    line: 0,
+                    block_type: BlockType::heading(1),
+                    result: Some(BlockType::Paragraph),
+                },
+                BlockLoss {
+                    line: 2,
+                    block_type: BlockType::block_quote(),
+                    result: Some(BlockType::Paragraph),
+                },
+            ]
+        );
+        assert!(!report.is_lossless());
+    }
+
+    #[test]
+    fn test_round_trip_with_changed_text() {
+        // HTML collapses the run of spaces, so offsets no longer line up
+        let mut doc = Document::from_text(
+            "Wide   gap
+next",
+        );
+        doc.set_block_type(Range::from_offsets(11, 15), BlockType::heading(2));
+        doc.set_block_type(Range::from_offsets(0, 10), BlockType::Paragraph);
+        doc.apply_format(Range::from_offsets(0, 4), InlineFormat::Underline);
+
+        let report = doc.verify_round_trip(RoundTripFormat::Html).unwrap();
+        assert!(!report.text_preserved);
+        assert!(report.lost_formats.is_empty());
+        assert!(report.lost_blocks.is_empty());
+
+        let report = doc.verify_round_trip(RoundTripFormat::PlainText).unwrap();
+        assert!(report.text_preserved);
+        assert_eq!(report.lost_formats[0].ranges, [Range::from_offsets(0, 4)]);
+        assert_eq!(
+            RoundTripFormat::from_name("MD"),
+            Some(RoundTripFormat::Markdown)
+        );
+        assert_eq!(RoundTripFormat::from_name("docx"), None);
+    }
+}
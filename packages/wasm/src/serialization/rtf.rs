@@ -0,0 +1,1074 @@
+use crate::document::validation::validate_size_limit;
+use crate::document::{Document, EMBED_CHAR, Position, Range, SOFT_BREAK};
+use crate::formatting::storage::BlockInfo;
+use crate::formatting::{BlockType, InlineFormat};
+use crate::serialization::docx::{HEADING_SIZES, hex_color};
+use crate::serialization::html::HtmlSanitizer;
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+/// Errors that can occur during RTF import
+#[derive(Debug, Error)]
+pub enum RtfError {
+    #[error("Input is not an RTF document")]
+    NotRtf,
+
+    #[error("Unbalanced RTF groups")]
+    UnbalancedGroups,
+
+    #[error("Invalid RTF text: {0}")]
+    InvalidText(String),
+}
+
+/// Control words whose groups hold no document text
+const SKIPPED_DESTINATIONS: &[&str] = &[
+    "info",
+    "pict",
+    "object",
+    "shppict",
+    "nonshppict",
+    "header",
+    "headerl",
+    "headerr",
+    "headerf",
+    "footer",
+    "footerl",
+    "footerr",
+    "footerf",
+    "footnote",
+    "annotation",
+    "listtable",
+    "listoverridetable",
+    "themedata",
+    "colorschememapping",
+    "latentstyles",
+    "datastore",
+    "xmlnstbl",
+    "rsidtbl",
+    "generator",
+    "filetbl",
+    "revtbl",
+    "bkmkstart",
+    "bkmkend",
+    "pntxta",
+    "pntxtb",
+];
+
+/// Windows-1252 characters for bytes 0x80-0x9F; zero marks unassigned bytes
+const CP1252_HIGH: [u16; 32] = [
+    0x20AC, 0, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 0x02C6, 0x2030, 0x0160, 0x2039,
+    0x0152, 0, 0x017D, 0, 0, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014, 0x02DC,
+    0x2122, 0x0161, 0x203A, 0x0153, 0, 0x017E, 0x0178,
+];
+
+impl Document {
+    /// Exports the document as RTF
+    ///
+    /// Block types map onto a small stylesheet (Normal, heading 1-6, Quote,
+    /// Code, List Bullet and List Number) so word processors show them as
+    /// named styles. Inline formats become character properties and links
+    /// become `HYPERLINK` fields. Non-ASCII text is written as `\u` escapes
+    /// and soft line breaks as `\line`.
+    ///
+    /// Images and quote citations are not exported.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Range};
+    /// use rte_core::formatting::InlineFormat;
+    ///
+    /// let mut doc = Document::from_text("Hello World");
+    /// doc.apply_format(Range::from_offsets(0, 5), InlineFormat::Bold);
+    /// let rtf = doc.to_rtf();
+    /// assert!(rtf.starts_with(r"{\rtf1"));
+    /// assert!(rtf.contains(r"{\b Hello}{ World}\par"));
+    /// ```
+    pub fn to_rtf(&self) -> String {
+        let text: Vec<char> = self.get_content().chars().collect();
+        let mut colors: Vec<String> = Vec::new();
+        let mut body = String::new();
+        let mut line_start = 0;
+        let mut previous_block = BlockType::Paragraph;
+        let mut list_number = 0;
+
+        loop {
+            let line_end = self.line_end_from(line_start);
+            let block_type = self.get_block_type_at(Position::new(line_start));
+
+            if block_type == BlockType::NumberedList {
+                if previous_block != BlockType::NumberedList {
+                    list_number = 0;
+                }
+                list_number += 1;
+            }
+            body.push_str(&paragraph_properties(&block_type, list_number));
+
+            for (range, formats) in self
+                .formats()
+                .styled_runs(Range::from_offsets(line_start, line_end))
+            {
+                let run: String = text[range.start_offset()..range.end_offset()]
+                    .iter()
+                    .collect();
+                body.push_str(&run_rtf(&run, &formats, &mut colors));
+            }
+            body.push_str("\\par\n");
+            previous_block = block_type;
+
+            if line_end >= text.len() {
+                break;
+            }
+            line_start = line_end + 1;
+        }
+
+        let mut rtf = String::from("{\\rtf1\\ansi\\ansicpg1252\\deff0\\uc1\n");
+        rtf.push_str(
+            "{\\fonttbl{\\f0\\fswiss\\fcharset0 Helvetica;}{\\f1\\fmodern\\fcharset0 Courier New;}}\n",
+        );
+        rtf.push_str("{\\colortbl;");
+        for hex in &colors {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+            rtf.push_str(&format!(
+                "\\red{}\\green{}\\blue{};",
+                channel(0),
+                channel(2),
+                channel(4)
+            ));
+        }
+        rtf.push_str("}\n");
+        rtf.push_str(&stylesheet());
+        rtf.push_str(&body);
+        rtf.push('}');
+        rtf
+    }
+
+    /// Imports a document from RTF
+    ///
+    /// Reads text, character formatting, `HYPERLINK` fields and paragraph
+    /// styles. Block types come from the paragraph style name when the
+    /// stylesheet names one this editor knows (as Word and `to_rtf` write
+    /// them), then from list numbering, then from `\outlinelevel`.
+    /// `\line` becomes a soft line break within the paragraph.
+    /// Monospaced fonts become inline code. Pictures, headers, footers and
+    /// other non-text destinations are skipped, and links with unsafe URLs
+    /// are dropped.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::document::{Document, Position};
+    /// use rte_core::formatting::{BlockType, InlineFormat};
+    ///
+    /// let rtf = r"{\rtf1\ansi{\stylesheet{\s1 heading 1;}}
+    /// \pard\s1 Title\par
+    /// \pard Some {\i body} text\par}";
+    /// let doc = Document::from_rtf(rtf).unwrap();
+    /// assert_eq!(doc.get_content(), "Title\nSome body text");
+    /// assert_eq!(doc.get_block_type_at(Position::new(0)), BlockType::heading(1));
+    /// assert_eq!(doc.get_block_type_at(Position::new(6)), BlockType::Paragraph);
+    /// assert!(doc.get_formats_at(Position::new(11)).contains(&InlineFormat::Italic));
+    /// ```
+    pub fn from_rtf(rtf: &str) -> Result<Self, RtfError> {
+        if !rtf.trim_start().starts_with("{\\rtf") {
+            return Err(RtfError::NotRtf);
+        }
+
+        let mut reader = RtfReader::new();
+        reader.read(rtf.trim_start())?;
+        let (text, formats, blocks) = reader.finish();
+
+        validate_size_limit(0, text.chars().count())
+            .map_err(|e| RtfError::InvalidText(e.to_string()))?;
+
+        let mut doc = Document::from_text(&text);
+        for (start, end, format) in formats {
+            doc.formats_mut()
+                .apply_format(Range::from_offsets(start, end), format);
+        }
+        doc.formats_mut().set_blocks(blocks);
+
+        Ok(doc)
+    }
+}
+
+fn stylesheet() -> String {
+    let mut styles = String::from("{\\stylesheet{\\s0 Normal;}");
+    for (index, size) in HEADING_SIZES.iter().enumerate() {
+        styles.push_str(&format!(
+            "{{\\s{}\\outlinelevel{}\\fs{} heading {};}}",
+            index + 1,
+            index,
+            size,
+            index + 1
+        ));
+    }
+    styles.push_str("{\\s7\\li720 Quote;}{\\s8\\f1 Code;}");
+    styles.push_str("{\\s9\\fi-360\\li720 List Bullet;}{\\s10\\fi-360\\li720 List Number;}}\n");
+    styles
+}
+
+fn paragraph_properties(block_type: &BlockType, list_number: usize) -> String {
+    match block_type {
+        BlockType::Paragraph => "\\pard\\plain\\s0".to_string(),
+        BlockType::Heading { level } => {
+            let level = (*level).clamp(1, 6) as usize;
+            format!(
+                "\\pard\\plain\\s{}\\outlinelevel{}\\fs{}",
+                level,
+                level - 1,
+                HEADING_SIZES[level - 1]
+            )
+        }
+        BlockType::BulletList => "\\pard\\plain\\s9\\fi-360\\li720\
+             {\\*\\pn\\pnlvlblt\\pnindent360{\\pntxtb\\bullet}}{\\pntext\\bullet\\tab}"
+            .to_string(),
+        BlockType::NumberedList => format!(
+            "\\pard\\plain\\s10\\fi-360\\li720\
+             {{\\*\\pn\\pnlvlbody\\pndec{}\\pnindent360{{\\pntxta .}}}}{{\\pntext {}.\\tab}}",
+            if list_number == 1 { "\\pnstart1" } else { "" },
+            list_number
+        ),
+        BlockType::BlockQuote { depth, .. } => {
+            format!("\\pard\\plain\\s7\\li{}", 720 * u32::from(*depth))
+        }
+        BlockType::CodeBlock { .. } => "\\pard\\plain\\s8\\f1".to_string(),
+    }
+}
+
+fn run_rtf(text: &str, formats: &HashSet<InlineFormat>, colors: &mut Vec<String>) -> String {
+    let mut properties = String::new();
+    let mut link = None;
+
+    // Fixed order keeps the output stable regardless of set iteration
+    for (format, word) in [
+        (InlineFormat::Bold, "\\b"),
+        (InlineFormat::Italic, "\\i"),
+        (InlineFormat::Underline, "\\ul"),
+        (InlineFormat::Strikethrough, "\\strike"),
+        (InlineFormat::SmallCaps, "\\scaps"),
+        (InlineFormat::Subscript, "\\sub"),
+        (InlineFormat::Superscript, "\\super"),
+        (InlineFormat::Code, "\\f1"),
+    ] {
+        if formats.contains(&format) {
+            properties.push_str(word);
+        }
+    }
+    for format in formats {
+        match format {
+            InlineFormat::TextColor { color } => {
+                if let Some(hex) = hex_color(color) {
+                    properties.push_str(&format!("\\cf{}", color_index(colors, hex)));
+                }
+            }
+            InlineFormat::BackgroundColor { color } => {
+                if let Some(hex) = hex_color(color) {
+                    properties.push_str(&format!("\\chcbpat{}", color_index(colors, hex)));
+                }
+            }
+            InlineFormat::Link { url } => link = Some(url),
+            _ => {}
+        }
+    }
+    if formats.contains(&InlineFormat::Highlight) {
+        let index = color_index(colors, "FFFF00".to_string());
+        properties.push_str(&format!("\\highlight{}", index));
+    }
+
+    let run = if properties.is_empty() {
+        format!("{{{}}}", escape_rtf(text))
+    } else {
+        format!("{{{} {}}}", properties, escape_rtf(text))
+    };
+
+    match link {
+        Some(url) => format!(
+            "{{\\field{{\\*\\fldinst{{HYPERLINK \"{}\"}}}}{{\\fldrslt{}}}}}",
+            escape_rtf(&url.replace('"', "%22")),
+            run
+        ),
+        None => run,
+    }
+}
+
+/// Returns the 1-based color table index of `hex`, adding it if needed
+fn color_index(colors: &mut Vec<String>, hex: String) -> usize {
+    match colors.iter().position(|c| *c == hex) {
+        Some(index) => index + 1,
+        None => {
+            colors.push(hex);
+            colors.len()
+        }
+    }
+}
+
+fn escape_rtf(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\\' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(ch);
+            }
+            '\t' => escaped.push_str("\\tab "),
+            SOFT_BREAK => escaped.push_str("\\line "),
+            EMBED_CHAR => {}
+            c if c.is_control() => {}
+            c if c.is_ascii() => escaped.push(c),
+            c => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    // RTF takes signed 16-bit values, with `?` for readers
+                    // that don't understand \u
+                    escaped.push_str(&format!("\\u{}?", *unit as i16));
+                }
+            }
+        }
+    }
+    escaped
+}
+
+/// Maps a stylesheet name onto the block type it stands for
+fn style_block_type(name: &str) -> Option<BlockType> {
+    let name = name.trim().to_lowercase();
+    if let Some(level) = name.strip_prefix("heading") {
+        return match level.trim().parse::<u8>() {
+            Ok(level @ 1..=6) => Some(BlockType::heading(level)),
+            _ => None,
+        };
+    }
+    match name.as_str() {
+        "quote" | "block text" | "intense quote" => Some(BlockType::block_quote()),
+        "code" | "html preformatted" => Some(BlockType::code_block()),
+        "list bullet" => Some(BlockType::BulletList),
+        "list number" => Some(BlockType::NumberedList),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Destination {
+    Text,
+    FontTable,
+    ColorTable,
+    StyleSheet,
+    FieldInstruction,
+    ListText,
+    Skip,
+}
+
+/// Character properties and destination of one RTF group
+#[derive(Debug, Clone)]
+struct GroupState {
+    destination: Destination,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strike: bool,
+    small_caps: bool,
+    subscript: bool,
+    superscript: bool,
+    font: Option<i32>,
+    color: usize,
+    background: usize,
+    highlight: usize,
+    link: Option<String>,
+    unicode_skip: usize,
+}
+
+impl GroupState {
+    fn new() -> Self {
+        Self {
+            destination: Destination::Text,
+            bold: false,
+            italic: false,
+            underline: false,
+            strike: false,
+            small_caps: false,
+            subscript: false,
+            superscript: false,
+            font: None,
+            color: 0,
+            background: 0,
+            highlight: 0,
+            link: None,
+            unicode_skip: 1,
+        }
+    }
+
+    /// Handles `\plain`, which resets character properties but keeps the
+    /// destination, link and `\uc` count
+    fn reset_characters(&mut self) {
+        *self = Self {
+            destination: self.destination,
+            link: self.link.take(),
+            unicode_skip: self.unicode_skip,
+            ..Self::new()
+        };
+    }
+}
+
+/// Paragraph properties, reset by `\pard`
+#[derive(Debug, Clone, Default)]
+struct ParagraphState {
+    style: Option<i32>,
+    outline_level: Option<i32>,
+    list_marker: Option<BlockType>,
+    in_list: bool,
+    left_indent: i32,
+}
+
+struct RtfReader {
+    state: GroupState,
+    stack: Vec<GroupState>,
+    paragraph: ParagraphState,
+    ignorable: bool,
+    skip_chars: usize,
+    high_surrogate: Option<u16>,
+
+    mono_fonts: HashSet<i32>,
+    colors: Vec<Option<String>>,
+    styles: HashMap<i32, BlockType>,
+    entry_number: Option<i32>,
+    entry_mono: bool,
+    entry_text: String,
+    rgb: [Option<u8>; 3],
+    field_instruction: String,
+    list_text: String,
+    list_text_kind: Option<BlockType>,
+
+    text: String,
+    length: usize,
+    formats: Vec<(usize, usize, InlineFormat)>,
+    open_formats: HashMap<InlineFormat, usize>,
+    blocks: Vec<BlockInfo>,
+    line_start: usize,
+}
+
+impl RtfReader {
+    fn new() -> Self {
+        Self {
+            state: GroupState::new(),
+            stack: Vec::new(),
+            paragraph: ParagraphState::default(),
+            ignorable: false,
+            skip_chars: 0,
+            high_surrogate: None,
+            mono_fonts: HashSet::new(),
+            colors: Vec::new(),
+            styles: HashMap::new(),
+            entry_number: None,
+            entry_mono: false,
+            entry_text: String::new(),
+            rgb: [None; 3],
+            field_instruction: String::new(),
+            list_text: String::new(),
+            list_text_kind: None,
+            text: String::new(),
+            length: 0,
+            formats: Vec::new(),
+            open_formats: HashMap::new(),
+            blocks: Vec::new(),
+            line_start: 0,
+        }
+    }
+
+    fn read(&mut self, rtf: &str) -> Result<(), RtfError> {
+        let chars: Vec<char> = rtf.chars().collect();
+        let mut i = 0;
+
+        while i < chars.len() {
+            match chars[i] {
+                '{' => {
+                    self.stack.push(self.state.clone());
+                    i += 1;
+                }
+                '}' => {
+                    let parent = self.stack.pop().ok_or(RtfError::UnbalancedGroups)?;
+                    let closed = std::mem::replace(&mut self.state, parent);
+                    self.close_group(closed.destination);
+                    i += 1;
+                    if self.stack.is_empty() {
+                        // Anything after the outermost group is not part of the document
+                        break;
+                    }
+                }
+                '\\' => i = self.read_control(&chars, i + 1),
+                '\r' | '\n' => i += 1,
+                ch => {
+                    self.text_char(ch);
+                    i += 1;
+                }
+            }
+        }
+
+        if !self.stack.is_empty() {
+            return Err(RtfError::UnbalancedGroups);
+        }
+        Ok(())
+    }
+
+    /// Reads the control word or symbol after a backslash at `i`, returning
+    /// the index after it
+    fn read_control(&mut self, chars: &[char], mut i: usize) -> usize {
+        let Some(&first) = chars.get(i) else {
+            return i;
+        };
+
+        if !first.is_ascii_alphabetic() {
+            i += 1;
+            match first {
+                '\'' => {
+                    let hex: String = chars.iter().skip(i).take(2).collect();
+                    i += hex.len();
+                    if let Ok(byte) = u8::from_str_radix(&hex, 16) {
+                        if let Some(ch) = decode_cp1252(byte) {
+                            self.text_char(ch);
+                        } else {
+                            self.skip_chars = self.skip_chars.saturating_sub(1);
+                        }
+                    }
+                }
+                '*' => self.ignorable = true,
+                '~' => self.text_char('\u{a0}'),
+                '_' => self.text_char('-'),
+                '\\' | '{' | '}' => self.text_char(first),
+                '\r' | '\n' => self.control_word("par", None),
+                _ => {}
+            }
+            return i;
+        }
+
+        let start = i;
+        while i < chars.len() && chars[i].is_ascii_alphabetic() {
+            i += 1;
+        }
+        let word: String = chars[start..i].iter().collect();
+
+        let parameter_start = i;
+        if chars.get(i) == Some(&'-') {
+            i += 1;
+        }
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+        let parameter = chars[parameter_start..i]
+            .iter()
+            .collect::<String>()
+            .parse::<i64>()
+            .ok()
+            .map(|p| p.clamp(i32::MIN as i64, i32::MAX as i64) as i32);
+
+        if chars.get(i) == Some(&' ') {
+            i += 1;
+        }
+
+        self.control_word(&word, parameter);
+        i
+    }
+
+    fn control_word(&mut self, word: &str, parameter: Option<i32>) {
+        let ignorable = std::mem::take(&mut self.ignorable);
+        if self.state.destination == Destination::Skip {
+            return;
+        }
+
+        let destination = match word {
+            "fonttbl" => Some(Destination::FontTable),
+            "colortbl" => Some(Destination::ColorTable),
+            "stylesheet" => Some(Destination::StyleSheet),
+            "fldinst" => Some(Destination::FieldInstruction),
+            "listtext" | "pntext" => Some(Destination::ListText),
+            _ if SKIPPED_DESTINATIONS.contains(&word) => Some(Destination::Skip),
+            // Legacy paragraph numbering is read rather than skipped
+            "pn" => None,
+            _ if ignorable => Some(Destination::Skip),
+            _ => None,
+        };
+        if let Some(destination) = destination {
+            if destination == Destination::FieldInstruction {
+                self.field_instruction.clear();
+            }
+            if destination == Destination::ListText {
+                self.list_text.clear();
+            }
+            self.state.destination = destination;
+            return;
+        }
+
+        let on = parameter != Some(0);
+        match self.state.destination {
+            Destination::FontTable => match word {
+                "f" => self.entry_number = parameter,
+                "fmodern" => self.entry_mono = true,
+                _ => {}
+            },
+            Destination::ColorTable => {
+                let channel = match word {
+                    "red" => 0,
+                    "green" => 1,
+                    "blue" => 2,
+                    _ => return,
+                };
+                self.rgb[channel] = parameter.map(|p| p.clamp(0, 255) as u8);
+            }
+            Destination::StyleSheet => {
+                if word == "s" {
+                    self.entry_number = parameter;
+                }
+            }
+            Destination::FieldInstruction | Destination::ListText | Destination::Skip => {
+                if word == "tab" && self.state.destination == Destination::ListText {
+                    self.list_text.push('\t');
+                }
+            }
+            Destination::Text => match word {
+                "par" => self.end_paragraph(),
+                "line" => self.text_char(SOFT_BREAK),
+                "pard" => self.paragraph = ParagraphState::default(),
+                "plain" => self.state.reset_characters(),
+                "b" => self.state.bold = on,
+                "i" => self.state.italic = on,
+                "ul" | "uld" | "uldb" | "ulw" | "uldash" | "ulwave" | "ulth" => {
+                    self.state.underline = on
+                }
+                "ulnone" => self.state.underline = false,
+                "strike" | "striked" => self.state.strike = on,
+                "scaps" => self.state.small_caps = on,
+                "sub" => {
+                    self.state.subscript = on;
+                    self.state.superscript = false;
+                }
+                "super" => {
+                    self.state.superscript = on;
+                    self.state.subscript = false;
+                }
+                "nosupersub" => {
+                    self.state.subscript = false;
+                    self.state.superscript = false;
+                }
+                "f" => self.state.font = parameter,
+                "cf" => self.state.color = parameter.unwrap_or(0).max(0) as usize,
+                "cb" | "chcbpat" => self.state.background = parameter.unwrap_or(0).max(0) as usize,
+                "highlight" => self.state.highlight = parameter.unwrap_or(0).max(0) as usize,
+                "s" => self.paragraph.style = parameter,
+                "outlinelevel" => self.paragraph.outline_level = parameter,
+                "li" => self.paragraph.left_indent = parameter.unwrap_or(0),
+                "pnlvlblt" => self.paragraph.list_marker = Some(BlockType::BulletList),
+                "pnlvlbody" | "pndec" => self.paragraph.list_marker = Some(BlockType::NumberedList),
+                "ls" => self.paragraph.in_list = true,
+                "uc" => self.state.unicode_skip = parameter.unwrap_or(1).max(0) as usize,
+                "u" => {
+                    if let Some(parameter) = parameter {
+                        self.unicode_char(parameter);
+                    }
+                }
+                "tab" => self.text_char('\t'),
+                "emdash" => self.text_char('\u{2014}'),
+                "endash" => self.text_char('\u{2013}'),
+                "bullet" => self.text_char('\u{2022}'),
+                "lquote" => self.text_char('\u{2018}'),
+                "rquote" => self.text_char('\u{2019}'),
+                "ldblquote" => self.text_char('\u{201C}'),
+                "rdblquote" => self.text_char('\u{201D}'),
+                "emspace" | "enspace" | "qmspace" => self.text_char(' '),
+                _ => {}
+            },
+        }
+    }
+
+    fn unicode_char(&mut self, parameter: i32) {
+        let unit = if parameter < 0 {
+            (parameter + 65536) as u32
+        } else {
+            parameter as u32
+        };
+        let ch = match (self.high_surrogate.take(), unit) {
+            (_, 0xD800..=0xDBFF) => {
+                self.high_surrogate = Some(unit as u16);
+                None
+            }
+            (Some(high), 0xDC00..=0xDFFF) => char::decode_utf16([high, unit as u16])
+                .next()
+                .and_then(Result::ok),
+            (_, unit) => char::from_u32(unit),
+        };
+        if let Some(ch) = ch {
+            self.text_char(ch);
+        }
+        self.skip_chars = self.state.unicode_skip;
+    }
+
+    fn text_char(&mut self, ch: char) {
+        // Fallback characters after \u stand in for the character just read
+        if self.skip_chars > 0 {
+            self.skip_chars -= 1;
+            return;
+        }
+
+        match self.state.destination {
+            Destination::Text => self.push_char(ch),
+            Destination::FontTable => {
+                if ch == ';' {
+                    let name = std::mem::take(&mut self.entry_text).to_lowercase();
+                    let mono = std::mem::take(&mut self.entry_mono)
+                        || ["courier", "mono", "consolas"]
+                            .iter()
+                            .any(|m| name.contains(m));
+                    if let Some(number) = self.entry_number.take()
+                        && mono
+                    {
+                        self.mono_fonts.insert(number);
+                    }
+                } else {
+                    self.entry_text.push(ch);
+                }
+            }
+            Destination::ColorTable => {
+                if ch == ';' {
+                    let color = match std::mem::take(&mut self.rgb) {
+                        [None, None, None] => None,
+                        [r, g, b] => Some(format!(
+                            "{:02X}{:02X}{:02X}",
+                            r.unwrap_or(0),
+                            g.unwrap_or(0),
+                            b.unwrap_or(0)
+                        )),
+                    };
+                    self.colors.push(color);
+                }
+            }
+            Destination::StyleSheet => {
+                if ch == ';' {
+                    let name = std::mem::take(&mut self.entry_text);
+                    if let Some(block_type) = style_block_type(&name) {
+                        self.styles
+                            .insert(self.entry_number.unwrap_or(0), block_type);
+                    }
+                    self.entry_number = None;
+                } else {
+                    self.entry_text.push(ch);
+                }
+            }
+            Destination::FieldInstruction => self.field_instruction.push(ch),
+            Destination::ListText => self.list_text.push(ch),
+            Destination::Skip => {}
+        }
+    }
+
+    fn close_group(&mut self, destination: Destination) {
+        if destination == self.state.destination {
+            return;
+        }
+        match destination {
+            Destination::FieldInstruction => {
+                let url = hyperlink_url(&self.field_instruction);
+                self.state.link = url.filter(|url| HtmlSanitizer::new().is_safe_url(url));
+            }
+            Destination::ListText => {
+                self.list_text_kind = Some(
+                    if self.list_text.chars().any(|c| c.is_ascii_alphanumeric()) {
+                        BlockType::NumberedList
+                    } else {
+                        BlockType::BulletList
+                    },
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn paragraph_block(&self) -> BlockType {
+        let paragraph = &self.paragraph;
+        if let Some(style) = paragraph.style
+            && let Some(block_type) = self.styles.get(&style)
+        {
+            if block_type.is_block_quote() {
+                let depth = (paragraph.left_indent / 720).clamp(1, u8::MAX as i32) as u8;
+                return BlockType::block_quote_with(None, depth);
+            }
+            return block_type.clone();
+        }
+        if let Some(marker) = &paragraph.list_marker {
+            return marker.clone();
+        }
+        if paragraph.in_list {
+            return self.list_text_kind.clone().unwrap_or(BlockType::BulletList);
+        }
+        match paragraph.outline_level {
+            Some(level @ 0..=5) => BlockType::heading(level as u8 + 1),
+            _ => BlockType::Paragraph,
+        }
+    }
+
+    fn push_char(&mut self, ch: char) {
+        if ch == EMBED_CHAR || (ch.is_control() && ch != '\t') {
+            return;
+        }
+
+        let code_block = self.paragraph_block().is_code_block();
+        for format in self.active_formats(code_block) {
+            match self.open_formats.get(&format) {
+                Some(&index) if self.formats[index].1 == self.length => {
+                    self.formats[index].1 += 1;
+                }
+                _ => {
+                    self.open_formats.insert(format.clone(), self.formats.len());
+                    self.formats.push((self.length, self.length + 1, format));
+                }
+            }
+        }
+
+        self.text.push(ch);
+        self.length += 1;
+    }
+
+    fn active_formats(&self, code_block: bool) -> Vec<InlineFormat> {
+        let state = &self.state;
+        let color = |index: usize| self.colors.get(index).cloned().flatten();
+        let mut formats = Vec::new();
+
+        for (on, format) in [
+            (state.bold, InlineFormat::Bold),
+            (state.italic, InlineFormat::Italic),
+            (state.underline, InlineFormat::Underline),
+            (state.strike, InlineFormat::Strikethrough),
+            (state.small_caps, InlineFormat::SmallCaps),
+            (state.subscript, InlineFormat::Subscript),
+            (state.superscript, InlineFormat::Superscript),
+            (state.highlight > 0, InlineFormat::Highlight),
+        ] {
+            if on {
+                formats.push(format);
+            }
+        }
+        if !code_block
+            && let Some(font) = state.font
+            && self.mono_fonts.contains(&font)
+        {
+            formats.push(InlineFormat::Code);
+        }
+        if state.color > 0
+            && let Some(hex) = color(state.color)
+        {
+            formats.push(InlineFormat::TextColor {
+                color: format!("#{}", hex),
+            });
+        }
+        if state.background > 0
+            && let Some(hex) = color(state.background)
+        {
+            formats.push(InlineFormat::BackgroundColor {
+                color: format!("#{}", hex),
+            });
+        }
+        if let Some(url) = &state.link {
+            formats.push(InlineFormat::Link { url: url.clone() });
+        }
+        formats
+    }
+
+    fn end_paragraph(&mut self) {
+        self.push_block();
+        self.text.push('\n');
+        self.length += 1;
+        self.line_start = self.length;
+        self.list_text_kind = None;
+    }
+
+    fn push_block(&mut self) {
+        let block_type = self.paragraph_block();
+        if self.blocks.last().map(|b| &b.block_type) != Some(&block_type) {
+            self.blocks
+                .push(BlockInfo::new(self.line_start, block_type));
+        }
+    }
+
+    fn finish(mut self) -> (String, Vec<(usize, usize, InlineFormat)>, Vec<BlockInfo>) {
+        // Every paragraph ends in \par, so the final one leaves a newline the
+        // model doesn't keep
+        if self.text.ends_with('\n') {
+            self.text.pop();
+        } else if self.line_start < self.length || self.blocks.is_empty() {
+            self.push_block();
+        }
+        (self.text, self.formats, self.blocks)
+    }
+}
+
+/// Decodes a Windows-1252 byte, or None if the byte is unassigned
+pub(crate) fn decode_cp1252(byte: u8) -> Option<char> {
+    match byte {
+        0x80..=0x9F => match CP1252_HIGH[(byte - 0x80) as usize] {
+            0 => None,
+            code => char::from_u32(code as u32),
+        },
+        _ => Some(byte as char),
+    }
+}
+
+/// Extracts the target of a `HYPERLINK "url"` field instruction
+fn hyperlink_url(instruction: &str) -> Option<String> {
+    let rest = instruction.trim().strip_prefix("HYPERLINK")?.trim_start();
+    let url = match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next()?,
+        None => rest.split_whitespace().next()?,
+    };
+    (!url.is_empty()).then(|| url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_inline_formats() {
+        let mut doc = Document::from_text("Bold link red");
+        doc.apply_format(Range::from_offsets(0, 4), InlineFormat::Bold);
+        doc.apply_format(
+            Range::from_offsets(5, 9),
+            InlineFormat::Link {
+                url: "https://example.com".to_string(),
+            },
+        );
+        doc.apply_format(
+            Range::from_offsets(10, 13),
+            InlineFormat::TextColor {
+                color: "#f00".to_string(),
+            },
+        );
+
+        let rtf = doc.to_rtf();
+        assert!(rtf.contains(r"{\colortbl;\red255\green0\blue0;}"));
+        assert!(rtf.contains(r"{\b Bold}{ }"));
+        assert!(
+            rtf.contains(
+                r#"{\field{\*\fldinst{HYPERLINK "https://example.com"}}{\fldrslt{link}}}"#
+            )
+        );
+        assert!(rtf.contains(r"{\cf1 red}\par"));
+    }
+
+    #[test]
+    fn test_export_blocks_and_escaping() {
+        let mut doc = Document::from_text("Title\nOne\nTwo\n{a\\b} café 😀");
+        doc.set_block_type(Range::from_offsets(0, 5), BlockType::heading(1));
+        doc.set_block_type(Range::from_offsets(6, 13), BlockType::NumberedList);
+        doc.set_block_type(Range::from_offsets(14, 26), BlockType::Paragraph);
+
+        let rtf = doc.to_rtf();
+        assert!(rtf.contains(r"\pard\plain\s1\outlinelevel0\fs48{Title}\par"));
+        assert!(rtf.contains(r"\pnstart1"));
+        assert!(rtf.contains(r"{\pntext 2.\tab}"));
+        assert!(rtf.contains(r"{\{a\\b\} caf\u233? \u-10179?\u-8704?}"));
+    }
+
+    #[test]
+    fn test_soft_breaks() {
+        let doc = Document::from_text("one\u{2028}two\nthree");
+        let rtf = doc.to_rtf();
+        assert!(rtf.contains(r"{one\line two}\par"));
+
+        let imported = Document::from_rtf(&rtf).unwrap();
+        assert_eq!(imported.get_content(), doc.get_content());
+
+        let imported = Document::from_rtf(r"{\rtf1\ansi Street 1\line Town\par Next}").unwrap();
+        assert_eq!(imported.get_content(), "Street 1\u{2028}Town\nNext");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut doc = Document::from_text("Heading\nItem one\nItem two\nlet x = 1;\nQuote");
+        doc.set_block_type(Range::from_offsets(0, 7), BlockType::heading(2));
+        doc.set_block_type(Range::from_offsets(8, 25), BlockType::BulletList);
+        doc.set_block_type(Range::from_offsets(26, 36), BlockType::code_block());
+        doc.set_block_type(
+            Range::from_offsets(37, 42),
+            BlockType::block_quote_with(None, 2),
+        );
+        doc.apply_format(Range::from_offsets(8, 12), InlineFormat::Italic);
+        doc.apply_format(Range::from_offsets(13, 16), InlineFormat::Code);
+        doc.apply_format(Range::from_offsets(17, 21), InlineFormat::Highlight);
+        doc.apply_format(Range::from_offsets(22, 25), InlineFormat::Subscript);
+        doc.apply_format(Range::from_offsets(38, 40), InlineFormat::Superscript);
+
+        let imported = Document::from_rtf(&doc.to_rtf()).unwrap();
+        assert_eq!(imported.get_content(), doc.get_content());
+        for offset in [0, 8, 17, 26, 37] {
+            assert_eq!(
+                imported.get_block_type_at(Position::new(offset)),
+                doc.get_block_type_at(Position::new(offset)),
+                "block at {}",
+                offset
+            );
+        }
+        for offset in 0..doc.get_content().chars().count() {
+            assert_eq!(
+                imported.get_formats_at(Position::new(offset)),
+                doc.get_formats_at(Position::new(offset)),
+                "formats at {}",
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn test_import_word_style_rtf() {
+        let rtf = r#"{\rtf1\ansi\ansicpg1252\deff0
+{\fonttbl{\f0\froman Times New Roman;}{\f1\fmodern Courier New;}}
+{\colortbl;\red0\green0\blue255;}
+{\stylesheet{\s0 Normal;}{\s2 heading 2;}{\*\cs10 Default Paragraph Font;}}
+{\*\generator Word;}{\info{\title Ignored}}
+\pard\plain\s2 Intro\par
+\pard\plain Visit {\field{\*\fldinst{HYPERLINK "https://example.com"}}{\fldrslt{\cf1\ul site}}} or run {\f1 ls}.\par
+{\listtext\pard\plain \'95\tab}\pard\plain\ls1 Caf\'e9 \u8364?\par
+}"#;
+        let doc = Document::from_rtf(rtf).unwrap();
+        assert_eq!(doc.get_content(), "Intro\nVisit site or run ls.\nCafé €");
+        assert_eq!(
+            doc.get_block_type_at(Position::new(0)),
+            BlockType::heading(2)
+        );
+        assert_eq!(
+            doc.get_block_type_at(Position::new(6)),
+            BlockType::Paragraph
+        );
+        assert_eq!(
+            doc.get_block_type_at(Position::new(29)),
+            BlockType::BulletList
+        );
+
+        let formats = doc.get_formats_at(Position::new(13));
+        assert!(formats.contains(&InlineFormat::Link {
+            url: "https://example.com".to_string()
+        }));
+        assert!(formats.contains(&InlineFormat::Underline));
+        assert!(formats.contains(&InlineFormat::TextColor {
+            color: "#0000FF".to_string()
+        }));
+        assert!(
+            doc.get_formats_at(Position::new(24))
+                .contains(&InlineFormat::Code)
+        );
+    }
+
+    #[test]
+    fn test_import_drops_unsafe_links() {
+        let rtf = r#"{\rtf1{\field{\*\fldinst{HYPERLINK "javascript:alert(1)"}}{\fldrslt click}}}"#;
+        let doc = Document::from_rtf(rtf).unwrap();
+        assert_eq!(doc.get_content(), "click");
+        assert!(doc.get_formats_at(Position::new(1)).is_empty());
+    }
+
+    #[test]
+    fn test_import_rejects_invalid_input() {
+        assert!(matches!(
+            Document::from_rtf("plain text"),
+            Err(RtfError::NotRtf)
+        ));
+        assert!(matches!(
+            Document::from_rtf(r"{\rtf1 {\b open"),
+            Err(RtfError::UnbalancedGroups)
+        ));
+        assert!(Document::from_rtf(r"{\rtf1 text}}").is_ok());
+    }
+}
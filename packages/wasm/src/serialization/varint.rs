@@ -0,0 +1,69 @@
+//! Unsigned LEB128 varints
+//!
+//! Both the binary document format and CRDT updates store numbers this
+//! way: seven bits per byte, least significant first, with the high bit
+//! set on every byte but the last. Callers map `VarintError` to their own
+//! error types.
+
+/// Why a varint could not be read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VarintError {
+    /// The input ended before the last byte of the varint
+    UnexpectedEnd,
+    /// The value does not fit in 64 bits
+    Overflow,
+}
+
+/// Appends `value` to `bytes`
+pub(crate) fn write(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Reads the varint at the start of `bytes`, returning it and the number of
+/// bytes it took
+pub(crate) fn read(bytes: &[u8]) -> Result<(u64, usize), VarintError> {
+    let mut value = 0u64;
+    for (i, shift) in (0..u64::BITS).step_by(7).enumerate() {
+        let byte = *bytes.get(i).ok_or(VarintError::UnexpectedEnd)?;
+        let bits = u64::from(byte & 0x7f);
+        if (bits << shift) >> shift != bits {
+            return Err(VarintError::Overflow);
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    Err(VarintError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for value in [0, 1, 0x7f, 0x80, 300, u64::from(u32::MAX), u64::MAX] {
+            let mut bytes = Vec::new();
+            write(&mut bytes, value);
+            bytes.push(0xff);
+            assert_eq!(read(&bytes), Ok((value, bytes.len() - 1)));
+        }
+    }
+
+    #[test]
+    fn test_rejects_truncated_and_oversized() {
+        assert_eq!(read(&[]), Err(VarintError::UnexpectedEnd));
+        assert_eq!(read(&[0x80, 0x80]), Err(VarintError::UnexpectedEnd));
+
+        // The tenth byte holds only the top bit of a u64
+        let mut bytes = vec![0xff; 9];
+        bytes.push(0x02);
+        assert_eq!(read(&bytes), Err(VarintError::Overflow));
+        assert_eq!(read(&[0x80; 11]), Err(VarintError::Overflow));
+    }
+}
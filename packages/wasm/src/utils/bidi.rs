@@ -0,0 +1,603 @@
+//! Bidirectional text
+//!
+//! Resolves the embedding level of each character of a line following the
+//! Unicode Bidirectional Algorithm (Unicode Standard Annex #9), so that
+//! Arabic and Hebrew text can be displayed and navigated in visual order.
+//!
+//! Each line is its own paragraph, with its base direction either set on the
+//! block or taken from its first strong character. Explicit embedding,
+//! override and isolate controls are ignored, and character classes are
+//! approximated from Unicode block ranges rather than the full property
+//! data.
+
+use crate::formatting::TextDirection;
+
+/// Embedding levels resolved for one line of text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BidiLine {
+    /// Character offset of the start of the line
+    pub start: usize,
+    /// Base direction of the line
+    pub direction: TextDirection,
+    /// Embedding level of each character; odd levels are right-to-left
+    pub levels: Vec<u8>,
+}
+
+/// A run of characters with the same embedding level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BidiRun {
+    /// Character offset of the start of the run
+    pub start: usize,
+    /// Character offset of the end of the run (exclusive)
+    pub end: usize,
+    /// Embedding level of the run; odd levels are right-to-left
+    pub level: u8,
+}
+
+impl BidiRun {
+    /// Returns the direction the characters of the run are laid out in
+    pub fn direction(&self) -> TextDirection {
+        if self.level % 2 == 1 {
+            TextDirection::Rtl
+        } else {
+            TextDirection::Ltr
+        }
+    }
+}
+
+impl BidiLine {
+    /// Resolves the levels of `chars`, a line starting at offset `start`
+    ///
+    /// A `direction` of None takes the direction of the first strong
+    /// character, or left-to-right if there is none.
+    ///
+    /// # Example
+    /// ```
+    /// use rte_core::utils::bidi::BidiLine;
+    ///
+    /// let chars: Vec<char> = "abc אבג".chars().collect();
+    /// let line = BidiLine::resolve(&chars, 0, None);
+    /// assert_eq!(line.levels, vec![0, 0, 0, 0, 1, 1, 1]);
+    /// assert_eq!(line.visual_order(), vec![0, 1, 2, 3, 6, 5, 4]);
+    /// ```
+    pub fn resolve(chars: &[char], start: usize, direction: Option<TextDirection>) -> Self {
+        let classes: Vec<BidiClass> = chars.iter().map(|&c| bidi_class(c)).collect();
+        let direction = direction
+            .or_else(|| first_strong_direction(chars))
+            .unwrap_or(TextDirection::Ltr);
+        let levels = resolve_levels(&classes, direction);
+        Self {
+            start,
+            direction,
+            levels,
+        }
+    }
+
+    /// Returns the number of characters in the line
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Returns true if the line is empty
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Returns true if any character of the line is laid out right-to-left
+    pub fn has_rtl(&self) -> bool {
+        self.levels.iter().any(|level| level % 2 == 1)
+    }
+
+    /// Returns the offset of each character in display order, left to right
+    pub fn visual_order(&self) -> Vec<usize> {
+        self.visual_indices()
+            .into_iter()
+            .map(|i| self.start + i)
+            .collect()
+    }
+
+    /// Returns the runs of the line in display order, left to right
+    pub fn runs(&self) -> Vec<BidiRun> {
+        let mut runs: Vec<BidiRun> = Vec::new();
+        for i in self.visual_indices() {
+            let level = self.levels[i];
+            let offset = self.start + i;
+            match runs.last_mut() {
+                Some(run) if run.level == level && level.is_multiple_of(2) && run.end == offset => {
+                    run.end += 1
+                }
+                Some(run) if run.level == level && level % 2 == 1 && run.start == offset + 1 => {
+                    run.start -= 1
+                }
+                _ => runs.push(BidiRun {
+                    start: offset,
+                    end: offset + 1,
+                    level,
+                }),
+            }
+        }
+        runs
+    }
+
+    /// Returns the visual caret slot of the caret at `offset`, from 0 at the
+    /// left edge of the line to `len()` at the right edge
+    ///
+    /// A caret is drawn at the leading edge of the character after it, or
+    /// at the trailing edge of the last character at the end of the line.
+    pub fn visual_caret(&self, offset: usize) -> usize {
+        let inverse = self.inverse_order();
+        self.slot_of(offset.saturating_sub(self.start).min(self.len()), &inverse)
+    }
+
+    /// Returns the offset of the caret drawn at visual `slot`, the inverse
+    /// of `visual_caret`
+    pub fn logical_caret(&self, slot: usize) -> usize {
+        let order = self.visual_indices();
+        let inverse = self.inverse_order();
+        let slot = slot.min(self.len());
+        let candidates = self.carets_beside(&order, slot);
+        let caret = candidates
+            .iter()
+            .flatten()
+            .find(|&&c| self.slot_of(c, &inverse) == slot)
+            .or_else(|| candidates.iter().flatten().next());
+        self.start + caret.copied().unwrap_or(0)
+    }
+
+    /// Returns the offset reached by moving the caret at `offset` one
+    /// character to the left or right on screen
+    ///
+    /// Returns None at the edge of the line.
+    pub fn move_visually(&self, offset: usize, right: bool) -> Option<usize> {
+        let order = self.visual_indices();
+        let inverse = self.inverse_order();
+        let n = self.len();
+        let current = offset.saturating_sub(self.start).min(n);
+
+        let mut slot = self.slot_of(current, &inverse);
+        loop {
+            slot = if right {
+                Some(slot + 1).filter(|&s| s <= n)?
+            } else {
+                slot.checked_sub(1)?
+            };
+            let candidates = self.carets_beside(&order, slot);
+            let moved = candidates
+                .iter()
+                .flatten()
+                .filter(|&&c| c != current)
+                .find(|&&c| self.slot_of(c, &inverse) == slot)
+                .or_else(|| candidates.iter().flatten().find(|&&c| c != current));
+            if let Some(&moved) = moved {
+                return Some(self.start + moved);
+            }
+        }
+    }
+
+    /// Returns the line-relative offsets at the edges of the characters on
+    /// either side of visual `slot` that face it
+    fn carets_beside(&self, order: &[usize], slot: usize) -> [Option<usize>; 2] {
+        let rtl = |i: usize| self.levels[i] % 2 == 1;
+        let after = order.get(slot).map(|&i| if rtl(i) { i + 1 } else { i });
+        let before = slot
+            .checked_sub(1)
+            .map(|k| order[k])
+            .map(|i| if rtl(i) { i } else { i + 1 });
+        [after, before]
+    }
+
+    /// Returns the index of each character in display order (rule L2)
+    fn visual_indices(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.len()).collect();
+        let Some(&highest) = self.levels.iter().max() else {
+            return order;
+        };
+        let lowest_odd = self
+            .levels
+            .iter()
+            .copied()
+            .filter(|level| level % 2 == 1)
+            .min()
+            .unwrap_or(highest + 1);
+
+        for level in (lowest_odd..=highest).rev() {
+            let mut i = 0;
+            while i < order.len() {
+                if self.levels[order[i]] < level {
+                    i += 1;
+                    continue;
+                }
+                let start = i;
+                while i < order.len() && self.levels[order[i]] >= level {
+                    i += 1;
+                }
+                order[start..i].reverse();
+            }
+        }
+        order
+    }
+
+    fn inverse_order(&self) -> Vec<usize> {
+        let mut inverse = vec![0; self.len()];
+        for (slot, i) in self.visual_indices().into_iter().enumerate() {
+            inverse[i] = slot;
+        }
+        inverse
+    }
+
+    /// Returns the caret slot for the line-relative offset `i`
+    fn slot_of(&self, i: usize, inverse: &[usize]) -> usize {
+        let rtl = |i: usize| self.levels[i] % 2 == 1;
+        match self.len() {
+            0 => 0,
+            n if i < n => inverse[i] + usize::from(rtl(i)),
+            n => inverse[n - 1] + usize::from(!rtl(n - 1)),
+        }
+    }
+}
+
+/// Returns the direction of the first strong character in `chars`
+pub fn first_strong_direction(chars: &[char]) -> Option<TextDirection> {
+    chars.iter().find_map(|&c| match bidi_class(c) {
+        BidiClass::L => Some(TextDirection::Ltr),
+        BidiClass::R | BidiClass::AL => Some(TextDirection::Rtl),
+        _ => None,
+    })
+}
+
+/// Bidi character types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BidiClass {
+    /// Left-to-right letter
+    L,
+    /// Right-to-left letter
+    R,
+    /// Arabic letter
+    AL,
+    /// European number
+    EN,
+    /// European number separator
+    ES,
+    /// European number terminator
+    ET,
+    /// Arabic number
+    AN,
+    /// Common number separator
+    CS,
+    /// Nonspacing mark
+    Nsm,
+    /// Boundary neutral
+    BN,
+    /// Paragraph separator
+    B,
+    /// Segment separator
+    S,
+    /// Whitespace
+    WS,
+    /// Other neutral
+    ON,
+}
+
+fn bidi_class(c: char) -> BidiClass {
+    use BidiClass::*;
+    match c {
+        '\n' | '\r' | '\u{1C}'..='\u{1E}' | '\u{85}' | '\u{2029}' => B,
+        '\t' | '\u{B}' | '\u{1F}' => S,
+        ' '
+        | '\u{C}'
+        | '\u{1680}'
+        | '\u{2000}'..='\u{200A}'
+        | '\u{2028}'
+        | '\u{205F}'
+        | '\u{3000}' => WS,
+        '\u{0}'..='\u{8}'
+        | '\u{E}'..='\u{1B}'
+        | '\u{7F}'..='\u{84}'
+        | '\u{86}'..='\u{9F}'
+        | '\u{AD}'
+        | '\u{200B}'..='\u{200D}'
+        | '\u{202A}'..='\u{202E}'
+        | '\u{2060}'..='\u{2069}'
+        | '\u{FEFF}' => BN,
+        '\u{200E}' => L,
+        '\u{200F}' => R,
+        '\u{61C}' => AL,
+        '0'..='9'
+        | '\u{B2}'
+        | '\u{B3}'
+        | '\u{B9}'
+        | '\u{6F0}'..='\u{6F9}'
+        | '\u{2070}'
+        | '\u{2074}'..='\u{2079}'
+        | '\u{2080}'..='\u{2089}'
+        | '\u{FF10}'..='\u{FF19}' => EN,
+        '\u{600}'..='\u{605}' | '\u{660}'..='\u{669}' | '\u{66B}' | '\u{66C}' | '\u{6DD}' => AN,
+        '+' | '-' | '\u{207A}' | '\u{207B}' | '\u{208A}' | '\u{208B}' | '\u{2212}' | '\u{FB29}'
+        | '\u{FE62}' | '\u{FE63}' | '\u{FF0B}' | '\u{FF0D}' => ES,
+        '#'
+        | '$'
+        | '%'
+        | '\u{A2}'..='\u{A5}'
+        | '\u{B0}'
+        | '\u{B1}'
+        | '\u{609}'
+        | '\u{60A}'
+        | '\u{66A}'
+        | '\u{2030}'..='\u{2034}'
+        | '\u{20A0}'..='\u{20CF}'
+        | '\u{FE5F}'
+        | '\u{FE69}'
+        | '\u{FE6A}'
+        | '\u{FF03}'..='\u{FF05}'
+        | '\u{FFE0}'
+        | '\u{FFE1}'
+        | '\u{FFE5}'
+        | '\u{FFE6}' => ET,
+        ',' | '.' | '/' | ':' | '\u{A0}' | '\u{60C}' | '\u{202F}' | '\u{2044}' | '\u{FE50}'
+        | '\u{FE52}' | '\u{FE55}' | '\u{FF0C}' | '\u{FF0E}' | '\u{FF0F}' | '\u{FF1A}' => CS,
+        '\u{300}'..='\u{36F}'
+        | '\u{483}'..='\u{489}'
+        | '\u{591}'..='\u{5BD}'
+        | '\u{5BF}'
+        | '\u{5C1}'
+        | '\u{5C2}'
+        | '\u{5C4}'
+        | '\u{5C5}'
+        | '\u{5C7}'
+        | '\u{610}'..='\u{61A}'
+        | '\u{64B}'..='\u{65F}'
+        | '\u{670}'
+        | '\u{6D6}'..='\u{6DC}'
+        | '\u{6DF}'..='\u{6E4}'
+        | '\u{6E7}'
+        | '\u{6E8}'
+        | '\u{6EA}'..='\u{6ED}'
+        | '\u{711}'
+        | '\u{730}'..='\u{74A}'
+        | '\u{7A6}'..='\u{7B0}'
+        | '\u{7EB}'..='\u{7F3}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}' => Nsm,
+        // Hebrew, NKo, Samaritan, Mandaic and Hebrew presentation forms
+        '\u{590}'..='\u{5FF}'
+        | '\u{7C0}'..='\u{85F}'
+        | '\u{FB1D}'..='\u{FB4F}'
+        | '\u{10800}'..='\u{10FFF}'
+        | '\u{1E800}'..='\u{1EFFF}' => R,
+        // Arabic, Syriac, Thaana and Arabic presentation forms
+        '\u{600}'..='\u{7BF}'
+        | '\u{860}'..='\u{8FF}'
+        | '\u{FB50}'..='\u{FDFF}'
+        | '\u{FE70}'..='\u{FEFF}' => AL,
+        _ if c.is_alphanumeric() => L,
+        _ => ON,
+    }
+}
+
+/// Resolves the embedding level of each character of a paragraph
+fn resolve_levels(original: &[BidiClass], direction: TextDirection) -> Vec<u8> {
+    use BidiClass::*;
+    let base: u8 = if direction.is_rtl() { 1 } else { 0 };
+    let sos = if direction.is_rtl() { R } else { L };
+    let mut classes = original.to_vec();
+    let n = classes.len();
+
+    // W1: nonspacing marks and boundary neutrals take the type before them
+    let mut previous = sos;
+    for class in &mut classes {
+        if matches!(*class, Nsm | BN) {
+            *class = previous;
+        }
+        previous = *class;
+    }
+
+    // W2, W3: numbers after Arabic letters are Arabic numbers, and Arabic
+    // letters are right-to-left letters
+    let mut last_strong = sos;
+    for class in &mut classes {
+        match *class {
+            L | R | AL => last_strong = *class,
+            EN if last_strong == AL => *class = AN,
+            _ => {}
+        }
+    }
+    for class in &mut classes {
+        if *class == AL {
+            *class = R;
+        }
+    }
+
+    // W4: a single separator between two numbers of the same type joins them
+    for i in 1..n.saturating_sub(1) {
+        let (before, after) = (classes[i - 1], classes[i + 1]);
+        classes[i] = match classes[i] {
+            ES if before == EN && after == EN => EN,
+            CS if before == after && matches!(before, EN | AN) => before,
+            class => class,
+        };
+    }
+
+    // W5: terminators next to European numbers become part of them
+    let mut i = 0;
+    while i < n {
+        if classes[i] != ET {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < n && classes[i] == ET {
+            i += 1;
+        }
+        let touches_number = (start > 0 && classes[start - 1] == EN) || (i < n && classes[i] == EN);
+        if touches_number {
+            classes[start..i].fill(EN);
+        }
+    }
+
+    // W6: remaining separators and terminators are neutral
+    for class in &mut classes {
+        if matches!(*class, ES | ET | CS) {
+            *class = ON;
+        }
+    }
+
+    // W7: European numbers after left-to-right text are left-to-right
+    let mut last_strong = sos;
+    for class in &mut classes {
+        match *class {
+            L | R => last_strong = *class,
+            EN if last_strong == L => *class = L,
+            _ => {}
+        }
+    }
+
+    // N1, N2: neutrals between text of the same direction take it, others
+    // take the paragraph direction; numbers count as right-to-left
+    let strong = |class: BidiClass| match class {
+        L => Some(L),
+        R | EN | AN => Some(R),
+        _ => None,
+    };
+    let mut i = 0;
+    while i < n {
+        if strong(classes[i]).is_some() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < n && strong(classes[i]).is_none() {
+            i += 1;
+        }
+        let before = if start == 0 {
+            sos
+        } else {
+            strong(classes[start - 1]).unwrap_or(sos)
+        };
+        let after = if i == n {
+            sos
+        } else {
+            strong(classes[i]).unwrap_or(sos)
+        };
+        let resolved = if before == after { before } else { sos };
+        classes[start..i].fill(resolved);
+    }
+
+    // I1, I2: implicit levels
+    let mut levels: Vec<u8> = classes
+        .iter()
+        .map(|&class| match (base % 2, class) {
+            (0, R) => base + 1,
+            (0, AN | EN) => base + 2,
+            (1, L | EN | AN) => base + 1,
+            _ => base,
+        })
+        .collect();
+
+    // L1: separators, and whitespace before them or at the end of the
+    // line, go back to the paragraph level
+    let mut trailing = true;
+    for i in (0..n).rev() {
+        match original[i] {
+            B | S => {
+                levels[i] = base;
+                trailing = true;
+            }
+            WS | BN if trailing => levels[i] = base,
+            _ => trailing = false,
+        }
+    }
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str, direction: Option<TextDirection>) -> BidiLine {
+        let chars: Vec<char> = text.chars().collect();
+        BidiLine::resolve(&chars, 0, direction)
+    }
+
+    #[test]
+    fn test_resolve_detects_direction() {
+        assert_eq!(line("abc", None).direction, TextDirection::Ltr);
+        assert_eq!(line("123 שלום", None).direction, TextDirection::Rtl);
+        assert_eq!(line("123", None).direction, TextDirection::Ltr);
+        assert!(!line("abc", None).has_rtl());
+    }
+
+    #[test]
+    fn test_numbers_in_rtl_text() {
+        // "שלום 123 abc" in a right-to-left line: the number and the Latin
+        // word keep their own order
+        let line = line("שלום 123 abc", None);
+        assert_eq!(line.levels, vec![1, 1, 1, 1, 1, 2, 2, 2, 1, 2, 2, 2]);
+        assert_eq!(
+            line.visual_order(),
+            vec![9, 10, 11, 8, 5, 6, 7, 4, 3, 2, 1, 0]
+        );
+
+        // Arabic-Indic digits and a decimal separator
+        let line = self::line("عدد ١٢٫٥", None);
+        assert_eq!(&line.levels[4..], &[2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn test_neutrals_and_trailing_whitespace() {
+        // Punctuation between two Hebrew words is right-to-left, the
+        // trailing space goes back to the paragraph level
+        let line = line("abc אב, גד ", Some(TextDirection::Ltr));
+        assert_eq!(line.levels, vec![0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 0]);
+        assert_eq!(
+            line.runs(),
+            vec![
+                BidiRun {
+                    start: 0,
+                    end: 4,
+                    level: 0
+                },
+                BidiRun {
+                    start: 4,
+                    end: 10,
+                    level: 1
+                },
+                BidiRun {
+                    start: 10,
+                    end: 11,
+                    level: 0
+                },
+            ]
+        );
+        assert_eq!(line.runs()[1].direction(), TextDirection::Rtl);
+    }
+
+    #[test]
+    fn test_move_visually() {
+        let line = line("ab אבג", None);
+        let mut offset = 0;
+        let mut visited = vec![offset];
+        while let Some(next) = line.move_visually(offset, true) {
+            offset = next;
+            visited.push(offset);
+        }
+        // Across the Hebrew word the caret moves backwards in the text
+        assert_eq!(visited, vec![0, 1, 2, 6, 5, 4, 3]);
+        assert_eq!(line.visual_caret(3), 6);
+        assert_eq!(line.logical_caret(6), 3);
+        assert_eq!(line.logical_caret(3), 6);
+        assert_eq!(line.logical_caret(0), 0);
+
+        let mut back = vec![offset];
+        while let Some(next) = line.move_visually(offset, false) {
+            offset = next;
+            back.push(offset);
+        }
+        visited.reverse();
+        assert_eq!(back, visited);
+
+        assert_eq!(self::line("", None).move_visually(0, true), None);
+    }
+}
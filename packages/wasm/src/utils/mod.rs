@@ -7,12 +7,21 @@
 //!
 //! - IME (Input Method Editor) composition state management
 //! - String interning for memory efficiency
+//! - ZIP archive writing for document exports
+//! - Word segmentation for scripts written without spaces
+//! - Bidirectional text level resolution and visual ordering
 //! - Other shared utility functions
 //!
 //! # Key Types
 //!
 //! - `CompositionState`: Manages IME composition sessions
 //! - `StringInterner`: Deduplicates repeated strings (URLs, colors)
+//! - `write_stored`: Writes an uncompressed ZIP archive
+//! - `WordSegmenter`: Splits Thai, Lao, Khmer and Myanmar runs into words
+//! - `BidiLine`: Embedding levels and visual order of a line of text
 
+pub mod bidi;
 pub mod ime;
 pub mod interner;
+pub mod segmenter;
+pub mod zip;
@@ -0,0 +1,370 @@
+//! Word segmentation
+//!
+//! Text is split into words following the word boundary rules of Unicode
+//! Standard Annex #29: contractions such as "can't" and numbers such as
+//! "3.14" stay whole, each CJK ideograph is a word of its own, and combining
+//! marks stay with the character they modify. The character classes are
+//! approximated from the standard library's Unicode tables rather than the
+//! full property data.
+//!
+//! Thai, Lao, Khmer and Myanmar write words without spaces, which needs a
+//! dictionary to split correctly. Rather than bundling one, the editor
+//! accepts a `WordSegmenter` (in the browser, typically backed by
+//! `Intl.Segmenter`) and asks it to split runs of those scripts. Without a
+//! segmenter such a run counts as a single word.
+
+/// Splits runs of text written without spaces into words
+pub trait WordSegmenter {
+    /// Returns the character offsets within `text` at which words start
+    ///
+    /// `text` is a run of Thai, Lao, Khmer or Myanmar word characters.
+    /// Offsets of 0, `text.chars().count()` or beyond are ignored.
+    fn segment(&self, text: &str) -> Vec<usize>;
+}
+
+impl<F: Fn(&str) -> Vec<usize>> WordSegmenter for F {
+    fn segment(&self, text: &str) -> Vec<usize> {
+        self(text)
+    }
+}
+
+/// Returns true if `c` belongs to a script written without spaces between words
+pub fn is_complex_script(c: char) -> bool {
+    matches!(c,
+        '\u{0E00}'..='\u{0E7F}' // Thai
+        | '\u{0E80}'..='\u{0EFF}' // Lao
+        | '\u{1000}'..='\u{109F}' // Myanmar
+        | '\u{1780}'..='\u{17FF}' // Khmer
+        | '\u{19E0}'..='\u{19FF}' // Khmer symbols
+    )
+}
+
+/// Returns true if `c` is part of a word
+///
+/// Letters, digits and underscores are word characters. In scripts written
+/// without spaces, vowel signs and tone marks are too, while their
+/// punctuation is not.
+pub fn is_word_char(c: char) -> bool {
+    if is_complex_script(c) {
+        return !matches!(c,
+            '\u{0E2F}' | '\u{0E4F}' | '\u{0E5A}' | '\u{0E5B}' // Thai
+            | '\u{0EAF}' // Lao
+            | '\u{104A}'..='\u{104F}' // Myanmar
+            | '\u{17D4}'..='\u{17DA}' // Khmer
+        );
+    }
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Returns the character offsets where one word ends and the next begins
+/// with no separator in between, in ascending order
+///
+/// These are the script changes at either end of a Thai, Lao, Khmer or
+/// Myanmar run, plus the boundaries `segmenter` finds inside it.
+pub fn word_breaks(chars: &[char], segmenter: Option<&dyn WordSegmenter>) -> Vec<usize> {
+    let mut breaks = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if !(is_complex_script(chars[i]) && is_word_char(chars[i])) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && is_complex_script(chars[i]) && is_word_char(chars[i]) {
+            i += 1;
+        }
+
+        if start > 0 && is_word_char(chars[start - 1]) {
+            breaks.push(start);
+        }
+        if let Some(segmenter) = segmenter {
+            let run: String = chars[start..i].iter().collect();
+            breaks.extend(
+                segmenter
+                    .segment(&run)
+                    .into_iter()
+                    .filter(|&offset| offset > 0 && offset < i - start)
+                    .map(|offset| start + offset),
+            );
+        }
+        if i < chars.len() && is_word_char(chars[i]) {
+            breaks.push(i);
+        }
+    }
+    breaks.sort_unstable();
+    breaks.dedup();
+    breaks
+}
+
+/// A piece of text between two word boundaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WordSegment {
+    /// Character offset of the start of the segment
+    pub start: usize,
+    /// Character offset of the end of the segment (exclusive)
+    pub end: usize,
+    /// Whether the segment is a word, rather than spaces or punctuation
+    pub is_word: bool,
+}
+
+/// Splits `chars` into segments at word boundaries
+///
+/// The segments cover the whole text in order. Runs of Thai, Lao, Khmer or
+/// Myanmar text are split further by `segmenter`.
+///
+/// # Example
+/// ```
+/// use rte_core::utils::segmenter::word_segments;
+///
+/// let chars: Vec<char> = "can't stop".chars().collect();
+/// let words: Vec<String> = word_segments(&chars, None)
+///     .iter()
+///     .filter(|s| s.is_word)
+///     .map(|s| chars[s.start..s.end].iter().collect())
+///     .collect();
+/// assert_eq!(words, vec!["can't", "stop"]);
+/// ```
+pub fn word_segments(chars: &[char], segmenter: Option<&dyn WordSegmenter>) -> Vec<WordSegment> {
+    // Combining marks, joiners and what a zero width joiner glues on belong
+    // to the character before them (WB4)
+    let mut bases: Vec<(usize, BreakClass)> = Vec::with_capacity(chars.len());
+    let mut after_zwj = false;
+    for (i, &c) in chars.iter().enumerate() {
+        let class = break_class(c);
+        let attaches = (class == BreakClass::Extend || (after_zwj && class == BreakClass::Other))
+            && bases
+                .last()
+                .is_some_and(|&(_, last)| last != BreakClass::Newline);
+        after_zwj = c == '\u{200D}';
+        if !attaches {
+            let class = if class == BreakClass::Extend {
+                BreakClass::Other
+            } else {
+                class
+            };
+            bases.push((i, class));
+        }
+    }
+
+    let mut boundaries: Vec<usize> = (1..bases.len())
+        .filter(|&j| {
+            let (prev_offset, prev) = bases[j - 1];
+            let (offset, class) = bases[j];
+            let crlf = chars[prev_offset] == '\r' && chars[offset] == '\n';
+            let before = j.checked_sub(2).map(|k| bases[k].1);
+            let after = bases.get(j + 1).map(|&(_, class)| class);
+            !crlf && !joins(before, prev, class, after)
+        })
+        .map(|j| bases[j].0)
+        .collect();
+    boundaries.extend(word_breaks(chars, segmenter));
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut segments = Vec::with_capacity(boundaries.len() + 1);
+    let mut start = 0;
+    for end in boundaries.into_iter().chain(std::iter::once(chars.len())) {
+        if end > start {
+            let is_word = chars[start..end].iter().any(|&c| break_class(c).is_word());
+            segments.push(WordSegment {
+                start,
+                end,
+                is_word,
+            });
+            start = end;
+        }
+    }
+    segments
+}
+
+/// Simplified UAX #29 word break property values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BreakClass {
+    Newline,
+    Space,
+    Letter,
+    Numeric,
+    Katakana,
+    /// Han ideographs and Hiragana, which break around every character
+    Ideographic,
+    /// Thai, Lao, Khmer and Myanmar letters, split by the `WordSegmenter`
+    ComplexScript,
+    ExtendNumLet,
+    MidLetter,
+    MidNum,
+    MidNumLet,
+    Extend,
+    Other,
+}
+
+impl BreakClass {
+    fn is_word(self) -> bool {
+        matches!(
+            self,
+            Self::Letter | Self::Numeric | Self::Katakana | Self::Ideographic | Self::ComplexScript
+        )
+    }
+}
+
+fn break_class(c: char) -> BreakClass {
+    match c {
+        '\n' | '\r' | '\u{B}' | '\u{C}' | '\u{85}' | '\u{2028}' | '\u{2029}' => BreakClass::Newline,
+        '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{200C}'
+        | '\u{200D}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}'
+        | '\u{1F3FB}'..='\u{1F3FF}'
+        | '\u{E0020}'..='\u{E007F}'
+        | '\u{E0100}'..='\u{E01EF}' => BreakClass::Extend,
+        '_'
+        | '\u{203F}'
+        | '\u{2040}'
+        | '\u{2054}'
+        | '\u{FE33}'
+        | '\u{FE34}'
+        | '\u{FE4D}'..='\u{FE4F}'
+        | '\u{FF3F}' => BreakClass::ExtendNumLet,
+        ':' | '\u{B7}' | '\u{387}' | '\u{55F}' | '\u{5F4}' | '\u{2027}' | '\u{FE13}'
+        | '\u{FE55}' | '\u{FF1A}' => BreakClass::MidLetter,
+        ',' | ';' | '\u{37E}' | '\u{589}' | '\u{60C}' | '\u{60D}' | '\u{66C}' | '\u{7F8}'
+        | '\u{2044}' | '\u{FE10}' | '\u{FE14}' | '\u{FE50}' | '\u{FE54}' | '\u{FF0C}'
+        | '\u{FF1B}' => BreakClass::MidNum,
+        '.' | '\'' | '\u{2018}' | '\u{2019}' | '\u{2024}' | '\u{FE52}' | '\u{FF07}'
+        | '\u{FF0E}' => BreakClass::MidNumLet,
+        '\u{3031}'..='\u{3035}'
+        | '\u{309B}'
+        | '\u{309C}'
+        | '\u{30A0}'..='\u{30FA}'
+        | '\u{30FC}'..='\u{30FF}'
+        | '\u{31F0}'..='\u{31FF}'
+        | '\u{32D0}'..='\u{32FE}'
+        | '\u{3300}'..='\u{3357}'
+        | '\u{FF66}'..='\u{FF9F}' => BreakClass::Katakana,
+        '\u{3005}'
+        | '\u{3007}'
+        | '\u{3040}'..='\u{309F}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{20000}'..='\u{3FFFF}' => BreakClass::Ideographic,
+        _ if is_complex_script(c) => {
+            if is_word_char(c) {
+                BreakClass::ComplexScript
+            } else {
+                BreakClass::Other
+            }
+        }
+        _ if c.is_whitespace() => BreakClass::Space,
+        _ if c.is_numeric() => BreakClass::Numeric,
+        _ if c.is_alphabetic() => BreakClass::Letter,
+        _ => BreakClass::Other,
+    }
+}
+
+/// Returns true if there is no word boundary between `prev` and `class`
+///
+/// `before` and `after` are the classes around the pair, for the rules that
+/// look past it, such as the apostrophe in "can't".
+fn joins(
+    before: Option<BreakClass>,
+    prev: BreakClass,
+    class: BreakClass,
+    after: Option<BreakClass>,
+) -> bool {
+    use BreakClass::*;
+    match (prev, class) {
+        // WB3d
+        (Space, Space) => true,
+        // WB5, WB8, WB9, WB10, WB13, plus runs of scripts without spaces
+        (Letter | Numeric, Letter | Numeric)
+        | (Katakana, Katakana)
+        | (ComplexScript, ComplexScript) => true,
+        // WB13a, WB13b
+        (Letter | Numeric | Katakana | ExtendNumLet, ExtendNumLet)
+        | (ExtendNumLet, Letter | Numeric | Katakana) => true,
+        // WB6, WB7
+        (Letter, MidLetter | MidNumLet) => after == Some(Letter),
+        (MidLetter | MidNumLet, Letter) => before == Some(Letter),
+        // WB11, WB12
+        (Numeric, MidNum | MidNumLet) => after == Some(Numeric),
+        (MidNum | MidNumLet, Numeric) => before == Some(Numeric),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_chars() {
+        assert!(is_word_char('a'));
+        assert!(is_word_char('_'));
+        assert!(!is_word_char('.'));
+        // Thai sara i (a combining vowel sign) and mai ek (a tone mark)
+        assert!(is_word_char('\u{0E34}'));
+        assert!(is_word_char('\u{0E48}'));
+        // Khmer khan, the sentence-ending mark
+        assert!(!is_word_char('\u{17D4}'));
+    }
+
+    #[test]
+    fn test_word_breaks_without_segmenter() {
+        let chars: Vec<char> = "abcภาษาไทย def".chars().collect();
+        assert_eq!(word_breaks(&chars, None), vec![3]);
+
+        let chars: Vec<char> = "hello world".chars().collect();
+        assert!(word_breaks(&chars, None).is_empty());
+    }
+
+    fn words(text: &str) -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        word_segments(&chars, None)
+            .iter()
+            .map(|s| chars[s.start..s.end].iter().collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_word_segments() {
+        assert_eq!(
+            words("It’s 3.14, e.g. foo_bar!"),
+            vec![
+                "It’s", " ", "3.14", ",", " ", "e.g", ".", " ", "foo_bar", "!"
+            ]
+        );
+        assert_eq!(words("你好世界"), vec!["你", "好", "世", "界"]);
+        assert_eq!(words("カタカナです"), vec!["カタカナ", "で", "す"]);
+        assert_eq!(words("a\r\n\nb"), vec!["a", "\r\n", "\n", "b"]);
+        // Combining marks and emoji joined with ZWJ stay together
+        assert_eq!(
+            words("cafe\u{301} 👩\u{200D}💻!"),
+            vec!["cafe\u{301}", " ", "👩\u{200D}💻", "!"]
+        );
+    }
+
+    #[test]
+    fn test_word_segments_flags_words() {
+        let chars: Vec<char> = "Hi, 你".chars().collect();
+        let flags: Vec<bool> = word_segments(&chars, None)
+            .iter()
+            .map(|s| s.is_word)
+            .collect();
+        assert_eq!(flags, vec![true, false, false, true]);
+    }
+
+    #[test]
+    fn test_word_breaks_with_segmenter() {
+        // Splits "ภาษาไทย" ("Thai language") after "ภาษา"
+        let segmenter = |text: &str| {
+            assert_eq!(text, "ภาษาไทย");
+            vec![0, 4, 7, 12]
+        };
+        let chars: Vec<char> = "ภาษาไทยx".chars().collect();
+        assert_eq!(word_breaks(&chars, Some(&segmenter)), vec![4, 7]);
+    }
+}
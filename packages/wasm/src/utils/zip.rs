@@ -0,0 +1,156 @@
+//! Minimal ZIP archive writer
+//!
+//! Writes archives with stored (uncompressed) entries, which is all the
+//! OOXML exporters need and keeps the crate free of a compression
+//! dependency. Readers such as Word and LibreOffice accept stored entries.
+
+/// Signature of a local file header
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+/// Signature of a central directory file header
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+/// Signature of the end of central directory record
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+/// Version 2.0, the minimum that supports directories and stored entries
+const VERSION: u16 = 20;
+/// MS-DOS date for 1980-01-01, so output is reproducible
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+/// CRC-32 (IEEE) lookup table
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Computes the CRC-32 checksum of `data`
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Writes a ZIP archive containing the given `(path, contents)` entries
+///
+/// Entries are stored in the given order without compression.
+///
+/// # Example
+/// ```
+/// use rte_core::utils::zip::write_stored;
+///
+/// let archive = write_stored(&[("hello.txt", b"Hello".as_slice())]);
+/// assert_eq!(&archive[..4], b"PK\x03\x04");
+/// ```
+pub fn write_stored(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+
+    for (path, data) in entries {
+        let offset = out.len() as u32;
+        let crc = crc32(data);
+        let size = data.len() as u32;
+        let name = path.as_bytes();
+
+        put_u32(&mut out, LOCAL_HEADER_SIGNATURE);
+        put_u16(&mut out, VERSION);
+        put_u16(&mut out, 0); // flags
+        put_u16(&mut out, 0); // method: stored
+        put_u16(&mut out, 0); // time
+        put_u16(&mut out, DOS_DATE);
+        put_u32(&mut out, crc);
+        put_u32(&mut out, size); // compressed size
+        put_u32(&mut out, size);
+        put_u16(&mut out, name.len() as u16);
+        put_u16(&mut out, 0); // extra field length
+        out.extend_from_slice(name);
+        out.extend_from_slice(data);
+
+        put_u32(&mut central, CENTRAL_HEADER_SIGNATURE);
+        put_u16(&mut central, VERSION); // made by
+        put_u16(&mut central, VERSION); // needed to extract
+        put_u16(&mut central, 0);
+        put_u16(&mut central, 0);
+        put_u16(&mut central, 0);
+        put_u16(&mut central, DOS_DATE);
+        put_u32(&mut central, crc);
+        put_u32(&mut central, size);
+        put_u32(&mut central, size);
+        put_u16(&mut central, name.len() as u16);
+        put_u16(&mut central, 0); // extra field length
+        put_u16(&mut central, 0); // comment length
+        put_u16(&mut central, 0); // disk number
+        put_u16(&mut central, 0); // internal attributes
+        put_u32(&mut central, 0); // external attributes
+        put_u32(&mut central, offset);
+        central.extend_from_slice(name);
+    }
+
+    let central_offset = out.len() as u32;
+    let central_size = central.len() as u32;
+    out.extend_from_slice(&central);
+
+    put_u32(&mut out, END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+    put_u16(&mut out, 0); // this disk
+    put_u16(&mut out, 0); // disk with central directory
+    put_u16(&mut out, entries.len() as u16);
+    put_u16(&mut out, entries.len() as u16);
+    put_u32(&mut out, central_size);
+    put_u32(&mut out, central_offset);
+    put_u16(&mut out, 0); // comment length
+    out
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_archive_layout() {
+        let archive = write_stored(&[("a.txt", b"abc".as_slice()), ("dir/b.txt", b"".as_slice())]);
+
+        // Local header of the first entry, followed by its name and data
+        assert_eq!(&archive[0..4], &LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        assert_eq!(&archive[30..35], b"a.txt");
+        assert_eq!(&archive[35..38], b"abc");
+
+        // End of central directory record lists both entries
+        let end = &archive[archive.len() - 22..];
+        assert_eq!(
+            &end[0..4],
+            &END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes()
+        );
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+        let central_offset = u32::from_le_bytes([end[16], end[17], end[18], end[19]]) as usize;
+        assert_eq!(
+            &archive[central_offset..central_offset + 4],
+            &CENTRAL_HEADER_SIGNATURE.to_le_bytes()
+        );
+    }
+}